@@ -4,4 +4,15 @@ fn main() {
         // Windows 资源文件包含清单，确保安装字体时使用管理员权限
         embed_resource::compile("packaging/windows/fontsync.rc", std::iter::empty::<&str>());
     }
+
+    // gRPC 接口代码由 proto 文件生成，用 protox 解析，不需要安装 protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/fontsync.proto");
+        let descriptors = protox::compile(["proto/fontsync.proto"], ["proto"]).expect("Failed to parse proto/fontsync.proto");
+        tonic_build::configure()
+            .bytes(["."])
+            .compile_fds(descriptors)
+            .expect("Failed to generate gRPC code");
+    }
 }
@@ -0,0 +1,140 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::utils;
+
+// 角色按权限从低到高排列：读取者可列出与下载，上传者还可上传与修改标签，
+// 管理员还可删除、恢复、查看客户端并使用管理接口
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Uploader,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Reader => "reader",
+            Role::Uploader => "uploader",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenEntry {
+    pub token: String,
+    pub role: Role,
+    // 记录为上传者，便于区分令牌的持有者；设置配额时必须提供
+    #[serde(default)]
+    pub name: Option<String>,
+    // 用该令牌上传的字体总大小上限，如 "5GB"
+    #[serde(default, deserialize_with = "deserialize_quota")]
+    pub quota: Option<u64>,
+}
+
+fn deserialize_quota<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) => utils::parse_file_size(&value).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+// 服务器令牌配置（JSON 数组，每项含 token、role 与可选的 name）；
+// 为空时字体接口保持匿名访问，管理接口仍需管理员令牌
+#[derive(Debug, Clone, Default)]
+pub struct AccessTokens {
+    entries: Vec<TokenEntry>,
+}
+
+impl AccessTokens {
+    pub fn new(entries: Vec<TokenEntry>) -> Result<Self> {
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.token.trim().is_empty() {
+                bail!("Token #{} is empty", i + 1);
+            }
+            if entries[..i].iter().any(|other| other.token == entry.token) {
+                bail!("Token #{} is listed more than once", i + 1);
+            }
+            if entry.quota.is_some() && entry.name.is_none() {
+                bail!("Token #{} has a quota but no name to attribute uploads to", i + 1);
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read token file {:?}", path))?;
+        let entries: Vec<TokenEntry> =
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse token file {:?}", path))?;
+        Self::new(entries).with_context(|| format!("Invalid token file {:?}", path))
+    }
+
+    // 未配置令牌时不要求认证
+    pub fn is_open(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn has_admin(&self) -> bool {
+        self.entries.iter().any(|e| e.role == Role::Admin)
+    }
+
+    pub fn lookup(&self, token: &str) -> Option<&TokenEntry> {
+        self.entries.iter().find(|e| tokens_match(token, &e.token))
+    }
+
+    // 按名称查找配额，同名的多个令牌共用其中最小的配额
+    pub fn quota(&self, name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .filter(|e| e.name.as_deref() == Some(name))
+            .filter_map(|e| e.quota)
+            .min()
+    }
+}
+
+// 按字节累积差异，比较耗时与不匹配的位置无关
+pub fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_tokens_with_roles() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("tokens.json");
+        fs::write(
+            &path,
+            r#"[
+                { "token": "read-1", "role": "reader" },
+                { "token": "up-1", "role": "uploader", "name": "design team", "quota": "2MB" }
+            ]"#,
+        )
+        .unwrap();
+
+        let tokens = AccessTokens::load(&path).expect("load");
+        assert!(!tokens.is_open());
+        assert!(!tokens.has_admin());
+        assert_eq!(tokens.lookup("up-1").map(|e| e.role), Some(Role::Uploader));
+        assert!(tokens.lookup("up-2").is_none());
+        assert_eq!(tokens.quota("design team"), Some(2 * 1024 * 1024));
+        assert_eq!(tokens.quota("read-1"), None);
+        assert!(Role::Reader < Role::Uploader && Role::Uploader < Role::Admin);
+
+        fs::write(&path, r#"[{ "token": "x", "role": "owner" }]"#).unwrap();
+        assert!(AccessTokens::load(&path).is_err());
+        fs::write(&path, r#"[{ "token": "x", "role": "uploader", "quota": "1GB" }]"#).unwrap();
+        assert!(AccessTokens::load(&path).is_err());
+        fs::write(&path, r#"[{ "token": "x", "role": "reader" }, { "token": "x", "role": "admin" }]"#).unwrap();
+        assert!(AccessTokens::load(&path).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::client_state::ClientState;
+
+const ACTIVATED_FILE: &str = "activated.json";
+// 检查是否有字体被 deactivate 停用的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// 一个临时启用的字体及持有它的 activate 进程
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    pub path: PathBuf,
+    pub pid: u32,
+    pub activated_at: u64,
+}
+
+impl Activation {
+    pub fn file_name(&self) -> String {
+        self.path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+    }
+
+    // 按完整路径或文件名匹配
+    fn matches(&self, font: &str) -> bool {
+        self.path == Path::new(font) || self.file_name() == font
+    }
+}
+
+fn activated_path() -> PathBuf {
+    ClientState::state_dir().join(ACTIVATED_FILE)
+}
+
+pub fn list() -> Vec<Activation> {
+    load_from(&activated_path())
+}
+
+fn load_from(path: &Path) -> Vec<Activation> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_to(path: &Path, activations: &[Activation]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create state directory")?;
+    }
+    let content = serde_json::to_string_pretty(activations).context("Failed to serialize activations")?;
+    // 先写临时文件再重命名，避免中断时留下半截记录
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, content).context("Failed to write activations")?;
+    fs::rename(&tmp_path, path).context("Failed to replace activations")?;
+    Ok(())
+}
+
+// 重新读取后修改，避免覆盖其他进程同时写入的记录；返回被移除的记录
+fn remove_where(path: &Path, predicate: impl Fn(&Activation) -> bool) -> Result<Vec<Activation>> {
+    let (removed, kept): (Vec<_>, Vec<_>) = load_from(path).into_iter().partition(|a| predicate(a));
+    if !removed.is_empty() {
+        save_to(path, &kept)?;
+    }
+    Ok(removed)
+}
+
+// 临时启用字体并保持到收到退出信号或全部被 deactivate 停用，退出前停用仍启用的字体
+pub async fn hold(fonts: Vec<PathBuf>, shutdown: impl Future<Output = ()>) -> Result<()> {
+    let pid = std::process::id();
+    let record_path = activated_path();
+    let mut active = Vec::new();
+    for font in fonts {
+        let path = font.canonicalize().with_context(|| format!("Font not found: {:?}", font))?;
+        match register(&path) {
+            Ok(()) => {
+                info!("Activated {:?} for this session", path);
+                active.push(Activation { path, pid, activated_at: chrono::Utc::now().timestamp() as u64 });
+            }
+            Err(e) => warn!("Failed to activate {:?}: {:#}", path, e),
+        }
+    }
+    if active.is_empty() {
+        return Err(anyhow::anyhow!("No fonts were activated"));
+    }
+
+    let mut records = load_from(&record_path);
+    records.retain(|r| !active.iter().any(|a| a.path == r.path));
+    records.extend(active.iter().cloned());
+    save_to(&record_path, &records)?;
+    info!("{} fonts active; press Ctrl+C or run `fontsync deactivate` to remove them", active.len());
+
+    tokio::pin!(shutdown);
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = ticker.tick() => {}
+        }
+        // 其他进程停用的字体已从记录中移除
+        let records = load_from(&record_path);
+        active.retain(|a| {
+            let kept = records.contains(a);
+            if !kept {
+                release(&a.path);
+            }
+            kept
+        });
+        if active.is_empty() {
+            info!("All fonts were deactivated");
+            return Ok(());
+        }
+    }
+
+    for activation in &active {
+        release(&activation.path);
+    }
+    remove_where(&record_path, |r| active.contains(r))?;
+    info!("Deactivated {} fonts", active.len());
+    Ok(())
+}
+
+// 停用匹配的字体，fonts 为空时停用全部；返回被停用的记录
+pub fn deactivate(fonts: &[String]) -> Result<Vec<Activation>> {
+    let removed = remove_where(&activated_path(), |r| fonts.is_empty() || fonts.iter().any(|f| r.matches(f)))?;
+    // Windows 上的私有字体只能由持有它的进程停用，其他系统上会话范围的注册在此直接撤销
+    if !cfg!(target_os = "windows") {
+        for activation in &removed {
+            release(&activation.path);
+        }
+    }
+    Ok(removed)
+}
+
+// 停用失败只记录，字体可能已被其他进程停用
+fn release(path: &Path) {
+    match unregister(path) {
+        Ok(()) => info!("Deactivated {:?}", path),
+        Err(e) => debug!("Failed to deactivate {:?}: {:#}", path, e),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn register(path: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Graphics::Gdi::{AddFontResourceExW, FR_PRIVATE};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    if unsafe { AddFontResourceExW(wide.as_ptr(), FR_PRIVATE, std::ptr::null()) } == 0 {
+        return Err(anyhow::anyhow!("AddFontResourceExW failed"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn unregister(path: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Graphics::Gdi::{RemoveFontResourceExW, FR_PRIVATE};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    if unsafe { RemoveFontResourceExW(wide.as_ptr(), FR_PRIVATE, std::ptr::null()) } == 0 {
+        return Err(anyhow::anyhow!("RemoveFontResourceExW failed"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+mod core_text {
+    use std::ffi::c_void;
+
+    // kCTFontManagerScopeSession：只在当前登录会话内有效
+    pub const SCOPE_SESSION: u32 = 3;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        pub fn CFURLCreateFromFileSystemRepresentation(
+            allocator: *const c_void,
+            buffer: *const u8,
+            length: isize,
+            is_directory: u8,
+        ) -> *const c_void;
+        pub fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "CoreText", kind = "framework")]
+    unsafe extern "C" {
+        pub fn CTFontManagerRegisterFontsForURL(url: *const c_void, scope: u32, error: *mut *const c_void) -> u8;
+        pub fn CTFontManagerUnregisterFontsForURL(url: *const c_void, scope: u32, error: *mut *const c_void) -> u8;
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn with_font_url(path: &Path, action: unsafe extern "C" fn(*const std::ffi::c_void, u32, *mut *const std::ffi::c_void) -> u8) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let bytes = path.as_os_str().as_bytes();
+    unsafe {
+        let url = core_text::CFURLCreateFromFileSystemRepresentation(std::ptr::null(), bytes.as_ptr(), bytes.len() as isize, 0);
+        if url.is_null() {
+            return Err(anyhow::anyhow!("Failed to create a URL for {:?}", path));
+        }
+        let mut error = std::ptr::null();
+        let ok = action(url, core_text::SCOPE_SESSION, &mut error);
+        if !error.is_null() {
+            core_text::CFRelease(error);
+        }
+        core_text::CFRelease(url);
+        if ok == 0 {
+            return Err(anyhow::anyhow!("Core Text rejected {:?}", path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn register(path: &Path) -> Result<()> {
+    with_font_url(path, core_text::CTFontManagerRegisterFontsForURL)
+}
+
+#[cfg(target_os = "macos")]
+fn unregister(path: &Path) -> Result<()> {
+    with_font_url(path, core_text::CTFontManagerUnregisterFontsForURL)
+}
+
+// Linux 没有会话范围的字体注册，把符号链接放进用户字体目录下的独立子目录，停用时删除
+#[cfg(target_os = "linux")]
+fn session_link(path: &Path) -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().context("Failed to get home directory")?;
+    let name = path.file_name().context("Failed to get font filename")?;
+    Ok(home_dir.join(".local/share/fonts/fontsync-temporary").join(name))
+}
+
+#[cfg(target_os = "linux")]
+fn register(path: &Path) -> Result<()> {
+    let link = session_link(path)?;
+    if let Some(dir) = link.parent() {
+        fs::create_dir_all(dir).context("Failed to create fonts directory")?;
+    }
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(path, &link).with_context(|| format!("Failed to link {:?}", link))?;
+    refresh_font_cache();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn unregister(path: &Path) -> Result<()> {
+    let link = session_link(path)?;
+    fs::remove_file(&link).with_context(|| format!("Failed to remove {:?}", link))?;
+    refresh_font_cache();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn refresh_font_cache() {
+    if let Err(e) = std::process::Command::new("fc-cache").status() {
+        debug!("Failed to run fc-cache: {}", e);
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn register(_path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!("Temporary font activation not supported on this OS"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn unregister(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deactivation_removes_matching_records() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join(ACTIVATED_FILE);
+        let activation = |name: &str, pid| Activation { path: dir.path().join(name), pid, activated_at: 1 };
+        save_to(&path, &[activation("a.ttf", 1), activation("b.otf", 1), activation("c.ttf", 2)]).unwrap();
+
+        let removed = remove_where(&path, |r| r.matches("b.otf")).unwrap();
+        assert_eq!(removed, vec![activation("b.otf", 1)]);
+        let full_path = dir.path().join("c.ttf").to_string_lossy().into_owned();
+        assert_eq!(remove_where(&path, |r| r.matches(&full_path)).unwrap().len(), 1);
+        assert_eq!(load_from(&path), vec![activation("a.ttf", 1)]);
+        assert!(remove_where(&path, |r| r.matches("missing.ttf")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,1887 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::event_log::EventRecord;
+use crate::font_metadata::{self, EmbeddingPermission};
+use crate::identity::{CLIENT_ID_HEADER, HOSTNAME_HEADER, OS_HEADER};
+use crate::ignore::glob_matches;
+use crate::metadata_store::normalize_tags;
+use crate::utils::FontSort;
+use crate::websocket_server::ConnectedClient;
+
+// 下载响应中携带服务器文件修改时间（Unix 秒）的头部
+pub const MODIFIED_HEADER: &str = "x-fontsync-modified";
+// POST /fonts/batch 单次请求最多包含的字体数
+pub const MAX_BATCH_UPLOADS: usize = 50;
+// 增量下载响应的内容类型：delta 为增量，full 为完整文件
+pub const DELTA_HEADER: &str = "x-fontsync-delta";
+// 客户端在请求与 WebSocket 握手中携带的程序版本与协议版本，服务器在所有响应中返回自己的协议版本
+pub const VERSION_HEADER: &str = "x-fontsync-version";
+pub const PROTOCOL_HEADER: &str = "x-fontsync-protocol";
+// 接口或消息格式发生不兼容的变化时递增；服务器同时接受不早于 MIN_PROTOCOL_VERSION 的客户端
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+// 客户端在 WebSocket 握手中声明只关心的字体，如 family=Noto%20Sans%20CJK*&tag=cjk&format=otf
+pub const SUBSCRIPTION_HEADER: &str = "x-fontsync-subscribe";
+
+// 服务器与客户端共用的 HTTP 接口类型，修改字段时需同步更新下方的 OpenAPI 描述
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FontInfo {
+    pub name: String,
+    pub size: u64,
+    pub mime_type: String,
+    // 服务器仍在后台计算时为 null，此时 status 为 hashing
+    #[serde(with = "pending_sha256")]
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<FontStatus>,
+    // 旧版服务器不提供修改时间
+    #[serde(default)]
+    pub modified: Option<u64>,
+    // 端到端加密字体的明文哈希，用于客户端去重
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plaintext_sha256: Option<String>,
+    // OS/2 fsType 声明的嵌入许可，加密或无法解析的字体为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<EmbeddingPermission>,
+    // fsType 禁止再分发
+    #[serde(default)]
+    pub restricted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    // 与 family 一起标识同一字体，旧版服务器不提供
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    // 基本覆盖的 Unicode 区段，如 Latin、CJK Unified
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unicode_ranges: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    // 最近一次上传的客户端，旧版服务器或上传者未知时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<Attribution>,
+}
+
+impl FontInfo {
+    // 用于比较的内容哈希：加密字体取明文哈希
+    pub fn content_sha256(&self) -> &str {
+        self.plaintext_sha256.as_deref().unwrap_or(&self.sha256)
+    }
+
+    // 家族与样式标识的同一字体，见 font_metadata::face_key
+    pub fn face_key(&self) -> Option<String> {
+        font_metadata::face_key(self.family.as_deref(), self.style.as_deref())
+    }
+
+    // 哈希尚未计算完成，不能用于比较
+    pub fn is_pending(&self) -> bool {
+        self.status == Some(FontStatus::Hashing)
+    }
+
+    // 上传尚未经管理员批准，不能下载
+    pub fn is_awaiting_approval(&self) -> bool {
+        self.status == Some(FontStatus::AwaitingApproval)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FontStatus {
+    Hashing,
+    AwaitingApproval,
+}
+
+// 空哈希序列化为 null，反序列化时 null 还原为空字符串
+mod pending_sha256 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(sha256: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        if sha256.is_empty() {
+            serializer.serialize_none()
+        } else {
+            serializer.serialize_str(sha256)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FontList {
+    pub fonts: Vec<FontInfo>,
+    // 过滤后、分页前的字体总数，旧版服务器不返回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+}
+
+// GET /fonts/hashes 的结果：文件名到内容哈希（加密字体取明文哈希）
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FontHashes {
+    pub fonts: BTreeMap<String, String>,
+    // 整个映射的摘要，相同时双方内容一致
+    pub digest: String,
+    // 服务器仍在计算哈希、未包含在映射中的字体
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pending: BTreeSet<String>,
+}
+
+impl FontHashes {
+    pub fn new(fonts: BTreeMap<String, String>) -> Self {
+        let mut hasher = Sha256::new();
+        for (name, sha256) in &fonts {
+            hasher.update(name.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(sha256.as_bytes());
+            hasher.update(b"\n");
+        }
+        Self { fonts, digest: hex::encode(hasher.finalize()), pending: BTreeSet::new() }
+    }
+}
+
+// GET /fonts 的查询参数，未设置的字段不发送
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FontQuery {
+    // 逗号分隔，字体带有其中任一标签即匹配
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    // 文件名或家族名子串，不区分大小写
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    // 逗号分隔的扩展名，如 ttf,otf
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<FontSort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    // 必须支持的字符，如 U+4E2D,U+6587
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub covers: Option<String>,
+}
+
+// 上传、删除、恢复字体的结果
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FontActionResponse {
+    pub success: bool,
+    pub filename: String,
+    // added、modified、unchanged、deleted、restored 或 awaiting_approval
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<EmbeddingPermission>,
+    // 服务器上文件的修改时间（Unix 秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl FontActionResponse {
+    // 服务器要求审核，上传暂未生效
+    pub fn is_awaiting_approval(&self) -> bool {
+        self.action == "awaiting_approval"
+    }
+}
+
+// POST /fonts/batch 中单个字体的结果：成功时 response 为单次上传的响应，失败时 error 为错误内容
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchUploadResult {
+    pub filename: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<FontActionResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchUploadResponse {
+    pub results: Vec<BatchUploadResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagsRequest {
+    pub tags: Vec<String>,
+}
+
+// POST /fonts/{name}/rename 的请求体
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenameRequest {
+    pub to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagsResponse {
+    pub name: String,
+    pub tags: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrashEntry {
+    pub name: String,
+    pub size: u64,
+    pub deleted_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrashList {
+    pub fonts: Vec<TrashEntry>,
+}
+
+// 上传者：客户端身份请求头与所用令牌的名称
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Attribution {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl std::fmt::Display for Attribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.client_id, &self.hostname) {
+            (Some(id), Some(host)) => write!(f, "{} ({})", id, host)?,
+            (Some(id), None) => write!(f, "{}", id)?,
+            (None, _) => write!(f, "anonymous client")?,
+        }
+        match &self.token {
+            Some(token) => write!(f, " with token '{}'", token),
+            None => Ok(()),
+        }
+    }
+}
+
+// 等待管理员审核的上传
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingUpload {
+    pub name: String,
+    pub sha256: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plaintext_sha256: Option<String>,
+    // 客户端文件的原始修改时间，批准后应用到字体文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    pub uploaded_by: Attribution,
+    pub submitted_at: u64,
+    // 提交时服务器上同名字体的 SHA256，批准时据此判断期间是否被替换
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PendingList {
+    pub uploads: Vec<PendingUpload>,
+}
+
+// 删除记录：同名同内容的字体不会被自动同步重新上传
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tombstone {
+    pub sha256: String,
+    pub deleted_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TombstoneEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub tombstone: Tombstone,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TombstoneList {
+    pub tombstones: Vec<TombstoneEntry>,
+}
+
+// 服务器拒绝存储的字体：文件名或家族名匹配 pattern（不区分大小写），或内容哈希等于 sha256
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockEntry {
+    pub id: String,
+    #[serde(flatten)]
+    pub rule: BlockRule,
+    // 来自服务器的 --blocklist 配置文件，不能通过接口删除
+    #[serde(default)]
+    pub configured: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BlockList {
+    pub rules: Vec<BlockEntry>,
+}
+
+// 维护模式下服务器只接受读取请求
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClientList {
+    pub clients: Vec<ConnectedClient>,
+}
+
+// 客户端安装的字体及从 name 表读取的家族与样式，无法解析时为空
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InstalledFont {
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+}
+
+impl InstalledFont {
+    // 如 "Noto Sans Bold"，没有家族名时为文件名
+    pub fn label(&self) -> String {
+        match (&self.family, &self.style) {
+            (Some(family), Some(style)) => format!("{} {}", family, style),
+            (Some(family), None) => family.clone(),
+            _ => self.filename.clone(),
+        }
+    }
+}
+
+// 管理员经 WebSocket 发给指定客户端的指令
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ClientCommand {
+    // 立即追赶事件或完整同步
+    Resync,
+    // 暂停与恢复自动下载
+    Pause,
+    Resume,
+    FetchFont { filename: String },
+}
+
+// 指令的投递状态，客户端收到后回复确认
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandStatus {
+    pub id: String,
+    pub client_id: String,
+    #[serde(flatten)]
+    pub command: ClientCommand,
+    pub sent_at: u64,
+    // 指令投递到的连接数，同一客户端可能有多个连接
+    pub connections: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acknowledged_at: Option<u64>,
+}
+
+// 客户端订阅的字体：家族名通配符（不区分大小写）、标签与格式（扩展名），各项为空时不按其过滤；
+// 字体缺少某项信息（如加密字体没有家族名，旧版服务器的事件没有标签）时该项视为匹配
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subscription {
+    pub families: Vec<String>,
+    pub tags: BTreeSet<String>,
+    pub formats: BTreeSet<String>,
+}
+
+impl Subscription {
+    pub fn new(families: &[String], tags: &[String], formats: &[String]) -> Self {
+        Self {
+            families: families.iter().map(|f| f.trim().to_lowercase()).filter(|f| !f.is_empty()).collect(),
+            tags: normalize_tags(tags),
+            formats: formats
+                .iter()
+                .map(|f| f.trim().trim_start_matches('.').to_lowercase())
+                .filter(|f| !f.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.families.is_empty() && self.tags.is_empty() && self.formats.is_empty()
+    }
+
+    pub fn matches(&self, filename: &str, family: Option<&str>, tags: Option<&BTreeSet<String>>) -> bool {
+        let format_ok = self.formats.is_empty()
+            || std::path::Path::new(filename)
+                .extension()
+                .is_some_and(|ext| self.formats.contains(&ext.to_string_lossy().to_lowercase()));
+        let family_ok = match family {
+            Some(family) if !self.families.is_empty() => {
+                let family = family.to_lowercase();
+                self.families.iter().any(|pattern| glob_matches(pattern, &family))
+            }
+            _ => true,
+        };
+        let tags_ok = match tags {
+            Some(tags) if !self.tags.is_empty() => tags.iter().any(|tag| self.tags.contains(tag)),
+            _ => true,
+        };
+        format_ok && family_ok && tags_ok
+    }
+
+    pub fn matches_font(&self, font: &FontInfo) -> bool {
+        self.matches(&font.name, font.family.as_deref(), Some(&font.tags))
+    }
+
+    // 握手头的取值，各项值经百分号编码
+    pub fn to_header(&self) -> String {
+        use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+        let families = self.families.iter().map(|f| ("family", f));
+        let tags = self.tags.iter().map(|t| ("tag", t));
+        let formats = self.formats.iter().map(|f| ("format", f));
+        families
+            .chain(tags)
+            .chain(formats)
+            .map(|(key, value)| format!("{}={}", key, utf8_percent_encode(value, NON_ALPHANUMERIC)))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    // 未知的键忽略，便于以后增加订阅条件
+    pub fn parse(header: Option<&str>) -> Self {
+        let (mut families, mut tags, mut formats) = (Vec::new(), Vec::new(), Vec::new());
+        for pair in header.unwrap_or_default().split('&') {
+            let Some((key, value)) = pair.split_once('=') else { continue };
+            let value = percent_encoding::percent_decode_str(value).decode_utf8_lossy().into_owned();
+            match key.trim() {
+                "family" => families.push(value),
+                "tag" => tags.push(value),
+                "format" => formats.push(value),
+                _ => {}
+            }
+        }
+        Self::new(&families, &tags, &formats)
+    }
+}
+
+// 完整性检查发现的问题，file 为相对字体目录的路径
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IntegrityIssue {
+    pub file: String,
+    pub expected_sha256: String,
+    // 文件缺失或无法读取时为空
+    #[serde(default)]
+    pub actual_sha256: Option<String>,
+    pub repaired: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntegrityReport {
+    pub started_at: u64,
+    pub finished_at: u64,
+    // 重新计算哈希的文件数，包括数据存储中的文件
+    pub checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn unrepaired(&self) -> usize {
+        self.issues.iter().filter(|i| !i.repaired).count()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntegrityStatus {
+    pub running: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_report: Option<IntegrityReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LargestFont {
+    pub name: String,
+    pub size: u64,
+}
+
+// 服务器字体与上传活动的汇总
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServerStats {
+    pub total_fonts: usize,
+    pub total_bytes: u64,
+    // 按扩展名统计
+    pub formats: BTreeMap<String, usize>,
+    // 加密或尚未解析的字体不计入
+    pub families: BTreeMap<String, usize>,
+    pub largest: Vec<LargestFont>,
+    pub recent_activity: Vec<EventRecord>,
+    // 事件日志中每个客户端（无客户端 ID 时为令牌名称）的上传次数
+    pub uploads_by_client: BTreeMap<String, usize>,
+}
+
+// GET /healthz 的响应，进程能处理请求即为 ok
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HealthStatus {
+    pub status: String,
+}
+
+impl HealthStatus {
+    pub fn ok() -> Self {
+        Self { status: "ok".to_string() }
+    }
+}
+
+// GET /version 的响应
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    pub version: String,
+    pub protocol: u32,
+    pub supported_protocols: Vec<u32>,
+    // 服务器另外提供 gRPC 接口时的端口，与 HTTP 接口位于同一地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_port: Option<u16>,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol: PROTOCOL_VERSION,
+            supported_protocols: (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).collect(),
+            grpc_port: None,
+        }
+    }
+
+    pub fn supports(&self, protocol: u32) -> bool {
+        self.supported_protocols.contains(&protocol)
+    }
+}
+
+// 错误类别，客户端据此显示说明并决定是否重试；旧版服务器或未知的新类别解析为 Unknown
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    // 文件名或内容命中禁止列表
+    Blocked,
+    NotFound,
+    Timeout,
+    Conflict,
+    // If-Match 指定的版本不是服务器上的当前版本
+    PreconditionFailed,
+    // 未要求覆盖时服务器上已有同名的不同字体
+    AlreadyExists,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    Unprocessable,
+    IncompatibleProtocol,
+    RateLimited,
+    // 只读维护模式
+    Maintenance,
+    Unavailable,
+    QuotaExceeded,
+    // 原子批量上传中其他字体失败，本字体未生效
+    Aborted,
+    Internal,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn for_status(status: u16) -> Self {
+        match status {
+            400 | 405 | 411 => ErrorCode::BadRequest,
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            408 => ErrorCode::Timeout,
+            409 => ErrorCode::Conflict,
+            412 => ErrorCode::PreconditionFailed,
+            413 => ErrorCode::PayloadTooLarge,
+            415 => ErrorCode::UnsupportedMediaType,
+            422 => ErrorCode::Unprocessable,
+            424 => ErrorCode::Aborted,
+            426 => ErrorCode::IncompatibleProtocol,
+            429 => ErrorCode::RateLimited,
+            502..=504 => ErrorCode::Unavailable,
+            507 => ErrorCode::QuotaExceeded,
+            500..=599 => ErrorCode::Internal,
+            _ => ErrorCode::Unknown,
+        }
+    }
+
+    // 稍后原样重试可能成功
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Timeout | ErrorCode::RateLimited | ErrorCode::Maintenance | ErrorCode::Unavailable
+        )
+    }
+}
+
+// 所有接口共用的错误格式；error 为简短标题，message 为面向用户的说明，details 为按类别而定的附加数据
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorResponse {
+    #[serde(default)]
+    pub code: ErrorCode,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+    #[serde(default)]
+    pub retryable: bool,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, error: &str, message: String) -> Self {
+        Self {
+            code,
+            error: error.to_string(),
+            message: Some(message),
+            details: None,
+            retryable: code.is_retryable(),
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema_ref(schema) } }
+    })
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, "ErrorResponse")
+}
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "description": description, "schema": { "type": "string" } })
+}
+
+fn query_param(name: &str, schema: Value, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+fn binary_response(description: &str, content_type: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { content_type: { "schema": { "type": "string", "format": "binary" } } }
+    })
+}
+
+// 批量上传、改名与回收站接口，与其余路径分开以免单个 json! 宏过大
+fn file_management_paths() -> Value {
+    let font_name = path_param("name", "Font file name");
+
+    json!({
+        "/fonts/batch": {
+            "post": {
+                "operationId": "uploadFonts",
+                "summary": "Upload several fonts in one request; each font is checked like a single upload and new fonts are announced in one FontsAdded WebSocket notification",
+                "parameters": [
+                    { "name": "overwrite", "in": "query", "required": false, "description": "Allow every font in the batch to replace a different stored font with the same name", "schema": { "type": "boolean" } },
+                    { "name": "atomic", "in": "query", "required": false, "description": "Store the fonts only if every font passes its checks and the whole form is received; otherwise nothing is stored and fonts that passed report status 424 (code aborted)", "schema": { "type": "boolean" } }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "multipart/form-data": {
+                            "schema": {
+                                "type": "object",
+                                "description": format!(
+                                    "Up to {} font parts; plaintext_sha256, readd, overwrite, modified and delta_base parts apply to the next font part only, as in POST /fonts",
+                                    MAX_BATCH_UPLOADS
+                                ),
+                                "required": ["font"],
+                                "properties": {
+                                    "font": { "type": "array", "items": { "type": "string", "format": "binary" } }
+                                }
+                            }
+                        }
+                    }
+                },
+                "responses": {
+                    "200": json_response("Per-font results; fonts after a timeout or form error are missing", "BatchUploadResponse"),
+                    "400": error_response("No font part, too many fonts or an invalid option"),
+                    "408": error_response("Upload did not start within the server's time limit"),
+                    "413": error_response("Request exceeds the server's batch size limit"),
+                    "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                }
+            }
+        },
+        "/fonts/{name}/rename": {
+            "parameters": [font_name.clone()],
+            "post": {
+                "operationId": "renameFont",
+                "summary": "Rename a font, keeping its content, tags and uploader; clients see the old name removed and the new name added",
+                "parameters": [{
+                    "name": "If-Match",
+                    "in": "header",
+                    "required": false,
+                    "description": "SHA256 (or plaintext SHA256 for encrypted fonts) of the stored version to rename, optionally quoted",
+                    "schema": { "type": "string" }
+                }],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("RenameRequest") } }
+                },
+                "responses": {
+                    "200": json_response("Renamed; filename is the new name", "FontActionResponse"),
+                    "400": error_response("The new name is not a font file name, or If-Match is malformed"),
+                    "404": error_response("Font not found"),
+                    "409": error_response("A font with the new name exists"),
+                    "412": error_response("The stored version does not match If-Match; details.current_sha256 is the server's current version"),
+                    "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                }
+            }
+        },
+        "/trash": {
+            "get": {
+                "operationId": "listTrash",
+                "summary": "List deleted fonts",
+                "responses": { "200": json_response("Deleted fonts", "TrashList") }
+            }
+        },
+        "/trash/{name}/restore": {
+            "parameters": [font_name],
+            "post": {
+                "operationId": "restoreFont",
+                "summary": "Restore a font from the trash",
+                "responses": {
+                    "200": json_response("Restored", "FontActionResponse"),
+                    "404": error_response("Font not in trash"),
+                    "409": error_response("A font with the same name exists"),
+                    "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                }
+            }
+        }
+    })
+}
+
+// 需要管理员令牌的接口，与其余路径分开以免单个 json! 宏过大
+fn admin_paths() -> Value {
+    let font_name = path_param("name", "Font file name");
+
+    json!({
+        "/admin/pending": {
+            "get": {
+                "operationId": "listPendingUploads",
+                "summary": "Uploads waiting for approval (--require-approval)",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Pending uploads", "PendingList"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            }
+        },
+        "/admin/pending/{name}/approve": {
+            "parameters": [font_name.clone()],
+            "post": {
+                "operationId": "approvePendingUpload",
+                "summary": "Store a pending upload and broadcast it like a normal upload",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Approved", "FontActionResponse"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "404": error_response("No pending upload with this name"),
+                    "409": error_response("The stored font changed after the upload was submitted")
+                }
+            }
+        },
+        "/admin/pending/{name}": {
+            "parameters": [font_name],
+            "delete": {
+                "operationId": "rejectPendingUpload",
+                "summary": "Discard a pending upload",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Rejected upload", "PendingUpload"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "404": error_response("No pending upload with this name")
+                }
+            }
+        },
+        "/admin/maintenance": {
+            "get": {
+                "operationId": "getMaintenance",
+                "summary": "Whether the server is read-only",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Maintenance status", "MaintenanceStatus"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            },
+            "put": {
+                "operationId": "setMaintenance",
+                "summary": "Enter or leave maintenance mode; uploads, deletions and other changes return 503 while enabled",
+                "security": [{ "adminToken": [] }],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("MaintenanceStatus") } }
+                },
+                "responses": {
+                    "200": json_response("New maintenance status", "MaintenanceStatus"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            }
+        },
+        "/admin/integrity": {
+            "get": {
+                "operationId": "getIntegrity",
+                "summary": "Result of the last integrity scrub of stored fonts",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Integrity status", "IntegrityStatus"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            },
+            "post": {
+                "operationId": "runIntegrityScrub",
+                "summary": "Re-hash stored fonts now, repairing mismatches if the server was started with --scrub-repair",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Scrub result", "IntegrityReport"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "409": error_response("A scrub is already running")
+                }
+            }
+        },
+        "/admin/blocklist": {
+            "get": {
+                "operationId": "listBlocklist",
+                "summary": "Fonts the server refuses to store",
+                "security": [{ "adminToken": [] }],
+                "responses": {
+                    "200": json_response("Blocklist rules", "BlockList"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            },
+            "post": {
+                "operationId": "addBlockRule",
+                "summary": "Block fonts by file or family name pattern, or by content SHA256",
+                "security": [{ "adminToken": [] }],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("BlockRule") } }
+                },
+                "responses": {
+                    "201": json_response("Added rule", "BlockEntry"),
+                    "400": error_response("Rule has neither pattern nor a valid sha256"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled")
+                }
+            }
+        },
+        "/admin/clients/{client_id}/commands": {
+            "post": {
+                "operationId": "sendClientCommand",
+                "summary": "Tell a connected client to resync, pause or resume downloads, or fetch a font",
+                "security": [{ "adminToken": [] }],
+                "parameters": [path_param("client_id", "Persistent client ID, as listed by /clients")],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("ClientCommand") } }
+                },
+                "responses": {
+                    "202": json_response("Command queued; poll /admin/commands/{id} for the acknowledgement", "CommandStatus"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "404": error_response("Client is not connected over WebSocket")
+                }
+            }
+        },
+        "/admin/commands/{id}": {
+            "get": {
+                "operationId": "getClientCommand",
+                "summary": "Delivery status of a client command",
+                "security": [{ "adminToken": [] }],
+                "parameters": [path_param("id", "Command id")],
+                "responses": {
+                    "200": json_response("Command status", "CommandStatus"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "404": error_response("Unknown or expired command")
+                }
+            }
+        },
+        "/admin/blocklist/{id}": {
+            "delete": {
+                "operationId": "removeBlockRule",
+                "summary": "Remove a blocklist rule",
+                "security": [{ "adminToken": [] }],
+                "parameters": [path_param("id", "Rule id")],
+                "responses": {
+                    "200": json_response("Removed rule", "BlockEntry"),
+                    "401": error_response("Missing or invalid admin token"),
+                    "403": error_response("Admin API disabled"),
+                    "404": error_response("Rule not found"),
+                    "409": error_response("Rule comes from the server configuration file")
+                }
+            }
+        }
+    })
+}
+
+// GET /openapi.json 返回的接口描述
+pub fn openapi() -> Value {
+    let font_name = path_param("name", "Font file name");
+    let string = json!({ "type": "string" });
+    let integer = json!({ "type": "integer", "minimum": 0 });
+
+    let mut spec = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "FontSync API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Font synchronization server. Font names in paths must be percent-encoded. When the server is started with --tokens, every font endpoint requires a bearer token whose role allows it: reader (list and download), uploader (also upload and edit tags) or admin (also delete, restore, list clients and the /admin endpoints). Missing or invalid tokens return 401, insufficient roles 403. Clients send their protocol version in the x-fontsync-protocol header; requests with a protocol version the server does not support (see /version) return 426."
+        },
+        "security": [{}, { "accessToken": [] }],
+        "paths": {
+            "/fonts": {
+                "get": {
+                    "operationId": "listFonts",
+                    "summary": "List fonts with optional filtering, sorting and pagination",
+                    "parameters": [
+                        query_param("tag", string.clone(), "Comma separated tags; fonts with any of them match"),
+                        query_param("q", string.clone(), "Case-insensitive substring of the file or family name"),
+                        query_param("format", string.clone(), "Comma separated extensions, e.g. ttf,otf"),
+                        query_param("min_size", integer.clone(), "Minimum file size in bytes"),
+                        query_param("sort", json!({ "type": "string", "enum": ["name", "size", "modified"] }), "Sort field"),
+                        query_param("offset", integer.clone(), "Number of fonts to skip"),
+                        query_param("limit", integer.clone(), "Maximum number of fonts to return"),
+                        query_param("covers", string.clone(), "Required characters, e.g. U+4E2D,U+6587")
+                    ],
+                    "responses": {
+                        "200": json_response("Matching fonts", "FontList"),
+                        "400": error_response("Invalid covers parameter")
+                    }
+                },
+                "post": {
+                    "operationId": "uploadFont",
+                    "summary": "Upload or replace a font",
+                    "parameters": [
+                        { "name": CLIENT_ID_HEADER, "in": "header", "required": false, "schema": string },
+                        { "name": HOSTNAME_HEADER, "in": "header", "required": false, "schema": string },
+                        { "name": OS_HEADER, "in": "header", "required": false, "schema": string },
+                        {
+                            "name": "If-Match",
+                            "in": "header",
+                            "required": false,
+                            "description": "SHA256 (or plaintext SHA256 for encrypted fonts) of the stored version this upload replaces, optionally quoted; * requires that the font exists. Implies overwrite",
+                            "schema": string
+                        },
+                        query_param("overwrite", json!({ "type": "boolean" }), "Replace a different stored font with the same name; without it (or If-Match) such uploads fail with 409")
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "multipart/form-data": {
+                                "schema": {
+                                    "type": "object",
+                                    "required": ["font"],
+                                    "properties": {
+                                        "font": { "type": "string", "format": "binary" },
+                                        "plaintext_sha256": {
+                                            "type": "string",
+                                            "description": "SHA256 of the plaintext for end-to-end encrypted uploads; must precede the font part"
+                                        },
+                                        "readd": {
+                                            "type": "boolean",
+                                            "description": "Explicitly re-add a font whose deletion is recorded as a tombstone; must precede the font part"
+                                        },
+                                        "overwrite": {
+                                            "type": "boolean",
+                                            "description": "Same as the overwrite query parameter; must precede the font part"
+                                        },
+                                        "modified": {
+                                            "type": "integer",
+                                            "description": "Original modification time in Unix seconds, applied to the stored file; must precede the font part"
+                                        },
+                                        "delta_base": {
+                                            "type": "string",
+                                            "description": "SHA256 of the stored version the font part is a delta against (see /fonts/{name}/signature); must precede the font part"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": json_response("Upload result", "FontActionResponse"),
+                        "202": json_response("Server requires approval; the upload is held until an admin approves it", "FontActionResponse"),
+                        "400": error_response("Missing font part or invalid plaintext_sha256"),
+                        "403": error_response("Restricted license or blocklisted font refused by server policy"),
+                        "408": error_response("Upload did not complete within the server's time limit"),
+                        "413": error_response("Upload exceeds the server's size limit"),
+                        "507": error_response("Upload would exceed the client's or token's storage quota"),
+                        "409": error_response("A different font with the same name exists and neither overwrite nor If-Match was given (code already_exists, details.current_sha256 is the stored version), same content was deleted and readd was not set, or the stored file no longer matches delta_base"),
+                        "412": error_response("The stored version does not match If-Match; details.current_sha256 is the server's current version"),
+                        "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                    }
+                }
+            },
+            "/fonts/{name}": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "downloadFont",
+                    "summary": "Download a font file",
+                    "responses": {
+                        "200": {
+                            "description": "Font file",
+                            "headers": {
+                                MODIFIED_HEADER: {
+                                    "description": "Modification time of the stored file in Unix seconds",
+                                    "schema": integer
+                                }
+                            },
+                            "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } }
+                        },
+                        "404": { "description": "Font not found" }
+                    }
+                },
+                "delete": {
+                    "operationId": "deleteFont",
+                    "summary": "Move a font to the trash",
+                    "parameters": [{
+                        "name": "If-Match",
+                        "in": "header",
+                        "required": false,
+                        "description": "SHA256 (or plaintext SHA256 for encrypted fonts) of the stored version to delete, optionally quoted",
+                        "schema": string
+                    }],
+                    "responses": {
+                        "200": json_response("Deleted", "FontActionResponse"),
+                        "400": error_response("Malformed If-Match"),
+                        "404": error_response("Font not found"),
+                        "412": error_response("The stored version does not match If-Match; details.current_sha256 is the server's current version"),
+                        "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                    }
+                }
+            },
+            "/blobs/{sha256}": {
+                "parameters": [path_param("sha256", "Content SHA256 of the stored file (ciphertext for encrypted fonts)")],
+                "get": {
+                    "operationId": "downloadBlob",
+                    "summary": "Download stored font data by content hash",
+                    "responses": {
+                        "200": binary_response("Font data", "application/octet-stream"),
+                        "404": error_response("No stored data with this hash")
+                    }
+                }
+            },
+            "/fonts/{name}/signature": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "getFontSignature",
+                    "summary": "Block signature of the stored font for delta uploads",
+                    "responses": {
+                        "200": binary_response("Rolling checksum and strong hash of each block", "application/octet-stream"),
+                        "404": error_response("Font not found")
+                    }
+                }
+            },
+            "/fonts/{name}/delta": {
+                "parameters": [font_name.clone()],
+                "post": {
+                    "operationId": "downloadFontDelta",
+                    "summary": "Download a font as a delta against the client's block signature of an older version",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Delta, or the full file when a delta would not save enough",
+                            "headers": {
+                                DELTA_HEADER: { "description": "delta or full", "schema": { "type": "string", "enum": ["delta", "full"] } },
+                                MODIFIED_HEADER: { "description": "Modification time of the stored file in Unix seconds", "schema": integer }
+                            },
+                            "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } }
+                        },
+                        "400": error_response("Invalid signature"),
+                        "404": error_response("Font not found")
+                    }
+                }
+            },
+            "/fonts/{name}/sha256": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "getFontSha256",
+                    "summary": "SHA256 of a stored font",
+                    "responses": {
+                        "200": {
+                            "description": "Digest",
+                            "content": { "application/json": { "schema": {
+                                "type": "object",
+                                "properties": { "filename": string, "sha256": string }
+                            } } }
+                        },
+                        "404": error_response("Font not found")
+                    }
+                }
+            },
+            "/fonts/{name}/preview.png": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "previewFont",
+                    "summary": "Render sample text with the font",
+                    "parameters": [
+                        query_param("text", string.clone(), "Sample text, at most 200 characters"),
+                        query_param("size", json!({ "type": "number", "minimum": 8, "maximum": 256 }), "Font size in pixels")
+                    ],
+                    "responses": {
+                        "200": binary_response("PNG image", "image/png"),
+                        "400": error_response("Invalid text or size"),
+                        "404": error_response("Font not found"),
+                        "415": error_response("Font cannot be rendered"),
+                        "422": error_response("Font is end-to-end encrypted")
+                    }
+                }
+            },
+            "/fonts/{name}/instance": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "instantiateFont",
+                    "summary": "Generate a static TrueType instance of a variable font",
+                    "parameters": [
+                        query_param("instance", string.clone(), "Named instance such as Bold; otherwise every other parameter is an axis tag with its coordinate, e.g. wght=700")
+                    ],
+                    "responses": {
+                        "200": binary_response("Static font", "font/ttf"),
+                        "400": error_response("Unknown axis, coordinate outside the axis range or unknown named instance"),
+                        "404": error_response("Font not found"),
+                        "415": error_response("Font is not a variable font with TrueType outlines"),
+                        "422": error_response("Font is end-to-end encrypted")
+                    }
+                }
+            },
+            "/fonts/{name}/subset": {
+                "parameters": [font_name.clone()],
+                "get": {
+                    "operationId": "subsetFont",
+                    "summary": "Generate a font containing only the requested characters",
+                    "parameters": [
+                        query_param("text", string.clone(), "Characters to keep"),
+                        query_param("unicodes", string.clone(), "Code point ranges to keep, e.g. U+0020-007E,U+4E00"),
+                        query_param("format", json!({ "type": "string", "enum": ["ttf", "woff", "woff2"] }), "Output format (default ttf)")
+                    ],
+                    "responses": {
+                        "200": binary_response("Subset font", "font/woff2"),
+                        "400": error_response("No characters requested or invalid ranges"),
+                        "404": error_response("Font not found"),
+                        "415": error_response("Font cannot be subset or maps none of the characters"),
+                        "422": error_response("Font is end-to-end encrypted")
+                    }
+                }
+            },
+            "/fonts/{name}/tags": {
+                "parameters": [font_name.clone()],
+                "put": {
+                    "operationId": "setFontTags",
+                    "summary": "Replace the tags of a font",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("TagsRequest") } }
+                    },
+                    "responses": {
+                        "200": json_response("Updated tags", "TagsResponse"),
+                        "404": error_response("Font not found"),
+                        "503": error_response("Server is in read-only maintenance mode; retry after the Retry-After interval")
+                    }
+                }
+            },
+            "/fonts/hashes": {
+                "get": {
+                    "operationId": "listFontHashes",
+                    "summary": "Compact name to content SHA256 map for cheap sync comparisons",
+                    "parameters": [
+                        query_param("tag", string.clone(), "Comma separated tags; fonts with any of them match")
+                    ],
+                    "responses": { "200": json_response("Content hashes", "FontHashes") }
+                }
+            },
+            "/tombstones": {
+                "get": {
+                    "operationId": "listTombstones",
+                    "summary": "Deleted fonts that sync clients must not re-upload",
+                    "responses": { "200": json_response("Deletion records", "TombstoneList") }
+                }
+            },
+            "/clients": {
+                "get": {
+                    "operationId": "listClients",
+                    "summary": "Clients connected over WebSocket",
+                    "responses": { "200": json_response("Connected clients", "ClientList") }
+                }
+            },
+            "/metrics": {
+                "get": {
+                    "operationId": "getMetrics",
+                    "summary": "Integrity scrub metrics in Prometheus text format",
+                    "responses": {
+                        "200": {
+                            "description": "Prometheus metrics",
+                            "content": { "text/plain": { "schema": { "type": "string" } } }
+                        }
+                    }
+                }
+            },
+            "/stats": {
+                "get": {
+                    "operationId": "getStats",
+                    "summary": "Font counts, storage use and recent upload activity",
+                    "responses": { "200": json_response("Server statistics", "ServerStats") }
+                }
+            },
+            "/events": {
+                "get": {
+                    "operationId": "listEvents",
+                    "summary": "Font change events after a sequence number",
+                    "parameters": [
+                        query_param("since", integer.clone(), "Return events with a greater sequence number"),
+                        query_param("limit", integer, "Maximum number of events, 1000 by default")
+                    ],
+                    "responses": { "200": json_response("Events", "EventPage") }
+                }
+            },
+            "/manifest": {
+                "get": {
+                    "operationId": "getManifest",
+                    "summary": "Signed manifest of all fonts",
+                    "responses": { "200": json_response("Signed manifest", "SignedManifest") }
+                }
+            },
+            "/signing-key": {
+                "get": {
+                    "operationId": "getSigningKey",
+                    "summary": "Manifest signing public key",
+                    "responses": { "200": json_response("Public key", "PublicKeyInfo") }
+                }
+            },
+            "/webfonts/{file}": {
+                "parameters": [path_param("file", "{family}.css for a stylesheet or {family}.zip for a webfont kit")],
+                "get": {
+                    "operationId": "getWebfonts",
+                    "summary": "@font-face stylesheet or downloadable webfont kit for a family",
+                    "parameters": [
+                        query_param("format", json!({ "type": "string", "enum": ["woff2", "woff", "original"] }), "Font file format; woff2 falls back to woff in the stylesheet")
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Stylesheet or zip archive",
+                            "content": {
+                                "text/css": { "schema": string },
+                                "application/zip": { "schema": { "type": "string", "format": "binary" } }
+                            }
+                        },
+                        "404": error_response("Family not found")
+                    }
+                }
+            },
+            "/webfonts/files/{file}": {
+                "parameters": [path_param("file", "Font file name, or the file name followed by .woff or .woff2")],
+                "get": {
+                    "operationId": "getWebfontFile",
+                    "summary": "Font file referenced by a webfont stylesheet",
+                    "responses": {
+                        "200": binary_response("Font file", "font/woff"),
+                        "404": error_response("Font not found")
+                    }
+                }
+            },
+            "/healthz": {
+                "get": {
+                    "operationId": "getHealth",
+                    "summary": "Liveness check for clients and load balancers; requires no token",
+                    "responses": { "200": json_response("Server is up", "HealthStatus") }
+                }
+            },
+            "/version": {
+                "get": {
+                    "operationId": "getVersion",
+                    "summary": "Server version and supported protocol versions; requires no token",
+                    "responses": { "200": json_response("Server version", "VersionInfo") }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "operationId": "getOpenApi",
+                    "summary": "This document",
+                    "responses": { "200": { "description": "OpenAPI document" } }
+                }
+            }
+        },
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "accessToken": { "type": "http", "scheme": "bearer", "description": "Token with the reader, uploader or admin role" },
+                "adminToken": { "type": "http", "scheme": "bearer" }
+            }
+        }
+    });
+    if let (Some(paths), Value::Object(files)) = (spec["paths"].as_object_mut(), file_management_paths()) {
+        paths.extend(files);
+    }
+    if let (Some(paths), Value::Object(admin)) = (spec["paths"].as_object_mut(), admin_paths()) {
+        paths.extend(admin);
+    }
+    spec
+}
+
+fn schemas() -> Value {
+    let string = json!({ "type": "string" });
+    let integer = json!({ "type": "integer", "minimum": 0 });
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let embedding = json!({
+        "type": "string",
+        "enum": ["installable", "restricted", "preview-and-print", "editable"]
+    });
+
+    let mut schemas = json!({
+        "FontInfo": {
+            "type": "object",
+            "required": ["name", "size", "mime_type", "sha256"],
+            "properties": {
+                "name": string,
+                "size": integer,
+                "mime_type": string,
+                "sha256": { "type": "string", "nullable": true, "description": "Null while the server is still hashing the file" },
+                "status": {
+                    "type": "string",
+                    "enum": ["hashing", "awaiting_approval"],
+                    "description": "hashing while sha256 is not yet available; awaiting_approval for uploads an admin has not approved yet, which cannot be downloaded"
+                },
+                "modified": { "type": "integer", "description": "Unix timestamp in seconds" },
+                "plaintext_sha256": { "type": "string", "description": "Set for end-to-end encrypted fonts" },
+                "embedding": embedding,
+                "restricted": { "type": "boolean" },
+                "family": string,
+                "style": string,
+                "version": string,
+                "unicode_ranges": strings,
+                "tags": strings,
+                "uploaded_by": schema_ref("Attribution")
+            }
+        },
+        "Attribution": {
+            "type": "object",
+            "description": "Client that last uploaded the font; fields are missing when the uploader did not identify itself",
+            "properties": {
+                "client_id": string,
+                "hostname": string,
+                "token": { "type": "string", "description": "Name of the access token used for the upload" }
+            }
+        },
+        "FontList": {
+            "type": "object",
+            "required": ["fonts"],
+            "properties": {
+                "fonts": { "type": "array", "items": schema_ref("FontInfo") },
+                "total": { "type": "integer", "description": "Matching fonts before pagination" }
+            }
+        },
+        "FontActionResponse": {
+            "type": "object",
+            "required": ["success", "filename", "action"],
+            "properties": {
+                "success": { "type": "boolean" },
+                "filename": string,
+                "action": { "type": "string", "enum": ["added", "modified", "unchanged", "deleted", "restored"] },
+                "sha256": string,
+                "size": integer,
+                "embedding": embedding,
+                "modified": { "type": "integer", "description": "Modification time of the stored file in Unix seconds" },
+                "message": string
+            }
+        },
+        "BatchUploadResult": {
+            "type": "object",
+            "required": ["filename", "status"],
+            "properties": {
+                "filename": string,
+                "status": { "type": "integer", "description": "HTTP status the font would have had as a single upload" },
+                "response": schema_ref("FontActionResponse"),
+                "error": schema_ref("ErrorResponse")
+            }
+        },
+        "BatchUploadResponse": {
+            "type": "object",
+            "required": ["results"],
+            "properties": { "results": { "type": "array", "items": schema_ref("BatchUploadResult") } }
+        },
+        "FontHashes": {
+            "type": "object",
+            "required": ["fonts", "digest"],
+            "properties": {
+                "fonts": {
+                    "type": "object",
+                    "description": "Font name to content SHA256 (plaintext for encrypted fonts)",
+                    "additionalProperties": string
+                },
+                "digest": { "type": "string", "description": "SHA256 over the sorted name/hash pairs" },
+                "pending": { "type": "array", "items": string, "description": "Fonts the server is still hashing, not included in fonts" }
+            }
+        },
+        "TagsRequest": {
+            "type": "object",
+            "required": ["tags"],
+            "properties": { "tags": strings }
+        },
+        "RenameRequest": {
+            "type": "object",
+            "required": ["to"],
+            "properties": { "to": string }
+        },
+        "TagsResponse": {
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": { "name": string, "tags": strings }
+        },
+        "TrashEntry": {
+            "type": "object",
+            "required": ["name", "size", "deleted_at"],
+            "properties": { "name": string, "size": integer, "deleted_at": integer }
+        },
+        "TrashList": {
+            "type": "object",
+            "required": ["fonts"],
+            "properties": { "fonts": { "type": "array", "items": schema_ref("TrashEntry") } }
+        },
+        "TombstoneEntry": {
+            "type": "object",
+            "required": ["name", "sha256", "deleted_at"],
+            "properties": {
+                "name": string,
+                "sha256": { "type": "string", "description": "Content SHA256 (plaintext for encrypted fonts) at deletion" },
+                "deleted_at": integer
+            }
+        },
+        "TombstoneList": {
+            "type": "object",
+            "required": ["tombstones"],
+            "properties": { "tombstones": { "type": "array", "items": schema_ref("TombstoneEntry") } }
+        },
+        "ConnectedClient": {
+            "type": "object",
+            "required": ["client_id", "addr", "connected_at"],
+            "properties": {
+                "client_id": string,
+                "addr": string,
+                "hostname": string,
+                "os": string,
+                "connected_at": integer,
+                "rtt_ms": { "type": "number", "minimum": 0, "description": "Last measured Ping/Pong round-trip time" },
+                "dropped_events": { "type": "integer", "minimum": 0, "description": "Events dropped because the client's send queue was full" }
+            }
+        },
+        "ClientList": {
+            "type": "object",
+            "required": ["clients"],
+            "properties": { "clients": { "type": "array", "items": schema_ref("ConnectedClient") } }
+        },
+        "EventRecord": {
+            "type": "object",
+            "required": ["seq", "timestamp", "event"],
+            "properties": {
+                "seq": integer,
+                "timestamp": integer,
+                "event": {
+                    "type": "object",
+                    "description": "FontAdded, FontModified, FontRemoved or FontRenamed with its data",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["FontAdded", "FontModified", "FontRemoved", "FontRenamed"] },
+                        "data": { "type": "object" }
+                    }
+                },
+                "actor": schema_ref("Attribution"),
+                "request_id": string
+            }
+        },
+        "LargestFont": {
+            "type": "object",
+            "required": ["name", "size"],
+            "properties": { "name": string, "size": integer }
+        },
+        "HealthStatus": {
+            "type": "object",
+            "required": ["status"],
+            "properties": { "status": string }
+        },
+        "VersionInfo": {
+            "type": "object",
+            "required": ["version", "protocol", "supported_protocols"],
+            "properties": {
+                "version": string,
+                "protocol": integer,
+                "supported_protocols": { "type": "array", "items": integer },
+                "grpc_port": integer
+            }
+        },
+        "ServerStats": {
+            "type": "object",
+            "required": ["total_fonts", "total_bytes", "formats", "families", "largest", "recent_activity", "uploads_by_client"],
+            "properties": {
+                "total_fonts": integer,
+                "total_bytes": integer,
+                "formats": { "type": "object", "additionalProperties": integer, "description": "Font count per file extension" },
+                "families": { "type": "object", "additionalProperties": integer, "description": "Font count per family; encrypted fonts are not counted" },
+                "largest": { "type": "array", "items": schema_ref("LargestFont") },
+                "recent_activity": { "type": "array", "items": schema_ref("EventRecord") },
+                "uploads_by_client": { "type": "object", "additionalProperties": integer, "description": "Uploads per client ID, or token name for clients without an ID" }
+            }
+        },
+        "EventPage": {
+            "type": "object",
+            "required": ["events", "latest_seq"],
+            "properties": {
+                "events": { "type": "array", "items": schema_ref("EventRecord") },
+                "latest_seq": integer,
+                "truncated": { "type": "boolean", "description": "Events after `since` were dropped by log retention; clients must do a full sync" }
+            }
+        },
+        "SignedManifest": {
+            "type": "object",
+            "required": ["payload", "signature", "public_key"],
+            "properties": {
+                "payload": { "type": "string", "description": "JSON manifest exactly as signed" },
+                "signature": string,
+                "public_key": string
+            }
+        },
+        "PublicKeyInfo": {
+            "type": "object",
+            "required": ["algorithm", "public_key"],
+            "properties": { "algorithm": string, "public_key": string }
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["code", "error", "retryable"],
+            "properties": {
+                "code": {
+                    "type": "string",
+                    "enum": [
+                        "bad_request", "unauthorized", "forbidden", "blocked", "not_found", "timeout", "conflict",
+                        "precondition_failed", "already_exists", "payload_too_large", "unsupported_media_type", "unprocessable", "incompatible_protocol",
+                        "rate_limited", "maintenance", "unavailable", "quota_exceeded", "aborted", "internal"
+                    ]
+                },
+                "error": { "type": "string", "description": "Short title" },
+                "message": { "type": "string", "description": "Explanation for the user" },
+                "details": { "type": "object", "description": "Code specific data, e.g. max_size for payload_too_large" },
+                "retryable": { "type": "boolean", "description": "Whether repeating the request later may succeed" }
+            }
+        }
+    });
+    if let (Some(schemas), Value::Object(admin)) = (schemas.as_object_mut(), admin_schemas()) {
+        schemas.extend(admin);
+    }
+    schemas
+}
+
+// 管理接口使用的类型
+fn admin_schemas() -> Value {
+    let string = json!({ "type": "string" });
+    let integer = json!({ "type": "integer", "minimum": 0 });
+
+    json!({
+        "BlockRule": {
+            "type": "object",
+            "required": ["reason"],
+            "properties": {
+                "pattern": { "type": "string", "description": "Glob matched against the file and family name, case-insensitive" },
+                "sha256": { "type": "string", "description": "Content SHA256 (plaintext for encrypted fonts)" },
+                "reason": string
+            }
+        },
+        "BlockEntry": {
+            "type": "object",
+            "required": ["id", "reason", "configured"],
+            "properties": {
+                "id": string,
+                "pattern": string,
+                "sha256": string,
+                "reason": string,
+                "configured": { "type": "boolean", "description": "Loaded from the server configuration file and read-only" }
+            }
+        },
+        "BlockList": {
+            "type": "object",
+            "required": ["rules"],
+            "properties": { "rules": { "type": "array", "items": schema_ref("BlockEntry") } }
+        },
+        "PendingUpload": {
+            "type": "object",
+            "required": ["name", "sha256", "size", "uploaded_by", "submitted_at"],
+            "properties": {
+                "name": string,
+                "sha256": string,
+                "size": integer,
+                "plaintext_sha256": string,
+                "modified": integer,
+                "uploaded_by": schema_ref("Attribution"),
+                "submitted_at": integer,
+                "replaces": { "type": "string", "description": "SHA256 of the stored font this upload replaces, absent for new fonts" }
+            }
+        },
+        "PendingList": {
+            "type": "object",
+            "required": ["uploads"],
+            "properties": { "uploads": { "type": "array", "items": schema_ref("PendingUpload") } }
+        },
+        "MaintenanceStatus": {
+            "type": "object",
+            "required": ["enabled"],
+            "properties": {
+                "enabled": { "type": "boolean" },
+                "message": { "type": "string", "description": "Shown to clients whose changes are refused" }
+            }
+        },
+        "ClientCommand": {
+            "type": "object",
+            "required": ["command"],
+            "properties": {
+                "command": { "type": "string", "enum": ["resync", "pause", "resume", "fetch_font"] },
+                "filename": { "type": "string", "description": "Font to download, for fetch_font" }
+            }
+        },
+        "CommandStatus": {
+            "type": "object",
+            "required": ["id", "client_id", "command", "sent_at", "connections"],
+            "properties": {
+                "id": string,
+                "client_id": string,
+                "command": { "type": "string", "enum": ["resync", "pause", "resume", "fetch_font"] },
+                "filename": string,
+                "sent_at": integer,
+                "connections": integer,
+                "acknowledged_at": { "type": "integer", "minimum": 0, "description": "Unix time the client confirmed receipt" }
+            }
+        },
+        "IntegrityIssue": {
+            "type": "object",
+            "required": ["file", "expected_sha256", "repaired"],
+            "properties": {
+                "file": { "type": "string", "description": "Path relative to the font directory" },
+                "expected_sha256": string,
+                "actual_sha256": { "type": "string", "nullable": true, "description": "Null when the file is missing or unreadable" },
+                "repaired": { "type": "boolean" }
+            }
+        },
+        "IntegrityReport": {
+            "type": "object",
+            "required": ["started_at", "finished_at", "checked", "issues"],
+            "properties": {
+                "started_at": integer,
+                "finished_at": integer,
+                "checked": integer,
+                "issues": { "type": "array", "items": schema_ref("IntegrityIssue") }
+            }
+        },
+        "IntegrityStatus": {
+            "type": "object",
+            "required": ["running"],
+            "properties": {
+                "running": { "type": "boolean" },
+                "last_report": schema_ref("IntegrityReport")
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 描述中的属性必须覆盖类型序列化出的全部字段
+    fn assert_documented<T: Serialize>(schema: &str, value: &T) {
+        let spec = openapi();
+        let properties = &spec["components"]["schemas"][schema]["properties"];
+        for key in serde_json::to_value(value).unwrap().as_object().unwrap().keys() {
+            assert!(properties.get(key).is_some(), "{}.{} is not documented", schema, key);
+        }
+    }
+
+    #[test]
+    fn installed_fonts_are_labelled_by_family_and_style() {
+        let font = |family: Option<&str>, style: Option<&str>| InstalledFont {
+            filename: "NotoSans-Bold.ttf".to_string(),
+            family: family.map(str::to_string),
+            style: style.map(str::to_string),
+        };
+        assert_eq!(font(Some("Noto Sans"), Some("Bold")).label(), "Noto Sans Bold");
+        assert_eq!(font(Some("Noto Sans"), None).label(), "Noto Sans");
+        // 只有样式时无法识别字体，以文件名标识
+        assert_eq!(font(None, Some("Bold")).label(), "NotoSans-Bold.ttf");
+
+        // 旧版客户端上报的字体只有文件名
+        let value = serde_json::to_value(font(None, None)).unwrap();
+        assert_eq!(value, serde_json::json!({ "filename": "NotoSans-Bold.ttf" }));
+        let parsed: InstalledFont = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, font(None, None));
+    }
+
+    #[test]
+    fn openapi_documents_shared_types() {
+        let font = FontInfo {
+            name: "a.ttf".to_string(),
+            size: 1,
+            mime_type: "font/ttf".to_string(),
+            sha256: "00".to_string(),
+            status: Some(FontStatus::Hashing),
+            modified: Some(1),
+            plaintext_sha256: Some("11".to_string()),
+            embedding: Some(EmbeddingPermission::Installable),
+            restricted: false,
+            family: Some("A".to_string()),
+            style: Some("Regular".to_string()),
+            version: Some("Version 1.000".to_string()),
+            unicode_ranges: vec!["Latin".to_string()],
+            tags: BTreeSet::from(["brand".to_string()]),
+            uploaded_by: Some(Attribution {
+                client_id: Some("c1".to_string()),
+                hostname: Some("host".to_string()),
+                token: Some("design".to_string()),
+            }),
+        };
+        assert_documented("FontInfo", &font);
+        // 待定条目的哈希以 null 传输
+        let pending = serde_json::to_value(FontInfo { sha256: String::new(), ..font.clone() }).unwrap();
+        assert!(pending["sha256"].is_null());
+        let parsed: FontInfo = serde_json::from_value(pending).unwrap();
+        assert!(parsed.is_pending() && parsed.sha256.is_empty());
+        assert_documented("FontList", &FontList { fonts: Vec::new(), total: Some(0) });
+        assert_documented(
+            "FontActionResponse",
+            &FontActionResponse {
+                success: true,
+                filename: "a.ttf".to_string(),
+                action: "uploaded".to_string(),
+                sha256: Some("00".to_string()),
+                size: Some(1),
+                embedding: Some(EmbeddingPermission::Installable),
+                modified: Some(1),
+                message: Some("ok".to_string()),
+            },
+        );
+        assert_documented("RenameRequest", &RenameRequest { to: "b.ttf".to_string() });
+        let failed = BatchUploadResult {
+            filename: "b.ttf".to_string(),
+            status: 409,
+            response: None,
+            error: Some(ErrorResponse::new(ErrorCode::for_status(409), "e", "m".to_string())),
+        };
+        assert_documented("BatchUploadResult", &failed);
+        assert_documented("BatchUploadResponse", &BatchUploadResponse { results: vec![failed] });
+        assert_documented("TrashEntry", &TrashEntry { name: "a.ttf".to_string(), size: 1, deleted_at: 1 });
+        assert_documented(
+            "TombstoneEntry",
+            &TombstoneEntry { name: "a.ttf".to_string(), tombstone: Tombstone { sha256: "00".to_string(), deleted_at: 1 } },
+        );
+        assert_documented(
+            "BlockEntry",
+            &BlockEntry {
+                id: "1".to_string(),
+                rule: BlockRule { pattern: Some("*".to_string()), sha256: Some("00".to_string()), reason: "r".to_string() },
+                configured: false,
+            },
+        );
+        assert_documented(
+            "FontHashes",
+            &FontHashes {
+                pending: BTreeSet::from(["b.ttf".to_string()]),
+                ..FontHashes::new(BTreeMap::from([("a.ttf".to_string(), "00".to_string())]))
+            },
+        );
+        let upload = PendingUpload {
+            name: "a.ttf".to_string(),
+            sha256: "00".to_string(),
+            size: 1,
+            plaintext_sha256: None,
+            modified: Some(1),
+            uploaded_by: Attribution::default(),
+            submitted_at: 1,
+            replaces: Some("01".to_string()),
+        };
+        assert_documented("PendingUpload", &upload);
+        assert_documented("Attribution", font.uploaded_by.as_ref().unwrap());
+        assert_documented("PendingList", &PendingList { uploads: vec![upload] });
+        assert_documented("MaintenanceStatus", &MaintenanceStatus { enabled: true, message: Some("backup".to_string()) });
+        assert_documented("ServerStats", &ServerStats::default());
+        let version = VersionInfo::current();
+        assert!(version.supports(PROTOCOL_VERSION) && !version.supports(PROTOCOL_VERSION + 1));
+        assert_documented("VersionInfo", &VersionInfo { grpc_port: Some(50051), ..version });
+        assert_documented("HealthStatus", &HealthStatus::ok());
+        let issue = IntegrityIssue {
+            file: "a.ttf".to_string(),
+            expected_sha256: "00".to_string(),
+            actual_sha256: None,
+            repaired: false,
+        };
+        let report = IntegrityReport { issues: vec![issue.clone()], ..Default::default() };
+        assert_documented("IntegrityIssue", &issue);
+        assert_documented("IntegrityReport", &report);
+        assert_documented("IntegrityStatus", &IntegrityStatus { running: false, last_report: Some(report) });
+        assert_documented("LargestFont", &LargestFont { name: "a.ttf".to_string(), size: 1 });
+        let command = ClientCommand::FetchFont { filename: "a.ttf".to_string() };
+        assert_documented("ClientCommand", &command);
+        assert_documented(
+            "CommandStatus",
+            &CommandStatus {
+                id: "1".to_string(),
+                client_id: "c1".to_string(),
+                command,
+                sent_at: 1,
+                connections: 1,
+                acknowledged_at: Some(2),
+            },
+        );
+        let error = ErrorResponse::new(ErrorCode::Maintenance, "e", "m".to_string()).with_details(json!({ "retry_after": 30 }));
+        assert!(error.retryable);
+        assert_documented("ErrorResponse", &error);
+        // 旧版服务器的错误与未知的新类别
+        let old: ErrorResponse = serde_json::from_str(r#"{"error": "e"}"#).unwrap();
+        assert_eq!((old.code, old.retryable), (ErrorCode::Unknown, false));
+        let newer: ErrorResponse = serde_json::from_str(r#"{"code": "from_the_future", "error": "e", "retryable": true}"#).unwrap();
+        assert_eq!(newer.code, ErrorCode::Unknown);
+        assert_eq!(ErrorCode::for_status(507), ErrorCode::QuotaExceeded);
+        assert!(ErrorCode::for_status(504).is_retryable() && !ErrorCode::for_status(500).is_retryable());
+
+        // 所有引用都应指向已定义的组件
+        let spec = openapi().to_string();
+        for reference in spec.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(openapi()["components"]["schemas"].get(name).is_some(), "missing schema {}", name);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use futures::{StreamExt, TryStreamExt};
+use log::warn;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::api::{FontInfo, FontQuery};
+use crate::client::ApiClient;
+use crate::delta;
+use crate::utils::{calculate_sha256, format_file_size};
+
+// 比较的并发传输数
+pub const PARALLELISM: [usize; 4] = [1, 2, 4, 8];
+// 每个并发级别传输的字体数
+const TRANSFER_SAMPLE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub label: String,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Measurement {
+    fn new(label: impl Into<String>, bytes: u64, elapsed: Duration) -> Self {
+        Self { label: label.into(), bytes, elapsed }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        (self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+// 对同一块随机数据分别计算各种哈希；文件一项包含读取临时文件的开销
+pub fn hashing(size: usize) -> Result<Vec<Measurement>> {
+    let mut data = vec![0u8; size];
+    rand::thread_rng().fill_bytes(&mut data);
+    let bytes = size as u64;
+
+    let mut results = Vec::new();
+    let started = Instant::now();
+    black_box(Sha256::digest(&data));
+    results.push(Measurement::new("SHA-256", bytes, started.elapsed()));
+
+    let started = Instant::now();
+    black_box(openssl::sha::sha256(&data));
+    results.push(Measurement::new("SHA-256 (OpenSSL)", bytes, started.elapsed()));
+
+    let started = Instant::now();
+    black_box(Sha512::digest(&data));
+    results.push(Measurement::new("SHA-512", bytes, started.elapsed()));
+
+    let started = Instant::now();
+    black_box(delta::Signature::of(&data));
+    results.push(Measurement::new("Delta signature", bytes, started.elapsed()));
+
+    let file = tempfile::NamedTempFile::new()?;
+    std::fs::write(file.path(), &data)?;
+    let started = Instant::now();
+    black_box(calculate_sha256(file.path())?);
+    results.push(Measurement::new("SHA-256 (file)", bytes, started.elapsed()));
+    Ok(results)
+}
+
+// 服务器上最大的若干字体，作为传输测量的样本；端到端加密的字体无法原样重新上传，不参与
+pub async fn transfer_sample(api: &ApiClient) -> Result<Vec<FontInfo>> {
+    let mut fonts = api.list_fonts_hashed(&FontQuery::default()).await?.fonts;
+    fonts.retain(|font| font.plaintext_sha256.is_none());
+    fonts.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    fonts.truncate(TRANSFER_SAMPLE);
+    Ok(fonts)
+}
+
+// 以各并发数下载样本，只保存在内存中
+pub async fn downloads(api: &ApiClient, fonts: &[FontInfo]) -> Result<Vec<Measurement>> {
+    let mut results = Vec::new();
+    for parallel in PARALLELISM {
+        let started = Instant::now();
+        let bytes: u64 = futures::stream::iter(fonts)
+            .map(|font| async move { api.fetch_font(&font.name).await.map(|(data, _)| data.len() as u64) })
+            .buffer_unordered(parallel)
+            .try_fold(0, |total, len| async move { Ok(total + len) })
+            .await?;
+        results.push(Measurement::new(format!("{} parallel", parallel), bytes, started.elapsed()));
+    }
+    Ok(results)
+}
+
+// 以各并发数把样本以临时名称上传，每轮结束后删除；返回测量结果与删除失败的名称
+pub async fn uploads(api: &ApiClient, fonts: &[FontInfo]) -> Result<(Vec<Measurement>, Vec<String>)> {
+    let dir = tempfile::tempdir()?;
+    let mut files: Vec<(PathBuf, String, u64)> = Vec::new();
+    for font in fonts {
+        let (data, _) = api.fetch_font(&font.name).await?;
+        let path = dir.path().join(&font.name);
+        std::fs::write(&path, &data).with_context(|| format!("Failed to write {:?}", path))?;
+        let sha256 = calculate_sha256(&path)?;
+        files.push((path, sha256, data.len() as u64));
+    }
+
+    let run = uuid::Uuid::new_v4().simple().to_string();
+    let mut results = Vec::new();
+    let mut leftovers = Vec::new();
+    for parallel in PARALLELISM {
+        let names: Vec<String> = fonts
+            .iter()
+            .map(|font| format!("fontsync-bench-{}-{}-{}", &run[..8], parallel, font.name))
+            .collect();
+        let started = Instant::now();
+        let uploaded = futures::stream::iter(files.iter().zip(&names))
+            .map(|((path, sha256, size), name)| async move {
+                api.upload_font(path, name, sha256, None, true).await.map(|_| *size)
+            })
+            .buffer_unordered(parallel)
+            .try_fold(0, |total, len| async move { Ok(total + len) })
+            .await;
+        let elapsed = started.elapsed();
+        for name in &names {
+            if let Err(e) = api.delete_font(name, None).await {
+                warn!("Failed to remove benchmark upload '{}': {}", name, e);
+                leftovers.push(name.clone());
+            }
+        }
+        results.push(Measurement::new(format!("{} parallel", parallel), uploaded?, elapsed));
+    }
+    Ok((results, leftovers))
+}
+
+// 吞吐量最高的一项
+pub fn fastest(results: &[Measurement]) -> Option<&Measurement> {
+    results.iter().max_by_key(|m| m.bytes_per_second())
+}
+
+pub fn print_table(title: &str, results: &[Measurement]) {
+    println!("{}", console::style(title).bold());
+    let best = fastest(results).map(|m| m.bytes_per_second());
+    for result in results {
+        let marker = if Some(result.bytes_per_second()) == best && results.len() > 1 { "*" } else { " " };
+        println!(
+            "  {:<20} {:>12}/s  {:>10} in {:>8.2?} {}",
+            result.label,
+            format_file_size(result.bytes_per_second()),
+            format_file_size(result.bytes),
+            result.elapsed,
+            marker
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_hash_algorithm_is_measured() {
+        let results = hashing(64 * 1024).unwrap();
+        let labels: Vec<&str> = results.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(labels, ["SHA-256", "SHA-256 (OpenSSL)", "SHA-512", "Delta signature", "SHA-256 (file)"]);
+        assert!(results.iter().all(|m| m.bytes == 64 * 1024));
+        assert!(fastest(&results).is_some());
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STORE_DIR: &str = ".fontsync";
+const BLOBS_DIR: &str = "blobs";
+const NAMES_FILE: &str = "names.json";
+
+// 按内容 SHA256 保存的字体数据，字体目录中的同名文件是指向数据的硬链接，
+// 相同内容以不同名称上传时只保存一份
+pub struct BlobStore {
+    blobs_dir: PathBuf,
+    tmp_dir: PathBuf,
+    names_path: PathBuf,
+    // 文件名到内容 SHA256，写锁同时保证链接与垃圾回收不会交错
+    names: RwLock<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcSummary {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+impl BlobStore {
+    pub fn open(font_dir: &Path) -> Result<Self> {
+        let dir = font_dir.join(STORE_DIR);
+        let blobs_dir = dir.join(BLOBS_DIR);
+        fs::create_dir_all(&blobs_dir).context("Failed to create blob directory")?;
+        let names_path = dir.join(NAMES_FILE);
+
+        let names = if names_path.exists() {
+            let content = fs::read_to_string(&names_path).context("Failed to read name table")?;
+            serde_json::from_str(&content).context("Failed to parse name table")?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            blobs_dir,
+            tmp_dir: dir.join("tmp"),
+            names_path,
+            names: RwLock::new(names),
+        })
+    }
+
+    pub fn blob_path(&self, sha256: &str) -> Option<PathBuf> {
+        let valid = sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit());
+        let path = self.blobs_dir.join(sha256.to_lowercase());
+        (valid && path.is_file()).then_some(path)
+    }
+
+    pub fn sha256_of(&self, name: &str) -> Option<String> {
+        self.names.read().get(name).cloned()
+    }
+
+    // 名称表快照：文件名到内容 SHA256
+    pub fn names(&self) -> BTreeMap<String, String> {
+        self.names.read().clone()
+    }
+
+    // 数据文件的路径，不检查是否存在
+    pub fn blob_file(&self, sha256: &str) -> PathBuf {
+        self.blobs_dir.join(sha256)
+    }
+
+    // font_path 是否与数据是同一个文件（硬链接）
+    pub fn is_linked(&self, sha256: &str, font_path: &Path) -> bool {
+        same_file(&self.blob_file(sha256), font_path)
+    }
+
+    // 用校验过的副本替换损坏或缺失的数据，并重新链接引用该数据的所有字体
+    pub fn restore(&self, sha256: &str, source: &Path, font_dir: &Path) -> Result<Vec<String>> {
+        let names = self.names.write();
+        fs::create_dir_all(&self.tmp_dir).context("Failed to create temporary directory")?;
+        // 复制而不是链接，修复后的数据不再与损坏的文件共用 inode
+        let tmp_path = self.tmp_dir.join(uuid::Uuid::new_v4().to_string());
+        fs::copy(source, &tmp_path).context("Failed to copy repair source")?;
+        let blob = self.blob_file(sha256);
+        fs::rename(&tmp_path, &blob).context("Failed to replace blob")?;
+
+        let relinked: Vec<String> = names.iter().filter(|(_, s)| *s == sha256).map(|(n, _)| n.clone()).collect();
+        for name in &relinked {
+            self.link_blob(&blob, &font_dir.join(name))?;
+        }
+        Ok(relinked)
+    }
+
+    // 将已写完的临时文件存为数据并链接到 font_path，内容已存在时丢弃临时文件
+    pub fn store(&self, tmp_path: &Path, sha256: &str, name: &str, font_path: &Path) -> Result<()> {
+        let mut names = self.names.write();
+        let blob = self.blobs_dir.join(sha256);
+        if blob.is_file() {
+            fs::remove_file(tmp_path).context("Failed to remove duplicate upload")?;
+        } else {
+            fs::rename(tmp_path, &blob).context("Failed to store blob")?;
+        }
+        self.link_blob(&blob, font_path)?;
+        names.insert(name.to_string(), sha256.to_string());
+        self.persist(&names)
+    }
+
+    // 把字体目录中已有的文件纳入存储：已有相同内容时改为链接到该数据
+    pub fn adopt(&self, name: &str, sha256: &str, font_path: &Path) -> Result<()> {
+        let mut names = self.names.write();
+        let blob = self.blobs_dir.join(sha256);
+        if blob.is_file() {
+            if !same_file(&blob, font_path) {
+                self.link_blob(&blob, font_path)?;
+            }
+        } else if fs::hard_link(font_path, &blob).is_err() {
+            fs::copy(font_path, &blob).context("Failed to store blob")?;
+        }
+        if names.get(name).map(String::as_str) == Some(sha256) {
+            return Ok(());
+        }
+        names.insert(name.to_string(), sha256.to_string());
+        self.persist(&names)
+    }
+
+    // 字体移入回收站或被删除后调用，数据由垃圾回收处理
+    pub fn unlink(&self, name: &str) -> Result<()> {
+        let mut names = self.names.write();
+        if names.remove(name).is_some() {
+            self.persist(&names)?;
+        }
+        Ok(())
+    }
+
+    // 删除名称表中没有引用的数据
+    pub fn collect_garbage(&self) -> Result<GcSummary> {
+        let names = self.names.write();
+        let referenced: HashSet<&str> = names.values().map(String::as_str).collect();
+        let mut summary = GcSummary::default();
+
+        for entry in fs::read_dir(&self.blobs_dir).context("Failed to read blob directory")? {
+            let entry = entry.context("Failed to read blob entry")?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if referenced.contains(name.as_str()) {
+                continue;
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            match fs::remove_file(entry.path()) {
+                Ok(()) => {
+                    summary.removed += 1;
+                    summary.freed += size;
+                }
+                Err(e) => warn!("Failed to remove unreferenced blob {}: {}", name, e),
+            }
+        }
+
+        if summary.removed > 0 {
+            info!("Removed {} unreferenced blobs ({} bytes)", summary.removed, summary.freed);
+        }
+        Ok(summary)
+    }
+
+    // 先在临时目录创建链接再替换，避免目标文件短暂缺失
+    fn link_blob(&self, blob: &Path, font_path: &Path) -> Result<()> {
+        fs::create_dir_all(&self.tmp_dir).context("Failed to create temporary directory")?;
+        let tmp_link = self.tmp_dir.join(uuid::Uuid::new_v4().to_string());
+        // 不支持硬链接的文件系统退回复制
+        if fs::hard_link(blob, &tmp_link).is_err() {
+            fs::copy(blob, &tmp_link).context("Failed to copy blob")?;
+        }
+        fs::rename(&tmp_link, font_path).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp_link);
+        }).context("Failed to link font to blob")
+    }
+
+    fn persist(&self, names: &BTreeMap<String, String>) -> Result<()> {
+        let content = serde_json::to_string_pretty(names).context("Failed to serialize name table")?;
+        let tmp_path = self.names_path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write name table")?;
+        fs::rename(&tmp_path, &self.names_path).context("Failed to replace name table")?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+// 无法比较 inode 时重新链接，结果相同
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::calculate_sha256;
+
+    #[test]
+    fn stores_identical_content_once() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = BlobStore::open(dir.path()).expect("open");
+        fs::create_dir_all(dir.path().join(".fontsync/tmp")).unwrap();
+
+        let upload = |name: &str, data: &[u8]| {
+            let tmp = dir.path().join(".fontsync/tmp").join(name);
+            fs::write(&tmp, data).unwrap();
+            let sha256 = calculate_sha256(&tmp).unwrap();
+            store.store(&tmp, &sha256, name, &dir.path().join(name)).expect("store");
+            sha256
+        };
+        let sha_a = upload("a.ttf", b"same font");
+        assert_eq!(upload("copy.ttf", b"same font"), sha_a);
+        let sha_b = upload("b.ttf", b"other font");
+        assert_eq!(fs::read_dir(dir.path().join(".fontsync/blobs")).unwrap().count(), 2);
+        assert_eq!(fs::read(dir.path().join("copy.ttf")).unwrap(), b"same font");
+        assert!(same_file(&dir.path().join("a.ttf"), &dir.path().join("copy.ttf")) || cfg!(not(unix)));
+
+        // 手动放入的重复文件被改为链接
+        fs::write(dir.path().join("manual.ttf"), b"other font").unwrap();
+        store.adopt("manual.ttf", &sha_b, &dir.path().join("manual.ttf")).expect("adopt");
+        assert_eq!(BlobStore::open(dir.path()).unwrap().sha256_of("manual.ttf"), Some(sha_b.clone()));
+
+        // 只有全部名称都不再引用时才回收
+        store.unlink("a.ttf").unwrap();
+        assert_eq!(store.collect_garbage().unwrap().removed, 0);
+        store.unlink("copy.ttf").unwrap();
+        assert_eq!(store.collect_garbage().unwrap(), GcSummary { removed: 1, freed: 9 });
+        assert!(store.blob_path(&sha_a).is_none());
+        assert!(store.blob_path(&sha_b).is_some());
+        assert!(store.blob_path("../names.json").is_none());
+    }
+}
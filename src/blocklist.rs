@@ -0,0 +1,174 @@
+use anyhow::{bail, Context, Result};
+use parking_lot::RwLock;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::{BlockEntry, BlockRule};
+use crate::ignore::glob_matches;
+
+const BLOCKLIST_DIR: &str = ".fontsync";
+const BLOCKLIST_FILE: &str = "blocklist.json";
+
+// 服务器拒绝存储的字体：配置文件中的规则只读，管理接口添加的规则保存在 .fontsync/blocklist.json
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    // 为空时规则只保存在内存中
+    path: Option<PathBuf>,
+    configured: Vec<BlockEntry>,
+    rules: RwLock<Vec<BlockEntry>>,
+}
+
+impl Blocklist {
+    pub fn open(font_dir: &Path, config: Option<&Path>) -> Result<Self> {
+        let dir = font_dir.join(BLOCKLIST_DIR);
+        fs::create_dir_all(&dir).context("Failed to create metadata directory")?;
+        let path = dir.join(BLOCKLIST_FILE);
+
+        let rules = if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read blocklist")?;
+            serde_json::from_str(&content).context("Failed to parse blocklist")?
+        } else {
+            Vec::new()
+        };
+
+        let configured = match config {
+            Some(config) => {
+                let content = fs::read_to_string(config)
+                    .with_context(|| format!("Failed to read blocklist config {:?}", config))?;
+                let rules: Vec<BlockRule> = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse blocklist config {:?}", config))?;
+                rules
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, rule)| {
+                        Ok(BlockEntry {
+                            id: format!("config-{}", i + 1),
+                            rule: normalize_rule(rule).with_context(|| format!("Invalid rule #{} in {:?}", i + 1, config))?,
+                            configured: true,
+                        })
+                    })
+                    .collect::<Result<_>>()?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            configured,
+            rules: RwLock::new(rules),
+        })
+    }
+
+    pub fn list(&self) -> Vec<BlockEntry> {
+        self.configured.iter().cloned().chain(self.rules.read().iter().cloned()).collect()
+    }
+
+    pub fn add(&self, rule: BlockRule) -> Result<BlockEntry> {
+        let entry = BlockEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            rule: normalize_rule(rule)?,
+            configured: false,
+        };
+        let mut rules = self.rules.write();
+        rules.push(entry.clone());
+        self.persist(&rules)?;
+        Ok(entry)
+    }
+
+    // 只能删除通过接口添加的规则，返回被删除的规则
+    pub fn remove(&self, id: &str) -> Result<Option<BlockEntry>> {
+        let mut rules = self.rules.write();
+        let Some(index) = rules.iter().position(|r| r.id == id) else {
+            return Ok(None);
+        };
+        let entry = rules.remove(index);
+        self.persist(&rules)?;
+        Ok(Some(entry))
+    }
+
+    // 返回第一条命中的规则；加密字体无法解析家族名，只按文件名与明文哈希匹配
+    pub fn find(&self, filename: &str, family: Option<&str>, sha256s: &[&str]) -> Option<BlockEntry> {
+        let names: Vec<String> = std::iter::once(filename).chain(family).map(str::to_lowercase).collect();
+        self.list().into_iter().find(|entry| {
+            let rule = &entry.rule;
+            rule.pattern
+                .as_ref()
+                .is_some_and(|pattern| names.iter().any(|name| glob_matches(pattern, name)))
+                || rule.sha256.as_ref().is_some_and(|sha| sha256s.iter().any(|s| s.eq_ignore_ascii_case(sha)))
+        })
+    }
+
+    fn persist(&self, rules: &[BlockEntry]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_string_pretty(rules).context("Failed to serialize blocklist")?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write blocklist")?;
+        fs::rename(&tmp_path, path).context("Failed to replace blocklist")?;
+        Ok(())
+    }
+}
+
+// 模式统一转为小写，哈希必须是十六进制 SHA256
+fn normalize_rule(rule: BlockRule) -> Result<BlockRule> {
+    let pattern = rule
+        .pattern
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty());
+    let sha256 = rule
+        .sha256
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty());
+    if sha256.as_ref().is_some_and(|s| s.len() != 64 || !s.chars().all(|c| c.is_ascii_hexdigit())) {
+        bail!("sha256 must be a hex SHA256 digest");
+    }
+    if pattern.is_none() && sha256.is_none() {
+        bail!("A rule needs a name pattern or a sha256");
+    }
+    let reason = rule.reason.trim();
+    Ok(BlockRule {
+        pattern,
+        sha256,
+        reason: if reason.is_empty() { "Blocked by server policy".to_string() } else { reason.to_string() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: Option<&str>, sha256: Option<&str>) -> BlockRule {
+        BlockRule {
+            pattern: pattern.map(str::to_string),
+            sha256: sha256.map(str::to_string),
+            reason: "pirated".to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_configured_and_added_rules() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let config = dir.path().join("blocklist.json");
+        fs::write(&config, r#"[{ "pattern": "Helvetica*", "reason": "unlicensed" }]"#).unwrap();
+        let sha = "AB".repeat(32);
+
+        let blocklist = Blocklist::open(dir.path(), Some(&config)).expect("open");
+        assert!(blocklist.add(rule(None, Some("abc"))).is_err());
+        assert!(blocklist.add(rule(Some("  "), None)).is_err());
+        let added = blocklist.add(rule(None, Some(&sha))).expect("add");
+
+        // 文件名不匹配时按家族名匹配
+        let hit = blocklist.find("hn.ttf", Some("Helvetica Neue"), &[]).expect("family");
+        assert_eq!((hit.id.as_str(), hit.rule.reason.as_str()), ("config-1", "unlicensed"));
+        assert_eq!(blocklist.find("renamed.ttf", None, &[&sha.to_lowercase()]).map(|e| e.id), Some(added.id.clone()));
+        assert!(blocklist.find("Arial.ttf", Some("Arial"), &["00"]).is_none());
+
+        // 重新打开后保留通过接口添加的规则
+        let reopened = Blocklist::open(dir.path(), None).expect("reopen");
+        assert_eq!(reopened.list().len(), 1);
+        assert!(reopened.remove("config-1").expect("remove").is_none());
+        assert!(reopened.remove(&added.id).expect("remove").is_some());
+        assert!(Blocklist::open(dir.path(), None).expect("reopen").list().is_empty());
+    }
+}
@@ -0,0 +1,525 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use tempfile::{NamedTempFile, TempDir};
+
+use crate::api::FontQuery;
+use crate::client::{ApiClient, SyncOptions};
+use crate::client_state::ClientState;
+use crate::event_log::EventRecord;
+use crate::font_installer;
+use crate::protected::ProtectedPaths;
+use crate::signing::SignedManifest;
+use crate::utils::{self, calculate_sha256};
+use crate::websocket_server::WebSocketMessage;
+
+// 字体包为 gzip 压缩的条目序列：[u16 名称长度][名称][u64 数据长度][数据]，
+// 第一个条目是 bundle.json，其余为字体文件
+const MAGIC: &[u8] = b"FSBUNDLE1\n";
+const MANIFEST_ENTRY: &str = "bundle.json";
+
+// 包中一个字体的元数据，哈希与大小必须与签名清单一致
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleFont {
+    pub name: String,
+    pub sha256: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+// 差异包起点之后在服务器上删除的字体，sha256 为删除前的内容，旧版服务器不提供
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleRemoval {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub source_server: String,
+    pub created_at: u64,
+    // 服务器签名的文件名与哈希，安装时据此校验每个字体
+    pub signed_manifest: SignedManifest,
+    pub fonts: Vec<BundleFont>,
+    // 差异包只包含该事件序号之后新增或修改的字体，完整包为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since_seq: Option<u64>,
+    // 打包时服务器事件日志的最新序号，下一个差异包可从这里开始
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<BundleRemoval>,
+}
+
+impl BundleManifest {
+    pub fn total_size(&self) -> u64 {
+        self.fonts.iter().map(|f| f.size).sum()
+    }
+}
+
+// 差异包的起点：纯数字为事件序号，否则为当地日期（2024-05-01）或 RFC 3339 时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Since {
+    Sequence(u64),
+    Timestamp(u64),
+}
+
+impl Since {
+    fn includes(self, record: &EventRecord) -> bool {
+        match self {
+            Since::Sequence(seq) => record.seq > seq,
+            Since::Timestamp(timestamp) => record.timestamp >= timestamp,
+        }
+    }
+}
+
+pub fn parse_since(value: &str) -> Result<Since> {
+    let value = value.trim();
+    if let Ok(seq) = value.parse() {
+        return Ok(Since::Sequence(seq));
+    }
+    let time = match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+            .map(|t| t.timestamp()),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(value).ok().map(|t| t.timestamp()),
+    };
+    match time {
+        Some(timestamp) => Ok(Since::Timestamp(timestamp.max(0) as u64)),
+        None => bail!("Invalid --since value '{}': expected an event sequence number or a date", value),
+    }
+}
+
+// 日志按保留数量清理后，最早保留的事件已在起点之后时缺少中间的变更
+fn history_complete(events: &[EventRecord], since: Since) -> bool {
+    match (events.first(), since) {
+        (None, _) => true,
+        (Some(first), _) if first.seq == 1 => true,
+        (Some(first), Since::Sequence(seq)) => first.seq <= seq + 1,
+        (Some(first), Since::Timestamp(_)) => !since.includes(first),
+    }
+}
+
+// 按顺序折叠起点之后的事件，得到之后新增或修改的字体与已删除字体删除前的哈希；
+// 改名等同于删除原名称并新增新名称
+fn changes_since(events: &[EventRecord], since: Since) -> (HashSet<String>, HashMap<String, Option<String>>) {
+    let mut changed = HashSet::new();
+    let mut removed = HashMap::new();
+    for record in events.iter().filter(|r| since.includes(r)) {
+        match &record.event {
+            WebSocketMessage::FontAdded { filename, .. } | WebSocketMessage::FontModified { filename, .. } => {
+                removed.remove(filename);
+                changed.insert(filename.clone());
+            }
+            WebSocketMessage::FontRemoved { filename, sha256, .. } => {
+                changed.remove(filename);
+                removed.insert(filename.clone(), sha256.clone());
+            }
+            WebSocketMessage::FontRenamed { from, to, sha256, .. } => {
+                changed.remove(from);
+                removed.insert(from.clone(), Some(sha256.clone()));
+                removed.remove(to);
+                changed.insert(to.clone());
+            }
+            _ => {}
+        }
+    }
+    (changed, removed)
+}
+
+// 先写入同目录的临时文件，完成后再替换目标，中途失败不会留下不完整的包
+struct BundleWriter {
+    encoder: GzEncoder<BufWriter<NamedTempFile>>,
+}
+
+impl BundleWriter {
+    fn create(output: &Path, manifest: &BundleManifest) -> Result<Self> {
+        let dir = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let file = NamedTempFile::new_in(dir).with_context(|| format!("Failed to create bundle in {:?}", dir))?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+        encoder.write_all(MAGIC)?;
+        let mut writer = Self { encoder };
+        writer.add(MANIFEST_ENTRY, &serde_json::to_vec_pretty(manifest)?)?;
+        Ok(writer)
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let name_len = u16::try_from(name.len()).with_context(|| format!("File name too long: {}", name))?;
+        self.encoder.write_all(&name_len.to_be_bytes())?;
+        self.encoder.write_all(name.as_bytes())?;
+        self.encoder.write_all(&(data.len() as u64).to_be_bytes())?;
+        self.encoder.write_all(data)?;
+        Ok(())
+    }
+
+    fn finish(self, output: &Path) -> Result<()> {
+        let file = self.encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.as_file().sync_all()?;
+        file.persist(output).with_context(|| format!("Failed to write bundle {:?}", output))?;
+        Ok(())
+    }
+}
+
+// 读取下一个条目，正好在条目边界结束时返回 None
+fn read_entry(reader: &mut impl Read) -> Result<Option<(String, Vec<u8>)>> {
+    let mut len = [0u8; 2];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read bundle"),
+    }
+    let mut name = vec![0u8; u16::from_be_bytes(len) as usize];
+    reader.read_exact(&mut name).context("Bundle is truncated")?;
+    let name = String::from_utf8(name).context("Bundle contains an invalid file name")?;
+
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len).context("Bundle is truncated")?;
+    let len = u64::from_be_bytes(len);
+    // 按实际读到的数据增长缓冲区，不信任包中声明的长度
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data).context("Failed to read bundle")?;
+    if data.len() as u64 != len {
+        bail!("Bundle is truncated at '{}'", name);
+    }
+    Ok(Some((name, data)))
+}
+
+// 显式指定的公钥优先，否则使用与该服务器同步时固定的公钥
+fn trusted_key(server_url: &str, explicit: Option<&str>) -> Option<String> {
+    explicit
+        .map(str::to_string)
+        .or_else(|| ClientState::load().server(server_url).and_then(|s| s.signing_key.clone()))
+}
+
+// 打包服务器上订阅范围内的字体；只包含签名清单覆盖的字体，端到端加密的字体离线无法解密，不打包。
+// 指定 since 时按服务器事件日志只打包之后新增或修改的字体，并记录之后删除的字体
+pub async fn create(server_url: &str, output: &Path, options: &SyncOptions, since: Option<Since>) -> Result<BundleManifest> {
+    let api = ApiClient::new(server_url)?;
+    let (latest_seq, since_seq, changes) = match since {
+        Some(since) => {
+            let page = api.all_events(0).await.context("Differential bundles require the server event log")?;
+            if matches!(since, Since::Sequence(seq) if seq > page.latest_seq) {
+                bail!("Server event log was reset (latest event is #{}); create a full bundle instead", page.latest_seq);
+            }
+            if !history_complete(&page.events, since) {
+                bail!("Server no longer keeps the events since the requested start; create a full bundle instead");
+            }
+            // 按日期导出时记录起点之前最后一个事件的序号
+            let since_seq = match since {
+                Since::Sequence(seq) => seq,
+                Since::Timestamp(_) => page
+                    .events
+                    .iter()
+                    .find(|r| since.includes(r))
+                    .map(|r| r.seq - 1)
+                    .unwrap_or(page.latest_seq),
+            };
+            (Some(page.latest_seq), Some(since_seq), Some(changes_since(&page.events, since)))
+        }
+        None => (api.events(0, Some(0)).await.ok().map(|page| page.latest_seq), None, None),
+    };
+
+    let signed = api.manifest().await.context("Server does not provide a signed manifest")?;
+    let trusted = trusted_key(server_url, options.trusted_signing_key.as_deref());
+    let hashes = signed
+        .verify(trusted.as_deref())
+        .context("Signed manifest verification failed")?
+        .hashes();
+
+    let subscription = options.subscription();
+    let mut fonts = Vec::new();
+    for font in api.list_fonts_hashed(&FontQuery::default()).await?.fonts {
+        if !subscription.matches_font(&font) || font.is_pending() || font.is_awaiting_approval() {
+            continue;
+        }
+        if changes.as_ref().is_some_and(|(changed, _)| !changed.contains(&font.name)) {
+            continue;
+        }
+        if font.plaintext_sha256.is_some() {
+            warn!("Skipping end-to-end encrypted font '{}'", font.name);
+            continue;
+        }
+        if hashes.get(&font.name) != Some(&font.sha256) {
+            warn!("Skipping '{}': not covered by the signed manifest", font.name);
+            continue;
+        }
+        fonts.push(BundleFont {
+            name: font.name,
+            sha256: font.sha256,
+            size: font.size,
+            family: font.family,
+            style: font.style,
+            modified: font.modified,
+            tags: font.tags,
+        });
+    }
+    // 删除后又以同一名称上传的字体仍在服务器上，只作为更新打包
+    let mut removed: Vec<BundleRemoval> = changes
+        .map(|(_, removed)| removed)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| !hashes.contains_key(name))
+        .map(|(name, sha256)| BundleRemoval { name, sha256 })
+        .collect();
+    removed.sort_by(|a, b| a.name.cmp(&b.name));
+    if fonts.is_empty() && removed.is_empty() {
+        match since_seq {
+            Some(seq) => bail!("No fonts on {} changed since event #{}", server_url, seq),
+            None => bail!("No fonts on {} to bundle", server_url),
+        }
+    }
+
+    let manifest = BundleManifest {
+        source_server: server_url.trim_end_matches('/').to_string(),
+        created_at: chrono::Utc::now().timestamp() as u64,
+        signed_manifest: signed,
+        fonts,
+        since_seq,
+        latest_seq,
+        removed,
+    };
+    let mut writer = BundleWriter::create(output, &manifest)?;
+    for font in &manifest.fonts {
+        let (data, _) = api.fetch_font(&font.name).await?;
+        if hex::encode(Sha256::digest(&data)) != font.sha256 {
+            bail!("'{}' changed on the server while bundling; run the command again", font.name);
+        }
+        writer.add(&font.name, &data)?;
+        info!("Bundled {}", font.name);
+    }
+    writer.finish(output)?;
+    Ok(manifest)
+}
+
+// 已校验的字体包，字体解出到临时目录，随值一起删除
+pub struct OpenedBundle {
+    pub manifest: BundleManifest,
+    dir: TempDir,
+}
+
+impl OpenedBundle {
+    pub fn font_dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+// 校验签名与每个字体的哈希后解出字体；任一检查失败都不解出任何字体
+pub fn open(path: &Path, trusted: Option<&str>) -> Result<OpenedBundle> {
+    let file = File::open(path).with_context(|| format!("Failed to open bundle {:?}", path))?;
+    let mut reader = GzDecoder::new(BufReader::new(file));
+    let mut magic = [0u8; MAGIC.len()];
+    if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+        bail!("{:?} is not a fontsync bundle", path);
+    }
+    let manifest: BundleManifest = match read_entry(&mut reader)? {
+        Some((name, data)) if name == MANIFEST_ENTRY => {
+            serde_json::from_slice(&data).context("Invalid bundle manifest")?
+        }
+        _ => bail!("Bundle manifest is missing"),
+    };
+
+    let trusted = trusted_key(&manifest.source_server, trusted);
+    if trusted.is_none() {
+        warn!(
+            "No signing key is known for {}; verifying with the key embedded in the bundle ({}). Pass --server-key to check the publisher",
+            manifest.source_server, manifest.signed_manifest.public_key
+        );
+    }
+    let signed = manifest
+        .signed_manifest
+        .verify(trusted.as_deref())
+        .context("Bundle signature verification failed")?;
+    let signed: HashMap<String, (String, u64)> =
+        signed.fonts.into_iter().map(|e| (e.name, (e.sha256, e.size))).collect();
+
+    let mut expected: HashMap<&str, &BundleFont> = HashMap::new();
+    for font in &manifest.fonts {
+        if utils::sanitize_filename(&font.name) != font.name {
+            bail!("Bundle contains an unsafe file name: {}", font.name);
+        }
+        if signed.get(&font.name) != Some(&(font.sha256.clone(), font.size)) {
+            bail!("'{}' is not covered by the signed manifest", font.name);
+        }
+        expected.insert(&font.name, font);
+    }
+    // 删除记录同样不能指向服务器上仍存在的字体
+    for removal in &manifest.removed {
+        if utils::sanitize_filename(&removal.name) != removal.name || signed.contains_key(&removal.name) {
+            bail!("Bundle contains an invalid removal of '{}'", removal.name);
+        }
+    }
+
+    let dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+    let mut seen = HashSet::new();
+    while let Some((name, data)) = read_entry(&mut reader)? {
+        let Some(font) = expected.get(name.as_str()) else { bail!("Bundle contains unlisted file '{}'", name) };
+        if !seen.insert(name.clone()) {
+            bail!("Bundle contains '{}' more than once", name);
+        }
+        if hex::encode(Sha256::digest(&data)) != font.sha256 {
+            bail!("'{}' does not match the signed manifest", name);
+        }
+        std::fs::write(dir.path().join(&name), &data).with_context(|| format!("Failed to extract '{}'", name))?;
+    }
+    if let Some(missing) = manifest.fonts.iter().find(|f| !seen.contains(&f.name)) {
+        bail!("Bundle is missing '{}'", missing.name);
+    }
+    Ok(OpenedBundle { manifest, dir })
+}
+
+// 卸载差异包中已删除的字体，只移除系统字体目录中与删除前内容一致的副本；返回移除的数量
+pub async fn apply_removals(manifest: &BundleManifest) -> Result<usize> {
+    let state = ClientState::load();
+    let protected = ProtectedPaths::load();
+    let mut removed = 0;
+    for removal in &manifest.removed {
+        let Some(sha256) = &removal.sha256 else {
+            warn!("Not removing '{}': the server did not record its content", removal.name);
+            continue;
+        };
+        // 安装时规范化过的字体在系统目录中是另一个名称
+        let names: Vec<&str> = std::iter::once(removal.name.as_str()).chain(state.normalized_names(&removal.name)).collect();
+        for dir in utils::get_system_font_directories() {
+            for name in &names {
+                let path = dir.join(name);
+                if !path.exists() || calculate_sha256(&path)? != *sha256 {
+                    continue;
+                }
+                if let Err(e) = protected.check_removal(&path) {
+                    warn!("Skipped removing font from system: {}", e);
+                    continue;
+                }
+                font_installer::uninstall_font(&path).await?;
+                info!("Removed font from system: {}", removal.name);
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::{Manifest, ManifestEntry, ServerSigner};
+
+    fn font(name: &str, data: &[u8]) -> BundleFont {
+        BundleFont {
+            name: name.to_string(),
+            sha256: hex::encode(Sha256::digest(data)),
+            size: data.len() as u64,
+            family: None,
+            style: None,
+            modified: None,
+            tags: BTreeSet::new(),
+        }
+    }
+
+    fn write_bundle(dir: &Path, signer: &ServerSigner, fonts: &[(&str, &[u8])], entries: &[(&str, &[u8])]) -> std::path::PathBuf {
+        let fonts: Vec<BundleFont> = fonts.iter().map(|(name, data)| font(name, data)).collect();
+        let signed = Manifest {
+            generated_at: 1,
+            fonts: fonts
+                .iter()
+                .map(|f| ManifestEntry { name: f.name.clone(), sha256: f.sha256.clone(), size: f.size })
+                .collect(),
+        };
+        let manifest = BundleManifest {
+            source_server: "http://bundle.invalid".to_string(),
+            created_at: 1,
+            signed_manifest: signer.sign_manifest(&signed).unwrap(),
+            fonts,
+            since_seq: None,
+            latest_seq: None,
+            removed: Vec::new(),
+        };
+        let path = dir.join("team-fonts.fsbundle");
+        let mut writer = BundleWriter::create(&path, &manifest).unwrap();
+        for (name, data) in entries {
+            writer.add(name, data).unwrap();
+        }
+        writer.finish(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn bundles_are_verified_before_extraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ServerSigner::load_or_create(dir.path()).unwrap();
+        let key = signer.public_key().unwrap();
+        let fonts: &[(&str, &[u8])] = &[("a.ttf", b"font a"), ("b.otf", b"font b")];
+
+        let path = write_bundle(dir.path(), &signer, fonts, fonts);
+        let opened = open(&path, Some(&key)).unwrap();
+        assert_eq!(opened.manifest.fonts.len(), 2);
+        assert_eq!(std::fs::read(opened.font_dir().join("b.otf")).unwrap(), b"font b");
+
+        // 其他服务器的公钥、被替换的内容、缺少或多出的文件都会被拒绝
+        let other = ServerSigner::load_or_create(&dir.path().join("other")).unwrap();
+        assert!(open(&path, Some(&other.public_key().unwrap())).is_err());
+        let path = write_bundle(dir.path(), &signer, fonts, &[("a.ttf", b"font a"), ("b.otf", b"evil")]);
+        assert!(open(&path, Some(&key)).is_err());
+        let path = write_bundle(dir.path(), &signer, fonts, &fonts[..1]);
+        assert!(open(&path, Some(&key)).is_err());
+        let path = write_bundle(dir.path(), &signer, &fonts[..1], fonts);
+        assert!(open(&path, Some(&key)).is_err());
+        let path = write_bundle(dir.path(), &signer, &[("../a.ttf", b"font a")], &[("../a.ttf", b"font a")]);
+        assert!(open(&path, Some(&key)).is_err());
+
+        std::fs::write(&path, b"not a bundle").unwrap();
+        assert!(open(&path, Some(&key)).is_err());
+    }
+
+    fn record(seq: u64, timestamp: u64, kind: &str, data: serde_json::Value) -> EventRecord {
+        let event = serde_json::json!({ "type": kind, "data": data });
+        serde_json::from_value(serde_json::json!({ "seq": seq, "timestamp": timestamp, "event": event })).unwrap()
+    }
+
+    #[test]
+    fn differential_changes_fold_the_event_log() {
+        let events = [
+            record(1, 100, "FontAdded", serde_json::json!({"filename": "old.ttf", "sha256": "o", "size": 1})),
+            record(2, 200, "FontModified", serde_json::json!({"filename": "a.ttf", "sha256": "a2", "size": 1})),
+            record(3, 300, "FontRemoved", serde_json::json!({"filename": "b.ttf", "sha256": "b"})),
+            record(4, 400, "FontRenamed", serde_json::json!({"from": "c.ttf", "to": "d.ttf", "sha256": "c", "size": 1})),
+            record(5, 500, "FontAdded", serde_json::json!({"filename": "e.ttf", "sha256": "e", "size": 1})),
+            record(6, 600, "FontRemoved", serde_json::json!({"filename": "e.ttf", "sha256": "e"})),
+        ];
+        let (changed, removed) = changes_since(&events, Since::Sequence(1));
+        assert_eq!(changed, HashSet::from(["a.ttf".to_string(), "d.ttf".to_string()]));
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed["c.ttf"].as_deref(), Some("c"));
+        assert_eq!(removed["e.ttf"].as_deref(), Some("e"));
+        let (changed, removed) = changes_since(&events, Since::Timestamp(450));
+        assert!(changed.is_empty());
+        assert_eq!(removed.keys().collect::<Vec<_>>(), ["e.ttf"]);
+
+        // 清理后的日志从 #3 开始
+        assert!(history_complete(&events, Since::Sequence(0)));
+        assert!(history_complete(&events[2..], Since::Sequence(2)));
+        assert!(!history_complete(&events[2..], Since::Sequence(1)));
+        assert!(history_complete(&events[2..], Since::Timestamp(350)));
+        assert!(!history_complete(&events[2..], Since::Timestamp(250)));
+
+        assert_eq!(parse_since("42").unwrap(), Since::Sequence(42));
+        assert_eq!(parse_since("2024-05-01T00:00:00Z").unwrap(), Since::Timestamp(1714521600));
+        assert!(matches!(parse_since("2024-05-01").unwrap(), Since::Timestamp(_)));
+        assert!(parse_since("last week").is_err());
+    }
+}
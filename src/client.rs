@@ -1,30 +1,706 @@
 use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use reqwest::multipart;
-use serde::Deserialize;
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::fs::{create_dir_all, File};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
+use tokio_util::sync::CancellationToken;
 use walkdir::WalkDir;
 
-use crate::font_installer;
-use crate::utils;
+use crate::api::{self, BatchUploadResponse, BatchUploadResult, ErrorCode, ErrorResponse, FontActionResponse, FontHashes, FontInfo, FontList, FontQuery, ServerStats, Subscription, Tombstone, TombstoneList, VersionInfo, MAX_BATCH_UPLOADS};
+use crate::event_log::EventPage;
+use crate::client_state::ClientState;
+use crate::delta;
+use crate::e2e::{self, TeamKey};
+use crate::font_installer::{self, InstallOptions, InstallScope};
+use crate::font_metadata;
+use crate::http::{HttpClient, SendWithRetry, TRANSFER_TIMEOUT};
+use crate::signing::SignedManifest;
+use crate::identity::ClientIdentity;
+use crate::ignore::IgnoreRules;
+use crate::placeholder::{self, Placeholder};
+use crate::progress::{Operation, Progress};
+use crate::sync_report::{FileAction, SyncReport};
+use crate::utils::{self, ChangeOrigin, ConflictPolicy, SyncDirection};
 
-#[derive(Deserialize, Debug)]
-pub struct FontInfo {
-    pub name: String,
-    pub size: u64,
-    pub mime_type: String,
-    pub sha256: String,
+// 服务器仍在计算哈希时重新获取列表的间隔
+const HASHING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// 同步队列因服务器繁忙暂停的次数上限（每个文件），之后按失败处理
+const MAX_BUSY_WAITS: u32 = 5;
+// 繁忙响应未给出 Retry-After 时的暂停时间，以及服务器要求的暂停时间上限
+const DEFAULT_BUSY_DELAY: Duration = Duration::from_secs(5);
+const MAX_BUSY_DELAY: Duration = Duration::from_secs(5 * 60);
+// 服务器上还没有的、不超过该大小的字体在同步时合并为批量上传
+const SMALL_UPLOAD_SIZE: u64 = 512 * 1024;
+
+// 服务器返回的错误；类别与是否可重试取自统一的错误格式，旧版服务器的错误按状态码推断
+#[derive(Debug)]
+pub struct ServerError {
+    // 失败的操作，如 "Failed to upload font"
+    pub context: String,
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    pub retryable: bool,
+    pub retry_after: Option<Duration>,
+}
+
+impl ServerError {
+    async fn from_response(response: reqwest::Response, context: &str) -> Self {
+        let context = context.to_string();
+        let status = response.status();
+        let retry_after = crate::http::retry_after(response.headers());
+        let text = response.text().await.unwrap_or_default();
+        match serde_json::from_str::<ErrorResponse>(&text) {
+            Ok(e) if e.code != ErrorCode::Unknown => Self { retry_after, ..Self::from_error_response(e, &context) },
+            parsed => {
+                let code = ErrorCode::for_status(status.as_u16());
+                Self {
+                    context,
+                    code,
+                    message: match parsed {
+                        Ok(e) => e.message.map_or(e.error.clone(), |m| format!("{}: {}", e.error, m)),
+                        Err(_) if text.trim().is_empty() => status.to_string(),
+                        Err(_) => text,
+                    },
+                    details: None,
+                    retryable: code.is_retryable(),
+                    retry_after,
+                }
+            }
+        }
+    }
+
+    // 批量上传中单个字体的错误
+    pub(crate) fn from_error_response(e: ErrorResponse, context: &str) -> Self {
+        Self {
+            context: context.to_string(),
+            code: e.code,
+            message: e.message.map_or(e.error.clone(), |m| format!("{}: {}", e.error, m)),
+            details: e.details,
+            retryable: e.retryable,
+            retry_after: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.context)?;
+        match self.code {
+            ErrorCode::Maintenance => write!(f, "server is in maintenance mode ({})", self.message)?,
+            ErrorCode::PayloadTooLarge => write!(f, "file exceeds the server's upload size limit ({})", self.message)?,
+            ErrorCode::Unauthorized => write!(f, "{} (store a token with `fontsync login`)", self.message)?,
+            ErrorCode::Blocked => {
+                write!(f, "refused by the server's blocklist ({})", self.message)?;
+                if let Some(rule) = self.details.as_ref().and_then(|d| d["rule"].as_str()) {
+                    write!(f, " [rule {}]", rule)?;
+                }
+            }
+            ErrorCode::QuotaExceeded => write!(f, "upload quota exceeded ({})", self.message)?,
+            ErrorCode::PreconditionFailed => write!(f, "changed on the server since it was last synced ({})", self.message)?,
+            ErrorCode::AlreadyExists => write!(f, "a different version already exists on the server ({})", self.message)?,
+            ErrorCode::IncompatibleProtocol => write!(f, "incompatible server version: {}", self.message)?,
+            _ => f.write_str(&self.message)?,
+        }
+        match self.retry_after.filter(|_| self.retryable) {
+            Some(after) => write!(f, ", retry in {}s", after.as_secs()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+pub fn server_error(error: &anyhow::Error) -> Option<&ServerError> {
+    error.chain().find_map(|e| e.downcast_ref::<ServerError>())
+}
+
+// 上传时服务器上的版本已被他人修改或他人已上传同名字体，应按冲突处理
+pub fn is_version_conflict(error: &anyhow::Error) -> bool {
+    server_error(error).is_some_and(|e| matches!(e.code, ErrorCode::PreconditionFailed | ErrorCode::AlreadyExists))
+}
+
+// 服务器表示稍后重试可能成功，如维护模式或网关错误
+pub fn is_retryable_error(error: &anyhow::Error) -> bool {
+    server_error(error).is_some_and(|e| e.retryable)
+}
+
+// 服务器因限流、维护或过载暂时拒绝请求时应暂停的时间
+fn busy_delay(error: &anyhow::Error) -> Option<Duration> {
+    let error = server_error(error)?;
+    matches!(error.code, ErrorCode::RateLimited | ErrorCode::Maintenance | ErrorCode::Unavailable)
+        .then(|| error.retry_after.unwrap_or(DEFAULT_BUSY_DELAY).min(MAX_BUSY_DELAY))
+}
+
+// 同步队列中的单个传输：服务器繁忙时通过进度回调报告并按 Retry-After 暂停，之后重试同一文件；
+// 暂停次数用完后返回最后的错误。同步被取消时返回 None
+async fn transfer_when_ready<T, F>(
+    operation: Operation,
+    name: &str,
+    options: &SyncOptions,
+    progress: &Progress,
+    mut transfer: impl FnMut() -> F,
+) -> Option<Result<T>>
+where
+    F: std::future::Future<Output = Result<T>>,
+{
+    let mut waits = 0;
+    loop {
+        let result = tokio::select! {
+            result = transfer() => result,
+            _ = options.cancel.cancelled() => return None,
+        };
+        let delay = match &result {
+            Err(e) if waits < MAX_BUSY_WAITS => busy_delay(e),
+            _ => None,
+        };
+        let Some(delay) = delay else {
+            return Some(result);
+        };
+        waits += 1;
+        warn!("Server busy, retrying '{}' in {}s", name, delay.as_secs());
+        progress.server_busy(operation, name, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = options.cancel.cancelled() => return None,
+        }
+    }
+}
+
+// 同步时字体列表与文件传输使用的接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Transport {
+    /// HTTP 接口
+    #[default]
+    Http,
+    /// 服务器以 --grpc-port 提供的 gRPC 接口（需要编译 grpc 支持）
+    Grpc,
+}
+
+// 服务器 HTTP 接口的类型化客户端，请求与响应类型与服务器共用 api 模块
+#[derive(Clone)]
+pub struct ApiClient {
+    http: HttpClient,
+    base_url: String,
+    // 上传与下载单个文件时报告传输的字节数
+    progress: Progress,
+    // 设置时字体列表与单个文件的上传下载改用 gRPC，其余请求仍走 HTTP
+    #[cfg(feature = "grpc")]
+    grpc: Option<crate::grpc::GrpcClient>,
+}
+
+impl ApiClient {
+    pub fn new(server_url: &str) -> Result<Self> {
+        Self::with_transport(server_url, Transport::Http)
+    }
+
+    pub fn with_transport(server_url: &str, transport: Transport) -> Result<Self> {
+        #[cfg(not(feature = "grpc"))]
+        if transport == Transport::Grpc {
+            anyhow::bail!("This build does not include gRPC support; rebuild with --features grpc");
+        }
+        Ok(Self {
+            http: HttpClient::for_server(server_url)?,
+            base_url: http_base_url(&crate::http::resolve_server_url(server_url)),
+            progress: Progress::none(),
+            #[cfg(feature = "grpc")]
+            grpc: match transport {
+                Transport::Http => None,
+                Transport::Grpc => Some(crate::grpc::GrpcClient::new(server_url)?),
+            },
+        })
+    }
+
+    // 增量传输与批量上传只有 HTTP 接口，使用 gRPC 时跳过
+    fn uses_grpc(&self) -> bool {
+        #[cfg(feature = "grpc")]
+        return self.grpc.is_some();
+        #[cfg(not(feature = "grpc"))]
+        false
+    }
+
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // 非 2xx 响应转换为 ServerError
+    async fn check(response: reqwest::Response, context: &str) -> Result<reqwest::Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        Err(anyhow::Error::new(ServerError::from_response(response, context).await))
+    }
+
+    pub async fn health(&self) -> Result<api::HealthStatus> {
+        let response = self.http.get(self.url("/healthz")).send().await?;
+        let response = Self::check(response, "Health check failed").await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn version(&self) -> Result<VersionInfo> {
+        let response = self.http.get(self.url("/version")).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get server version").await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn list_fonts(&self, query: &FontQuery) -> Result<FontList> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            return grpc.list_fonts(query).await;
+        }
+        let response = self.http.get(self.url("/fonts")).query(query).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get font list").await?;
+        Ok(response.json().await?)
+    }
+
+    // 同步需要比较哈希，服务器仍在后台计算时等待其完成；等待审核的上传尚不能下载，不参与同步
+    pub async fn list_fonts_hashed(&self, query: &FontQuery) -> Result<FontList> {
+        loop {
+            let mut list = self.list_fonts(query).await?;
+            let pending = list.fonts.iter().filter(|f| f.is_pending()).count();
+            if pending == 0 {
+                list.fonts.retain(|f| !f.is_awaiting_approval());
+                return Ok(list);
+            }
+            info!("Server is still hashing {} fonts, waiting", pending);
+            tokio::time::sleep(HASHING_POLL_INTERVAL).await;
+        }
+    }
+
+    // 由服务器按标签过滤，tags 为空时返回全部字体
+    pub async fn list_fonts_tagged(&self, tags: &[String]) -> Result<FontList> {
+        let query = FontQuery {
+            tag: Some(tags.join(",")).filter(|tag| !tag.is_empty()),
+            ..FontQuery::default()
+        };
+        self.list_fonts_hashed(&query).await
+    }
+
+    pub async fn events(&self, since: u64, limit: Option<usize>) -> Result<EventPage> {
+        let mut request = self.http.get(self.url("/events")).query(&[("since", since)]);
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+        let response = Self::check(request.send_with_retry().await?, "Failed to get server events").await?;
+        Ok(response.json().await?)
+    }
+
+    // since 之后的全部事件，按服务器的分页逐页获取；truncated 取自第一页
+    pub async fn all_events(&self, since: u64) -> Result<EventPage> {
+        let mut page = self.events(since, None).await?;
+        loop {
+            let Some(last) = page.events.last().map(|r| r.seq) else {
+                return Ok(page);
+            };
+            if last >= page.latest_seq {
+                return Ok(page);
+            }
+            let next = self.events(last, None).await?;
+            if next.events.is_empty() {
+                return Ok(page);
+            }
+            page.latest_seq = next.latest_seq;
+            page.events.extend(next.events);
+        }
+    }
+
+    pub async fn stats(&self) -> Result<ServerStats> {
+        let response = self.http.get(self.url("/stats")).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get server statistics").await?;
+        Ok(response.json().await?)
+    }
+
+    // 通过 WebSocket 连接的客户端，启用令牌时需要管理员权限
+    #[cfg(feature = "gui")]
+    pub async fn clients(&self) -> Result<api::ClientList> {
+        let response = self.http.get(self.url("/clients")).send_with_retry().await?;
+        let response = Self::check(response, "Failed to list connected clients").await?;
+        Ok(response.json().await?)
+    }
+
+    // 只含文件名与内容哈希，旧版服务器不支持
+    pub async fn font_hashes(&self, tags: &[String]) -> Result<FontHashes> {
+        let mut request = self.http.get(self.url("/fonts/hashes"));
+        if !tags.is_empty() {
+            request = request.query(&[("tag", tags.join(","))]);
+        }
+        let response = Self::check(request.send_with_retry().await?, "Failed to get font hashes").await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn tombstones(&self) -> Result<TombstoneList> {
+        let response = self.http.get(self.url("/tombstones")).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get deleted fonts").await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn manifest(&self) -> Result<SignedManifest> {
+        let response = self.http.get(self.url("/manifest")).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get signed manifest").await?;
+        Ok(response.json().await?)
+    }
+
+    // readd 表示用户主动添加，允许重新上传服务器记录为已删除的相同内容
+    pub async fn upload_font(
+        &self,
+        file_path: &Path,
+        filename: &str,
+        sha256: &str,
+        e2e_key: Option<&TeamKey>,
+        readd: bool,
+    ) -> Result<FontActionResponse> {
+        self.upload_font_replacing(file_path, filename, sha256, e2e_key, readd, None).await
+    }
+
+    // if_match 为要替换的服务器版本的哈希，服务器上的版本已变化时上传以 412 失败
+    async fn upload_font_replacing(
+        &self,
+        file_path: &Path,
+        filename: &str,
+        sha256: &str,
+        e2e_key: Option<&TeamKey>,
+        readd: bool,
+        if_match: Option<&str>,
+    ) -> Result<FontActionResponse> {
+        let file = File::open(file_path).await?;
+        let metadata = file.metadata().await?;
+
+        // 读取文件内容
+        let mut buffer = Vec::with_capacity(metadata.len() as usize);
+        let mut reader = tokio::io::BufReader::new(file);
+        reader.read_to_end(&mut buffer).await?;
+
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            let mut header = crate::grpc::proto::UploadHeader {
+                filename: filename.to_string(),
+                readd,
+                modified: utils::get_file_timestamp(file_path).ok(),
+                if_match: if_match.map(str::to_string).into_iter().collect(),
+                ..Default::default()
+            };
+            if let Some(key) = e2e_key {
+                buffer = key.encrypt(&buffer)?;
+                header.plaintext_sha256 = Some(sha256.to_string());
+            }
+            let (progress, name) = (self.progress.clone(), filename.to_string());
+            return grpc
+                .upload_font(header, buffer, move |sent, total| progress.file_progress(Operation::Upload, &name, sent, total))
+                .await;
+        }
+
+        // 请求体一次发送，只在开始与结束时报告
+        let size = metadata.len();
+        self.progress.file_progress(Operation::Upload, filename, 0, size);
+        let form = Self::add_font_part(multipart::Form::new(), buffer, file_path, filename, sha256, e2e_key, readd)?;
+
+        let mut request = self.http.post(self.url("/fonts")).timeout(TRANSFER_TIMEOUT).multipart(form);
+        for (name, value) in ClientIdentity::current().headers() {
+            request = request.header(name, value);
+        }
+        if let Some(expected) = if_match {
+            request = request.header(reqwest::header::IF_MATCH, format!("\"{}\"", expected));
+        }
+        let response = Self::check(request.send().await?, "Server error").await?;
+        self.progress.file_progress(Operation::Upload, filename, size, size);
+        Ok(response.json().await?)
+    }
+
+    // 多个字体一次请求上传，返回各字体的结果；旧版服务器没有批量接口时返回 None
+    pub async fn upload_fonts(
+        &self,
+        fonts: &[(PathBuf, String, String)],
+        e2e_key: Option<&TeamKey>,
+        readd: bool,
+    ) -> Result<Option<BatchUploadResponse>> {
+        if self.uses_grpc() {
+            return Ok(None);
+        }
+        let mut form = multipart::Form::new();
+        for (file_path, filename, sha256) in fonts {
+            let buffer = tokio::fs::read(file_path).await?;
+            form = Self::add_font_part(form, buffer, file_path, filename, sha256, e2e_key, readd)?;
+        }
+
+        let mut request = self.http.post(self.url("/fonts/batch")).timeout(TRANSFER_TIMEOUT).multipart(form);
+        for (name, value) in ClientIdentity::current().headers() {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        if matches!(response.status(), reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED) {
+            return Ok(None);
+        }
+        let response = Self::check(response, "Server error").await?;
+        Ok(Some(response.json().await?))
+    }
+
+    // 选项需先于对应的 font 部分提交，加密模式下明文哈希供服务器记录
+    fn add_font_part(
+        mut form: multipart::Form,
+        mut buffer: Vec<u8>,
+        file_path: &Path,
+        filename: &str,
+        sha256: &str,
+        e2e_key: Option<&TeamKey>,
+        readd: bool,
+    ) -> Result<multipart::Form> {
+        if readd {
+            form = form.text("readd", "true");
+        }
+        // 服务器据此保留原始修改时间
+        if let Ok(modified) = utils::get_file_timestamp(file_path) {
+            form = form.text("modified", modified.to_string());
+        }
+        if let Some(key) = e2e_key {
+            buffer = key.encrypt(&buffer)?;
+            form = form.text("plaintext_sha256", sha256.to_string());
+        }
+
+        let part = multipart::Part::bytes(buffer)
+            .file_name(filename.to_string())
+            .mime_str("application/octet-stream")?;
+        Ok(form.part("font", part))
+    }
+
+    // 服务器上已有旧版本时只上传增量；增量不划算、服务器不支持或版本不符时上传完整文件。
+    // remote_sha256 同时作为 If-Match，期间服务器上的版本被他人修改时返回冲突错误（见 is_version_conflict）
+    pub async fn upload_changed_font(
+        &self,
+        file_path: &Path,
+        filename: &str,
+        sha256: &str,
+        e2e_key: Option<&TeamKey>,
+        readd: bool,
+        remote_sha256: Option<&str>,
+    ) -> Result<FontActionResponse> {
+        // 加密后的内容每次都不同，增量没有意义
+        if let (Some(base), None, false) = (remote_sha256, e2e_key, self.uses_grpc()) {
+            match self.upload_font_delta(file_path, filename, base, readd).await {
+                Ok(Some(response)) => return Ok(response),
+                Ok(None) => {}
+                Err(e) if is_version_conflict(&e) => return Err(e),
+                Err(e) => debug!("Delta upload of '{}' failed, sending full file: {:#}", filename, e),
+            }
+        }
+        self.upload_font_replacing(file_path, filename, sha256, e2e_key, readd, remote_sha256).await
+    }
+
+    async fn upload_font_delta(
+        &self,
+        file_path: &Path,
+        filename: &str,
+        base_sha256: &str,
+        readd: bool,
+    ) -> Result<Option<FontActionResponse>> {
+        let response = self.http.get(self.url(&format!("/fonts/{}/signature", utils::encode_path_segment(filename)))).send_with_retry().await?;
+        let response = Self::check(response, "Failed to get font signature").await?;
+        let signature = delta::Signature::decode(&response.bytes().await?)?;
+
+        let data = tokio::fs::read(file_path).await?;
+        let patch = signature.diff(&data);
+        if !delta::is_worthwhile(patch.len(), data.len()) {
+            return Ok(None);
+        }
+
+        let mut form = multipart::Form::new().text("delta_base", base_sha256.to_string());
+        if readd {
+            form = form.text("readd", "true");
+        }
+        if let Ok(modified) = utils::get_file_timestamp(file_path) {
+            form = form.text("modified", modified.to_string());
+        }
+        let patch_len = patch.len();
+        let part = multipart::Part::bytes(patch)
+            .file_name(filename.to_string())
+            .mime_str("application/octet-stream")?;
+        let mut request = self
+            .http
+            .post(self.url("/fonts"))
+            .timeout(TRANSFER_TIMEOUT)
+            .header(reqwest::header::IF_MATCH, format!("\"{}\"", base_sha256))
+            .multipart(form.part("font", part));
+        for (name, value) in ClientIdentity::current().headers() {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        // 服务器上的版本已变化
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return Ok(None);
+        }
+        let response = Self::check(response, "Server error").await?;
+        info!("Uploaded '{}' as delta ({} of {} bytes)", filename, patch_len, data.len());
+        Ok(Some(response.json().await?))
+    }
+
+    // 服务器将文件移入回收站；if_match 为要删除的版本，服务器上的版本已变化时以 412 失败
+    pub async fn delete_font(&self, filename: &str, if_match: Option<&str>) -> Result<FontActionResponse> {
+        let mut request = self.http.delete(self.url(&format!("/fonts/{}", utils::encode_path_segment(filename))));
+        if let Some(expected) = if_match {
+            request = request.header(reqwest::header::IF_MATCH, format!("\"{}\"", expected));
+        }
+        let response = request.send().await?;
+        let response = Self::check(response, "Failed to delete font").await?;
+        Ok(response.json().await?)
+    }
+
+    // 服务器上改名，内容与标签保留；if_match 同 delete_font
+    pub async fn rename_font(&self, filename: &str, to: &str, if_match: Option<&str>) -> Result<FontActionResponse> {
+        let mut request = self
+            .http
+            .post(self.url(&format!("/fonts/{}/rename", utils::encode_path_segment(filename))))
+            .json(&api::RenameRequest { to: to.to_string() });
+        if let Some(expected) = if_match {
+            request = request.header(reqwest::header::IF_MATCH, format!("\"{}\"", expected));
+        }
+        let response = request.send().await?;
+        let response = Self::check(response, "Failed to rename font").await?;
+        Ok(response.json().await?)
+    }
+
+    // SSE 变更通知流，last_event_id 之后的事件会先被回放
+    pub async fn event_stream(&self, last_event_id: Option<u64>) -> Result<reqwest::Response> {
+        let mut request = self.http.request(reqwest::Method::GET, self.url("/events/stream")).header("accept", "text/event-stream");
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id.to_string());
+        }
+        Self::check(request.send().await?, "Failed to open event stream").await
+    }
+
+    // 返回文件内容与服务器上的修改时间
+    pub async fn fetch_font(&self, filename: &str) -> Result<(bytes::Bytes, Option<u64>)> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            let (data, modified) = grpc.download_font(filename, |_, _| {}).await?;
+            return Ok((data.into(), modified));
+        }
+        let response = self.http.get(self.url(&format!("/fonts/{}", utils::encode_path_segment(filename)))).timeout(TRANSFER_TIMEOUT).send_with_retry().await?;
+        let response = Self::check(response, "Failed to download font").await?;
+        let modified = remote_modified(&response);
+        Ok((response.bytes().await?, modified))
+    }
+
+    // 以本地旧版本为基础获取新版本，返回完整内容与服务器上的修改时间
+    pub async fn fetch_font_delta(&self, filename: &str, base: &[u8]) -> Result<(Vec<u8>, Option<u64>)> {
+        let response = self
+            .http
+            .post(self.url(&format!("/fonts/{}/delta", utils::encode_path_segment(filename))))
+            .body(delta::Signature::of(base).encode())
+            .timeout(TRANSFER_TIMEOUT)
+            .send_with_retry()
+            .await?;
+        let response = Self::check(response, "Failed to download font delta").await?;
+        let modified = remote_modified(&response);
+        let is_delta = response.headers().get(api::DELTA_HEADER).is_some_and(|v| v == "delta");
+        let body = response.bytes().await?;
+        if is_delta {
+            debug!("Received '{}' as delta ({} bytes)", filename, body.len());
+            Ok((delta::apply(base, &body)?, modified))
+        } else {
+            Ok((body.to_vec(), modified))
+        }
+    }
+
+    // 本地已有旧版本时按增量下载，失败时退回完整下载
+    pub async fn download_font_from_base(
+        &self,
+        filename: &str,
+        base_path: &Path,
+        output_path: &Path,
+        expected_sha256: &str,
+    ) -> Result<()> {
+        if self.uses_grpc() {
+            return self.download_font(filename, output_path, expected_sha256).await;
+        }
+        let fetched = match tokio::fs::read(base_path).await {
+            Ok(base) => self.fetch_font_delta(filename, &base).await.and_then(|(data, modified)| {
+                utils::write_atomic(output_path, &data, Some(expected_sha256))?;
+                Ok(modified)
+            }),
+            Err(e) => Err(e.into()),
+        };
+        match fetched {
+            Ok(modified) => {
+                if let Some(modified) = modified
+                    && let Err(e) = utils::set_file_timestamp(output_path, modified)
+                {
+                    warn!("Failed to set modified time of {:?}: {}", output_path, e);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                debug!("Delta download of '{}' failed, fetching full file: {:#}", filename, e);
+                self.download_font(filename, output_path, expected_sha256).await
+            }
+        }
+    }
+
+    // 内容校验通过后才替换 output_path，中断的下载不会覆盖原有文件
+    pub async fn download_font(&self, filename: &str, output_path: &Path, expected_sha256: &str) -> Result<()> {
+        let (bytes, modified) = self.receive_font(filename).await?;
+
+        utils::write_atomic(output_path, &bytes, Some(expected_sha256))?;
+
+        if let Some(modified) = modified
+            && let Err(e) = utils::set_file_timestamp(output_path, modified)
+        {
+            warn!("Failed to set modified time of {:?}: {}", output_path, e);
+        }
+
+        Ok(())
+    }
+
+    // 逐块接收并报告进度，返回内容与服务器上的修改时间
+    async fn receive_font(&self, filename: &str) -> Result<(Vec<u8>, Option<u64>)> {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            let progress = |received, total| self.progress.file_progress(Operation::Download, filename, received, total);
+            return grpc.download_font(filename, progress).await;
+        }
+        let response = self.http.get(self.url(&format!("/fonts/{}", utils::encode_path_segment(filename)))).timeout(TRANSFER_TIMEOUT).send_with_retry().await?;
+        let mut response = Self::check(response, "Failed to download font").await?;
+
+        let total_size = response
+            .content_length()
+            .unwrap_or(0);
+        let modified = remote_modified(&response);
+
+        let mut bytes = Vec::with_capacity(total_size as usize);
+        while let Some(chunk) = response.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            self.progress.file_progress(Operation::Download, filename, bytes.len() as u64, total_size);
+        }
+        Ok((bytes, modified))
+    }
+}
+
+fn remote_modified(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(api::MODIFIED_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
 }
 
-#[derive(Deserialize, Debug)]
-pub struct FontList {
-    pub fonts: Vec<FontInfo>,
+// 监控客户端使用 WebSocket 地址，HTTP 接口位于同一主机的对应路径前缀下
+fn http_base_url(server_url: &str) -> String {
+    let base = server_url.trim_end_matches('/');
+    let (scheme, rest) = match base.split_once("://") {
+        Some(("ws", rest)) => ("http", rest),
+        Some(("wss", rest)) => ("https", rest),
+        _ => return base.to_string(),
+    };
+    let rest = rest.strip_suffix("/ws").unwrap_or(rest);
+    format!("{}://{}", scheme, rest)
 }
 
 pub async fn run_client(
@@ -72,232 +748,733 @@ pub async fn run_client(
     Ok(())
 }
 
+// 同步过程的公共选项
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub interactive: bool,
+    pub on_conflict: ConflictPolicy,
+    // 设置后上传前加密、下载后解密，服务器只保存密文
+    pub e2e_key: Option<TeamKey>,
+    // 拒绝未签名或与签名清单不符的内容
+    pub require_signed: bool,
+    // 预先分发的服务器签名公钥，优先于本地固定的公钥
+    pub trusted_signing_key: Option<String>,
+    // 只下载带有其中任一标签的字体，为空时不过滤
+    pub tags: Vec<String>,
+    // 只下载家族名匹配其中任一通配符、格式在其中的字体，为空时不过滤
+    pub families: Vec<String>,
+    pub formats: Vec<String>,
+    // 安装下载字体的范围
+    pub install_scope: InstallScope,
+    // 上传与安装时按 name 表把文件改名为 "<Family>-<Style>.<ext>"
+    pub normalize_names: bool,
+    // 扫描本地目录时进入符号链接的目录，同一文件经由不同路径只处理一次
+    pub follow_symlinks: bool,
+    // 本地没有的字体只写入占位条目，之后用 get 按需下载
+    pub lazy: bool,
+    // 字体列表与文件传输使用的接口
+    pub transport: Transport,
+    // 取消后不再开始新的传输，进行中的传输被中止
+    pub cancel: CancellationToken,
+}
+
+impl SyncOptions {
+    pub fn install_options(&self) -> InstallOptions {
+        InstallOptions { scope: self.install_scope, normalize_names: self.normalize_names, link: false }
+    }
+
+    // 监控模式在握手中声明、完整同步时在本地应用的订阅条件
+    pub fn subscription(&self) -> Subscription {
+        Subscription::new(&self.families, &self.tags, &self.formats)
+    }
+}
+
 pub async fn upload_local_fonts(
     server_url: &str,
     local_dir: &Path,
-    interactive: bool,
+    options: &SyncOptions,
+    report: &mut SyncReport,
 ) -> Result<(usize, usize)> {
     info!("Scanning local fonts for upload...");
     
-    let client = reqwest::Client::new();
+    let api = ApiClient::with_transport(server_url, options.transport)?.with_progress(report.progress.clone());
     let mut uploaded = 0;
     let mut skipped = 0;
 
     // 先获取服务器上已有字体及其 SHA256
-    let server_fonts = get_server_fonts_with_sha256(server_url).await?;
-    let server_font_map: std::collections::HashMap<String, String> = server_fonts
+    let server_fonts = api.list_fonts_hashed(&FontQuery::default()).await?;
+    // 家族与样式到服务器文件名，文件名不同的同一字体也能发现
+    let mut server_faces: HashMap<String, String> = HashMap::new();
+    for font in &server_fonts.fonts {
+        if let Some(key) = font.face_key() {
+            server_faces.entry(key).or_insert_with(|| font.name.clone());
+        }
+    }
+    let server_font_map: HashMap<String, FontInfo> = server_fonts
         .fonts
-        .iter()
-        .map(|f| (f.name.clone(), f.sha256.clone()))
+        .into_iter()
+        .map(|f| (f.name.clone(), f))
         .collect();
+    let tombstones = known_tombstones(&api, server_url).await;
+    let last_synced = last_synced_hashes(server_url);
+    let state = ClientState::load();
+    let mut synced = Vec::new();
+    let ignore = IgnoreRules::for_dir(local_dir);
 
-    for entry in WalkDir::new(local_dir)
-        .follow_links(true)
+    let files: Vec<PathBuf> = utils::font_files(local_dir, options.follow_symlinks, &mut HashSet::new())
         .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && utils::is_font_file(path) {
-            let filename = path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-
-            // 计算本地 SHA256
-            let local_sha256 = match utils::calculate_sha256(path) {
-                Ok(sha) => sha,
-                Err(e) => {
-                    error!("Failed to calculate SHA256 for '{}': {}", filename, e);
-                    continue;
+        .filter(|path| !ignore.is_ignored(path))
+        .collect();
+    let mut small_fonts = Vec::new();
+    report.progress.started(Operation::Upload, files.len());
+    for path in &files {
+        if options.cancel.is_cancelled() {
+            break;
+        }
+        let path = path.as_path();
+        // 与服务器保存时的规则一致，NFD 文件名也能与服务器上的名称对应
+        let mut filename = utils::sanitize_filename(path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown"));
+
+        // 计算本地 SHA256
+        let local_sha256 = match utils::calculate_sha256(path) {
+            Ok(sha) => sha,
+            Err(e) => {
+                error!("Failed to calculate SHA256 for '{}': {}", filename, e);
+                report.record_failed(&filename, SyncDirection::Upload, Duration::ZERO, format!("{:#}", e));
+                continue;
+            }
+        };
+
+        // 以规范化的名称上传，记住原名称以便下载时按内容匹配本地文件
+        if options.normalize_names
+            && let Some(normalized) = font_metadata::normalized_filename(path)
+            && normalized != filename
+        {
+            info!("Uploading '{}' as '{}'", filename, normalized);
+            if let Err(e) = ClientState::record_renamed(&filename, &normalized) {
+                warn!("Failed to save client state: {}", e);
+            }
+            filename = normalized;
+        }
+
+        // 其他客户端删除过的相同内容不再重新上传
+        if !server_font_map.contains_key(&filename)
+            && tombstones.get(&filename).is_some_and(|t| t.sha256 == local_sha256)
+        {
+            info!("Font '{}' was deleted on the server, skipping", filename);
+            report.record_skipped(&filename, SyncDirection::Upload);
+            skipped += 1;
+            continue;
+        }
+
+        // 服务器上以其他文件名保存的同一字体，替换后上传成功时删除
+        let mut superseded = None;
+        let descriptor = (!server_font_map.contains_key(&filename)).then(|| font_metadata::descriptor(path));
+        if let Some(descriptor) = &descriptor
+            && let Some(key) = descriptor.face_key()
+            && let Some(remote) = server_faces.get(&key).and_then(|name| server_font_map.get(name))
+        {
+            if remote.content_sha256() == local_sha256 {
+                info!("Font '{}' is already on the server as '{}', skipping", filename, remote.name);
+                if let Err(e) = ClientState::record_renamed(&filename, &remote.name) {
+                    warn!("Failed to save client state: {}", e);
                 }
-            };
+                report.record_skipped(&filename, SyncDirection::Upload);
+                synced.push((remote.name.clone(), local_sha256));
+                skipped += 1;
+                continue;
+            }
 
-            // 检查服务器是否已有该文件
-            if let Some(remote_sha256) = server_font_map.get(&filename) {
-                if local_sha256 == *remote_sha256 {
-                    info!("Font '{}' already exists with same SHA256, skipping", filename);
+            warn!(
+                "Font '{}' is the same face as server font '{}' with different content: local version {}, server version {}",
+                filename,
+                remote.name,
+                descriptor.version.as_deref().unwrap_or("unknown"),
+                remote.version.as_deref().unwrap_or("unknown"),
+            );
+            let conflict = utils::FileConflict {
+                filename: &filename,
+                local_sha256: &local_sha256,
+                remote_sha256: remote.content_sha256(),
+                local_modified: utils::get_file_timestamp(path).ok(),
+                remote_modified: remote.modified,
+                direction: SyncDirection::Upload,
+                counterpart: Some(&remote.name),
+            };
+            let resolution = utils::prompt_conflict_resolution(
+                &conflict,
+                options.interactive,
+                options.on_conflict,
+            )
+            .await?;
+            report.record_conflict(&filename, SyncDirection::Upload, resolution.clone(), None);
+            match resolution {
+                utils::ConflictResolution::Overwrite => {
+                    info!("Replacing server font '{}' with '{}'", remote.name, filename);
+                    superseded = Some(remote.name.clone());
+                }
+                utils::ConflictResolution::Rename => {
+                    info!("Keeping both '{}' and server font '{}'", filename, remote.name);
+                }
+                utils::ConflictResolution::Skip => {
+                    info!("Skipping font '{}'", filename);
+                    report.record_skipped(&filename, SyncDirection::Upload);
                     skipped += 1;
                     continue;
-                } else {
-                    // 检测到冲突
-                    info!("Conflict detected for '{}': local SHA256={}, remote SHA256={}", 
-                        filename, local_sha256, remote_sha256);
-                    
-                    let resolution = utils::prompt_conflict_resolution(
-                        &filename,
-                        &local_sha256,
-                        remote_sha256,
-                        interactive,
-                    )?;
+                }
+            }
+        }
 
-                    match resolution {
-                        utils::ConflictResolution::Overwrite => {
-                            info!("Overwriting font '{}'", filename);
-                        }
-                        utils::ConflictResolution::Rename => {
-                            // 生成唯一名称
-                            let mut counter = 1;
-                            let mut new_filename = utils::generate_unique_filename(path, counter);
-                            while server_font_map.contains_key(&new_filename) {
-                                counter += 1;
-                                new_filename = utils::generate_unique_filename(path, counter);
-                            }
-                            info!("Renaming font '{}' to '{}'", filename, new_filename);
-                            // 待办：实现重命名逻辑
-                            skipped += 1;
-                            continue;
-                        }
-                        utils::ConflictResolution::Skip => {
-                            info!("Skipping font '{}'", filename);
-                            skipped += 1;
-                            continue;
+        // 检查服务器是否已有该文件
+        if let Some(remote) = server_font_map.get(&filename) {
+            let origin = utils::classify_change(
+                last_synced.get(&filename).map(String::as_str),
+                &local_sha256,
+                remote.content_sha256(),
+            );
+            if local_sha256 == remote.content_sha256() {
+                info!("Font '{}' already exists with same SHA256, skipping", filename);
+                report.record_skipped(&filename, SyncDirection::Upload);
+                synced.push((filename, local_sha256));
+                skipped += 1;
+                continue;
+            } else if state.holds_pinned(&filename, remote.content_sha256()) {
+                info!("Font '{}' is pinned, not replacing the server version", filename);
+                report.record_pinned(&filename, SyncDirection::Upload);
+                skipped += 1;
+                continue;
+            } else if origin == ChangeOrigin::RemoteOnly {
+                info!("Font '{}' only changed on the server since last sync, leaving it for download", filename);
+                report.record_skipped(&filename, SyncDirection::Upload);
+                skipped += 1;
+                continue;
+            } else if origin == ChangeOrigin::LocalOnly {
+                info!("Font '{}' only changed locally since last sync, updating server", filename);
+            } else {
+                // 双方自上次同步后都有修改
+                info!("Conflict detected for '{}': local SHA256={}, remote SHA256={}", 
+                    filename, local_sha256, remote.content_sha256());
+                
+                let conflict = utils::FileConflict {
+                    filename: &filename,
+                    local_sha256: &local_sha256,
+                    remote_sha256: remote.content_sha256(),
+                    local_modified: utils::get_file_timestamp(path).ok(),
+                    remote_modified: remote.modified,
+                    direction: SyncDirection::Upload,
+                    counterpart: None,
+                };
+                let resolution = utils::prompt_conflict_resolution(
+                    &conflict,
+                    options.interactive,
+                    options.on_conflict,
+                )
+                .await?;
+
+                match resolution {
+                    utils::ConflictResolution::Overwrite => {
+                        info!("Overwriting font '{}'", filename);
+                        report.record_conflict(&filename, SyncDirection::Upload, resolution, None);
+                    }
+                    utils::ConflictResolution::Rename => {
+                        // 生成服务器与本地均不存在的唯一名称
+                        let mut counter = 1;
+                        let mut new_filename = utils::generate_unique_filename(path, counter);
+                        while server_font_map.contains_key(&new_filename)
+                            || local_dir.join(&new_filename).exists()
+                        {
+                            counter += 1;
+                            new_filename = utils::generate_unique_filename(path, counter);
                         }
-                                            }
+                        info!("Renaming font '{}' to '{}'", filename, new_filename);
+                        report.record_conflict(
+                            &filename,
+                            SyncDirection::Upload,
+                            resolution,
+                            Some(new_filename.clone()),
+                        );
+                        filename = new_filename;
+                    }
+                    utils::ConflictResolution::Skip => {
+                        info!("Skipping font '{}'", filename);
+                        report.record_conflict(&filename, SyncDirection::Upload, resolution, None);
+                        report.record_skipped(&filename, SyncDirection::Upload);
+                        skipped += 1;
+                        continue;
+                    }
                 }
             }
+        }
 
-            info!("Uploading font: {}", filename);
-            
-            match upload_font_file(&client, server_url, path, &filename, &local_sha256).await {
-                Ok(_) => {
-                    info!("Successfully uploaded: {}", filename);
-                    uploaded += 1;
-                    
-                    // 小延迟，避免请求过密
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-                }
-                Err(e) => {
-                    error!("Failed to upload '{}': {}", filename, e);
+        if let Some(permission) = font_metadata::embedding_permission(path)
+            && permission.is_restricted()
+        {
+            warn!(
+                "Font '{}' has a Restricted License embedding permission (fsType); redistribution may not be allowed",
+                filename
+            );
+        }
+
+        let remote_sha256 = server_font_map.get(&filename).map(|remote| remote.sha256.as_str());
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        // 服务器上还没有的小字体攒够一批后一次请求上传
+        if remote_sha256.is_none() && superseded.is_none() && size <= SMALL_UPLOAD_SIZE {
+            small_fonts.push((path.to_path_buf(), filename, local_sha256));
+            if small_fonts.len() == MAX_BATCH_UPLOADS {
+                let (batch_uploaded, batch_skipped) =
+                    upload_small_fonts(&api, std::mem::take(&mut small_fonts), options, report, &mut synced).await;
+                uploaded += batch_uploaded;
+                skipped += batch_skipped;
+            }
+            continue;
+        }
+
+        info!("Uploading font: {}", filename);
+        let started = std::time::Instant::now();
+        let upload =
+            || api.upload_changed_font(path, &filename, &local_sha256, options.e2e_key.as_ref(), false, remote_sha256);
+        let Some(result) = transfer_when_ready(Operation::Upload, &filename, options, &report.progress, upload).await else {
+            report.record_aborted(&filename, SyncDirection::Upload);
+            break;
+        };
+        match result {
+            // 批准前不记为已同步
+            Ok(response) if response.is_awaiting_approval() => {
+                info!("Font '{}' is awaiting approval on the server", filename);
+                report.record_transfer(
+                    &filename,
+                    SyncDirection::Upload,
+                    FileAction::AwaitingApproval,
+                    size,
+                    started.elapsed(),
+                );
+                uploaded += 1;
+            }
+            Ok(_) => {
+                info!("Successfully uploaded: {}", filename);
+                report.record_transfer(&filename, SyncDirection::Upload, FileAction::Uploaded, size, started.elapsed());
+                uploaded += 1;
+                if let Some(old) = superseded {
+                    // 只删除替换时看到的版本
+                    let expected = server_font_map.get(&old).map(|font| font.sha256.as_str());
+                    match api.delete_font(&old, expected).await {
+                        Ok(_) => info!("Removed superseded server font '{}'", old),
+                        Err(e) => warn!("Failed to remove superseded server font '{}': {}", old, e),
+                    }
                 }
+                synced.push((filename, local_sha256));
+                
+                // 小延迟，避免请求过密
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            // 列出服务器字体后又有他人上传，不覆盖，留待下一次同步按冲突处理
+            Err(e) if is_version_conflict(&e) => {
+                warn!("Font '{}' changed on the server during sync, not overwriting it", filename);
+                report.record_conflict(&filename, SyncDirection::Upload, utils::ConflictResolution::Skip, None);
+                report.record_skipped(&filename, SyncDirection::Upload);
+                skipped += 1;
+            }
+            Err(e) if is_retryable_error(&e) => {
+                warn!("Stopping upload, try again later: {:#}", e);
+                report.record_failed(&filename, SyncDirection::Upload, started.elapsed(), format!("{:#}", e));
+                break;
+            }
+            Err(e) => {
+                error!("Failed to upload '{}': {}", filename, e);
+                report.record_failed(&filename, SyncDirection::Upload, started.elapsed(), format!("{:#}", e));
             }
         }
     }
+    if !small_fonts.is_empty() && !options.cancel.is_cancelled() {
+        let (batch_uploaded, batch_skipped) = upload_small_fonts(&api, small_fonts, options, report, &mut synced).await;
+        uploaded += batch_uploaded;
+        skipped += batch_skipped;
+    }
+    report.progress.finished(Operation::Upload);
 
+    if let Err(e) = ClientState::record_synced(server_url, synced) {
+        warn!("Failed to save client state: {}", e);
+    }
     info!("Upload complete: {} uploaded, {} skipped", uploaded, skipped);
     Ok((uploaded, skipped))
 }
 
-async fn upload_font_file(
-    client: &reqwest::Client,
-    server_url: &str,
-    file_path: &Path,
-    filename: &str,
-    _sha256: &str,
-) -> Result<()> {
-    let file = File::open(file_path).await?;
-    let metadata = file.metadata().await?;
-    
-    let pb = ProgressBar::new(metadata.len());
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    
-    // 读取文件内容
-    let mut buffer = Vec::with_capacity(metadata.len() as usize);
-    let mut reader = tokio::io::BufReader::new(file);
-    reader.read_to_end(&mut buffer).await?;
-    
-    pb.finish_and_clear();
-    
-    // 创建 multipart 表单
-    let part = multipart::Part::bytes(buffer)
-        .file_name(filename.to_string())
-        .mime_str("application/octet-stream")?;
-    
-    let form = multipart::Form::new().part("font", part);
-    
-    let url = format!("{}/fonts", server_url);
-    let response = client.post(&url).multipart(form).send().await?;
-    
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(anyhow::anyhow!("Server error: {}", error_text));
+// 服务器上还没有的字体一次请求上传，旧版服务器没有批量接口时逐个上传；返回上传与跳过的数量
+async fn upload_small_fonts(
+    api: &ApiClient,
+    fonts: Vec<(PathBuf, String, String)>,
+    options: &SyncOptions,
+    report: &mut SyncReport,
+    synced: &mut Vec<(String, String)>,
+) -> (usize, usize) {
+    info!("Uploading {} small fonts in one request", fonts.len());
+    let started = std::time::Instant::now();
+    let label = format!("{} fonts", fonts.len());
+    let upload = || api.upload_fonts(&fonts, options.e2e_key.as_ref(), false);
+    // 结果与提交的字体顺序一致，超时后的字体不在结果中
+    let results: Vec<Result<FontActionResponse>> =
+        match transfer_when_ready(Operation::Upload, &label, options, &report.progress, upload).await {
+            None => Vec::new(),
+            Some(Ok(Some(response))) => response.results.into_iter().map(batch_font_result).collect(),
+            Some(Ok(None)) => {
+                info!("Server does not support batch uploads, uploading fonts one by one");
+                let mut results = Vec::new();
+                for (path, filename, sha256) in &fonts {
+                    let upload = || api.upload_font(path, filename, sha256, options.e2e_key.as_ref(), false);
+                    match transfer_when_ready(Operation::Upload, filename, options, &report.progress, upload).await {
+                        Some(result) => results.push(result),
+                        None => break,
+                    }
+                }
+                results
+            }
+            Some(Err(e)) => fonts.iter().map(|_| Err(anyhow::anyhow!("{:#}", e))).collect(),
+        };
+
+    let (mut uploaded, mut skipped) = (0, 0);
+    let mut results = results.into_iter();
+    for (path, filename, sha256) in fonts {
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        match results.next() {
+            None if options.cancel.is_cancelled() => report.record_aborted(&filename, SyncDirection::Upload),
+            None => report.record_failed(&filename, SyncDirection::Upload, started.elapsed(), "Not processed by the server"),
+            Some(Ok(response)) if response.is_awaiting_approval() => {
+                info!("Font '{}' is awaiting approval on the server", filename);
+                report.record_transfer(&filename, SyncDirection::Upload, FileAction::AwaitingApproval, size, started.elapsed());
+                uploaded += 1;
+            }
+            Some(Ok(_)) => {
+                info!("Successfully uploaded: {}", filename);
+                report.record_transfer(&filename, SyncDirection::Upload, FileAction::Uploaded, size, started.elapsed());
+                uploaded += 1;
+                synced.push((filename, sha256));
+            }
+            // 列出服务器字体后他人上传了同名字体，留待下一次同步按冲突处理
+            Some(Err(e)) if is_version_conflict(&e) => {
+                warn!("Font '{}' was added on the server during sync, not overwriting it", filename);
+                report.record_conflict(&filename, SyncDirection::Upload, utils::ConflictResolution::Skip, None);
+                report.record_skipped(&filename, SyncDirection::Upload);
+                skipped += 1;
+            }
+            Some(Err(e)) => {
+                error!("Failed to upload '{}': {}", filename, e);
+                report.record_failed(&filename, SyncDirection::Upload, started.elapsed(), format!("{:#}", e));
+            }
+        }
     }
-    
-    Ok(())
+    (uploaded, skipped)
 }
 
-pub async fn get_server_fonts(server_url: &str) -> Result<FontList> {
-    get_server_fonts_with_sha256(server_url).await
+fn batch_font_result(result: BatchUploadResult) -> Result<FontActionResponse> {
+    match (result.response, result.error) {
+        (Some(response), _) => Ok(response),
+        (None, Some(error)) => Err(anyhow::Error::new(ServerError::from_error_response(error, "Server error"))),
+        (None, None) => Err(anyhow::anyhow!("Server error: status {}", result.status)),
+    }
 }
 
-pub async fn get_server_fonts_with_sha256(server_url: &str) -> Result<FontList> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/fonts", server_url);
-    
-    let response = client.get(&url).send().await?;
-    
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(anyhow::anyhow!("Failed to get font list: {}", error_text));
+fn last_synced_hashes(server_url: &str) -> HashMap<String, String> {
+    ClientState::load()
+        .server(server_url)
+        .map(|s| s.synced.clone())
+        .unwrap_or_default()
+}
+
+// 服务器记录的删除与本地记住的删除合并，旧版服务器不提供删除记录时只使用本地记录
+pub async fn known_tombstones(api: &ApiClient, server_url: &str) -> HashMap<String, Tombstone> {
+    let mut tombstones = ClientState::load()
+        .server(server_url)
+        .map(|s| s.tombstones.clone())
+        .unwrap_or_default();
+    match api.tombstones().await {
+        Ok(list) => tombstones.extend(list.tombstones.into_iter().map(|t| (t.name, t.tombstone))),
+        Err(e) => debug!("Server does not provide deletion records: {}", e),
     }
-    
-    let font_list: FontList = response.json().await?;
-    Ok(font_list)
+    tombstones
+}
+
+// 返回经签名校验的文件名到 SHA256 的映射；服务器不提供清单且未要求签名时返回 None
+pub async fn verified_manifest(
+    server_url: &str,
+    options: &SyncOptions,
+) -> Result<Option<HashMap<String, String>>> {
+    let signed = match ApiClient::new(server_url)?.manifest().await {
+        Ok(signed) => signed,
+        Err(e) if !options.require_signed => {
+            info!("Server does not provide a signed manifest: {}", e);
+            return Ok(None);
+        }
+        Err(e) => return Err(e.context("Server does not provide a signed manifest")),
+    };
+
+    let pinned = ClientState::load().server(server_url).and_then(|s| s.signing_key.clone());
+    let trusted = options.trusted_signing_key.clone().or(pinned);
+    let manifest = signed
+        .verify(trusted.as_deref())
+        .context("Signed manifest verification failed")?;
+
+    // 严格模式下首次连接时固定服务器公钥，之后更换密钥会被拒绝
+    if options.require_signed && trusted.is_none() {
+        info!("Pinning manifest signing key for {}: {}", server_url, signed.public_key);
+        ClientState::update(|state| {
+            state.server_mut(server_url).signing_key = Some(signed.public_key.clone());
+        })?;
+    }
+
+    Ok(Some(manifest.hashes()))
+}
+
+// 本地副本全部无需下载时返回内容一致的文件及其哈希，否则返回 None
+fn local_copies_up_to_date(
+    local_dir: &Path,
+    hashes: &FontHashes,
+    last_synced: &HashMap<String, String>,
+) -> Option<Vec<(String, String)>> {
+    if !hashes.pending.is_empty() {
+        return None;
+    }
+    let mut identical = Vec::new();
+    for (name, remote_sha256) in &hashes.fonts {
+        let local_sha256 = utils::calculate_sha256(&local_dir.join(name)).ok()?;
+        if local_sha256 == *remote_sha256 {
+            identical.push((name.clone(), local_sha256));
+            continue;
+        }
+        let origin = utils::classify_change(last_synced.get(name).map(String::as_str), &local_sha256, remote_sha256);
+        if origin != ChangeOrigin::LocalOnly {
+            return None;
+        }
+    }
+    Some(identical)
 }
 
 pub async fn download_server_fonts(
     server_url: &str,
     local_dir: &Path,
-    interactive: bool,
+    options: &SyncOptions,
+    report: &mut SyncReport,
 ) -> Result<(usize, usize)> {
     info!("Downloading fonts from server...");
     
-    let font_list = get_server_fonts_with_sha256(server_url).await?;
-    let client = reqwest::Client::new();
+    let api = ApiClient::with_transport(server_url, options.transport)?.with_progress(report.progress.clone());
+    let last_synced = last_synced_hashes(server_url);
+
+    // 预检：所有服务器字体在本地都已是最新或只在本地修改过时，不必获取完整列表
+    match api.font_hashes(&options.tags).await {
+        Ok(hashes) => {
+            if let Some(up_to_date) = local_copies_up_to_date(local_dir, &hashes, &last_synced) {
+                info!("All {} server fonts are up to date locally", hashes.fonts.len());
+                if let Err(e) = ClientState::record_synced(server_url, up_to_date) {
+                    warn!("Failed to save client state: {}", e);
+                }
+                return Ok((0, hashes.fonts.len()));
+            }
+        }
+        Err(e) => debug!("Hash preflight unavailable: {}", e),
+    }
+
+    let font_list = api.list_fonts_tagged(&options.tags).await?;
+    let signed_hashes = verified_manifest(server_url, options).await?;
+    let state = ClientState::load();
+    let server_names: HashSet<String> =
+        font_list.fonts.iter().map(|f| f.name.clone()).collect();
+    let mut synced = Vec::new();
     let mut downloaded = 0;
     let mut skipped = 0;
+    let mut placeholders = 0;
+    // 首次需要时才读取本地字体的家族与样式
+    let mut local_faces: Option<HashMap<String, PathBuf>> = None;
 
+    let subscription = options.subscription();
+    let total = font_list.fonts.iter().filter(|font| subscription.matches_font(font)).count();
+    report.progress.started(Operation::Download, total);
     for font in font_list.fonts {
-        let font_path = local_dir.join(&font.name);
+        if options.cancel.is_cancelled() {
+            break;
+        }
+        let mut font_path = local_dir.join(&font.name);
         
+        // 旧版服务器会忽略标签参数，家族与格式只在本地过滤
+        if !subscription.matches_font(&font) {
+            debug!("Font '{}' is outside the subscription, skipping", font.name);
+            continue;
+        }
+
+        // 固定的字体保留本地版本
+        if state.holds_pinned(&font.name, font.content_sha256()) {
+            info!("Font '{}' is pinned, keeping the local version", font.name);
+            report.record_pinned(&font.name, SyncDirection::Download);
+            skipped += 1;
+            continue;
+        }
+        
+        // 只下载签名清单覆盖的内容
+        if let Some(hashes) = &signed_hashes
+            && hashes.get(&font.name) != Some(&font.sha256)
+        {
+            error!("Font '{}' does not match the signed manifest, refusing", font.name);
+            report.record_failed(&font.name, SyncDirection::Download, Duration::ZERO, "Does not match the signed manifest");
+            skipped += 1;
+            continue;
+        }
+        
+        // 没有团队密钥时无法解密
+        if font.plaintext_sha256.is_some() && options.e2e_key.is_none() {
+            warn!("Font '{}' is end-to-end encrypted, skipping (no team key)", font.name);
+            report.record_skipped(&font.name, SyncDirection::Download);
+            skipped += 1;
+            continue;
+        }
+        
+        // 以规范化名称上传的字体在本地仍是原名称，内容相同时不再下载
+        if !font_path.exists()
+            && let Some(original) = state.original_name(&font.name)
+            && utils::calculate_sha256(&local_dir.join(original)).is_ok_and(|sha| sha == font.content_sha256())
+        {
+            info!("Font '{}' is stored locally as '{}', skipping", font.name, original);
+            synced.push((font.name.clone(), font.content_sha256().to_string()));
+            report.record_skipped(&font.name, SyncDirection::Download);
+            skipped += 1;
+            continue;
+        }
+
+        // 本地以其他文件名保存的同一字体，替换后下载成功时删除
+        let mut superseded = None;
+        if !font_path.exists()
+            && let Some(key) = font.face_key()
+            && let Some(local_path) = local_faces.get_or_insert_with(|| local_faces_in(local_dir)).get(&key)
+        {
+            let local_name = local_path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+            match utils::calculate_sha256(local_path) {
+                Ok(local_sha256) if local_sha256 == font.content_sha256() => {
+                    info!("Font '{}' is stored locally as '{}', skipping", font.name, local_name);
+                    if let Err(e) = ClientState::record_renamed(&local_name, &font.name) {
+                        warn!("Failed to save client state: {}", e);
+                    }
+                    synced.push((font.name.clone(), local_sha256));
+                    report.record_skipped(&font.name, SyncDirection::Download);
+                    skipped += 1;
+                    continue;
+                }
+                Ok(local_sha256) => {
+                    warn!(
+                        "Font '{}' is the same face as local file '{}' with different content: local version {}, server version {}",
+                        font.name,
+                        local_name,
+                        font_metadata::descriptor(local_path).version.as_deref().unwrap_or("unknown"),
+                        font.version.as_deref().unwrap_or("unknown"),
+                    );
+                    let conflict = utils::FileConflict {
+                        filename: &font.name,
+                        local_sha256: &local_sha256,
+                        remote_sha256: font.content_sha256(),
+                        local_modified: utils::get_file_timestamp(local_path).ok(),
+                        remote_modified: font.modified,
+                        direction: SyncDirection::Download,
+                        counterpart: Some(&local_name),
+                    };
+                    let resolution = utils::prompt_conflict_resolution(
+                        &conflict,
+                        options.interactive,
+                        options.on_conflict,
+                    )
+                    .await?;
+                    report.record_conflict(&font.name, SyncDirection::Download, resolution.clone(), None);
+                    match resolution {
+                        utils::ConflictResolution::Overwrite => {
+                            info!("Replacing local file '{}' with '{}'", local_name, font.name);
+                            superseded = Some(local_path.clone());
+                        }
+                        utils::ConflictResolution::Rename => {
+                            info!("Keeping both '{}' and local file '{}'", font.name, local_name);
+                        }
+                        utils::ConflictResolution::Skip => {
+                            info!("Skipping font '{}'", font.name);
+                            report.record_skipped(&font.name, SyncDirection::Download);
+                            skipped += 1;
+                            continue;
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to calculate SHA256 for local file '{}': {}", local_name, e),
+            }
+        }
+
         // 检查本地是否已存在
         if font_path.exists() {
             match utils::calculate_sha256(&font_path) {
                 Ok(local_sha256) => {
-                    if local_sha256 == font.sha256 {
+                    let origin = utils::classify_change(
+                        last_synced.get(&font.name).map(String::as_str),
+                        &local_sha256,
+                        font.content_sha256(),
+                    );
+                    if local_sha256 == font.content_sha256() {
                         info!("Font '{}' already exists with same SHA256, skipping", font.name);
+                        synced.push((font.name.clone(), local_sha256));
+                        report.record_skipped(&font.name, SyncDirection::Download);
+                        skipped += 1;
+                        continue;
+                    } else if origin == ChangeOrigin::LocalOnly {
+                        info!("Font '{}' only changed locally since last sync, keeping local copy", font.name);
+                        report.record_skipped(&font.name, SyncDirection::Download);
                         skipped += 1;
                         continue;
+                    } else if origin == ChangeOrigin::RemoteOnly {
+                        info!("Font '{}' only changed on the server since last sync, updating local copy", font.name);
                     } else {
-                        // 检测到冲突
+                        // 双方自上次同步后都有修改
                         info!("Conflict detected for '{}': local SHA256={}, remote SHA256={}", 
-                            font.name, local_sha256, font.sha256);
+                            font.name, local_sha256, font.content_sha256());
                         
+                        let conflict = utils::FileConflict {
+                            filename: &font.name,
+                            local_sha256: &local_sha256,
+                            remote_sha256: font.content_sha256(),
+                            local_modified: utils::get_file_timestamp(&font_path).ok(),
+                            remote_modified: font.modified,
+                            direction: SyncDirection::Download,
+                            counterpart: None,
+                        };
                         let resolution = utils::prompt_conflict_resolution(
-                            &font.name,
-                            &local_sha256,
-                            &font.sha256,
-                            interactive,
-                        )?;
+                            &conflict,
+                            options.interactive,
+                            options.on_conflict,
+                        )
+                        .await?;
 
                         match resolution {
                             utils::ConflictResolution::Overwrite => {
                                 info!("Overwriting font '{}'", font.name);
+                                report.record_conflict(&font.name, SyncDirection::Download, resolution, None);
                             }
                             utils::ConflictResolution::Rename => {
-                                // 生成唯一名称
+                                // 生成本地与服务器均不存在的唯一名称
                                 let mut counter = 1;
                                 let mut new_filename = utils::generate_unique_filename(&font_path, counter);
-                                while local_dir.join(&new_filename).exists() {
+                                while local_dir.join(&new_filename).exists()
+                                    || server_names.contains(&new_filename)
+                                {
                                     counter += 1;
                                     new_filename = utils::generate_unique_filename(&font_path, counter);
                                 }
                                 info!("Renaming font '{}' to '{}'", font.name, new_filename);
-                                // 待办：实现重命名逻辑
-                                skipped += 1;
-                                continue;
+                                report.record_conflict(
+                                    &font.name,
+                                    SyncDirection::Download,
+                                    resolution,
+                                    Some(new_filename.clone()),
+                                );
+                                font_path = local_dir.join(new_filename);
                             }
                             utils::ConflictResolution::Skip => {
                                 info!("Skipping font '{}'", font.name);
+                                report.record_conflict(&font.name, SyncDirection::Download, resolution, None);
+                                report.record_skipped(&font.name, SyncDirection::Download);
                                 skipped += 1;
                                 continue;
                             }
-                                                    }
+                        }
                     }
                 }
                 Err(e) => {
@@ -307,25 +1484,103 @@ pub async fn download_server_fonts(
             }
         }
 
+        // 稀疏同步只为本地没有的字体写入占位，已下载的字体照常更新
+        if options.lazy && superseded.is_none() && !local_dir.join(&font.name).exists() {
+            match placeholder::write(local_dir, &Placeholder::new(server_url, &font)) {
+                Ok(()) => {
+                    debug!("Wrote placeholder for '{}'", font.name);
+                    report.record_skipped(&font.name, SyncDirection::Download);
+                    placeholders += 1;
+                    skipped += 1;
+                }
+                Err(e) => {
+                    error!("{:#}", e);
+                    report.record_failed(&font.name, SyncDirection::Download, Duration::ZERO, format!("{:#}", e));
+                }
+            }
+            continue;
+        }
+
+        if font.restricted {
+            warn!("Font '{}' has a Restricted License embedding permission (fsType)", font.name);
+        }
+
         info!("Downloading font: {} ({} bytes)", font.name, font.size);
         
-        match download_font_file(&client, server_url, &font.name, &font_path).await {
+        // 本地已有的旧版本可作为增量的基础，加密字体除外
+        let base_path = local_dir.join(&font.name);
+        let started = std::time::Instant::now();
+        let download = {
+            let (api, font, base_path, font_path) = (&api, &font, &base_path, &font_path);
+            move || async move {
+                if font.plaintext_sha256.is_none() && base_path.is_file() {
+                    api.download_font_from_base(&font.name, base_path, font_path, &font.sha256).await
+                } else {
+                    api.download_font(&font.name, font_path, &font.sha256).await
+                }
+            }
+        };
+        let Some(fetched) = transfer_when_ready(Operation::Download, &font.name, options, &report.progress, download).await
+        else {
+            report.record_aborted(&font.name, SyncDirection::Download);
+            break;
+        };
+        match fetched {
             Ok(_) => {
                 // 校验已下载文件的 SHA256
                 match utils::calculate_sha256(&font_path) {
                     Ok(downloaded_sha256) => {
                         if downloaded_sha256 == font.sha256 {
-                            info!("Successfully downloaded and verified: {}", font.name);
-                            downloaded += 1;
+                            let decrypted = match (&font.plaintext_sha256, &options.e2e_key) {
+                                (Some(expected), Some(key)) => decrypt_downloaded_font(&font_path, key, expected),
+                                _ => Ok(()),
+                            };
+                            match decrypted {
+                                Ok(()) => {
+                                    // 解密会重写文件，按列表中的时间再设置一次
+                                    if let Some(modified) = font.modified
+                                        && let Err(e) = utils::set_file_timestamp(&font_path, modified)
+                                    {
+                                        warn!("Failed to set modified time of '{}': {}", font.name, e);
+                                    }
+                                    info!("Successfully downloaded and verified: {}", font.name);
+                                    report.record_transfer(
+                                        &font.name,
+                                        SyncDirection::Download,
+                                        FileAction::Downloaded,
+                                        font.size,
+                                        started.elapsed(),
+                                    );
+                                    downloaded += 1;
+                                    // 重命名保存的副本与服务器上的同名文件不对应
+                                    if font_path == local_dir.join(&font.name) {
+                                        synced.push((font.name.clone(), font.content_sha256().to_string()));
+                                        placeholder::remove(local_dir, &font.name);
+                                    }
+                                    if let Some(old) = &superseded {
+                                        match fs::remove_file(old) {
+                                            Ok(()) => info!("Removed superseded local file {:?}", old),
+                                            Err(e) => warn!("Failed to remove superseded local file {:?}: {}", old, e),
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    error!("Failed to decrypt '{}': {}", font.name, e);
+                                    report.record_failed(&font.name, SyncDirection::Download, started.elapsed(), format!("{:#}", e));
+                                    let _ = fs::remove_file(&font_path);
+                                }
+                            }
                         } else {
                             error!("SHA256 mismatch for downloaded file '{}': expected={}, got={}", 
                                 font.name, font.sha256, downloaded_sha256);
+                            report.record_failed(&font.name, SyncDirection::Download, started.elapsed(), "SHA256 mismatch");
                             // 移除损坏文件
                             let _ = fs::remove_file(&font_path);
                         }
                     }
                     Err(e) => {
                         error!("Failed to verify SHA256 for '{}': {}", font.name, e);
+                        report.record_failed(&font.name, SyncDirection::Download, started.elapsed(), format!("{:#}", e));
                     }
                 }
                 
@@ -334,81 +1589,154 @@ pub async fn download_server_fonts(
             }
             Err(e) => {
                 error!("Failed to download '{}': {}", font.name, e);
+                report.record_failed(&font.name, SyncDirection::Download, started.elapsed(), format!("{:#}", e));
             }
         }
     }
 
+    if let Err(e) = ClientState::record_synced(server_url, synced) {
+        warn!("Failed to save client state: {}", e);
+    }
+    // 按标签过滤的列表不完整，无法判断其余字体是否已删除
+    if options.tags.is_empty() && !options.cancel.is_cancelled() {
+        placeholder::prune(local_dir, server_url, &server_names);
+    }
+    report.progress.finished(Operation::Download);
+    if placeholders > 0 {
+        info!("Wrote {} placeholders; use `fontsync get` to download fonts on demand", placeholders);
+    }
     info!("Download complete: {} downloaded, {} skipped", downloaded, skipped);
     Ok((downloaded, skipped))
 }
 
-async fn download_font_file(
-    client: &reqwest::Client,
+// 下载占位条目对应的字体并删除占位，返回下载的字体路径；
+// 占位写入后服务器上的字体可能已更新，按服务器当前的版本下载
+pub async fn materialize_placeholders(
     server_url: &str,
-    filename: &str,
-    output_path: &Path,
-) -> Result<()> {
-    let url = format!("{}/fonts/{}", server_url, filename);
-    
-    let response = client.get(&url).send().await?;
-    
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(anyhow::anyhow!("Failed to download font: {}", error_text));
+    local_dir: &Path,
+    placeholders: &[Placeholder],
+    options: &SyncOptions,
+    report: &mut SyncReport,
+) -> Result<Vec<PathBuf>> {
+    let api = ApiClient::with_transport(server_url, options.transport)?.with_progress(report.progress.clone());
+    let server_fonts: HashMap<String, FontInfo> = api
+        .list_fonts_hashed(&FontQuery::default())
+        .await?
+        .fonts
+        .into_iter()
+        .map(|font| (font.name.clone(), font))
+        .collect();
+    let signed_hashes = verified_manifest(server_url, options).await?;
+
+    let mut paths = Vec::new();
+    report.progress.started(Operation::Download, placeholders.len());
+    for placeholder in placeholders {
+        let started = std::time::Instant::now();
+        let fetched = async {
+            let font = server_fonts
+                .get(&placeholder.name)
+                .with_context(|| format!("'{}' no longer exists on the server", placeholder.name))?;
+            if let Some(hashes) = &signed_hashes
+                && hashes.get(&font.name) != Some(&font.sha256)
+            {
+                anyhow::bail!("Does not match the signed manifest");
+            }
+            if font.plaintext_sha256.is_some() && options.e2e_key.is_none() {
+                anyhow::bail!("End-to-end encrypted and no team key was given");
+            }
+            let path = local_dir.join(&font.name);
+            api.download_font(&font.name, &path, &font.sha256).await?;
+            if let (Some(expected), Some(key)) = (&font.plaintext_sha256, &options.e2e_key)
+                && let Err(e) = decrypt_downloaded_font(&path, key, expected)
+            {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+            anyhow::Ok((path, font))
+        };
+        match fetched.await {
+            Ok((path, font)) => {
+                info!("Downloaded {}", font.name);
+                report.record_transfer(&font.name, SyncDirection::Download, FileAction::Downloaded, font.size, started.elapsed());
+                if let Err(e) = ClientState::record_synced(server_url, [(font.name.clone(), font.content_sha256().to_string())]) {
+                    warn!("Failed to save client state: {}", e);
+                }
+                placeholder::remove(local_dir, &font.name);
+                paths.push(path);
+            }
+            Err(e) => {
+                error!("Failed to download '{}': {:#}", placeholder.name, e);
+                report.record_failed(&placeholder.name, SyncDirection::Download, started.elapsed(), format!("{:#}", e));
+            }
+        }
     }
-    
-    let total_size = response
-        .content_length()
-        .unwrap_or(0);
-    
-    let pb = ProgressBar::new(total_size);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    
-    let mut file = File::create(output_path).await?;
-    let bytes = response.bytes().await?;
-    
-    file.write_all(&bytes).await?;
-    pb.inc(bytes.len() as u64);
-    
-    pb.finish_and_clear();
-    file.flush().await?;
-    
-    Ok(())
+    report.progress.finished(Operation::Download);
+    Ok(paths)
+}
+
+// 同步目录中字体文件的家族与样式，同一字体有多个文件时取文件名最小的一个
+fn local_faces_in(local_dir: &Path) -> HashMap<String, PathBuf> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(local_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|path| path.is_file() && utils::is_font_file(path))
+            .collect(),
+        Err(_) => return HashMap::new(),
+    };
+    paths.sort();
+
+    let mut faces = HashMap::new();
+    for path in paths {
+        if let Some(key) = font_metadata::descriptor(&path).face_key() {
+            faces.entry(key).or_insert(path);
+        }
+    }
+    faces
 }
 
-pub async fn install_downloaded_fonts(local_dir: &Path) -> Result<(usize, usize)> {
+// 将下载的密文原地替换为明文，并校验明文哈希
+pub fn decrypt_downloaded_font(path: &Path, key: &TeamKey, expected_sha256: &str) -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let data = fs::read(path).context("Failed to read downloaded font")?;
+    if !e2e::is_encrypted(&data) {
+        return Err(anyhow::anyhow!("Server marked the font as encrypted but it is not"));
+    }
+
+    let plaintext = key.decrypt(&data)?;
+    let actual = hex::encode(Sha256::digest(&plaintext));
+    if actual != expected_sha256 {
+        return Err(anyhow::anyhow!(
+            "Plaintext SHA256 mismatch: expected={}, got={}",
+            expected_sha256,
+            actual
+        ));
+    }
+
+    utils::write_atomic(path, &plaintext, None).context("Failed to write decrypted font")
+}
+
+pub async fn install_downloaded_fonts(local_dir: &Path, options: &SyncOptions, report: &mut SyncReport) -> Result<(usize, usize)> {
     info!("Installing downloaded fonts...");
     
-    let mut installed = 0;
-    let mut failed = 0;
-
-    for entry in WalkDir::new(local_dir)
+    let paths = WalkDir::new(local_dir)
         .max_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && utils::is_font_file(path) {
-            info!("Installing font: {:?}", path.file_name().unwrap_or_default());
-            
-            match font_installer::install_font(path).await {
-                Ok(_) => {
-                    info!("Successfully installed font");
-                    installed += 1;
-                }
-                Err(e) => {
-                    error!("Failed to install font: {}", e);
-                    failed += 1;
-                }
-            }
-        }
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && utils::is_font_file(path))
+        .collect();
+
+    let results = font_installer::install_fonts(paths, options.install_options(), &report.progress).await;
+    for font in results.iter().filter_map(|r| r.installed()) {
+        report.record_installed(font);
     }
+    let (installed, failed, skipped) = font_installer::tally(&results);
 
-    info!("Installation complete: {} installed, {} failed", installed, failed);
+    info!(
+        "Installation complete: {} installed, {} failed, {} skipped (protected)",
+        installed, failed, skipped
+    );
     Ok((installed, failed))
 }
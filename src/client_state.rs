@@ -0,0 +1,248 @@
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::api::Tombstone;
+
+const STATE_FILE: &str = "state.json";
+
+// 同一进程内的读取-修改-保存依次进行，避免并发任务互相覆盖
+static STATE_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+// 单个服务器对应的客户端同步状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerState {
+    #[serde(default)]
+    pub last_event_seq: Option<u64>,
+    // 首次严格校验时记录的服务器清单签名公钥
+    #[serde(default)]
+    pub signing_key: Option<String>,
+    // 已知在服务器上被删除的字体，服务器不可用时也不会重新上传
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tombstones: HashMap<String, Tombstone>,
+    // 各文件上次同步成功时双方一致的内容哈希，用于区分单方修改与真正的冲突
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub synced: HashMap<String, String>,
+}
+
+// 固定版本的字体：与服务器内容不同时自动同步既不下载也不上传，保留本地版本
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontPin {
+    // 允许同步的内容哈希，为空时只保留本地现有版本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    pub pinned_at: u64,
+}
+
+// 下载目录中安装过的字体；安装后副本被清理时仍保留，用于校验删除通知针对的是否同一字体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    pub sha256: String,
+    pub downloaded_at: u64,
+}
+
+// 客户端持久化状态，按服务器 URL 区分
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientState {
+    #[serde(default)]
+    pub servers: HashMap<String, ServerState>,
+    // 按文件名固定的字体，对所有服务器生效
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pins: BTreeMap<String, FontPin>,
+    // 按 name 表规范化后的文件名到本地原文件名的映射，对所有服务器生效
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub renamed: BTreeMap<String, String>,
+    // 下载目录中按文件名记录的内容哈希，对所有服务器生效
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub downloads: BTreeMap<String, DownloadRecord>,
+}
+
+impl ClientState {
+    pub fn state_dir() -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("fontsync")
+    }
+
+    fn state_path() -> PathBuf {
+        Self::state_dir().join(STATE_FILE)
+    }
+
+    // 读取状态文件，不存在或损坏时返回默认状态
+    pub fn load() -> Self {
+        fs::read_to_string(Self::state_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    // 只在持有 STATE_LOCK 时调用，外部修改统一经过 update
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(Self::state_dir()).context("Failed to create state directory")?;
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize client state")?;
+        // 先写临时文件再重命名，避免中断时留下半截状态
+        let tmp_path = Self::state_path().with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write client state")?;
+        fs::rename(&tmp_path, Self::state_path()).context("Failed to replace client state")?;
+        Ok(())
+    }
+
+    // 加锁后重新读取、修改并保存，避免用过期的快照覆盖其他任务写入的状态
+    pub fn update<T>(modify: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        let result = modify(&mut state);
+        state.save()?;
+        Ok(result)
+    }
+
+    // 重新读取后合并，避免覆盖其他任务同时写入的状态
+    pub fn record_synced<I>(server_url: &str, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let _guard = STATE_LOCK.lock();
+        let mut entries = entries.into_iter().peekable();
+        if entries.peek().is_none() {
+            return Ok(());
+        }
+        let mut state = Self::load();
+        state.server_mut(server_url).synced.extend(entries);
+        state.save()
+    }
+
+    // 服务器上改名后，同步记录随之转到新名称
+    pub fn record_moved(server_url: &str, from: &str, to: &str, sha256: &str) -> Result<()> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        let server = state.server_mut(server_url);
+        server.synced.remove(from);
+        server.synced.insert(to.to_string(), sha256.to_string());
+        state.save()
+    }
+
+    pub fn pin(filename: &str, sha256: Option<String>) -> Result<()> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        let pinned_at = chrono::Utc::now().timestamp() as u64;
+        state.pins.insert(filename.to_string(), FontPin { sha256, pinned_at });
+        state.save()
+    }
+
+    // 返回是否存在该固定
+    pub fn unpin(filename: &str) -> Result<bool> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        let removed = state.pins.remove(filename).is_some();
+        if removed {
+            state.save()?;
+        }
+        Ok(removed)
+    }
+
+    // 字体已固定且服务器上的内容不是固定的版本
+    pub fn holds_pinned(&self, filename: &str, remote_sha256: &str) -> bool {
+        self.pins
+            .get(filename)
+            .is_some_and(|pin| pin.sha256.as_deref() != Some(remote_sha256))
+    }
+
+    pub fn record_download(filename: &str, sha256: &str) -> Result<()> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        let downloaded_at = chrono::Utc::now().timestamp() as u64;
+        state.downloads.insert(filename.to_string(), DownloadRecord { sha256: sha256.to_string(), downloaded_at });
+        state.save()
+    }
+
+    pub fn forget_download(filename: &str) -> Result<()> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        if state.downloads.remove(filename).is_some() {
+            state.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn downloaded_sha256(&self, filename: &str) -> Option<&str> {
+        self.downloads.get(filename).map(|record| record.sha256.as_str())
+    }
+
+    pub fn record_renamed(original: &str, normalized: &str) -> Result<()> {
+        let _guard = STATE_LOCK.lock();
+        let mut state = Self::load();
+        if state.renamed.get(normalized).map(String::as_str) == Some(original) {
+            return Ok(());
+        }
+        state.renamed.insert(normalized.to_string(), original.to_string());
+        state.save()
+    }
+
+    pub fn original_name(&self, normalized: &str) -> Option<&str> {
+        self.renamed.get(normalized).map(String::as_str)
+    }
+
+    // 由 original 规范化得到的所有文件名
+    pub fn normalized_names(&self, original: &str) -> Vec<&str> {
+        self.renamed
+            .iter()
+            .filter(|(_, from)| from.as_str() == original)
+            .map(|(to, _)| to.as_str())
+            .collect()
+    }
+
+    pub fn server(&self, server_url: &str) -> Option<&ServerState> {
+        self.servers.get(server_url.trim_end_matches('/'))
+    }
+
+    pub fn server_mut(&mut self, server_url: &str) -> &mut ServerState {
+        self.servers
+            .entry(server_url.trim_end_matches('/').to_string())
+            .or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_hold_every_other_version() {
+        let mut state = ClientState::default();
+        state.pins.insert("local.ttf".to_string(), FontPin { sha256: None, pinned_at: 1 });
+        state.pins.insert("exact.ttf".to_string(), FontPin { sha256: Some("aa".to_string()), pinned_at: 1 });
+
+        // 未指定哈希时服务器上的任何内容都不同步
+        assert!(state.holds_pinned("local.ttf", "aa"));
+        // 服务器内容正是固定的版本时照常同步
+        assert!(!state.holds_pinned("exact.ttf", "aa"));
+        assert!(state.holds_pinned("exact.ttf", "bb"));
+        assert!(!state.holds_pinned("other.ttf", "bb"));
+
+        // 没有固定时不写入 pins 字段
+        let value = serde_json::to_value(ClientState::default()).unwrap();
+        assert!(value.get("pins").is_none());
+        let value = serde_json::to_value(&state).unwrap();
+        assert!(value["pins"]["local.ttf"].get("sha256").is_none());
+        let parsed: ClientState = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.pins, state.pins);
+    }
+
+    #[test]
+    fn pin_and_unpin_update_the_saved_state() {
+        let font = format!("pin-{}.ttf", uuid::Uuid::new_v4());
+        ClientState::pin(&font, Some("aa".to_string())).unwrap();
+        assert_eq!(ClientState::load().pins[&font].sha256.as_deref(), Some("aa"));
+
+        // 再次固定时替换原来的版本
+        ClientState::pin(&font, None).unwrap();
+        assert!(ClientState::load().holds_pinned(&font, "aa"));
+
+        assert!(ClientState::unpin(&font).unwrap());
+        assert!(!ClientState::load().pins.contains_key(&font));
+        assert!(!ClientState::unpin(&font).unwrap());
+    }
+}
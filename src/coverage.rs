@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+
+// 报告覆盖率的 Unicode 区段（名称, [(起始码位, 结束码位)]）
+// 希腊字母与西里尔字母只统计现代常用字母，避免区块中未分配的码位拉低覆盖率
+pub const RANGES: &[(&str, &[(u32, u32)])] = &[
+    ("Latin", &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)]),
+    ("Greek", &[(0x0384, 0x038A), (0x038C, 0x038C), (0x038E, 0x03A1), (0x03A3, 0x03CE)]),
+    ("Cyrillic", &[(0x0400, 0x045F)]),
+    ("CJK Unified", &[(0x4E00, 0x9FFF)]),
+    ("Emoji", &[(0x1F300, 0x1F5FF), (0x1F600, 0x1F64F), (0x1F680, 0x1F6FF), (0x1F900, 0x1F9FF)]),
+];
+
+// 覆盖率达到该比例即视为支持该区段
+const SUPPORTED_THRESHOLD: f64 = 0.9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeCoverage {
+    pub name: &'static str,
+    pub covered: usize,
+    pub total: usize,
+}
+
+impl RangeCoverage {
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.covered as f64 / self.total as f64
+        }
+    }
+}
+
+// 读取所有 Unicode cmap 子表映射的码位，无法解析时返回 None
+pub fn read_codepoints(data: &[u8]) -> Option<BTreeSet<u32>> {
+    let cmap = cmap_table(data)?;
+    let mut codepoints = BTreeSet::new();
+    for subtable in cmap.subtables.into_iter().filter(|s| s.is_unicode()) {
+        subtable.codepoints(|cp| {
+            if subtable.glyph_index(cp).is_some_and(|glyph| glyph.0 != 0) {
+                codepoints.insert(cp);
+            }
+        });
+    }
+    Some(codepoints)
+}
+
+pub fn range_coverage(codepoints: &BTreeSet<u32>) -> Vec<RangeCoverage> {
+    RANGES
+        .iter()
+        .map(|(name, blocks)| RangeCoverage {
+            name,
+            covered: blocks
+                .iter()
+                .map(|&(start, end)| codepoints.range(start..=end).count())
+                .sum(),
+            total: blocks.iter().map(|&(start, end)| (end - start + 1) as usize).sum(),
+        })
+        .collect()
+}
+
+// 覆盖率达到阈值的区段名称
+pub fn supported_ranges(data: &[u8]) -> Vec<String> {
+    read_codepoints(data)
+        .map(|codepoints| {
+            range_coverage(&codepoints)
+                .into_iter()
+                .filter(|range| range.ratio() >= SUPPORTED_THRESHOLD)
+                .map(|range| range.name.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 逐个查询 cmap，不必展开全部码位
+pub fn supports_all(data: &[u8], codepoints: &[u32]) -> bool {
+    let Some(cmap) = cmap_table(data) else {
+        return false;
+    };
+    codepoints.iter().all(|&cp| {
+        cmap.subtables
+            .into_iter()
+            .filter(|s| s.is_unicode())
+            .any(|s| s.glyph_index(cp).is_some_and(|glyph| glyph.0 != 0))
+    })
+}
+
+// 解析 "U+4E2D,U+6587" 形式的码位列表，不带 U+ 前缀的部分按字面字符处理
+pub fn parse_codepoints(text: &str) -> Result<Vec<u32>> {
+    let mut codepoints = Vec::new();
+    for token in text.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        match token.strip_prefix("U+").or_else(|| token.strip_prefix("u+")) {
+            Some(hex) => codepoints.push(
+                u32::from_str_radix(hex, 16)
+                    .with_context(|| format!("Invalid code point: {}", token))?,
+            ),
+            None => codepoints.extend(token.chars().map(u32::from)),
+        }
+    }
+    Ok(codepoints)
+}
+
+fn cmap_table(data: &[u8]) -> Option<ttf_parser::cmap::Table<'_>> {
+    let face = ttf_parser::RawFace::parse(data, 0).ok()?;
+    ttf_parser::cmap::Table::parse(face.table(ttf_parser::Tag::from_bytes(b"cmap"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_metadata::tests::{cmap_table, sfnt_with_tables};
+
+    #[test]
+    fn reports_range_coverage() {
+        let font = sfnt_with_tables(&[(b"cmap", cmap_table(&[(0x0020, 0x017F), (0x4E2D, 0x4E2D)]))]);
+
+        let coverage = range_coverage(&read_codepoints(&font).expect("cmap"));
+        assert_eq!(coverage[0].covered, coverage[0].total);
+        assert_eq!(coverage[3].covered, 1);
+        assert_eq!(supported_ranges(&font), vec!["Latin"]);
+
+        assert!(supports_all(&font, &parse_codepoints("U+4E2D,A").unwrap()));
+        assert!(!supports_all(&font, &parse_codepoints("文").unwrap()));
+        assert!(parse_codepoints("U+XYZ").is_err());
+        assert!(read_codepoints(b"not a font").is_none());
+    }
+}
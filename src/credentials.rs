@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::http;
+
+// 系统密钥环中的服务名，账户名为服务器 URL
+const SERVICE_NAME: &str = "fontsync";
+
+// 令牌按服务器的 HTTP 地址（协议、主机与端口）保存，
+// login 使用的 http:// 地址与监控使用的 ws://、带路径的地址对应同一条目
+fn normalize_server(server_url: &str) -> String {
+    let trimmed = server_url.trim().trim_end_matches('/');
+    if http::unix_socket_path(trimmed).is_some() {
+        return trimmed.to_string();
+    }
+    let parsed = if trimmed.contains("://") {
+        Url::parse(trimmed)
+    } else {
+        Url::parse(&format!("http://{}", trimmed))
+    };
+    let Some(url) = parsed.ok().filter(|url| url.host_str().is_some()) else {
+        return trimmed.to_string();
+    };
+    let host = url.host_str().unwrap_or_default();
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        scheme => scheme,
+    };
+    // ws 与 http、wss 与 https 的默认端口相同，默认端口不写出
+    match url.port() {
+        Some(port) => format!("{}://{}:{}", scheme, host, port),
+        None => format!("{}://{}", scheme, host),
+    }
+}
+
+// 每次请求都访问系统凭据存储代价较高，按服务器缓存查询结果
+fn token_cache() -> &'static Mutex<HashMap<String, Option<String>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn save_token(server_url: &str, token: &str) -> Result<()> {
+    let server = normalize_server(server_url);
+    store_token(&server, token)?;
+    token_cache().lock().insert(server, Some(token.to_string()));
+    Ok(())
+}
+
+pub fn delete_token(server_url: &str) -> Result<()> {
+    let server = normalize_server(server_url);
+    remove_token(&server)?;
+    token_cache().lock().insert(server, None);
+    Ok(())
+}
+
+// 查询失败（如系统没有密钥环服务）时视为未登录，不影响匿名访问
+pub fn token_for(server_url: &str) -> Option<String> {
+    let server = normalize_server(server_url);
+    if let Some(cached) = token_cache().lock().get(&server) {
+        return cached.clone();
+    }
+
+    // 早期版本按原始地址保存，规范化的条目不存在时再按原始地址查询
+    let raw = server_url.trim_end_matches('/');
+    let found = lookup_token(&server).and_then(|token| match token {
+        None if raw != server => lookup_token(raw),
+        token => Ok(token),
+    });
+    let token = match found {
+        Ok(token) => token,
+        // 缺少密钥环服务或读取失败时照常匿名访问，但提示用户令牌没有生效
+        Err(e) => {
+            warn!("Failed to read credentials for {}, continuing without a token: {:#}", server, e);
+            None
+        }
+    };
+    debug!("Credentials for {}: {}", server, if token.is_some() { "found" } else { "none" });
+    token_cache().lock().insert(server, token.clone());
+    token
+}
+
+// 系统凭据存储中的条目：Linux 为 Secret Service，macOS 为钥匙串，Windows 为凭据管理器
+fn entry(server: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE_NAME, server).context("Failed to open the system credential store")
+}
+
+fn store_token(server: &str, token: &str) -> Result<()> {
+    entry(server)?.set_password(token).context("Failed to store token in the system credential store")
+}
+
+fn lookup_token(server: &str) -> Result<Option<String>> {
+    match entry(server)?.get_password() {
+        Ok(token) => Ok(Some(token).filter(|t| !t.is_empty())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e).context("Failed to read the system credential store"),
+    }
+}
+
+// 条目不存在时视为已删除
+fn remove_token(server: &str) -> Result<()> {
+    match entry(server)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e).context("Failed to remove token from the system credential store"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_and_client_urls_share_one_entry() {
+        let canonical = "http://fonts.local:8080";
+        for url in [
+            "http://fonts.local:8080",
+            "http://fonts.local:8080/",
+            "ws://fonts.local:8080",
+            "ws://FONTS.local:8080/ws",
+            "fonts.local:8080",
+        ] {
+            assert_eq!(normalize_server(url), canonical, "{}", url);
+        }
+        assert_eq!(normalize_server("wss://fonts.example.com/ws"), "https://fonts.example.com");
+        assert_eq!(normalize_server("https://fonts.example.com:443"), "https://fonts.example.com");
+        assert_eq!(normalize_server("http://[::1]:9000"), "http://[::1]:9000");
+        assert_ne!(normalize_server("http://fonts.local:8081"), canonical);
+        assert_eq!(normalize_server("unix:/run/fontsync.sock"), "unix:/run/fontsync.sock");
+    }
+}
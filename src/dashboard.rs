@@ -0,0 +1,11 @@
+use warp::{Filter, Rejection, Reply};
+
+// 管理界面为单个静态页面，只调用服务器现有的 JSON 接口
+const INDEX_HTML: &str = include_str!("dashboard.html");
+
+// path! 自带 end()，末尾带斜杠的 /ui/ 同样匹配
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("ui")
+        .and(warp::get())
+        .map(|| warp::reply::html(INDEX_HTML))
+}
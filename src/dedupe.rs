@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::protected::ProtectedPaths;
+use crate::utils::FontInfo;
+
+// 处理多余副本的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DedupeAction {
+    /// 删除多余副本
+    #[default]
+    Remove,
+    /// 用指向保留副本的符号链接替换多余副本
+    Symlink,
+}
+
+// 内容完全相同的一组文件，keep 为保留的副本
+#[derive(Debug, Clone)]
+pub struct DuplicateSet {
+    pub sha256: String,
+    pub keep: PathBuf,
+    pub redundant: Vec<PathBuf>,
+}
+
+// 同一家族与样式存在内容不同的多个版本
+#[derive(Debug, Clone)]
+pub struct VersionConflict {
+    pub family: String,
+    pub style: String,
+    pub fonts: Vec<(PathBuf, Option<String>)>,
+}
+
+// fonts 按目录优先级排列，靠前的副本被保留；已是同一文件的符号链接不算重复
+pub fn find_duplicates(fonts: &[FontInfo]) -> Vec<DuplicateSet> {
+    let mut by_hash: BTreeMap<&str, Vec<&Path>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for font in fonts {
+        let canonical = fs::canonicalize(&font.path).unwrap_or_else(|_| font.path.clone());
+        if seen.insert(canonical) {
+            by_hash.entry(&font.sha256).or_default().push(&font.path);
+        }
+    }
+
+    by_hash
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(sha256, paths)| DuplicateSet {
+            sha256: sha256.to_string(),
+            keep: paths[0].to_path_buf(),
+            redundant: paths[1..].iter().map(|p| p.to_path_buf()).collect(),
+        })
+        .collect()
+}
+
+pub fn find_version_conflicts(fonts: &[FontInfo]) -> Vec<VersionConflict> {
+    let mut groups: BTreeMap<(String, String), Vec<&FontInfo>> = BTreeMap::new();
+    for font in fonts {
+        let Some(family) = &font.descriptor.family else {
+            continue;
+        };
+        let style = font.descriptor.style.clone().unwrap_or_else(|| "Regular".to_string());
+        groups.entry((family.clone(), style)).or_default().push(font);
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, fonts)| {
+            let versions: HashSet<_> = fonts.iter().map(|f| &f.descriptor.version).collect();
+            let hashes: HashSet<_> = fonts.iter().map(|f| &f.sha256).collect();
+            versions.len() > 1 && hashes.len() > 1
+        })
+        .map(|((family, style), fonts)| VersionConflict {
+            family,
+            style,
+            fonts: fonts
+                .into_iter()
+                .map(|f| (f.path.clone(), f.descriptor.version.clone()))
+                .collect(),
+        })
+        .collect()
+}
+
+pub fn apply(set: &DuplicateSet, action: DedupeAction) -> Result<usize> {
+    // 相对路径的链接目标会相对于链接所在目录解析，需使用绝对路径
+    let target = fs::canonicalize(&set.keep)
+        .with_context(|| format!("Failed to resolve {:?}", set.keep))?;
+    let mut handled = 0;
+    let protected = ProtectedPaths::load();
+    for path in &set.redundant {
+        if let Err(e) = protected.check_removal(path) {
+            warn!("Skipped {}", e);
+            continue;
+        }
+        fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+        if action == DedupeAction::Symlink {
+            symlink(&target, path)
+                .with_context(|| format!("Failed to link {:?} to {:?}", path, target))?;
+        }
+        handled += 1;
+    }
+    Ok(handled)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::scan_font_directory;
+
+    #[tokio::test]
+    async fn duplicates_are_found_and_replaced() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        fs::write(dir.path().join("a.ttf"), b"same font").unwrap();
+        fs::write(dir.path().join("b.ttf"), b"same font").unwrap();
+        fs::write(dir.path().join("c.ttf"), b"other font").unwrap();
+
+        let mut fonts = scan_font_directory(dir.path(), false, &mut HashSet::new()).await.expect("scan");
+        fonts.sort_by(|a, b| a.path.cmp(&b.path));
+        let sets = find_duplicates(&fonts);
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].keep, dir.path().join("a.ttf"));
+        assert_eq!(sets[0].redundant, vec![dir.path().join("b.ttf")]);
+
+        assert_eq!(apply(&sets[0], DedupeAction::Symlink).expect("apply"), 1);
+        assert!(fs::symlink_metadata(dir.path().join("b.ttf")).unwrap().file_type().is_symlink());
+
+        // 符号链接指向同一文件，不再视为重复
+        let fonts = scan_font_directory(dir.path(), false, &mut HashSet::new()).await.expect("rescan");
+        assert!(find_duplicates(&fonts).is_empty());
+        assert!(find_version_conflicts(&fonts).is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// rsync 式增量传输：接收方发送旧版本各块的签名，发送方只传输签名中找不到的数据
+
+const SIGNATURE_MAGIC: &[u8; 4] = b"FSS1";
+const DELTA_MAGIC: &[u8; 4] = b"FSD1";
+const OP_COPY: u8 = 1;
+const OP_DATA: u8 = 2;
+const MIN_BLOCK_SIZE: usize = 1024;
+const MAX_BLOCK_SIZE: usize = 64 * 1024;
+const STRONG_LEN: usize = 16;
+
+// 块大小取文件大小的平方根附近，按 1KB 对齐
+pub fn block_size_for(len: u64) -> usize {
+    let root = (len as f64).sqrt() as usize;
+    (root / MIN_BLOCK_SIZE * MIN_BLOCK_SIZE).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+// 增量数据不足完整文件的七成时才值得使用
+pub fn is_worthwhile(delta_len: usize, full_len: usize) -> bool {
+    delta_len * 10 < full_len * 7
+}
+
+// 旧版本每个完整块的弱校验和与强哈希，末尾不足一块的数据总是按字面传输
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    block_size: usize,
+    blocks: Vec<(u32, [u8; STRONG_LEN])>,
+}
+
+impl Signature {
+    pub fn of(data: &[u8]) -> Self {
+        let block_size = block_size_for(data.len() as u64);
+        let blocks = data
+            .chunks_exact(block_size)
+            .map(|block| (Rolling::new(block).digest(), strong_hash(block)))
+            .collect();
+        Self { block_size, blocks }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.blocks.len() * (4 + STRONG_LEN));
+        out.extend_from_slice(SIGNATURE_MAGIC);
+        out.extend_from_slice(&(self.block_size as u32).to_le_bytes());
+        for (weak, strong) in &self.blocks {
+            out.extend_from_slice(&weak.to_le_bytes());
+            out.extend_from_slice(strong);
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader(data);
+        if reader.take(4)? != SIGNATURE_MAGIC {
+            bail!("Not a delta signature");
+        }
+        let block_size = reader.u32()? as usize;
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+            bail!("Invalid block size {}", block_size);
+        }
+        let mut blocks = Vec::new();
+        while !reader.0.is_empty() {
+            let weak = reader.u32()?;
+            let strong = reader.take(STRONG_LEN)?.try_into()?;
+            blocks.push((weak, strong));
+        }
+        Ok(Self { block_size, blocks })
+    }
+
+    // 生成把旧版本变为 data 的增量
+    pub fn diff(&self, data: &[u8]) -> Vec<u8> {
+        let size = self.block_size;
+        let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, (weak, _)) in self.blocks.iter().enumerate() {
+            index.entry(*weak).or_default().push(i);
+        }
+
+        let mut delta = DeltaWriter::new(size);
+        let mut pos = 0;
+        let mut rolling = (data.len() >= size).then(|| Rolling::new(&data[..size]));
+        while let Some(window) = rolling.as_mut() {
+            let matched = index.get(&window.digest()).and_then(|candidates| {
+                let strong = strong_hash(&data[pos..pos + size]);
+                candidates.iter().copied().find(|&i| self.blocks[i].1 == strong)
+            });
+            if let Some(block) = matched {
+                delta.copy(block);
+                pos += size;
+                rolling = (data.len() >= pos + size).then(|| Rolling::new(&data[pos..pos + size]));
+            } else {
+                delta.literal(data[pos]);
+                if pos + size < data.len() {
+                    window.roll(data[pos], data[pos + size]);
+                    pos += 1;
+                } else {
+                    pos += 1;
+                    rolling = None;
+                }
+            }
+        }
+        for &byte in &data[pos..] {
+            delta.literal(byte);
+        }
+        delta.finish()
+    }
+}
+
+// 将增量应用到旧版本
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader(delta);
+    if reader.take(4)? != DELTA_MAGIC {
+        bail!("Not a delta");
+    }
+    let block_size = reader.u32()? as usize;
+    let mut out = Vec::new();
+    while !reader.0.is_empty() {
+        match reader.take(1)?[0] {
+            OP_COPY => {
+                let start = reader.u32()? as usize * block_size;
+                let end = start + reader.u32()? as usize * block_size;
+                let range = base.get(start..end).context("Delta refers to data outside the base file")?;
+                out.extend_from_slice(range);
+            }
+            OP_DATA => {
+                let len = reader.u32()? as usize;
+                out.extend_from_slice(reader.take(len)?);
+            }
+            op => bail!("Unknown delta operation {}", op),
+        }
+    }
+    Ok(out)
+}
+
+fn strong_hash(block: &[u8]) -> [u8; STRONG_LEN] {
+    let digest = Sha256::digest(block);
+    let mut strong = [0u8; STRONG_LEN];
+    strong.copy_from_slice(&digest[..STRONG_LEN]);
+    strong
+}
+
+// Adler-32 式滚动校验和，窗口滑动一个字节时 O(1) 更新
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let len = window.len() as u32;
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, &byte) in window.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        Self { a: a & 0xffff, b: b & 0xffff, len }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32) & 0xffff;
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out as u32)).wrapping_add(self.a) & 0xffff;
+    }
+
+    fn digest(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+// 连续的块引用合并为一条，字面数据攒够后再写出
+struct DeltaWriter {
+    out: Vec<u8>,
+    copy: Option<(usize, usize)>,
+    literal: Vec<u8>,
+}
+
+impl DeltaWriter {
+    fn new(block_size: usize) -> Self {
+        let mut out = DELTA_MAGIC.to_vec();
+        out.extend_from_slice(&(block_size as u32).to_le_bytes());
+        Self { out, copy: None, literal: Vec::new() }
+    }
+
+    fn copy(&mut self, block: usize) {
+        self.flush_literal();
+        match &mut self.copy {
+            Some((start, count)) if *start + *count == block => *count += 1,
+            _ => {
+                self.flush_copy();
+                self.copy = Some((block, 1));
+            }
+        }
+    }
+
+    fn literal(&mut self, byte: u8) {
+        self.flush_copy();
+        self.literal.push(byte);
+    }
+
+    fn flush_copy(&mut self) {
+        if let Some((start, count)) = self.copy.take() {
+            self.out.push(OP_COPY);
+            self.out.extend_from_slice(&(start as u32).to_le_bytes());
+            self.out.extend_from_slice(&(count as u32).to_le_bytes());
+        }
+    }
+
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            self.out.push(OP_DATA);
+            self.out.extend_from_slice(&(self.literal.len() as u32).to_le_bytes());
+            self.out.append(&mut self.literal);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.flush_copy();
+        self.flush_literal();
+        self.out
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("Truncated delta data");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_edits_produce_small_deltas() {
+        let base = pseudo_random(200_000, 1);
+        let mut edited = base.clone();
+        // 中间改动几个字节，并在开头插入数据使后续块错位
+        edited[100_000..100_010].copy_from_slice(b"new tables");
+        edited.splice(0..0, b"inserted header".iter().copied());
+
+        let signature = Signature::decode(&Signature::of(&base).encode()).expect("decode");
+        let delta = signature.diff(&edited);
+        assert!(is_worthwhile(delta.len(), edited.len()));
+        assert!(delta.len() < 5 * block_size_for(base.len() as u64));
+        assert_eq!(apply(&base, &delta).expect("apply"), edited);
+
+        // 完全不同的内容不值得增量传输，但结果仍然正确
+        let other = pseudo_random(50_000, 2);
+        let delta = signature.diff(&other);
+        assert!(!is_worthwhile(delta.len(), other.len()));
+        assert_eq!(apply(&base, &delta).expect("apply"), other);
+
+        // 短文件与空文件
+        assert_eq!(apply(b"", &Signature::of(b"").diff(b"tiny")).unwrap(), b"tiny");
+        assert!(apply(&base, b"FSD1").is_err());
+    }
+}
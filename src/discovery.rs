@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+#[cfg(any(feature = "gui", test))]
+use std::net::SocketAddr;
+#[cfg(any(feature = "gui", test))]
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+
+use crate::identity::ClientIdentity;
+
+// 局域网发现使用的 UDP 端口，客户端向该端口广播探测包
+pub const DISCOVERY_PORT: u16 = 47800;
+const PROBE: &[u8] = b"FONTSYNC_DISCOVER";
+// 应答只含名称、端口与版本，超出的数据报忽略
+const MAX_DATAGRAM: usize = 1024;
+
+// 服务器对探测包的应答
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Announcement {
+    name: String,
+    port: u16,
+    version: String,
+}
+
+// 在局域网中找到的服务器
+#[cfg(any(feature = "gui", test))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    pub name: String,
+    pub url: String,
+    pub version: String,
+}
+
+#[cfg(any(feature = "gui", test))]
+impl std::fmt::Display for DiscoveredServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, v{})", self.name, self.url, self.version)
+    }
+}
+
+// 在后台应答探测包，直到 shutdown 被取消；端口被占用时只记录警告
+pub fn spawn_responder(http_port: u16, shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("LAN discovery disabled, cannot bind UDP port {}: {}", DISCOVERY_PORT, e);
+                return;
+            }
+        };
+        info!("Answering LAN discovery on UDP port {}", DISCOVERY_PORT);
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            result = respond(&socket, http_port) => {
+                if let Err(e) = result {
+                    warn!("LAN discovery stopped: {:#}", e);
+                }
+            }
+        }
+    });
+}
+
+async fn respond(socket: &UdpSocket, http_port: u16) -> Result<()> {
+    let reply = serde_json::to_vec(&Announcement {
+        name: ClientIdentity::current().hostname.clone(),
+        port: http_port,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })?;
+    let mut buf = [0u8; MAX_DATAGRAM];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await.context("Failed to receive discovery probe")?;
+        if &buf[..len] != PROBE {
+            continue;
+        }
+        debug!("Answering discovery probe from {}", from);
+        if let Err(e) = socket.send_to(&reply, from).await {
+            debug!("Failed to answer discovery probe from {}: {}", from, e);
+        }
+    }
+}
+
+// 向局域网广播探测包，收集 timeout 内应答的服务器
+#[cfg(feature = "gui")]
+pub async fn discover(timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    discover_at(SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)), timeout).await
+}
+
+#[cfg(any(feature = "gui", test))]
+async fn discover_at(target: SocketAddr, timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.context("Failed to bind discovery socket")?;
+    socket.set_broadcast(true).context("Failed to enable broadcast")?;
+    socket.send_to(PROBE, target).await.context("Failed to send discovery probe")?;
+
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buf = [0u8; MAX_DATAGRAM];
+    let deadline = tokio::time::Instant::now() + timeout;
+    // 同一台服务器可能从多个地址应答，按 URL 去重
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = received.context("Failed to receive discovery reply")?;
+        let Ok(announcement) = serde_json::from_slice::<Announcement>(&buf[..len]) else {
+            debug!("Ignoring malformed discovery reply from {}", from);
+            continue;
+        };
+        let url = format!("http://{}", SocketAddr::new(from.ip(), announcement.port));
+        if servers.iter().all(|server| server.url != url) {
+            servers.push(DiscoveredServer { name: announcement.name, url, version: announcement.version });
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn probes_are_answered_with_the_http_port() {
+        let responder = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let target = responder.local_addr().unwrap();
+        tokio::spawn(async move { respond(&responder, 8123).await });
+
+        let servers = discover_at(target, Duration::from_millis(500)).await.unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].url, "http://127.0.0.1:8123");
+        assert_eq!(servers[0].version, env!("CARGO_PKG_VERSION"));
+    }
+}
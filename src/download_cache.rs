@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::client_state::ClientState;
+use crate::utils::{calculate_sha256, hard_link_count, is_font_file};
+
+// 实时同步下载的字体先保存在这里再安装到系统字体目录
+pub fn download_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fontsync/downloads")
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStatus {
+    pub files: usize,
+    pub size: u64,
+    // 与已安装字体以硬链接共用数据的副本，不额外占用空间
+    pub linked: usize,
+    // 最早下载的副本的时间（Unix 秒）
+    pub oldest: Option<u64>,
+    // 记录了内容哈希的字体，包括副本已清理的
+    pub recorded: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CleanSummary {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    downloaded_at: u64,
+    linked: bool,
+}
+
+// 下载时间取自下载记录；文件的修改时间已设为服务器上的时间，只在没有记录时使用
+fn cached_files(dir: &Path, state: &ClientState) -> Vec<CachedFile> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<CachedFile> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_font_file(path))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let name = path.file_name()?.to_str()?;
+            let downloaded_at = state.downloads.get(name).map(|record| record.downloaded_at).unwrap_or_else(|| {
+                metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs())
+            });
+            let linked = hard_link_count(&path) > 1;
+            Some(CachedFile { size: metadata.len(), downloaded_at, linked, path })
+        })
+        .collect();
+    files.sort_by_key(|file| file.downloaded_at);
+    files
+}
+
+pub fn status(dir: &Path, state: &ClientState) -> CacheStatus {
+    let files = cached_files(dir, state);
+    CacheStatus {
+        files: files.len(),
+        size: files.iter().map(|file| file.size).sum(),
+        linked: files.iter().filter(|file| file.linked).count(),
+        oldest: files.first().map(|file| file.downloaded_at),
+        recorded: state.downloads.len(),
+    }
+}
+
+// 删除早于 older_than 的副本，之后仍超过 max_size 时从最早的开始删除；两者都未设置时删除全部副本。
+// 固定版本的字体保留副本，内容哈希记录都保留。与已安装字体硬链接的副本不计入大小，删除也不释放空间
+pub fn clean(
+    dir: &Path,
+    state: &ClientState,
+    older_than: Option<Duration>,
+    max_size: Option<u64>,
+    now: u64,
+) -> Result<CleanSummary> {
+    let files = cached_files(dir, state);
+    let mut remaining: u64 = files.iter().filter(|file| !file.linked).map(|file| file.size).sum();
+    let cutoff = older_than.map(|age| now.saturating_sub(age.as_secs()));
+
+    let mut summary = CleanSummary::default();
+    for file in files {
+        if file.path.file_name().and_then(|n| n.to_str()).is_some_and(|name| state.pins.contains_key(name)) {
+            continue;
+        }
+        let expired = cutoff.is_some_and(|cutoff| file.downloaded_at <= cutoff);
+        let over_size = !file.linked && max_size.is_some_and(|max| remaining > max);
+        if !(expired || over_size || (older_than.is_none() && max_size.is_none())) {
+            continue;
+        }
+        fs::remove_file(&file.path).with_context(|| format!("Failed to remove {:?}", file.path))?;
+        summary.removed += 1;
+        if !file.linked {
+            remaining -= file.size;
+            summary.freed += file.size;
+        }
+    }
+    Ok(summary)
+}
+
+// 安装成功后删除下载副本，只保留内容哈希供之后校验删除通知；固定版本的字体
+// 以及与安装的字体硬链接、不额外占用空间的副本保留，之后更新时可以只下载增量
+pub fn release_installed(path: &Path) -> Result<()> {
+    let Some(filename) = path.file_name().and_then(|n| n.to_str()) else { return Ok(()) };
+    let sha256 = calculate_sha256(path)?;
+    ClientState::record_download(filename, &sha256)?;
+    if ClientState::load().pins.contains_key(filename) || hard_link_count(path) > 1 {
+        return Ok(());
+    }
+    fs::remove_file(path).with_context(|| format!("Failed to remove installed copy {:?}", path))?;
+    info!("Removed installed copy of {} from the download cache", filename);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_state::{DownloadRecord, FontPin};
+
+    #[test]
+    fn cleaning_removes_old_copies_then_the_oldest_until_under_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = ClientState::default();
+        for (name, size, downloaded_at) in [("a.ttf", 10, 100), ("b.ttf", 10, 200), ("c.ttf", 10, 300), ("d.ttf", 10, 400)] {
+            fs::write(dir.path().join(name), vec![0u8; size]).unwrap();
+            let record = DownloadRecord { sha256: name.to_string(), downloaded_at };
+            state.downloads.insert(name.to_string(), record);
+        }
+        state.pins.insert("b.ttf".to_string(), FontPin { sha256: None, pinned_at: 1 });
+        assert_eq!(status(dir.path(), &state), CacheStatus { files: 4, size: 40, linked: 0, oldest: Some(100), recorded: 4 });
+
+        // a.ttf 已过期；固定的 b.ttf 保留但计入大小；之后删除剩余中最早的 c.ttf 即不超过 25 字节
+        let summary = clean(dir.path(), &state, Some(Duration::from_secs(250)), Some(25), 400).unwrap();
+        assert_eq!(summary, CleanSummary { removed: 2, freed: 20 });
+        let left: Vec<_> = cached_files(dir.path(), &state).into_iter().map(|f| f.path).collect();
+        assert_eq!(left, [dir.path().join("b.ttf"), dir.path().join("d.ttf")]);
+
+        let summary = clean(dir.path(), &state, None, None, 400).unwrap();
+        assert_eq!(summary, CleanSummary { removed: 1, freed: 10 });
+        assert_eq!(status(dir.path(), &state).files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_linked_to_installed_fonts_do_not_count_towards_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let installed = tempfile::tempdir().unwrap();
+        let state = ClientState::default();
+        fs::write(dir.path().join("linked.ttf"), vec![0u8; 10]).unwrap();
+        fs::write(dir.path().join("copied.ttf"), vec![1u8; 10]).unwrap();
+        let target = installed.path().join("linked.ttf");
+        assert!(crate::utils::link_or_copy_atomic(&dir.path().join("linked.ttf"), &target).unwrap());
+        // 再次安装同一文件时链接保持不变
+        assert!(crate::utils::link_or_copy_atomic(&dir.path().join("linked.ttf"), &target).unwrap());
+        assert_eq!(status(dir.path(), &state).linked, 1);
+
+        let summary = clean(dir.path(), &state, None, Some(0), 400).unwrap();
+        assert_eq!(summary, CleanSummary { removed: 1, freed: 10 });
+        assert!(dir.path().join("linked.ttf").exists());
+        assert!(target.exists());
+    }
+}
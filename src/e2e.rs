@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::fs;
+use std::path::Path;
+
+// 加密文件格式：魔数 + 12 字节随机 nonce + 密文 + 16 字节认证标签
+const MAGIC: &[u8; 4] = b"FSE1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// 团队共享密钥，服务器端不持有
+#[derive(Clone)]
+pub struct TeamKey([u8; KEY_LEN]);
+
+impl std::fmt::Debug for TeamKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TeamKey(..)")
+    }
+}
+
+impl TeamKey {
+    pub fn generate() -> Result<Self> {
+        let mut key = [0u8; KEY_LEN];
+        rand_bytes(&mut key).context("Failed to generate random key")?;
+        Ok(Self(key))
+    }
+
+    // 密钥文件内容为 base64 文本
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read team key {:?}", path))?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(content.trim())
+            .context("Team key is not valid base64")?;
+        let key: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Team key must be {} bytes", KEY_LEN))?;
+        Ok(Self(key))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(anyhow::anyhow!("Refusing to overwrite existing key {:?}", path));
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(self.0);
+        fs::write(path, format!("{}\n", encoded))
+            .with_context(|| format!("Failed to write team key {:?}", path))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict team key permissions")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand_bytes(&mut nonce).context("Failed to generate nonce")?;
+
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::chacha20_poly1305(),
+            &self.0,
+            Some(&nonce),
+            MAGIC,
+            plaintext,
+            &mut tag,
+        )
+        .context("Failed to encrypt font")?;
+
+        let mut output = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len() + TAG_LEN);
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        output.extend_from_slice(&tag);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if !is_encrypted(data) || data.len() < MAGIC.len() + NONCE_LEN + TAG_LEN {
+            return Err(anyhow::anyhow!("Data is not an encrypted font"));
+        }
+
+        let nonce = &data[MAGIC.len()..MAGIC.len() + NONCE_LEN];
+        let (ciphertext, tag) = data[MAGIC.len() + NONCE_LEN..].split_at(data.len() - MAGIC.len() - NONCE_LEN - TAG_LEN);
+
+        decrypt_aead(Cipher::chacha20_poly1305(), &self.0, Some(nonce), MAGIC, ciphertext, tag)
+            .context("Failed to decrypt font (wrong team key?)")
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_round_trip_and_wrong_key() {
+        let key = TeamKey::generate().expect("key");
+        let plaintext = b"font bytes";
+
+        let encrypted = key.encrypt(plaintext).expect("encrypt");
+        assert!(is_encrypted(&encrypted));
+        assert_ne!(&encrypted[MAGIC.len() + NONCE_LEN..][..plaintext.len()], plaintext);
+        assert_eq!(key.decrypt(&encrypted).expect("decrypt"), plaintext);
+
+        let other = TeamKey::generate().expect("key");
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn team_key_file_round_trip() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("team.key");
+
+        let key = TeamKey::generate().expect("key");
+        key.save(&path).expect("save");
+        assert!(key.save(&path).is_err());
+
+        let loaded = TeamKey::load(&path).expect("load");
+        let encrypted = key.encrypt(b"abc").expect("encrypt");
+        assert_eq!(loaded.decrypt(&encrypted).expect("decrypt"), b"abc");
+    }
+}
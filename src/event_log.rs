@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+
+use crate::api::Attribution;
+use crate::websocket_server::WebSocketMessage;
+
+// 事件日志保存在字体目录下的隐藏目录中，避免被当作字体列出
+const EVENT_LOG_DIR: &str = ".fontsync";
+const EVENT_LOG_FILE: &str = "events.jsonl";
+// 订阅者落后超过该数量时需从日志补齐
+const SUBSCRIBER_CAPACITY: usize = 256;
+// 日志只保留最近的这么多事件，更早的事件在追加时清理
+const RETAINED_EVENTS: usize = 10_000;
+// /events 未指定 limit 时每页返回的事件数
+pub const DEFAULT_PAGE_SIZE: usize = 1_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub event: WebSocketMessage,
+    // 引起该事件的上传者，删除与恢复等未识别来源的操作为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<Attribution>,
+    // 产生该事件的 HTTP 请求，与响应头及访问日志中的 X-Request-Id 相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventPage {
+    pub events: Vec<EventRecord>,
+    pub latest_seq: u64,
+    // since 之后的部分事件已被清理，无法按日志补齐，客户端需完整同步
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+pub struct EventLog {
+    path: PathBuf,
+    records: RwLock<Vec<EventRecord>>,
+    notify: broadcast::Sender<EventRecord>,
+    retained: usize,
+}
+
+impl EventLog {
+    // 从字体目录加载事件日志，文件不存在时创建空日志
+    pub fn open(font_dir: &Path) -> Result<Self> {
+        Self::open_with_retention(font_dir, RETAINED_EVENTS)
+    }
+
+    fn open_with_retention(font_dir: &Path, retained: usize) -> Result<Self> {
+        let dir = font_dir.join(EVENT_LOG_DIR);
+        fs::create_dir_all(&dir).context("Failed to create event log directory")?;
+        let path = dir.join(EVENT_LOG_FILE);
+
+        let mut records = Vec::new();
+        if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read event log")?;
+            for (line_no, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<EventRecord>(line) {
+                    Ok(record) => records.push(record),
+                    Err(e) => warn!("Skipping corrupt event log line {}: {}", line_no + 1, e),
+                }
+            }
+        }
+
+        let log = Self {
+            path,
+            records: RwLock::new(records),
+            notify: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            retained,
+        };
+        let mut records = log.records.write();
+        if records.len() > retained {
+            log.compact(&mut records)?;
+        }
+        drop(records);
+        Ok(log)
+    }
+
+    // 只保留最近 retained 个事件并重写日志文件
+    fn compact(&self, records: &mut Vec<EventRecord>) -> Result<()> {
+        let excess = records.len().saturating_sub(self.retained);
+        records.drain(..excess);
+        let mut content = String::new();
+        for record in records.iter() {
+            content.push_str(&serde_json::to_string(record).context("Failed to serialize event record")?);
+            content.push('\n');
+        }
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&tmp_path, content).context("Failed to write compacted event log")?;
+        fs::rename(&tmp_path, &self.path).context("Failed to replace event log")?;
+        debug!("Compacted event log to {} events", records.len());
+        Ok(())
+    }
+
+    // 追加事件并分配单调递增的序号
+    pub fn append(&self, event: WebSocketMessage, actor: Option<Attribution>, request_id: Option<String>) -> Result<EventRecord> {
+        let mut records = self.records.write();
+        let seq = records.last().map(|r| r.seq + 1).unwrap_or(1);
+        let record = EventRecord {
+            seq,
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            event: event.with_seq(seq),
+            actor,
+            request_id,
+        };
+
+        let line = serde_json::to_string(&record).context("Failed to serialize event record")?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open event log")?;
+        writeln!(file, "{}", line).context("Failed to write event log")?;
+        file.sync_data().context("Failed to flush event log")?;
+
+        records.push(record.clone());
+        // 超出保留数量的一成后才清理，避免每次追加都重写文件
+        if records.len() > self.retained + self.retained / 10
+            && let Err(e) = self.compact(&mut records)
+        {
+            warn!("Failed to compact event log: {:#}", e);
+        }
+        // 没有订阅者时发送失败，可忽略
+        let _ = self.notify.send(record.clone());
+        Ok(record)
+    }
+
+    // 订阅之后追加的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.notify.subscribe()
+    }
+
+    // 最近的 limit 个事件，按时间顺序
+    pub fn recent(&self, limit: usize) -> Vec<EventRecord> {
+        let records = self.records.read();
+        records[records.len().saturating_sub(limit)..].to_vec()
+    }
+
+    // 按客户端 ID（无 ID 时为令牌名称）统计上传次数，未记录来源的旧事件不计入
+    pub fn upload_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for record in self.records.read().iter() {
+            let is_upload = matches!(record.event, WebSocketMessage::FontAdded { .. } | WebSocketMessage::FontModified { .. });
+            if let (true, Some(actor)) = (is_upload, &record.actor) {
+                let client = actor.client_id.clone().or_else(|| actor.token.clone());
+                *counts.entry(client.unwrap_or_else(|| "anonymous".to_string())).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    // 返回序号大于 since 的事件，limit 为 None 时不限制数量；
+    // 保留的最早事件之前还有 since 之后的事件时标记为 truncated
+    pub fn since(&self, since: u64, limit: Option<usize>) -> EventPage {
+        let records = self.records.read();
+        // 记录按序号递增排列
+        let start = records.partition_point(|r| r.seq <= since);
+        let events = records[start..]
+            .iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+
+        EventPage {
+            events,
+            latest_seq: records.last().map(|r| r.seq).unwrap_or(0),
+            truncated: records.first().is_some_and(|first| first.seq > since.saturating_add(1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removed(name: &str) -> WebSocketMessage {
+        WebSocketMessage::FontRemoved { filename: name.to_string(), sha256: None, seq: None }
+    }
+
+    #[test]
+    fn old_events_are_compacted_and_reported_as_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = EventLog::open_with_retention(dir.path(), 10).unwrap();
+        for i in 0..11 {
+            log.append(removed(&format!("{}.ttf", i)), None, None).unwrap();
+        }
+        // 超出一成之后才清理
+        assert_eq!(log.since(0, None).events.len(), 11);
+        assert!(!log.since(0, None).truncated);
+        log.append(removed("11.ttf"), None, None).unwrap();
+
+        let page = log.since(0, None);
+        assert_eq!((page.events[0].seq, page.latest_seq), (3, 12));
+        assert!(page.truncated);
+        assert!(log.since(1, None).truncated);
+        assert!(!log.since(2, None).truncated);
+        assert_eq!(log.since(5, Some(2)).events.iter().map(|r| r.seq).collect::<Vec<_>>(), [6, 7]);
+
+        // 清理后的日志文件重新加载时从保留的第一个事件开始
+        let reopened = EventLog::open_with_retention(dir.path(), 10).unwrap();
+        assert_eq!(reopened.since(0, None).events.len(), 10);
+        assert_eq!(reopened.append(removed("next.ttf"), None, None).unwrap().seq, 13);
+        let reopened = EventLog::open_with_retention(dir.path(), 5).unwrap();
+        assert_eq!(reopened.since(0, None).events[0].seq, 9);
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use log::warn;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client_state::ClientState;
+use crate::font_monitor::FontInfo;
+
+const CACHE_FILE: &str = "font_cache.json";
+// 内存中最多保留的条目数，其余只在磁盘上
+pub const DEFAULT_CAPACITY: usize = 4096;
+// 累积这么多未保存的变更后写入磁盘
+const FLUSH_THRESHOLD: usize = 256;
+
+// 磁盘上的条目只保存判断文件是否变化所需的信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedFont {
+    sha256: String,
+    size: u64,
+    modified_nanos: u64,
+}
+
+impl CachedFont {
+    fn of(info: &FontInfo) -> Self {
+        Self { sha256: info.sha256.clone(), size: info.size, modified_nanos: nanos(info.modified) }
+    }
+
+    fn info(&self, path: &Path) -> FontInfo {
+        FontInfo {
+            path: path.to_path_buf(),
+            sha256: self.sha256.clone(),
+            size: self.size,
+            modified: UNIX_EPOCH + Duration::from_nanos(self.modified_nanos),
+        }
+    }
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    // 内存中的条目数与上限
+    pub entries: usize,
+    pub capacity: usize,
+    // 磁盘上的条目数，即已知的全部字体文件
+    pub persisted: usize,
+    pub hits: u64,
+    // 内存未命中、需要读取磁盘的查询
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+// 监控器的字体缓存：内存中按路径保留最近使用的条目，全部条目保存在客户端状态目录中，
+// 重启后大小与修改时间未变的文件不必重新计算哈希
+pub struct FontCache {
+    memory: LruCache<PathBuf, FontInfo>,
+    path: Option<PathBuf>,
+    // 尚未写入磁盘的变更，None 表示删除
+    pending: HashMap<PathBuf, Option<CachedFont>>,
+    persisted: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl FontCache {
+    // 持久化到客户端状态目录
+    pub fn open(capacity: usize) -> Self {
+        Self::with_store(Some(ClientState::state_dir().join(CACHE_FILE)), capacity)
+    }
+
+    // path 为空时只在内存中缓存，超出容量的条目直接丢弃
+    pub fn with_store(path: Option<PathBuf>, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        let persisted = path.as_deref().map(|p| load_store(p).len()).unwrap_or(0);
+        Self {
+            memory: LruCache::new(capacity),
+            path,
+            pending: HashMap::new(),
+            persisted,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, path: &Path) -> Option<FontInfo> {
+        if let Some(info) = self.memory.get(path) {
+            self.hits += 1;
+            return Some(info.clone());
+        }
+        self.misses += 1;
+        let cached = match self.pending.get(path) {
+            Some(pending) => pending.clone(),
+            None => self.path.as_deref().and_then(|store| load_store(store).remove(path)),
+        }?;
+        let info = cached.info(path);
+        self.remember(info.clone());
+        Some(info)
+    }
+
+    pub fn contains(&mut self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    // 返回同一路径原有的条目
+    pub fn insert(&mut self, info: FontInfo) -> Option<FontInfo> {
+        let previous = self.get(&info.path);
+        if previous.as_ref() != Some(&info) {
+            self.pending.insert(info.path.clone(), Some(CachedFont::of(&info)));
+            self.flush_if_needed();
+        }
+        self.remember(info);
+        previous
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<FontInfo> {
+        let previous = self.get(path);
+        self.memory.pop(path);
+        if previous.is_some() {
+            self.pending.insert(path.to_path_buf(), None);
+            self.flush_if_needed();
+        }
+        previous
+    }
+
+    // 内存中内容为 sha256 的条目，用于发现经由其他路径到达的同一文件
+    pub fn recent_with_sha256<'a>(&'a self, sha256: &'a str) -> impl Iterator<Item = &'a FontInfo> + 'a {
+        self.memory.iter().map(|(_, info)| info).filter(move |info| info.sha256 == sha256)
+    }
+
+    // 完整扫描的结果替换全部条目，磁盘上不再存在的文件随之清除
+    pub fn replace_all(&mut self, fonts: &[FontInfo]) -> Result<()> {
+        self.memory.clear();
+        self.pending.clear();
+        for info in fonts {
+            self.remember(info.clone());
+        }
+        let store: BTreeMap<&Path, CachedFont> =
+            fonts.iter().map(|info| (info.path.as_path(), CachedFont::of(info))).collect();
+        self.persisted = store.len();
+        self.write_store(&store)
+    }
+
+    // 扫描时大小与修改时间与上次一致的文件直接使用保存的哈希
+    pub fn fresh_hashes(&self) -> impl Fn(&Path, u64, SystemTime) -> Option<String> + use<> {
+        let store = self.snapshot();
+        move |path, size, modified| {
+            store
+                .get(path)
+                .filter(|cached| cached.size == size && cached.modified_nanos == nanos(modified))
+                .map(|cached| cached.sha256.clone())
+        }
+    }
+
+    // 全部已知条目，包括只在磁盘上的；启动时即上次运行结束时的状态
+    pub fn known(&self) -> Vec<FontInfo> {
+        self.snapshot().iter().map(|(path, cached)| cached.info(path)).collect()
+    }
+
+    fn snapshot(&self) -> BTreeMap<PathBuf, CachedFont> {
+        let mut store = self.path.as_deref().map(load_store).unwrap_or_default();
+        for (path, pending) in &self.pending {
+            match pending {
+                Some(cached) => store.insert(path.clone(), cached.clone()),
+                None => store.remove(path),
+            };
+        }
+        store
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let Some(path) = self.path.clone() else {
+            self.pending.clear();
+            return Ok(());
+        };
+        let mut store = load_store(&path);
+        for (file, pending) in self.pending.drain() {
+            match pending {
+                Some(cached) => store.insert(file, cached),
+                None => store.remove(&file),
+            };
+        }
+        self.persisted = store.len();
+        self.write_store(&store.iter().map(|(file, cached)| (file.as_path(), cached.clone())).collect())
+    }
+
+    // 缩小容量时淘汰最久未用的条目，它们仍保存在磁盘上
+    pub fn resize(&mut self, capacity: usize) {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        let evicted = self.memory.len().saturating_sub(capacity.get());
+        self.memory.resize(capacity);
+        self.evictions += evicted as u64;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.memory.len(),
+            capacity: self.memory.cap().get(),
+            persisted: self.persisted,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn remember(&mut self, info: FontInfo) {
+        let path = info.path.clone();
+        // 同一路径的旧值被替换时不算淘汰
+        if self.memory.push(path.clone(), info).is_some_and(|(evicted, _)| evicted != path) {
+            self.evictions += 1;
+        }
+    }
+
+    fn flush_if_needed(&mut self) {
+        if self.pending.len() >= FLUSH_THRESHOLD
+            && let Err(e) = self.flush()
+        {
+            warn!("Failed to save font cache: {}", e);
+        }
+    }
+
+    fn write_store(&self, store: &BTreeMap<&Path, CachedFont>) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create state directory")?;
+        }
+        // 非 UTF-8 路径无法作为 JSON 键，下次启动时重新计算
+        let store: BTreeMap<&str, &CachedFont> =
+            store.iter().filter_map(|(file, cached)| Some((file.to_str()?, cached))).collect();
+        let content = serde_json::to_string(&store).context("Failed to serialize font cache")?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write font cache")?;
+        fs::rename(&tmp_path, path).context("Failed to replace font cache")?;
+        Ok(())
+    }
+}
+
+// 不存在或损坏时视为空缓存
+fn load_store(path: &Path) -> BTreeMap<PathBuf, CachedFont> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(path: &str, sha256: &str) -> FontInfo {
+        FontInfo { path: PathBuf::from(path), sha256: sha256.to_string(), size: 4, modified: UNIX_EPOCH + Duration::from_secs(1) }
+    }
+
+    #[test]
+    fn evicted_entries_are_read_back_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join(CACHE_FILE);
+        let mut cache = FontCache::with_store(Some(store.clone()), 2);
+        cache.replace_all(&[font("/fonts/a.ttf", "a"), font("/fonts/b.ttf", "b")]).unwrap();
+        assert_eq!(cache.insert(font("/fonts/c.ttf", "c")), None);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.stats().evictions, 1);
+
+        // 被淘汰的 a.ttf 仍然已知
+        assert_eq!(cache.get(Path::new("/fonts/a.ttf")).map(|f| f.sha256), Some("a".to_string()));
+        assert_eq!(cache.remove(Path::new("/fonts/b.ttf")).map(|f| f.sha256), Some("b".to_string()));
+        cache.flush().unwrap();
+
+        let reopened = FontCache::with_store(Some(store), 2);
+        assert_eq!(reopened.stats().persisted, 2);
+        let fresh = reopened.fresh_hashes();
+        let modified = UNIX_EPOCH + Duration::from_secs(1);
+        assert_eq!(fresh(Path::new("/fonts/c.ttf"), 4, modified), Some("c".to_string()));
+        assert_eq!(fresh(Path::new("/fonts/c.ttf"), 5, modified), None);
+        assert_eq!(fresh(Path::new("/fonts/b.ttf"), 4, modified), None);
+    }
+}
@@ -1,83 +1,279 @@
 use anyhow::{Context, Result};
-use log::{error, info};
-use std::path::Path;
+use futures::StreamExt;
+use log::{error, info, warn};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::process::Command;
+use std::sync::Arc;
 
-pub async fn install_font(font_path: &Path) -> Result<()> {
-    #[cfg(target_os = "windows")]
-    return install_font_windows(font_path).await;
-    
-    #[cfg(target_os = "linux")]
-    return install_font_linux(font_path).await;
-    
-    #[cfg(target_os = "macos")]
-    return install_font_macos(font_path).await;
-    
-    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-    return Err(anyhow::anyhow!("Font installation not supported on this OS"));
+use crate::api::InstalledFont;
+use crate::client_state::ClientState;
+use crate::font_metadata;
+use crate::progress::{Operation, Progress};
+use crate::protected::{self, ProtectedPaths};
+use crate::sync_report::FileAction;
+
+// 同时复制的字体数，系统字体缓存在整批完成后只刷新一次
+const MAX_CONCURRENT_INSTALLS: usize = 8;
+
+// 安装范围。auto 在 Windows 上有管理员权限时安装到系统字体目录，否则只为当前用户安装；
+// 其他系统上 auto 为当前用户安装，system 需要 root 权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum InstallScope {
+    #[default]
+    Auto,
+    User,
+    System,
 }
 
-pub async fn install_fonts_from_directory(dir_path: &Path) -> Result<(usize, usize)> {
-    let mut installed = 0;
-    let mut failed = 0;
-    
+impl InstallScope {
+    // 把 auto 换成实际使用的范围
+    pub fn resolve(self) -> InstallScope {
+        match self {
+            #[cfg(target_os = "windows")]
+            InstallScope::Auto if is_elevated() => InstallScope::System,
+            InstallScope::Auto => InstallScope::User,
+            scope => scope,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstallOptions {
+    pub scope: InstallScope,
+    // 按 name 表把安装后的文件命名为 "<Family>-<Style>.<ext>"
+    pub normalize_names: bool,
+    // 以硬链接代替复制，用于从下载缓存安装；不在同一文件系统时仍复制
+    pub link: bool,
+}
+
+// 单个字体的安装结果，成功时带有字体的家族与样式
+#[derive(Debug)]
+pub struct InstallResult {
+    pub path: PathBuf,
+    pub result: Result<InstalledFont>,
+}
+
+impl InstallResult {
+    pub fn file_name(&self) -> String {
+        self.path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+    }
+
+    pub fn is_installed(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    pub fn installed(&self) -> Option<&InstalledFont> {
+        self.result.as_ref().ok()
+    }
+
+    // 会覆盖受保护的系统字体而被跳过
+    pub fn is_skipped(&self) -> bool {
+        self.result.as_ref().is_err_and(protected::is_protected_error)
+    }
+}
+
+// 返回 (安装, 失败, 跳过) 的数量
+pub fn tally(results: &[InstallResult]) -> (usize, usize, usize) {
+    let installed = results.iter().filter(|r| r.is_installed()).count();
+    let skipped = results.iter().filter(|r| r.is_skipped()).count();
+    (installed, results.len() - installed - skipped, skipped)
+}
+
+pub async fn install_font(font_path: &Path, options: InstallOptions) -> Result<InstalledFont> {
+    let protected = ProtectedPaths::load();
+    let target_name = target_name(font_path, options.normalize_names);
+    copy_font(font_path, &target_name, options.scope.resolve(), &protected, options.link)?;
+    refresh_font_cache().await?;
+    remember_renamed(font_path, &target_name);
+    Ok(describe(font_path, &target_name))
+}
+
+// 读取字体 name 表中的家族与样式，filename 为安装后的文件名
+pub fn describe(font_path: &Path, filename: &str) -> InstalledFont {
+    let descriptor = font_metadata::descriptor(font_path);
+    InstalledFont {
+        filename: filename.to_string(),
+        family: descriptor.family,
+        style: descriptor.style,
+    }
+}
+
+// 安装后的文件名，规范化时按 name 表命名，无法读取家族名时保留原名
+fn target_name(font_path: &Path, normalize: bool) -> String {
+    normalize
+        .then(|| font_metadata::normalized_filename(font_path))
+        .flatten()
+        .unwrap_or_else(|| font_path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+}
+
+// 记住规范化前的名称，服务器删除原名称的字体时据此找到安装的副本
+fn remember_renamed(font_path: &Path, target_name: &str) {
+    let original = font_path.file_name().unwrap_or_default().to_string_lossy();
+    if original != target_name
+        && let Err(e) = ClientState::record_renamed(&original, target_name)
+    {
+        warn!("Failed to save client state: {}", e);
+    }
+}
+
+// 并发复制一批字体，全部完成后统一刷新字体缓存并通知系统
+pub async fn install_fonts(paths: Vec<PathBuf>, options: InstallOptions, progress: &Progress) -> Vec<InstallResult> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    progress.started(Operation::Install, paths.len());
+
+    let resolved = options.scope.resolve();
+    if options.scope == InstallScope::Auto && cfg!(target_os = "windows") && resolved == InstallScope::User {
+        info!("Not running elevated; installing fonts for the current user only");
+    }
+    let targets: Vec<(PathBuf, String)> = paths
+        .into_iter()
+        .map(|path| {
+            let name = target_name(&path, options.normalize_names);
+            (path, name)
+        })
+        .collect();
+    let protected = Arc::new(ProtectedPaths::load());
+    let mut results: Vec<InstallResult> = match escalated_fonts_dir(resolved) {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        Some(fonts_dir) => {
+            let targets = targets.clone();
+            let results = tokio::task::spawn_blocking(move || copy_fonts_escalated(targets, &fonts_dir, &protected))
+                .await
+                .unwrap_or_default();
+            results.iter().for_each(|r| report_installed(progress, r));
+            results
+        }
+        _ => futures::stream::iter(targets.clone())
+            .map(|(path, name)| {
+                let protected = protected.clone();
+                async move {
+                    let copy_path = path.clone();
+                    let result = tokio::task::spawn_blocking(move || {
+                        copy_font(&copy_path, &name, resolved, &protected, options.link).map(|_| describe(&copy_path, &name))
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(anyhow::anyhow!("Install task failed: {}", e)));
+                    let result = InstallResult { path, result };
+                    report_installed(progress, &result);
+                    result
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_INSTALLS)
+            .collect()
+            .await,
+    };
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    for (path, name) in &targets {
+        if results.iter().any(|r| &r.path == path && r.is_installed()) {
+            remember_renamed(path, name);
+        }
+    }
+
+    for r in &results {
+        match &r.result {
+            Ok(font) => info!("Successfully installed font: {:?} ({})", r.file_name(), font.label()),
+            Err(e) if r.is_skipped() => warn!("Skipped installing {:?}: {:#}", r.file_name(), e),
+            Err(e) => error!("Failed to install font {:?}: {:#}", r.file_name(), e),
+        }
+    }
+    let (installed, _, skipped) = tally(&results);
+    if skipped > 0 {
+        warn!("Skipped {} fonts that would overwrite protected system fonts", skipped);
+    }
+    if installed > 0
+        && let Err(e) = refresh_font_cache().await
+    {
+        warn!("Fonts were copied but the font cache could not be refreshed: {:#}", e);
+    }
+    progress.finished(Operation::Install);
+
+    results
+}
+
+fn report_installed(progress: &Progress, result: &InstallResult) {
+    let action = match &result.result {
+        Ok(_) => FileAction::Installed,
+        Err(_) if result.is_skipped() => FileAction::Skipped,
+        Err(_) => FileAction::Failed,
+    };
+    progress.file_finished(Operation::Install, &result.file_name(), action);
+}
+
+pub async fn install_fonts_from_directory(dir_path: &Path, options: InstallOptions, progress: &Progress) -> Result<Vec<InstallResult>> {
     use walkdir::WalkDir;
-    
-    for entry in WalkDir::new(dir_path)
+
+    let paths = WalkDir::new(dir_path)
         .max_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && is_font_file(path) {
-            match install_font(path).await {
-                Ok(_) => {
-                    info!("Successfully installed font: {:?}", path.file_name().unwrap_or_default());
-                    installed += 1;
-                }
-                Err(e) => {
-                    error!("Failed to install font {:?}: {}", path.file_name().unwrap_or_default(), e);
-                    failed += 1;
-                }
-            }
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && is_font_file(path))
+        .collect();
+
+    Ok(install_fonts(paths, options, progress).await)
+}
+
+// 从系统字体目录移除已安装的字体并通知系统
+pub async fn uninstall_font(font_path: &Path) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    unregister_font_windows(font_path);
+
+    match tokio::fs::remove_file(font_path).await {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let path = font_path.to_path_buf();
+            tokio::task::spawn_blocking(move || run_escalated(&path, [OsStr::new("rm"), OsStr::new("-f"), path.as_os_str()]))
+                .await
+                .context("Uninstall task failed")?
+                .context("Failed to remove font from system")?;
         }
+        result => result.context("Failed to remove font from system")?,
+    }
+    refresh_font_cache().await
+}
+
+// 把字体以 target_name 复制到 scope（已解析，不为 auto）对应的字体目录，返回目标路径；不刷新字体缓存
+fn copy_font(font_path: &Path, target_name: &str, scope: InstallScope, protected: &ProtectedPaths, link: bool) -> Result<PathBuf> {
+    #[cfg(target_os = "windows")]
+    return copy_font_windows(font_path, target_name, scope, protected, link);
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    return copy_font_to(font_path, &unix_fonts_dir(scope)?, target_name, protected, link);
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    {
+        let _ = (font_path, target_name, scope, protected, link);
+        Err(anyhow::anyhow!("Font installation not supported on this OS"))
     }
-    
-    Ok((installed, failed))
 }
 
 #[cfg(target_os = "windows")]
-async fn install_font_windows(font_path: &Path) -> Result<()> {
-    use std::fs;
+fn copy_font_windows(font_path: &Path, target_name: &str, scope: InstallScope, protected: &ProtectedPaths, link: bool) -> Result<PathBuf> {
     use windows_sys::Win32::System::Registry::{
-        RegCloseKey, RegCreateKeyW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE, KEY_SET_VALUE, REG_SZ,
+        RegCloseKey, RegCreateKeyW, RegSetValueExW, HKEY, REG_SZ,
     };
 
-    info!("Installing font on Windows: {:?}", font_path);
-
-    // 获取 Windows 字体目录（固定为 %WINDIR%\\Fonts）
-    let fonts_dir = std::env::var_os("WINDIR")
-        .map(|win_dir| std::path::PathBuf::from(win_dir).join("Fonts"))
-        .context("Failed to get fonts directory")?;
+    info!("Installing font on Windows ({:?}): {:?}", scope, font_path);
 
-    let font_filename = font_path
-        .file_name()
-        .context("Failed to get font filename")?;
-    
-    let target_path = fonts_dir.join(font_filename);
+    let fonts_dir = windows_fonts_dir(scope)?;
+    std::fs::create_dir_all(&fonts_dir).context("Failed to create fonts directory")?;
 
-    // 复制字体到字体目录
-    fs::copy(font_path, &target_path)
-        .context("Failed to copy font to fonts directory")?;
+    let target_path = fonts_dir.join(target_name);
+    protected.check_install(&target_path)?;
 
-    info!("Font copied to: {:?}", target_path);
+    // 复制或链接字体到字体目录
+    place_font(font_path, &target_path, link)?;
 
     // 写入注册表，确保字体对系统可见
     let mut key: HKEY = 0;
     let subkey = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Fonts";
     let subkey_wide: Vec<u16> = subkey.encode_utf16().chain(std::iter::once(0)).collect();
-    let status = unsafe { RegCreateKeyW(HKEY_LOCAL_MACHINE, subkey_wide.as_ptr(), &mut key) };
+    let status = unsafe { RegCreateKeyW(windows_fonts_root(scope), subkey_wide.as_ptr(), &mut key) };
     if status != 0 {
         return Err(anyhow::anyhow!("Failed to open fonts registry key: {}", status));
     }
@@ -87,7 +283,11 @@ async fn install_font_windows(font_path: &Path) -> Result<()> {
         .and_then(|n| n.to_str())
         .unwrap_or("FontSyncFont");
     let value_name_wide: Vec<u16> = value_name.encode_utf16().chain(std::iter::once(0)).collect();
-    let value_data = format!("{}", target_path.file_name().unwrap_or_default().to_string_lossy());
+    // 系统字体目录中的字体只记文件名，用户字体需要完整路径
+    let value_data = match scope {
+        InstallScope::System => target_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        _ => target_path.to_string_lossy().into_owned(),
+    };
     let mut value_data_wide: Vec<u16> = value_data.encode_utf16().collect();
     value_data_wide.push(0);
     let status = unsafe {
@@ -108,33 +308,187 @@ async fn install_font_windows(font_path: &Path) -> Result<()> {
     }
     info!("Font registered in registry");
 
-    // 通知其他应用字体发生变化
-    // 广播 WM_FONTCHANGE 消息
-    use windows_sys::Win32::Graphics::Gdi::GdiFlush;
-    use windows_sys::Win32::UI::WindowsAndMessaging::{
-        SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_FONTCHANGE,
-    };
-    
+    // 注册表只在下次登录时生效，同时加载到当前会话，运行中的程序收到 WM_FONTCHANGE 后即可使用
+    add_session_font(&target_path)?;
+    if let Some(family) = crate::font_metadata::descriptor(&target_path).family
+        && !gdi_has_family(&family)
+    {
+        warn!("Font {:?} was loaded but GDI does not list family '{}'", target_path, family);
+    }
+
+    Ok(target_path)
+}
+
+// 系统范围为 %WINDIR%\Fonts，用户范围为 %LOCALAPPDATA%\Microsoft\Windows\Fonts
+#[cfg(target_os = "windows")]
+fn windows_fonts_dir(scope: InstallScope) -> Result<PathBuf> {
+    match scope {
+        InstallScope::System => std::env::var_os("WINDIR")
+            .map(|win_dir| PathBuf::from(win_dir).join("Fonts"))
+            .context("Failed to get fonts directory"),
+        _ => dirs::data_local_dir()
+            .map(|dir| dir.join("Microsoft\\Windows\\Fonts"))
+            .context("Failed to get user fonts directory"),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn windows_fonts_root(scope: InstallScope) -> windows_sys::Win32::System::Registry::HKEY {
+    use windows_sys::Win32::System::Registry::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    match scope {
+        InstallScope::System => HKEY_LOCAL_MACHINE,
+        _ => HKEY_CURRENT_USER,
+    }
+}
+
+// 当前进程是否以管理员权限运行
+#[cfg(target_os = "windows")]
+pub fn is_elevated() -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
     unsafe {
-        let mut result = 0;
-        SendMessageTimeoutW(
-            HWND_BROADCAST,
-            WM_FONTCHANGE,
-            0,
-            0,
-            SMTO_ABORTIFHUNG,
-            1000,
-            &mut result,
+        let mut token: HANDLE = 0;
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size = 0u32;
+        let ok = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut TOKEN_ELEVATION as *mut _,
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
         );
-        GdiFlush();
-        info!("Font change notification sent");
+        CloseHandle(token);
+        ok != 0 && elevation.TokenIsElevated != 0
     }
-    // 等待系统刷新字体列表，避免安装后立即检查失败
-    std::thread::sleep(std::time::Duration::from_secs(2));
+}
+
+// 通过 UAC 以管理员权限重新运行当前程序并等待其结束，返回退出码
+#[cfg(target_os = "windows")]
+pub fn relaunch_elevated(args: &[String]) -> Result<u32> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject, INFINITE};
+    use windows_sys::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use windows_sys::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let exe = std::env::current_exe().context("Failed to locate the fontsync executable")?;
+    let exe_wide = wide_path(&exe);
+    let verb: Vec<u16> = "runas".encode_utf16().chain(std::iter::once(0)).collect();
+    let params = args
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let params_wide: Vec<u16> = params.encode_utf16().chain(std::iter::once(0)).collect();
+
+    unsafe {
+        let mut info: SHELLEXECUTEINFOW = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = exe_wide.as_ptr();
+        info.lpParameters = params_wide.as_ptr();
+        info.nShow = SW_SHOWNORMAL;
+        if ShellExecuteExW(&mut info) == 0 || info.hProcess == 0 {
+            return Err(anyhow::anyhow!("Elevation was cancelled or failed"));
+        }
+        WaitForSingleObject(info.hProcess, INFINITE);
+        let mut code = 0u32;
+        GetExitCodeProcess(info.hProcess, &mut code);
+        CloseHandle(info.hProcess);
+        Ok(code)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn wide_path(path: &Path) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str().encode_wide().chain(std::iter::once(0)).collect()
+}
 
+// 把字体加载到当前会话，不写注册表
+#[cfg(target_os = "windows")]
+fn add_session_font(font_path: &Path) -> Result<()> {
+    use windows_sys::Win32::Graphics::Gdi::AddFontResourceW;
+    let path_wide = wide_path(font_path);
+    if unsafe { AddFontResourceW(path_wide.as_ptr()) } == 0 {
+        return Err(anyhow::anyhow!("AddFontResourceW failed for {:?}", font_path));
+    }
     Ok(())
 }
 
+// 从当前会话卸载字体，返回是否成功
+#[cfg(target_os = "windows")]
+fn remove_session_font(font_path: &Path) -> bool {
+    use windows_sys::Win32::Graphics::Gdi::RemoveFontResourceW;
+    let path_wide = wide_path(font_path);
+    unsafe { RemoveFontResourceW(path_wide.as_ptr()) != 0 }
+}
+
+// 通过 GDI 枚举确认字体家族对当前会话可见
+#[cfg(target_os = "windows")]
+fn gdi_has_family(family: &str) -> bool {
+    use windows_sys::Win32::Foundation::LPARAM;
+    use windows_sys::Win32::Graphics::Gdi::{
+        EnumFontFamiliesExW, GetDC, ReleaseDC, DEFAULT_CHARSET, LOGFONTW, TEXTMETRICW,
+    };
+
+    unsafe extern "system" fn found(_: *const LOGFONTW, _: *const TEXTMETRICW, _: u32, lparam: LPARAM) -> i32 {
+        unsafe { *(lparam as *mut bool) = true };
+        0
+    }
+
+    let name: Vec<u16> = family.encode_utf16().collect();
+    let mut logfont: LOGFONTW = unsafe { std::mem::zeroed() };
+    if name.len() >= logfont.lfFaceName.len() {
+        return true;
+    }
+    logfont.lfFaceName[..name.len()].copy_from_slice(&name);
+    logfont.lfCharSet = DEFAULT_CHARSET;
+
+    let mut matched = false;
+    unsafe {
+        let hdc = GetDC(0);
+        EnumFontFamiliesExW(hdc, &logfont, Some(found), &mut matched as *mut bool as LPARAM, 0);
+        ReleaseDC(0, hdc);
+    }
+    matched
+}
+
+// 从当前会话卸载字体并删除注册表项，失败时只记录警告，删除文件时才报告错误
+#[cfg(target_os = "windows")]
+fn unregister_font_windows(font_path: &Path) {
+    use windows_sys::Win32::System::Registry::{RegCloseKey, RegDeleteValueW, RegOpenKeyExW, HKEY, KEY_SET_VALUE};
+
+    if !remove_session_font(font_path) {
+        warn!("RemoveFontResourceW failed for {:?}", font_path);
+    }
+
+    let Some(value_name) = font_path.file_name().and_then(|n| n.to_str()) else {
+        return;
+    };
+    let scope = match windows_fonts_dir(InstallScope::System) {
+        Ok(dir) if font_path.starts_with(&dir) => InstallScope::System,
+        _ => InstallScope::User,
+    };
+    let mut key: HKEY = 0;
+    let subkey = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Fonts";
+    let subkey_wide: Vec<u16> = subkey.encode_utf16().chain(std::iter::once(0)).collect();
+    let value_name_wide: Vec<u16> = value_name.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        if RegOpenKeyExW(windows_fonts_root(scope), subkey_wide.as_ptr(), 0, KEY_SET_VALUE, &mut key) != 0 {
+            warn!("Failed to open fonts registry key to unregister {:?}", font_path);
+            return;
+        }
+        RegDeleteValueW(key, value_name_wide.as_ptr());
+        RegCloseKey(key);
+    }
+}
+
 fn is_font_file(path: &Path) -> bool {
     if let Some(ext) = path.extension() {
         let ext_str = ext.to_string_lossy().to_lowercase();
@@ -147,78 +501,180 @@ fn is_font_file(path: &Path) -> bool {
     }
 }
 
-#[cfg(target_os = "linux")]
-async fn install_font_linux(font_path: &Path) -> Result<()> {
-    use std::fs;
-    
-    info!("Installing font on Linux: {:?}", font_path);
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn unix_fonts_dir(scope: InstallScope) -> Result<PathBuf> {
+    let macos = cfg!(target_os = "macos");
+    Ok(match scope {
+        InstallScope::System if macos => PathBuf::from("/Library/Fonts"),
+        InstallScope::System => PathBuf::from("/usr/local/share/fonts"),
+        // 获取用户字体目录
+        _ => {
+            let home_dir = dirs::home_dir().context("Failed to get home directory")?;
+            home_dir.join(if macos { "Library/Fonts" } else { ".local/share/fonts" })
+        }
+    })
+}
 
-    // 获取用户字体目录
-    let home_dir = dirs::home_dir()
-        .context("Failed to get home directory")?;
-    
-    let user_fonts_dir = home_dir.join(".local/share/fonts");
-    
-    // 字体目录不存在时创建
-    if !user_fonts_dir.exists() {
-        fs::create_dir_all(&user_fonts_dir)
-            .context("Failed to create fonts directory")?;
+// 系统范围且当前用户无法写入系统字体目录时，返回需要提权写入的目录
+fn escalated_fonts_dir(scope: InstallScope) -> Option<PathBuf> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if scope == InstallScope::System
+        && let Ok(dir) = unix_fonts_dir(scope)
+    {
+        let writable = std::fs::create_dir_all(&dir).is_ok() && tempfile::tempfile_in(&dir).is_ok();
+        return (!writable).then_some(dir);
     }
+    let _ = scope;
+    None
+}
 
-    let font_filename = font_path
-        .file_name()
-        .context("Failed to get font filename")?;
-    
-    let target_path = user_fonts_dir.join(font_filename);
-
-    // 复制字体到字体目录
-    fs::copy(font_path, &target_path)
-        .context("Failed to copy font to fonts directory")?;
+// 通过 sudo 或 pkexec 一次复制整批字体，只需授权一次
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_fonts_escalated(targets: Vec<(PathBuf, String)>, fonts_dir: &Path, protected: &ProtectedPaths) -> Vec<InstallResult> {
+    let mut results = Vec::new();
+    let mut allowed = Vec::new();
+    for (path, name) in targets {
+        match protected.check_install(&fonts_dir.join(&name)) {
+            Ok(()) => allowed.push((path, name)),
+            Err(e) => results.push(InstallResult { path, result: Err(e.into()) }),
+        }
+    }
+    if allowed.is_empty() {
+        return results;
+    }
 
-    info!("Font copied to: {:?}", target_path);
+    info!("Copying {} fonts to {:?} with elevated privileges", allowed.len(), fonts_dir);
+    // 路径作为位置参数成对传入（源文件、目标文件名），不拼接进脚本
+    let mut args = vec![
+        OsStr::new("sh"),
+        OsStr::new("-c"),
+        OsStr::new(r#"d="$0"; mkdir -p "$d" || exit 1; while [ $# -gt 1 ]; do cp -f "$1" "$d/$2" || exit 1; shift 2; done"#),
+        fonts_dir.as_os_str(),
+    ];
+    for (path, name) in &allowed {
+        args.push(path.as_os_str());
+        args.push(OsStr::new(name.as_str()));
+    }
+    let outcome = run_escalated(fonts_dir, args);
+    results.extend(allowed.into_iter().map(|(path, name)| {
+        let result = match &outcome {
+            Ok(()) => Ok(describe(&path, &name)),
+            Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+        };
+        InstallResult { path, result }
+    }));
+    results
+}
 
-    // 更新字体缓存
-    update_font_cache()?;
+// 有终端时用 sudo（可输入密码），图形会话中用 pkexec，否则给出提示
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run_escalated<'a>(target: &Path, args: impl IntoIterator<Item = &'a OsStr>) -> Result<()> {
+    use std::io::IsTerminal;
 
+    let graphical = std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let tool = if std::io::stdin().is_terminal() && find_in_path("sudo") {
+        "sudo"
+    } else if graphical && find_in_path("pkexec") {
+        "pkexec"
+    } else {
+        return Err(anyhow::anyhow!(
+            "Writing to {:?} requires root; run fontsync with sudo or use --scope user",
+            target
+        ));
+    };
+    let status = Command::new(tool)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", tool))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} exited with {} while writing to {:?}", tool, status, target));
+    }
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-async fn install_font_macos(font_path: &Path) -> Result<()> {
-    use std::fs;
-    
-    info!("Installing font on macOS: {:?}", font_path);
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn find_in_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_font_to(font_path: &Path, user_fonts_dir: &Path, target_name: &str, protected: &ProtectedPaths, link: bool) -> Result<PathBuf> {
+    info!("Installing font: {:?}", font_path);
 
-    // 获取用户字体目录
-    let home_dir = dirs::home_dir()
-        .context("Failed to get home directory")?;
-    
-    let user_fonts_dir = home_dir.join("Library/Fonts");
-    
     // 字体目录不存在时创建
-    if !user_fonts_dir.exists() {
-        fs::create_dir_all(&user_fonts_dir)
-            .context("Failed to create fonts directory")?;
+    std::fs::create_dir_all(user_fonts_dir)
+        .context("Failed to create fonts directory")?;
+
+    let target_path = user_fonts_dir.join(target_name);
+    protected.check_install(&target_path)?;
+
+    // 复制或链接字体到字体目录
+    place_font(font_path, &target_path, link)?;
+    Ok(target_path)
+}
+
+// 硬链接与源文件共用数据，卸载时只删除链接，源文件不受影响
+fn place_font(font_path: &Path, target_path: &Path, link: bool) -> Result<()> {
+    let linked = if link {
+        crate::utils::link_or_copy_atomic(font_path, target_path).context("Failed to copy font to fonts directory")?
+    } else {
+        crate::utils::copy_atomic(font_path, target_path).context("Failed to copy font to fonts directory")?;
+        false
+    };
+    if linked {
+        info!("Font linked to: {:?}", target_path);
+    } else {
+        info!("Font copied to: {:?}", target_path);
     }
+    Ok(())
+}
 
-    let font_filename = font_path
-        .file_name()
-        .context("Failed to get font filename")?;
+#[cfg(target_os = "windows")]
+async fn refresh_font_cache() -> Result<()> {
+    // 通知其他应用字体发生变化
+    // 广播 WM_FONTCHANGE 消息
+    use windows_sys::Win32::Graphics::Gdi::GdiFlush;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_FONTCHANGE,
+    };
     
-    let target_path = user_fonts_dir.join(font_filename);
-
-    // 复制字体到字体目录
-    fs::copy(font_path, &target_path)
-        .context("Failed to copy font to fonts directory")?;
+    unsafe {
+        let mut result = 0;
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_FONTCHANGE,
+            0,
+            0,
+            SMTO_ABORTIFHUNG,
+            1000,
+            &mut result,
+        );
+        GdiFlush();
+        info!("Font change notification sent");
+    }
 
-    info!("Font copied to: {:?}", target_path);
+    Ok(())
+}
 
+#[cfg(target_os = "macos")]
+async fn refresh_font_cache() -> Result<()> {
     // 在 macOS 上更新字体缓存
-    Command::new("atsutil")
-        .args(["databases", "-remove"])
-        .status()
+    tokio::task::spawn_blocking(|| Command::new("atsutil").args(["databases", "-remove"]).status())
+        .await
+        .context("Font cache task failed")?
         .context("Failed to update font cache")?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn refresh_font_cache() -> Result<()> {
+    tokio::task::spawn_blocking(update_font_cache)
+        .await
+        .context("Font cache task failed")?
+}
 
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+async fn refresh_font_cache() -> Result<()> {
     Ok(())
 }
 
@@ -248,3 +704,95 @@ fn update_font_cache() -> Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protected::ProtectedPathError;
+
+    #[test]
+    fn tallies_install_results() {
+        let result = |name: &str, result: Result<()>| InstallResult {
+            path: PathBuf::from("/fonts").join(name),
+            result: result.map(|()| describe(Path::new(name), name)),
+        };
+        let protected = ProtectedPathError { path: PathBuf::from("/system/Arial.ttf"), reason: "protected".to_string() };
+        let results = vec![
+            result("a.ttf", Ok(())),
+            result("b.ttf", Ok(())),
+            result("Arial.ttf", Err(anyhow::Error::new(protected).context("Failed to install"))),
+            result("broken.ttf", Err(anyhow::anyhow!("copy failed"))),
+        ];
+        assert_eq!(tally(&results), (2, 1, 1));
+        assert!(results[2].is_skipped() && !results[3].is_skipped());
+        assert_eq!(results[3].file_name(), "broken.ttf");
+        // 无法解析 name 表时以文件名标识
+        assert_eq!(results[0].installed().map(|font| font.label()).as_deref(), Some("a.ttf"));
+        assert!(results[3].installed().is_none());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn installed_fonts_are_described_by_their_name_table() {
+        use crate::font_metadata::tests::{name_table, sfnt_with_tables};
+
+        let source = tempfile::tempdir().unwrap();
+        let fonts_dir = tempfile::tempdir().unwrap();
+        let path = source.path().join("download(1).ttf");
+        std::fs::write(&path, sfnt_with_tables(&[(b"name", name_table(&[(1, "Noto Sans"), (2, "Bold")]))])).unwrap();
+
+        let name = target_name(&path, true);
+        assert_eq!(name, "NotoSans-Bold.ttf");
+        let result = InstallResult {
+            path: path.clone(),
+            result: copy_font_to(&path, fonts_dir.path(), &name, &ProtectedPaths::load(), false).map(|_| describe(&path, &name)),
+        };
+        assert!(fonts_dir.path().join(&name).exists());
+        let font = result.installed().unwrap();
+        assert_eq!(font.filename, "NotoSans-Bold.ttf");
+        assert_eq!((font.family.as_deref(), font.style.as_deref()), (Some("Noto Sans"), Some("Bold")));
+        assert_eq!(font.label(), "Noto Sans Bold");
+
+        // 不规范化时保留原名，无法解析的文件没有家族与样式
+        std::fs::write(&path, b"not a font").unwrap();
+        assert_eq!(target_name(&path, true), "download(1).ttf");
+        let font = describe(&path, "download(1).ttf");
+        assert_eq!((font.family, font.style), (None, None));
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn session_fonts_are_listed_by_gdi_until_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("NotoSansTest-Regular.ttf");
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("test_fonts/NotoSansTest-Regular.ttf"), &path).unwrap();
+        let family = font_metadata::descriptor(&path).family.expect("test font has a family name");
+
+        // 开发机上可能已安装同名字体，卸载后恢复原状即可
+        let listed = gdi_has_family(&family);
+        add_session_font(&path).unwrap();
+        assert!(gdi_has_family(&family));
+        assert!(remove_session_font(&path));
+        assert_eq!(gdi_has_family(&family), listed);
+        assert!(!remove_session_font(&path));
+
+        assert!(add_session_font(&dir.path().join("missing.ttf")).is_err());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn user_scope_installs_into_the_profile() {
+        assert!(windows_fonts_dir(InstallScope::User).unwrap().ends_with("Microsoft\\Windows\\Fonts"));
+        assert!(windows_fonts_dir(InstallScope::System).unwrap().ends_with("Fonts"));
+        assert_eq!(wide_path(Path::new("a.ttf")), "a.ttf\0".encode_utf16().collect::<Vec<_>>());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn auto_scope_installs_for_the_current_user() {
+        assert_eq!(InstallScope::Auto.resolve(), InstallScope::User);
+        assert_eq!(InstallScope::System.resolve(), InstallScope::System);
+        assert!(unix_fonts_dir(InstallScope::System).unwrap().starts_with("/"));
+        assert!(escalated_fonts_dir(InstallScope::User).is_none());
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// OS/2 表 fsType 字段声明的嵌入与分发许可
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmbeddingPermission {
+    Installable,
+    Restricted,
+    PreviewAndPrint,
+    Editable,
+}
+
+impl EmbeddingPermission {
+    // Restricted License 字体不得嵌入或再分发
+    pub fn is_restricted(self) -> bool {
+        self == EmbeddingPermission::Restricted
+    }
+}
+
+impl std::fmt::Display for EmbeddingPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            EmbeddingPermission::Installable => "installable",
+            EmbeddingPermission::Restricted => "restricted license",
+            EmbeddingPermission::PreviewAndPrint => "preview & print",
+            EmbeddingPermission::Editable => "editable",
+        };
+        f.write_str(text)
+    }
+}
+
+// 无法解析（如 WOFF2 或加密内容）或缺少 OS/2 表时返回 None
+pub fn read_embedding_permission(data: &[u8]) -> Option<EmbeddingPermission> {
+    // 只读取 OS/2 表，不要求字体包含完整的必需表
+    let face = ttf_parser::RawFace::parse(data, 0).ok()?;
+    let os2 = ttf_parser::os2::Table::parse(face.table(ttf_parser::Tag::from_bytes(b"OS/2"))?)?;
+    let permission = match os2.permissions()? {
+        ttf_parser::Permissions::Installable => EmbeddingPermission::Installable,
+        ttf_parser::Permissions::Restricted => EmbeddingPermission::Restricted,
+        ttf_parser::Permissions::PreviewAndPrint => EmbeddingPermission::PreviewAndPrint,
+        ttf_parser::Permissions::Editable => EmbeddingPermission::Editable,
+    };
+    Some(permission)
+}
+
+pub fn embedding_permission(path: &Path) -> Option<EmbeddingPermission> {
+    let data = fs::read(path).ok()?;
+    read_embedding_permission(&data)
+}
+
+// 从 name 与 OS/2 表读取的字体描述信息，缺失的字段为空
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FontDescriptor {
+    pub family: Option<String>,
+    pub style: Option<String>,
+    pub weight: Option<u16>,
+    pub version: Option<String>,
+}
+
+pub fn read_descriptor(data: &[u8]) -> FontDescriptor {
+    let Ok(face) = ttf_parser::RawFace::parse(data, 0) else {
+        return FontDescriptor::default();
+    };
+
+    let names = face
+        .table(ttf_parser::Tag::from_bytes(b"name"))
+        .and_then(ttf_parser::name::Table::parse);
+    let name = |preferred: u16, legacy: u16| {
+        let names = names.as_ref()?;
+        find_name(names, preferred).or_else(|| find_name(names, legacy))
+    };
+    let weight = face
+        .table(ttf_parser::Tag::from_bytes(b"OS/2"))
+        .and_then(ttf_parser::os2::Table::parse)
+        .map(|os2| os2.weight().to_number());
+
+    FontDescriptor {
+        // 优先使用排版家族名（name ID 16/17），其次是旧式名称（name ID 1/2）
+        family: name(ttf_parser::name_id::TYPOGRAPHIC_FAMILY, ttf_parser::name_id::FAMILY),
+        style: name(ttf_parser::name_id::TYPOGRAPHIC_SUBFAMILY, ttf_parser::name_id::SUBFAMILY),
+        weight,
+        version: name(ttf_parser::name_id::VERSION, ttf_parser::name_id::VERSION),
+    }
+}
+
+impl FontDescriptor {
+    pub fn face_key(&self) -> Option<String> {
+        face_key(self.family.as_deref(), self.style.as_deref())
+    }
+}
+
+// 家族名与样式名相同（不区分大小写与空白）即为同一字体，缺少任一名称时无法判断
+pub fn face_key(family: Option<&str>, style: Option<&str>) -> Option<String> {
+    let clean = |value: &str| value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let (family, style) = (clean(family?), clean(style?));
+    (!family.is_empty() && !style.is_empty()).then(|| format!("{}\n{}", family, style))
+}
+
+pub fn descriptor(path: &Path) -> FontDescriptor {
+    fs::read(path).map(|data| read_descriptor(&data)).unwrap_or_default()
+}
+
+// 按 name 表生成 "<Family>-<Style>.<ext>" 形式的文件名，缺少家族名时返回 None
+pub fn normalized_filename(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    let descriptor = descriptor(path);
+    normalize_name(descriptor.family.as_deref()?, descriptor.style.as_deref(), &ext)
+}
+
+// 去掉空格与文件名中不安全的字符，保留各语言的字母与数字
+pub(crate) fn normalize_name(family: &str, style: Option<&str>, ext: &str) -> Option<String> {
+    let clean = |value: &str| -> String {
+        value.chars().filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect()
+    };
+    let family = clean(family);
+    if family.is_empty() {
+        return None;
+    }
+    let style = style.map(clean).filter(|s| !s.is_empty()).unwrap_or_else(|| "Regular".to_string());
+    Some(format!("{}-{}.{}", family, style, ext))
+}
+
+// 优先取美式英语记录，否则取第一个可解码的记录
+pub(crate) fn find_name(names: &ttf_parser::name::Table, name_id: u16) -> Option<String> {
+    let mut fallback = None;
+    for name in names.names {
+        if name.name_id != name_id {
+            continue;
+        }
+        let Some(text) = name.to_string() else {
+            continue;
+        };
+        if name.language() == ttf_parser::Language::English_UnitedStates {
+            return Some(text);
+        }
+        fallback.get_or_insert(text);
+    }
+    fallback
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 构造只包含指定表的最小 sfnt 数据，用于测试；表目录须按标签排序
+    pub(crate) fn sfnt_with_tables(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut tables = tables.to_vec();
+        tables.sort_by_key(|(tag, _)| **tag);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        data.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        data.extend_from_slice(&[0u8; 6]);
+
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            data.extend_from_slice(*tag);
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in &tables {
+            data.extend_from_slice(table);
+        }
+        data
+    }
+
+    // 可完整解析的 TrueType 字体（家族名 Square）：字母 A 映射为占满字身的方块
+    pub(crate) fn square_font() -> Vec<u8> {
+        let be16 = |values: &[i16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+
+        let mut hhea = vec![0u8; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+
+        let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+        maxp.extend_from_slice(&2u16.to_be_bytes());
+
+        // 单轮廓、四个在曲线上的点，坐标以 16 位增量存储
+        let mut glyf = be16(&[1, 100, 0, 900, 800, 3, 0]);
+        glyf.extend_from_slice(&[0x01; 4]);
+        glyf.extend(be16(&[100, 0, 800, 0, 0, 800, 0, -800]));
+        glyf.resize(36, 0);
+
+        sfnt_with_tables(&[
+            (b"cmap", cmap_table(&[(0x41, 0x41)])),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", be16(&[1000, 0, 1000, 0])),
+            (b"loca", be16(&[0, 0, 18])),
+            (b"maxp", maxp),
+            (b"name", name_table(&[(1, "Square"), (2, "Regular")])),
+        ])
+    }
+
+    // 版本 0 的 OS/2 表长 78 字节，fsType 位于偏移 8
+    pub(crate) fn os2_table(fs_type: u16) -> Vec<u8> {
+        let mut os2 = vec![0u8; 78];
+        os2[8..10].copy_from_slice(&fs_type.to_be_bytes());
+        os2
+    }
+
+    // Windows 平台、Unicode BMP 编码、美式英语的 name 表
+    pub(crate) fn name_table(records: &[(u16, &str)]) -> Vec<u8> {
+        let mut storage = Vec::new();
+        let mut table = Vec::new();
+        table.extend_from_slice(&0u16.to_be_bytes());
+        table.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        table.extend_from_slice(&(6 + 12 * records.len() as u16).to_be_bytes());
+        for (name_id, text) in records {
+            let encoded: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            for value in [3u16, 1, 0x0409, *name_id, encoded.len() as u16, storage.len() as u16] {
+                table.extend_from_slice(&value.to_be_bytes());
+            }
+            storage.extend_from_slice(&encoded);
+        }
+        table.extend_from_slice(&storage);
+        table
+    }
+
+    // 格式 12 的 cmap 表，每段码位映射到从 1 开始的连续字形
+    pub(crate) fn cmap_table(groups: &[(u32, u32)]) -> Vec<u8> {
+        let mut table = Vec::new();
+        for value in [0u16, 1, 3, 10] {
+            table.extend_from_slice(&value.to_be_bytes());
+        }
+        table.extend_from_slice(&12u32.to_be_bytes());
+
+        table.extend_from_slice(&12u16.to_be_bytes());
+        table.extend_from_slice(&0u16.to_be_bytes());
+        let length = 16 + 12 * groups.len() as u32;
+        let mut glyph = 1;
+        for value in [length, 0, groups.len() as u32] {
+            table.extend_from_slice(&value.to_be_bytes());
+        }
+        for &(start, end) in groups {
+            for value in [start, end, glyph] {
+                table.extend_from_slice(&value.to_be_bytes());
+            }
+            glyph += end - start + 1;
+        }
+        table
+    }
+
+    pub(crate) fn sfnt_with_fs_type(fs_type: u16) -> Vec<u8> {
+        sfnt_with_tables(&[(b"OS/2", os2_table(fs_type))])
+    }
+
+    #[test]
+    fn reads_font_descriptor() {
+        let legacy = sfnt_with_tables(&[(b"name", name_table(&[(1, "Noto Sans"), (2, "Bold")]))]);
+        let descriptor = read_descriptor(&legacy);
+        assert_eq!(descriptor.family.as_deref(), Some("Noto Sans"));
+        assert_eq!(descriptor.style.as_deref(), Some("Bold"));
+        assert_eq!(descriptor.weight, None);
+
+        let mut os2 = os2_table(0);
+        os2[4..6].copy_from_slice(&300u16.to_be_bytes());
+        let typographic = sfnt_with_tables(&[
+            (b"OS/2", os2),
+            (
+                b"name",
+                name_table(&[(1, "Noto Sans Light"), (5, "Version 2.004"), (16, "Noto Sans"), (17, "Light")]),
+            ),
+        ]);
+        let descriptor = read_descriptor(&typographic);
+        assert_eq!(descriptor.family.as_deref(), Some("Noto Sans"));
+        assert_eq!(descriptor.style.as_deref(), Some("Light"));
+        assert_eq!(descriptor.weight, Some(300));
+        assert_eq!(descriptor.version.as_deref(), Some("Version 2.004"));
+
+        assert_eq!(read_descriptor(b"not a font"), FontDescriptor::default());
+    }
+
+    #[test]
+    fn face_keys_ignore_case_and_spacing() {
+        assert_eq!(face_key(Some("Foo  Sans"), Some("Bold")), face_key(Some("foo sans"), Some(" bold ")));
+        assert_ne!(face_key(Some("Foo Sans"), Some("Bold")), face_key(Some("Foo Sans"), Some("Regular")));
+        assert_eq!(face_key(Some("Foo Sans"), None), None);
+        assert_eq!(face_key(Some(" "), Some("Bold")), None);
+    }
+
+    #[test]
+    fn normalizes_file_names_from_the_name_table() {
+        assert_eq!(normalize_name("Noto Sans SC", Some("Bold Italic"), "otf").as_deref(), Some("NotoSansSC-BoldItalic.otf"));
+        assert_eq!(normalize_name("思源黑体", None, "ttf").as_deref(), Some("思源黑体-Regular.ttf"));
+        assert_eq!(normalize_name("../ /", Some("Bold"), "ttf"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("font(3).TTF");
+        fs::write(&path, sfnt_with_tables(&[(b"name", name_table(&[(1, "Noto Sans"), (2, "Bold")]))])).unwrap();
+        assert_eq!(normalized_filename(&path).as_deref(), Some("NotoSans-Bold.ttf"));
+        fs::write(&path, b"not a font").unwrap();
+        assert_eq!(normalized_filename(&path), None);
+    }
+
+    #[test]
+    fn reads_fs_type_permissions() {
+        assert_eq!(
+            read_embedding_permission(&sfnt_with_fs_type(0x0002)),
+            Some(EmbeddingPermission::Restricted)
+        );
+        assert_eq!(
+            read_embedding_permission(&sfnt_with_fs_type(0)),
+            Some(EmbeddingPermission::Installable)
+        );
+        assert_eq!(
+            read_embedding_permission(&sfnt_with_fs_type(0x0008)),
+            Some(EmbeddingPermission::Editable)
+        );
+        assert_eq!(read_embedding_permission(b"not a font"), None);
+    }
+}
@@ -1,23 +1,41 @@
 use anyhow::{Context, Result};
 use chrono::Local;
 use log::{error, info, warn};
-use notify::{Event, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use walkdir::WalkDir;
+use tokio_util::sync::CancellationToken;
 
-use crate::utils::{calculate_sha256, is_font_file};
+use crate::font_cache::{self, CacheStats, FontCache};
+use crate::ignore::{IgnoreRules, IGNORE_FILE};
+use crate::utils::{self, calculate_sha256, is_font_file};
 
 #[derive(Debug, Clone)]
 pub enum FontEvent {
     Added(PathBuf, String), // 路径，sha256
-    Modified(PathBuf, String),
-    Removed(PathBuf),
+    Modified(PathBuf, String, String), // 路径，新 sha256，原 sha256
+    Removed(PathBuf, Option<String>), // 路径，删除前缓存的 sha256
+    Renamed(PathBuf, PathBuf, Option<String>), // 原路径，新路径，sha256
 }
 
-#[derive(Debug, Clone)]
+// 改名的两半分两个事件到达，来源事件后这么久没有配对的目标时按删除处理
+const RENAME_PAIR_TIMEOUT: Duration = Duration::from_millis(500);
+
+// 等待配对的改名来源；tracker 为 inotify 的 cookie，其他平台为空
+#[derive(Debug, Clone, PartialEq)]
+struct PendingRename {
+    path: PathBuf,
+    tracker: Option<usize>,
+}
+
+type SharedCache = Arc<parking_lot::Mutex<FontCache>>;
+type PendingRenameSlot = Arc<parking_lot::Mutex<Option<PendingRename>>>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FontInfo {
     pub path: PathBuf,
     pub sha256: String,
@@ -27,19 +45,32 @@ pub struct FontInfo {
 
 pub struct FontMonitor {
     watch_paths: Vec<PathBuf>,
-    font_cache: Arc<parking_lot::RwLock<HashMap<PathBuf, FontInfo>>>,
+    follow_symlinks: bool,
+    font_cache: SharedCache,
+    ignore: Arc<parking_lot::RwLock<IgnoreRules>>,
+    pending_rename: PendingRenameSlot,
     event_sender: mpsc::UnboundedSender<FontEvent>,
     event_receiver: Option<mpsc::UnboundedReceiver<FontEvent>>,
+    shutdown: CancellationToken,
 }
 
 impl FontMonitor {
+    // 缓存保存在客户端状态目录中，重启时未变化的文件不再重新计算哈希
     pub fn new() -> Self {
+        Self::with_cache(FontCache::open(font_cache::DEFAULT_CAPACITY))
+    }
+
+    pub fn with_cache(cache: FontCache) -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
         Self {
             watch_paths: Vec::new(),
-            font_cache: Arc::new(parking_lot::RwLock::new(HashMap::new())),
+            follow_symlinks: false,
+            font_cache: Arc::new(parking_lot::Mutex::new(cache)),
+            ignore: Arc::new(parking_lot::RwLock::new(IgnoreRules::default())),
+            pending_rename: Arc::new(parking_lot::Mutex::new(None)),
             event_sender: sender,
             event_receiver: Some(receiver),
+            shutdown: CancellationToken::new(),
         }
     }
 
@@ -47,6 +78,20 @@ impl FontMonitor {
         self.watch_paths.push(path);
     }
 
+    // 停止文件监听；之后不再产生新的事件
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.font_cache.lock().resize(capacity);
+    }
+
+    // 默认不进入监控目录中链接到其他位置的目录
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        self.follow_symlinks = follow;
+    }
+
     // 获取当前系统的默认字体目录列表
     pub fn get_system_font_paths() -> Vec<PathBuf> {
         let mut paths = Vec::new();
@@ -87,8 +132,10 @@ impl FontMonitor {
     // 扫描所有监控路径，初始化缓存并返回字体列表
     pub async fn scan_fonts(&self) -> Result<Vec<FontInfo>> {
         let mut fonts = Vec::new();
-        let mut cache = self.font_cache.write();
-        cache.clear();
+        let fresh = self.font_cache.lock().fresh_hashes();
+        let ignore = IgnoreRules::load(&self.watch_paths);
+        // 监控目录互为链接时（如 ~/.fonts 与 ~/.local/share/fonts）同一文件只缓存一次
+        let mut seen = HashSet::new();
 
         for watch_path in &self.watch_paths {
             if !watch_path.exists() {
@@ -96,18 +143,11 @@ impl FontMonitor {
                 continue;
             }
 
-            for entry in WalkDir::new(watch_path)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(|e| e.ok())
-            {
-                let path = entry.path();
-                if path.is_file() && is_font_file(path) {
-                    match self.scan_font_file(path).await {
-                        Ok(font_info) => {
-                            cache.insert(path.to_path_buf(), font_info.clone());
-                            fonts.push(font_info);
-                        }
+            for path in utils::font_files(watch_path, self.follow_symlinks, &mut seen) {
+                let path = path.as_path();
+                if !ignore.is_ignored(path) {
+                    match self.scan_font_file(path, &fresh).await {
+                        Ok(font_info) => fonts.push(font_info),
                         Err(e) => {
                             error!("Failed to scan font file {:?}: {}", path, e);
                         }
@@ -116,28 +156,60 @@ impl FontMonitor {
             }
         }
 
-        info!("Scanned {} fonts", fonts.len());
+        let mut cache = self.font_cache.lock();
+        if let Err(e) = cache.replace_all(&fonts) {
+            warn!("Failed to save font cache: {}", e);
+        }
+        *self.ignore.write() = ignore;
+        info!("Scanned {} fonts ({} kept in memory)", fonts.len(), cache.stats().entries);
         Ok(fonts)
     }
 
-    async fn scan_font_file(&self, path: &Path) -> Result<FontInfo> {
+    async fn scan_font_file(
+        &self,
+        path: &Path,
+        fresh: &impl Fn(&Path, u64, std::time::SystemTime) -> Option<String>,
+    ) -> Result<FontInfo> {
         let metadata = tokio::fs::metadata(path)
             .await
             .context("Failed to get file metadata")?;
+        let modified = metadata.modified()?;
 
-        let sha256 = calculate_sha256(path)?;
+        // 上次扫描后未变化的文件不再读取内容
+        let sha256 = match fresh(path, metadata.len(), modified) {
+            Some(sha256) => sha256,
+            None => calculate_sha256(path)?,
+        };
         
         Ok(FontInfo {
             path: path.to_path_buf(),
             sha256,
             size: metadata.len(),
-            modified: metadata.modified()?,
+            modified,
         })
     }
 
+    pub fn cache_stats(&self) -> CacheStats {
+        self.font_cache.lock().stats()
+    }
+
+    // 缓存中记录的字体，在首次扫描之前调用即上次运行时的状态
+    pub fn known_fonts(&self) -> Vec<FontInfo> {
+        self.font_cache.lock().known()
+    }
+
+    // 退出前保存尚未写入磁盘的缓存变更
+    pub fn flush_cache(&self) -> Result<()> {
+        self.font_cache.lock().flush()
+    }
+
     pub async fn start_monitoring(&mut self) -> Result<()> {
         let event_sender = self.event_sender.clone();
         let font_cache = Arc::clone(&self.font_cache);
+        let ignore = Arc::clone(&self.ignore);
+        let pending_rename = Arc::clone(&self.pending_rename);
+        let watch_paths = self.watch_paths.clone();
+        let follow_symlinks = self.follow_symlinks;
         
         // 初始扫描：建立缓存
         self.scan_fonts().await?;
@@ -145,12 +217,25 @@ impl FontMonitor {
         // 创建文件系统监控器
         let watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
             match res {
-                Ok(event) => {
+                Ok(mut event) => {
+                    // notify 总是跟随链接递归监控，不跟随时丢弃经由链接目录的事件
+                    if !follow_symlinks {
+                        event.paths.retain(|path| !watch_paths.iter().any(|root| utils::via_symlinked_dir(root, path)));
+                        if event.paths.is_empty() {
+                            return;
+                        }
+                    }
                     let event_sender = event_sender.clone();
                     let font_cache = Arc::clone(&font_cache);
+
+                    // 忽略文件被修改时重新加载规则，只影响之后的事件
+                    if event.paths.iter().any(|p| p.file_name().is_some_and(|n| n == IGNORE_FILE)) {
+                        *ignore.write() = IgnoreRules::load(&watch_paths);
+                        info!("Reloaded {} rules", IGNORE_FILE);
+                    }
                     
                     // 同步处理事件，避免跨线程 Send 问题
-                    Self::handle_file_event_sync(event, event_sender, font_cache);
+                    Self::handle_file_event_sync(event, event_sender, font_cache, &ignore.read(), &pending_rename);
                 }
                 Err(e) => {
                     error!("File watcher error: {}", e);
@@ -168,10 +253,14 @@ impl FontMonitor {
             }
         }
 
-        // 保持 watcher 存活（直到 Ctrl+C）
+        // 保持 watcher 存活（直到 Ctrl+C 或调用 stop）
+        let shutdown = self.shutdown.clone();
         tokio::spawn(async move {
             let _watcher = watcher; // 保持 watcher 在作用域内
-            tokio::signal::ctrl_c().await.ok();
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = shutdown.cancelled() => {}
+            }
             info!("File monitoring stopped");
         });
 
@@ -181,7 +270,7 @@ impl FontMonitor {
     async fn handle_file_event(
         event: Event,
         event_sender: mpsc::UnboundedSender<FontEvent>,
-        font_cache: Arc<parking_lot::RwLock<HashMap<PathBuf, FontInfo>>>,
+        font_cache: SharedCache,
     ) {
         // 异步版本：对文件变更进行去重与哈希对比
         for path in event.paths {
@@ -193,7 +282,7 @@ impl FontMonitor {
                 notify::EventKind::Create(_) => {
                     if let Ok(font_info) = Self::scan_single_font(&path).await {
                         let sha256 = font_info.sha256.clone();
-                        font_cache.write().insert(path.clone(), font_info);
+                        font_cache.lock().insert(font_info);
                         
                         info!(
                             "[{}] Font added: {:?} (SHA256: {})",
@@ -206,14 +295,13 @@ impl FontMonitor {
                     }
                 }
                 notify::EventKind::Modify(_) => {
-                    let cache = font_cache.read();
+                    let existing = font_cache.lock().get(&path);
                     
-                    if let Some(existing_info) = cache.get(&path) {
+                    if let Some(existing_info) = existing {
                         if let Ok(font_info) = Self::scan_single_font(&path).await {
                             if font_info.sha256 != existing_info.sha256 {
                                 let sha256 = font_info.sha256.clone();
-                                drop(cache); // 释放读锁
-                                font_cache.write().insert(path.clone(), font_info);
+                                font_cache.lock().insert(font_info);
                                 
                                 info!(
                                     "[{}] Font modified: {:?} (SHA256: {})",
@@ -222,15 +310,14 @@ impl FontMonitor {
                                     &sha256[..8]
                                 );
                                 
-                                let _ = event_sender.send(FontEvent::Modified(path, sha256));
+                                let _ = event_sender.send(FontEvent::Modified(path, sha256, existing_info.sha256));
                             }
                         }
                     } else {
                         // 缓存中不存在的新文件
                         if let Ok(font_info) = Self::scan_single_font(&path).await {
                             let sha256 = font_info.sha256.clone();
-                            drop(cache); // 释放读锁
-                            font_cache.write().insert(path.clone(), font_info);
+                            font_cache.lock().insert(font_info);
                             
                             info!(
                                 "[{}] Font added: {:?} (SHA256: {})",
@@ -244,7 +331,7 @@ impl FontMonitor {
                     }
                 }
                 notify::EventKind::Remove(_) => {
-                    font_cache.write().remove(&path);
+                    let removed = font_cache.lock().remove(&path);
                     
                     info!(
                         "[{}] Font removed: {:?}",
@@ -252,7 +339,7 @@ impl FontMonitor {
                         path.file_name().unwrap_or_default()
                     );
                     
-                    let _ = event_sender.send(FontEvent::Removed(path));
+                    let _ = event_sender.send(FontEvent::Removed(path, removed.map(|font| font.sha256)));
                 }
                 _ => {}
             }
@@ -262,40 +349,206 @@ impl FontMonitor {
     fn handle_file_event_sync(
         event: Event,
         event_sender: mpsc::UnboundedSender<FontEvent>,
-        font_cache: Arc<parking_lot::RwLock<HashMap<PathBuf, FontInfo>>>,
+        font_cache: SharedCache,
+        ignore: &IgnoreRules,
+        pending_rename: &PendingRenameSlot,
     ) {
+        if let EventKind::Modify(ModifyKind::Name(mode)) = event.kind {
+            let tracker = event.tracker();
+            return Self::handle_rename_event(mode, tracker, event.paths, &event_sender, &font_cache, ignore, pending_rename);
+        }
+
         // 同步版本：尽量轻量处理，避免阻塞通知线程
         for path in event.paths {
-            if !is_font_file(&path) {
+            if !is_font_file(&path) || ignore.is_ignored(&path) {
                 continue;
             }
 
             match event.kind {
-                notify::EventKind::Create(_) => {
-                    // 新文件在下次扫描时处理
-                    info!("Font file created: {:?}", path.file_name().unwrap_or_default());
+                EventKind::Create(_) | EventKind::Modify(_) => Self::record_change(path, &event_sender, &font_cache),
+                EventKind::Remove(_) => Self::record_removal(path, &event_sender, &font_cache),
+                _ => {}
+            }
+        }
+    }
+
+    // inotify 依次发出 From、To 与成对的 Both，Windows 只有 From 与 To，macOS 只有不区分方向的 Any
+    fn handle_rename_event(
+        mode: RenameMode,
+        tracker: Option<usize>,
+        paths: Vec<PathBuf>,
+        event_sender: &mpsc::UnboundedSender<FontEvent>,
+        font_cache: &SharedCache,
+        ignore: &IgnoreRules,
+        pending_rename: &PendingRenameSlot,
+    ) {
+        let watched = |path: &Path| is_font_file(path) && !ignore.is_ignored(path);
+
+        match (mode, paths.as_slice()) {
+            // 已通过 To 事件配对时原路径不在缓存中
+            (RenameMode::Both, [from, to]) => {
+                if !font_cache.lock().contains(from) {
+                    return;
                 }
-                notify::EventKind::Modify(_) => {
-                    info!("Font file modified: {:?}", path.file_name().unwrap_or_default());
+                let mut pending = pending_rename.lock();
+                if pending.as_ref().is_some_and(|p| p.path == *from) {
+                    pending.take();
                 }
-                notify::EventKind::Remove(_) => {
-                    font_cache.write().remove(&path);
-                    
-                    info!(
-                        "[{}] Font removed: {:?}",
-                        Local::now().format("%Y-%m-%d %H:%M:%S"),
-                        path.file_name().unwrap_or_default()
-                    );
-                    
-                    if path.file_name().and_then(|n| n.to_str()).is_some() {
-                        let _ = event_sender.send(FontEvent::Removed(path));
+                drop(pending);
+                Self::finish_rename(from.clone(), to.clone(), watched(to), event_sender, font_cache);
+            }
+            (RenameMode::From, [from]) => {
+                if watched(from) && font_cache.lock().contains(from) {
+                    Self::await_rename_target(from.clone(), tracker, event_sender, font_cache, pending_rename);
+                }
+            }
+            (RenameMode::To, [to]) => Self::rename_target(to.clone(), tracker, watched(to), event_sender, font_cache, pending_rename),
+            (_, paths) => {
+                for path in paths {
+                    if path.exists() {
+                        Self::rename_target(path.clone(), tracker, watched(path), event_sender, font_cache, pending_rename);
+                    } else if watched(path) && font_cache.lock().contains(path) {
+                        Self::await_rename_target(path.clone(), tracker, event_sender, font_cache, pending_rename);
                     }
                 }
-                _ => {}
             }
         }
     }
 
+    // 记下改名来源，超时仍未配对时视为移出监控目录
+    fn await_rename_target(
+        path: PathBuf,
+        tracker: Option<usize>,
+        event_sender: &mpsc::UnboundedSender<FontEvent>,
+        font_cache: &SharedCache,
+        pending_rename: &PendingRenameSlot,
+    ) {
+        let pending = PendingRename { path, tracker };
+        if let Some(previous) = pending_rename.lock().replace(pending.clone()) {
+            Self::record_removal(previous.path, event_sender, font_cache);
+        }
+
+        let event_sender = event_sender.clone();
+        let font_cache = Arc::clone(font_cache);
+        let pending_rename = Arc::clone(pending_rename);
+        std::thread::spawn(move || {
+            std::thread::sleep(RENAME_PAIR_TIMEOUT);
+            let mut slot = pending_rename.lock();
+            if slot.as_ref() == Some(&pending) {
+                slot.take();
+                drop(slot);
+                Self::record_removal(pending.path, &event_sender, &font_cache);
+            }
+        });
+    }
+
+    fn rename_target(
+        to: PathBuf,
+        tracker: Option<usize>,
+        watched: bool,
+        event_sender: &mpsc::UnboundedSender<FontEvent>,
+        font_cache: &SharedCache,
+        pending_rename: &PendingRenameSlot,
+    ) {
+        let mut pending = pending_rename.lock();
+        let paired = pending
+            .as_ref()
+            .is_some_and(|p| p.path != to && (p.tracker.is_none() || tracker.is_none() || p.tracker == tracker));
+        match pending.take_if(|_| paired) {
+            Some(from) => {
+                drop(pending);
+                Self::finish_rename(from.path, to, watched, event_sender, font_cache);
+            }
+            // 从监控范围外或非字体文件改名而来，如保存时先写临时文件再改名
+            None if watched => {
+                drop(pending);
+                Self::record_change(to, event_sender, font_cache);
+            }
+            None => {}
+        }
+    }
+
+    fn finish_rename(
+        from: PathBuf,
+        to: PathBuf,
+        watched: bool,
+        event_sender: &mpsc::UnboundedSender<FontEvent>,
+        font_cache: &SharedCache,
+    ) {
+        // 改成了不同步的名称，或覆盖了另一个已知字体：按删除与修改处理
+        if !watched || font_cache.lock().contains(&to) {
+            Self::record_removal(from, event_sender, font_cache);
+            if watched {
+                Self::record_change(to, event_sender, font_cache);
+            }
+            return;
+        }
+
+        let mut cache = font_cache.lock();
+        let Some(mut font_info) = cache.remove(&from) else {
+            drop(cache);
+            Self::record_change(to, event_sender, font_cache);
+            return;
+        };
+        let sha256 = font_info.sha256.clone();
+        font_info.path = to.clone();
+        cache.insert(font_info);
+        drop(cache);
+
+        info!(
+            "Font file renamed: {:?} -> {:?}",
+            from.file_name().unwrap_or_default(),
+            to.file_name().unwrap_or_default()
+        );
+        let _ = event_sender.send(FontEvent::Renamed(from, to, Some(sha256)));
+    }
+
+    fn record_change(path: PathBuf, event_sender: &mpsc::UnboundedSender<FontEvent>, font_cache: &SharedCache) {
+        // 写入过程中会收到多次通知，内容哈希未变时不重复发送
+        let Ok(metadata) = std::fs::metadata(&path) else { return };
+        let Ok(sha256) = calculate_sha256(&path) else { return };
+        // 同一文件经由另一条路径（链接或重复的监控目录）已在缓存中时不重复上报
+        let canonical = std::fs::canonicalize(&path).ok();
+        let same_file = |other: &Path| canonical.is_some() && std::fs::canonicalize(other).ok() == canonical;
+        let mut cache = font_cache.lock();
+        if !cache.contains(&path) && cache.recent_with_sha256(&sha256).any(|font| same_file(&font.path)) {
+            return;
+        }
+        let previous = cache.insert(FontInfo {
+            path: path.clone(),
+            sha256: sha256.clone(),
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(std::time::SystemTime::now()),
+        });
+        drop(cache);
+
+        match previous {
+            Some(previous) if previous.sha256 == sha256 => {}
+            Some(previous) => {
+                info!("Font file modified: {:?}", path.file_name().unwrap_or_default());
+                let _ = event_sender.send(FontEvent::Modified(path, sha256, previous.sha256));
+            }
+            None => {
+                info!("Font file created: {:?}", path.file_name().unwrap_or_default());
+                let _ = event_sender.send(FontEvent::Added(path, sha256));
+            }
+        }
+    }
+
+    fn record_removal(path: PathBuf, event_sender: &mpsc::UnboundedSender<FontEvent>, font_cache: &SharedCache) {
+        let removed = font_cache.lock().remove(&path);
+
+        info!(
+            "[{}] Font removed: {:?}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            path.file_name().unwrap_or_default()
+        );
+
+        if path.file_name().and_then(|n| n.to_str()).is_some() {
+            let _ = event_sender.send(FontEvent::Removed(path, removed.map(|font| font.sha256)));
+        }
+    }
+
     async fn scan_single_font(path: &Path) -> Result<FontInfo> {
         let metadata = tokio::fs::metadata(path)
             .await
@@ -315,7 +568,7 @@ impl FontMonitor {
         self.event_receiver.take()
     }
 
-    pub fn get_font_cache(&self) -> Arc<parking_lot::RwLock<HashMap<PathBuf, FontInfo>>> {
+    pub fn get_font_cache(&self) -> SharedCache {
         Arc::clone(&self.font_cache)
     }
 }
@@ -344,3 +597,59 @@ pub async fn monitor_font_changes(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_event(mode: RenameMode, paths: &[&Path], tracker: Option<usize>) -> Event {
+        let mut event = Event::new(EventKind::Modify(ModifyKind::Name(mode)));
+        for path in paths {
+            event = event.add_path(path.to_path_buf());
+        }
+        match tracker {
+            Some(tracker) => event.set_tracker(tracker),
+            None => event,
+        }
+    }
+
+    #[tokio::test]
+    async fn rename_events_are_paired() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old.ttf"), dir.path().join("new.ttf"));
+        std::fs::write(&old, b"font").unwrap();
+
+        let mut monitor = FontMonitor::with_cache(FontCache::with_store(None, font_cache::DEFAULT_CAPACITY));
+        monitor.add_watch_path(dir.path().to_path_buf());
+        monitor.scan_fonts().await.unwrap();
+        let mut events = monitor.take_event_receiver().unwrap();
+        let handle = |event: Event| {
+            let ignore = IgnoreRules::default();
+            FontMonitor::handle_file_event_sync(
+                event,
+                monitor.event_sender.clone(),
+                Arc::clone(&monitor.font_cache),
+                &ignore,
+                &monitor.pending_rename,
+            );
+        };
+
+        // inotify 的 From、To 与 Both 只产生一个改名事件
+        std::fs::rename(&old, &new).unwrap();
+        handle(rename_event(RenameMode::From, &[&old], Some(7)));
+        handle(rename_event(RenameMode::To, &[&new], Some(7)));
+        handle(rename_event(RenameMode::Both, &[&old, &new], Some(7)));
+        match events.try_recv() {
+            Ok(FontEvent::Renamed(from, to, _)) => assert_eq!((from, to), (old.clone(), new.clone())),
+            other => panic!("expected a rename, got {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+        assert!(monitor.font_cache.lock().contains(&new));
+
+        // 移出监控目录时没有配对的目标，超时后按删除处理
+        handle(rename_event(RenameMode::From, &[&new], None));
+        assert!(events.try_recv().is_err());
+        let removed = tokio::time::timeout(RENAME_PAIR_TIMEOUT * 4, events.recv()).await.unwrap();
+        assert!(matches!(removed, Some(FontEvent::Removed(path, _)) if path == new));
+    }
+}
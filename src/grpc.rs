@@ -0,0 +1,685 @@
+// tonic 的接口以 Status 作为错误类型
+#![allow(clippy::result_large_err)]
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::metadata::{AsciiMetadataValue, MetadataMap};
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status, Streaming};
+use warp::hyper::StatusCode;
+
+use crate::access::Role;
+use crate::api::{Attribution, ErrorCode, ErrorResponse, FontActionResponse, FontInfo, FontList, FontQuery};
+use crate::blob_store::BlobStore;
+use crate::client::{ApiClient, ServerError};
+use crate::event_log::EventLog;
+use crate::identity::{ClientIdentity, CLIENT_ID_HEADER, HOSTNAME_HEADER, OS_HEADER};
+use crate::metadata_store::MetadataStore;
+use crate::server::{self, ServerPolicy, UploadBody, UploadError, UploadOptions};
+use crate::utils::sanitize_filename;
+use crate::websocket_server::WebSocketServer;
+
+pub mod proto {
+    tonic::include_proto!("fontsync");
+}
+
+use proto::font_sync_client::FontSyncClient;
+use proto::font_sync_server::{FontSync, FontSyncServer};
+use proto::{DownloadChunk, DownloadRequest, Event, ListRequest, ListResponse, UploadChunk, UploadHeader, UploadResponse, WatchRequest};
+
+// 上传与下载时每条消息的数据大小，远小于 gRPC 默认的 4 MiB 消息上限
+const CHUNK_SIZE: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 与 HTTP 接口共用字体目录、元数据、事件日志与服务器策略
+#[derive(Clone)]
+pub struct FontSyncService {
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+}
+
+impl FontSyncService {
+    pub fn new(
+        font_dir: Arc<PathBuf>,
+        ws_server: Option<Arc<WebSocketServer>>,
+        event_log: Arc<EventLog>,
+        metadata: Arc<MetadataStore>,
+        blobs: Arc<BlobStore>,
+        policy: ServerPolicy,
+    ) -> Self {
+        Self { font_dir, ws_server, event_log, metadata, blobs, policy }
+    }
+
+    // 绑定失败时立即返回错误，返回的 future 在 shutdown 完成后结束
+    pub fn serve(
+        self,
+        addr: SocketAddr,
+        shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> Result<futures::future::BoxFuture<'static, ()>> {
+        let incoming = tonic::transport::server::TcpIncoming::new(addr, true, None)
+            .map_err(|e| anyhow::anyhow!("Failed to bind gRPC server on {}: {}", addr, e))?;
+        let server = tonic::transport::Server::builder()
+            .add_service(FontSyncServer::new(self))
+            .serve_with_incoming_shutdown(incoming, shutdown);
+        Ok(Box::pin(async move {
+            if let Err(e) = server.await {
+                log::error!("gRPC server error on {}: {}", addr, e);
+            }
+        }))
+    }
+
+    fn authorize(&self, metadata: &MetadataMap, required: Role) -> Result<(), Status> {
+        self.policy.authorize(metadata_value(metadata, "authorization").as_deref(), required, false).map_err(|denied| {
+            let code = ErrorCode::for_status(denied.status.as_u16());
+            error_status((denied.status, ErrorResponse::new(code, denied.error, denied.message)))
+        })
+    }
+
+    fn attribution(&self, metadata: &MetadataMap) -> Attribution {
+        let identity = ClientIdentity::from_headers(
+            metadata_value(metadata, CLIENT_ID_HEADER),
+            metadata_value(metadata, HOSTNAME_HEADER),
+            metadata_value(metadata, OS_HEADER),
+        );
+        self.policy.attribution(identity, metadata_value(metadata, "authorization").as_deref())
+    }
+}
+
+#[tonic::async_trait]
+impl FontSync for FontSyncService {
+    async fn list(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        self.authorize(request.metadata(), Role::Reader)?;
+        let query = font_query(request.into_inner())?;
+        let list = server::query_fonts(&query, &self.font_dir, &self.metadata, &self.policy).await.map_err(error_status)?;
+        Ok(Response::new(ListResponse {
+            total: list.total.map(|total| total as u64),
+            fonts: list.fonts.into_iter().map(proto::Font::from).collect(),
+        }))
+    }
+
+    async fn upload(&self, request: Request<Streaming<UploadChunk>>) -> Result<Response<UploadResponse>, Status> {
+        self.authorize(request.metadata(), Role::Uploader)?;
+        if let Some(error) = self.policy.maintenance_error() {
+            return Err(error_status(error));
+        }
+        let uploaded_by = self.attribution(request.metadata());
+        let deadline = Instant::now() + self.policy.upload_timeout;
+        // 与 HTTP 上传共用并发名额，排队时间同样计入时限
+        let Ok(Ok(_permit)) =
+            tokio::time::timeout_at(deadline, Arc::clone(&self.policy.upload_slots).acquire_owned()).await
+        else {
+            return Err(error_status(server::upload_timeout_error(&self.policy)));
+        };
+
+        let mut chunks = request.into_inner();
+        let first = match tokio::time::timeout_at(deadline, chunks.message()).await {
+            Ok(Ok(Some(chunk))) => chunk,
+            Ok(Ok(None)) => return Err(invalid_argument("No font file found in upload", "No font file provided")),
+            Ok(Err(status)) => return Err(status),
+            Err(_) => return Err(error_status(server::upload_timeout_error(&self.policy))),
+        };
+        let Some(header) = first.header else {
+            return Err(invalid_argument("Missing upload header", "The first message must carry the upload header"));
+        };
+        let options = upload_options(&header)?;
+        let body: UploadBody = futures::stream::iter([Ok(first.data)])
+            .chain(chunks.map(|chunk| chunk.map(|chunk| chunk.data).map_err(anyhow::Error::from)))
+            .boxed();
+
+        let (_, response, event) = server::receive_font(
+            &header.filename,
+            body,
+            &options,
+            deadline,
+            &self.font_dir,
+            &self.metadata,
+            &self.blobs,
+            &uploaded_by,
+            &self.policy,
+        )
+        .await
+        .map_err(error_status)?;
+        if let Some(event) = event {
+            server::publish_event(&self.event_log, self.ws_server.as_ref(), event, Some(&uploaded_by));
+        }
+        Ok(Response::new(UploadResponse {
+            filename: response.filename,
+            action: response.action,
+            sha256: response.sha256.unwrap_or_default(),
+            size: response.size.unwrap_or_default(),
+            modified: response.modified,
+        }))
+    }
+
+    type DownloadStream = BoxStream<'static, Result<DownloadChunk, Status>>;
+
+    async fn download(&self, request: Request<DownloadRequest>) -> Result<Response<Self::DownloadStream>, Status> {
+        self.authorize(request.metadata(), Role::Reader)?;
+        let filename = sanitize_filename(&request.into_inner().filename);
+        let font_path = self.policy.font_path(&self.font_dir, &filename);
+        let file = match tokio::fs::File::open(&font_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(error_status(server::upload_error(
+                    StatusCode::NOT_FOUND,
+                    "Font not found",
+                    format!("Font '{}' not found", filename),
+                )));
+            }
+            Err(e) => {
+                return Err(error_status(server::upload_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read font",
+                    format!("Failed to open font file: {}", e),
+                )));
+            }
+        };
+        let metadata = file.metadata().await.map_err(|e| Status::internal(e.to_string()))?;
+        let header = DownloadChunk {
+            data: Bytes::new(),
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        };
+        let data = tokio_util::io::ReaderStream::with_capacity(file, CHUNK_SIZE).map(|data| match data {
+            Ok(data) => Ok(DownloadChunk { data, ..Default::default() }),
+            Err(e) => Err(Status::internal(format!("Failed to read font: {}", e))),
+        });
+        Ok(Response::new(futures::stream::iter([Ok(header)]).chain(data).boxed()))
+    }
+
+    type WatchEventsStream = BoxStream<'static, Result<Event, Status>>;
+
+    async fn watch_events(&self, request: Request<WatchRequest>) -> Result<Response<Self::WatchEventsStream>, Status> {
+        self.authorize(request.metadata(), Role::Reader)?;
+        let since = request.into_inner().since;
+        let events = server::follow_events(Arc::clone(&self.event_log), since).filter_map(|(seq, message)| async move {
+            match serde_json::to_string(&message) {
+                Ok(json) => Some(Ok(Event { seq, json })),
+                Err(e) => {
+                    warn!("Failed to encode event {:?}: {}", seq, e);
+                    None
+                }
+            }
+        });
+        Ok(Response::new(events.boxed()))
+    }
+}
+
+fn metadata_value(metadata: &MetadataMap, name: &str) -> Option<String> {
+    metadata.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+// 与 HTTP 状态码对应的 gRPC 状态，详情为与 HTTP 响应相同的 JSON 错误
+fn error_status((status, response): UploadError) -> Status {
+    let code = match status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::PRECONDITION_FAILED => Code::FailedPrecondition,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::INSUFFICIENT_STORAGE => Code::ResourceExhausted,
+        StatusCode::REQUEST_TIMEOUT => Code::DeadlineExceeded,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let message = response.message.clone().unwrap_or_else(|| response.error.clone());
+    Status::with_details(code, message, serde_json::to_vec(&response).unwrap_or_default().into())
+}
+
+fn invalid_argument(error: &str, message: &str) -> Status {
+    error_status(server::upload_error(StatusCode::BAD_REQUEST, error, message.to_string()))
+}
+
+// 枚举按 serde 的名称传递，与 HTTP 接口的写法相同
+fn enum_name<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_value(value).ok()?.as_str().map(str::to_string)
+}
+
+fn parse_enum<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+fn font_query(request: ListRequest) -> Result<FontQuery, Status> {
+    let sort = match request.sort.as_deref() {
+        Some(sort) => {
+            let parsed = parse_enum(sort);
+            Some(parsed.ok_or_else(|| invalid_argument("Invalid query", &format!("Unknown sort field '{}'", sort)))?)
+        }
+        None => None,
+    };
+    Ok(FontQuery {
+        tag: request.tag,
+        q: request.q,
+        format: request.format,
+        min_size: request.min_size,
+        sort,
+        offset: request.offset.map(|offset| offset as usize),
+        limit: request.limit.map(|limit| limit as usize),
+        covers: request.covers,
+    })
+}
+
+fn list_request(query: &FontQuery) -> ListRequest {
+    ListRequest {
+        tag: query.tag.clone(),
+        q: query.q.clone(),
+        format: query.format.clone(),
+        min_size: query.min_size,
+        sort: query.sort.as_ref().and_then(enum_name),
+        offset: query.offset.map(|offset| offset as u64),
+        limit: query.limit.map(|limit| limit as u64),
+        covers: query.covers.clone(),
+    }
+}
+
+fn upload_options(header: &UploadHeader) -> Result<UploadOptions, Status> {
+    let plaintext_sha256 = match header.plaintext_sha256.as_deref().map(str::trim) {
+        Some(value) if server::is_sha256_hex(value) => Some(value.to_lowercase()),
+        Some(_) => {
+            return Err(invalid_argument("Invalid plaintext_sha256", "plaintext_sha256 must be a hex SHA256 digest"));
+        }
+        None => None,
+    };
+    let if_match = match header.if_match.is_empty() {
+        true => None,
+        false => Some(
+            server::parse_if_match(&header.if_match.join(","))
+                .ok_or_else(|| invalid_argument("Invalid If-Match", "If-Match must be * or a list of SHA256 digests"))?,
+        ),
+    };
+    Ok(UploadOptions {
+        plaintext_sha256,
+        readd: header.readd,
+        overwrite: header.overwrite,
+        modified: header.modified,
+        delta_base: None,
+        if_match,
+    })
+}
+
+impl From<FontInfo> for proto::Font {
+    fn from(font: FontInfo) -> Self {
+        Self {
+            status: font.status.as_ref().and_then(enum_name),
+            name: font.name,
+            size: font.size,
+            sha256: font.sha256,
+            mime_type: font.mime_type,
+            modified: font.modified,
+            plaintext_sha256: font.plaintext_sha256,
+            family: font.family,
+            style: font.style,
+            tags: font.tags.into_iter().collect(),
+            restricted: font.restricted,
+            version: font.version,
+        }
+    }
+}
+
+impl From<proto::Font> for FontInfo {
+    fn from(font: proto::Font) -> Self {
+        Self {
+            status: font.status.as_deref().and_then(parse_enum),
+            name: font.name,
+            size: font.size,
+            mime_type: font.mime_type,
+            sha256: font.sha256,
+            modified: font.modified,
+            plaintext_sha256: font.plaintext_sha256,
+            embedding: None,
+            restricted: font.restricted,
+            family: font.family,
+            style: font.style,
+            version: font.version,
+            unicode_ranges: Vec::new(),
+            tags: font.tags.into_iter().collect(),
+            uploaded_by: None,
+        }
+    }
+}
+
+// 服务器在状态详情中附带与 HTTP 相同的 JSON 错误，据此还原为 ServerError；
+// 没有详情的状态（如连接失败）保留原样
+fn status_error(status: Status, context: &str) -> anyhow::Error {
+    match serde_json::from_slice::<ErrorResponse>(status.details()) {
+        Ok(response) => {
+            let retry_after = response.details.as_ref().and_then(|d| d["retry_after"].as_u64()).map(Duration::from_secs);
+            let error = ServerError::from_error_response(response, context);
+            anyhow::Error::new(ServerError { retry_after, ..error })
+        }
+        Err(_) => anyhow::Error::new(status).context(context.to_string()),
+    }
+}
+
+// 同步客户端的 gRPC 连接：首次使用时按 /version 公布的端口连接同一主机，
+// 令牌与客户端标识和 HTTP 请求一样通过元数据发送
+#[derive(Clone)]
+pub struct GrpcClient {
+    server_url: String,
+    authorization: Option<AsciiMetadataValue>,
+    client: Arc<tokio::sync::OnceCell<FontSyncClient<Channel>>>,
+}
+
+impl GrpcClient {
+    pub fn new(server_url: &str) -> Result<Self> {
+        let url = reqwest::Url::parse(server_url).with_context(|| format!("Invalid server URL '{}'", server_url))?;
+        if !matches!(url.scheme(), "http" | "ws") {
+            anyhow::bail!("The gRPC transport needs a plain http:// server URL, not '{}'", server_url);
+        }
+        let authorization = match crate::credentials::token_for(server_url) {
+            Some(token) => {
+                let mut value: AsciiMetadataValue =
+                    format!("Bearer {}", token).parse().context("Stored token is not a valid metadata value")?;
+                value.set_sensitive(true);
+                Some(value)
+            }
+            None => None,
+        };
+        Ok(Self { server_url: server_url.to_string(), authorization, client: Arc::default() })
+    }
+
+    async fn client(&self) -> Result<FontSyncClient<Channel>> {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                let version = ApiClient::new(&self.server_url)?.version().await?;
+                let port = version
+                    .grpc_port
+                    .with_context(|| format!("Server {} does not offer a gRPC endpoint", self.server_url))?;
+                let url = reqwest::Url::parse(&self.server_url)?;
+                let host = url.host_str().context("Server URL has no host")?;
+                let endpoint = format!("http://{}:{}", host, port);
+                let channel = Channel::from_shared(endpoint.clone())?
+                    .connect_timeout(CONNECT_TIMEOUT)
+                    .connect()
+                    .await
+                    .with_context(|| format!("Failed to connect to gRPC endpoint {}", endpoint))?;
+                info!("Using gRPC endpoint {}", endpoint);
+                Ok::<_, anyhow::Error>(FontSyncClient::new(channel))
+            })
+            .await?;
+        Ok(client.clone())
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        let metadata = request.metadata_mut();
+        if let Some(value) = &self.authorization {
+            metadata.insert("authorization", value.clone());
+        }
+        // 非 ASCII 的主机名无法放入元数据，服务器按未知处理
+        for (name, value) in ClientIdentity::current().headers() {
+            if let Ok(value) = value.parse() {
+                metadata.insert(name, value);
+            }
+        }
+        request
+    }
+
+    pub async fn list_fonts(&self, query: &FontQuery) -> Result<FontList> {
+        let mut client = self.client().await?;
+        let response = client
+            .list(self.request(list_request(query)))
+            .await
+            .map_err(|status| status_error(status, "Failed to get font list"))?
+            .into_inner();
+        Ok(FontList {
+            total: response.total.map(|total| total as usize),
+            fonts: response.fonts.into_iter().map(FontInfo::from).collect(),
+        })
+    }
+
+    // 按块发送，每发出一块调用一次 on_progress(已发送, 总大小)
+    pub async fn upload_font(
+        &self,
+        header: UploadHeader,
+        data: Vec<u8>,
+        on_progress: impl Fn(u64, u64) + Send + 'static,
+    ) -> Result<FontActionResponse> {
+        let mut client = self.client().await?;
+        let data = Bytes::from(data);
+        let size = data.len() as u64;
+        let mut chunks: Vec<UploadChunk> =
+            data.chunks(CHUNK_SIZE).map(|chunk| UploadChunk { header: None, data: data.slice_ref(chunk) }).collect();
+        match chunks.first_mut() {
+            Some(first) => first.header = Some(header),
+            None => chunks.push(UploadChunk { header: Some(header), data: Bytes::new() }),
+        }
+        let mut sent = 0;
+        let chunks = futures::stream::iter(chunks).map(move |chunk| {
+            sent += chunk.data.len() as u64;
+            on_progress(sent, size);
+            chunk
+        });
+        let response = client
+            .upload(self.request(chunks))
+            .await
+            .map_err(|status| status_error(status, "Server error"))?
+            .into_inner();
+        Ok(FontActionResponse {
+            success: true,
+            filename: response.filename,
+            action: response.action,
+            sha256: Some(response.sha256),
+            size: Some(response.size),
+            embedding: None,
+            modified: response.modified,
+            message: None,
+        })
+    }
+
+    // 返回文件内容与服务器上的修改时间，每收到一块调用一次 on_progress(已接收, 总大小)
+    pub async fn download_font(&self, filename: &str, on_progress: impl Fn(u64, u64)) -> Result<(Vec<u8>, Option<u64>)> {
+        let context = "Failed to download font";
+        let mut client = self.client().await?;
+        let mut chunks = client
+            .download(self.request(DownloadRequest { filename: filename.to_string() }))
+            .await
+            .map_err(|status| status_error(status, context))?
+            .into_inner();
+        let header = chunks.message().await.map_err(|status| status_error(status, context))?.unwrap_or_default();
+        let mut data = Vec::with_capacity(header.size as usize);
+        data.extend_from_slice(&header.data);
+        while let Some(chunk) = chunks.message().await.map_err(|status| status_error(status, context))? {
+            data.extend_from_slice(&chunk.data);
+            on_progress(data.len() as u64, header.size);
+        }
+        Ok((data, header.modified))
+    }
+
+    #[cfg(test)]
+    async fn watch_events(&self, since: Option<u64>) -> Result<Streaming<Event>> {
+        let mut client = self.client().await?;
+        let response = client
+            .watch_events(self.request(WatchRequest { since }))
+            .await
+            .map_err(|status| status_error(status, "Failed to open event stream"))?;
+        Ok(response.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::FontStatus;
+    use crate::client::{self, Transport};
+    use crate::websocket_server::WebSocketMessage;
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    // 在空闲端口上启动服务器，返回 HTTP 地址
+    async fn start_test_server(font_dir: &std::path::Path, policy: ServerPolicy) -> (String, tokio::task::JoinHandle<Result<()>>) {
+        let port = free_port();
+        let policy = ServerPolicy { grpc_port: Some(free_port()), ..policy };
+        let server = tokio::spawn(server::start_server(
+            vec!["127.0.0.1".to_string()],
+            Vec::new(),
+            port,
+            font_dir.to_string_lossy().to_string(),
+            false,
+            false,
+            policy,
+        ));
+        while tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+            assert!(!server.is_finished(), "server exited early");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        (format!("http://127.0.0.1:{}", port), server)
+    }
+
+    #[test]
+    fn errors_keep_their_json_details() {
+        let status = error_status(server::upload_error(StatusCode::PRECONDITION_FAILED, "Version mismatch", "changed".to_string()));
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "changed");
+        let error = status_error(status, "Server error");
+        assert!(client::is_version_conflict(&error));
+
+        let busy = ServerPolicy::default();
+        *busy.maintenance.write() = Some("backup".to_string());
+        let status = error_status(busy.maintenance_error().expect("maintenance"));
+        assert_eq!(status.code(), Code::Unavailable);
+        let error = status_error(status, "Server error");
+        let error = client::server_error(&error).expect("server error");
+        assert_eq!(error.code, ErrorCode::Maintenance);
+        assert!(error.retryable && error.retry_after.is_some());
+
+        let error = status_error(Status::unavailable("connection refused"), "Failed to get font list");
+        assert!(client::server_error(&error).is_none());
+    }
+
+    #[test]
+    fn queries_and_fonts_round_trip() {
+        let query = FontQuery {
+            tag: Some("cjk".to_string()),
+            sort: Some(crate::utils::FontSort::Size),
+            limit: Some(5),
+            ..Default::default()
+        };
+        let parsed = font_query(list_request(&query)).expect("query");
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(&query).unwrap());
+        let invalid = ListRequest { sort: Some("colour".to_string()), ..Default::default() };
+        assert_eq!(font_query(invalid).unwrap_err().code(), Code::InvalidArgument);
+
+        let font = FontInfo {
+            name: "a.ttf".to_string(),
+            size: 3,
+            mime_type: "font/ttf".to_string(),
+            sha256: String::new(),
+            status: Some(FontStatus::Hashing),
+            modified: Some(1),
+            plaintext_sha256: None,
+            embedding: None,
+            restricted: true,
+            family: Some("A".to_string()),
+            style: Some("Bold".to_string()),
+            version: None,
+            unicode_ranges: Vec::new(),
+            tags: ["x".to_string()].into(),
+            uploaded_by: None,
+        };
+        let restored = FontInfo::from(proto::Font::from(font.clone()));
+        assert_eq!(serde_json::to_value(&restored).unwrap(), serde_json::to_value(&font).unwrap());
+    }
+
+    #[tokio::test]
+    async fn lists_uploads_and_downloads_over_grpc() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("existing.ttf"), b"existing font").unwrap();
+        let (server_url, server) = start_test_server(server_dir.path(), ServerPolicy::default()).await;
+        let api = ApiClient::with_transport(&server_url, Transport::Grpc).expect("api client");
+
+        let listed = api.list_fonts_hashed(&FontQuery::default()).await.expect("list");
+        assert_eq!(listed.fonts.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["existing.ttf"]);
+        assert_eq!(listed.total, Some(1));
+
+        let grpc = GrpcClient::new(&server_url).expect("grpc client");
+        let mut events = grpc.watch_events(None).await.expect("event stream");
+
+        // 超过一块的文件分块上传
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let local = local_dir.path().join("big.ttf");
+        std::fs::write(&local, &data).unwrap();
+        let sha256 = crate::utils::calculate_sha256(&local).unwrap();
+        let uploaded = api.upload_font(&local, "big.ttf", &sha256, None, false).await.expect("upload");
+        assert_eq!((uploaded.action.as_str(), uploaded.sha256.as_deref()), ("added", Some(sha256.as_str())));
+        assert_eq!(std::fs::read(server_dir.path().join("big.ttf")).unwrap(), data);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.message()).await.expect("event").unwrap().unwrap();
+        assert!(event.seq.is_some());
+        let message: WebSocketMessage = serde_json::from_str(&event.json).expect("event json");
+        assert!(matches!(message, WebSocketMessage::FontAdded { .. }));
+
+        let (fetched, modified) = api.fetch_font("big.ttf").await.expect("fetch");
+        assert_eq!(fetched.as_ref(), data.as_slice());
+        assert!(modified.is_some());
+        let output = local_dir.path().join("copy.ttf");
+        api.download_font("big.ttf", &output, &sha256).await.expect("download");
+        assert_eq!(std::fs::read(&output).unwrap(), data);
+
+        // 错误与 HTTP 接口相同，冲突仍按冲突处理
+        std::fs::write(&local, b"another version").unwrap();
+        let other = crate::utils::calculate_sha256(&local).unwrap();
+        let conflict = api.upload_font(&local, "big.ttf", &other, None, false).await.expect_err("conflict");
+        assert!(client::is_version_conflict(&conflict));
+        let missing = api.fetch_font("missing.ttf").await.expect_err("missing");
+        assert_eq!(client::server_error(&missing).map(|e| e.code), Some(ErrorCode::NotFound));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn grpc_requests_need_a_token() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let tokens_file = server_dir.path().join("tokens.json");
+        std::fs::write(&tokens_file, r#"[{"token": "reader-token", "role": "reader"}]"#).unwrap();
+        let policy = ServerPolicy {
+            tokens: Arc::new(crate::access::AccessTokens::load(&tokens_file).expect("tokens")),
+            ..Default::default()
+        };
+        let (server_url, server) = start_test_server(server_dir.path(), policy).await;
+
+        let anonymous = GrpcClient::new(&server_url).expect("grpc client");
+        let error = anonymous.list_fonts(&FontQuery::default()).await.expect_err("anonymous list");
+        assert_eq!(client::server_error(&error).map(|e| e.code), Some(ErrorCode::Unauthorized));
+
+        let reader = GrpcClient {
+            authorization: Some("Bearer reader-token".parse().unwrap()),
+            ..GrpcClient::new(&server_url).expect("grpc client")
+        };
+        assert!(reader.list_fonts(&FontQuery::default()).await.expect("list").fonts.is_empty());
+        let header = UploadHeader { filename: "a.ttf".to_string(), ..Default::default() };
+        let error = reader.upload_font(header, b"font".to_vec(), |_, _| {}).await.expect_err("reader upload");
+        assert_eq!(client::server_error(&error).map(|e| e.code), Some(ErrorCode::Forbidden));
+
+        server.abort();
+    }
+
+    #[test]
+    fn needs_a_plain_http_server_url() {
+        assert!(GrpcClient::new("https://fonts.example.com").is_err());
+        assert!(GrpcClient::new("unix:///run/fontsync.sock").is_err());
+        assert!(GrpcClient::new("http://127.0.0.1:8080").is_ok());
+    }
+}
@@ -1,25 +1,40 @@
 #[cfg(feature = "gui")]
 use fltk::{
     app,
-    button::Button,
-    enums::{Align, Color, Event, Font, FrameType},
+    browser::HoldBrowser,
+    button::{Button, CheckButton, RadioRoundButton},
+    dialog::{NativeFileChooser, NativeFileChooserAction, NativeFileChooserOptions, NativeFileChooserType},
+    enums::{Align, CallbackTrigger, Color, Event, Font, FrameType},
     frame::Frame,
-    group::{Group, Pack, PackType},
+    group::{Group, Pack, PackType, Wizard},
     image::PngImage,
-    input::{Input, IntInput},
+    input::{Input, IntInput, MultilineInput},
+    menu::Choice,
     prelude::*,
     text::{TextBuffer, TextDisplay},
     window::Window,
 };
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::runtime::Runtime;
+use tokio_util::sync::CancellationToken;
 #[cfg(feature = "tray")]
 use tray_item::{IconSource, TrayItem};
 
 const LOGO_PNG: &[u8] = include_bytes!("../logo.png");
 const TRAY_ICON_SIZE: i32 = 32;
+// 日志面板保留的记录数
+const LOG_CAPACITY: usize = 1000;
+// 托盘状态的刷新间隔（秒）
+const TRAY_REFRESH_SECS: f64 = 5.0;
+// 设置向导写入的配置文件，位于客户端状态目录
+const SETUP_FILE: &str = "gui.json";
+// 局域网发现等待服务器应答的时间
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(1500);
 
 fn load_logo_png() -> Option<PngImage> {
     PngImage::from_data(LOGO_PNG).ok()
@@ -200,6 +215,12 @@ struct AppState {
     sync_in_progress: Arc<Mutex<bool>>,
     server_url: Arc<Mutex<String>>,
     status_message: Arc<Mutex<String>>,
+    // 实时同步运行时用于停止它的令牌
+    live_sync: Arc<Mutex<Option<CancellationToken>>>,
+    // 本机服务器的地址与字体目录，未启动时为 None
+    local_server: Arc<Mutex<Option<(String, PathBuf)>>>,
+    // 本机服务器上连接的客户端数，由托盘状态刷新时查询
+    connected_clients: Arc<Mutex<Option<usize>>>,
 }
 
 impl AppState {
@@ -210,6 +231,9 @@ impl AppState {
             sync_in_progress: Arc::new(Mutex::new(false)),
             server_url: Arc::new(Mutex::new("http://localhost:8080".to_string())),
             status_message: Arc::new(Mutex::new("Ready".to_string())),
+            live_sync: Arc::new(Mutex::new(None)),
+            local_server: Arc::new(Mutex::new(None)),
+            connected_clients: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -219,16 +243,147 @@ enum TrayEvent {
     Show,
     Hide,
     Quit,
+    SyncNow,
+    ToggleMonitoring,
+    OpenFontFolder,
+    // 定时刷新托盘中的状态
+    Refresh,
+}
+
+// 托盘中显示的状态摘要
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrayStatus {
+    server_running: bool,
+    // 本机服务器上通过 WebSocket 连接的客户端数，尚未查询到时为 None
+    clients: Option<usize>,
+    last_sync: Option<chrono::DateTime<chrono::Local>>,
+    // 最近一次同步中被跳过、仍未解决的冲突
+    conflicts: usize,
+    monitoring: bool,
+}
+
+impl TrayStatus {
+    // 取同步历史中该服务器最近一次同步的时间与其中跳过的冲突
+    fn with_history(mut self, runs: &[crate::sync_history::SyncRun], server_url: &str) -> Self {
+        let server_url = server_url.trim_end_matches('/');
+        if let Some(run) = runs.iter().rev().find(|run| run.server_url == server_url) {
+            self.last_sync = chrono::DateTime::from_timestamp(run.finished_at as i64, 0)
+                .map(|time| time.with_timezone(&chrono::Local))
+                .max(self.last_sync);
+            self.conflicts = run
+                .conflicts
+                .iter()
+                .filter(|c| c.resolution == crate::utils::ConflictResolution::Skip)
+                .count();
+        }
+        self
+    }
+
+    fn server_line(&self) -> String {
+        match (self.server_running, self.clients) {
+            (false, _) => "Server: stopped".to_string(),
+            (true, None) => "Server: running".to_string(),
+            (true, Some(1)) => "Server: running, 1 client connected".to_string(),
+            (true, Some(n)) => format!("Server: running, {} clients connected", n),
+        }
+    }
+
+    fn last_sync_line(&self) -> String {
+        match self.last_sync {
+            Some(time) => format!("Last sync: {}", time.format("%Y-%m-%d %H:%M")),
+            None => "Last sync: never".to_string(),
+        }
+    }
+
+    fn conflicts_line(&self) -> String {
+        format!("Pending conflicts: {}", self.conflicts)
+    }
+
+    fn monitoring_action(&self) -> &'static str {
+        if self.monitoring { "Pause monitoring" } else { "Resume monitoring" }
+    }
+
+    #[cfg(all(feature = "tray", target_os = "windows"))]
+    fn tooltip(&self) -> String {
+        format!("FontSync\n{}\n{}\n{}", self.server_line(), self.last_sync_line(), self.conflicts_line())
+    }
+}
+
+// 托盘菜单中文字随状态更新的菜单项
+#[cfg(all(feature = "tray", any(target_os = "linux", target_os = "windows")))]
+#[derive(Default)]
+struct TrayMenuIds {
+    server: Option<u32>,
+    last_sync: Option<u32>,
+    conflicts: Option<u32>,
+    monitor_event: Option<u32>,
+    monitor_action: Option<u32>,
 }
 
 #[cfg(feature = "tray")]
 struct TrayHandle {
     tray: Option<TrayItem>,
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    ids: TrayMenuIds,
 }
 
 #[cfg(not(feature = "tray"))]
 struct TrayHandle;
 
+// 托盘菜单项与提示文字的最大长度（Windows 提示最多 127 个字符）
+#[cfg(feature = "tray")]
+const TRAY_STATUS_MAX_CHARS: usize = 120;
+
+impl TrayHandle {
+    // 在托盘菜单中显示实时同步的最新事件
+    #[cfg(feature = "tray")]
+    fn set_status(&mut self, status: &str) {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Some(tray) = self.tray.as_mut() {
+            set_menu_label(tray, self.ids.monitor_event, status);
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let _ = status;
+    }
+
+    // 更新托盘菜单中的状态行、暂停/恢复监控菜单项与提示文字
+    #[cfg(feature = "tray")]
+    fn set_summary(&mut self, status: &TrayStatus) {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Some(tray) = self.tray.as_mut() {
+            set_menu_label(tray, self.ids.server, &status.server_line());
+            set_menu_label(tray, self.ids.last_sync, &status.last_sync_line());
+            set_menu_label(tray, self.ids.conflicts, &status.conflicts_line());
+            set_menu_label(tray, self.ids.monitor_action, status.monitoring_action());
+            #[cfg(target_os = "windows")]
+            let _ = tray.inner_mut().set_tooltip(&truncate_chars(&status.tooltip(), TRAY_STATUS_MAX_CHARS));
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let _ = status;
+    }
+
+    #[cfg(not(feature = "tray"))]
+    fn set_status(&mut self, _status: &str) {}
+
+    #[cfg(not(feature = "tray"))]
+    fn set_summary(&mut self, _status: &TrayStatus) {}
+}
+
+#[cfg(all(feature = "tray", any(target_os = "linux", target_os = "windows")))]
+fn set_menu_label(tray: &mut TrayItem, id: Option<u32>, label: &str) {
+    if let Some(id) = id {
+        let _ = tray.inner_mut().set_menu_item_label(&truncate_chars(label, TRAY_STATUS_MAX_CHARS), id);
+    }
+}
+
+#[cfg(feature = "tray")]
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
 #[cfg(feature = "tray")]
 fn init_tray() -> (app::Sender<TrayEvent>, app::Receiver<TrayEvent>, bool, TrayHandle) {
     let (tray_sender, tray_receiver) = app::channel::<TrayEvent>();
@@ -247,6 +402,39 @@ fn init_tray() -> (app::Sender<TrayEvent>, app::Receiver<TrayEvent>, bool, TrayH
     };
     let tray_enabled = tray.is_some();
 
+    // 状态行只用于显示，点击不做任何事
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    let mut ids = TrayMenuIds::default();
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    if let Some(tray) = tray.as_mut() {
+        let status = TrayStatus::default();
+        let inner = tray.inner_mut();
+        ids.server = inner.add_menu_item_with_id(&status.server_line(), || {}).ok();
+        ids.last_sync = inner.add_menu_item_with_id(&status.last_sync_line(), || {}).ok();
+        ids.conflicts = inner.add_menu_item_with_id(&status.conflicts_line(), || {}).ok();
+        ids.monitor_event = inner.add_menu_item_with_id("Real-time sync off", || {}).ok();
+        let _ = inner.add_separator();
+        let sender = tray_sender;
+        let _ = inner.add_menu_item("Sync now", move || sender.send(TrayEvent::SyncNow));
+        let sender = tray_sender;
+        ids.monitor_action = inner
+            .add_menu_item_with_id(status.monitoring_action(), move || sender.send(TrayEvent::ToggleMonitoring))
+            .ok();
+        let sender = tray_sender;
+        let _ = inner.add_menu_item("Open font folder", move || sender.send(TrayEvent::OpenFontFolder));
+        let _ = inner.add_separator();
+    }
+    // 其他平台的菜单项文字无法更新，只提供快捷操作
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    if let Some(tray) = tray.as_mut() {
+        let sender = tray_sender;
+        let _ = tray.add_menu_item("Sync now", move || sender.send(TrayEvent::SyncNow));
+        let sender = tray_sender;
+        let _ = tray.add_menu_item("Pause or resume monitoring", move || sender.send(TrayEvent::ToggleMonitoring));
+        let sender = tray_sender;
+        let _ = tray.add_menu_item("Open font folder", move || sender.send(TrayEvent::OpenFontFolder));
+    }
+
     if let Some(tray) = tray.as_mut() {
         let sender = tray_sender;
         let _ = tray.add_menu_item("Show", move || sender.send(TrayEvent::Show));
@@ -256,7 +444,16 @@ fn init_tray() -> (app::Sender<TrayEvent>, app::Receiver<TrayEvent>, bool, TrayH
         let _ = tray.add_menu_item("Quit", move || sender.send(TrayEvent::Quit));
     }
 
-    (tray_sender, tray_receiver, tray_enabled, TrayHandle { tray })
+    (
+        tray_sender,
+        tray_receiver,
+        tray_enabled,
+        TrayHandle {
+            tray,
+            #[cfg(any(target_os = "linux", target_os = "windows"))]
+            ids,
+        },
+    )
 }
 
 #[cfg(not(feature = "tray"))]
@@ -307,7 +504,7 @@ pub fn run_gui() -> Result<()> {
     server_divider.set_color(Color::from_rgb(200, 200, 200));
     
     let mut server_pack = Pack::default()
-        .with_size(0, 140);
+        .with_size(0, 154);
     server_pack.set_type(PackType::Vertical);
     server_pack.set_spacing(6);
     
@@ -351,7 +548,11 @@ pub fn run_gui() -> Result<()> {
         .with_size(360, 28);
     server_font_dir_input.set_text_size(13);
     server_font_dir_input.set_value("./fonts");
+    let server_dir_chooser = directory_chooser_button("选择字体目录");
     server_row3.end();
+    let server_dir_error = path_error_frame();
+    watch_directory_input(&mut server_font_dir_input, server_dir_chooser, &server_dir_error, true);
+    let mut server_dir_error_for_start = server_dir_error.clone();
     
     let mut server_button_pack = Pack::default().with_size(0, 30);
     server_button_pack.set_type(PackType::Horizontal);
@@ -451,32 +652,68 @@ pub fn run_gui() -> Result<()> {
     sync_once_btn.set_label_color(Color::from_rgb(49, 99, 239));
     sync_once_btn.set_frame(FrameType::BorderBox);
 
+    let mut live_sync_btn = CheckButton::default()
+        .with_size(96, 28)
+        .with_label("实时同步");
+    live_sync_btn.set_label_size(13);
+    live_sync_btn.set_tooltip("监控本机字体目录，变更后自动上传，并接收服务器上的新字体");
+
+    client_button_pack.end();
+    let client_path_error = path_error_frame();
+    let mut client_path_error_for_sync = client_path_error.clone();
+
     let mut disconnect_client_btn_for_connect = disconnect_client_btn.clone();
     let mut sync_once_btn_for_connect = sync_once_btn.clone();
     let mut sync_once_btn_for_disconnect = sync_once_btn.clone();
+    let mut sync_once_btn_for_tray = sync_once_btn.clone();
     let client_host_input_for_connect = client_host_input.clone();
     let client_port_input_for_connect = client_port_input.clone();
     let client_host_input_for_sync = client_host_input.clone();
     let client_port_input_for_sync = client_port_input.clone();
     
-    client_button_pack.end();
     client_pack.end();
     
+    // 日志标题与筛选、搜索、导出工具栏
+    let mut status_toolbar = Pack::default().with_size(0, 26);
+    status_toolbar.set_type(PackType::Horizontal);
+    status_toolbar.set_spacing(12);
     let mut status_title = Frame::default()
-        .with_size(0, 20)
+        .with_size(60, 26)
         .with_label("日志");
     status_title.set_label_size(14);
     status_title.set_label_font(Font::HelveticaBold);
     status_title.set_label_color(Color::from_rgb(40, 40, 40));
     status_title.set_align(Align::Left | Align::Inside);
+    let mut log_level_choice = Choice::default()
+        .with_size(110, 26);
+    log_level_choice.add_choice("全部|警告及以上|仅错误");
+    log_level_choice.set_value(0);
+    log_level_choice.set_text_size(12);
+    let mut log_search_input = Input::default()
+        .with_size(260, 26);
+    log_search_input.set_text_size(12);
+    log_search_input.set_tooltip("搜索日志");
+    log_search_input.set_trigger(CallbackTrigger::Changed);
+    let mut log_autoscroll = CheckButton::default()
+        .with_size(90, 26)
+        .with_label("自动滚动");
+    log_autoscroll.set_label_size(12);
+    log_autoscroll.set_checked(true);
+    let mut save_log_btn = Button::default()
+        .with_size(96, 26)
+        .with_label("保存日志…");
+    save_log_btn.set_color(Color::from_rgb(255, 255, 255));
+    save_log_btn.set_label_color(Color::from_rgb(49, 99, 239));
+    save_log_btn.set_frame(FrameType::BorderBox);
+    status_toolbar.end();
 
     let mut status_group = Group::default()
-        .with_size(780, 170);
+        .with_size(780, 160);
     status_group.set_frame(FrameType::EngravedBox);
     
     let mut status_text = TextDisplay::default()
         .with_pos(10, 12)
-        .with_size(760, 146);
+        .with_size(760, 136);
     status_text.set_text_font(Font::Courier);
     status_text.set_text_size(11);
     status_text.set_scrollbar_size(15);
@@ -489,7 +726,23 @@ pub fn run_gui() -> Result<()> {
     wind.end();
     wind.show();
 
-    let (tray_sender, tray_receiver, tray_enabled, _tray_handle) = init_tray();
+    // 首次启动时运行设置向导，跳过时按默认设置写入，之后不再询问
+    let setup_path = SetupConfig::path();
+    let mut setup_error = None;
+    let setup = SetupConfig::load_from(&setup_path).unwrap_or_else(|| {
+        let setup = run_setup_wizard(&runtime).unwrap_or_default();
+        if let Err(e) = setup.save_to(&setup_path) {
+            setup_error = Some(format!("{:#}", e));
+        }
+        setup
+    });
+    server_host_input.set_value(&setup.server_host);
+    server_port_input.set_value(&setup.server_port.to_string());
+    server_font_dir_input.set_value(&setup.font_dir.to_string_lossy());
+    client_host_input.set_value(&setup.client_host);
+    client_port_input.set_value(&setup.client_port.to_string());
+
+    let (tray_sender, tray_receiver, tray_enabled, mut tray_handle) = init_tray();
 
     let tray_sender_for_close = tray_sender;
     wind.set_callback(move |w| {
@@ -508,28 +761,72 @@ pub fn run_gui() -> Result<()> {
     let status_buffer = TextBuffer::default();
     status_text.set_buffer(status_buffer.clone());
     
-    // 更新状态的辅助函数
-    let update_status = {
+    // 日志记录保存在环形缓冲区中，面板按当前的级别与搜索条件重新显示
+    let log = Arc::new(Mutex::new(LogBuffer::new(LOG_CAPACITY)));
+    let refresh_log = {
+        let log = log.clone();
         let status_buffer = status_buffer.clone();
-        move |message: &str| {
-            let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-            let log_message = format!("[{}] {}\n", timestamp, message);
-            
+        let status_text = status_text.clone();
+        let log_level_choice = log_level_choice.clone();
+        let log_search_input = log_search_input.clone();
+        let log_autoscroll = log_autoscroll.clone();
+        move || {
+            let level = LogLevel::from_choice(log_level_choice.value());
+            let text = log.lock().unwrap().render(level, &log_search_input.value());
             let mut buffer = status_buffer.clone();
-            let current_text = buffer.text();
-            let new_text = format!("{}{}", current_text, log_message);
-            
-            // 限制日志大小，避免内存问题
-            let lines: Vec<&str> = new_text.lines().collect();
-            let trimmed_text = if lines.len() > 1000 {
-                lines[lines.len() - 1000..].join("\n")
-            } else {
-                new_text
-            };
-            
-            buffer.set_text(&trimmed_text);
+            buffer.set_text(&text);
+            if log_autoscroll.is_checked() {
+                let mut display = status_text.clone();
+                let lines = display.count_lines(0, buffer.length(), true);
+                display.scroll(lines, 0);
+            }
         }
     };
+    log_level_choice.set_callback({
+        let refresh_log = refresh_log.clone();
+        move |_| refresh_log()
+    });
+    log_search_input.set_callback({
+        let refresh_log = refresh_log.clone();
+        move |_| refresh_log()
+    });
+    log_autoscroll.set_callback({
+        let refresh_log = refresh_log.clone();
+        move |_| refresh_log()
+    });
+
+    // 更新状态的辅助函数
+    let update_status = {
+        let log = log.clone();
+        move |level: LogLevel, message: &str| {
+            log.lock().unwrap().push(level, message);
+            refresh_log();
+        }
+    };
+
+    if let Some(e) = setup_error {
+        update_status(LogLevel::Error, &format!("Failed to save setup to {}: {}", setup_path.display(), e));
+    }
+
+    // 保存全部日志记录，不受筛选条件影响
+    save_log_btn.set_callback({
+        let log = log.clone();
+        let update_status = update_status.clone();
+        move |_| {
+            let mut dialog = NativeFileChooser::new(NativeFileChooserType::BrowseSaveFile);
+            dialog.set_title("保存日志");
+            dialog.set_option(NativeFileChooserOptions::SaveAsConfirm);
+            dialog.set_preset_file(&format!("fontsync-{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+            if let Ok(NativeFileChooserAction::Success) = dialog.try_show() {
+                let path = dialog.filename();
+                let saved = log.lock().unwrap().save(&path);
+                match saved {
+                    Ok(()) => update_status(LogLevel::Info, &format!("Log saved to {}", path.display())),
+                    Err(e) => update_status(LogLevel::Error, &format!("Failed to save log to {}: {}", path.display(), e)),
+                }
+            }
+        }
+    });
     
     // 服务端按钮处理
     let state_clone = state.clone();
@@ -541,15 +838,25 @@ pub fn run_gui() -> Result<()> {
         let runtime = runtime_clone.clone();
         let update_status = update_status_for_start.clone();
 
+        // 字体目录不存在时由服务器创建
+        let font_dir = match validate_directory(&server_font_dir_input.value(), true) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(message) => {
+                show_path_error(&mut server_dir_error_for_start, Some(&message));
+                return;
+            }
+        };
+        show_path_error(&mut server_dir_error_for_start, None);
+
         btn.deactivate();
         stop_server_btn_for_start.activate();
 
         let host = server_host_input.value();
         let port: u16 = server_port_input.value().parse().unwrap_or(8080);
-        let font_dir = server_font_dir_input.value();
         
-        update_status(&format!("Starting server on {}:{} with font directory: {}", host, port, font_dir));
+        update_status(LogLevel::Info, &format!("Starting server on {}:{} with font directory: {}", host, port, font_dir));
         *state.server_running.lock().unwrap() = true;
+        *state.local_server.lock().unwrap() = Some((local_server_url(&host, port), PathBuf::from(&font_dir)));
 
         std::thread::spawn(move || {
             if let Err(e) = runtime.block_on(start_server_internal(host, port, font_dir)) {
@@ -569,7 +876,9 @@ pub fn run_gui() -> Result<()> {
         start_server_btn.activate();
         
         *state.server_running.lock().unwrap() = false;
-        update_status("Server stopped");
+        state.local_server.lock().unwrap().take();
+        *state.connected_clients.lock().unwrap() = None;
+        update_status(LogLevel::Info, "Server stopped");
     });
     
     // 客户端按钮处理
@@ -595,16 +904,16 @@ pub fn run_gui() -> Result<()> {
         let port: u16 = client_port_input_for_connect.value().parse().unwrap_or(8080);
         let server_url = format!("http://{}:{}", host.trim(), port);
         *state.server_url.lock().unwrap() = server_url.clone();
-        update_status(&format!("Connecting to server: {}", server_url));
+        update_status(LogLevel::Info, &format!("Connecting to server: {}", server_url));
 
         match runtime.block_on(connect_client_internal(server_url)) {
             Ok(_) => {
                 *state.client_connected.lock().unwrap() = true;
-                update_status("Client connected successfully");
+                update_status(LogLevel::Info, "Client connected successfully");
             }
             Err(e) => {
                 *state.client_connected.lock().unwrap() = false;
-                update_status(&format!("Failed to connect client: {}", e));
+                update_status(LogLevel::Error, &format!("Failed to connect client: {}", e));
                 btn.activate();
                 disconnect_client_btn_for_connect.deactivate();
                 sync_once_btn_for_connect.activate();
@@ -623,7 +932,7 @@ pub fn run_gui() -> Result<()> {
         sync_once_btn_for_disconnect.activate();
         
         *state.client_connected.lock().unwrap() = false;
-        update_status("Client disconnected");
+        update_status(LogLevel::Info, "Client disconnected");
     });
     
     let state_clone = state.clone();
@@ -634,6 +943,14 @@ pub fn run_gui() -> Result<()> {
         let state = state_clone.clone();
         let runtime = runtime_clone.clone();
         let update_status = update_status_for_sync.clone();
+
+        // 下载的字体先保存在缓存目录中
+        let download_dir = crate::download_cache::download_dir();
+        if let Err(message) = validate_directory(&download_dir.to_string_lossy(), true) {
+            show_path_error(&mut client_path_error_for_sync, Some(&message));
+            return;
+        }
+        show_path_error(&mut client_path_error_for_sync, None);
         
         let host_value = client_host_input_for_sync.value();
         let host = if host_value.trim().is_empty() {
@@ -644,18 +961,130 @@ pub fn run_gui() -> Result<()> {
         let port: u16 = client_port_input_for_sync.value().parse().unwrap_or(8080);
         let server_url = format!("http://{}:{}", host.trim(), port);
         *state.server_url.lock().unwrap() = server_url.clone();
-        update_status(&format!("Performing one-time sync with server: {}", server_url));
+        update_status(LogLevel::Info, &format!("Performing one-time sync with server: {}", server_url));
 
-        match runtime.block_on(perform_one_time_sync(server_url)) {
-            Ok((uploaded, downloaded)) => {
-                update_status(&format!("One-time sync completed: {} uploaded, {} downloaded", uploaded, downloaded));
+        let (progress_sender, progress_events) = std::sync::mpsc::channel();
+        let progress = crate::progress::Progress::channel(progress_sender);
+        match runtime.block_on(perform_one_time_sync(server_url, progress)) {
+            Ok(result) => {
+                update_status(LogLevel::Info, &format!(
+                    "One-time sync completed: {} uploaded, {} downloaded, {} pinned",
+                    result.uploaded, result.downloaded, result.pinned
+                ));
+                for font in &result.installed {
+                    update_status(LogLevel::Info, &format!("Installed {} ({})", font.label(), font.filename));
+                }
             }
             Err(e) => {
-                update_status(&format!("One-time sync failed: {}", e));
+                update_status(LogLevel::Error, &format!("One-time sync failed: {}", e));
+            }
+        }
+        // 逐个列出处理失败的文件与服务器繁忙导致的暂停
+        for event in progress_events.try_iter() {
+            match event {
+                crate::progress::ProgressEvent::FileFinished { name, action: crate::sync_report::FileAction::Failed, .. } => {
+                    update_status(LogLevel::Warn, &format!("Failed to sync {}", name));
+                }
+                crate::progress::ProgressEvent::ServerBusy { name, retry_in, .. } => {
+                    update_status(LogLevel::Warn, &format!("Server busy while syncing {}, retried after {}s", name, retry_in));
+                }
+                _ => {}
             }
         }
     });
     
+    // 实时同步：开启时在后台运行监控，事件经通道送回界面线程
+    let (monitor_sender, monitor_receiver) = app::channel::<crate::monitor::MonitorEvent>();
+    live_sync_btn.set_callback({
+        let state = state.clone();
+        let runtime = runtime.clone();
+        let update_status = update_status.clone();
+        let client_host_input = client_host_input.clone();
+        let client_port_input = client_port_input.clone();
+        let mut client_path_error = client_path_error.clone();
+        let watch_dirs = setup.watch_dirs.clone();
+        move |btn| {
+            if !btn.is_checked() {
+                if let Some(token) = state.live_sync.lock().unwrap().take() {
+                    update_status(LogLevel::Info, "Stopping real-time sync");
+                    token.cancel();
+                }
+                return;
+            }
+
+            let download_dir = crate::download_cache::download_dir();
+            if let Err(message) = validate_directory(&download_dir.to_string_lossy(), true) {
+                show_path_error(&mut client_path_error, Some(&message));
+                btn.set_checked(false);
+                return;
+            }
+            show_path_error(&mut client_path_error, None);
+
+            let host_value = client_host_input.value();
+            let host = if host_value.trim().is_empty() {
+                "127.0.0.1".to_string()
+            } else {
+                host_value
+            };
+            let port: u16 = client_port_input.value().parse().unwrap_or(8080);
+            let server_url = format!("http://{}:{}", host.trim(), port);
+            *state.server_url.lock().unwrap() = server_url.clone();
+            update_status(LogLevel::Info, &format!("Starting real-time sync with server: {}", server_url));
+
+            // 指向该服务器的第一个同步映射决定监控的目录、方向与冲突策略
+            let mappings = match crate::mappings::load() {
+                Ok(mappings) => mappings,
+                Err(e) => {
+                    update_status(LogLevel::Warn, &format!("Ignoring sync mappings: {:#}", e));
+                    Vec::new()
+                }
+            };
+            let mapping = crate::mappings::for_server(&mappings, &server_url).next().cloned();
+            if let Some(mapping) = &mapping {
+                update_status(LogLevel::Info, &format!("Using sync mapping '{}' ({})", mapping.name, mapping.local_path().display()));
+            }
+
+            let token = CancellationToken::new();
+            *state.live_sync.lock().unwrap() = Some(token.clone());
+            let options = crate::client::SyncOptions::default();
+            let config = match mapping {
+                Some(mapping) => crate::monitor::MonitorConfig {
+                    server_url,
+                    watch_paths: vec![mapping.local_path()],
+                    client_id: crate::identity::ClientIdentity::current().client_id.clone(),
+                    options: mapping.sync_options(&options),
+                    cache_size: crate::font_cache::DEFAULT_CAPACITY,
+                    direction: mapping.direction,
+                },
+                None => crate::monitor::MonitorConfig {
+                    server_url,
+                    // 设置向导中选择的目录，未选择时使用系统字体目录
+                    watch_paths: if watch_dirs.is_empty() { get_system_font_directories() } else { watch_dirs.clone() },
+                    client_id: crate::identity::ClientIdentity::current().client_id.clone(),
+                    options,
+                    cache_size: crate::font_cache::DEFAULT_CAPACITY,
+                    direction: Default::default(),
+                },
+            };
+            runtime.spawn(async move {
+                let result = crate::monitor::run(config, token, move |event| monitor_sender.send(event)).await;
+                // 启动失败时监控不会自行发出停止事件
+                if let Err(e) = result {
+                    monitor_sender.send(crate::monitor::MonitorEvent::Failed(format!("{:#}", e)));
+                    monitor_sender.send(crate::monitor::MonitorEvent::Stopped);
+                }
+            });
+        }
+    });
+
+    // 定时刷新托盘中的状态
+    app::add_timeout3(0.0, move |handle| {
+        tray_sender.send(TrayEvent::Refresh);
+        app::repeat_timeout3(TRAY_REFRESH_SECS, handle);
+    });
+    // 实时同步最近一次成功提交变更的时间
+    let mut last_live_sync = None;
+
     // 定时器用于周期更新
     app::add_timeout3(1.0, {
         let state = state.clone();
@@ -678,8 +1107,70 @@ pub fn run_gui() -> Result<()> {
     });
     
     while app.wait() {
+        while let Some(event) = monitor_receiver.recv() {
+            let level = if event.is_warning() { LogLevel::Warn } else { LogLevel::Info };
+            update_status(level, &event.to_string());
+            tray_handle.set_status(&event.to_string());
+            if let crate::monitor::MonitorEvent::Flushed { applied, .. } = event
+                && applied > 0
+            {
+                last_live_sync = Some(chrono::Local::now());
+            }
+            if event == crate::monitor::MonitorEvent::Stopped {
+                state.live_sync.lock().unwrap().take();
+                live_sync_btn.set_checked(false);
+                tray_sender.send(TrayEvent::Refresh);
+            }
+        }
         if let Some(event) = tray_receiver.recv() {
             match event {
+                TrayEvent::SyncNow => {
+                    if sync_once_btn_for_tray.active() {
+                        sync_once_btn_for_tray.do_callback();
+                    }
+                    tray_sender.send(TrayEvent::Refresh);
+                }
+                TrayEvent::ToggleMonitoring => {
+                    live_sync_btn.set_checked(!live_sync_btn.is_checked());
+                    live_sync_btn.do_callback();
+                    tray_sender.send(TrayEvent::Refresh);
+                }
+                TrayEvent::OpenFontFolder => {
+                    // 本机服务器运行时打开其字体目录，否则打开本机的字体目录
+                    let folder = state.local_server.lock().unwrap().as_ref().map(|(_, dir)| dir.clone()).or_else(|| {
+                        crate::utils::get_system_font_directories().into_iter().find(|dir| dir.is_dir())
+                    });
+                    match folder {
+                        Some(folder) => {
+                            if let Err(e) = open_folder(&folder) {
+                                update_status(LogLevel::Error, &format!("Failed to open {}: {}", folder.display(), e));
+                            }
+                        }
+                        None => update_status(LogLevel::Warn, "No font folder to open"),
+                    }
+                }
+                TrayEvent::Refresh => {
+                    let status = TrayStatus {
+                        server_running: *state.server_running.lock().unwrap(),
+                        clients: *state.connected_clients.lock().unwrap(),
+                        last_sync: last_live_sync,
+                        conflicts: 0,
+                        monitoring: state.live_sync.lock().unwrap().is_some(),
+                    }
+                    .with_history(&crate::sync_history::load(), &state.server_url.lock().unwrap());
+                    tray_handle.set_summary(&status);
+                    // 客户端数在后台查询，下次刷新时显示
+                    if let Some((url, _)) = state.local_server.lock().unwrap().clone() {
+                        let connected_clients = state.connected_clients.clone();
+                        runtime.spawn(async move {
+                            let clients = match crate::client::ApiClient::new(&url) {
+                                Ok(api) => api.clients().await.ok().map(|list| list.clients.len()),
+                                Err(_) => None,
+                            };
+                            *connected_clients.lock().unwrap() = clients;
+                        });
+                    }
+                }
                 TrayEvent::Show => {
                     wind.show();
                     wind.redraw();
@@ -694,51 +1185,713 @@ pub fn run_gui() -> Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    // 级别下拉框的选项依次为全部、警告及以上、仅错误
+    fn from_choice(index: i32) -> Self {
+        match index {
+            1 => LogLevel::Warn,
+            2 => LogLevel::Error,
+            _ => LogLevel::Info,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+struct LogEntry {
+    time: chrono::DateTime<chrono::Local>,
+    level: LogLevel,
+    message: String,
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {:<5} {}", self.time.format("%Y-%m-%d %H:%M:%S"), self.level.label(), self.message)
+    }
+}
+
+// 日志面板的环形缓冲区，超过容量时丢弃最早的记录
+struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    fn push(&mut self, level: LogLevel, message: &str) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry { time: chrono::Local::now(), level, message: message.to_string() });
+    }
+
+    // 不低于 level 且包含搜索词（不区分大小写）的记录，每条一行
+    fn render(&self, level: LogLevel, query: &str) -> String {
+        let query = query.trim().to_lowercase();
+        self.entries
+            .iter()
+            .filter(|entry| entry.level >= level)
+            .filter(|entry| query.is_empty() || entry.message.to_lowercase().contains(&query))
+            .map(|entry| format!("{}\n", entry))
+            .collect()
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.render(LogLevel::Info, ""))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SetupMode {
+    Server,
+    Client,
+    Both,
+}
+
+impl SetupMode {
+    fn runs_server(self) -> bool {
+        matches!(self, SetupMode::Server | SetupMode::Both)
+    }
+
+    fn runs_client(self) -> bool {
+        matches!(self, SetupMode::Client | SetupMode::Both)
+    }
+}
+
+// 设置向导写入的配置，每次启动时填入界面
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SetupConfig {
+    mode: SetupMode,
+    server_host: String,
+    server_port: u16,
+    font_dir: PathBuf,
+    client_host: String,
+    client_port: u16,
+    // 实时同步监控的目录，为空时使用系统字体目录
+    #[serde(default)]
+    watch_dirs: Vec<PathBuf>,
+}
+
+impl Default for SetupConfig {
+    fn default() -> Self {
+        Self {
+            mode: SetupMode::Client,
+            server_host: "127.0.0.1".to_string(),
+            server_port: 8080,
+            font_dir: PathBuf::from("./fonts"),
+            client_host: "127.0.0.1".to_string(),
+            client_port: 8080,
+            watch_dirs: Vec::new(),
+        }
+    }
+}
+
+impl SetupConfig {
+    fn path() -> PathBuf {
+        crate::client_state::ClientState::state_dir().join(SETUP_FILE)
+    }
+
+    // 文件不存在或无法解析时返回 None，此时运行设置向导
+    fn load_from(path: &Path) -> Option<Self> {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("Failed to create state directory")?;
+        }
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize setup")?;
+        std::fs::write(path, content).with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+// 从局域网发现的服务器地址中取出主机与端口
+fn split_server_url(url: &str) -> Option<(String, u16)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some((url.host_str()?.to_string(), url.port_or_known_default()?))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WizardAction {
+    Skip,
+    Back,
+    Next,
+    Search,
+    Pick,
+    Test,
+}
+
+// 向导中的一段说明文字
+fn wizard_text(x: i32, y: i32, w: i32, h: i32, text: &str, size: i32) -> Frame {
+    let mut frame = Frame::new(x, y, w, h, None).with_label(text);
+    frame.set_label_size(size);
+    frame.set_align(Align::Left | Align::Inside | Align::Wrap);
+    frame
+}
+
+fn wizard_button(x: i32, y: i32, w: i32, label: &str) -> Button {
+    let mut button = Button::new(x, y, w, 28, None).with_label(label);
+    button.set_color(Color::from_rgb(255, 255, 255));
+    button.set_label_color(Color::from_rgb(49, 99, 239));
+    button.set_frame(FrameType::BorderBox);
+    button
+}
+
+fn show_wizard_result(frame: &mut Frame, ok: bool, message: &str) {
+    frame.set_label_color(if ok { Color::from_rgb(30, 130, 60) } else { Color::from_rgb(200, 40, 40) });
+    frame.set_label(message);
+    frame.redraw();
+}
+
+// 首次启动时的设置向导：选择使用方式，在局域网中查找并测试服务器，选择字体目录；跳过或关闭时返回 None
+fn run_setup_wizard(runtime: &Runtime) -> Option<SetupConfig> {
+    let defaults = SetupConfig::default();
+    let (sender, receiver) = app::channel::<WizardAction>();
+
+    let mut win = Window::default()
+        .with_size(560, 400)
+        .with_label("FontSync 设置向导");
+    win.set_color(Color::from_rgb(247, 244, 236));
+    let mut wizard = Wizard::new(10, 10, 540, 330, None);
+
+    // 第一步：使用方式
+    let mode_page = Group::new(10, 10, 540, 330, None);
+    wizard_text(30, 20, 500, 30, "这台电脑如何使用 FontSync？", 17);
+    let mut server_mode = RadioRoundButton::new(30, 70, 500, 30, None).with_label("服务器：在本机保存字体，供局域网中的其他电脑同步");
+    let mut client_mode = RadioRoundButton::new(30, 110, 500, 30, None).with_label("客户端：与局域网中的服务器同步本机字体");
+    let mut both_mode = RadioRoundButton::new(30, 150, 500, 30, None).with_label("两者：既运行服务器，也同步本机字体");
+    for button in [&mut server_mode, &mut client_mode, &mut both_mode] {
+        button.set_label_size(13);
+    }
+    client_mode.set_value(true);
+    mode_page.end();
+
+    // 第二步：连接服务器
+    let server_page = Group::new(10, 10, 540, 330, None);
+    wizard_text(30, 20, 500, 30, "连接到服务器", 17);
+    wizard_text(30, 50, 500, 20, "搜索开启了局域网发现的服务器，或手动输入地址。", 12);
+    let mut search_btn = wizard_button(30, 76, 110, "搜索局域网");
+    search_btn.emit(sender, WizardAction::Search);
+    let mut found = HoldBrowser::new(30, 110, 500, 100, None);
+    found.set_text_size(13);
+    found.emit(sender, WizardAction::Pick);
+    wizard_text(30, 222, 80, 28, "服务器地址", 12);
+    let mut host_input = Input::new(110, 222, 200, 28, None);
+    host_input.set_text_size(13);
+    host_input.set_value(&defaults.client_host);
+    wizard_text(326, 222, 40, 28, "端口", 12);
+    let mut port_input = IntInput::new(366, 222, 80, 28, None);
+    port_input.set_text_size(13);
+    port_input.set_value(&defaults.client_port.to_string());
+    let mut test_btn = wizard_button(30, 260, 110, "测试连接");
+    test_btn.emit(sender, WizardAction::Test);
+    let mut server_result = wizard_text(30, 294, 500, 24, "", 12);
+    server_page.end();
+
+    // 第三步：字体目录
+    let dirs_page = Group::new(10, 10, 540, 330, None);
+    wizard_text(30, 20, 500, 30, "选择字体目录", 17);
+    let mut font_dir_label = wizard_text(30, 56, 500, 20, "服务器保存字体的目录（不存在时自动创建）", 12);
+    let mut font_dir_row = Pack::new(30, 80, 500, 28, None);
+    font_dir_row.set_type(PackType::Horizontal);
+    font_dir_row.set_spacing(8);
+    let mut font_dir_input = Input::default().with_size(420, 28);
+    font_dir_input.set_text_size(13);
+    font_dir_input.set_value(&defaults.font_dir.to_string_lossy());
+    let font_dir_chooser = directory_chooser_button("选择服务器字体目录");
+    font_dir_row.end();
+    let mut font_dir_error = path_error_frame();
+    font_dir_error.resize(30, 110, 500, 16);
+    watch_directory_input(&mut font_dir_input, font_dir_chooser, &font_dir_error, true);
+    let mut watch_label = wizard_text(30, 140, 500, 20, "实时同步监控的本机目录，每行一个", 12);
+    let mut watch_input = MultilineInput::new(30, 164, 500, 120, None);
+    watch_input.set_text_size(13);
+    let system_dirs: Vec<String> = get_system_font_directories()
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .collect();
+    watch_input.set_value(&system_dirs.join("\n"));
+    let mut dirs_result = wizard_text(30, 294, 500, 24, "", 12);
+    dirs_page.end();
+    wizard.end();
+
+    let mut skip_btn = wizard_button(10, 360, 80, "跳过");
+    skip_btn.emit(sender, WizardAction::Skip);
+    let mut back_btn = wizard_button(370, 360, 80, "上一步");
+    back_btn.emit(sender, WizardAction::Back);
+    let mut next_btn = wizard_button(460, 360, 90, "下一步");
+    next_btn.emit(sender, WizardAction::Next);
+    win.end();
+    win.make_modal(true);
+    win.show();
+
+    let pages = [mode_page, server_page, dirs_page];
+    let mut current = 0;
+    let mut servers: Vec<crate::discovery::DiscoveredServer> = Vec::new();
+    let mode_of = || {
+        if server_mode.value() {
+            SetupMode::Server
+        } else if both_mode.value() {
+            SetupMode::Both
+        } else {
+            SetupMode::Client
+        }
+    };
+    // 只作服务器时不需要连接其他服务器
+    let visible = |mode: SetupMode, page: usize| page != 1 || mode.runs_client();
+
+    let mut result = None;
+    loop {
+        let mode = mode_of();
+        let last = (current + 1..pages.len()).all(|page| !visible(mode, page));
+        if current == 0 {
+            back_btn.deactivate();
+        } else {
+            back_btn.activate();
+        }
+        next_btn.set_label(if last { "完成" } else { "下一步" });
+        // 字体目录页只显示所选方式需要的目录
+        if mode.runs_server() {
+            font_dir_label.show();
+            font_dir_row.show();
+            font_dir_error.show();
+        } else {
+            font_dir_label.hide();
+            font_dir_row.hide();
+            font_dir_error.hide();
+        }
+        if mode.runs_client() {
+            watch_label.show();
+            watch_input.show();
+        } else {
+            watch_label.hide();
+            watch_input.hide();
+        }
+        wizard.set_current_widget(&pages[current]);
+
+        if !win.shown() || !app::wait() {
+            break;
+        }
+        let Some(action) = receiver.recv() else { continue };
+        match action {
+            WizardAction::Skip => break,
+            WizardAction::Back => {
+                current = (0..current).rev().find(|&page| visible(mode, page)).unwrap_or(0);
+            }
+            WizardAction::Next if !last => {
+                current = (current + 1..pages.len()).find(|&page| visible(mode, page)).unwrap_or(current);
+            }
+            WizardAction::Next => {
+                let font_dir = if mode.runs_server() {
+                    match validate_directory(&font_dir_input.value(), true) {
+                        Ok(path) => path,
+                        Err(message) => {
+                            show_wizard_result(&mut dirs_result, false, &message);
+                            continue;
+                        }
+                    }
+                } else {
+                    defaults.font_dir.clone()
+                };
+                let watch_dirs = if mode.runs_client() {
+                    let checked = watch_input
+                        .value()
+                        .lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .map(|line| validate_directory(line, false).map_err(|message| format!("{}：{}", line.trim(), message)))
+                        .collect::<std::result::Result<Vec<_>, String>>();
+                    match checked {
+                        Ok(dirs) => dirs,
+                        Err(message) => {
+                            show_wizard_result(&mut dirs_result, false, &message);
+                            continue;
+                        }
+                    }
+                } else {
+                    Vec::new()
+                };
+                let client_host = host_input.value().trim().to_string();
+                result = Some(SetupConfig {
+                    mode,
+                    // 作为服务器时监听所有地址，局域网中的其他电脑才能连接
+                    server_host: if mode.runs_server() { "0.0.0.0".to_string() } else { defaults.server_host.clone() },
+                    font_dir,
+                    client_host: if client_host.is_empty() { defaults.client_host.clone() } else { client_host },
+                    client_port: port_input.value().parse().unwrap_or(defaults.client_port),
+                    watch_dirs,
+                    ..defaults.clone()
+                });
+                break;
+            }
+            WizardAction::Search => {
+                show_wizard_result(&mut server_result, true, "正在搜索…");
+                app::flush();
+                match runtime.block_on(crate::discovery::discover(DISCOVERY_TIMEOUT)) {
+                    Ok(list) => {
+                        servers = list;
+                        found.clear();
+                        for server in &servers {
+                            found.add(&server.to_string());
+                        }
+                        if servers.is_empty() {
+                            show_wizard_result(&mut server_result, false, "未找到服务器，请确认服务器开启了局域网发现，或手动输入地址");
+                        } else {
+                            show_wizard_result(&mut server_result, true, &format!("找到 {} 台服务器，选择一台以填入地址", servers.len()));
+                        }
+                    }
+                    Err(e) => show_wizard_result(&mut server_result, false, &format!("搜索失败：{}", e)),
+                }
+            }
+            WizardAction::Pick => {
+                // 列表的行号从 1 开始，未选中时为 0
+                let selected = usize::try_from(found.value() - 1).ok().and_then(|index| servers.get(index));
+                if let Some((host, port)) = selected.and_then(|server| split_server_url(&server.url)) {
+                    host_input.set_value(&host);
+                    port_input.set_value(&port.to_string());
+                }
+            }
+            WizardAction::Test => {
+                let url = format!("http://{}:{}", host_input.value().trim(), port_input.value().trim());
+                show_wizard_result(&mut server_result, true, &format!("正在连接 {}…", url));
+                app::flush();
+                let version = runtime.block_on(async { crate::client::ApiClient::new(&url)?.version().await });
+                match version {
+                    Ok(info) => show_wizard_result(&mut server_result, true, &format!("连接成功：服务器版本 {}", info.version)),
+                    Err(e) => show_wizard_result(&mut server_result, false, &format!("无法连接 {}：{}", url, e)),
+                }
+            }
+        }
+    }
+    win.hide();
+    result
+}
+
+// 目录输入框右侧的“浏览…”按钮，由 watch_directory_input 连接到输入框
+fn directory_chooser_button(title: &'static str) -> Button {
+    let mut button = Button::default()
+        .with_size(72, 28)
+        .with_label("浏览…");
+    button.set_color(Color::from_rgb(255, 255, 255));
+    button.set_label_color(Color::from_rgb(49, 99, 239));
+    button.set_frame(FrameType::BorderBox);
+    button.set_tooltip(title);
+    button
+}
+
+// 输入框下方显示路径错误的一行，没有错误时为空
+fn path_error_frame() -> Frame {
+    let mut frame = Frame::default().with_size(0, 16);
+    frame.set_label_size(12);
+    frame.set_label_color(Color::from_rgb(200, 40, 40));
+    frame.set_align(Align::Left | Align::Inside);
+    frame
+}
+
+fn show_path_error(frame: &mut Frame, message: Option<&str>) {
+    frame.set_label(message.unwrap_or(""));
+    frame.redraw();
+}
+
+// 通过系统的目录选择对话框或手动输入修改路径后立即检查，错误显示在输入框下方
+fn watch_directory_input(input: &mut Input, mut chooser: Button, error: &Frame, create_missing: bool) {
+    let mut error_for_input = error.clone();
+    input.set_trigger(CallbackTrigger::Changed);
+    input.set_callback(move |input| {
+        let message = validate_directory(&input.value(), create_missing).err();
+        show_path_error(&mut error_for_input, message.as_deref());
+    });
+
+    let mut input = input.clone();
+    chooser.set_callback(move |button| {
+        let mut dialog = NativeFileChooser::new(NativeFileChooserType::BrowseDir);
+        dialog.set_title(&button.tooltip().unwrap_or_default());
+        dialog.set_option(NativeFileChooserOptions::NewFolder);
+        let current = PathBuf::from(input.value().trim());
+        if current.is_dir() {
+            let _ = dialog.set_directory(&current);
+        }
+        if let Ok(NativeFileChooserAction::Success) = dialog.try_show() {
+            let chosen = dialog.filename();
+            if !chosen.as_os_str().is_empty() {
+                input.set_value(&chosen.to_string_lossy());
+                input.do_callback();
+            }
+        }
+    });
+}
+
+// 检查输入的目录：已存在时须为可写的目录；create_missing 时允许目录不存在，
+// 此时最近的已存在上级须为可写的目录，以便之后创建
+fn validate_directory(value: &str, create_missing: bool) -> std::result::Result<PathBuf, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("请选择目录".to_string());
+    }
+    let path = PathBuf::from(value);
+    if path.exists() {
+        if !path.is_dir() {
+            return Err(format!("{} 不是目录", value));
+        }
+        check_writable(&path)?;
+        return Ok(path);
+    }
+    if !create_missing {
+        return Err(format!("目录 {} 不存在", value));
+    }
+    let ancestor = path
+        .ancestors()
+        .skip(1)
+        .map(|ancestor| if ancestor.as_os_str().is_empty() { Path::new(".") } else { ancestor })
+        .find(|ancestor| ancestor.exists());
+    match ancestor {
+        Some(ancestor) if ancestor.is_dir() => check_writable(ancestor).map(|_| path),
+        Some(ancestor) => Err(format!("无法创建 {}：{} 不是目录", value, ancestor.display())),
+        None => Err(format!("目录 {} 不存在", value)),
+    }
+}
+
+fn check_writable(dir: &Path) -> std::result::Result<(), String> {
+    tempfile::tempfile_in(dir)
+        .map(|_| ())
+        .map_err(|e| format!("目录 {} 不可写：{}", dir.display(), e))
+}
+
+// 从本机访问服务器的地址；监听所有地址时使用回环地址
+fn local_server_url(host: &str, port: u16) -> String {
+    let host = host.trim();
+    let host = match host {
+        "" | "0.0.0.0" | "::" | "[::]" => "127.0.0.1".to_string(),
+        host if host.contains(':') && !host.starts_with('[') => format!("[{}]", host),
+        host => host.to_string(),
+    };
+    format!("http://{}:{}", host, port)
+}
+
+// 用系统的文件管理器打开目录
+fn open_folder(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+    std::process::Command::new(program).arg(path).spawn().map(|_| ())
+}
+
 async fn start_server_internal(host: String, port: u16, font_dir: String) -> Result<()> {
     use crate::server;
     
-    server::start_server_with_websocket(host, port, font_dir, true).await
+    server::start_server_with_websocket(
+        vec![host],
+        Vec::new(),
+        port,
+        font_dir,
+        true,
+        false,
+        // 监听非回环地址时应答设置向导的局域网搜索
+        server::ServerPolicy { discoverable: true, ..Default::default() },
+    )
+    .await
 }
 
 async fn connect_client_internal(server_url: String) -> Result<()> {
     use crate::websocket_client;
     
-    let client_id = format!("gui_client_{}", uuid::Uuid::new_v4());
-    let _client = websocket_client::start_websocket_client(server_url, client_id).await?;
+    let client_id = crate::identity::ClientIdentity::current().client_id.clone();
+    // 先诊断连接，失败时在状态栏显示具体原因
+    let probe = crate::probe::run(&server_url, &client_id).await;
+    if probe.failure().is_some() {
+        return Err(anyhow::anyhow!("{}", probe.summary()));
+    }
+    if probe.warnings().next().is_some() {
+        log::warn!("{}", probe.summary());
+    }
+    let _client = websocket_client::start_websocket_client(
+        server_url,
+        client_id,
+        crate::client::SyncOptions::default(),
+    ).await?;
     
     // 客户端在后台运行
     Ok(())
 }
 
-async fn perform_one_time_sync(server_url: String) -> Result<(usize, usize)> {
+// 一次性同步的结果：上传、下载与因固定版本而保留的字体数，以及安装的字体
+struct OneTimeSyncResult {
+    uploaded: usize,
+    downloaded: usize,
+    pinned: usize,
+    installed: Vec<crate::api::InstalledFont>,
+}
+
+async fn perform_one_time_sync(server_url: String, progress: crate::progress::Progress) -> Result<OneTimeSyncResult> {
     use crate::client;
     
-    let local_font_dirs = get_system_font_directories();
-    let download_dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("fontsync/downloads");
-    
-    tokio::fs::create_dir_all(&download_dir).await?;
-    
     let mut total_uploaded = 0;
     let mut total_downloaded = 0;
+    let options = client::SyncOptions::default();
+    let mut report = crate::sync_report::SyncReport::with_progress(progress);
+    let started_at = chrono::Utc::now().timestamp() as u64;
     
-    // 上传本地字体
-    for font_dir in local_font_dirs {
-        if font_dir.exists() {
-            let (uploaded, _) = client::upload_local_fonts(&server_url, &font_dir, false).await?;
+    // 配置了指向该服务器的同步映射时按映射的目录与方向同步，下载到映射的目录并从那里安装
+    let mappings = crate::mappings::load()?;
+    let mapped: Vec<_> = crate::mappings::for_server(&mappings, &server_url).collect();
+    for mapping in &mapped {
+        let dir = mapping.local_path();
+        tokio::fs::create_dir_all(&dir).await?;
+        let options = mapping.sync_options(&options);
+        if mapping.direction.uploads() {
+            let (uploaded, _) = client::upload_local_fonts(&server_url, &dir, &options, &mut report).await?;
             total_uploaded += uploaded;
         }
+        if mapping.direction.downloads() {
+            let (downloaded, _) = client::download_server_fonts(&server_url, &dir, &options, &mut report).await?;
+            total_downloaded += downloaded;
+            if downloaded > 0 {
+                client::install_downloaded_fonts(&dir, &options, &mut report).await?;
+            }
+        }
     }
     
-    // 下载服务器字体
-    let (downloaded, _) = client::download_server_fonts(&server_url, &download_dir, false).await?;
-    total_downloaded += downloaded;
-    
-    // 安装已下载字体
-    if total_downloaded > 0 {
-        client::install_downloaded_fonts(&download_dir).await?;
+    if mapped.is_empty() {
+        let download_dir = crate::download_cache::download_dir();
+        tokio::fs::create_dir_all(&download_dir).await?;
+        
+        // 上传本地字体
+        for font_dir in get_system_font_directories() {
+            if font_dir.exists() {
+                let (uploaded, _) = client::upload_local_fonts(&server_url, &font_dir, &options, &mut report).await?;
+                total_uploaded += uploaded;
+            }
+        }
+        
+        // 下载服务器字体
+        let (downloaded, _) = client::download_server_fonts(&server_url, &download_dir, &options, &mut report).await?;
+        total_downloaded += downloaded;
+        
+        // 安装已下载字体
+        if downloaded > 0 {
+            client::install_downloaded_fonts(&download_dir, &options, &mut report).await?;
+        }
     }
+    report.log_summary();
     
-    Ok((total_uploaded, total_downloaded))
+    crate::sync_history::record(&server_url, started_at, &report, false);
+    Ok(OneTimeSyncResult {
+        uploaded: total_uploaded,
+        downloaded: total_downloaded,
+        pinned: report.count(crate::sync_report::FileAction::Pinned),
+        installed: report.installed_fonts(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_buffer_filters_and_drops_the_oldest_entries() {
+        let mut log = LogBuffer::new(3);
+        log.push(LogLevel::Info, "Connecting to server");
+        log.push(LogLevel::Warn, "Failed to sync a.ttf");
+        log.push(LogLevel::Error, "One-time sync failed");
+        log.push(LogLevel::Info, "Client connected successfully");
+
+        assert_eq!(log.render(LogLevel::Info, "").lines().count(), 3);
+        assert!(!log.render(LogLevel::Info, "").contains("Connecting"));
+        assert_eq!(log.render(LogLevel::Warn, "").lines().count(), 2);
+        let errors = log.render(LogLevel::Error, "");
+        assert!(errors.contains("ERROR One-time sync failed"), "{}", errors);
+        assert_eq!(log.render(LogLevel::Info, " SYNC ").lines().count(), 2);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        log.save(file.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), log.render(LogLevel::Info, ""));
+    }
+
+    #[test]
+    fn directories_are_validated_before_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("font.ttf");
+        std::fs::write(&file, b"font").unwrap();
+        let missing = dir.path().join("new/fonts");
+
+        assert_eq!(validate_directory(&format!(" {} ", dir.path().display()), false), Ok(dir.path().to_path_buf()));
+        assert!(validate_directory("  ", true).is_err());
+        assert!(validate_directory(&file.to_string_lossy(), true).unwrap_err().contains("不是目录"));
+        assert!(validate_directory(&missing.to_string_lossy(), false).is_err());
+        assert_eq!(validate_directory(&missing.to_string_lossy(), true), Ok(missing));
+        assert!(validate_directory(&file.join("fonts").to_string_lossy(), true).is_err());
+    }
+
+    #[test]
+    fn tray_status_summarizes_the_latest_sync() {
+        let runs: Vec<crate::sync_history::SyncRun> = serde_json::from_value(serde_json::json!([
+            { "server_url": "http://a:8080", "started_at": 90, "finished_at": 100, "conflicts": [
+                { "filename": "x.ttf", "direction": "upload", "resolution": "skip", "renamed_to": null },
+                { "filename": "y.ttf", "direction": "download", "resolution": "skip", "renamed_to": null }
+            ] },
+            { "server_url": "http://a:8080", "started_at": 190, "finished_at": 200, "conflicts": [
+                { "filename": "x.ttf", "direction": "upload", "resolution": "skip", "renamed_to": null },
+                { "filename": "z.ttf", "direction": "upload", "resolution": "rename", "renamed_to": "z (1).ttf" }
+            ] },
+            { "server_url": "http://b:8080", "started_at": 290, "finished_at": 300 }
+        ]))
+        .unwrap();
+
+        let status = TrayStatus::default().with_history(&runs, "http://a:8080/");
+        assert_eq!(status.last_sync.map(|t| t.timestamp()), Some(200));
+        assert_eq!(status.conflicts_line(), "Pending conflicts: 1");
+        assert_eq!(TrayStatus::default().with_history(&runs, "http://c:8080").last_sync_line(), "Last sync: never");
+
+        let running = TrayStatus { server_running: true, clients: Some(2), monitoring: true, ..status };
+        assert_eq!(running.server_line(), "Server: running, 2 clients connected");
+        assert_eq!(running.monitoring_action(), "Pause monitoring");
+
+        assert_eq!(local_server_url("0.0.0.0", 8080), "http://127.0.0.1:8080");
+        assert_eq!(local_server_url("::1", 80), "http://[::1]:80");
+    }
+
+    #[test]
+    fn setup_is_written_once_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join(SETUP_FILE);
+        assert_eq!(SetupConfig::load_from(&path), None);
+
+        let setup = SetupConfig {
+            mode: SetupMode::Both,
+            server_host: "0.0.0.0".to_string(),
+            watch_dirs: vec![dir.path().to_path_buf()],
+            ..SetupConfig::default()
+        };
+        setup.save_to(&path).unwrap();
+        assert_eq!(SetupConfig::load_from(&path), Some(setup));
+        assert!(std::fs::read_to_string(&path).unwrap().contains("\"mode\": \"both\""));
+        assert!(SetupMode::Both.runs_server() && SetupMode::Both.runs_client());
+        assert!(!SetupMode::Server.runs_client());
+
+        assert_eq!(split_server_url("http://192.168.1.20:8080"), Some(("192.168.1.20".to_string(), 8080)));
+        assert_eq!(split_server_url("http://[fe80::1]:9000"), Some(("[fe80::1]".to_string(), 9000)));
+        assert_eq!(split_server_url("not a url"), None);
+    }
 }
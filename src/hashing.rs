@@ -0,0 +1,144 @@
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::blob_store::BlobStore;
+use crate::metadata_store::MetadataStore;
+use crate::utils::is_font_file;
+use crate::websocket_server::{WebSocketMessage, WebSocketServer};
+
+// 同时计算哈希的文件数上限，避免启动时占满磁盘与 CPU
+const MAX_HASH_WORKERS: usize = 4;
+
+// 启动时在后台计算缓存中缺失或过期的哈希，并将尚未纳入数据存储的字体链接到对应数据；
+// 列表接口在此期间立即返回待定条目
+pub fn spawn_startup_hashing(
+    font_dir: &Path,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    ws_server: Option<Arc<WebSocketServer>>,
+) -> tokio::task::JoinHandle<()> {
+    let queue = stale_fonts(font_dir, &metadata, &blobs);
+    let total = queue.len();
+    metadata.mark_hash_pending(
+        queue
+            .iter()
+            .filter(|(name, path)| metadata.cached_sha256(name, path).is_none())
+            .map(|(name, _)| name.clone()),
+    );
+    let queue = Arc::new(Mutex::new(queue));
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(MAX_HASH_WORKERS)
+        .min(total.max(1));
+
+    tokio::spawn(async move {
+        if total == 0 {
+            return;
+        }
+        info!("Hashing {} fonts in the background with {} workers", total, workers);
+
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let metadata = Arc::clone(&metadata);
+                let blobs = Arc::clone(&blobs);
+                tokio::task::spawn_blocking(move || {
+                    loop {
+                        let Some((name, path)) = queue.lock().pop() else {
+                            break;
+                        };
+                        // 计算前已被删除的文件
+                        if !path.is_file() {
+                            metadata.forget_sha256(&name);
+                            continue;
+                        }
+                        let sha256 = match metadata.file_sha256(&name, &path) {
+                            Ok(sha256) => sha256,
+                            Err(e) => {
+                                error!("Failed to calculate SHA256 for {:?}: {}", path, e);
+                                metadata.forget_sha256(&name);
+                                continue;
+                            }
+                        };
+                        if let Err(e) = blobs.adopt(&name, &sha256, &path) {
+                            error!("Failed to store {:?} in the blob store: {:#}", path, e);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Err(e) = handle.await {
+                error!("Hash worker failed: {}", e);
+            }
+        }
+
+        if let Err(e) = metadata.flush_hashes() {
+            warn!("Failed to save hash cache: {}", e);
+        }
+        info!("Background hashing complete ({} fonts)", total);
+        if let Some(server) = ws_server {
+            server.broadcast_font_event(WebSocketMessage::HashingComplete { fonts: total });
+        }
+    })
+}
+
+// 字体目录中哈希缓存缺失、过期或尚未纳入数据存储的文件
+fn stale_fonts(font_dir: &Path, metadata: &MetadataStore, blobs: &BlobStore) -> Vec<(String, PathBuf)> {
+    let entries = match fs::read_dir(font_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read font directory {:?}: {}", font_dir, e);
+            return Vec::new();
+        }
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file() && is_font_file(path))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_string();
+            let cached = metadata.cached_sha256(&name, &path);
+            let stored = cached.is_some() && blobs.sha256_of(&name) == cached;
+            (!stored).then_some((name, path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn hashes_stale_fonts_in_background() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::write(dir.path().join("a.ttf"), b"font a").unwrap();
+        fs::write(dir.path().join("b.otf"), b"font b").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"not a font").unwrap();
+        let metadata = Arc::new(MetadataStore::open(dir.path()).expect("store"));
+        metadata.file_sha256("a.ttf", &dir.path().join("a.ttf")).unwrap();
+
+        let blobs = Arc::new(BlobStore::open(dir.path()).expect("blobs"));
+        let handle = spawn_startup_hashing(dir.path(), Arc::clone(&metadata), Arc::clone(&blobs), None);
+        // 已缓存的字体不再排队
+        assert!(!metadata.is_hash_pending("a.ttf"));
+        handle.await.expect("hashing");
+        assert!(!metadata.is_hash_pending("b.otf"));
+        assert_eq!(
+            metadata.cached_sha256("b.otf", &dir.path().join("b.otf")),
+            Some(crate::utils::calculate_sha256(&dir.path().join("b.otf")).unwrap())
+        );
+        // 已有的文件都纳入数据存储
+        let sha_b = metadata.cached_sha256("b.otf", &dir.path().join("b.otf")).unwrap();
+        assert_eq!(blobs.sha256_of("b.otf"), Some(sha_b.clone()));
+        assert!(blobs.sha256_of("a.ttf").is_some());
+        assert!(blobs.blob_path(&sha_b).is_some());
+        // 结果已保存，重新打开后无需再计算
+        let reopened = MetadataStore::open(dir.path()).expect("reopen");
+        assert!(reopened.cached_sha256("b.otf", &dir.path().join("b.otf")).is_some());
+    }
+}
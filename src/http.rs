@@ -0,0 +1,444 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use log::debug;
+use percent_encoding::percent_decode_str;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::api;
+use crate::credentials;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+// 普通接口请求的总超时
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+// 上传与下载字体文件的总超时
+pub const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+// CONNECT 响应头的长度上限
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+static PROXY: OnceLock<Url> = OnceLock::new();
+
+// 命令行 --proxy 指定的代理，优先于 HTTP_PROXY 等环境变量；须在发出第一个请求前设置
+pub fn set_proxy(proxy: &str) -> Result<()> {
+    let url = Url::parse(proxy).with_context(|| format!("Invalid proxy URL '{}'", proxy))?;
+    if !PROXY_SCHEMES.contains(&url.scheme()) || url.host_str().is_none() {
+        bail!("Unsupported proxy URL '{}' (expected http://, https://, socks5:// or socks5h://)", proxy);
+    }
+    PROXY.set(url).map_err(|_| anyhow::anyhow!("Proxy is already configured"))
+}
+
+// 进程内共用的连接池；未指定 --proxy 时由 reqwest 读取 HTTP_PROXY、HTTPS_PROXY、ALL_PROXY 与 NO_PROXY
+fn shared_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+            .tcp_keepalive(TCP_KEEPALIVE);
+        if let Some(proxy) = PROXY.get() {
+            match reqwest::Proxy::all(proxy.as_str()) {
+                Ok(proxy) => builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env())),
+                Err(e) => debug!("Ignoring proxy {}: {}", proxy, e),
+            }
+        }
+        builder.build().unwrap_or_else(|e| {
+            debug!("Failed to build tuned HTTP client, using defaults: {}", e);
+            reqwest::Client::new()
+        })
+    })
+}
+
+// unix:///run/fontsync.sock 或 unix:/run/fontsync.sock 形式的服务器地址
+pub fn unix_socket_path(server_url: &str) -> Option<PathBuf> {
+    let rest = server_url.strip_prefix("unix:")?;
+    let path = rest.strip_prefix("//").unwrap_or(rest);
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+// 经 Unix 套接字发出的请求使用的地址，只用于构造 URL 与 Host 头，连接总是打开套接字
+pub const UNIX_SOCKET_URL: &str = "http://localhost";
+
+// Unix 套接字地址换成请求 URL 使用的 http://localhost，其他地址原样返回
+pub fn resolve_server_url(server_url: &str) -> String {
+    match unix_socket_path(server_url) {
+        Some(_) => UNIX_SOCKET_URL.to_string(),
+        None => server_url.to_string(),
+    }
+}
+
+// 每个套接字一个连接池，直接连接套接字文件，访问权限由文件权限决定，也不经代理
+fn unix_socket_client(path: &Path) -> Result<reqwest::Client> {
+    static CLIENTS: OnceLock<Mutex<HashMap<PathBuf, reqwest::Client>>> = OnceLock::new();
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut clients = CLIENTS.get_or_init(Default::default).lock();
+    if let Some(client) = clients.get(path) {
+        return Ok(client.clone());
+    }
+
+    #[cfg(unix)]
+    {
+        let client = reqwest::Client::builder()
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .unix_socket(path)
+            .build()
+            .context("Failed to create Unix socket HTTP client")?;
+        clients.insert(path.to_path_buf(), client.clone());
+        Ok(client)
+    }
+    #[cfg(not(unix))]
+    bail!("Unix domain sockets are not supported on this platform ({:?})", path)
+}
+
+// 打开到服务器 Unix 套接字的连接，供 WebSocket 握手使用
+#[cfg(unix)]
+pub async fn connect_unix_socket(path: &Path) -> Result<tokio::net::UnixStream> {
+    tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::UnixStream::connect(path))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out connecting to unix:{}", path.display()))?
+        .with_context(|| format!("Failed to connect to unix:{}", path.display()))
+}
+
+// WebSocket 连接使用的代理，与 HTTP 请求遵循相同的配置
+pub fn proxy_for(target: &Url) -> Option<Url> {
+    select_proxy(target, PROXY.get(), |name| std::env::var(name).ok())
+}
+
+fn select_proxy(target: &Url, explicit: Option<&Url>, env: impl Fn(&str) -> Option<String>) -> Option<Url> {
+    let var = |name: &str| env(name).or_else(|| env(&name.to_lowercase())).filter(|v| !v.trim().is_empty());
+    let host = target.host_str()?;
+    if let Some(no_proxy) = var("NO_PROXY")
+        && bypasses_proxy(&no_proxy, host)
+    {
+        return None;
+    }
+    if let Some(proxy) = explicit {
+        return Some(proxy.clone());
+    }
+    let scheme_var = match target.scheme() {
+        "https" | "wss" => "HTTPS_PROXY",
+        _ => "HTTP_PROXY",
+    };
+    var(scheme_var)
+        .or_else(|| var("ALL_PROXY"))
+        .and_then(|proxy| Url::parse(&proxy).ok())
+        .filter(|proxy| PROXY_SCHEMES.contains(&proxy.scheme()))
+}
+
+// NO_PROXY 为逗号分隔的主机名，"*" 表示全部，条目同时匹配其子域名
+fn bypasses_proxy(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    no_proxy.split(',').map(str::trim).filter(|e| !e.is_empty()).any(|entry| {
+        let entry = entry.trim_start_matches('.');
+        entry == "*" || host.eq_ignore_ascii_case(entry) || host.to_ascii_lowercase().ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+    })
+}
+
+// 经代理建立到 host:port 的 TCP 隧道：HTTP 代理使用 CONNECT，SOCKS5 代理由 tokio-socks 握手
+pub async fn tunnel(proxy: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let proxy_host = proxy.host_str().context("Proxy URL has no host")?;
+    let credentials = (!proxy.username().is_empty()).then(|| {
+        (
+            percent_decode_str(proxy.username()).decode_utf8_lossy().into_owned(),
+            percent_decode_str(proxy.password().unwrap_or_default()).decode_utf8_lossy().into_owned(),
+        )
+    });
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port))
+                .await
+                .with_context(|| format!("Failed to connect to proxy {}:{}", proxy_host, proxy_port))?;
+            let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+            if let Some((user, password)) = &credentials {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await?;
+
+            // 逐字节读取响应头，避免读走隧道中的后续数据
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                if response.len() >= MAX_CONNECT_RESPONSE {
+                    bail!("Proxy sent an oversized CONNECT response");
+                }
+                let byte = stream.read_u8().await.context("Proxy closed the connection during CONNECT")?;
+                response.push(byte);
+            }
+            let response = String::from_utf8_lossy(&response);
+            let status_line = response.lines().next().unwrap_or_default();
+            match status_line.split_whitespace().nth(1) {
+                Some(code) if code.starts_with('2') => Ok(stream),
+                _ => bail!("Proxy refused CONNECT to {}:{}: {}", host, port, status_line),
+            }
+        }
+        "socks5" | "socks5h" => {
+            let proxy_addr = (proxy_host, proxy.port().unwrap_or(1080));
+            // socks5 在本地解析目标地址，socks5h 交给代理解析
+            let target = if proxy.scheme() == "socks5" {
+                let addr = tokio::net::lookup_host((host, port))
+                    .await?
+                    .next()
+                    .with_context(|| format!("Failed to resolve {}", host))?;
+                tokio_socks::TargetAddr::Ip(addr)
+            } else {
+                tokio_socks::TargetAddr::Domain(host.to_string().into(), port)
+            };
+            let stream = match &credentials {
+                Some((user, password)) => {
+                    tokio_socks::tcp::Socks5Stream::connect_with_password(proxy_addr, target, user, password).await
+                }
+                None => tokio_socks::tcp::Socks5Stream::connect(proxy_addr, target).await,
+            }
+            .with_context(|| format!("SOCKS proxy {} failed to connect to {}:{}", proxy_host, host, port))?;
+            Ok(stream.into_inner())
+        }
+        scheme => bail!("{} proxies are not supported for WebSocket connections", scheme),
+    }
+}
+
+// 共用连接池上的某个服务器的客户端，每个请求附带该服务器的认证头
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    authorization: Option<HeaderValue>,
+}
+
+impl HttpClient {
+    pub fn for_server(server_url: &str) -> Result<Self> {
+        let authorization = match credentials::token_for(server_url) {
+            Some(token) => {
+                let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                    .context("Stored token is not a valid header value")?;
+                value.set_sensitive(true);
+                Some(value)
+            }
+            None => None,
+        };
+        let client = match unix_socket_path(server_url) {
+            Some(path) => unix_socket_client(&path)?,
+            None => shared_client().clone(),
+        };
+        Ok(Self { client, authorization })
+    }
+
+    // 不设总超时，用于长连接的事件流
+    pub fn request(&self, method: Method, url: String) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, url)
+            .header(api::VERSION_HEADER, env!("CARGO_PKG_VERSION"))
+            .header(api::PROTOCOL_HEADER, api::PROTOCOL_VERSION);
+        match &self.authorization {
+            Some(value) => request.header(AUTHORIZATION, value.clone()),
+            None => request,
+        }
+    }
+
+    pub fn get(&self, url: String) -> RequestBuilder {
+        self.request(Method::GET, url).timeout(REQUEST_TIMEOUT)
+    }
+
+    pub fn post(&self, url: String) -> RequestBuilder {
+        self.request(Method::POST, url).timeout(REQUEST_TIMEOUT)
+    }
+
+    pub fn delete(&self, url: String) -> RequestBuilder {
+        self.request(Method::DELETE, url).timeout(REQUEST_TIMEOUT)
+    }
+}
+
+// 幂等请求遇到网络错误、429 或网关类 5xx 时按指数退避重试，服务器给出的 Retry-After 不超过
+// 最大退避时间时按其等待；更长的等待交给调用方（如同步队列）处理。请求体无法复制时只发送一次
+pub trait SendWithRetry {
+    fn send_with_retry(self) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl SendWithRetry for RequestBuilder {
+    async fn send_with_retry(self) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let Some(request) = self.try_clone().filter(|_| attempt < MAX_RETRIES) else {
+                return self.send().await;
+            };
+            let delay = match request.send().await {
+                Ok(response) if is_transient_status(response.status()) => match retry_after(response.headers()) {
+                    Some(after) if after > RETRY_MAX_DELAY => return Ok(response),
+                    after => {
+                        debug!("Request to {} returned {}, retrying", response.url(), response.status());
+                        after.unwrap_or_else(|| backoff(attempt))
+                    }
+                },
+                Err(e) if is_transient_error(&e) => {
+                    debug!("Request failed, retrying: {}", e);
+                    backoff(attempt)
+                }
+                result => return result,
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// Retry-After 响应头，秒数或 HTTP 日期两种形式；已过去的日期视为立即重试
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+// 第 attempt 次重试前的等待时间：指数增长并加入最多一半的随机抖动，避免客户端同时重试
+fn backoff(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY.saturating_mul(1 << attempt.min(16)).min(RETRY_MAX_DELAY);
+    base + base.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use warp::Filter;
+
+    #[test]
+    fn backoff_grows_with_bounded_jitter() {
+        for attempt in 0..10 {
+            let base = RETRY_BASE_DELAY.saturating_mul(1 << attempt).min(RETRY_MAX_DELAY);
+            let delay = backoff(attempt);
+            assert!(delay >= base && delay <= base.mul_f64(1.5), "attempt {}: {:?}", attempt, delay);
+        }
+        assert!(backoff(40) <= RETRY_MAX_DELAY.mul_f64(1.5));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let route = warp::path("flaky").map(move || {
+            let status = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                _ => warp::http::StatusCode::OK,
+            };
+            warp::reply::with_status("ok", status)
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let http = HttpClient { client: shared_client().clone(), authorization: None };
+        let response = http.get(format!("http://{}/flaky", addr)).send_with_retry().await.expect("send");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn honors_short_retry_after_and_returns_long_ones() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let route = warp::path!("busy" / u64).map(move |seconds: u64| {
+            let reply = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => warp::http::StatusCode::TOO_MANY_REQUESTS,
+                _ if seconds > 0 => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                _ => warp::http::StatusCode::OK,
+            };
+            warp::reply::with_header(warp::reply::with_status("busy", reply), "retry-after", seconds.to_string())
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let http = HttpClient { client: shared_client().clone(), authorization: None };
+        let response = http.get(format!("http://{}/busy/0", addr)).send_with_retry().await.expect("send");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+
+        // 超过最大退避时间的等待不在这里进行
+        let response = http.get(format!("http://{}/busy/60", addr)).send_with_retry().await.expect("send");
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after(response.headers()), Some(Duration::from_secs(60)));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&later).unwrap());
+        assert!(retry_after(&headers).is_some_and(|after| after > Duration::from_secs(80)));
+    }
+
+    #[test]
+    fn selects_proxy_from_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        let wss = Url::parse("wss://fonts.example.com/ws").unwrap();
+        let ws = Url::parse("ws://intranet.corp:8080/ws").unwrap();
+        let vars = env(&[("HTTPS_PROXY", "http://proxy:3128"), ("all_proxy", "socks5h://socks:1080"), ("NO_PROXY", "localhost, .corp")]);
+
+        assert_eq!(select_proxy(&wss, None, vars).unwrap().as_str(), "http://proxy:3128/");
+        assert!(select_proxy(&ws, None, vars).is_none());
+        let other = Url::parse("ws://fonts.example.com/ws").unwrap();
+        assert_eq!(select_proxy(&other, None, vars).unwrap().scheme(), "socks5h");
+
+        let explicit = Url::parse("http://cli-proxy:8080").unwrap();
+        assert_eq!(select_proxy(&wss, Some(&explicit), vars), Some(explicit.clone()));
+        assert!(select_proxy(&ws, Some(&explicit), vars).is_none());
+        assert!(select_proxy(&wss, None, env(&[])).is_none());
+        assert!(bypasses_proxy("*", "anything"));
+        assert!(!bypasses_proxy("example.com", "badexample.com"));
+    }
+
+    #[tokio::test]
+    async fn tunnels_through_http_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(socket.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+            assert!(request.starts_with("CONNECT fonts.example.com:443 HTTP/1.1\r\n"));
+            assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwIHc=\r\n"));
+            socket.write_all(b"HTTP/1.1 200 Connection established\r\n\r\nhello").await.unwrap();
+        });
+
+        let proxy = Url::parse(&format!("http://user:p%20w@{}", addr)).unwrap();
+        let mut stream = tunnel(&proxy, "fonts.example.com", 443).await.expect("tunnel");
+        let mut greeting = [0u8; 5];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"hello");
+    }
+}
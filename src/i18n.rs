@@ -0,0 +1,235 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::api::ErrorCode;
+use crate::client;
+use crate::utils::ConflictResolution;
+
+// 命令行输出的语言，由 --lang 或 LC_ALL、LC_MESSAGES、LANG 选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    ZhCn,
+    EnUs,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+// --lang 的取值，如 zh-CN、zh_CN、en-US、en
+pub fn parse_lang(value: &str) -> Result<Lang, String> {
+    from_tag(value).ok_or_else(|| format!("unsupported language '{}', expected zh-CN or en-US", value))
+}
+
+fn from_tag(tag: &str) -> Option<Lang> {
+    // 去掉 zh_CN.UTF-8 中的编码与 @ 修饰
+    let tag = tag.split(['.', '@']).next().unwrap_or_default().to_ascii_lowercase();
+    let language = tag.split(['_', '-']).next().unwrap_or_default();
+    match language {
+        "zh" => Some(Lang::ZhCn),
+        "en" | "c" | "posix" => Some(Lang::EnUs),
+        _ => None,
+    }
+}
+
+// 按 POSIX 的优先顺序取 LC_ALL、LC_MESSAGES、LANG 中第一个非空的值，不支持的语言使用英文
+fn from_env(env: impl Fn(&str) -> Option<String>) -> Lang {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| env(name).filter(|v| !v.trim().is_empty()))
+        .map(|value| from_tag(&value).unwrap_or(Lang::EnUs))
+        .next()
+        .unwrap_or(Lang::EnUs)
+}
+
+// 只在启动时设置一次；未设置时从环境变量判断
+pub fn set(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn current() -> Lang {
+    // 测试中固定为英文，不受运行环境影响
+    if cfg!(test) {
+        return *LANG.get_or_init(|| Lang::EnUs);
+    }
+    *LANG.get_or_init(|| from_env(|name| std::env::var(name).ok()))
+}
+
+const EN_US: &[(&str, &str)] = &[
+    ("error", "Error: {message}"),
+    ("error.hint", "Hint: {hint}"),
+    ("error.unauthorized", "The server rejected the token; run `fontsync login <server-url>` to store a valid one"),
+    ("error.forbidden", "The token is valid but lacks the permission for this operation"),
+    ("error.incompatible_protocol", "The client and server protocol versions are incompatible; upgrade the older one"),
+    ("error.rate_limited", "The server is limiting requests; try again later"),
+    ("error.maintenance", "The server is in read-only maintenance mode; try again after maintenance ends"),
+    ("error.quota_exceeded", "The upload quota for this client is used up; ask the administrator to raise it"),
+    ("error.payload_too_large", "The file exceeds the server's upload size limit"),
+    ("error.blocked", "The server's blocklist does not accept this font"),
+    ("error.unavailable", "The server is temporarily unavailable; try again later"),
+    ("sync.summary", "{uploaded} uploaded, {downloaded} downloaded, {installed} installed, {skipped} skipped, {pinned} pinned, {failed} failed"),
+    ("sync.complete", "Synchronization complete: {summary}"),
+    ("sync.cancelled", "Synchronization cancelled: {summary}"),
+    ("sync.installed", "Installed fonts ({count}):"),
+    ("sync.pinned", "Pinned fonts kept at their local version ({count}):"),
+    ("sync.conflicts", "Conflict decisions ({count}):"),
+    ("sync.cancelled_transfers", "Sync cancelled: {completed} transfers completed, {aborted} aborted"),
+    ("conflict.resolved", "Font conflict detected for '{name}': local SHA256={local}, remote SHA256={remote}. Resolved as {resolution} by --on-conflict {policy}."),
+    ("conflict.detected", "⚠️  Font file conflict detected!"),
+    ("conflict.filename", "Filename: {name}"),
+    ("conflict.local_sha256", "Local SHA256:  {sha256}..."),
+    ("conflict.remote_sha256", "Remote SHA256: {sha256}..."),
+    ("conflict.question", "What would you like to do?"),
+    ("conflict.same_face_remote", "Same font face as '{name}' on the server"),
+    ("conflict.replace_remote", "1) Replace '{name}' on the server with the local version"),
+    ("conflict.same_face_local", "Same font face as local file '{name}'"),
+    ("conflict.replace_local", "1) Replace '{name}' with the remote version"),
+    ("conflict.keep_both", "2) Keep both versions"),
+    ("conflict.overwrite_remote", "1) Overwrite remote file with local version"),
+    ("conflict.upload_renamed", "2) Upload local file under a new name"),
+    ("conflict.overwrite_local", "1) Overwrite local file with remote version"),
+    ("conflict.save_renamed", "2) Save remote file under a new name"),
+    ("conflict.skip_file", "3) Skip this file"),
+    ("conflict.overwrite", "Overwrite"),
+    ("conflict.rename", "Rename"),
+    ("conflict.skip", "Skip"),
+];
+
+const ZH_CN: &[(&str, &str)] = &[
+    ("error", "错误：{message}"),
+    ("error.hint", "提示：{hint}"),
+    ("error.unauthorized", "服务器拒绝了令牌，请运行 `fontsync login <服务器 URL>` 保存有效的令牌"),
+    ("error.forbidden", "令牌有效，但没有执行此操作的权限"),
+    ("error.incompatible_protocol", "客户端与服务器的协议版本不兼容，请升级较旧的一方"),
+    ("error.rate_limited", "服务器正在限制请求频率，请稍后重试"),
+    ("error.maintenance", "服务器处于只读维护模式，请在维护结束后重试"),
+    ("error.quota_exceeded", "此客户端的上传配额已用完，请联系管理员提高配额"),
+    ("error.payload_too_large", "文件超过了服务器的上传大小限制"),
+    ("error.blocked", "服务器的禁止列表不接受此字体"),
+    ("error.unavailable", "服务器暂时不可用，请稍后重试"),
+    ("sync.summary", "上传 {uploaded} 个，下载 {downloaded} 个，安装 {installed} 个，跳过 {skipped} 个，固定 {pinned} 个，失败 {failed} 个"),
+    ("sync.complete", "同步完成：{summary}"),
+    ("sync.cancelled", "同步已取消：{summary}"),
+    ("sync.installed", "已安装的字体（{count}）："),
+    ("sync.pinned", "因固定版本而保留本地版本的字体（{count}）："),
+    ("sync.conflicts", "冲突的处理结果（{count}）："),
+    ("sync.cancelled_transfers", "同步已取消：{completed} 个传输已完成，{aborted} 个已中止"),
+    ("conflict.resolved", "字体 '{name}' 存在冲突：本地 SHA256={local}，服务器 SHA256={remote}。按 --on-conflict {policy} 处理为「{resolution}」。"),
+    ("conflict.detected", "⚠️  检测到字体文件冲突！"),
+    ("conflict.filename", "文件名：{name}"),
+    ("conflict.local_sha256", "本地 SHA256：  {sha256}..."),
+    ("conflict.remote_sha256", "服务器 SHA256：{sha256}..."),
+    ("conflict.question", "要如何处理？"),
+    ("conflict.same_face_remote", "与服务器上的 '{name}' 是同一字体"),
+    ("conflict.replace_remote", "1) 用本地版本替换服务器上的 '{name}'"),
+    ("conflict.same_face_local", "与本地文件 '{name}' 是同一字体"),
+    ("conflict.replace_local", "1) 用服务器版本替换 '{name}'"),
+    ("conflict.keep_both", "2) 保留两个版本"),
+    ("conflict.overwrite_remote", "1) 用本地版本覆盖服务器上的文件"),
+    ("conflict.upload_renamed", "2) 以新名称上传本地文件"),
+    ("conflict.overwrite_local", "1) 用服务器版本覆盖本地文件"),
+    ("conflict.save_renamed", "2) 以新名称保存服务器上的文件"),
+    ("conflict.skip_file", "3) 跳过此文件"),
+    ("conflict.overwrite", "覆盖"),
+    ("conflict.rename", "重命名"),
+    ("conflict.skip", "跳过"),
+];
+
+fn catalog(lang: Lang) -> &'static [(&'static str, &'static str)] {
+    match lang {
+        Lang::ZhCn => ZH_CN,
+        Lang::EnUs => EN_US,
+    }
+}
+
+fn lookup(lang: Lang, key: &str) -> Option<&'static str> {
+    catalog(lang).iter().find(|(k, _)| *k == key).map(|(_, text)| *text)
+}
+
+// 当前语言缺少的条目使用英文，英文也没有时返回键本身
+pub fn text(key: &'static str) -> &'static str {
+    lookup(current(), key).or_else(|| lookup(Lang::EnUs, key)).unwrap_or(key)
+}
+
+// 将 {name} 形式的占位替换为参数
+pub fn format(key: &'static str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    args.iter()
+        .fold(text(key).to_string(), |text, (name, value)| text.replace(&format!("{{{}}}", name), &value.to_string()))
+}
+
+// t!("key") 或 t!("key", name = value, ...)
+macro_rules! t {
+    ($key:literal) => {
+        $crate::i18n::text($key).to_string()
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::format($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+pub(crate) use t;
+
+pub fn resolution(resolution: &ConflictResolution) -> String {
+    match resolution {
+        ConflictResolution::Overwrite => t!("conflict.overwrite"),
+        ConflictResolution::Rename => t!("conflict.rename"),
+        ConflictResolution::Skip => t!("conflict.skip"),
+    }
+}
+
+// 命令失败时输出的说明；服务器返回的错误类别附上处理建议
+pub fn describe_error(error: &anyhow::Error) -> String {
+    let mut message = t!("error", message = format!("{:#}", error));
+    let hint = client::server_error(error).and_then(|e| match e.code {
+        ErrorCode::Unauthorized => Some(t!("error.unauthorized")),
+        ErrorCode::Forbidden => Some(t!("error.forbidden")),
+        ErrorCode::IncompatibleProtocol => Some(t!("error.incompatible_protocol")),
+        ErrorCode::RateLimited => Some(t!("error.rate_limited")),
+        ErrorCode::Maintenance => Some(t!("error.maintenance")),
+        ErrorCode::QuotaExceeded => Some(t!("error.quota_exceeded")),
+        ErrorCode::PayloadTooLarge => Some(t!("error.payload_too_large")),
+        ErrorCode::Blocked => Some(t!("error.blocked")),
+        ErrorCode::Unavailable => Some(t!("error.unavailable")),
+        _ => None,
+    });
+    if let Some(hint) = hint {
+        message.push('\n');
+        message.push_str(&t!("error.hint", hint = hint));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashMap};
+
+    fn placeholders(text: &str) -> BTreeSet<&str> {
+        text.split('{').skip(1).filter_map(|rest| rest.split_once('}')).map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn catalogs_match_and_languages_are_detected() {
+        // 两种语言的条目与占位一致
+        let zh: HashMap<_, _> = ZH_CN.iter().copied().collect();
+        assert_eq!(zh.len(), EN_US.len());
+        for (key, en) in EN_US {
+            let zh = zh.get(key).unwrap_or_else(|| panic!("'{}' has no zh-CN text", key));
+            assert_eq!(placeholders(en), placeholders(zh), "{}", key);
+        }
+        assert_eq!(
+            format("sync.cancelled", &[("summary", &"x")]),
+            "Synchronization cancelled: x"
+        );
+        assert_eq!(text("no.such.key"), "no.such.key");
+
+        assert_eq!(parse_lang("zh-CN"), Ok(Lang::ZhCn));
+        assert_eq!(parse_lang("en"), Ok(Lang::EnUs));
+        assert!(parse_lang("fr").is_err());
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        assert_eq!(from_env(env(&[("LANG", "zh_CN.UTF-8")])), Lang::ZhCn);
+        assert_eq!(from_env(env(&[("LANG", "zh_CN.UTF-8"), ("LC_ALL", "C")])), Lang::EnUs);
+        assert_eq!(from_env(env(&[("LC_ALL", ""), ("LC_MESSAGES", "zh_TW"), ("LANG", "en_US")])), Lang::ZhCn);
+        assert_eq!(from_env(env(&[("LANG", "de_DE.UTF-8")])), Lang::EnUs);
+        assert_eq!(from_env(env(&[])), Lang::EnUs);
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::client_state::ClientState;
+
+const IDENTITY_FILE: &str = "identity.json";
+
+pub const CLIENT_ID_HEADER: &str = "x-fontsync-client-id";
+pub const HOSTNAME_HEADER: &str = "x-fontsync-hostname";
+pub const OS_HEADER: &str = "x-fontsync-os";
+
+// 客户端的稳定身份，首次运行时生成并持久化
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientIdentity {
+    pub client_id: String,
+    pub hostname: String,
+    pub os: String,
+}
+
+impl ClientIdentity {
+    // 同一进程内只读取一次身份文件
+    pub fn current() -> &'static ClientIdentity {
+        static IDENTITY: OnceLock<ClientIdentity> = OnceLock::new();
+        IDENTITY.get_or_init(|| Self::load_or_create(&ClientState::state_dir().join(IDENTITY_FILE)))
+    }
+
+    fn load_or_create(path: &Path) -> ClientIdentity {
+        let stored: Option<ClientIdentity> = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok());
+
+        // ID 一经生成不再改变，主机名与系统信息每次启动时刷新
+        let identity = ClientIdentity {
+            client_id: stored
+                .as_ref()
+                .map(|s| s.client_id.clone())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            hostname: local_hostname(),
+            os: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+        };
+
+        if stored.as_ref() != Some(&identity)
+            && let Err(e) = identity.save(path)
+        {
+            warn!("Failed to save client identity: {}", e);
+        }
+
+        identity
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create state directory")?;
+        }
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize client identity")?;
+        fs::write(path, content).context("Failed to write client identity")?;
+        Ok(())
+    }
+
+    // 服务器端根据请求头还原客户端身份，未携带 ID 的旧客户端返回 None
+    pub fn from_headers(
+        client_id: Option<String>,
+        hostname: Option<String>,
+        os: Option<String>,
+    ) -> Option<ClientIdentity> {
+        let client_id = client_id.filter(|id| !id.is_empty())?;
+        Some(ClientIdentity {
+            client_id,
+            hostname: hostname.unwrap_or_else(|| "unknown".to_string()),
+            os: os.unwrap_or_else(|| "unknown".to_string()),
+        })
+    }
+
+    pub fn headers(&self) -> [(&'static str, &str); 3] {
+        [
+            (CLIENT_ID_HEADER, self.client_id.as_str()),
+            (HOSTNAME_HEADER, self.hostname.as_str()),
+            (OS_HEADER, self.os.as_str()),
+        ]
+    }
+}
+
+fn local_hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        // 请求头只接受可见 ASCII 字符
+        .map(|name| name.trim().chars().filter(|c| c.is_ascii_graphic()).collect::<String>())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_id_is_stable_across_loads() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join(IDENTITY_FILE);
+
+        let first = ClientIdentity::load_or_create(&path);
+        assert!(path.exists());
+
+        let second = ClientIdentity::load_or_create(&path);
+        assert_eq!(first.client_id, second.client_id);
+        assert!(!second.hostname.is_empty());
+    }
+}
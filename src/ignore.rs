@@ -0,0 +1,266 @@
+use log::warn;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::client_state::ClientState;
+
+// 监控目录根部的忽略文件，语法与 .gitignore 相同的子集
+pub const IGNORE_FILE: &str = ".fontsyncignore";
+// 对所有目录生效的全局忽略文件，位于客户端状态目录
+const GLOBAL_IGNORE_FILE: &str = "fontsyncignore";
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    // 包含 / 的模式相对目录根匹配，否则匹配任意层级的名称
+    anchored: bool,
+    // 以 / 结尾的模式只匹配目录
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        if line.is_empty() {
+            return None;
+        }
+        Some(Self {
+            glob: line.chars().collect(),
+            negated,
+            anchored,
+            dir_only,
+        })
+    }
+
+    // components 为相对目录根的路径各级名称，最后一级是文件本身
+    fn matches(&self, components: &[String]) -> bool {
+        // 目录被匹配时其中的文件也被忽略
+        let candidates = if self.dir_only {
+            components.len().saturating_sub(1)
+        } else {
+            components.len()
+        };
+        (0..candidates).any(|i| {
+            if self.anchored {
+                let prefix: Vec<char> = components[..=i].join("/").chars().collect();
+                glob_match(&self.glob, &prefix)
+            } else {
+                let name: Vec<char> = components[i].chars().collect();
+                glob_match(&self.glob, &name)
+            }
+        })
+    }
+}
+
+// 单个模式与文本的匹配，供服务器端按名称过滤使用
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    glob_match(&pattern.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>())
+}
+
+// 支持 *、**、? 与 [a-z]/[!a-z] 字符类，* 和 ? 不跨越 /
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // "**/" 也可以匹配零级目录
+            if rest.first() == Some(&'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => matches!(text.first(), Some(c) if *c != '/') && glob_match(&pattern[1..], &text[1..]),
+        Some('[') => match (text.first(), parse_class(&pattern[1..])) {
+            (Some(&c), Some((matched, len))) if c != '/' && matched(c) => glob_match(&pattern[1 + len..], &text[1..]),
+            (_, Some(_)) => false,
+            // 没有闭合的 [ 按普通字符处理
+            (Some('['), None) => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some(&c) => text.first() == Some(&c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+// 解析 [ 之后的字符类，返回判断函数与包括 ] 在内消耗的字符数
+fn parse_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool, usize)> {
+    let (negated, start) = match pattern.first() {
+        Some('!') | Some('^') => (true, 1),
+        _ => (false, 0),
+    };
+    // 紧跟在开头的 ] 视为普通字符
+    let end = pattern
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .find(|(_, c)| **c == ']')
+        .map(|(i, _)| i)?;
+
+    let body = &pattern[start..end];
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        if i + 2 < body.len() && body[i + 1] == '-' {
+            ranges.push((body[i], body[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((body[i], body[i]));
+            i += 1;
+        }
+    }
+    let matched = move |c: char| ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != negated;
+    Some((matched, end + 1))
+}
+
+#[derive(Debug, Clone, Default)]
+struct RootRules {
+    root: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+// 一组监控目录的忽略规则：全局规则在前，目录自身的规则在后，最后一条匹配的规则生效
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    global: Vec<Pattern>,
+    roots: Vec<RootRules>,
+}
+
+impl IgnoreRules {
+    pub fn global_path() -> PathBuf {
+        ClientState::state_dir().join(GLOBAL_IGNORE_FILE)
+    }
+
+    pub fn load(roots: &[PathBuf]) -> Self {
+        Self {
+            global: read_patterns(&Self::global_path()),
+            roots: roots
+                .iter()
+                .map(|root| RootRules {
+                    root: root.clone(),
+                    patterns: read_patterns(&root.join(IGNORE_FILE)),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn for_dir(root: &Path) -> Self {
+        Self::load(&[root.to_path_buf()])
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        // 取包含该路径的最深一级监控目录
+        let root = self
+            .roots
+            .iter()
+            .filter(|r| path.starts_with(&r.root))
+            .max_by_key(|r| r.root.components().count());
+        let relative = root.and_then(|r| path.strip_prefix(&r.root).ok());
+        let components: Vec<String> = match relative {
+            Some(relative) => relative
+                .components()
+                .filter_map(|c| match c {
+                    Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect(),
+            None => path.file_name().map(|n| n.to_string_lossy().into_owned()).into_iter().collect(),
+        };
+        if components.is_empty() {
+            return false;
+        }
+
+        let root_patterns = root.map(|r| r.patterns.as_slice()).unwrap_or_default();
+        self.global
+            .iter()
+            .chain(root_patterns)
+            .rev()
+            .find(|p| p.matches(&components))
+            .is_some_and(|p| !p.negated)
+    }
+}
+
+fn read_patterns(path: &Path) -> Vec<Pattern> {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().filter_map(Pattern::parse).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            warn!("Failed to read ignore file {:?}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_gitignore_style_patterns() {
+        let root = Path::new("/fonts");
+        let content = "# 个人字体\n*Handwriting*\npersonal/\n/Top.ttf\ndrafts/**/*.otf\n[Tt]est?.ttf\n!Handwriting-Company.ttf\n";
+        let rules = IgnoreRules {
+            global: Vec::new(),
+            roots: vec![RootRules {
+                root: root.to_path_buf(),
+                patterns: content.lines().filter_map(Pattern::parse).collect(),
+            }],
+        };
+        let ignored = |path: &str| rules.is_ignored(&root.join(path));
+
+        assert!(ignored("MyHandwriting.ttf"));
+        assert!(ignored("sub/MyHandwriting-Bold.otf"));
+        assert!(!ignored("Handwriting-Company.ttf"));
+        assert!(ignored("personal/Font.ttf"));
+        assert!(ignored("a/personal/b/Font.ttf"));
+        // 只匹配目录的模式不匹配同名文件
+        assert!(!ignored("personal"));
+        assert!(ignored("Top.ttf"));
+        assert!(!ignored("sub/Top.ttf"));
+        assert!(ignored("drafts/Draft.otf"));
+        assert!(ignored("drafts/a/b/Draft.otf"));
+        assert!(!ignored("drafts/Draft.ttf"));
+        assert!(ignored("test1.ttf"));
+        assert!(ignored("Test2.ttf"));
+        assert!(!ignored("best1.ttf"));
+        assert!(!ignored("Regular.ttf"));
+        // 目录自己的规则不作用于其他目录
+        assert!(!rules.is_ignored(Path::new("/elsewhere/MyHandwriting.ttf")));
+    }
+
+    #[test]
+    fn glob_wildcards_do_not_cross_directories() {
+        let glob = glob_matches;
+        assert!(glob("a/*.ttf", "a/b.ttf"));
+        assert!(!glob("a/*.ttf", "a/b/c.ttf"));
+        assert!(glob("a/**", "a/b/c.ttf"));
+        assert!(glob("**/c.ttf", "c.ttf"));
+        assert!(glob("[!a-c]x", "dx"));
+        assert!(!glob("[!a-c]x", "bx"));
+        assert!(glob("[]]", "]"));
+    }
+}
@@ -0,0 +1,624 @@
+use anyhow::{bail, Context, Result};
+use ttf_parser::{GlyphId, OutlineBuilder, Tag};
+
+use crate::font_metadata;
+
+// 实例化后不再需要的可变字体表；轮廓重建后原有的微调指令与其数据也随之失效
+const DROPPED_TABLES: &[&[u8; 4]] = &[
+    b"fvar", b"gvar", b"avar", b"cvar", b"HVAR", b"VVAR", b"MVAR", b"STAT", b"DSIG", b"hdmx", b"LTSH", b"VDMX", b"fpgm",
+    b"prep", b"cvt ",
+];
+// 实例的 name 表中重新生成的名称
+const REPLACED_NAME_IDS: &[u16] = &[1, 2, 3, 4, 6, 16, 17, 21, 22, 25];
+
+// fvar 表中的一条可变轴
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub tag: String,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+}
+
+// fvar 表中命名的实例，如 Bold 对应 wght=700
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    pub name: String,
+    pub coordinates: Vec<(String, f32)>,
+}
+
+// 生成的静态字体
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub data: Vec<u8>,
+    pub family: String,
+    pub style: String,
+}
+
+impl Instance {
+    pub fn file_name(&self) -> String {
+        font_metadata::normalize_name(&self.family, Some(&self.style), "ttf").unwrap_or_else(|| "instance.ttf".to_string())
+    }
+}
+
+// 解析命令行中的 "wght=700"
+pub fn parse_axis(value: &str) -> std::result::Result<(String, f32), String> {
+    let (tag, coordinate) = value.split_once('=').ok_or_else(|| format!("Expected TAG=VALUE, got '{}'", value))?;
+    let tag = tag.trim();
+    if tag.is_empty() || tag.len() > 4 || !tag.is_ascii() {
+        return Err(format!("Invalid axis tag '{}'", tag));
+    }
+    let coordinate: f32 = coordinate.trim().parse().map_err(|_| format!("Invalid value for axis '{}': {}", tag, coordinate))?;
+    if !coordinate.is_finite() {
+        return Err(format!("Invalid value for axis '{}': {}", tag, coordinate));
+    }
+    Ok((tag.to_string(), coordinate))
+}
+
+pub fn axes(data: &[u8]) -> Result<Vec<Axis>> {
+    let face = variable_face(data)?;
+    Ok(face
+        .variation_axes()
+        .into_iter()
+        .map(|axis| Axis {
+            tag: axis.tag.to_string(),
+            min: axis.min_value,
+            default: axis.def_value,
+            max: axis.max_value,
+        })
+        .collect())
+}
+
+// ttf-parser 只解析 fvar 中的轴，命名实例按规范直接读取
+pub fn named_instances(data: &[u8]) -> Result<Vec<NamedInstance>> {
+    let face = variable_face(data)?;
+    let fvar = face.raw_face().table(Tag::from_bytes(b"fvar")).context("Font has no fvar table")?;
+    let tags: Vec<String> = face.variation_axes().into_iter().map(|axis| axis.tag.to_string()).collect();
+    let names = face.raw_face().table(Tag::from_bytes(b"name")).and_then(ttf_parser::name::Table::parse);
+
+    let u16_at = |at: usize| fvar.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let (Some(axes_offset), Some(axis_count), Some(axis_size), Some(count), Some(size)) =
+        (u16_at(4), u16_at(8), u16_at(10), u16_at(12), u16_at(14))
+    else {
+        bail!("Malformed fvar table");
+    };
+    let mut instances = Vec::new();
+    let start = axes_offset as usize + axis_count as usize * axis_size as usize;
+    for i in 0..count as usize {
+        let at = start + i * size as usize;
+        let Some(name_id) = u16_at(at) else { break };
+        let coordinates = tags
+            .iter()
+            .enumerate()
+            .filter_map(|(axis, tag)| {
+                let at = at + 4 + axis * 4;
+                let fixed = fvar.get(at..at + 4)?;
+                Some((tag.clone(), i32::from_be_bytes(fixed.try_into().ok()?) as f32 / 65536.0))
+            })
+            .collect();
+        let name = names
+            .as_ref()
+            .and_then(|names| font_metadata::find_name(names, name_id))
+            .unwrap_or_else(|| format!("Instance {}", i + 1));
+        instances.push(NamedInstance { name, coordinates });
+    }
+    Ok(instances)
+}
+
+// 按名称（不区分大小写）生成命名实例
+pub fn instantiate_named(data: &[u8], name: &str) -> Result<Instance> {
+    let instances = named_instances(data)?;
+    let Some(instance) = instances.iter().find(|i| i.name.eq_ignore_ascii_case(name)) else {
+        let available: Vec<_> = instances.iter().map(|i| i.name.as_str()).collect();
+        bail!("No named instance '{}'; available: {}", name, available.join(", "));
+    };
+    instantiate(data, &instance.coordinates)
+}
+
+// 在指定位置生成静态 TrueType 字体，未指定的轴取默认值。
+// 轮廓与前进宽度应用 gvar/HVAR 的变化，组合字形展开为简单字形，微调指令被去掉
+pub fn instantiate(data: &[u8], location: &[(String, f32)]) -> Result<Instance> {
+    let mut face = variable_face(data)?;
+    let axes = axes(data)?;
+    for (tag, value) in location {
+        let Some(axis) = axes.iter().find(|a| a.tag == *tag) else {
+            let available: Vec<_> = axes.iter().map(|a| a.tag.as_str()).collect();
+            bail!("Font has no '{}' axis; available: {}", tag, available.join(", "));
+        };
+        if *value < axis.min || *value > axis.max {
+            bail!("{}={} is outside the axis range {}..{}", tag, value, axis.min, axis.max);
+        }
+        face.set_variation(Tag::from_bytes_lossy(tag.as_bytes()), *value);
+    }
+    let value_of = |tag: &str| {
+        location
+            .iter()
+            .rev()
+            .find(|(t, _)| t == tag)
+            .map(|(_, v)| *v)
+            .or_else(|| axes.iter().find(|a| a.tag == tag).map(|a| a.default))
+    };
+
+    let descriptor = font_metadata::read_descriptor(data);
+    let family = descriptor.family.unwrap_or_else(|| "Instance".to_string());
+    let style = named_instances(data)
+        .ok()
+        .and_then(|instances| {
+            instances.into_iter().find(|instance| {
+                axes.iter().all(|axis| {
+                    let coordinate = instance.coordinates.iter().find(|(t, _)| *t == axis.tag).map_or(axis.default, |(_, v)| *v);
+                    value_of(&axis.tag).is_some_and(|v| (v - coordinate).abs() < 0.5)
+                })
+            })
+        })
+        .map(|instance| instance.name)
+        .unwrap_or_else(|| {
+            let parts: Vec<String> = axes
+                .iter()
+                .filter_map(|axis| value_of(&axis.tag).filter(|v| *v != axis.default).map(|v| format!("{}{}", axis.tag.trim(), v)))
+                .collect();
+            if parts.is_empty() { "Regular".to_string() } else { parts.join(" ") }
+        });
+
+    // 重建 glyf/loca/hmtx，统一使用长格式 loca
+    let glyph_count = face.number_of_glyphs();
+    let mut glyf = Vec::new();
+    let mut loca = Vec::with_capacity((glyph_count as usize + 1) * 4);
+    let mut hmtx = Vec::with_capacity(glyph_count as usize * 4);
+    let mut stats = GlyphStats::default();
+    for id in 0..glyph_count {
+        let glyph_id = GlyphId(id);
+        let mut outline = Outline::default();
+        face.outline_glyph(glyph_id, &mut outline);
+        if outline.cubic {
+            bail!("Glyph {} has cubic curves; only TrueType outlines can be instanced", id);
+        }
+        loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+        let bbox = outline.encode(&mut glyf, &mut stats);
+        let advance = face.glyph_hor_advance(glyph_id).unwrap_or(0);
+        let lsb = bbox.map_or(0, |b| b[0]);
+        hmtx.extend_from_slice(&advance.to_be_bytes());
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+        stats.metrics(advance, bbox);
+    }
+    loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+
+    let raw = face.raw_face();
+    let mut tables: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    for record in raw.table_records {
+        let tag = record.tag.to_bytes();
+        if DROPPED_TABLES.contains(&&tag) {
+            continue;
+        }
+        let table = raw.table(record.tag).context("Table extends past end of font")?;
+        let table = match &tag {
+            b"glyf" => glyf.clone(),
+            b"loca" => loca.clone(),
+            b"hmtx" => hmtx.clone(),
+            b"head" => patch_head(table, &stats)?,
+            b"hhea" => patch_hhea(table, glyph_count, &stats)?,
+            b"maxp" => patch_maxp(table, &stats),
+            b"OS/2" => patch_os2(table, value_of("wght"), value_of("wdth")),
+            b"name" => instance_names(table, &family, &style, descriptor.version.as_deref()),
+            _ => table.to_vec(),
+        };
+        tables.push((tag, table));
+    }
+    Ok(Instance { data: build_sfnt(tables), family, style })
+}
+
+fn variable_face(data: &[u8]) -> Result<ttf_parser::Face<'_>> {
+    let face = ttf_parser::Face::parse(data, 0).context("Unsupported font format")?;
+    if !face.is_variable() {
+        bail!("Font is not a variable font");
+    }
+    if face.tables().glyf.is_none() {
+        bail!("Only variable fonts with TrueType outlines (glyf) can be instanced");
+    }
+    Ok(face)
+}
+
+// 收集一个轮廓的点：(x, y, 是否在曲线上)
+#[derive(Default)]
+struct Outline {
+    contours: Vec<Vec<(f32, f32, bool)>>,
+    cubic: bool,
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(vec![(x, y, true)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push((x, y, true));
+        }
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push((x1, y1, false));
+            contour.push((x, y, true));
+        }
+    }
+
+    fn curve_to(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _x: f32, _y: f32) {
+        self.cubic = true;
+    }
+
+    // 闭合时回到起点的线段在 glyf 中是隐含的
+    fn close(&mut self) {
+        if let Some(contour) = self.contours.last_mut()
+            && contour.len() > 1
+            && contour.first() == contour.last()
+        {
+            contour.pop();
+        }
+    }
+}
+
+impl Outline {
+    // 写入简单字形，坐标一律以 16 位增量存储；返回 [xMin, yMin, xMax, yMax]，空字形返回 None
+    fn encode(&self, glyf: &mut Vec<u8>, stats: &mut GlyphStats) -> Option<[i16; 4]> {
+        let points: Vec<(i16, i16, bool)> = self
+            .contours
+            .iter()
+            .flatten()
+            .map(|&(x, y, on_curve)| (x.round() as i16, y.round() as i16, on_curve))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        let mut bbox = [i16::MAX, i16::MAX, i16::MIN, i16::MIN];
+        for &(x, y, _) in &points {
+            bbox = [bbox[0].min(x), bbox[1].min(y), bbox[2].max(x), bbox[3].max(y)];
+        }
+
+        glyf.extend_from_slice(&(self.contours.len() as i16).to_be_bytes());
+        for value in bbox {
+            glyf.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut end = 0usize;
+        for contour in &self.contours {
+            end += contour.len();
+            glyf.extend_from_slice(&(end as u16 - 1).to_be_bytes());
+        }
+        glyf.extend_from_slice(&0u16.to_be_bytes());
+        glyf.extend(points.iter().map(|&(_, _, on_curve)| on_curve as u8));
+        for coordinate in [0, 1] {
+            let mut previous = 0i16;
+            for &(x, y, _) in &points {
+                let value = if coordinate == 0 { x } else { y };
+                glyf.extend_from_slice(&value.wrapping_sub(previous).to_be_bytes());
+                previous = value;
+            }
+        }
+        if glyf.len() % 2 == 1 {
+            glyf.push(0);
+        }
+
+        stats.max_points = stats.max_points.max(points.len() as u16);
+        stats.max_contours = stats.max_contours.max(self.contours.len() as u16);
+        Some(bbox)
+    }
+}
+
+// 重新计算 head、hhea 与 maxp 中依赖字形的字段
+#[derive(Debug)]
+struct GlyphStats {
+    bbox: Option<[i16; 4]>,
+    max_points: u16,
+    max_contours: u16,
+    advance_max: u16,
+    min_lsb: i16,
+    min_rsb: i16,
+    max_extent: i16,
+}
+
+impl Default for GlyphStats {
+    fn default() -> Self {
+        Self {
+            bbox: None,
+            max_points: 0,
+            max_contours: 0,
+            advance_max: 0,
+            min_lsb: i16::MAX,
+            min_rsb: i16::MAX,
+            max_extent: i16::MIN,
+        }
+    }
+}
+
+impl GlyphStats {
+    fn metrics(&mut self, advance: u16, bbox: Option<[i16; 4]>) {
+        self.advance_max = self.advance_max.max(advance);
+        let Some([x_min, y_min, x_max, y_max]) = bbox else {
+            return;
+        };
+        self.min_lsb = self.min_lsb.min(x_min);
+        self.min_rsb = self.min_rsb.min((advance as i32 - x_max as i32) as i16);
+        self.max_extent = self.max_extent.max(x_max);
+        self.bbox = Some(match self.bbox {
+            Some(b) => [b[0].min(x_min), b[1].min(y_min), b[2].max(x_max), b[3].max(y_max)],
+            None => [x_min, y_min, x_max, y_max],
+        });
+    }
+}
+
+fn set_u16(table: &mut [u8], at: usize, value: u16) {
+    if let Some(field) = table.get_mut(at..at + 2) {
+        field.copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn patch_head(table: &[u8], stats: &GlyphStats) -> Result<Vec<u8>> {
+    if table.len() < 54 {
+        bail!("Malformed head table");
+    }
+    let mut head = table.to_vec();
+    // checkSumAdjustment 在整个字体组装后重新计算
+    head[8..12].fill(0);
+    for (i, value) in stats.bbox.unwrap_or_default().into_iter().enumerate() {
+        set_u16(&mut head, 36 + i * 2, value as u16);
+    }
+    set_u16(&mut head, 50, 1);
+    Ok(head)
+}
+
+fn patch_hhea(table: &[u8], glyph_count: u16, stats: &GlyphStats) -> Result<Vec<u8>> {
+    if table.len() < 36 {
+        bail!("Malformed hhea table");
+    }
+    let mut hhea = table.to_vec();
+    set_u16(&mut hhea, 10, stats.advance_max);
+    if stats.bbox.is_some() {
+        set_u16(&mut hhea, 12, stats.min_lsb as u16);
+        set_u16(&mut hhea, 14, stats.min_rsb as u16);
+        set_u16(&mut hhea, 16, stats.max_extent as u16);
+    }
+    set_u16(&mut hhea, 34, glyph_count);
+    Ok(hhea)
+}
+
+// 版本 1.0 的 maxp：组合字形已展开，指令已去掉
+fn patch_maxp(table: &[u8], stats: &GlyphStats) -> Vec<u8> {
+    let mut maxp = table.to_vec();
+    if maxp.len() >= 32 {
+        set_u16(&mut maxp, 6, stats.max_points);
+        set_u16(&mut maxp, 8, stats.max_contours);
+        for at in [10, 12, 26, 28, 30] {
+            set_u16(&mut maxp, at, 0);
+        }
+    }
+    maxp
+}
+
+// usWeightClass 取 wght 坐标，usWidthClass 取最接近 wdth 百分比的等级
+fn patch_os2(table: &[u8], weight: Option<f32>, width: Option<f32>) -> Vec<u8> {
+    const WIDTH_CLASSES: [f32; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+    let mut os2 = table.to_vec();
+    if let Some(weight) = weight {
+        set_u16(&mut os2, 4, weight.round().clamp(1.0, 1000.0) as u16);
+    }
+    if let Some(width) = width {
+        let class = WIDTH_CLASSES
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (*a - width).abs().total_cmp(&(*b - width).abs()))
+            .map_or(5, |(i, _)| i as u16 + 1);
+        set_u16(&mut os2, 6, class);
+    }
+    os2
+}
+
+// 旧程序按 name ID 1/2 分组，实例以 "<Family> <Style>" 作为独立家族，样式为 Regular；
+// 其余名称（版权、许可等）原样保留
+fn instance_names(table: &[u8], family: &str, style: &str, version: Option<&str>) -> Vec<u8> {
+    let full_name = format!("{} {}", family, style);
+    let postscript: String = format!("{}-{}", family, style)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let unique = format!("{};{}", version.unwrap_or("Version 1.000"), postscript);
+
+    let mut records: Vec<(u16, u16, u16, u16, Vec<u8>)> = ttf_parser::name::Table::parse(table)
+        .map(|names| {
+            names
+                .names
+                .into_iter()
+                .filter(|name| !REPLACED_NAME_IDS.contains(&name.name_id))
+                .map(|name| (platform_id(name.platform_id), name.encoding_id, name.language_id, name.name_id, name.name.to_vec()))
+                .collect()
+        })
+        .unwrap_or_default();
+    for (name_id, text) in [
+        (1, full_name.as_str()),
+        (2, "Regular"),
+        (3, unique.as_str()),
+        (4, full_name.as_str()),
+        (6, postscript.as_str()),
+        (16, family),
+        (17, style),
+    ] {
+        records.push((3, 1, 0x0409, name_id, text.encode_utf16().flat_map(u16::to_be_bytes).collect()));
+    }
+    records.sort_by_key(|r| (r.0, r.1, r.2, r.3));
+
+    let mut out = Vec::new();
+    let mut storage = Vec::new();
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(records.len() as u16).to_be_bytes());
+    out.extend_from_slice(&(6 + 12 * records.len() as u16).to_be_bytes());
+    for (platform, encoding, language, name_id, text) in &records {
+        for value in [*platform, *encoding, *language, *name_id, text.len() as u16, storage.len() as u16] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        storage.extend_from_slice(text);
+    }
+    out.extend_from_slice(&storage);
+    out
+}
+
+fn platform_id(platform: ttf_parser::PlatformId) -> u16 {
+    match platform {
+        ttf_parser::PlatformId::Unicode => 0,
+        ttf_parser::PlatformId::Macintosh => 1,
+        ttf_parser::PlatformId::Iso => 2,
+        ttf_parser::PlatformId::Windows => 3,
+        ttf_parser::PlatformId::Custom => 4,
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+// 按标签排序组装 sfnt，表按 4 字节对齐，最后写入 head.checkSumAdjustment
+pub(crate) fn build_sfnt(mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.max(1).leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for value in [num_tables, search_range, entry_selector, num_tables * 16 - search_range] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, table) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(table).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += (table.len() + 3) & !3;
+    }
+    for (_, table) in &tables {
+        font.extend_from_slice(table);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+    if let Some(at) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[at + 8..at + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::font_metadata::tests::{cmap_table, name_table, os2_table, sfnt_with_tables};
+
+    // 在 square_font 的基础上加入 wght 轴（100..900，默认 400）：wght=900 时方块右边与前进宽度各加 100
+    pub(crate) fn variable_font() -> Vec<u8> {
+        let be16 = |values: &[i16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+        let fixed = |value: i32| (value << 16).to_be_bytes();
+
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0u8; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+        let mut maxp = 0x0001_0000u32.to_be_bytes().to_vec();
+        maxp.extend_from_slice(&2u16.to_be_bytes());
+        maxp.resize(32, 0);
+        let mut glyf = be16(&[1, 100, 0, 900, 800, 3, 0]);
+        glyf.extend_from_slice(&[0x01; 4]);
+        glyf.extend(be16(&[100, 800, 0, -800, 0, 0, 800, 0]));
+        glyf.resize(36, 0);
+
+        // 一条轴、两个命名实例（Regular 与 Black）
+        let mut fvar = Vec::new();
+        for value in [1u16, 0, 16, 2, 1, 20, 2, 8] {
+            fvar.extend_from_slice(&value.to_be_bytes());
+        }
+        fvar.extend_from_slice(b"wght");
+        for value in [100, 400, 900] {
+            fvar.extend_from_slice(&fixed(value));
+        }
+        fvar.extend_from_slice(&[0, 0, 1, 0]);
+        for (name_id, wght) in [(257u16, 400), (258, 900)] {
+            fvar.extend_from_slice(&name_id.to_be_bytes());
+            fvar.extend_from_slice(&[0, 0]);
+            fvar.extend_from_slice(&fixed(wght));
+        }
+
+        // 字形 1 的一组变化：内嵌峰值 1.0，私有点号 0 表示全部点（含 4 个幻影点）
+        let mut deltas = vec![0x00, 0x47];
+        deltas.extend(be16(&[0, 100, 100, 0, 0, 100, 0, 0]));
+        deltas.extend_from_slice(&[0x87, 0x00]);
+        let mut variation = be16(&[1, 10, 19, (0x8000u16 | 0x2000) as i16, 0x4000]);
+        variation.extend_from_slice(&deltas);
+        let mut gvar = be16(&[1, 0, 1, 0]);
+        gvar.extend_from_slice(&26u32.to_be_bytes());
+        gvar.extend(be16(&[2, 0]));
+        gvar.extend_from_slice(&26u32.to_be_bytes());
+        gvar.extend(be16(&[0, 0, variation.len() as i16 / 2]));
+        gvar.extend_from_slice(&variation);
+
+        let mut os2 = os2_table(0);
+        os2[4..6].copy_from_slice(&400u16.to_be_bytes());
+        sfnt_with_tables(&[
+            (b"OS/2", os2),
+            (b"cmap", cmap_table(&[(0x41, 0x41)])),
+            (b"fvar", fvar),
+            (b"glyf", glyf),
+            (b"gvar", gvar),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", be16(&[1000, 0, 1000, 100])),
+            (b"loca", be16(&[0, 0, 18])),
+            (b"maxp", maxp),
+            (b"name", name_table(&[(0, "Copyright Test"), (1, "Flex"), (2, "Regular"), (257, "Regular"), (258, "Black")])),
+        ])
+    }
+
+    #[test]
+    fn instantiates_variable_fonts() {
+        let font = variable_font();
+        assert_eq!(axes(&font).unwrap(), vec![Axis { tag: "wght".to_string(), min: 100.0, default: 400.0, max: 900.0 }]);
+        let names: Vec<_> = named_instances(&font).unwrap().into_iter().map(|i| i.name).collect();
+        assert_eq!(names, ["Regular", "Black"]);
+
+        let instance = instantiate(&font, &[("wght".to_string(), 650.0)]).unwrap();
+        assert_eq!((instance.style.as_str(), instance.file_name().as_str()), ("wght650", "Flex-wght650.ttf"));
+        let face = ttf_parser::Face::parse(&instance.data, 0).expect("instance parses");
+        assert!(!face.is_variable());
+        assert!(face.raw_face().table(Tag::from_bytes(b"gvar")).is_none());
+        let glyph = face.glyph_index('A').unwrap();
+        let bbox = face.glyph_bounding_box(glyph).unwrap();
+        assert_eq!((bbox.x_min, bbox.x_max, bbox.y_max), (100, 950, 800));
+        assert_eq!(face.glyph_hor_advance(glyph), Some(1050));
+        assert_eq!(face.weight().to_number(), 650);
+        assert_eq!(checksum(&instance.data), 0xB1B0_AFBA);
+
+        let descriptor = font_metadata::read_descriptor(&instance.data);
+        assert_eq!((descriptor.family.as_deref(), descriptor.style.as_deref()), (Some("Flex"), Some("wght650")));
+        let names = ttf_parser::name::Table::parse(face.raw_face().table(Tag::from_bytes(b"name")).unwrap()).unwrap();
+        assert_eq!(font_metadata::find_name(&names, 1).as_deref(), Some("Flex wght650"));
+        assert_eq!(font_metadata::find_name(&names, 0).as_deref(), Some("Copyright Test"));
+
+        let black = instantiate_named(&font, "black").unwrap();
+        assert_eq!(black.file_name(), "Flex-Black.ttf");
+        let face = ttf_parser::Face::parse(&black.data, 0).unwrap();
+        assert_eq!(face.glyph_bounding_box(GlyphId(1)).unwrap().x_max, 1000);
+
+        assert!(instantiate(&font, &[("wght".to_string(), 1000.0)]).is_err());
+        assert!(instantiate(&font, &[("wdth".to_string(), 100.0)]).is_err());
+        assert!(instantiate_named(&font, "Bold").is_err());
+        assert!(instantiate(&crate::font_metadata::tests::square_font(), &[]).is_err());
+        assert_eq!(parse_axis("wght=700"), Ok(("wght".to_string(), 700.0)));
+        assert!(parse_axis("wght").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use log::{error, warn};
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::api::{IntegrityIssue, IntegrityReport, IntegrityStatus};
+use crate::blob_store::BlobStore;
+use crate::utils::calculate_sha256;
+
+// 最近一次完整性检查的结果；同一时间只运行一次检查
+#[derive(Debug, Default)]
+pub struct IntegrityState {
+    last: RwLock<Option<IntegrityReport>>,
+    running: Mutex<()>,
+}
+
+impl IntegrityState {
+    pub fn status(&self) -> IntegrityStatus {
+        IntegrityStatus {
+            running: self.running.try_lock().is_none(),
+            last_report: self.last.read().clone(),
+        }
+    }
+
+    // 已有检查在运行时返回 None
+    pub fn run(&self, font_dir: &Path, blobs: &BlobStore, backup_dir: Option<&Path>, repair: bool) -> Option<IntegrityReport> {
+        let _running = self.running.try_lock()?;
+        let report = scrub(font_dir, blobs, backup_dir, repair);
+        *self.last.write() = Some(report.clone());
+        Some(report)
+    }
+
+    // Prometheus 文本格式，尚未运行过检查时只输出运行状态
+    pub fn metrics(&self) -> String {
+        let status = self.status();
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        gauge("fontsync_integrity_running", "Whether an integrity scrub is in progress", status.running as u64);
+        if let Some(report) = &status.last_report {
+            gauge("fontsync_integrity_last_run_timestamp_seconds", "Unix time the last scrub finished", report.finished_at);
+            gauge("fontsync_integrity_files_checked", "Files re-hashed by the last scrub", report.checked as u64);
+            gauge("fontsync_integrity_mismatches", "Files whose content did not match the store", report.issues.len() as u64);
+            gauge("fontsync_integrity_unrepaired", "Mismatched files that could not be repaired", report.unrepaired() as u64);
+        }
+        out
+    }
+}
+
+// 按名称表重新计算字体与数据的哈希。与数据硬链接的字体共用数据的结果，不再重复读取；
+// repair 为 true 时从存储中完好的副本或备份目录中的同名文件恢复
+pub fn scrub(font_dir: &Path, blobs: &BlobStore, backup_dir: Option<&Path>, repair: bool) -> IntegrityReport {
+    let mut report = IntegrityReport { started_at: unix_now(), ..Default::default() };
+    let mut by_sha256: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, sha256) in blobs.names() {
+        by_sha256.entry(sha256).or_default().push(name);
+    }
+
+    for (sha256, names) in by_sha256 {
+        let blob = blobs.blob_file(&sha256);
+        let blob_actual = calculate_sha256(&blob).ok();
+        report.checked += 1;
+        let mut good_copy = (blob_actual.as_deref() == Some(sha256.as_str())).then(|| blob.clone());
+        let mut issues = Vec::new();
+        if good_copy.is_none() {
+            issues.push(issue(font_dir, &blob, &sha256, blob_actual.clone()));
+        }
+
+        for name in &names {
+            let path = font_dir.join(name);
+            let actual = if blobs.is_linked(&sha256, &path) {
+                blob_actual.clone()
+            } else {
+                report.checked += 1;
+                calculate_sha256(&path).ok()
+            };
+            if actual.as_deref() == Some(sha256.as_str()) {
+                good_copy.get_or_insert(path);
+            } else if blobs.sha256_of(name).as_deref() == Some(sha256.as_str()) {
+                // 检查期间被替换或删除的字体不算损坏
+                issues.push(issue(font_dir, &path, &sha256, actual));
+            }
+        }
+        if issues.is_empty() {
+            continue;
+        }
+
+        if repair && good_copy.is_none() {
+            good_copy = backup_dir.and_then(|dir| find_backup(dir, &names, &sha256));
+        }
+        let repaired = match good_copy.filter(|_| repair) {
+            Some(source) => match blobs.restore(&sha256, &source, font_dir) {
+                Ok(_) => true,
+                Err(e) => {
+                    error!("Failed to repair {} from {:?}: {:#}", sha256, source, e);
+                    false
+                }
+            },
+            None => false,
+        };
+        for issue in &mut issues {
+            issue.repaired = repaired;
+            warn!(
+                "Integrity mismatch in {}: expected {}, found {} ({})",
+                issue.file,
+                issue.expected_sha256,
+                issue.actual_sha256.as_deref().unwrap_or("missing"),
+                if repaired { "repaired" } else { "not repaired" }
+            );
+        }
+        report.issues.extend(issues);
+    }
+
+    report.finished_at = unix_now();
+    report
+}
+
+fn issue(font_dir: &Path, path: &Path, expected: &str, actual: Option<String>) -> IntegrityIssue {
+    IntegrityIssue {
+        file: path.strip_prefix(font_dir).unwrap_or(path).to_string_lossy().into_owned(),
+        expected_sha256: expected.to_string(),
+        actual_sha256: actual,
+        repaired: false,
+    }
+}
+
+// 备份目录中内容相符的同名文件
+fn find_backup(dir: &Path, names: &[String], sha256: &str) -> Option<PathBuf> {
+    names
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| calculate_sha256(path).ok().as_deref() == Some(sha256))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn detects_and_repairs_corruption() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = BlobStore::open(dir.path()).expect("open");
+        fs::create_dir_all(dir.path().join(".fontsync/tmp")).unwrap();
+        let tmp = dir.path().join(".fontsync/tmp/upload");
+        fs::write(&tmp, b"original font").unwrap();
+        let sha256 = calculate_sha256(&tmp).unwrap();
+        store.store(&tmp, &sha256, "a.ttf", &dir.path().join("a.ttf")).expect("store");
+
+        let state = IntegrityState::default();
+        let clean = state.run(dir.path(), &store, None, true).expect("scrub");
+        assert!(clean.issues.is_empty());
+        assert!(clean.checked >= 1);
+
+        // 硬链接的字体与数据一起损坏，只能从备份恢复
+        fs::write(dir.path().join("a.ttf"), b"bit rotted!!!").unwrap();
+        let report = state.run(dir.path(), &store, None, true).expect("scrub");
+        assert!(!report.issues.is_empty());
+        assert!(report.issues.iter().any(|i| i.file == "a.ttf" && !i.repaired));
+        assert!(state.metrics().contains("fontsync_integrity_unrepaired"));
+
+        let backup = tempfile::tempdir().expect("backup");
+        fs::write(backup.path().join("a.ttf"), b"original font").unwrap();
+        let report = state.run(dir.path(), &store, Some(backup.path()), true).expect("scrub");
+        assert!(report.issues.iter().all(|i| i.repaired));
+        assert_eq!(fs::read(dir.path().join("a.ttf")).unwrap(), b"original font");
+        assert!(scrub(dir.path(), &store, None, false).issues.is_empty());
+        assert_eq!(state.status().last_report.unwrap().unrepaired(), 0);
+    }
+}
@@ -1,16 +1,77 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use log::info;
-use std::path::PathBuf;
-use crate::utils::scan_font_directory;
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::api::FontQuery;
+use crate::client::{ApiClient, SyncOptions, Transport};
+use crate::dedupe::DedupeAction;
+use crate::font_installer::{InstallOptions, InstallScope};
+use crate::i18n::t;
+use crate::progress::{Progress, ProgressMode};
+use crate::subset::SubsetFormat;
+use crate::utils::{scan_font_directory, ConflictPolicy, FontSort};
 
+mod access;
+mod activation;
+mod api;
+mod bench;
+mod blob_store;
+mod blocklist;
+mod bundle;
 mod client;
+mod client_state;
+mod coverage;
+mod credentials;
+mod dashboard;
+mod dedupe;
+mod delta;
+mod discovery;
+mod download_cache;
+mod e2e;
+mod event_log;
+mod font_cache;
 mod font_installer;
+mod font_metadata;
 mod font_monitor;
+#[cfg(feature = "grpc")]
+mod grpc;
 #[cfg(feature = "gui")]
 mod gui;
+mod hashing;
+mod http;
+mod i18n;
+mod identity;
+mod ignore;
+mod instancer;
+mod integrity;
+mod mappings;
+mod metadata_store;
+mod moderation;
+mod monitor;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
+mod notify;
+mod offline_queue;
+mod placeholder;
+mod preview;
+mod probe;
+mod progress;
+mod protected;
+mod reconcile;
+mod request_log;
 mod server;
+mod signing;
+mod sse;
+mod subset;
+mod sync_history;
+mod sync_report;
+mod terminal_preview;
+mod tui;
 mod utils;
+mod webfont;
 mod websocket_client;
 mod websocket_server;
 
@@ -27,23 +88,39 @@ struct Cli {
     
     #[arg(long, global = true, help = "Disable GUI mode")]
     no_gui: bool,
+
+    #[arg(long, global = true, help = "Proxy for connections to the server (http://, https://, socks5:// or socks5h://); overrides HTTP_PROXY/HTTPS_PROXY/ALL_PROXY")]
+    proxy: Option<String>,
+
+    #[arg(long, global = true, value_parser = i18n::parse_lang, help = "Language of command output (zh-CN or en-US); defaults to LC_ALL/LC_MESSAGES/LANG")]
+    lang: Option<i18n::Lang>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// 启动用于字体同步的 HTTP/WebSocket 服务器
     Serve {
-        /// 服务器主机地址
-        #[arg(long, default_value = "127.0.0.1")]
-        host: String,
+        /// 监听地址，可重复指定；支持 IPv6（如 :: 或 [::1]）与主机名（监听其解析出的全部地址）。
+        /// 未指定 --host 与 --listen 时为 127.0.0.1
+        #[arg(long = "host")]
+        hosts: Vec<String>,
+
+        /// 监听的 Unix 套接字，如 unix:/run/fontsync.sock，可重复指定；只指定 --listen 时不监听 TCP
+        #[arg(long = "listen", value_parser = server::parse_listen_address)]
+        unix_sockets: Vec<PathBuf>,
         
         /// 服务器端口
         #[arg(long, default_value_t = 8080)]
         port: u16,
         
-        /// 字体存储目录
-        #[arg(long, default_value = "./fonts")]
-        font_dir: String,
+        /// 字体目录，可重复指定；列表合并各目录的字体，同名时取先指定的目录中的文件
+        #[arg(long = "font-dir", default_value = "./fonts")]
+        font_dirs: Vec<String>,
+
+        /// 接收上传的目录，须为 --font-dir 之一，默认为第一个；其余目录只读。
+        /// 服务器状态（回收站、缓存、元数据等）也保存在这里
+        #[arg(long)]
+        upload_dir: Option<String>,
         
         /// 启用 WebSocket 通知
         #[arg(
@@ -55,6 +132,106 @@ enum Commands {
             default_missing_value = "true"
         )]
         websocket: bool,
+
+        /// 额外在端口 + 1 上监听 WebSocket，兼容旧版客户端
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        legacy_ws_port: bool,
+        
+        /// 拒绝存储 fsType 为 Restricted License（禁止再分发）的字体
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        refuse_restricted: bool,
+
+        /// 在同一地址的该端口上另外提供 gRPC 接口（需要编译 grpc 支持）
+        #[cfg(feature = "grpc")]
+        #[arg(long)]
+        grpc_port: Option<u16>,
+
+        /// 禁止存储的字体规则文件（JSON 数组，每项含 pattern 或 sha256 以及 reason）
+        #[arg(long)]
+        blocklist: Option<PathBuf>,
+
+        /// 管理接口（/admin/*）的 Bearer 令牌，也可通过环境变量 FONTSYNC_ADMIN_TOKEN 设置；未设置时管理接口关闭
+        #[arg(long)]
+        admin_token: Option<String>,
+
+        /// 访问令牌文件（JSON 数组，每项含 token、role（reader/uploader/admin）与可选的 name、quota）；设置后所有字体接口都需要令牌
+        #[arg(long)]
+        tokens: Option<PathBuf>,
+
+        /// 单个上传请求的大小上限，如 500MB、2GB
+        #[arg(long, default_value = "100MB", value_parser = utils::parse_file_size)]
+        max_upload_size: u64,
+
+        /// 同时处理的上传数，超出的请求排队等待
+        #[arg(long, default_value_t = server::DEFAULT_MAX_CONCURRENT_UPLOADS as u32, value_parser = clap::value_parser!(u32).range(1..))]
+        max_concurrent_uploads: u32,
+
+        /// 接收上传请求体的时限（秒），包括排队等待时间
+        #[arg(long, default_value_t = server::DEFAULT_UPLOAD_TIMEOUT.as_secs())]
+        upload_timeout: u64,
+
+        /// 以只读维护模式启动：下载正常，上传、删除等修改返回 503，可通过 /admin/maintenance 切换
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        read_only: bool,
+
+        /// 上传先进入待审核区，管理员通过 /admin/pending 批准后才生效
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        require_approval: bool,
+
+        /// 每个客户端（按 client_id）上传字体的总大小上限，如 2GB；超出时上传返回 507
+        #[arg(long, value_parser = utils::parse_file_size)]
+        client_quota: Option<u64>,
+
+        /// 定期完整性检查的间隔（秒），重新计算存储文件的哈希；0 表示只通过 /admin/integrity 手动运行
+        #[arg(long, default_value_t = server::DEFAULT_SCRUB_INTERVAL.as_secs())]
+        scrub_interval: u64,
+
+        /// 完整性检查发现损坏时，从存储中完好的副本或 --backup-dir 中的同名文件恢复
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        scrub_repair: bool,
+
+        /// 字体备份目录，完整性检查修复时按文件名查找内容相符的副本
+        #[arg(long)]
+        backup_dir: Option<PathBuf>,
+
+        /// 应答局域网中客户端的发现广播（UDP 端口 47800），需同时监听非回环地址
+        #[arg(long)]
+        discoverable: bool,
     },
     
     /// 启动字体监控客户端
@@ -67,9 +244,9 @@ enum Commands {
         #[arg(long, value_delimiter = ',')]
         watch_dirs: Option<Vec<String>>,
         
-        /// 用于识别的客户端 ID
-        #[arg(long, default_value = "default_client")]
-        client_id: String,
+        /// 用于识别的客户端 ID（默认使用首次运行时生成的持久 ID）
+        #[arg(long)]
+        client_id: Option<String>,
         
         /// 启用交互模式用于冲突处理
         #[arg(
@@ -81,9 +258,58 @@ enum Commands {
             default_missing_value = "true"
         )]
         interactive: bool,
+        
+        /// 非交互模式下的冲突处理策略
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Skip)]
+        on_conflict: ConflictPolicy,
+        
+        /// 团队密钥文件，设置后启用端到端加密
+        #[arg(long)]
+        e2e_key: Option<String>,
+        
+        /// 拒绝未签名或与签名清单不符的字体
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        require_signed: bool,
+        
+        /// 服务器清单签名公钥（base64），默认在首次严格校验时固定
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 只下载带有这些标签的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        
+        /// 只下载家族名匹配这些通配符的字体（逗号分隔，不区分大小写，如 "Noto Sans CJK*"）
+        #[arg(long, value_delimiter = ',')]
+        families: Vec<String>,
+        
+        /// 只下载这些格式的字体（扩展名，逗号分隔，如 otf,ttc）
+        #[arg(long, value_delimiter = ',')]
+        formats: Vec<String>,
+        
+        /// 扫描与监控时进入符号链接指向的目录，同一文件经由不同路径只处理一次
+        #[arg(long)]
+        follow_symlinks: bool,
+        
+        /// 内存中最多缓存的字体文件数，其余条目只保存在磁盘上
+        #[arg(long, default_value_t = font_cache::DEFAULT_CAPACITY)]
+        cache_size: usize,
+        
+        /// 使用同步映射配置中该名称的服务器、目录、方向与冲突策略（见 `fontsync mappings`）
+        #[arg(long, conflicts_with_all = ["server_url", "watch_dirs"])]
+        mapping: Option<String>,
     },
     
     /// 执行一次性字体同步
+    ///
+    /// 退出码：0 有传输且全部成功，1 致命错误，2 部分文件失败，3 没有需要传输的文件，130 被中断
     Sync {
         /// 服务器 URL
         #[arg(long, default_value = "http://localhost:8080")]
@@ -136,8 +362,150 @@ enum Commands {
             default_missing_value = "true"
         )]
         install: bool,
+        
+        /// 非交互模式下的冲突处理策略
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Skip)]
+        on_conflict: ConflictPolicy,
+        
+        /// 团队密钥文件，设置后启用端到端加密
+        #[arg(long)]
+        e2e_key: Option<String>,
+        
+        /// 拒绝未签名或与签名清单不符的字体
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        require_signed: bool,
+        
+        /// 服务器清单签名公钥（base64），默认在首次严格校验时固定
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 只下载带有这些标签的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        
+        /// 只下载家族名匹配这些通配符的字体（逗号分隔，不区分大小写，如 "Noto Sans CJK*"）
+        #[arg(long, value_delimiter = ',')]
+        families: Vec<String>,
+        
+        /// 只下载这些格式的字体（扩展名，逗号分隔，如 otf,ttc）
+        #[arg(long, value_delimiter = ',')]
+        formats: Vec<String>,
+        
+        /// 将每个文件的处理结果写入该路径，扩展名为 .csv 时写 CSV，否则写 JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+        
+        /// 安装已下载字体的范围：system 安装到系统字体目录，供所有用户使用
+        #[arg(long, value_enum, default_value_t = InstallScope::Auto)]
+        scope: InstallScope,
+        
+        /// 上传与安装时按字体的家族与样式把文件改名为 <Family>-<Style>.<ext>
+        #[arg(long)]
+        normalize_names: bool,
+        
+        /// 扫描与监控时进入符号链接指向的目录，同一文件经由不同路径只处理一次
+        #[arg(long)]
+        follow_symlinks: bool,
+        
+        /// 字体列表与文件传输使用的接口：grpc 需要服务器以 --grpc-port 启动（需要编译 grpc 支持）
+        #[arg(long, value_enum, default_value_t = Transport::Http)]
+        transport: Transport,
+        
+        /// 进度显示方式：bar 为终端进度条，json 为每行一个 JSON 事件，none 不显示
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar)]
+        progress: ProgressMode,
+        
+        /// 不显示进度与汇总明细，结束时只在标准输出打印一行汇总
+        #[arg(long, short, conflicts_with = "progress")]
+        quiet: bool,
+        
+        /// 依次同步同步映射配置中这些名称的映射（逗号分隔），代替 --server-url 与 --local-dir
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["server_url", "local_dir"])]
+        mapping: Vec<String>,
+        
+        /// 依次同步配置中的全部映射
+        #[arg(long, conflicts_with_all = ["server_url", "local_dir", "mapping"])]
+        all_mappings: bool,
+        
+        /// 同步结束后发送通知的通道（通知配置中的名称，逗号分隔），映射可各自指定
+        #[arg(long, value_delimiter = ',')]
+        notify: Vec<String>,
+        
+        /// 稀疏同步：本地没有的字体只写入占位条目，之后用 get 按需下载
+        #[arg(long)]
+        lazy: bool,
+    },
+    
+    /// 把服务器上的字体挂载为只读目录，打开文件时才下载（需要编译 fuse 支持）
+    #[cfg(all(feature = "fuse", target_os = "linux"))]
+    Mount {
+        /// 挂载点，须为空目录
+        mountpoint: PathBuf,
+        
+        /// 服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+        
+        /// 只显示带有这些标签的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        
+        /// 只显示家族名匹配这些通配符的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        families: Vec<String>,
+        
+        /// 只显示这些格式的字体（扩展名，逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        formats: Vec<String>,
+    },
+    
+    /// 下载并安装稀疏同步中占位的字体
+    Get {
+        /// 字体家族名（支持通配符，不区分大小写）或文件名
+        family: String,
+        
+        /// 同步目录
+        #[arg(long, default_value = "./local_fonts")]
+        local_dir: String,
+        
+        /// 团队密钥文件，用于解密端到端加密的字体
+        #[arg(long)]
+        e2e_key: Option<String>,
+        
+        /// 服务器清单签名公钥（base64）
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 安装下载的字体
+        #[arg(
+            long,
+            default_value_t = true,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        install: bool,
+        
+        /// 安装范围
+        #[arg(long, value_enum, default_value_t = InstallScope::Auto)]
+        scope: InstallScope,
+        
+        /// 按字体的家族与样式把安装后的文件命名为 <Family>-<Style>.<ext>
+        #[arg(long)]
+        normalize_names: bool,
     },
     
+    /// 列出同步映射配置中的本地目录与服务器的对应关系
+    Mappings,
+    
     /// 从目录安装字体
     Install {
         /// 包含字体文件的目录
@@ -154,9 +522,39 @@ enum Commands {
             default_missing_value = "true"
         )]
         verbose: bool,
+        
+        /// 安装范围：auto 在有管理员权限时安装到系统，否则只为当前用户安装
+        #[arg(long, value_enum, default_value_t = InstallScope::Auto)]
+        scope: InstallScope,
+        
+        /// 按字体的家族与样式把安装后的文件命名为 <Family>-<Style>.<ext>
+        #[arg(long)]
+        normalize_names: bool,
+    },
+    
+    /// 查找内容重复或版本不一致的本地字体
+    Dedupe {
+        /// 要检查的目录（可重复指定），默认为系统字体目录，靠前目录中的副本会被保留
+        #[arg(long = "dir")]
+        dirs: Vec<String>,
+        
+        /// 处理多余副本，默认只输出报告
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        apply: bool,
+        
+        /// 多余副本的处理方式
+        #[arg(long, value_enum, default_value_t = DedupeAction::Remove)]
+        action: DedupeAction,
     },
     
-    /// 列出系统字体目录
+    /// 按字体家族列出系统字体，或列出服务器上的字体
     ListFonts {
         /// 显示包含 SHA256 的详细信息
         #[arg(
@@ -168,52 +566,428 @@ enum Commands {
             default_missing_value = "true"
         )]
         detailed: bool,
+        
+        /// 只显示名称包含该文本的字体家族
+        #[arg(long, conflicts_with = "remote")]
+        family: Option<String>,
+        
+        /// 列出服务器上的字体而不是本地字体
+        #[arg(long, value_name = "SERVER_URL")]
+        remote: Option<String>,
+        
+        /// 按文件名或家族名搜索（仅 --remote）
+        #[arg(long, short = 'q', requires = "remote")]
+        query: Option<String>,
+        
+        /// 只列出这些格式，如 ttf,otf（仅 --remote）
+        #[arg(long, requires = "remote")]
+        format: Option<String>,
+        
+        /// 最小文件大小（字节，仅 --remote）
+        #[arg(long, requires = "remote")]
+        min_size: Option<u64>,
+        
+        /// 排序字段（仅 --remote）
+        #[arg(long, value_enum, requires = "remote")]
+        sort: Option<FontSort>,
+        
+        /// 跳过前若干条结果（仅 --remote）
+        #[arg(long, requires = "remote")]
+        offset: Option<usize>,
+        
+        /// 最多显示的条数（仅 --remote）
+        #[arg(long, requires = "remote")]
+        limit: Option<usize>,
+        
+        /// 只列出支持这些字符的字体，如 U+4E2D（仅 --remote）
+        #[arg(long, requires = "remote")]
+        covers: Option<String>,
     },
     
-    /// 启动 GUI 界面（需要编译 GUI 支持）
-    #[cfg(feature = "gui")]
-    Gui {
-        /// 以服务器模式启动
+    /// 分析字体的 Unicode 覆盖范围
+    Coverage {
+        /// 字体文件路径，或系统字体中的家族名
+        target: String,
+    },
+    
+    /// 从可变字体生成静态实例，供不支持可变字体的旧程序使用
+    Instantiate {
+        /// 可变字体文件（TrueType 轮廓）
+        font: PathBuf,
+        
+        /// 轴坐标，如 wght=700，可重复；未指定的轴取默认值
+        #[arg(long = "axis", value_parser = instancer::parse_axis)]
+        axes: Vec<(String, f32)>,
+        
+        /// 字体中命名实例的样式名，如 Bold
+        #[arg(long, conflicts_with = "axes")]
+        instance: Option<String>,
+        
+        /// 列出可变轴与命名实例后退出
         #[arg(long)]
-        server: bool,
+        list: bool,
         
-        /// 以客户端模式启动
+        /// 输出路径（默认在原字体旁保存为 <Family>-<Style>.ttf）
         #[arg(long)]
-        client: bool,
+        output: Option<PathBuf>,
         
-        /// 客户端模式的服务器 URL
+        /// 生成后安装到系统
+        #[arg(long)]
+        install: bool,
+        
+        /// 生成后上传到该服务器
+        #[arg(long)]
+        upload: Option<String>,
+    },
+    
+    /// 生成只包含指定字符的子集字体，用于网页或应用打包
+    Subset {
+        /// 字体文件（TrueType 轮廓）
+        font: PathBuf,
+        
+        /// 需要保留的文字
+        #[arg(long)]
+        text: Option<String>,
+        
+        /// 从文件读取需要保留的文字，如项目的全部文案
+        #[arg(long)]
+        text_file: Option<PathBuf>,
+        
+        /// 需要保留的 Unicode 范围，如 U+0020-007E,U+4E00-9FFF
+        #[arg(long)]
+        unicodes: Option<String>,
+        
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = SubsetFormat::Ttf)]
+        format: SubsetFormat,
+        
+        /// 输出路径（默认在原字体旁保存为 <name>.subset.<ext>）
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// 在终端中预览字体的示例文字，字体可以是本地文件或服务器上的字体名
+    Preview {
+        /// 字体文件路径，或服务器上的字体文件名
+        font: String,
+        
+        /// 示例文字
+        #[arg(long)]
+        text: Option<String>,
+        
+        /// 字号（像素），默认按显示方式选择
+        #[arg(long)]
+        size: Option<f32>,
+        
+        /// 显示方式；auto 在支持 sixel 的终端中显示图像，否则使用半块字符
+        #[arg(long, value_enum, default_value_t = terminal_preview::TerminalMode::Auto)]
+        mode: terminal_preview::TerminalMode,
+        
+        /// 本地没有该文件时从此服务器获取
         #[arg(long, default_value = "http://localhost:8080")]
         server_url: String,
     },
-}
-
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let command = cli.command;
     
-    // 初始化日志
-    if cli.verbose {
-        env_logger::Builder::from_default_env()
-            .filter_level(log::LevelFilter::Debug)
-            .init();
-    } else {
-        env_logger::init();
-    }
+    /// 登录服务器并将令牌保存到系统密钥环
+    Login {
+        /// 服务器 URL
+        server_url: String,
+        
+        /// 访问令牌（省略时交互输入）
+        #[arg(long)]
+        token: Option<String>,
+    },
     
-    // 处理 GUI 模式
-    #[cfg(feature = "gui")]
-    {
-        if !cli.no_gui {
-            if let Some(Commands::Gui { .. }) = &command {
-                info!("Starting GUI interface...");
-                return gui::run_gui().map_err(|e| anyhow::anyhow!("GUI error: {}", e));
-            }
-
-            if command.is_none() {
-                info!("Starting GUI interface (default)...");
-                return gui::run_gui().map_err(|e| anyhow::anyhow!("GUI error: {}", e));
-            }
-
+    /// 从系统密钥环删除服务器令牌
+    Logout {
+        /// 服务器 URL
+        server_url: String,
+    },
+    
+    /// 显示过去的同步记录与各字体的处理结果
+    History {
+        /// 只显示该字体的记录
+        #[arg(long)]
+        font: Option<String>,
+        
+        /// 只显示与该服务器的同步，并从其事件日志补充其他客户端的操作
+        #[arg(long)]
+        server_url: Option<String>,
+        
+        /// 最多显示的条数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    
+    /// 固定字体的版本，与服务器内容不同时不再自动下载或上传
+    Pin {
+        /// 字体文件名
+        font: String,
+        
+        /// 允许同步的内容 SHA256（省略时保留本地现有版本）
+        #[arg(long)]
+        sha: Option<String>,
+    },
+    
+    /// 取消字体的版本固定
+    Unpin {
+        /// 字体文件名
+        font: String,
+    },
+    
+    /// 导出或安装离线字体包，供无法连接服务器的机器使用
+    Bundle {
+        #[command(subcommand)]
+        action: BundleAction,
+    },
+    
+    /// 管理实时同步的下载缓存目录
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    
+    /// 启用字体文件；--temporary 时只在当前会话内启用，不安装到系统
+    Activate {
+        /// 字体文件路径
+        #[arg(required = true)]
+        fonts: Vec<PathBuf>,
+        
+        /// 只在当前会话内启用，命令保持运行，按 Ctrl+C 或执行 deactivate 后停用
+        #[arg(long)]
+        temporary: bool,
+    },
+    
+    /// 停用临时启用的字体（文件名或路径），不指定时停用全部
+    Deactivate {
+        fonts: Vec<String>,
+    },
+    
+    /// 逐步检查与服务器的连接（DNS、TCP、TLS、/healthz、协议版本、令牌与 WebSocket），给出失败原因与处理建议
+    Doctor {
+        /// 服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+    },
+    
+    /// 显示同步状态、固定的字体与离线队列中待提交的变更
+    Status {
+        /// 服务器 URL（省略时显示所有服务器）
+        server_url: Option<String>,
+    },
+    
+    /// 显示服务器上字体数量、占用空间与上传活动的统计
+    Stats {
+        /// 服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+        
+        /// 以 JSON 输出
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        json: bool,
+    },
+    
+    /// 测量本地哈希与服务器传输的吞吐量，用于排查同步慢的原因
+    Bench {
+        /// 同时测量从该服务器下载的吞吐量
+        #[arg(long)]
+        server_url: Option<String>,
+        
+        /// 同时测量上传吞吐量：样本字体以临时名称上传后立即删除，连接中的客户端会收到对应的通知
+        #[arg(long, requires = "server_url")]
+        upload: bool,
+        
+        /// 哈希测量使用的数据量
+        #[arg(long, default_value = "64MB", value_parser = utils::parse_file_size)]
+        hash_size: u64,
+    },
+    
+    /// 生成端到端加密使用的团队密钥
+    GenerateKey {
+        /// 密钥输出路径
+        #[arg(long, default_value = "fontsync-team.key")]
+        output: String,
+    },
+    
+    /// 在终端界面中逐个选择同步操作，并实时查看传输进度
+    Tui {
+        /// 服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+        
+        /// 本地字体目录
+        #[arg(long, default_value = "./local_fonts")]
+        local_dir: String,
+        
+        /// 安装已下载字体
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        install: bool,
+        
+        /// 团队密钥文件，设置后启用端到端加密
+        #[arg(long)]
+        e2e_key: Option<String>,
+        
+        /// 拒绝未签名或与签名清单不符的字体
+        #[arg(
+            long,
+            default_value_t = false,
+            action = clap::ArgAction::Set,
+            value_parser = clap::builder::BoolishValueParser::new(),
+            num_args = 0..=1,
+            default_missing_value = "true"
+        )]
+        require_signed: bool,
+        
+        /// 服务器清单签名公钥（base64），默认在首次严格校验时固定
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 扫描与监控时进入符号链接指向的目录，同一文件经由不同路径只处理一次
+        #[arg(long)]
+        follow_symlinks: bool,
+    },
+    
+    /// 启动 GUI 界面（需要编译 GUI 支持）
+    #[cfg(feature = "gui")]
+    Gui {
+        /// 以服务器模式启动
+        #[arg(long)]
+        server: bool,
+        
+        /// 以客户端模式启动
+        #[arg(long)]
+        client: bool,
+        
+        /// 客户端模式的服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum BundleAction {
+    /// 把服务器上的字体连同签名清单打包为一个文件
+    Create {
+        /// 服务器 URL
+        #[arg(long, default_value = "http://localhost:8080")]
+        server_url: String,
+        
+        /// 字体包输出路径，如 team-fonts.fsbundle
+        #[arg(long, short)]
+        output: PathBuf,
+        
+        /// 服务器清单签名公钥（base64），默认使用同步时固定的公钥
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 只打包带有这些标签的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        
+        /// 只打包家族名匹配这些通配符的字体（逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        families: Vec<String>,
+        
+        /// 只打包这些格式的字体（扩展名，逗号分隔）
+        #[arg(long, value_delimiter = ',')]
+        formats: Vec<String>,
+        
+        /// 只打包该事件序号或日期（如 2024-05-01）之后新增或修改的字体，并记录之后删除的字体
+        #[arg(long, value_parser = bundle::parse_since)]
+        since: Option<bundle::Since>,
+    },
+    
+    /// 校验字体包的签名与内容后安装其中的字体
+    Install {
+        /// 字体包文件
+        bundle: PathBuf,
+        
+        /// 来源服务器的清单签名公钥（base64），默认使用同步时固定的公钥
+        #[arg(long)]
+        server_key: Option<String>,
+        
+        /// 安装范围
+        #[arg(long, value_enum, default_value_t = InstallScope::Auto)]
+        scope: InstallScope,
+        
+        /// 按字体的家族与样式把安装后的文件命名为 <Family>-<Style>.<ext>
+        #[arg(long)]
+        normalize_names: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// 显示下载缓存中的副本数量与占用空间
+    Status,
+    
+    /// 删除下载缓存中的副本，不指定条件时删除全部；已安装字体的内容哈希会保留
+    Clean {
+        /// 只删除早于该时长下载的副本，如 30d、12h
+        #[arg(long, value_parser = utils::parse_duration)]
+        older_than: Option<Duration>,
+        
+        /// 之后仍超过该大小时从最早的副本开始删除，如 2G
+        #[arg(long, value_parser = utils::parse_file_size)]
+        max_size: Option<u64>,
+    },
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", i18n::describe_error(&e));
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    if let Some(lang) = cli.lang {
+        i18n::set(lang);
+    }
+    let command = cli.command;
+    
+    // 初始化日志
+    if cli.verbose {
+        env_logger::Builder::from_default_env()
+            .filter_level(log::LevelFilter::Debug)
+            .init();
+    } else {
+        env_logger::init();
+    }
+
+    if let Some(proxy) = &cli.proxy {
+        http::set_proxy(proxy)?;
+    }
+    
+    // 处理 GUI 模式
+    #[cfg(feature = "gui")]
+    {
+        if !cli.no_gui {
+            if let Some(Commands::Gui { .. }) = &command {
+                info!("Starting GUI interface...");
+                return gui::run_gui().map_err(|e| anyhow::anyhow!("GUI error: {}", e));
+            }
+
+            if command.is_none() {
+                info!("Starting GUI interface (default)...");
+                return gui::run_gui().map_err(|e| anyhow::anyhow!("GUI error: {}", e));
+            }
+
             // 检查是否需要默认启动 GUI
             if std::env::var("FONT_SYNC_GUI").is_ok() {
                 info!("Starting GUI interface (via environment variable)...");
@@ -247,23 +1021,116 @@ fn main() -> Result<()> {
 
     runtime.block_on(async move {
         match command {
-            Some(Commands::Serve { host, port, font_dir, websocket }) => {
-                info!("Starting font server on {}:{}", host, port);
+            Some(Commands::Serve {
+                mut hosts,
+                unix_sockets,
+                port,
+                font_dirs,
+                upload_dir,
+                websocket,
+                legacy_ws_port,
+                refuse_restricted,
+                blocklist,
+                admin_token,
+                tokens,
+                max_upload_size,
+                max_concurrent_uploads,
+                upload_timeout,
+                read_only,
+                require_approval,
+                client_quota,
+                scrub_interval,
+                scrub_repair,
+                backup_dir,
+                discoverable,
+                #[cfg(feature = "grpc")]
+                grpc_port,
+            }) => {
+                if hosts.is_empty() && unix_sockets.is_empty() {
+                    hosts.push("127.0.0.1".to_string());
+                }
+                if !hosts.is_empty() {
+                    info!("Starting font server on {} port {}", hosts.join(", "), port);
+                }
+                let (font_dir, all_font_dirs) = server::resolve_font_dirs(&font_dirs, upload_dir.as_deref())?;
                 info!("Font directory: {}", font_dir);
+                for dir in all_font_dirs.iter().filter(|dir| dir.as_path() != Path::new(&font_dir)) {
+                    info!("Read-only font directory: {}", dir.display());
+                }
                 info!("WebSocket enabled: {}", websocket);
                 
+                let policy = server::ServerPolicy {
+                    refuse_restricted,
+                    #[cfg(feature = "grpc")]
+                    grpc_port,
+                    #[cfg(not(feature = "grpc"))]
+                    grpc_port: None,
+                    blocklist: std::sync::Arc::new(blocklist::Blocklist::open(Path::new(&font_dir), blocklist.as_deref())?),
+                    admin_token: admin_token
+                        .or_else(|| std::env::var("FONTSYNC_ADMIN_TOKEN").ok())
+                        .filter(|t| !t.trim().is_empty()),
+                    max_upload_size,
+                    upload_slots: std::sync::Arc::new(tokio::sync::Semaphore::new(max_concurrent_uploads as usize)),
+                    upload_timeout: Duration::from_secs(upload_timeout),
+                    maintenance: std::sync::Arc::new(parking_lot::RwLock::new(
+                        read_only.then(|| server::DEFAULT_MAINTENANCE_MESSAGE.to_string()),
+                    )),
+                    tokens: std::sync::Arc::new(match &tokens {
+                        Some(path) => access::AccessTokens::load(path)?,
+                        None => access::AccessTokens::default(),
+                    }),
+                    moderation: if require_approval {
+                        Some(std::sync::Arc::new(moderation::PendingUploads::open(Path::new(&font_dir))?))
+                    } else {
+                        None
+                    },
+                    client_quota,
+                    scrub_interval: (scrub_interval > 0).then(|| Duration::from_secs(scrub_interval)),
+                    scrub_repair,
+                    backup_dir,
+                    integrity: Default::default(),
+                    discoverable,
+                    upload_locks: Default::default(),
+                    font_dirs: std::sync::Arc::new(all_font_dirs),
+                };
+                if require_approval {
+                    info!("Uploads require admin approval");
+                }
+                if !policy.tokens.is_open() {
+                    info!("Token authentication enabled; anonymous requests are refused");
+                }
+                if read_only {
+                    info!("Read-only maintenance mode enabled");
+                }
+                info!(
+                    "Upload limits: {} per request, {} concurrent, {}s timeout",
+                    utils::format_file_size(max_upload_size),
+                    max_concurrent_uploads,
+                    upload_timeout
+                );
                 if websocket {
-                    server::start_server_with_websocket(host, port, font_dir, true).await?;
+                    server::start_server_with_websocket(hosts, unix_sockets, port, font_dir, true, legacy_ws_port, policy).await?;
                 } else {
-                    server::start_server(host, port, font_dir, false).await?;
+                    server::start_server(hosts, unix_sockets, port, font_dir, false, false, policy).await?;
                 }
             }
             
-            Some(Commands::Monitor { server_url, watch_dirs, client_id, interactive: _ }) => {
+            Some(Commands::Monitor { server_url, watch_dirs, client_id, interactive, on_conflict, e2e_key, require_signed, server_key, tags, families, formats, follow_symlinks, cache_size, mapping }) => {
+                let mapping = match mapping {
+                    Some(name) => mappings::select(&mappings::load()?, &[name])?.pop(),
+                    None => None,
+                };
+                let server_url = mapping.as_ref().map_or(server_url, |m| m.server_url.clone());
+                let watch_dirs = mapping.as_ref().map_or(watch_dirs, |m| Some(vec![m.local_path().to_string_lossy().into_owned()]));
                 info!("Starting font monitor client");
                 info!("Server URL: {}", server_url);
+                let client_id = client_id
+                    .unwrap_or_else(|| identity::ClientIdentity::current().client_id.clone());
                 info!("Client ID: {}", client_id);
-                info!("Interactive mode: {}", false);
+                // 后台运行时没有终端可供询问，改用 --on-conflict 策略
+                let interactive = interactive && std::io::stdin().is_terminal();
+                info!("Interactive mode: {}", interactive);
+                info!("On conflict: {:?}", on_conflict);
                 
                 let watch_paths = if let Some(dirs) = watch_dirs {
                     dirs.into_iter().map(PathBuf::from).collect()
@@ -273,28 +1140,242 @@ fn main() -> Result<()> {
                 
                 info!("Monitoring directories: {:?}", watch_paths);
                 
-                run_monitor_client(server_url, watch_paths, client_id, false).await?;
+                let options = SyncOptions {
+                    interactive,
+                    on_conflict,
+                    e2e_key: load_team_key(e2e_key)?,
+                    require_signed,
+                    trusted_signing_key: server_key,
+                    tags,
+                    families,
+                    formats,
+                    follow_symlinks,
+                    ..SyncOptions::default()
+                };
+                let (options, direction) = match &mapping {
+                    Some(mapping) => (mapping.sync_options(&options), mapping.direction),
+                    None => (options, mappings::MappingDirection::Both),
+                };
+                run_monitor_client(server_url, watch_paths, client_id, options, cache_size, direction).await?;
             }
             
-            Some(Commands::Sync { server_url, local_dir, interactive, upload, download, install }) => {
+            Some(Commands::Sync { server_url, local_dir, interactive, upload, download, install, on_conflict, e2e_key, require_signed, server_key, tags, families, formats, report, scope, normalize_names, follow_symlinks, transport, progress, quiet, mapping, all_mappings, notify, lazy }) => {
+                let selected = match (all_mappings, mapping.is_empty()) {
+                    (true, _) => mappings::load()?,
+                    (false, false) => mappings::select(&mappings::load()?, &mapping)?,
+                    (false, true) => Vec::new(),
+                };
+                if all_mappings && selected.is_empty() {
+                    anyhow::bail!("No sync mappings are configured in {:?}", mappings::config_path());
+                }
                 info!("Performing one-time font synchronization");
-                info!("Server URL: {}", server_url);
-                info!("Local directory: {}", local_dir);
+                if selected.is_empty() {
+                    info!("Server URL: {}", server_url);
+                    info!("Local directory: {}", local_dir);
+                }
                 info!("Interactive mode: {}", interactive);
+                info!("On conflict: {:?}", on_conflict);
                 info!("Upload: {}", upload);
                 info!("Download: {}", download);
                 info!("Install: {}", install);
                 
-                run_sync_command(server_url, local_dir, interactive, upload, download, install).await?;
+                let options = SyncOptions {
+                    interactive,
+                    on_conflict,
+                    e2e_key: load_team_key(e2e_key)?,
+                    require_signed,
+                    trusted_signing_key: server_key,
+                    tags,
+                    families,
+                    formats,
+                    install_scope: scope,
+                    normalize_names,
+                    follow_symlinks,
+                    lazy,
+                    transport,
+                    ..SyncOptions::default()
+                };
+                let progress = if quiet { ProgressMode::None } else { progress };
+                let notifiers = notify::load()?;
+                let code = if selected.is_empty() {
+                    let notifiers = notify::select(&notifiers, &notify)?;
+                    let result = run_sync_command(server_url.clone(), local_dir, options, upload, download, install, report, progress, quiet).await;
+                    notify::notify(&notifiers, &notify::SyncSummary::new(None, &server_url, &result)).await;
+                    result?.0
+                } else {
+                    run_mapped_sync(&selected, options, upload, download, install, report, progress, quiet, &notifiers, &notify).await?
+                };
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+            
+            Some(Commands::Get { family, local_dir, e2e_key, server_key, install, scope, normalize_names }) => {
+                let options = SyncOptions {
+                    e2e_key: load_team_key(e2e_key)?,
+                    trusted_signing_key: server_key,
+                    install_scope: scope,
+                    normalize_names,
+                    ..SyncOptions::default()
+                };
+                let code = run_get_command(&family, Path::new(&local_dir), &options, install).await?;
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+            
+            #[cfg(all(feature = "fuse", target_os = "linux"))]
+            Some(Commands::Mount { mountpoint, server_url, tags, families, formats }) => {
+                let subscription = api::Subscription::new(&families, &tags, &formats);
+                mount::mount(&server_url, &mountpoint, subscription, shutdown_signal()).await?;
             }
             
-            Some(Commands::Install { font_dir, verbose }) => {
+            Some(Commands::Mappings) => {
+                run_mappings_command()?;
+            }
+            
+            Some(Commands::Dedupe { dirs, apply, action }) => {
+                run_dedupe_command(dirs, apply, action).await?;
+            }
+            
+            Some(Commands::Install { font_dir, verbose, scope, normalize_names }) => {
                 info!("Installing fonts from directory: {}", font_dir);
-                run_install_command(font_dir, verbose).await?;
+                run_install_command(font_dir, verbose, InstallOptions { scope, normalize_names, link: false }).await?;
+            }
+            
+            Some(Commands::ListFonts { detailed, family, remote, query, format, min_size, sort, offset, limit, covers }) => {
+                match remote {
+                    Some(server_url) => {
+                        let query = FontQuery { q: query, format, min_size, sort, offset, limit, covers, ..FontQuery::default() };
+                        run_remote_list_fonts_command(server_url, query, detailed).await?;
+                    }
+                    None => run_list_fonts_command(detailed, family).await?,
+                }
+            }
+            
+            Some(Commands::Coverage { target }) => {
+                run_coverage_command(target).await?;
+            }
+            
+            Some(Commands::Instantiate { font, axes, instance, list, output, install, upload }) => {
+                run_instantiate_command(font, axes, instance, list, output, install, upload).await?;
+            }
+            
+            Some(Commands::Subset { font, text, text_file, unicodes, format, output }) => {
+                run_subset_command(font, text, text_file, unicodes, format, output)?;
+            }
+            
+            Some(Commands::Preview { font, text, size, mode, server_url }) => {
+                run_preview_command(&font, text, size, mode, &server_url).await?;
+            }
+            
+            Some(Commands::Login { server_url, token }) => {
+                let token = match token {
+                    Some(token) => token,
+                    None => dialoguer::Password::new()
+                        .with_prompt(format!("Token for {}", server_url))
+                        .interact()?,
+                };
+                credentials::save_token(&server_url, token.trim())?;
+                println!("Saved token for {}", server_url);
+            }
+            
+            Some(Commands::Logout { server_url }) => {
+                credentials::delete_token(&server_url)?;
+                println!("Removed token for {}", server_url);
+            }
+            
+            Some(Commands::History { font, server_url, limit }) => {
+                run_history_command(font, server_url, limit).await?;
+            }
+            
+            Some(Commands::Pin { font, sha }) => {
+                if let Some(sha) = &sha
+                    && (sha.len() != 64 || !sha.chars().all(|c| c.is_ascii_hexdigit()))
+                {
+                    anyhow::bail!("--sha must be a hex SHA256 digest");
+                }
+                let sha = sha.map(|sha| sha.to_ascii_lowercase());
+                client_state::ClientState::pin(&font, sha.clone())?;
+                match sha {
+                    Some(sha) => println!("Pinned {} at {}", font, sha),
+                    None => println!("Pinned {} at its local version", font),
+                }
+            }
+            
+            Some(Commands::Unpin { font }) => {
+                if client_state::ClientState::unpin(&font)? {
+                    println!("Unpinned {}", font);
+                } else {
+                    println!("{} was not pinned", font);
+                }
+            }
+            
+            Some(Commands::Bundle { action }) => {
+                run_bundle_command(action).await?;
+            }
+            
+            Some(Commands::Cache { action }) => {
+                run_cache_command(action)?;
             }
             
-            Some(Commands::ListFonts { detailed }) => {
-                run_list_fonts_command(detailed).await?;
+            Some(Commands::Activate { fonts, temporary }) => {
+                if temporary {
+                    activation::hold(fonts, shutdown_signal()).await?;
+                } else {
+                    let results = font_installer::install_fonts(fonts, InstallOptions::default(), &Progress::bars()).await;
+                    let (installed, failed, skipped) = font_installer::tally(&results);
+                    info!("Activation complete: {} installed, {} failed, {} skipped", installed, failed, skipped);
+                }
+            }
+            
+            Some(Commands::Deactivate { fonts }) => {
+                let removed = activation::deactivate(&fonts)?;
+                if removed.is_empty() {
+                    println!("No matching temporarily activated fonts");
+                }
+                for activation in removed {
+                    println!("Deactivated {} (held by process {})", activation.path.display(), activation.pid);
+                }
+            }
+            
+            Some(Commands::Doctor { server_url }) => {
+                let client_id = identity::ClientIdentity::current().client_id.clone();
+                let report = probe::run(&server_url, &client_id).await;
+                print!("{}", report);
+                println!("{}", report.summary());
+                if report.failure().is_some() {
+                    std::process::exit(1);
+                }
+            }
+            
+            Some(Commands::Status { server_url }) => {
+                run_status_command(server_url)?;
+            }
+            
+            Some(Commands::Stats { server_url, json }) => {
+                run_stats_command(server_url, json).await?;
+            }
+            
+            Some(Commands::Bench { server_url, upload, hash_size }) => {
+                run_bench_command(server_url, upload, hash_size).await?;
+            }
+            
+            Some(Commands::GenerateKey { output }) => {
+                e2e::TeamKey::generate()?.save(&PathBuf::from(&output))?;
+                println!("Team key written to {}", output);
+                println!("Share it with team members over a secure channel; the server never needs it.");
+            }
+            
+            Some(Commands::Tui { server_url, local_dir, install, e2e_key, require_signed, server_key, follow_symlinks }) => {
+                let options = SyncOptions {
+                    e2e_key: load_team_key(e2e_key)?,
+                    require_signed,
+                    trusted_signing_key: server_key,
+                    follow_symlinks,
+                    ..SyncOptions::default()
+                };
+                tui::run_tui(server_url, local_dir, install, options).await?;
             }
 
             None => {
@@ -317,73 +1398,404 @@ fn main() -> Result<()> {
     })
 }
 
+fn load_team_key(path: Option<String>) -> Result<Option<e2e::TeamKey>> {
+    path.map(|p| e2e::TeamKey::load(&PathBuf::from(p))).transpose()
+}
+
 async fn run_monitor_client(
     server_url: String,
     watch_paths: Vec<PathBuf>,
     client_id: String,
-    _interactive: bool,
+    options: SyncOptions,
+    cache_size: usize,
+    direction: mappings::MappingDirection,
 ) -> Result<()> {
-    info!("Starting real-time font monitoring...");
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            // 持续运行直到被中断
+            if tokio::signal::ctrl_c().await.is_ok() {
+                shutdown.cancel();
+            }
+        }
+    });
+    info!("Press Ctrl+C to stop font monitoring.");
+    let config = monitor::MonitorConfig { server_url, watch_paths, client_id, options, cache_size, direction };
+    monitor::run(config, shutdown, |_| {}).await
+}
+
+async fn run_bundle_command(action: BundleAction) -> Result<()> {
+    match action {
+        BundleAction::Create { server_url, output, server_key, tags, families, formats, since } => {
+            let options = SyncOptions { trusted_signing_key: server_key, tags, families, formats, ..SyncOptions::default() };
+            let manifest = bundle::create(&server_url, &output, &options, since).await?;
+            println!(
+                "Bundled {} font(s) ({}) and {} removal(s) from {} into {}",
+                manifest.fonts.len(),
+                utils::format_file_size(manifest.total_size()),
+                manifest.removed.len(),
+                manifest.source_server,
+                output.display()
+            );
+            if let Some(seq) = manifest.latest_seq {
+                println!("Server event log is at #{}; use --since {} for the next differential bundle", seq, seq);
+            }
+        }
+        BundleAction::Install { bundle, server_key, scope, normalize_names } => {
+            let opened = bundle::open(&bundle, server_key.as_deref())?;
+            info!(
+                "Verified bundle from {} created at {}",
+                opened.manifest.source_server,
+                format_timestamp(opened.manifest.created_at)
+            );
+            if let Some(seq) = opened.manifest.since_seq {
+                info!("Differential bundle with changes after event #{}", seq);
+            }
+            let options = InstallOptions { scope, normalize_names, link: false };
+            let results = font_installer::install_fonts_from_directory(opened.font_dir(), options, &Progress::bars()).await?;
+            for result in &results {
+                if let Err(e) = &result.result
+                    && !result.is_skipped()
+                {
+                    error!("Failed to install {}: {:#}", result.file_name(), e);
+                }
+            }
+            let removed = bundle::apply_removals(&opened.manifest).await?;
+            let (installed, failed, skipped) = font_installer::tally(&results);
+            println!("Installed {} font(s), {} failed, {} skipped, {} removed", installed, failed, skipped, removed);
+        }
+    }
+    Ok(())
+}
+
+fn run_cache_command(action: CacheAction) -> Result<()> {
+    let dir = download_cache::download_dir();
+    let state = client_state::ClientState::load();
+    match action {
+        CacheAction::Status => {
+            let status = download_cache::status(&dir, &state);
+            println!("{}", console::style(dir.display()).bold());
+            println!("  Cached copies:   {} ({})", status.files, utils::format_file_size(status.size));
+            println!("  Linked copies:   {} (sharing data with installed fonts)", status.linked);
+            println!("  Oldest copy:     {}", status.oldest.map(format_timestamp).unwrap_or_else(|| "-".to_string()));
+            println!("  Recorded hashes: {}", status.recorded);
+        }
+        CacheAction::Clean { older_than, max_size } => {
+            let now = chrono::Utc::now().timestamp() as u64;
+            let summary = download_cache::clean(&dir, &state, older_than, max_size, now)?;
+            println!("Removed {} cached copies, freed {}", summary.removed, utils::format_file_size(summary.freed));
+        }
+    }
+    Ok(())
+}
+
+fn run_status_command(server_url: Option<String>) -> Result<()> {
+    let state = client_state::ClientState::load();
+    let queue = offline_queue::OfflineQueue::load();
+    
+    let mut servers: Vec<String> = match server_url {
+        Some(url) => vec![url.trim_end_matches('/').to_string()],
+        None => state.servers.keys().chain(queue.servers.keys()).cloned().collect(),
+    };
+    servers.sort();
+    servers.dedup();
     
-    // 创建字体监控器
-    let mut monitor = font_monitor::FontMonitor::new();
-    for path in watch_paths {
-        monitor.add_watch_path(path);
+    if !state.pins.is_empty() {
+        println!("{}", console::style("Pinned fonts").bold());
+        for (font, pin) in &state.pins {
+            println!("  {:<32} {}", font, pin.sha256.as_deref().unwrap_or("local version"));
+        }
+        println!();
     }
     
-    // 初始扫描
-    let initial_fonts = monitor.scan_fonts().await?;
-    info!("Found {} fonts during initial scan", initial_fonts.len());
+    let activations = activation::list();
+    if !activations.is_empty() {
+        println!("{}", console::style("Temporarily activated fonts").bold());
+        for activation in &activations {
+            println!("  {:<32} process {}", activation.file_name(), activation.pid);
+        }
+        println!();
+    }
     
-    // 连接 WebSocket 服务器
-    let _ws_client = websocket_client::start_websocket_client(server_url, client_id).await?;
+    if servers.is_empty() {
+        println!("No servers have been synchronized yet.");
+        return Ok(());
+    }
     
-    // 开始监控
-    let mut event_receiver = monitor.take_event_receiver()
-        .context("Failed to get event receiver")?;
+    for (i, server) in servers.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", console::style(server).bold());
+        let last_seq = state.server(server).and_then(|s| s.last_event_seq);
+        println!("  Last event:      {}", last_seq.map(|seq| format!("#{}", seq)).unwrap_or_else(|| "-".to_string()));
+        println!("  Pending changes: {}", queue.depth(server));
+        for change in queue.pending(server) {
+            let (op, filename) = match &change.op {
+                offline_queue::PendingOp::Upload { .. } => ("upload", change.filename.clone()),
+                offline_queue::PendingOp::Remove { .. } => ("remove", change.filename.clone()),
+                offline_queue::PendingOp::Rename { from, .. } => ("rename", format!("{} -> {}", from, change.filename)),
+            };
+            let queued_at = chrono::DateTime::from_timestamp(change.queued_at as i64, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            println!("    {:<6} {}  {}", op, filename, console::style(queued_at).dim());
+        }
+    }
     
-    monitor.start_monitoring().await?;
+    Ok(())
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+// 本地同步记录与服务器事件日志合并为按时间倒序的列表
+async fn run_history_command(font: Option<String>, server_url: Option<String>, limit: usize) -> Result<()> {
+    use sync_report::FileAction;
     
-    // 处理字体事件
-    tokio::spawn(async move {
-        while let Some(event) = event_receiver.recv().await {
-            match event {
-                font_monitor::FontEvent::Added(path, sha256) => {
-                    info!("Font added: {:?} (SHA256: {}...)", 
-                        path.file_name().unwrap_or_default(), 
-                        &sha256[..8]
-                    );
-                }
-                font_monitor::FontEvent::Modified(path, sha256) => {
-                    info!("Font modified: {:?} (SHA256: {}...)", 
-                        path.file_name().unwrap_or_default(), 
-                        &sha256[..8]
-                    );
-                }
-                font_monitor::FontEvent::Removed(path) => {
-                    info!("Font removed: {:?}", path.file_name().unwrap_or_default());
+    let direction = |direction: utils::SyncDirection| match direction {
+        utils::SyncDirection::Upload => "upload",
+        utils::SyncDirection::Download => "download",
+    };
+    let server_filter = server_url.as_deref().map(|url| url.trim_end_matches('/'));
+    let matches_font = |name: &str| font.as_deref().is_none_or(|font| font == name);
+    let mut entries: Vec<(u64, String)> = Vec::new();
+    
+    for run in sync_history::load() {
+        if server_filter.is_some_and(|url| url != run.server_url) {
+            continue;
+        }
+        let mut lines = Vec::new();
+        for file in run.files.iter().filter(|f| matches_font(&f.filename)) {
+            let mut line = format!("{:<18} {:<8} {}", file.action.as_str(), direction(file.direction), file.filename);
+            if let Some(error) = &file.error {
+                line.push_str(&format!("  ({})", error));
+            }
+            lines.push((file.timestamp.max(run.started_at), line));
+        }
+        for conflict in run.conflicts.iter().filter(|c| matches_font(&c.filename)) {
+            let mut line = format!(
+                "{:<18} {:<8} {}  ({:?})",
+                "conflicted",
+                direction(conflict.direction),
+                conflict.filename,
+                conflict.resolution
+            );
+            if let Some(renamed) = &conflict.renamed_to {
+                line.push_str(&format!(" -> {}", renamed));
+            }
+            lines.push((run.finished_at, line));
+        }
+        
+        if font.is_some() {
+            entries.extend(lines.into_iter().map(|(at, line)| (at, format!("{}  {}  {}", format_timestamp(at), run.server_url, line))));
+            continue;
+        }
+        let mut text = format!(
+            "{}  {}  {} uploaded, {} downloaded, {} installed, {} conflicted, {} skipped{}",
+            console::style(format_timestamp(run.started_at)).bold(),
+            run.server_url,
+            run.count(FileAction::Uploaded) + run.count(FileAction::AwaitingApproval),
+            run.count(FileAction::Downloaded),
+            run.count(FileAction::Installed),
+            run.conflicts.len(),
+            run.skipped + run.count(FileAction::Pinned),
+            if run.cancelled { ", cancelled" } else { "" }
+        );
+        for (_, line) in lines {
+            text.push_str("\n    ");
+            text.push_str(&line);
+        }
+        entries.push((run.started_at, text));
+    }
+    
+    // 服务器事件日志中的变更，包括其他客户端的操作
+    if let Some(server_url) = &server_url {
+        let api = ApiClient::new(server_url)?;
+        let mut since = 0;
+        loop {
+            let page = api.events(since, None).await?;
+            let Some(last) = page.events.last() else {
+                break;
+            };
+            since = last.seq;
+            for record in page.events {
+                let (action, filename, matched) = match &record.event {
+                    websocket_server::WebSocketMessage::FontAdded { filename, .. } => ("added", filename.clone(), matches_font(filename)),
+                    websocket_server::WebSocketMessage::FontModified { filename, .. } => ("modified", filename.clone(), matches_font(filename)),
+                    websocket_server::WebSocketMessage::FontRemoved { filename, .. } => ("removed", filename.clone(), matches_font(filename)),
+                    // 按原名称或新名称查询都能看到改名
+                    websocket_server::WebSocketMessage::FontRenamed { from, to, .. } => {
+                        ("renamed", format!("{} -> {}", from, to), matches_font(from) || matches_font(to))
+                    }
+                    _ => continue,
+                };
+                if !matched {
+                    continue;
                 }
+                let actor = record.actor.as_ref().map(|a| format!(" by {}", a)).unwrap_or_default();
+                entries.push((
+                    record.timestamp,
+                    format!("{}  {}  server: {} {}{}", format_timestamp(record.timestamp), server_url, action, filename, actor),
+                ));
+            }
+            if since >= page.latest_seq {
+                break;
             }
         }
-    });
+    }
+    
+    if entries.is_empty() {
+        println!("No sync history recorded yet.");
+        return Ok(());
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+    for (_, text) in entries.into_iter().take(limit) {
+        println!("{}", text);
+    }
+    Ok(())
+}
+
+async fn run_bench_command(server_url: Option<String>, upload: bool, hash_size: u64) -> Result<()> {
+    let hash_size = usize::try_from(hash_size).context("--hash-size is too large")?;
+    let results = tokio::task::spawn_blocking(move || bench::hashing(hash_size)).await??;
+    bench::print_table("Hashing", &results);
     
-    info!("Font monitoring started. Press Ctrl+C to stop.");
+    let Some(server_url) = server_url else { return Ok(()) };
+    let api = ApiClient::new(&server_url)?;
+    let sample = bench::transfer_sample(&api).await?;
+    if sample.is_empty() {
+        println!();
+        println!("{} has no fonts to measure transfers with", server_url);
+        return Ok(());
+    }
+    
+    println!();
+    let downloads = bench::downloads(&api, &sample).await?;
+    bench::print_table(&format!("Download ({} fonts from {})", sample.len(), server_url), &downloads);
     
-    // 持续运行直到被中断
-    tokio::signal::ctrl_c().await?;
-    info!("Shutting down font monitor...");
+    let uploads = if upload {
+        println!();
+        let (uploads, leftovers) = bench::uploads(&api, &sample).await?;
+        bench::print_table(&format!("Upload ({} fonts to {})", sample.len(), server_url), &uploads);
+        if !leftovers.is_empty() {
+            println!("Could not remove benchmark uploads: {}", leftovers.join(", "));
+        }
+        uploads
+    } else {
+        Vec::new()
+    };
     
+    println!();
+    if let Some(best) = bench::fastest(&downloads) {
+        println!("Fastest download: {}", best.label);
+    }
+    if let Some(best) = bench::fastest(&uploads) {
+        println!("Fastest upload:   {}", best.label);
+    }
     Ok(())
 }
 
+async fn run_stats_command(server_url: String, json: bool) -> Result<()> {
+    let client = ApiClient::new(&server_url)?;
+    let stats = client.stats().await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    
+    println!("{}", console::style(&server_url).bold());
+    // 旧版服务器没有 /version
+    if let Ok(version) = client.version().await {
+        println!("  Version:     {} (protocol {})", version.version, version.protocol);
+    }
+    println!("  Fonts:       {}", stats.total_fonts);
+    println!("  Total size:  {}", utils::format_file_size(stats.total_bytes));
+    
+    let print_counts = |title: &str, counts: &std::collections::BTreeMap<String, usize>| {
+        if counts.is_empty() {
+            return;
+        }
+        let mut counts: Vec<_> = counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        println!();
+        println!("{}", title);
+        for (name, count) in counts {
+            println!("  {:>6}  {}", count, if name.is_empty() { "(none)" } else { name });
+        }
+    };
+    print_counts("Formats:", &stats.formats);
+    print_counts("Families:", &stats.families);
+    
+    if !stats.largest.is_empty() {
+        println!();
+        println!("Largest fonts:");
+        for font in &stats.largest {
+            println!("  {:>10}  {}", utils::format_file_size(font.size), font.name);
+        }
+    }
+    
+    print_counts("Uploads by client:", &stats.uploads_by_client);
+    
+    if !stats.recent_activity.is_empty() {
+        println!();
+        println!("Recent activity:");
+        for record in stats.recent_activity.iter().rev() {
+            let (action, filename) = match &record.event {
+                websocket_server::WebSocketMessage::FontAdded { filename, .. } => ("added", filename.clone()),
+                websocket_server::WebSocketMessage::FontModified { filename, .. } => ("modified", filename.clone()),
+                websocket_server::WebSocketMessage::FontRemoved { filename, .. } => ("removed", filename.clone()),
+                websocket_server::WebSocketMessage::FontRenamed { from, to, .. } => ("renamed", format!("{} -> {}", from, to)),
+                _ => continue,
+            };
+            let at = chrono::DateTime::from_timestamp(record.timestamp as i64, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let actor = record.actor.as_ref().map(|a| format!(" by {}", a)).unwrap_or_default();
+            println!("  {}  {:<8} {}{}", console::style(at).dim(), action, filename, actor);
+        }
+    }
+    
+    Ok(())
+}
+
+// Ctrl+C，或 Unix 上的 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_sync_command(
     server_url: String,
     local_dir: String,
-    interactive: bool,
+    options: SyncOptions,
     upload: bool,
     download: bool,
     install: bool,
-) -> Result<()> {
+    report_path: Option<PathBuf>,
+    progress_mode: ProgressMode,
+    quiet: bool,
+) -> Result<(i32, String)> {
     let local_dir_path = PathBuf::from(&local_dir);
     
     // 本地目录不存在时创建
@@ -395,47 +1807,339 @@ async fn run_sync_command(
     
     let mut total_uploaded = 0;
     let mut total_downloaded = 0;
+    let mut report = sync_report::SyncReport::with_progress(progress_mode.into());
+    let started_at = chrono::Utc::now().timestamp() as u64;
+    
+    let cancel = options.cancel.clone();
+    let signal = tokio::spawn(async move {
+        shutdown_signal().await;
+        warn!("Interrupted, cancelling sync...");
+        cancel.cancel();
+    });
     
-    if upload {
+    if upload && !options.cancel.is_cancelled() {
         info!("Uploading local fonts to server...");
-        let (uploaded, _) = client::upload_local_fonts(&server_url, &local_dir_path, interactive).await?;
+        let (uploaded, _) = client::upload_local_fonts(&server_url, &local_dir_path, &options, &mut report).await?;
         total_uploaded += uploaded;
         info!("Upload complete: {} fonts uploaded", uploaded);
     }
     
-    if download {
+    if download && !options.cancel.is_cancelled() {
         info!("Downloading fonts from server...");
-        let (downloaded, _) = client::download_server_fonts(&server_url, &local_dir_path, interactive).await?;
+        let (downloaded, _) = client::download_server_fonts(&server_url, &local_dir_path, &options, &mut report).await?;
         total_downloaded += downloaded;
         info!("Download complete: {} fonts downloaded", downloaded);
     }
     
+    signal.abort();
+    let cancelled = options.cancel.is_cancelled();
+    if let Some(path) = &report_path {
+        report.write(path)?;
+        info!("Sync report written to {:?}", path);
+    }
+    if cancelled {
+        let removed = utils::remove_partial_files(&local_dir_path);
+        if removed > 0 {
+            info!("Removed {} partial files", removed);
+        }
+        if quiet {
+            println!("{}", t!("sync.cancelled", summary = report.summary()));
+        } else {
+            eprintln!("{}", report.summary_text(true));
+        }
+        sync_history::record(&server_url, started_at, &report, true);
+        return Ok((report.exit_code(true), report.summary()));
+    }
+    
     if install && total_downloaded > 0 {
         info!("Installing downloaded fonts...");
-        let (installed, failed) = client::install_downloaded_fonts(&local_dir_path).await?;
+        let (installed, failed) = client::install_downloaded_fonts(&local_dir_path, &options, &mut report).await?;
         info!("Installation complete: {} installed, {} failed", installed, failed);
     }
     
-    info!("Synchronization complete: {} uploaded, {} downloaded", total_uploaded, total_downloaded);
+    sync_history::record(&server_url, started_at, &report, false);
+    debug!("Synchronization complete: {} uploaded, {} downloaded", total_uploaded, total_downloaded);
+    // 汇总总是输出；--quiet 时只输出统计一行，JSON 进度占用标准输出时改写到标准错误
+    if quiet {
+        println!("{}", t!("sync.complete", summary = report.summary()));
+    } else if progress_mode == ProgressMode::Json {
+        eprintln!("{}", report.summary_text(false));
+    } else {
+        println!("{}", report.summary_text(false));
+    }
+    
+    Ok((report.exit_code(false), report.summary()))
+}
+
+// 依次同步各映射，各自使用映射的服务器、目录、方向与冲突策略；有报告路径时每个映射写一份，
+// 每个映射结束后发送到映射指定（未指定时为 --notify）的通知通道。
+// 退出码取最严重的结果：被中断时不再同步其余映射
+#[allow(clippy::too_many_arguments)]
+async fn run_mapped_sync(
+    selected: &[mappings::SyncMapping],
+    options: SyncOptions,
+    upload: bool,
+    download: bool,
+    install: bool,
+    report_path: Option<PathBuf>,
+    progress: ProgressMode,
+    quiet: bool,
+    notifiers: &[notify::NotifierConfig],
+    notify_names: &[String],
+) -> Result<i32> {
+    // 先检查全部映射的通知配置，避免同步到一半才出错
+    let mapping_notifiers = selected
+        .iter()
+        .map(|mapping| {
+            let names = if mapping.notify.is_empty() { notify_names } else { &mapping.notify };
+            notify::select(notifiers, names)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut codes = Vec::new();
+    for (mapping, notifiers) in selected.iter().zip(&mapping_notifiers) {
+        info!("Syncing mapping '{}': {:?} <-> {}", mapping.name, mapping.local_path(), mapping.server_url);
+        let report_path = report_path.as_ref().map(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            match path.extension() {
+                Some(ext) => path.with_file_name(format!("{}.{}.{}", stem, mapping.name, ext.to_string_lossy())),
+                None => path.with_file_name(format!("{}.{}", stem, mapping.name)),
+            }
+        });
+        let result = run_sync_command(
+            mapping.server_url.clone(),
+            mapping.local_path().to_string_lossy().into_owned(),
+            mapping.sync_options(&options),
+            upload && mapping.direction.uploads(),
+            download && mapping.direction.downloads(),
+            install,
+            report_path,
+            progress,
+            quiet,
+        )
+        .await;
+        notify::notify(notifiers, &notify::SyncSummary::new(Some(&mapping.name), &mapping.server_url, &result)).await;
+        let (code, _) = result?;
+        codes.push(code);
+        if code == sync_report::EXIT_INTERRUPTED {
+            break;
+        }
+    }
+    let code = [sync_report::EXIT_INTERRUPTED, sync_report::EXIT_PARTIAL_FAILURE, 0]
+        .into_iter()
+        .find(|code| codes.contains(code))
+        .unwrap_or(sync_report::EXIT_NOTHING_TO_DO);
+    Ok(code)
+}
+
+// 本地文件优先，否则从服务器下载；输出不是终端时不使用 sixel
+async fn run_preview_command(
+    font: &str,
+    text: Option<String>,
+    size: Option<f32>,
+    mode: terminal_preview::TerminalMode,
+    server_url: &str,
+) -> Result<()> {
+    let data = if Path::new(font).is_file() {
+        tokio::fs::read(font).await.with_context(|| format!("Failed to read {}", font))?
+    } else {
+        ApiClient::new(server_url)?.fetch_font(font).await?.0.to_vec()
+    };
+    let term = console::Term::stdout();
+    let mode = match mode {
+        terminal_preview::TerminalMode::Auto if !term.is_term() => terminal_preview::TerminalMode::Blocks,
+        mode => mode.resolve(|name| std::env::var(name).ok()),
+    };
+    let columns = if term.is_term() { term.size().1 as usize } else { 80 };
+    let text = text.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| preview::DEFAULT_TEXT.to_string());
+    let size = size.unwrap_or(mode.default_size());
+    if !size.is_finite() {
+        anyhow::bail!("Invalid size: {}", size);
+    }
+    let size = size.clamp(preview::MIN_SIZE, preview::MAX_SIZE);
+    let output = terminal_preview::render(&data, &text, size, mode, columns)
+        .with_context(|| format!("Cannot preview '{}' (end-to-end encrypted fonts must be downloaded first)", font))?;
+    print!("{}", output);
+    Ok(())
+}
+
+// 按服务器分组下载匹配的占位字体，再一并安装
+async fn run_get_command(family: &str, local_dir: &Path, options: &SyncOptions, install: bool) -> Result<i32> {
+    let mut servers: std::collections::BTreeMap<String, Vec<placeholder::Placeholder>> = Default::default();
+    for placeholder in placeholder::list(local_dir).into_iter().filter(|p| p.matches(family)) {
+        servers.entry(placeholder.server_url.clone()).or_default().push(placeholder);
+    }
+    if servers.is_empty() {
+        anyhow::bail!("No placeholders matching '{}' in {:?}", family, local_dir);
+    }
+    
+    let mut report = sync_report::SyncReport::with_progress(Progress::bars());
+    let mut paths = Vec::new();
+    for (server_url, placeholders) in &servers {
+        info!("Downloading {} font(s) from {}", placeholders.len(), server_url);
+        paths.extend(client::materialize_placeholders(server_url, local_dir, placeholders, options, &mut report).await?);
+    }
+    if install && !paths.is_empty() {
+        let results = font_installer::install_fonts(paths, options.install_options(), &report.progress).await;
+        for font in results.iter().filter_map(|r| r.installed()) {
+            report.record_installed(font);
+        }
+    }
+    println!("{}", report.summary_text(false));
+    Ok(report.exit_code(false))
+}
+
+fn run_mappings_command() -> Result<()> {
+    let path = mappings::config_path();
+    let mappings = mappings::load()?;
+    if mappings.is_empty() {
+        println!("No sync mappings are configured. Add them to {}", path.display());
+        return Ok(());
+    }
+    println!("{}", console::style(path.display()).bold());
+    for mapping in &mappings {
+        println!();
+        println!("  {}", console::style(&mapping.name).bold());
+        println!("    Local directory: {}", mapping.local_path().display());
+        println!("    Server:          {}", mapping.server_url);
+        println!("    Direction:       {:?}", mapping.direction);
+        if let Some(policy) = mapping.on_conflict {
+            println!("    On conflict:     {:?}", policy);
+        }
+        for (label, values) in [("Tags", &mapping.tags), ("Families", &mapping.families), ("Formats", &mapping.formats)] {
+            if !values.is_empty() {
+                println!("    {:<17}{}", format!("{}:", label), values.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_dedupe_command(dirs: Vec<String>, apply: bool, action: DedupeAction) -> Result<()> {
+    let dirs: Vec<PathBuf> = if dirs.is_empty() {
+        utils::get_system_font_directories()
+    } else {
+        dirs.into_iter().map(PathBuf::from).collect()
+    };
+    
+    // 删除或替换副本时不进入链接到其他位置的目录
+    let mut fonts = Vec::new();
+    let mut seen = HashSet::new();
+    for dir in &dirs {
+        let mut found = scan_font_directory(dir, false, &mut seen).await?;
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        fonts.extend(found);
+    }
+    
+    let duplicates = dedupe::find_duplicates(&fonts);
+    println!("Identical files: {} set(s)", duplicates.len());
+    for set in &duplicates {
+        println!();
+        println!("  SHA256: {}...", &set.sha256[..16]);
+        println!("  {} {}", console::style("keep").green(), set.keep.display());
+        for path in &set.redundant {
+            println!("  {} {}", console::style("dup ").yellow(), path.display());
+        }
+    }
+    
+    let conflicts = dedupe::find_version_conflicts(&fonts);
+    println!();
+    println!("Same family and style with different versions: {}", conflicts.len());
+    for conflict in &conflicts {
+        println!();
+        println!("  {} {}", console::style(&conflict.family).bold(), conflict.style);
+        for (path, version) in &conflict.fonts {
+            println!("    {:<16} {}", version.as_deref().unwrap_or("-"), path.display());
+        }
+    }
+    
+    if !apply {
+        if !duplicates.is_empty() {
+            println!();
+            println!("Run again with --apply to {} redundant copies.", match action {
+                DedupeAction::Remove => "remove",
+                DedupeAction::Symlink => "symlink",
+            });
+        }
+        return Ok(());
+    }
+    
+    // 版本不一致需要人工判断，只处理内容完全相同的副本
+    let mut handled = 0;
+    let mut failed = 0;
+    for set in &duplicates {
+        match dedupe::apply(set, action) {
+            Ok(count) => handled += count,
+            Err(e) => {
+                error!("{:#}", e);
+                failed += 1;
+            }
+        }
+    }
+    println!();
+    println!("Handled {} redundant file(s), {} set(s) failed", handled, failed);
     
     Ok(())
 }
 
-async fn run_install_command(font_dir: String, verbose: bool) -> Result<()> {
+async fn run_install_command(font_dir: String, verbose: bool, options: InstallOptions) -> Result<()> {
     let font_dir_path = PathBuf::from(&font_dir);
     
     if !font_dir_path.exists() {
         return Err(anyhow::anyhow!("Font directory does not exist: {}", font_dir));
     }
     
+    // 没有管理员权限时询问是否通过 UAC 提权重新执行安装
+    #[cfg(target_os = "windows")]
+    if options.scope == InstallScope::System && !font_installer::is_elevated() {
+        let relaunch = std::io::stdin().is_terminal()
+            && dialoguer::Confirm::new()
+                .with_prompt("Installing for all users requires administrator rights. Relaunch elevated?")
+                .default(true)
+                .interact()?;
+        if !relaunch {
+            return Err(anyhow::anyhow!(
+                "Installing for all users requires administrator rights; run as administrator or use --scope user"
+            ));
+        }
+        let dir = font_dir_path.canonicalize().unwrap_or_else(|_| font_dir_path.clone());
+        let mut args = vec!["install".to_string(), "--font-dir".to_string(), dir.to_string_lossy().into_owned()];
+        args.extend(["--scope".to_string(), "system".to_string()]);
+        if verbose {
+            args.push("--verbose".to_string());
+        }
+        if options.normalize_names {
+            args.push("--normalize-names".to_string());
+        }
+        let code = font_installer::relaunch_elevated(&args)?;
+        if code != 0 {
+            return Err(anyhow::anyhow!("Elevated install exited with code {}", code));
+        }
+        info!("Elevated install finished");
+        return Ok(());
+    }
+    
     info!("Installing fonts from directory: {}", font_dir);
     
-    let (installed, failed) = font_installer::install_fonts_from_directory(&font_dir_path).await?;
+    let results = font_installer::install_fonts_from_directory(&font_dir_path, options, &Progress::bars()).await?;
+    let (installed, failed, skipped) = font_installer::tally(&results);
     
     if verbose {
         info!("Installation details:");
+        for result in &results {
+            match &result.result {
+                Ok(font) => info!(
+                    "  installed {} (family: {}, style: {})",
+                    result.file_name(),
+                    font.family.as_deref().unwrap_or("unknown"),
+                    font.style.as_deref().unwrap_or("unknown")
+                ),
+                Err(e) if result.is_skipped() => info!("  skipped   {} ({:#})", result.file_name(), e),
+                Err(e) => info!("  failed    {} ({:#})", result.file_name(), e),
+            }
+        }
         info!("  Successfully installed: {} fonts", installed);
         info!("  Failed to install: {} fonts", failed);
+        info!("  Skipped (protected): {} fonts", skipped);
     } else {
         info!("Installation complete: {} installed, {} failed", installed, failed);
     }
@@ -443,31 +2147,215 @@ async fn run_install_command(font_dir: String, verbose: bool) -> Result<()> {
     Ok(())
 }
 
-async fn run_list_fonts_command(detailed: bool) -> Result<()> {
+async fn run_list_fonts_command(detailed: bool, family: Option<String>) -> Result<()> {
     let font_dirs = utils::get_system_font_directories();
     
     println!("System font directories:");
+    let mut fonts = Vec::new();
+    let mut seen = HashSet::new();
     for (i, dir) in font_dirs.iter().enumerate() {
         println!("  {}. {}", i + 1, dir.display());
         
-        if detailed && dir.exists() {
-            match scan_font_directory(dir).await {
-                Ok(fonts) => {
-                    for font in fonts {
-                        println!("     - {} ({})", 
-                            font.path.file_name().unwrap_or_default().to_string_lossy(),
-                            utils::format_file_size(font.size)
-                        );
-                        if detailed {
-                            println!("       SHA256: {}...", &font.sha256[..16]);
-                        }
-                    }
+        if dir.exists() {
+            match scan_font_directory(dir, true, &mut seen).await {
+                Ok(found) => fonts.extend(found),
+                Err(e) => println!("     Error scanning directory: {}", e),
+            }
+        }
+    }
+    
+    // 家族名过滤不区分大小写，按子串匹配
+    if let Some(family) = family.as_deref().map(str::to_lowercase) {
+        fonts.retain(|font| {
+            font.descriptor
+                .family
+                .as_deref()
+                .is_some_and(|f| f.to_lowercase().contains(&family))
+        });
+    }
+    
+    let groups = utils::group_by_family(fonts);
+    println!();
+    println!("Font families: {}", groups.len());
+    for group in &groups {
+        let name = group.family.as_deref().unwrap_or("(unknown family)");
+        let mut header = format!("{} ({} files)", console::style(name).bold(), group.fonts.len());
+        if group.has_mixed_versions() {
+            header.push_str(&format!("  {}", console::style("mixed versions").yellow().bold()));
+        }
+        println!();
+        println!("{}", header);
+        println!("    {:<20} {:>6}  {:<16} File", "Style", "Weight", "Version");
+        
+        for font in &group.fonts {
+            let descriptor = &font.descriptor;
+            println!(
+                "    {:<20} {:>6}  {:<16} {}",
+                descriptor.style.as_deref().unwrap_or("-"),
+                descriptor.weight.map(|w| w.to_string()).unwrap_or_else(|| "-".to_string()),
+                descriptor.version.as_deref().unwrap_or("-"),
+                font.path.display()
+            );
+            if detailed {
+                println!("       Size: {}", utils::format_file_size(font.size));
+                println!("       SHA256: {}...", &font.sha256[..16]);
+                if let Some(embedding) = font.embedding {
+                    println!("       Embedding: {}", embedding);
                 }
-                Err(e) => {
-                    println!("     Error scanning directory: {}", e);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+async fn run_instantiate_command(
+    font: PathBuf,
+    axes: Vec<(String, f32)>,
+    instance: Option<String>,
+    list: bool,
+    output: Option<PathBuf>,
+    install: bool,
+    upload: Option<String>,
+) -> Result<()> {
+    let data = std::fs::read(&font).with_context(|| format!("Failed to read {:?}", font))?;
+    if list {
+        for axis in instancer::axes(&data)? {
+            println!("{}  {} .. {} (default {})", axis.tag, axis.min, axis.max, axis.default);
+        }
+        for named in instancer::named_instances(&data)? {
+            let coordinates: Vec<String> = named.coordinates.iter().map(|(tag, value)| format!("{}={}", tag, value)).collect();
+            println!("{:<20} {}", named.name, coordinates.join(" "));
+        }
+        return Ok(());
+    }
+    
+    let instance = match instance {
+        Some(name) => instancer::instantiate_named(&data, &name)?,
+        None => instancer::instantiate(&data, &axes)?,
+    };
+    let path = output.unwrap_or_else(|| font.with_file_name(instance.file_name()));
+    std::fs::write(&path, &instance.data).with_context(|| format!("Failed to write {:?}", path))?;
+    println!("Wrote {} {} to {}", instance.family, instance.style, path.display());
+    
+    if install {
+        let installed = font_installer::install_font(&path, InstallOptions::default()).await?;
+        println!("Installed {}", installed.label());
+    }
+    if let Some(server_url) = upload {
+        let filename = path.file_name().and_then(|n| n.to_str()).context("Invalid output file name")?;
+        let sha256 = utils::calculate_sha256(&path)?;
+        let response = ApiClient::new(&server_url)?.upload_font(&path, filename, &sha256, None, true).await?;
+        println!("Uploaded {} ({})", response.filename, response.action);
+    }
+    Ok(())
+}
+
+fn run_subset_command(
+    font: PathBuf,
+    text: Option<String>,
+    text_file: Option<PathBuf>,
+    unicodes: Option<String>,
+    format: SubsetFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let mut text = text.unwrap_or_default();
+    if let Some(path) = text_file {
+        text.push_str(&std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?);
+    }
+    let codepoints = subset::requested_codepoints(Some(&text), unicodes.as_deref())?;
+    let data = std::fs::read(&font).with_context(|| format!("Failed to read {:?}", font))?;
+    let subset_font = format.encode(subset::subset(&data, &codepoints)?)?;
+    
+    let filename = font.file_name().and_then(|n| n.to_str()).unwrap_or("font.ttf");
+    let path = output.unwrap_or_else(|| font.with_file_name(subset::subset_filename(filename, format)));
+    std::fs::write(&path, &subset_font).with_context(|| format!("Failed to write {:?}", path))?;
+    println!(
+        "Wrote {} ({} code points, {} -> {})",
+        path.display(),
+        codepoints.len(),
+        utils::format_file_size(data.len() as u64),
+        utils::format_file_size(subset_font.len() as u64)
+    );
+    Ok(())
+}
+
+async fn run_coverage_command(target: String) -> Result<()> {
+    // 参数不是文件时按家族名在系统字体目录中查找
+    let paths: Vec<PathBuf> = if Path::new(&target).is_file() {
+        vec![PathBuf::from(&target)]
+    } else {
+        let mut paths = Vec::new();
+        let mut seen = HashSet::new();
+        for dir in utils::get_system_font_directories() {
+            for font in scan_font_directory(&dir, true, &mut seen).await? {
+                if font.descriptor.family.as_deref().is_some_and(|f| f.eq_ignore_ascii_case(&target)) {
+                    paths.push(font.path);
                 }
             }
         }
+        paths
+    };
+    
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!("No font file or family named '{}'", target));
+    }
+    
+    for path in paths {
+        println!("{}", path.display());
+        let data = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let Some(codepoints) = coverage::read_codepoints(&data) else {
+            println!("    Unable to read cmap table");
+            continue;
+        };
+        println!("    {} code points mapped", codepoints.len());
+        for range in coverage::range_coverage(&codepoints) {
+            println!(
+                "    {:<12} {:>6}/{:<6} {:>5.1}%",
+                range.name,
+                range.covered,
+                range.total,
+                range.ratio() * 100.0
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+async fn run_remote_list_fonts_command(server_url: String, query: FontQuery, detailed: bool) -> Result<()> {
+    let font_list = ApiClient::new(&server_url)?.list_fonts(&query).await?;
+    
+    println!("Fonts on {}:", server_url);
+    for font in &font_list.fonts {
+        let family = font.family.as_deref().map(|f| format!(" [{}]", f)).unwrap_or_default();
+        println!("  - {} ({}){}", font.name, utils::format_file_size(font.size), family);
+        if detailed {
+            if font.is_pending() {
+                println!("       SHA256: (hashing)");
+            } else if font.is_awaiting_approval() {
+                println!("       SHA256: {}... (awaiting approval)", &font.sha256[..16.min(font.sha256.len())]);
+            } else {
+                println!("       SHA256: {}...", &font.sha256[..16.min(font.sha256.len())]);
+            }
+            if !font.tags.is_empty() {
+                println!("       Tags: {}", font.tags.iter().cloned().collect::<Vec<_>>().join(", "));
+            }
+            if !font.unicode_ranges.is_empty() {
+                println!("       Unicode: {}", font.unicode_ranges.join(", "));
+            }
+            if let Some(uploaded_by) = &font.uploaded_by {
+                println!("       Uploaded by: {}", uploaded_by);
+            }
+        }
+    }
+    
+    let total = font_list.total.unwrap_or(font_list.fonts.len());
+    let start = query.offset.unwrap_or(0);
+    if font_list.fonts.is_empty() {
+        println!("No fonts shown ({} matching)", total);
+    } else {
+        println!("Showing {}-{} of {}", start + 1, start + font_list.fonts.len(), total);
     }
     
     Ok(())
@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::client::SyncOptions;
+use crate::client_state::ClientState;
+use crate::utils::ConflictPolicy;
+
+// 本地目录与服务器的同步映射，JSON 数组，每项一个映射
+const MAPPINGS_FILE: &str = "mappings.json";
+
+// 映射的同步方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingDirection {
+    #[default]
+    Both,
+    // 只把本地变更上传到服务器
+    Upload,
+    // 只接收服务器上的字体
+    Download,
+}
+
+impl MappingDirection {
+    pub fn uploads(self) -> bool {
+        self != MappingDirection::Download
+    }
+
+    pub fn downloads(self) -> bool {
+        self != MappingDirection::Upload
+    }
+}
+
+// 一个本地目录与服务器的对应关系；同一主机上的多个字体库可以是反向代理下的不同路径前缀，
+// 如 https://fonts.example.com/brand
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncMapping {
+    pub name: String,
+    // 以 ~/ 开头时相对于用户主目录
+    pub local_dir: PathBuf,
+    pub server_url: String,
+    #[serde(default)]
+    pub direction: MappingDirection,
+    // 未设置时使用命令行的 --on-conflict
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictPolicy>,
+    // 设置后替代命令行的同类下载条件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub families: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<String>,
+    // 同步结束后发送通知的通道名称，设置后替代命令行的 --notify
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<String>,
+}
+
+impl SyncMapping {
+    pub fn local_path(&self) -> PathBuf {
+        match self.local_dir.strip_prefix("~") {
+            Ok(rest) => dirs::home_dir().map(|home| home.join(rest)).unwrap_or_else(|| self.local_dir.clone()),
+            Err(_) => self.local_dir.clone(),
+        }
+    }
+
+    // 命令行选项叠加映射自己的冲突策略与下载条件
+    pub fn sync_options(&self, options: &SyncOptions) -> SyncOptions {
+        let mut options = options.clone();
+        if let Some(policy) = self.on_conflict {
+            options.on_conflict = policy;
+        }
+        for (configured, option) in [
+            (&self.tags, &mut options.tags),
+            (&self.families, &mut options.families),
+            (&self.formats, &mut options.formats),
+        ] {
+            if !configured.is_empty() {
+                *option = configured.clone();
+            }
+        }
+        options
+    }
+
+    #[cfg(any(feature = "gui", test))]
+    fn serves(&self, server_url: &str) -> bool {
+        self.server_url.trim_end_matches('/') == server_url.trim_end_matches('/')
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    ClientState::state_dir().join(MAPPINGS_FILE)
+}
+
+// 没有配置文件时返回空列表
+pub fn load() -> Result<Vec<SyncMapping>> {
+    load_from(&config_path())
+}
+
+fn load_from(path: &Path) -> Result<Vec<SyncMapping>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read sync mappings {:?}", path)),
+    };
+    let mappings: Vec<SyncMapping> =
+        serde_json::from_str(&content).with_context(|| format!("Invalid sync mappings in {:?}", path))?;
+    let mut names = HashSet::new();
+    if let Some(duplicate) = mappings.iter().find(|m| !names.insert(m.name.as_str())) {
+        bail!("Sync mapping '{}' is defined more than once in {:?}", duplicate.name, path);
+    }
+    Ok(mappings)
+}
+
+// 按名称选出映射，保持命令行中的顺序
+pub fn select(mappings: &[SyncMapping], names: &[String]) -> Result<Vec<SyncMapping>> {
+    names
+        .iter()
+        .map(|name| {
+            mappings
+                .iter()
+                .find(|m| &m.name == name)
+                .cloned()
+                .with_context(|| format!("No sync mapping named '{}' in {:?}", name, config_path()))
+        })
+        .collect()
+}
+
+// 指向该服务器的映射，GUI 按输入的服务器地址选用
+#[cfg(any(feature = "gui", test))]
+pub fn for_server<'a>(mappings: &'a [SyncMapping], server_url: &'a str) -> impl Iterator<Item = &'a SyncMapping> {
+    mappings.iter().filter(move |m| m.serves(server_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_mappings_and_applies_their_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MAPPINGS_FILE);
+        assert!(load_from(&path).unwrap().is_empty());
+
+        fs::write(
+            &path,
+            r#"[
+                {"name": "brand", "local_dir": "~/Design/Fonts", "server_url": "https://fonts.example.com/brand/",
+                 "on_conflict": "overwrite-remote", "tags": ["brand"]},
+                {"name": "personal", "local_dir": "/home/me/.fonts", "server_url": "http://nas:8080", "direction": "download"}
+            ]"#,
+        )
+        .unwrap();
+        let mappings = load_from(&path).unwrap();
+        assert_eq!(mappings[0].direction, MappingDirection::Both);
+        assert!(!mappings[1].direction.uploads() && mappings[1].direction.downloads());
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(mappings[0].local_path(), home.join("Design/Fonts"));
+        }
+        assert_eq!(mappings[1].local_path(), PathBuf::from("/home/me/.fonts"));
+
+        let cli = SyncOptions { tags: vec!["cli".to_string()], formats: vec!["otf".to_string()], ..SyncOptions::default() };
+        let options = mappings[0].sync_options(&cli);
+        assert_eq!(options.on_conflict, ConflictPolicy::OverwriteRemote);
+        assert_eq!(options.tags, ["brand"]);
+        assert_eq!(options.formats, ["otf"]);
+        assert_eq!(mappings[1].sync_options(&cli).on_conflict, ConflictPolicy::Skip);
+
+        let selected = select(&mappings, &["personal".to_string()]).unwrap();
+        assert_eq!(selected[0].name, "personal");
+        assert!(select(&mappings, &["work".to_string()]).is_err());
+        let served: Vec<_> = for_server(&mappings, "https://fonts.example.com/brand").map(|m| m.name.as_str()).collect();
+        assert_eq!(served, ["brand"]);
+
+        fs::write(&path, r#"[{"name": "a", "local_dir": "x", "server_url": "u"}, {"name": "a", "local_dir": "y", "server_url": "v"}]"#).unwrap();
+        assert!(load_from(&path).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use crate::api::{Attribution, Tombstone, TombstoneEntry};
+use crate::utils::calculate_sha256;
+
+const METADATA_DIR: &str = ".fontsync";
+const METADATA_FILE: &str = "metadata.json";
+const HASHES_FILE: &str = "hashes.json";
+
+// 文件大小与修改时间未变时沿用缓存的 SHA256
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedHash {
+    sha256: String,
+    size: u64,
+    modified_nanos: u64,
+}
+
+impl CachedHash {
+    fn stat(path: &Path) -> Result<(u64, u64)> {
+        let metadata = fs::metadata(path).with_context(|| format!("Failed to get metadata for {:?}", path))?;
+        let modified_nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok((metadata.len(), modified_nanos))
+    }
+}
+
+// 单个字体的附加元数据，不随字体文件本身保存
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FontMetadata {
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    // 字体被删除时记录，显式重新添加或从回收站恢复时清除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<Tombstone>,
+    // 最近一次上传的客户端，用于列表显示与配额统计
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<Attribution>,
+}
+
+impl FontMetadata {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.deleted.is_none() && self.uploaded_by.is_none()
+    }
+}
+
+// 服务器端字体元数据库，整体保存为一个 JSON 文件
+pub struct MetadataStore {
+    path: PathBuf,
+    fonts: RwLock<HashMap<String, FontMetadata>>,
+    // 内容哈希缓存单独保存，避免每次列出字体都重新计算
+    hashes_path: PathBuf,
+    hashes: RwLock<HashMap<String, CachedHash>>,
+    hashes_dirty: AtomicBool,
+    // 后台尚未计算哈希的字体，列表中暂不返回哈希
+    pending_hashes: RwLock<HashSet<String>>,
+    flush_lock: Mutex<()>,
+}
+
+impl MetadataStore {
+    pub fn open(font_dir: &Path) -> Result<Self> {
+        let dir = font_dir.join(METADATA_DIR);
+        fs::create_dir_all(&dir).context("Failed to create metadata directory")?;
+        let path = dir.join(METADATA_FILE);
+
+        let fonts = if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read font metadata")?;
+            serde_json::from_str(&content).context("Failed to parse font metadata")?
+        } else {
+            HashMap::new()
+        };
+
+        // 缓存损坏时重新计算即可
+        let hashes_path = dir.join(HASHES_FILE);
+        let hashes = fs::read_to_string(&hashes_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Ok(Self {
+            path,
+            fonts: RwLock::new(fonts),
+            hashes_path,
+            hashes: RwLock::new(hashes),
+            hashes_dirty: AtomicBool::new(false),
+            pending_hashes: RwLock::new(HashSet::new()),
+            flush_lock: Mutex::new(()),
+        })
+    }
+
+    // 字体文件的 SHA256，缓存未命中时计算并记录；需调用 flush_hashes 保存
+    pub fn file_sha256(&self, name: &str, path: &Path) -> Result<String> {
+        let (size, modified_nanos) = CachedHash::stat(path)?;
+        if let Some(sha256) = self.fresh_sha256(name, size, modified_nanos) {
+            return Ok(sha256);
+        }
+
+        let sha256 = calculate_sha256(path)?;
+        self.hashes.write().insert(name.to_string(), CachedHash { sha256: sha256.clone(), size, modified_nanos });
+        self.hashes_dirty.store(true, Ordering::Relaxed);
+        self.pending_hashes.write().remove(name);
+        Ok(sha256)
+    }
+
+    // 缓存与文件一致时返回哈希，不计算
+    pub fn cached_sha256(&self, name: &str, path: &Path) -> Option<String> {
+        let (size, modified_nanos) = CachedHash::stat(path).ok()?;
+        self.fresh_sha256(name, size, modified_nanos)
+    }
+
+    fn fresh_sha256(&self, name: &str, size: u64, modified_nanos: u64) -> Option<String> {
+        self.hashes
+            .read()
+            .get(name)
+            .filter(|cached| cached.size == size && cached.modified_nanos == modified_nanos)
+            .map(|cached| cached.sha256.clone())
+    }
+
+    // 交给后台计算的字体，在计算完成或文件变化前视为待定
+    pub fn mark_hash_pending(&self, names: impl IntoIterator<Item = String>) {
+        self.pending_hashes.write().extend(names);
+    }
+
+    pub fn is_hash_pending(&self, name: &str) -> bool {
+        self.pending_hashes.read().contains(name)
+    }
+
+    // 删除或移出字体目录时丢弃缓存
+    pub fn forget_sha256(&self, name: &str) {
+        self.pending_hashes.write().remove(name);
+        if self.hashes.write().remove(name).is_some() {
+            self.hashes_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn flush_hashes(&self) -> Result<()> {
+        // 后台计算与请求处理可能同时保存
+        let _guard = self.flush_lock.lock();
+        if !self.hashes_dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_string(&*self.hashes.read()).context("Failed to serialize hash cache")?;
+        let tmp_path = self.hashes_path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write hash cache")?;
+        fs::rename(&tmp_path, &self.hashes_path).context("Failed to replace hash cache")?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> FontMetadata {
+        self.fonts.read().get(name).cloned().unwrap_or_default()
+    }
+
+    // 替换字体的全部标签，返回规范化后的标签
+    pub fn set_tags(&self, name: &str, tags: Vec<String>) -> Result<BTreeSet<String>> {
+        let tags = normalize_tags(tags);
+        let mut fonts = self.fonts.write();
+        let entry = fonts.entry(name.to_string()).or_default();
+        entry.tags = tags.clone();
+        if entry.is_empty() {
+            fonts.remove(name);
+        }
+        self.persist(&fonts)?;
+        Ok(tags)
+    }
+
+    // 标签与上传者随字体转到新名称，原名称记录删除，避免持有旧文件的客户端重新上传
+    pub fn rename(&self, from: &str, to: &str, sha256: Option<&str>) -> Result<()> {
+        let deleted_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut fonts = self.fonts.write();
+        let moved = fonts.remove(from).unwrap_or_default();
+        let entry = fonts.entry(to.to_string()).or_default();
+        entry.tags = moved.tags;
+        entry.uploaded_by = moved.uploaded_by;
+        entry.deleted = None;
+        if entry.is_empty() {
+            fonts.remove(to);
+        }
+        if let Some(sha256) = sha256 {
+            fonts.entry(from.to_string()).or_default().deleted = Some(Tombstone {
+                sha256: sha256.to_string(),
+                deleted_at,
+            });
+        }
+        self.persist(&fonts)
+    }
+
+    pub fn set_tombstone(&self, name: &str, sha256: &str) -> Result<()> {
+        let deleted_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut fonts = self.fonts.write();
+        fonts.entry(name.to_string()).or_default().deleted = Some(Tombstone {
+            sha256: sha256.to_string(),
+            deleted_at,
+        });
+        self.persist(&fonts)
+    }
+
+    pub fn clear_tombstone(&self, name: &str) -> Result<()> {
+        let mut fonts = self.fonts.write();
+        let Some(entry) = fonts.get_mut(name) else {
+            return Ok(());
+        };
+        if entry.deleted.take().is_none() {
+            return Ok(());
+        }
+        if entry.is_empty() {
+            fonts.remove(name);
+        }
+        self.persist(&fonts)
+    }
+
+    pub fn set_uploaded_by(&self, name: &str, uploaded_by: Attribution) -> Result<()> {
+        let mut fonts = self.fonts.write();
+        let entry = fonts.entry(name.to_string()).or_default();
+        if entry.uploaded_by.as_ref() == Some(&uploaded_by) {
+            return Ok(());
+        }
+        entry.uploaded_by = Some(uploaded_by);
+        self.persist(&fonts)
+    }
+
+    // 由符合条件的上传者上传的字体名称
+    pub fn uploaded_by(&self, matches: impl Fn(&Attribution) -> bool) -> Vec<String> {
+        self.fonts
+            .read()
+            .iter()
+            .filter(|(_, meta)| meta.deleted.is_none() && meta.uploaded_by.as_ref().is_some_and(&matches))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn tombstones(&self) -> Vec<TombstoneEntry> {
+        let mut tombstones: Vec<TombstoneEntry> = self
+            .fonts
+            .read()
+            .iter()
+            .filter_map(|(name, meta)| {
+                meta.deleted.clone().map(|tombstone| TombstoneEntry { name: name.clone(), tombstone })
+            })
+            .collect();
+        tombstones.sort_by(|a, b| a.name.cmp(&b.name));
+        tombstones
+    }
+
+    fn persist(&self, fonts: &HashMap<String, FontMetadata>) -> Result<()> {
+        let content = serde_json::to_string_pretty(fonts).context("Failed to serialize font metadata")?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write font metadata")?;
+        fs::rename(&tmp_path, &self.path).context("Failed to replace font metadata")?;
+        Ok(())
+    }
+}
+
+pub fn normalize_tags<I, S>(tags: I) -> BTreeSet<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    tags.into_iter()
+        .map(|tag| tag.as_ref().trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_normalized_and_persisted() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = MetadataStore::open(dir.path()).expect("open store");
+
+        let tags = store
+            .set_tags("a.ttf", vec![" cjk ".to_string(), "ui".to_string(), "".to_string(), "ui".to_string()])
+            .expect("set tags");
+        assert_eq!(tags.into_iter().collect::<Vec<_>>(), vec!["cjk", "ui"]);
+
+        let reopened = MetadataStore::open(dir.path()).expect("reopen store");
+        assert!(reopened.get("a.ttf").tags.contains("cjk"));
+
+        reopened.set_tags("a.ttf", Vec::new()).expect("clear tags");
+        assert!(reopened.get("a.ttf").tags.is_empty());
+    }
+
+    #[test]
+    fn hashes_are_cached_until_file_changes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = MetadataStore::open(dir.path()).expect("open store");
+        let path = dir.path().join("a.ttf");
+        fs::write(&path, b"one").unwrap();
+
+        let first = store.file_sha256("a.ttf", &path).expect("hash");
+        assert_eq!(first, calculate_sha256(&path).unwrap());
+        store.flush_hashes().expect("flush");
+
+        // 缓存命中时不读取文件内容
+        let reopened = MetadataStore::open(dir.path()).expect("reopen store");
+        reopened.hashes.write().get_mut("a.ttf").unwrap().sha256 = "cached".to_string();
+        assert_eq!(reopened.file_sha256("a.ttf", &path).unwrap(), "cached");
+
+        fs::write(&path, b"changed").unwrap();
+        assert_eq!(reopened.file_sha256("a.ttf", &path).unwrap(), calculate_sha256(&path).unwrap());
+    }
+
+    #[test]
+    fn tombstones_survive_reopen_until_cleared() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = MetadataStore::open(dir.path()).expect("open store");
+        store.set_tags("a.ttf", vec!["ui".to_string()]).expect("set tags");
+        store.set_tombstone("a.ttf", "aa").expect("set tombstone");
+        store.set_tombstone("b.ttf", "bb").expect("set tombstone");
+
+        let reopened = MetadataStore::open(dir.path()).expect("reopen store");
+        let names: Vec<_> = reopened.tombstones().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["a.ttf", "b.ttf"]);
+        assert_eq!(reopened.get("b.ttf").deleted.map(|t| t.sha256).as_deref(), Some("bb"));
+
+        reopened.clear_tombstone("a.ttf").expect("clear");
+        reopened.clear_tombstone("b.ttf").expect("clear");
+        assert!(reopened.tombstones().is_empty());
+        // 清除删除记录不影响标签
+        assert!(reopened.get("a.ttf").tags.contains("ui"));
+    }
+}
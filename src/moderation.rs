@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::PendingUpload;
+
+const PENDING_DIR: &str = ".fontsync/pending";
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredUpload {
+    #[serde(flatten)]
+    upload: PendingUpload,
+    // pending 目录中的数据文件名
+    file: String,
+}
+
+// 启用审核时上传先保存在 .fontsync/pending，管理员批准后才进入字体目录；
+// 同名的新提交替换尚未处理的旧提交
+#[derive(Debug)]
+pub struct PendingUploads {
+    dir: PathBuf,
+    index_path: PathBuf,
+    entries: RwLock<BTreeMap<String, StoredUpload>>,
+}
+
+impl PendingUploads {
+    pub fn open(font_dir: &Path) -> Result<Self> {
+        let dir = font_dir.join(PENDING_DIR);
+        fs::create_dir_all(&dir).context("Failed to create pending upload directory")?;
+        let index_path = dir.join(INDEX_FILE);
+
+        let entries = if index_path.exists() {
+            let content = fs::read_to_string(&index_path).context("Failed to read pending uploads")?;
+            serde_json::from_str(&content).context("Failed to parse pending uploads")?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { dir, index_path, entries: RwLock::new(entries) })
+    }
+
+    pub fn list(&self) -> Vec<PendingUpload> {
+        self.entries.read().values().map(|e| e.upload.clone()).collect()
+    }
+
+    // 待审核的上传及其数据文件
+    pub fn get(&self, name: &str) -> Option<(PendingUpload, PathBuf)> {
+        self.entries.read().get(name).map(|e| (e.upload.clone(), self.dir.join(&e.file)))
+    }
+
+    // 将已通过检查的临时文件移入待审核区
+    pub fn submit(&self, tmp_path: &Path, upload: PendingUpload) -> Result<()> {
+        let file = uuid::Uuid::new_v4().to_string();
+        fs::rename(tmp_path, self.dir.join(&file)).context("Failed to store pending upload")?;
+
+        let mut entries = self.entries.write();
+        let name = upload.name.clone();
+        if let Some(previous) = entries.insert(name, StoredUpload { upload, file }) {
+            let _ = fs::remove_file(self.dir.join(previous.file));
+        }
+        self.persist(&entries)
+    }
+
+    // 批准后数据文件已被移走，拒绝时一并删除
+    pub fn remove(&self, name: &str) -> Result<Option<PendingUpload>> {
+        let mut entries = self.entries.write();
+        let Some(entry) = entries.remove(name) else {
+            return Ok(None);
+        };
+        match fs::remove_file(self.dir.join(&entry.file)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to remove pending upload"),
+        }
+        self.persist(&entries)?;
+        Ok(Some(entry.upload))
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, StoredUpload>) -> Result<()> {
+        let content = serde_json::to_string_pretty(entries).context("Failed to serialize pending uploads")?;
+        let tmp_path = self.index_path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write pending uploads")?;
+        fs::rename(&tmp_path, &self.index_path).context("Failed to replace pending uploads")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(name: &str, sha256: &str) -> PendingUpload {
+        PendingUpload {
+            name: name.to_string(),
+            sha256: sha256.to_string(),
+            size: 4,
+            plaintext_sha256: None,
+            modified: None,
+            uploaded_by: Default::default(),
+            submitted_at: 1,
+            replaces: None,
+        }
+    }
+
+    #[test]
+    fn replaces_and_removes_pending_uploads() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let pending = PendingUploads::open(dir.path()).expect("open");
+        let tmp = dir.path().join("upload.tmp");
+
+        fs::write(&tmp, b"old!").unwrap();
+        pending.submit(&tmp, upload("a.ttf", "01")).expect("submit");
+        let (_, old_path) = pending.get("a.ttf").unwrap();
+        fs::write(&tmp, b"new!").unwrap();
+        pending.submit(&tmp, upload("a.ttf", "02")).expect("resubmit");
+
+        // 旧提交的数据随之删除
+        assert!(!old_path.exists());
+        let reopened = PendingUploads::open(dir.path()).expect("reopen");
+        let (entry, path) = reopened.get("a.ttf").unwrap();
+        assert_eq!(entry.sha256, "02");
+        assert_eq!(fs::read(&path).unwrap(), b"new!");
+
+        assert_eq!(reopened.remove("a.ttf").unwrap().map(|u| u.sha256), Some("02".to_string()));
+        assert!(!path.exists());
+        assert!(reopened.list().is_empty());
+        assert!(reopened.remove("a.ttf").unwrap().is_none());
+    }
+}
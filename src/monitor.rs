@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::SyncOptions;
+use crate::font_monitor::{FontEvent, FontMonitor};
+use crate::mappings::MappingDirection;
+use crate::offline_queue::{self, OfflineQueue, PendingChange, PendingOp};
+use crate::probe;
+use crate::reconcile;
+use crate::websocket_client::{self, WebSocketClient};
+
+// 文件变更后等待写入完成再提交离线队列，以及服务器不可达时的重试间隔
+const QUEUE_SETTLE_DELAY: Duration = Duration::from_secs(2);
+const QUEUE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+// 持续有变更时（如安装整个字体家族）最多推迟这么久提交，期间的变更合并为一次提交
+const QUEUE_MAX_SETTLE_DELAY: Duration = Duration::from_secs(15);
+
+// 实时同步的参数，命令行 monitor 与 GUI 的实时同步开关共用
+pub struct MonitorConfig {
+    pub server_url: String,
+    pub watch_paths: Vec<PathBuf>,
+    pub client_id: String,
+    pub options: SyncOptions,
+    pub cache_size: usize,
+    // 同步映射限定的方向，只上传时不跟随服务器变更，只下载时不提交本地变更
+    pub direction: MappingDirection,
+}
+
+// 实时同步过程中的事件，显示在 GUI 的日志面板与托盘提示中
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorEvent {
+    Started { fonts: usize, directories: usize },
+    Reconciled { queued: usize, downloaded: usize },
+    Offline,
+    LocalChange { change: &'static str, name: String },
+    Flushed { applied: usize, skipped: usize, remaining: usize },
+    Queued { depth: usize },
+    Failed(String),
+    Stopped,
+}
+
+impl MonitorEvent {
+    // 需要引起注意的事件，GUI 中记为警告
+    #[cfg(feature = "gui")]
+    pub fn is_warning(&self) -> bool {
+        matches!(self, MonitorEvent::Offline | MonitorEvent::Queued { .. } | MonitorEvent::Failed(_))
+    }
+}
+
+impl std::fmt::Display for MonitorEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MonitorEvent::Started { fonts, directories } => {
+                write!(f, "Real-time sync started: watching {} fonts in {} directories", fonts, directories)
+            }
+            MonitorEvent::Reconciled { queued, downloaded } => {
+                write!(f, "Caught up with the server: {} change(s) queued, {} font(s) downloaded", queued, downloaded)
+            }
+            MonitorEvent::Offline => write!(f, "Server unreachable, local changes will be queued"),
+            MonitorEvent::LocalChange { change, name } => write!(f, "Font {}: {}", change, name),
+            MonitorEvent::Flushed { applied, skipped, remaining } => {
+                write!(f, "Synced local changes: {} applied, {} skipped, {} remaining", applied, skipped, remaining)
+            }
+            MonitorEvent::Queued { depth } => write!(f, "Server unavailable, {} change(s) queued", depth),
+            MonitorEvent::Failed(message) => write!(f, "Real-time sync error: {}", message),
+            MonitorEvent::Stopped => write!(f, "Real-time sync stopped"),
+        }
+    }
+}
+
+// 把文件事件转换为待提交的变更，返回服务器上的文件名、操作与变更类型
+fn pending_change(event: FontEvent) -> Option<(String, PendingOp, &'static str)> {
+    let (path, op, change) = match event {
+        // 文件上一次的内容哈希作为提交时的冲突检查依据
+        FontEvent::Added(path, sha256) => {
+            info!("Font changed: {:?} (SHA256: {}...)",
+                path.file_name().unwrap_or_default(),
+                &sha256[..8]
+            );
+            (path.clone(), PendingOp::Upload { path, base_sha256: None }, "added")
+        }
+        FontEvent::Modified(path, sha256, previous) => {
+            info!("Font changed: {:?} (SHA256: {}...)",
+                path.file_name().unwrap_or_default(),
+                &sha256[..8]
+            );
+            (path.clone(), PendingOp::Upload { path, base_sha256: Some(previous) }, "modified")
+        }
+        FontEvent::Removed(path, sha256) => {
+            info!("Font removed: {:?}", path.file_name().unwrap_or_default());
+            (path, PendingOp::Remove { sha256 }, "removed")
+        }
+        FontEvent::Renamed(from, to, sha256) => {
+            info!("Font renamed: {:?} -> {:?}",
+                from.file_name().unwrap_or_default(),
+                to.file_name().unwrap_or_default()
+            );
+            // 只是移到了另一个监控目录，服务器上的名称不变
+            let from = from.file_name().and_then(|n| n.to_str())?;
+            if to.file_name().and_then(|n| n.to_str()) == Some(from) {
+                return None;
+            }
+            let op = PendingOp::Rename { from: from.to_string(), path: to.clone(), sha256 };
+            (to, op, "renamed")
+        }
+    };
+    let filename = path.file_name().and_then(|n| n.to_str())?.to_string();
+    Some((filename, op, change))
+}
+
+// 监控字体目录并把变更经离线队列提交到服务器，直到 shutdown 被取消；
+// 启动时先补上未运行期间的变更，服务器上的新字体由 WebSocket 客户端下载
+pub async fn run(
+    config: MonitorConfig,
+    shutdown: CancellationToken,
+    on_event: impl Fn(MonitorEvent) + Send + Sync + 'static,
+) -> Result<()> {
+    let MonitorConfig { server_url, watch_paths, client_id, mut options, cache_size, direction } = config;
+    // 停止时同时中断进行中的同步与事件流
+    options.cancel = shutdown.clone();
+    info!("Starting real-time font monitoring...");
+
+    // 创建字体监控器
+    let mut monitor = FontMonitor::new();
+    monitor.set_cache_capacity(cache_size);
+    let directories = watch_paths.len();
+    for path in watch_paths {
+        monitor.add_watch_path(path);
+    }
+    monitor.set_follow_symlinks(options.follow_symlinks);
+
+    // 初始扫描会覆盖缓存，先取出上次运行时的状态
+    let previous_fonts = monitor.known_fonts();
+    let initial_fonts = monitor.scan_fonts().await?;
+    info!("Found {} fonts during initial scan", initial_fonts.len());
+
+    // 启动时诊断连接，失败只记录原因，离线时仍继续监控
+    let probe = probe::run(&server_url, &client_id).await;
+    if probe.failure().is_some() || probe.warnings().next().is_some() {
+        warn!("Server connectivity: {}", probe.summary());
+        for line in probe.to_string().lines() {
+            info!("{}", line);
+        }
+    }
+
+    // 补上监控未运行期间的变更：上传与删除进入离线队列，服务器上的新字体先下载，之后再转为按事件同步
+    match reconcile::reconcile(&server_url, &initial_fonts, &previous_fonts, &options, direction.uploads()).await {
+        Ok((summary, downloads)) => {
+            let downloads = if direction.downloads() { downloads } else { Vec::new() };
+            let client = WebSocketClient::new(server_url.clone(), client_id.clone(), options.clone());
+            let downloaded = client.download_fonts(&downloads).await?;
+            info!(
+                "Startup reconciliation: {} change(s) queued, {} of {} font(s) downloaded",
+                summary.queued, downloaded, summary.downloads
+            );
+            on_event(MonitorEvent::Reconciled { queued: summary.queued, downloaded });
+        }
+        Err(e) => warn!("Startup reconciliation skipped: {:#}", e),
+    }
+
+    // 连接 WebSocket 服务器；离线时仍继续监控，本地变更进入离线队列
+    if !direction.downloads() {
+        info!("Upload-only mapping, not following changes on the server");
+    } else if let Err(e) = websocket_client::start_websocket_client(server_url.clone(), client_id, options.clone()).await {
+        warn!("Server unreachable, local changes will be queued until it is back: {}", e);
+        on_event(MonitorEvent::Offline);
+    }
+
+    // 开始监控
+    let mut event_receiver = monitor.take_event_receiver()
+        .context("Failed to get event receiver")?;
+
+    monitor.start_monitoring().await?;
+    on_event(MonitorEvent::Started { fonts: initial_fonts.len(), directories });
+
+    // 处理字体事件：先写入离线队列，稍后统一提交
+    let on_event = std::sync::Arc::new(on_event);
+    let stopped = shutdown.clone();
+    let handler = tokio::spawn({
+        let on_event = std::sync::Arc::clone(&on_event);
+        async move {
+            let mut queue = OfflineQueue::load();
+            let mut next_flush = tokio::time::Instant::now();
+            // 本轮连续变更中第一个事件的时间
+            let mut burst_started: Option<tokio::time::Instant> = None;
+            loop {
+                tokio::select! {
+                    _ = stopped.cancelled() => break,
+                    event = event_receiver.recv() => {
+                        let Some(event) = event else { break };
+                        let Some((filename, op, change)) = pending_change(event) else { continue };
+                        if !direction.uploads() {
+                            continue;
+                        }
+                        on_event(MonitorEvent::LocalChange { change, name: filename.clone() });
+                        queue.push(&server_url, PendingChange::new(filename, op));
+                        if let Err(e) = queue.save() {
+                            error!("Failed to save offline queue: {}", e);
+                        }
+                        // 等待文件写入完成后再提交，每个新变更重新计时，但不超过最长等待时间
+                        let now = tokio::time::Instant::now();
+                        let started = *burst_started.get_or_insert(now);
+                        next_flush = (now + QUEUE_SETTLE_DELAY).min(started + QUEUE_MAX_SETTLE_DELAY);
+                    }
+                    _ = tokio::time::sleep_until(next_flush) => {
+                        burst_started = None;
+                        next_flush = tokio::time::Instant::now() + QUEUE_RETRY_INTERVAL;
+                        if queue.depth(&server_url) == 0 {
+                            continue;
+                        }
+                        match queue.flush(&server_url, &options).await {
+                            Ok(summary) => {
+                                info!(
+                                    "Offline queue flushed: {} applied, {} skipped, {} remaining",
+                                    summary.applied, summary.skipped, summary.remaining
+                                );
+                                on_event(MonitorEvent::Flushed {
+                                    applied: summary.applied,
+                                    skipped: summary.skipped,
+                                    remaining: summary.remaining,
+                                });
+                            }
+                            Err(e) if offline_queue::is_unreachable(&e) => {
+                                let depth = queue.depth(&server_url);
+                                info!("Server unavailable, {} change(s) queued", depth);
+                                on_event(MonitorEvent::Queued { depth });
+                            }
+                            Err(e) => {
+                                error!("Failed to flush offline queue: {}", e);
+                                on_event(MonitorEvent::Failed(format!("{:#}", e)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    info!("Font monitoring started");
+
+    // 持续运行直到被停止
+    shutdown.cancelled().await;
+    info!("Shutting down font monitor...");
+    monitor.stop();
+    let _ = handler.await;
+    if let Err(e) = monitor.flush_cache() {
+        warn!("Failed to save font cache: {}", e);
+    }
+    let stats = monitor.cache_stats();
+    info!(
+        "Font cache: {}/{} entries in memory, {} on disk, {} hits, {} misses, {} evictions",
+        stats.entries, stats.capacity, stats.persisted, stats.hits, stats.misses, stats.evictions
+    );
+    on_event(MonitorEvent::Stopped);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_events_become_queued_changes() {
+        let (name, op, change) = pending_change(FontEvent::Modified(
+            PathBuf::from("/fonts/a.ttf"),
+            "22".repeat(32),
+            "11".repeat(32),
+        ))
+        .unwrap();
+        assert_eq!((name.as_str(), change), ("a.ttf", "modified"));
+        assert!(matches!(op, PendingOp::Upload { base_sha256: Some(base), .. } if base == "11".repeat(32)));
+
+        // 在两个监控目录之间移动不改变服务器上的名称
+        let moved = FontEvent::Renamed(PathBuf::from("/one/a.ttf"), PathBuf::from("/two/a.ttf"), None);
+        assert!(pending_change(moved).is_none());
+        let renamed = FontEvent::Renamed(PathBuf::from("/one/a.ttf"), PathBuf::from("/one/b.ttf"), None);
+        let (name, op, change) = pending_change(renamed).unwrap();
+        assert_eq!((name.as_str(), change), ("b.ttf", "renamed"));
+        assert!(matches!(op, PendingOp::Rename { from, .. } if from == "a.ttf"));
+
+        let event = MonitorEvent::LocalChange { change, name };
+        assert_eq!(event.to_string(), "Font renamed: b.ttf");
+    }
+}
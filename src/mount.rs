@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyOpen, Request, FUSE_ROOT_ID,
+};
+use log::{debug, error, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::api::{self, FontQuery, Subscription};
+use crate::client::ApiClient;
+use crate::utils;
+
+// 目录列表的缓存时间，之后读取目录时重新获取服务器列表
+const LIST_TTL: Duration = Duration::from_secs(60);
+// 内核缓存属性与目录项的时间
+const ATTR_TTL: Duration = Duration::from_secs(1);
+
+// 挂载的字体内容按 SHA256 缓存在这里，同一版本只下载一次
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fontsync/mount")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MountedFont {
+    name: String,
+    sha256: String,
+    size: u64,
+    modified: Option<u64>,
+}
+
+// 文件名到 inode 的对应在挂载期间保持不变，服务器删除后再上传的同名字体仍使用原 inode
+#[derive(Debug, Default)]
+struct FontTable {
+    inodes: HashMap<String, u64>,
+    fonts: BTreeMap<u64, MountedFont>,
+    refreshed: Option<Instant>,
+}
+
+impl FontTable {
+    // 端到端加密的字体只有密文，不出现在挂载目录中
+    fn refresh(&mut self, fonts: Vec<api::FontInfo>, subscription: &Subscription) {
+        self.fonts.clear();
+        for font in fonts {
+            if font.plaintext_sha256.is_some() || font.is_pending() || font.is_awaiting_approval() {
+                continue;
+            }
+            if !subscription.matches_font(&font) {
+                continue;
+            }
+            let next = FUSE_ROOT_ID + 1 + self.inodes.len() as u64;
+            let ino = *self.inodes.entry(font.name.clone()).or_insert(next);
+            self.fonts.insert(
+                ino,
+                MountedFont { name: font.name, sha256: font.sha256, size: font.size, modified: font.modified },
+            );
+        }
+        self.refreshed = Some(Instant::now());
+    }
+
+    fn is_stale(&self) -> bool {
+        self.refreshed.is_none_or(|at| at.elapsed() > LIST_TTL)
+    }
+
+    fn lookup(&self, name: &str) -> Option<(u64, &MountedFont)> {
+        let ino = *self.inodes.get(name)?;
+        Some((ino, self.fonts.get(&ino)?))
+    }
+}
+
+// 只读的服务器字体目录；FUSE 回调运行在独立线程中，经 runtime 句柄执行网络请求
+pub struct ServerFs {
+    api: ApiClient,
+    runtime: tokio::runtime::Handle,
+    subscription: Subscription,
+    cache_dir: PathBuf,
+    table: FontTable,
+    handles: HashMap<u64, File>,
+    next_handle: u64,
+    uid: u32,
+    gid: u32,
+}
+
+impl ServerFs {
+    pub fn new(server_url: &str, subscription: Subscription, cache_dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&cache_dir).with_context(|| format!("Failed to create cache directory {:?}", cache_dir))?;
+        Ok(Self {
+            api: ApiClient::new(server_url)?,
+            runtime: tokio::runtime::Handle::current(),
+            subscription,
+            cache_dir,
+            table: FontTable::default(),
+            handles: HashMap::new(),
+            next_handle: 1,
+            // SAFETY: getuid/getgid 总是成功且没有副作用
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+        })
+    }
+
+    // 服务器不可达时保留上次的列表
+    fn refresh_if_stale(&mut self) {
+        if !self.table.is_stale() {
+            return;
+        }
+        match self.runtime.block_on(self.api.list_fonts_hashed(&FontQuery::default())) {
+            Ok(list) => self.table.refresh(list.fonts, &self.subscription),
+            Err(e) => warn!("Failed to refresh the server font list: {:#}", e),
+        }
+    }
+
+    fn attr(&self, ino: u64, font: Option<&MountedFont>) -> FileAttr {
+        let mtime = font
+            .and_then(|f| f.modified)
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap_or(UNIX_EPOCH);
+        let (kind, perm, size, nlink) = match font {
+            Some(font) => (FileType::RegularFile, 0o444, font.size, 1),
+            None => (FileType::Directory, 0o555, 0, 2),
+        };
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+
+    // 缓存中已有同一版本时直接使用，否则下载并校验后写入缓存
+    fn cached_copy(&self, font: &MountedFont) -> Result<PathBuf> {
+        let path = self.cache_dir.join(&font.sha256);
+        if utils::calculate_sha256(&path).is_ok_and(|sha| sha == font.sha256) {
+            return Ok(path);
+        }
+        info!("Fetching {} from the server", font.name);
+        let (data, _) = self.runtime.block_on(self.api.fetch_font(&font.name))?;
+        let actual = hex::encode(Sha256::digest(&data));
+        if actual != font.sha256 {
+            anyhow::bail!("'{}' changed on the server (expected {}, got {})", font.name, font.sha256, actual);
+        }
+        utils::write_atomic(&path, &data, Some(&font.sha256))?;
+        Ok(path)
+    }
+}
+
+impl Filesystem for ServerFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        if parent != FUSE_ROOT_ID {
+            return reply.error(libc::ENOENT);
+        }
+        self.refresh_if_stale();
+        match name.to_str().and_then(|name| self.table.lookup(name)) {
+            Some((ino, font)) => reply.entry(&ATTR_TTL, &self.attr(ino, Some(font)), 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        if ino == FUSE_ROOT_ID {
+            return reply.attr(&ATTR_TTL, &self.attr(ino, None));
+        }
+        match self.table.fonts.get(&ino) {
+            Some(font) => reply.attr(&ATTR_TTL, &self.attr(ino, Some(font))),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+        let Some(font) = self.table.fonts.get(&ino).cloned() else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.cached_copy(&font).and_then(|path| Ok(File::open(path)?)) {
+            Ok(file) => {
+                let fh = self.next_handle;
+                self.next_handle += 1;
+                self.handles.insert(fh, file);
+                reply.opened(fh, 0);
+            }
+            Err(e) => {
+                error!("Failed to open '{}': {:#}", font.name, e);
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(file) = self.handles.get(&fh) else {
+            return reply.error(libc::EBADF);
+        };
+        let mut buffer = vec![0u8; size as usize];
+        let mut filled = 0;
+        // 读到文件末尾或读满为止
+        while filled < buffer.len() {
+            match file.read_at(&mut buffer[filled..], offset.max(0) as u64 + filled as u64) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) => {
+                    debug!("Read failed: {}", e);
+                    return reply.error(libc::EIO);
+                }
+            }
+        }
+        reply.data(&buffer[..filled]);
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.handles.remove(&fh);
+        reply.ok();
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        if ino != FUSE_ROOT_ID {
+            return reply.error(libc::ENOTDIR);
+        }
+        if offset == 0 {
+            self.refresh_if_stale();
+        }
+        let entries = [(FUSE_ROOT_ID, FileType::Directory, "."), (FUSE_ROOT_ID, FileType::Directory, "..")]
+            .into_iter()
+            .chain(self.table.fonts.iter().map(|(ino, font)| (*ino, FileType::RegularFile, font.name.as_str())));
+        // offset 为下一个条目的序号
+        for (index, (ino, kind, name)) in entries.enumerate().skip(offset.max(0) as usize) {
+            if reply.add(ino, index as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+// 挂载到 mountpoint，直到收到中断信号后卸载
+pub async fn mount(server_url: &str, mountpoint: &Path, subscription: Subscription, shutdown: impl std::future::Future<Output = ()>) -> Result<()> {
+    let fs = ServerFs::new(server_url, subscription, cache_dir())?;
+    let options = [
+        MountOption::RO,
+        MountOption::NoExec,
+        MountOption::FSName("fontsync".to_string()),
+        MountOption::Subtype("fontsync".to_string()),
+    ];
+    let mountpoint = mountpoint.to_path_buf();
+    let session = tokio::task::spawn_blocking(move || fuser::spawn_mount2(fs, &mountpoint, &options))
+        .await?
+        .context("Failed to mount (is fusermount installed and the mount point an empty directory?)")?;
+    info!("Mounted {}; press Ctrl+C to unmount", server_url);
+    shutdown.await;
+    // 卸载会等待进行中的回调结束
+    tokio::task::spawn_blocking(move || drop(session)).await?;
+    info!("Unmounted");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(name: &str, sha256: &str) -> api::FontInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "size": 3, "mime_type": "font/ttf", "sha256": sha256, "modified": 1
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn inodes_stay_stable_across_refreshes() {
+        let mut table = FontTable::default();
+        assert!(table.is_stale());
+        table.refresh(vec![font("a.ttf", "1"), font("b.otf", "2")], &Subscription::default());
+        assert!(!table.is_stale());
+        let (a, _) = table.lookup("a.ttf").unwrap();
+
+        table.refresh(vec![font("b.otf", "3")], &Subscription::default());
+        assert!(table.lookup("a.ttf").is_none());
+        assert_eq!(table.lookup("b.otf").unwrap().1.sha256, "3");
+        table.refresh(vec![font("a.ttf", "4")], &Subscription::default());
+        assert_eq!(table.lookup("a.ttf").unwrap().0, a);
+
+        let otf_only = Subscription::new(&[], &[], &["otf".to_string()]);
+        table.refresh(vec![font("a.ttf", "1"), font("b.otf", "2")], &otf_only);
+        assert_eq!(table.fonts.len(), 1);
+    }
+}
@@ -0,0 +1,466 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use futures::future::BoxFuture;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::client_state::ClientState;
+use crate::http::REQUEST_TIMEOUT;
+use crate::sync_report;
+
+// 同步结束后的通知通道，JSON 数组，每项一个通道
+const NOTIFIERS_FILE: &str = "notifiers.json";
+
+// 按同步结果过滤通知
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    // 明文连接后升级为 TLS，默认端口 587
+    #[default]
+    Starttls,
+    // 直接建立 TLS 连接，默认端口 465
+    Tls,
+    // 不加密，只用于本机或内网的中继，默认端口 25
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    // 密码从该环境变量读取，不写入配置文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Channel {
+    // 系统桌面通知
+    Desktop,
+    // 以 JSON 把同步摘要 POST 到该地址
+    Webhook { url: String },
+    Smtp(SmtpConfig),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifierConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub channel: Channel,
+    #[serde(default)]
+    pub on: NotifyOn,
+}
+
+impl NotifierConfig {
+    fn wants(&self, summary: &SyncSummary) -> bool {
+        match self.on {
+            NotifyOn::Always => true,
+            NotifyOn::Success => summary.succeeded,
+            NotifyOn::Failure => !summary.succeeded,
+        }
+    }
+
+    pub fn notifier(&self) -> Box<dyn Notifier + Send + Sync> {
+        match &self.channel {
+            Channel::Desktop => Box::new(DesktopNotifier),
+            Channel::Webhook { url } => Box::new(WebhookNotifier { url: url.clone() }),
+            Channel::Smtp(config) => Box::new(SmtpNotifier { config: config.clone() }),
+        }
+    }
+}
+
+// 一次同步的结果，profile 为同步映射的名称
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub server_url: String,
+    pub succeeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    // 如 "2 uploaded, 1 downloaded, ..."，同步出错时为错误信息
+    pub summary: String,
+    pub finished_at: u64,
+}
+
+impl SyncSummary {
+    // 没有可同步的内容也算成功
+    pub fn new(profile: Option<&str>, server_url: &str, result: &Result<(i32, String)>) -> Self {
+        let (succeeded, exit_code, summary) = match result {
+            Ok((code, summary)) => {
+                (matches!(*code, 0 | sync_report::EXIT_NOTHING_TO_DO), Some(*code), summary.clone())
+            }
+            Err(e) => (false, None, format!("{:#}", e)),
+        };
+        Self {
+            profile: profile.map(str::to_string),
+            server_url: server_url.to_string(),
+            succeeded,
+            exit_code,
+            summary,
+            finished_at: chrono::Utc::now().timestamp() as u64,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let target = self.profile.as_deref().unwrap_or(&self.server_url);
+        let outcome = match (self.succeeded, self.exit_code) {
+            (true, _) => "completed",
+            (false, Some(sync_report::EXIT_INTERRUPTED)) => "cancelled",
+            (false, Some(_)) => "completed with failures",
+            (false, None) => "failed",
+        };
+        format!("fontsync: sync of {} {}", target, outcome)
+    }
+}
+
+// 通知通道的公共接口，发送失败不影响同步结果
+pub trait Notifier {
+    fn send<'a>(&'a self, summary: &'a SyncSummary) -> BoxFuture<'a, Result<()>>;
+}
+
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn send<'a>(&'a self, summary: &'a SyncSummary) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (title, body) = (summary.title(), summary.summary.clone());
+            let output = tokio::task::spawn_blocking(move || desktop_command(&title, &body).output())
+                .await?
+                .context("Failed to show desktop notification")?;
+            if !output.status.success() {
+                bail!("Desktop notification failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn desktop_command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name", "fontsync", title, body]);
+    command
+}
+
+// 文本经参数传递，不拼接进脚本
+#[cfg(target_os = "macos")]
+fn desktop_command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command.args([
+        "-e",
+        "on run argv",
+        "-e",
+        "display notification (item 2 of argv) with title (item 1 of argv)",
+        "-e",
+        "end run",
+        title,
+        body,
+    ]);
+    command
+}
+
+// 文本经环境变量传递给 PowerShell，以系统通知中心的 toast 显示
+#[cfg(target_os = "windows")]
+fn desktop_command(title: &str, body: &str) -> Command {
+    const SCRIPT: &str = "[void][Windows.UI.Notifications.ToastNotificationManager,Windows.UI.Notifications,ContentType=WindowsRuntime]; \
+        $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+        $text = $xml.GetElementsByTagName('text'); \
+        [void]$text.Item(0).AppendChild($xml.CreateTextNode($env:FONTSYNC_TITLE)); \
+        [void]$text.Item(1).AppendChild($xml.CreateTextNode($env:FONTSYNC_BODY)); \
+        [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('fontsync').Show([Windows.UI.Notifications.ToastNotification]::new($xml))";
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("FONTSYNC_TITLE", title)
+        .env("FONTSYNC_BODY", body);
+    command
+}
+
+pub struct WebhookNotifier {
+    url: String,
+}
+
+impl Notifier for WebhookNotifier {
+    fn send<'a>(&'a self, summary: &'a SyncSummary) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+            let response = client.post(&self.url).json(summary).send().await.context("Failed to call webhook")?;
+            if !response.status().is_success() {
+                bail!("Webhook returned {}", response.status());
+            }
+            Ok(())
+        })
+    }
+}
+
+pub struct SmtpNotifier {
+    config: SmtpConfig,
+}
+
+impl Notifier for SmtpNotifier {
+    fn send<'a>(&'a self, summary: &'a SyncSummary) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let config = &self.config;
+            let port = config.port.unwrap_or(match config.security {
+                SmtpSecurity::Starttls => 587,
+                SmtpSecurity::Tls => 465,
+                SmtpSecurity::None => 25,
+            });
+            let stream = tokio::time::timeout(REQUEST_TIMEOUT, TcpStream::connect((config.host.as_str(), port)))
+                .await
+                .context("Timed out connecting to SMTP server")?
+                .with_context(|| format!("Failed to connect to SMTP server {}:{}", config.host, port))?;
+            let message = email_message(config, summary);
+            let session = async {
+                match config.security {
+                    SmtpSecurity::Tls => {
+                        let mut smtp = SmtpConnection::new(tls_connect(&config.host, stream).await?);
+                        smtp.expect(220).await?;
+                        smtp.send_mail(config, &message).await
+                    }
+                    SmtpSecurity::Starttls => {
+                        let mut smtp = SmtpConnection::new(stream);
+                        smtp.expect(220).await?;
+                        smtp.command("EHLO fontsync", 250).await?;
+                        smtp.command("STARTTLS", 220).await?;
+                        let stream = smtp.reader.into_inner();
+                        let mut smtp = SmtpConnection::new(tls_connect(&config.host, stream).await?);
+                        smtp.send_mail(config, &message).await
+                    }
+                    SmtpSecurity::None => {
+                        let mut smtp = SmtpConnection::new(stream);
+                        smtp.expect(220).await?;
+                        smtp.send_mail(config, &message).await
+                    }
+                }
+            };
+            tokio::time::timeout(REQUEST_TIMEOUT, session).await.context("SMTP session timed out")?
+        })
+    }
+}
+
+async fn tls_connect(host: &str, stream: TcpStream) -> Result<tokio_native_tls::TlsStream<TcpStream>> {
+    let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+    connector.connect(host, stream).await.context("TLS handshake with SMTP server failed")
+}
+
+// 纯文本邮件；正文中以点开头的行按 RFC 5321 补一个点
+fn email_message(config: &SmtpConfig, summary: &SyncSummary) -> String {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let mut body = format!("{}\r\n\r\nServer: {}\r\n", summary.summary, summary.server_url);
+    if let Some(profile) = &summary.profile {
+        body.push_str(&format!("Mapping: {}\r\n", profile));
+    }
+    let body: String = body
+        .lines()
+        .map(|line| if line.starts_with('.') { format!(".{}\r\n", line) } else { format!("{}\r\n", line) })
+        .collect();
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: =?UTF-8?B?{}?=\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}",
+        config.from,
+        config.to.join(", "),
+        engine.encode(summary.title()),
+        chrono::Utc::now().to_rfc2822(),
+        body
+    )
+}
+
+struct SmtpConnection<S> {
+    reader: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SmtpConnection<S> {
+    fn new(stream: S) -> Self {
+        Self { reader: BufReader::new(stream) }
+    }
+
+    // 读取一个可能跨多行的应答，状态码不符时返回服务器的说明
+    async fn expect(&mut self, code: u16) -> Result<()> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).await? == 0 {
+                bail!("SMTP server closed the connection");
+            }
+            reply.push_str(&line);
+            // "250-..." 表示后面还有行，"250 ..." 是最后一行
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        if !reply.starts_with(&code.to_string()) {
+            bail!("SMTP server replied: {}", reply.trim());
+        }
+        Ok(())
+    }
+
+    async fn command(&mut self, command: &str, code: u16) -> Result<()> {
+        self.reader.get_mut().write_all(format!("{}\r\n", command).as_bytes()).await?;
+        self.expect(code).await
+    }
+
+    async fn send_mail(&mut self, config: &SmtpConfig, message: &str) -> Result<()> {
+        self.command("EHLO fontsync", 250).await?;
+        if let Some(username) = &config.username {
+            let password = match &config.password_env {
+                Some(var) => std::env::var(var).with_context(|| format!("SMTP password variable {} is not set", var))?,
+                None => String::new(),
+            };
+            let credentials = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+            self.command(&format!("AUTH PLAIN {}", credentials), 235).await?;
+        }
+        self.command(&format!("MAIL FROM:<{}>", config.from), 250).await?;
+        for to in &config.to {
+            self.command(&format!("RCPT TO:<{}>", to), 250).await?;
+        }
+        self.command("DATA", 354).await?;
+        self.reader.get_mut().write_all(message.as_bytes()).await?;
+        self.command(".", 250).await?;
+        self.command("QUIT", 221).await
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    ClientState::state_dir().join(NOTIFIERS_FILE)
+}
+
+// 没有配置文件时返回空列表
+pub fn load() -> Result<Vec<NotifierConfig>> {
+    load_from(&config_path())
+}
+
+fn load_from(path: &Path) -> Result<Vec<NotifierConfig>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read notifiers {:?}", path)),
+    };
+    let notifiers: Vec<NotifierConfig> =
+        serde_json::from_str(&content).with_context(|| format!("Invalid notifiers in {:?}", path))?;
+    let mut names = HashSet::new();
+    if let Some(duplicate) = notifiers.iter().find(|n| !names.insert(n.name.as_str())) {
+        bail!("Notifier '{}' is defined more than once in {:?}", duplicate.name, path);
+    }
+    Ok(notifiers)
+}
+
+// 按名称选出通知通道，保持给定顺序
+pub fn select(notifiers: &[NotifierConfig], names: &[String]) -> Result<Vec<NotifierConfig>> {
+    names
+        .iter()
+        .map(|name| {
+            notifiers
+                .iter()
+                .find(|n| &n.name == name)
+                .cloned()
+                .with_context(|| format!("No notifier named '{}' in {:?}", name, config_path()))
+        })
+        .collect()
+}
+
+// 依次发送到结果符合过滤条件的通道，失败只记录警告
+pub async fn notify(notifiers: &[NotifierConfig], summary: &SyncSummary) {
+    for config in notifiers.iter().filter(|n| n.wants(summary)) {
+        match config.notifier().send(summary).await {
+            Ok(()) => info!("Sent sync notification via '{}'", config.name),
+            Err(e) => warn!("Failed to send sync notification via '{}': {:#}", config.name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn loads_notifiers_and_filters_by_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(NOTIFIERS_FILE);
+        assert!(load_from(&path).unwrap().is_empty());
+
+        fs::write(
+            &path,
+            r#"[
+                {"name": "desk", "type": "desktop"},
+                {"name": "ops", "type": "webhook", "url": "https://hooks.example.com/fontsync", "on": "failure"},
+                {"name": "mail", "type": "smtp", "host": "smtp.example.com", "from": "fonts@example.com", "to": ["me@example.com"], "on": "success"}
+            ]"#,
+        )
+        .unwrap();
+        let notifiers = load_from(&path).unwrap();
+        assert_eq!(notifiers[0].channel, Channel::Desktop);
+        let Channel::Smtp(smtp) = &notifiers[2].channel else { panic!("expected smtp") };
+        assert_eq!(smtp.security, SmtpSecurity::Starttls);
+
+        let ok = SyncSummary::new(None, "http://fonts", &Ok((sync_report::EXIT_NOTHING_TO_DO, "nothing".to_string())));
+        let partial = SyncSummary::new(Some("brand"), "http://fonts", &Ok((sync_report::EXIT_PARTIAL_FAILURE, "1 failed".to_string())));
+        let failed = SyncSummary::new(None, "http://fonts", &Err(anyhow::anyhow!("unreachable")));
+        let wanted = |summary: &SyncSummary| -> Vec<&str> {
+            notifiers.iter().filter(|n| n.wants(summary)).map(|n| n.name.as_str()).collect()
+        };
+        assert_eq!(wanted(&ok), ["desk", "mail"]);
+        assert_eq!(wanted(&partial), ["desk", "ops"]);
+        assert_eq!(wanted(&failed), ["desk", "ops"]);
+        assert_eq!(partial.title(), "fontsync: sync of brand completed with failures");
+
+        assert_eq!(select(&notifiers, &["ops".to_string()]).unwrap()[0].name, "ops");
+        assert!(select(&notifiers, &["pager".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn smtp_session_sends_the_summary() {
+        let (client, mut server) = tokio::io::duplex(64 * 1024);
+        let config = SmtpConfig {
+            host: "localhost".to_string(),
+            port: None,
+            security: SmtpSecurity::None,
+            username: None,
+            password_env: None,
+            from: "fonts@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+        };
+        let summary = SyncSummary::new(None, "http://fonts", &Ok((0, ".hidden line".to_string())));
+        let message = email_message(&config, &summary);
+        assert!(message.contains("\r\n\r\n..hidden line\r\n"));
+
+        // 按顺序预先写好服务器应答
+        server
+            .write_all(b"220 ready\r\n250-hello\r\n250 SIZE\r\n250 ok\r\n250 ok\r\n250 ok\r\n354 go\r\n250 queued\r\n221 bye\r\n")
+            .await
+            .unwrap();
+        let mut smtp = SmtpConnection::new(client);
+        smtp.expect(220).await.unwrap();
+        smtp.send_mail(&config, &message).await.unwrap();
+        drop(smtp);
+
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+        assert!(sent.starts_with("EHLO fontsync\r\nMAIL FROM:<fonts@example.com>\r\nRCPT TO:<a@example.com>\r\nRCPT TO:<b@example.com>\r\nDATA\r\n"));
+        assert!(sent.ends_with("\r\n.\r\nQUIT\r\n"));
+    }
+}
@@ -0,0 +1,568 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::{ErrorCode, FontQuery, MAX_BATCH_UPLOADS};
+use crate::client::{self, ApiClient, SyncOptions};
+use crate::client_state::ClientState;
+use crate::utils::{self, ConflictResolution, SyncDirection};
+
+const QUEUE_FILE: &str = "queue.json";
+
+// 等待提交到服务器的本地变更
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PendingOp {
+    // base_sha256 为变更前服务器上应有的内容，新增文件为 None
+    Upload { path: PathBuf, base_sha256: Option<String> },
+    // sha256 为删除前的本地内容，服务器内容不同时不删除
+    Remove { sha256: Option<String> },
+    // 本地从 from 改名为 filename；服务器上 from 的内容为 sha256 时在服务器上改名，否则删除后重新上传
+    Rename { from: String, path: PathBuf, sha256: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingChange {
+    pub filename: String,
+    #[serde(flatten)]
+    pub op: PendingOp,
+    pub queued_at: u64,
+}
+
+impl PendingChange {
+    pub fn new(filename: String, op: PendingOp) -> Self {
+        let queued_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self { filename, op, queued_at }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FlushSummary {
+    pub applied: usize,
+    pub skipped: usize,
+    pub remaining: usize,
+}
+
+// 按服务器 URL 区分的离线变更队列，保存在客户端状态目录
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OfflineQueue {
+    #[serde(default)]
+    pub servers: HashMap<String, Vec<PendingChange>>,
+}
+
+impl OfflineQueue {
+    fn queue_path() -> PathBuf {
+        ClientState::state_dir().join(QUEUE_FILE)
+    }
+
+    // 读取队列文件，不存在或损坏时返回空队列
+    pub fn load() -> Self {
+        fs::read_to_string(Self::queue_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(ClientState::state_dir()).context("Failed to create state directory")?;
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize offline queue")?;
+        let tmp_path = Self::queue_path().with_extension("json.tmp");
+        fs::write(&tmp_path, content).context("Failed to write offline queue")?;
+        fs::rename(&tmp_path, Self::queue_path()).context("Failed to replace offline queue")?;
+        Ok(())
+    }
+
+    pub fn pending(&self, server_url: &str) -> &[PendingChange] {
+        self.servers
+            .get(server_url.trim_end_matches('/'))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn depth(&self, server_url: &str) -> usize {
+        self.pending(server_url).len()
+    }
+
+    // 同一文件只保留一条待提交操作，合并时沿用最早记录的服务器内容作为冲突检查依据
+    pub fn push(&mut self, server_url: &str, change: PendingChange) {
+        if matches!(change.op, PendingOp::Rename { .. }) {
+            return self.push_rename(server_url, change);
+        }
+        let queue = self
+            .servers
+            .entry(server_url.trim_end_matches('/').to_string())
+            .or_default();
+        let previous = queue
+            .iter()
+            .position(|c| c.filename == change.filename)
+            .map(|i| queue.remove(i));
+
+        let op = match (previous.map(|c| c.op), change.op) {
+            (None, op) => Some(op),
+            (Some(PendingOp::Upload { base_sha256, .. }), PendingOp::Upload { path, .. }) => {
+                Some(PendingOp::Upload { path, base_sha256 })
+            }
+            (Some(PendingOp::Remove { sha256 }), PendingOp::Upload { path, .. }) => {
+                Some(PendingOp::Upload { path, base_sha256: sha256 })
+            }
+            // 离线期间新增又删除的文件服务器从未见过，两条操作一起丢弃
+            (Some(PendingOp::Upload { base_sha256: None, .. }), PendingOp::Remove { .. }) => None,
+            (Some(PendingOp::Upload { base_sha256, .. }), PendingOp::Remove { .. }) => {
+                Some(PendingOp::Remove { sha256: base_sha256 })
+            }
+            (Some(PendingOp::Remove { sha256 }), PendingOp::Remove { .. }) => Some(PendingOp::Remove { sha256 }),
+            // 改名后又修改，提交时先改名再上传新内容
+            (Some(PendingOp::Rename { from, sha256, .. }), PendingOp::Upload { path, .. }) => {
+                Some(PendingOp::Rename { from, path, sha256 })
+            }
+            // 改名后又删除，服务器上要删除的是原名称
+            (Some(PendingOp::Rename { from, sha256, .. }), PendingOp::Remove { .. }) => {
+                if queue.is_empty() {
+                    self.servers.remove(server_url.trim_end_matches('/'));
+                }
+                let removal = PendingChange { filename: from, op: PendingOp::Remove { sha256 }, queued_at: change.queued_at };
+                return self.push(server_url, removal);
+            }
+            (_, op @ PendingOp::Rename { .. }) => Some(op),
+        };
+
+        if let Some(op) = op {
+            queue.push(PendingChange { op, ..change });
+        }
+        if queue.is_empty() {
+            self.servers.remove(server_url.trim_end_matches('/'));
+        }
+    }
+
+    // 改名接管原名称的待提交操作；新名称已有待提交操作时按删除原名称与上传新名称处理
+    fn push_rename(&mut self, server_url: &str, change: PendingChange) {
+        let PendingOp::Rename { from, path, sha256 } = change.op else {
+            return;
+        };
+        let queued_at = change.queued_at;
+        let queue = self
+            .servers
+            .entry(server_url.trim_end_matches('/').to_string())
+            .or_default();
+        let source = queue.iter().position(|c| c.filename == from).map(|i| queue.remove(i));
+        let target_pending = queue.iter().any(|c| c.filename == change.filename);
+
+        let (from, sha256) = match source.map(|c| c.op) {
+            // 服务器从未见过原文件，改名等同于新增
+            Some(PendingOp::Upload { base_sha256: None, .. }) => {
+                let upload = PendingOp::Upload { path, base_sha256: None };
+                return self.push(server_url, PendingChange { filename: change.filename, op: upload, queued_at });
+            }
+            Some(PendingOp::Upload { base_sha256, .. }) => (from, base_sha256),
+            Some(PendingOp::Rename { from, sha256, .. }) => (from, sha256),
+            Some(PendingOp::Remove { .. }) | None => (from, sha256),
+        };
+
+        if from == change.filename {
+            // 改回原名称，只剩可能的内容变化
+            let upload = PendingOp::Upload { path, base_sha256: sha256 };
+            self.push(server_url, PendingChange { filename: change.filename, op: upload, queued_at });
+        } else if target_pending {
+            let removal = PendingOp::Remove { sha256 };
+            self.push(server_url, PendingChange { filename: from, op: removal, queued_at });
+            let upload = PendingOp::Upload { path, base_sha256: None };
+            self.push(server_url, PendingChange { filename: change.filename, op: upload, queued_at });
+        } else {
+            queue.push(PendingChange { filename: change.filename, op: PendingOp::Rename { from, path, sha256 }, queued_at });
+        }
+    }
+
+    // 按入队顺序提交，服务器不可达时停止并保留剩余操作
+    pub async fn flush(&mut self, server_url: &str, options: &SyncOptions) -> Result<FlushSummary> {
+        let mut summary = FlushSummary::default();
+        if self.depth(server_url) == 0 {
+            return Ok(summary);
+        }
+
+        let api = ApiClient::new(server_url)?;
+        let mut remote: RemoteFonts = api
+            .list_fonts_hashed(&FontQuery::default())
+            .await?
+            .fonts
+            .into_iter()
+            .map(|f| (f.name.clone(), (f.content_sha256().to_string(), f.modified)))
+            .collect();
+        let last_synced = ClientState::load()
+            .server(server_url)
+            .map(|s| s.synced.clone())
+            .unwrap_or_default();
+
+        // 新增的字体先成组上传，其余变更按入队顺序逐个提交
+        if let Err(e) = self.upload_new_fonts(server_url, &api, &mut remote, options, &mut summary).await {
+            warn!("Batch upload of queued fonts failed, uploading them one by one: {:#}", e);
+        }
+
+        while let Some(change) = self.pending(server_url).first().cloned() {
+            match apply_change(server_url, &api, &mut remote, &last_synced, &change, options).await {
+                Ok(true) => summary.applied += 1,
+                Ok(false) => summary.skipped += 1,
+                Err(e) if is_unreachable(&e) => {
+                    warn!("Server became unavailable while flushing offline queue: {:#}", e);
+                    break;
+                }
+                Err(e) => {
+                    error!("Failed to apply queued change for '{}': {}", change.filename, e);
+                    summary.skipped += 1;
+                }
+            }
+            if let Some(queue) = self.servers.get_mut(server_url.trim_end_matches('/')) {
+                queue.remove(0);
+                if queue.is_empty() {
+                    self.servers.remove(server_url.trim_end_matches('/'));
+                }
+            }
+            self.save()?;
+        }
+
+        summary.remaining = self.depth(server_url);
+        Ok(summary)
+    }
+
+    // 服务器上尚不存在的字体每 MAX_BATCH_UPLOADS 个一次请求上传，例如安装整个字体家族之后；
+    // 成功与被拒绝的变更移出队列，可重试的失败留给逐个提交
+    async fn upload_new_fonts(
+        &mut self,
+        server_url: &str,
+        api: &ApiClient,
+        remote: &mut RemoteFonts,
+        options: &SyncOptions,
+        summary: &mut FlushSummary,
+    ) -> Result<()> {
+        // 改名前的名称与新名称相关，仍按顺序提交
+        let renamed: HashSet<&str> = self
+            .pending(server_url)
+            .iter()
+            .filter_map(|change| match &change.op {
+                PendingOp::Rename { from, .. } => Some(from.as_str()),
+                _ => None,
+            })
+            .collect();
+        let new_fonts: Vec<(PathBuf, String, String)> = self
+            .pending(server_url)
+            .iter()
+            .filter(|change| !remote.contains_key(&change.filename) && !renamed.contains(change.filename.as_str()))
+            .filter_map(|change| match &change.op {
+                PendingOp::Upload { path, base_sha256: None } => {
+                    let sha256 = utils::calculate_sha256(path).ok()?;
+                    Some((path.clone(), change.filename.clone(), sha256))
+                }
+                _ => None,
+            })
+            .collect();
+        if new_fonts.len() < 2 {
+            return Ok(());
+        }
+
+        for chunk in new_fonts.chunks(MAX_BATCH_UPLOADS) {
+            // 监控到的本地变更来自用户操作，视为显式重新添加
+            let Some(response) = api.upload_fonts(chunk, options.e2e_key.as_ref(), true).await? else {
+                info!("Server does not support batch uploads");
+                return Ok(());
+            };
+            let mut synced = Vec::new();
+            let mut done = HashSet::new();
+            // 结果与提交的字体顺序一致，超时后的字体不在结果中
+            for ((_, filename, sha256), result) in chunk.iter().zip(response.results) {
+                match (result.response, result.error) {
+                    (Some(response), _) => {
+                        info!("Uploaded queued font '{}' ({})", filename, response.action);
+                        summary.applied += 1;
+                        // 等待审核的上传在批准前不记为已同步
+                        if !response.is_awaiting_approval() {
+                            synced.push((filename.clone(), sha256.clone()));
+                            remote.insert(filename.clone(), (sha256.clone(), response.modified));
+                        }
+                    }
+                    (None, Some(error)) if error.retryable => continue,
+                    // 列出服务器字体后他人上传了同名字体，留在队列中逐个提交时按冲突处理
+                    (None, Some(error)) if error.code == ErrorCode::AlreadyExists => continue,
+                    (None, error) => {
+                        let reason = error.map_or(result.status.to_string(), |e| e.message.unwrap_or(e.error));
+                        error!("Failed to apply queued change for '{}': {}", filename, reason);
+                        summary.skipped += 1;
+                    }
+                }
+                done.insert(filename.as_str());
+            }
+            if let Err(e) = ClientState::record_synced(server_url, synced) {
+                warn!("Failed to save client state: {}", e);
+            }
+            if let Some(queue) = self.servers.get_mut(server_url.trim_end_matches('/')) {
+                queue.retain(|change| {
+                    !(done.contains(change.filename.as_str()) && matches!(change.op, PendingOp::Upload { .. }))
+                });
+                if queue.is_empty() {
+                    self.servers.remove(server_url.trim_end_matches('/'));
+                }
+            }
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+// 服务器上的文件名到内容哈希与修改时间
+type RemoteFonts = HashMap<String, (String, Option<u64>)>;
+
+// 连接失败、超时或服务器表示可重试（如维护模式）时稍后重试，其余错误（如服务器拒绝）不再重试
+pub fn is_unreachable(error: &anyhow::Error) -> bool {
+    client::is_retryable_error(error)
+        || error
+            .chain()
+            .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+            .any(|e| e.is_connect() || e.is_timeout())
+}
+
+// 返回是否实际修改了服务器
+async fn apply_change(
+    server_url: &str,
+    api: &ApiClient,
+    remote: &mut RemoteFonts,
+    last_synced: &HashMap<String, String>,
+    change: &PendingChange,
+    options: &SyncOptions,
+) -> Result<bool> {
+    match &change.op {
+        PendingOp::Remove { sha256 } => apply_remove(api, remote, &change.filename, sha256.as_ref()).await,
+        PendingOp::Upload { path, base_sha256 } => {
+            apply_upload(server_url, api, remote, last_synced, &change.filename, path, base_sha256.as_ref(), options).await
+        }
+        PendingOp::Rename { from, path, sha256 } => {
+            if !path.is_file() {
+                info!("Queued rename of '{}' skipped, file no longer exists", change.filename);
+                return Ok(false);
+            }
+            let renamed = match (remote.get(from).cloned(), remote.contains_key(&change.filename)) {
+                (Some((current, _)), false) if sha256.as_ref() == Some(&current) => {
+                    match api.rename_font(from, &change.filename, Some(&current)).await {
+                        Ok(response) => {
+                            info!("Renamed '{}' to '{}' on server", from, change.filename);
+                            if let Err(e) = ClientState::record_moved(server_url, from, &change.filename, &current) {
+                                warn!("Failed to save client state: {}", e);
+                            }
+                            remote.remove(from);
+                            remote.insert(change.filename.clone(), (current, response.modified));
+                            true
+                        }
+                        Err(e) if is_unreachable(&e) => return Err(e),
+                        Err(e) => {
+                            warn!("Server could not rename '{}', uploading '{}' instead: {:#}", from, change.filename, e);
+                            false
+                        }
+                    }
+                }
+                _ => false,
+            };
+
+            // 改名后内容也可能有变化；无法改名时删除原名称并上传新名称
+            let removed = !renamed && apply_remove(api, remote, from, sha256.as_ref()).await?;
+            let base_sha256 = if renamed { sha256.as_ref() } else { None };
+            let uploaded =
+                apply_upload(server_url, api, remote, last_synced, &change.filename, path, base_sha256, options).await?;
+            Ok(renamed || removed || uploaded)
+        }
+    }
+}
+
+async fn apply_remove(api: &ApiClient, remote: &mut RemoteFonts, filename: &str, sha256: Option<&String>) -> Result<bool> {
+    let Some((current, _)) = remote.get(filename) else {
+        info!("Queued removal of '{}' already applied on server", filename);
+        return Ok(false);
+    };
+    if sha256 != Some(current) {
+        warn!("Server copy of '{}' changed since it was removed locally, keeping it", filename);
+        return Ok(false);
+    }
+    // 比较之后服务器上的版本仍可能被他人修改，由 If-Match 在服务器端把关
+    match api.delete_font(filename, Some(current)).await {
+        Ok(_) => {}
+        Err(e) if client::is_version_conflict(&e) => {
+            warn!("Server copy of '{}' changed since it was removed locally, keeping it", filename);
+            return Ok(false);
+        }
+        Err(e) => return Err(e),
+    }
+    remote.remove(filename);
+    info!("Removed '{}' from server", filename);
+    Ok(true)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn apply_upload(
+    server_url: &str,
+    api: &ApiClient,
+    remote: &mut RemoteFonts,
+    last_synced: &HashMap<String, String>,
+    filename: &str,
+    path: &Path,
+    base_sha256: Option<&String>,
+    options: &SyncOptions,
+) -> Result<bool> {
+    if !path.is_file() {
+        info!("Queued upload of '{}' skipped, file no longer exists", filename);
+        return Ok(false);
+    }
+    let local_sha256 = utils::calculate_sha256(path)?;
+    let mut filename = filename.to_string();
+    // 监控启动前就存在的文件以上次同步的内容作为基准
+    let base_sha256 = base_sha256.or_else(|| last_synced.get(&filename));
+    let mut refreshed = false;
+
+    let response = loop {
+        match remote.get(&filename) {
+            Some((remote_sha256, _)) if *remote_sha256 == local_sha256 => {
+                info!("Font '{}' already on server with same SHA256, skipping", filename);
+                return Ok(false);
+            }
+            // 服务器内容不是本地修改前的版本，说明其他客户端也改过
+            Some((remote_sha256, remote_modified)) if Some(remote_sha256) != base_sha256 => {
+                let conflict = utils::FileConflict {
+                    filename: &filename,
+                    local_sha256: &local_sha256,
+                    remote_sha256,
+                    local_modified: utils::get_file_timestamp(path).ok(),
+                    remote_modified: *remote_modified,
+                    direction: SyncDirection::Upload,
+                    counterpart: None,
+                };
+                match utils::prompt_conflict_resolution(&conflict, options.interactive, options.on_conflict).await? {
+                    ConflictResolution::Overwrite => {}
+                    ConflictResolution::Rename => filename = unique_name(path, remote),
+                    ConflictResolution::Skip => return Ok(false),
+                }
+            }
+            _ => {}
+        }
+
+        // 监控到的本地变更来自用户操作，视为显式重新添加；服务器上的版本作为 If-Match
+        let remote_sha256 = remote.get(&filename).map(|(sha256, _)| sha256.as_str());
+        match api.upload_changed_font(path, &filename, &local_sha256, options.e2e_key.as_ref(), true, remote_sha256).await {
+            // 列出服务器字体后又有他人上传，按服务器的当前版本重新判断一次冲突
+            Err(e) if client::is_version_conflict(&e) && !refreshed => {
+                warn!("Font '{}' changed on the server while the queue was flushed", filename);
+                let current = client::server_error(&e)
+                    .and_then(|error| error.details.as_ref()?["current_sha256"].as_str().map(str::to_string));
+                match current {
+                    Some(sha256) => remote.insert(filename.clone(), (sha256, None)),
+                    None => remote.remove(&filename),
+                };
+                refreshed = true;
+            }
+            result => break result?,
+        }
+    };
+    info!("Uploaded queued font '{}' ({})", filename, response.action);
+    // 等待审核的上传在批准前不记为已同步
+    if response.is_awaiting_approval() {
+        return Ok(true);
+    }
+    if let Err(e) = ClientState::record_synced(server_url, [(filename.clone(), local_sha256.clone())]) {
+        warn!("Failed to save client state: {}", e);
+    }
+    remote.insert(filename, (local_sha256, response.modified));
+    Ok(true)
+}
+
+fn unique_name(path: &Path, remote: &RemoteFonts) -> String {
+    let mut counter = 1;
+    let mut name = utils::generate_unique_filename(path, counter);
+    while remote.contains_key(&name) {
+        counter += 1;
+        name = utils::generate_unique_filename(path, counter);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = "http://localhost:8080";
+
+    fn upload(name: &str, base: Option<&str>) -> PendingChange {
+        PendingChange::new(
+            name.to_string(),
+            PendingOp::Upload { path: PathBuf::from(name), base_sha256: base.map(str::to_string) },
+        )
+    }
+
+    fn remove(name: &str, sha256: &str) -> PendingChange {
+        PendingChange::new(name.to_string(), PendingOp::Remove { sha256: Some(sha256.to_string()) })
+    }
+
+    #[test]
+    fn later_changes_supersede_queued_ones() {
+        let mut queue = OfflineQueue::default();
+        queue.push(SERVER, upload("a.ttf", Some("a0")));
+        queue.push(SERVER, upload("b.ttf", None));
+        queue.push(&format!("{}/", SERVER), upload("a.ttf", Some("a1")));
+        assert_eq!(queue.depth(SERVER), 2);
+        // 合并后仍以第一次修改前的服务器内容作为冲突依据
+        assert_eq!(queue.pending(SERVER)[1].op, upload("a.ttf", Some("a0")).op);
+
+        // 新增后又删除的文件不需要提交
+        queue.push(SERVER, remove("b.ttf", "b1"));
+        assert_eq!(queue.depth(SERVER), 1);
+
+        queue.push(SERVER, remove("a.ttf", "a1"));
+        assert_eq!(queue.pending(SERVER)[0].op, PendingOp::Remove { sha256: Some("a0".to_string()) });
+
+        queue.push(SERVER, upload("a.ttf", None));
+        assert_eq!(queue.pending(SERVER)[0].op, upload("a.ttf", Some("a0")).op);
+
+        let json = serde_json::to_string(&queue).unwrap();
+        let restored: OfflineQueue = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.pending(SERVER), queue.pending(SERVER));
+    }
+
+    fn rename(from: &str, to: &str, sha256: Option<&str>) -> PendingChange {
+        let op = PendingOp::Rename { from: from.to_string(), path: PathBuf::from(to), sha256: sha256.map(str::to_string) };
+        PendingChange::new(to.to_string(), op)
+    }
+
+    #[test]
+    fn renames_take_over_queued_changes() {
+        let mut queue = OfflineQueue::default();
+        queue.push(SERVER, rename("a.ttf", "b.ttf", Some("a0")));
+        // 改名后修改或再次改名，提交时仍从服务器上的原名称改名
+        queue.push(SERVER, upload("b.ttf", None));
+        queue.push(SERVER, rename("b.ttf", "c.ttf", None));
+        assert_eq!(queue.depth(SERVER), 1);
+        assert_eq!(queue.pending(SERVER)[0].filename, "c.ttf");
+        assert_eq!(queue.pending(SERVER)[0].op, rename("a.ttf", "c.ttf", Some("a0")).op);
+
+        // 改回原名称只剩内容变化
+        queue.push(SERVER, rename("c.ttf", "a.ttf", None));
+        assert_eq!(queue.pending(SERVER)[0].filename, "a.ttf");
+        assert_eq!(queue.pending(SERVER)[0].op, upload("a.ttf", Some("a0")).op);
+
+        // 服务器从未见过的文件改名后仍是新增
+        queue.push(SERVER, upload("n.ttf", None));
+        queue.push(SERVER, rename("n.ttf", "m.ttf", None));
+        assert_eq!(queue.depth(SERVER), 2);
+        assert_eq!(queue.pending(SERVER)[1].op, upload("m.ttf", None).op);
+
+        // 改名后删除，删除的是服务器上的原名称
+        queue.push(SERVER, rename("w.ttf", "x.ttf", Some("w0")));
+        queue.push(SERVER, remove("x.ttf", "w0"));
+        assert_eq!(queue.pending(SERVER)[2].filename, "w.ttf");
+        assert_eq!(queue.pending(SERVER)[2].op, PendingOp::Remove { sha256: Some("w0".to_string()) });
+
+        // 新名称已有待提交操作时拆成删除与上传
+        queue.push(SERVER, rename("m.ttf", "a.ttf", None));
+        queue.push(SERVER, rename("p.ttf", "a.ttf", Some("p0")));
+        assert!(queue.pending(SERVER).iter().any(|c| c.filename == "p.ttf" && c.op == PendingOp::Remove { sha256: Some("p0".to_string()) }));
+        assert!(queue.pending(SERVER).iter().any(|c| c.filename == "a.ttf" && matches!(c.op, PendingOp::Upload { .. })));
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::{self, Subscription};
+
+// 稀疏同步时代替字体文件的占位条目，保存为同步目录中的 "<字体名>.fsplaceholder"，
+// 扩展名不是字体格式，扫描、上传与安装都不会处理
+const EXTENSION: &str = "fsplaceholder";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Placeholder {
+    pub server_url: String,
+    pub name: String,
+    pub sha256: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+impl Placeholder {
+    pub fn new(server_url: &str, font: &api::FontInfo) -> Self {
+        Self {
+            server_url: server_url.trim_end_matches('/').to_string(),
+            name: font.name.clone(),
+            sha256: font.content_sha256().to_string(),
+            size: font.size,
+            family: font.family.clone(),
+            style: font.style.clone(),
+            modified: font.modified,
+            tags: font.tags.clone(),
+        }
+    }
+
+    // 按文件名或家族名通配符（不区分大小写）匹配
+    pub fn matches(&self, pattern: &str) -> bool {
+        if self.name.eq_ignore_ascii_case(pattern) {
+            return true;
+        }
+        let subscription = Subscription::new(&[pattern.to_string()], &[], &[]);
+        self.family.is_some() && subscription.matches(&self.name, self.family.as_deref(), None)
+    }
+}
+
+pub fn path_for(local_dir: &Path, name: &str) -> PathBuf {
+    local_dir.join(format!("{}.{}", name, EXTENSION))
+}
+
+// 内容未变时不重写，避免每次同步都改动目录
+pub fn write(local_dir: &Path, placeholder: &Placeholder) -> Result<()> {
+    let path = path_for(local_dir, &placeholder.name);
+    if load(&path).is_some_and(|existing| existing == *placeholder) {
+        return Ok(());
+    }
+    let content = serde_json::to_vec_pretty(placeholder)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write placeholder {:?}", path))
+}
+
+fn load(path: &Path) -> Option<Placeholder> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+// 字体已下载时删除占位
+pub fn remove(local_dir: &Path, name: &str) {
+    let path = path_for(local_dir, name);
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove placeholder {:?}: {}", path, e),
+    }
+}
+
+// 同步目录中的全部占位，按文件名排序
+pub fn list(local_dir: &Path) -> Vec<Placeholder> {
+    let mut placeholders: Vec<Placeholder> = match fs::read_dir(local_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|path| {
+                let placeholder = load(&path);
+                if placeholder.is_none() {
+                    warn!("Ignoring invalid placeholder {:?}", path);
+                }
+                placeholder
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    placeholders.sort_by(|a, b| a.name.cmp(&b.name));
+    placeholders
+}
+
+// 删除该服务器上已不存在的字体的占位
+pub fn prune(local_dir: &Path, server_url: &str, server_names: &HashSet<String>) {
+    let server_url = server_url.trim_end_matches('/');
+    for placeholder in list(local_dir) {
+        if placeholder.server_url == server_url && !server_names.contains(&placeholder.name) {
+            info!("Removing placeholder of '{}', which was deleted on the server", placeholder.name);
+            remove(local_dir, &placeholder.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(name: &str, family: Option<&str>) -> api::FontInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "size": 10, "mime_type": "font/otf", "sha256": "s", "modified": 1, "family": family
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_written_matched_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        for font in [font("NotoSansCJK-Bold.otf", Some("Noto Sans CJK SC")), font("Logo.ttf", None)] {
+            write(dir, &Placeholder::new("http://fonts/", &font)).unwrap();
+        }
+        let placeholders = list(dir);
+        assert_eq!(placeholders.len(), 2);
+        assert_eq!(placeholders[1].server_url, "http://fonts");
+        assert!(placeholders[1].matches("noto sans cjk*"));
+        assert!(!placeholders[1].matches("Roboto"));
+        assert!(placeholders[0].matches("logo.ttf"));
+        assert!(!placeholders[0].matches("*"));
+        // 占位不是字体文件
+        assert!(!crate::utils::is_font_file(&path_for(dir, "Logo.ttf")));
+
+        prune(dir, "http://other", &HashSet::new());
+        assert_eq!(list(dir).len(), 2);
+        prune(dir, "http://fonts", &HashSet::from(["Logo.ttf".to_string()]));
+        assert_eq!(list(dir).iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Logo.ttf"]);
+        remove(dir, "Logo.ttf");
+        assert!(list(dir).is_empty());
+    }
+}
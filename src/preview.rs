@@ -0,0 +1,298 @@
+use anyhow::{Context, Result};
+use std::io::Cursor;
+
+pub const DEFAULT_TEXT: &str = "The quick brown fox jumps over the lazy dog 0123456789";
+pub const DEFAULT_SIZE: f32 = 48.0;
+pub const MIN_SIZE: f32 = 8.0;
+pub const MAX_SIZE: f32 = 256.0;
+pub const MAX_TEXT_CHARS: usize = 200;
+// 超出宽度的文字被截断
+const MAX_WIDTH: usize = 4096;
+
+// 单行文字的灰度覆盖率（0..=1），按行存放
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub coverage: Vec<f32>,
+}
+
+impl Bitmap {
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        if x < self.width && y < self.height {
+            self.coverage[y * self.width + x]
+        } else {
+            0.0
+        }
+    }
+}
+
+// 将示例文字以黑字白底渲染为单行 PNG
+pub fn render_png(data: &[u8], text: &str, size: f32) -> Result<Vec<u8>> {
+    let bitmap = render(data, text, size)?;
+    let pixels = bitmap
+        .coverage
+        .into_iter()
+        .map(|c| 255 - (c * 255.0).round() as u8)
+        .collect();
+    let image = image::GrayImage::from_raw(bitmap.width as u32, bitmap.height as u32, pixels)
+        .context("Invalid preview dimensions")?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .context("Failed to encode preview")?;
+    Ok(png)
+}
+
+// 文字在该字号下的前进宽度（像素），不含留白
+pub fn text_width(data: &[u8], text: &str, size: f32) -> Result<f32> {
+    let face = ttf_parser::Face::parse(data, 0).context("Unsupported font format")?;
+    let scale = size / f32::from(face.units_per_em());
+    Ok(glyphs(&face, text)
+        .iter()
+        .map(|&glyph| f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale)
+        .sum())
+}
+
+fn glyphs(face: &ttf_parser::Face, text: &str) -> Vec<ttf_parser::GlyphId> {
+    text.chars()
+        .map(|c| face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0)))
+        .collect()
+}
+
+// 四周留出字号 1/4 的空白
+pub fn render(data: &[u8], text: &str, size: f32) -> Result<Bitmap> {
+    let face = ttf_parser::Face::parse(data, 0).context("Unsupported font format")?;
+    let scale = size / f32::from(face.units_per_em());
+    let ascender = f32::from(face.ascender()) * scale;
+    let descender = f32::from(face.descender()) * scale;
+    let padding = (size * 0.25).ceil();
+
+    let glyphs = glyphs(&face, text);
+    let advance: f32 = glyphs
+        .iter()
+        .map(|&glyph| f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale)
+        .sum();
+
+    let width = ((advance + padding * 2.0).ceil() as usize).clamp(1, MAX_WIDTH);
+    let height = ((ascender - descender + padding * 2.0).ceil() as usize).max(1);
+    let mut raster = Rasterizer::new(width, height);
+
+    let baseline = padding + ascender;
+    let mut pen_x = padding;
+    for glyph in glyphs {
+        let mut builder = OutlineBuilder {
+            raster: &mut raster,
+            origin: (pen_x, baseline),
+            scale,
+            start: (0.0, 0.0),
+            last: (0.0, 0.0),
+        };
+        face.outline_glyph(glyph, &mut builder);
+        pen_x += f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale;
+        if pen_x > width as f32 {
+            break;
+        }
+    }
+
+    Ok(Bitmap { width, height, coverage: raster.coverage() })
+}
+
+// 把字形坐标（y 向上）转换为像素坐标（y 向下），并把曲线拆成线段
+struct OutlineBuilder<'a> {
+    raster: &'a mut Rasterizer,
+    origin: (f32, f32),
+    scale: f32,
+    start: (f32, f32),
+    last: (f32, f32),
+}
+
+impl OutlineBuilder<'_> {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.origin.0 + x * self.scale, self.origin.1 - y * self.scale)
+    }
+
+    fn line(&mut self, to: (f32, f32)) {
+        self.raster.line(self.last, to);
+        self.last = to;
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+// 按控制多边形长度决定分段数，每段约 2 像素
+fn segments(length: f32) -> usize {
+    ((length / 2.0).ceil() as usize).clamp(1, 64)
+}
+
+impl ttf_parser::OutlineBuilder for OutlineBuilder<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.line(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.last, self.point(x1, y1), self.point(x, y));
+        let n = segments(distance(p0, p1) + distance(p1, p2));
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            self.line((
+                u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0,
+                u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (self.last, self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        let n = segments(distance(p0, p1) + distance(p1, p2) + distance(p2, p3));
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.line((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        let start = self.start;
+        self.line(start);
+    }
+}
+
+// 基于有向面积累加的抗锯齿扫描线光栅化，非零环绕规则
+struct Rasterizer {
+    width: usize,
+    height: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            // 末尾多留一格，避免线段恰好落在最右列时越界
+            accumulation: vec![0.0; width * height + 2],
+        }
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        // 水平方向超出画布的部分压到边缘，保持每行的环绕数正确
+        let max_x = self.width.saturating_sub(1) as f32;
+        let (p0, p1) = ((from.0.clamp(0.0, max_x), from.1), (to.0.clamp(0.0, max_x), to.1));
+        if (p0.1 - p1.1).abs() <= f32::EPSILON {
+            return;
+        }
+        let (direction, p0, p1) = if p0.1 < p1.1 { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+        let dxdy = (p1.0 - p0.0) / (p1.1 - p0.1);
+
+        let mut x = p0.0;
+        if p0.1 < 0.0 {
+            x -= p0.1 * dxdy;
+        }
+        let first_row = p0.1.max(0.0) as usize;
+        let last_row = (p1.1.ceil().max(0.0) as usize).min(self.height);
+
+        for row in first_row..last_row {
+            let row_start = row * self.width;
+            let dy = ((row + 1) as f32).min(p1.1) - (row as f32).max(p0.1);
+            let x_next = x + dxdy * dy;
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+
+            if x1i <= x0i + 1 {
+                // 线段在本行只经过一个像素
+                let xmf = 0.5 * (x + x_next) - x0_floor;
+                self.accumulation[row_start + x0i] += d - d * xmf;
+                self.accumulation[row_start + x0i + 1] += d * xmf;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                self.accumulation[row_start + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.accumulation[row_start + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.accumulation[row_start + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.accumulation[row_start + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.accumulation[row_start + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.accumulation[row_start + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    // 逐行累加得到每个像素的覆盖率（0..=1）
+    fn coverage(&self) -> Vec<f32> {
+        let mut output = Vec::with_capacity(self.width * self.height);
+        for row in self.accumulation[..self.width * self.height].chunks(self.width) {
+            let mut sum = 0.0f32;
+            for value in row {
+                sum += value;
+                output.push(sum.abs().min(1.0));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rasterizes_filled_square() {
+        let mut raster = Rasterizer::new(10, 10);
+        let square = [(2.0, 2.0), (8.0, 2.0), (8.0, 8.0), (2.0, 8.0), (2.0, 2.0)];
+        for pair in square.windows(2) {
+            raster.line(pair[0], pair[1]);
+        }
+
+        let coverage = raster.coverage();
+        let at = |x: usize, y: usize| coverage[y * 10 + x];
+        assert!((at(5, 5) - 1.0).abs() < 1e-4);
+        assert!(at(1, 5) < 1e-4);
+        assert!(at(5, 1) < 1e-4);
+        assert!(at(9, 5) < 1e-4);
+    }
+
+    #[test]
+    fn renders_font_glyphs() {
+        let font = crate::font_metadata::tests::square_font();
+        let png = render_png(&font, "A", 20.0).expect("render");
+        let image = image::load_from_memory(&png).expect("decode").to_luma8();
+
+        // 字号 20 时方块位于 x 5..21、y 5..21（含 5 像素留白）
+        assert_eq!(image.dimensions(), (30, 30));
+        assert_eq!(image.get_pixel(12, 14).0[0], 0);
+        assert_eq!(image.get_pixel(2, 2).0[0], 255);
+    }
+
+    #[test]
+    fn rejects_non_font_data() {
+        assert!(render_png(b"not a font", "abc", DEFAULT_SIZE).is_err());
+    }
+}
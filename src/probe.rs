@@ -0,0 +1,375 @@
+use anyhow::Result;
+use reqwest::Url;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::api::{ErrorCode, FontQuery, PROTOCOL_VERSION};
+use crate::client::{self, ApiClient};
+use crate::http;
+use crate::websocket_client;
+
+// 每一步的超时，避免不可达的地址让诊断长时间无响应
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 连接失败的类别，决定给出的处理建议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    InvalidUrl,
+    Dns,
+    Refused,
+    Unreachable,
+    Tls,
+    // 可以连接但应答的不是 fontsync 服务器，如端口或路径前缀错误
+    NotFontsync,
+    Auth,
+    Protocol,
+    WebSocket,
+}
+
+impl Failure {
+    pub fn label(self) -> &'static str {
+        match self {
+            Failure::InvalidUrl => "invalid server URL",
+            Failure::Dns => "DNS lookup failed",
+            Failure::Refused => "connection refused",
+            Failure::Unreachable => "host unreachable",
+            Failure::Tls => "TLS problem",
+            Failure::NotFontsync => "not a fontsync server",
+            Failure::Auth => "authentication failed",
+            Failure::Protocol => "protocol mismatch",
+            Failure::WebSocket => "WebSocket unavailable",
+        }
+    }
+
+    pub fn hint(self) -> &'static str {
+        match self {
+            Failure::InvalidUrl => "use a URL such as http://host:8080 or unix:/path/to/socket",
+            Failure::Dns => "check the host name and the DNS settings of this machine",
+            Failure::Refused => "check that the server is running and listening on this port",
+            Failure::Unreachable => "check the network route, firewall and proxy settings",
+            Failure::Tls => "check the server certificate and the system clock, or use http:// if the server has no TLS",
+            Failure::NotFontsync => "check the port and, behind a reverse proxy, the path prefix",
+            Failure::Auth => "run `fontsync login <server-url>` to store a valid token",
+            Failure::Protocol => "upgrade the client or the server so that both speak the same protocol",
+            Failure::WebSocket => {
+                "allow WebSocket upgrades in the reverse proxy; until then clients fall back to Server-Sent Events"
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Ok(String),
+    Skipped(String),
+    // 不影响使用但会降级，如 WebSocket 不可用时改用 SSE
+    Warning(Failure, String),
+    Failed(Failure, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub step: &'static str,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeReport {
+    pub server_url: String,
+    pub checks: Vec<Check>,
+}
+
+impl ProbeReport {
+    // 第一个失败的步骤，之后的步骤不再执行
+    pub fn failure(&self) -> Option<(Failure, &str)> {
+        self.checks.iter().find_map(|check| match &check.outcome {
+            Outcome::Failed(failure, detail) => Some((*failure, detail.as_str())),
+            _ => None,
+        })
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = (Failure, &str)> {
+        self.checks.iter().filter_map(|check| match &check.outcome {
+            Outcome::Warning(failure, detail) => Some((*failure, detail.as_str())),
+            _ => None,
+        })
+    }
+
+    // 一行结论，供状态栏与日志使用
+    pub fn summary(&self) -> String {
+        match self.failure() {
+            Some((failure, detail)) => format!("{}: {} ({})", failure.label(), detail, failure.hint()),
+            None => match self.warnings().next() {
+                Some((failure, detail)) => format!("reachable, but {}: {}", failure.label(), detail),
+                None => "all checks passed".to_string(),
+            },
+        }
+    }
+
+    fn push(&mut self, step: &'static str, outcome: Outcome) -> bool {
+        let failed = matches!(outcome, Outcome::Failed(..));
+        self.checks.push(Check { step, outcome });
+        !failed
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Connectivity check for {}", self.server_url)?;
+        for check in &self.checks {
+            let (mark, detail, hint) = match &check.outcome {
+                Outcome::Ok(detail) => ("ok", detail.clone(), None),
+                Outcome::Skipped(detail) => ("skip", detail.clone(), None),
+                Outcome::Warning(failure, detail) => ("warn", format!("{}: {}", failure.label(), detail), Some(failure.hint())),
+                Outcome::Failed(failure, detail) => ("FAIL", format!("{}: {}", failure.label(), detail), Some(failure.hint())),
+            };
+            writeln!(f, "  [{:<4}] {:<9} {}", mark, check.step, detail)?;
+            if let Some(hint) = hint {
+                writeln!(f, "  {:<16} hint: {}", "", hint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 依次检查地址、DNS、TCP、TLS、/healthz、协议版本、令牌与 WebSocket 握手，在第一个失败处停止
+pub async fn run(server_url: &str, client_id: &str) -> ProbeReport {
+    let mut report = ProbeReport { server_url: server_url.to_string(), checks: Vec::new() };
+    let _ = probe(server_url, client_id, &mut report).await;
+    report
+}
+
+async fn probe(server_url: &str, client_id: &str, report: &mut ProbeReport) -> Option<()> {
+    let unix_socket = http::unix_socket_path(server_url);
+    let resolved = http::resolve_server_url(server_url);
+    let url = match parse_url(&resolved) {
+        Ok(url) => url,
+        Err(detail) => {
+            report.push("url", Outcome::Failed(Failure::InvalidUrl, detail));
+            return None;
+        }
+    };
+    // IPv6 地址在 URL 中带方括号
+    let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let secure = matches!(url.scheme(), "https" | "wss");
+
+    if let Some(path) = &unix_socket {
+        report.push("url", Outcome::Ok(format!("Unix socket {}", path.display())));
+    } else {
+        report.push("url", Outcome::Ok(url.to_string()));
+    }
+
+    // 经代理时由代理解析与连接目标主机，只能从 HTTP 请求的结果判断
+    let proxy = unix_socket.is_none().then(|| http::proxy_for(&url)).flatten();
+    if let Some(path) = &unix_socket {
+        report.push("dns", Outcome::Skipped("Unix socket".to_string()));
+        if !report.push("tcp", connect_unix(path).await) {
+            return None;
+        }
+        report.push("tls", Outcome::Skipped("plain HTTP".to_string()));
+    } else if let Some(proxy) = &proxy {
+        let note = format!("connecting through proxy {}", proxy.host_str().unwrap_or_default());
+        for step in ["dns", "tcp", "tls"] {
+            report.push(step, Outcome::Skipped(note.clone()));
+        }
+    } else {
+        let addrs = match tokio::time::timeout(STEP_TIMEOUT, tokio::net::lookup_host((host.as_str(), port))).await {
+            Ok(Ok(addrs)) => addrs.collect::<Vec<SocketAddr>>(),
+            Ok(Err(e)) => {
+                report.push("dns", Outcome::Failed(Failure::Dns, format!("{}: {}", host, e)));
+                return None;
+            }
+            Err(_) => {
+                report.push("dns", Outcome::Failed(Failure::Dns, format!("{}: lookup timed out", host)));
+                return None;
+            }
+        };
+        if addrs.is_empty() {
+            report.push("dns", Outcome::Failed(Failure::Dns, format!("{} has no addresses", host)));
+            return None;
+        }
+        let listed: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        report.push("dns", Outcome::Ok(format!("{} resolves to {}", host, listed.join(", "))));
+
+        let stream = match connect_any(&addrs).await {
+            Ok(stream) => stream,
+            Err((failure, detail)) => {
+                report.push("tcp", Outcome::Failed(failure, detail));
+                return None;
+            }
+        };
+        let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        report.push("tcp", Outcome::Ok(format!("connected to {}", peer)));
+
+        if secure {
+            let connector = native_tls::TlsConnector::new().map(tokio_native_tls::TlsConnector::from);
+            let handshake = async { connector?.connect(&host, stream).await };
+            let outcome = match tokio::time::timeout(STEP_TIMEOUT, handshake).await {
+                Ok(Ok(_)) => Outcome::Ok("certificate accepted".to_string()),
+                Ok(Err(e)) => Outcome::Failed(Failure::Tls, e.to_string()),
+                Err(_) => Outcome::Failed(Failure::Tls, "handshake timed out".to_string()),
+            };
+            if !report.push("tls", outcome) {
+                return None;
+            }
+        } else {
+            report.push("tls", Outcome::Skipped("plain HTTP".to_string()));
+        }
+    }
+
+    let api = match ApiClient::new(server_url) {
+        Ok(api) => api,
+        Err(e) => {
+            report.push("healthz", Outcome::Failed(Failure::InvalidUrl, format!("{:#}", e)));
+            return None;
+        }
+    };
+    let outcome = match timed(api.health()).await {
+        Ok(health) => Outcome::Ok(format!("status {}", health.status)),
+        // 旧版服务器没有 /healthz，由后续的 /version 判断
+        Err(e) if client::server_error(&e).is_some_and(|e| e.code == ErrorCode::NotFound) => {
+            Outcome::Skipped("not provided by this server version".to_string())
+        }
+        Err(e) => {
+            let failure = classify_request_error(&e);
+            Outcome::Failed(failure, format!("{:#}", e))
+        }
+    };
+    if !report.push("healthz", outcome) {
+        return None;
+    }
+
+    let outcome = match timed(api.version()).await {
+        Ok(version) if version.supports(PROTOCOL_VERSION) => {
+            Outcome::Ok(format!("server {} speaks protocol {}", version.version, PROTOCOL_VERSION))
+        }
+        Ok(version) => Outcome::Failed(
+            Failure::Protocol,
+            format!(
+                "server {} supports protocol(s) {:?}, this client speaks {}",
+                version.version, version.supported_protocols, PROTOCOL_VERSION
+            ),
+        ),
+        Err(e) => Outcome::Failed(classify_request_error(&e), format!("{:#}", e)),
+    };
+    if !report.push("protocol", outcome) {
+        return None;
+    }
+
+    let query = FontQuery { limit: Some(1), ..FontQuery::default() };
+    let outcome = match timed(api.list_fonts(&query)).await {
+        Ok(_) => Outcome::Ok("font list accessible".to_string()),
+        Err(e) => Outcome::Failed(classify_request_error(&e), format!("{:#}", e)),
+    };
+    if !report.push("auth", outcome) {
+        return None;
+    }
+
+    let outcome = match timed(websocket_client::check_connection(server_url, client_id)).await {
+        Ok(ws_url) => Outcome::Ok(format!("handshake with {} succeeded", ws_url)),
+        Err(e) => match websocket_client::handshake_status(&e) {
+            Some(401 | 403) => Outcome::Failed(Failure::Auth, format!("{:#}", e)),
+            // 426 时 connect_ws 已换成服务器的说明
+            _ if e.to_string().starts_with("Incompatible server version") => {
+                Outcome::Failed(Failure::Protocol, format!("{:#}", e))
+            }
+            _ => Outcome::Warning(Failure::WebSocket, format!("{:#}", e)),
+        },
+    };
+    report.push("websocket", outcome).then_some(())
+}
+
+fn parse_url(server_url: &str) -> Result<Url, String> {
+    let url = Url::parse(server_url)
+        .or_else(|_| Url::parse(&format!("http://{}", server_url)))
+        .map_err(|e| format!("{}: {}", server_url, e))?;
+    if !matches!(url.scheme(), "http" | "https" | "ws" | "wss") {
+        return Err(format!("unsupported scheme '{}'", url.scheme()));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("{} has no host", server_url));
+    }
+    Ok(url)
+}
+
+// 依次尝试解析出的地址，全部失败时按第一个地址的错误分类
+async fn connect_any(addrs: &[SocketAddr]) -> Result<TcpStream, (Failure, String)> {
+    let mut first_error = None;
+    for addr in addrs {
+        let failure = match tokio::time::timeout(STEP_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => (Failure::Refused, format!("{}: {}", addr, e)),
+            Ok(Err(e)) => (Failure::Unreachable, format!("{}: {}", addr, e)),
+            Err(_) => (Failure::Unreachable, format!("{}: connection timed out", addr)),
+        };
+        first_error.get_or_insert(failure);
+    }
+    Err(first_error.unwrap_or((Failure::Unreachable, "no address to connect to".to_string())))
+}
+
+// Unix 套接字没有地址解析，只检查能否打开；没有权限时按认证失败提示
+async fn connect_unix(path: &Path) -> Outcome {
+    #[cfg(unix)]
+    return match http::connect_unix_socket(path).await {
+        Ok(_) => Outcome::Ok(format!("connected to {}", path.display())),
+        Err(e) => {
+            let failure = match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+                Some(std::io::ErrorKind::PermissionDenied) => Failure::Auth,
+                Some(std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused) => Failure::Refused,
+                _ => Failure::Unreachable,
+            };
+            Outcome::Failed(failure, format!("{:#}", e))
+        }
+    };
+    #[cfg(not(unix))]
+    Outcome::Failed(Failure::Unreachable, format!("Unix domain sockets are not supported on this platform ({})", path.display()))
+}
+
+async fn timed<T>(request: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(STEP_TIMEOUT, request)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("request timed out after {}s", STEP_TIMEOUT.as_secs())))
+}
+
+// HTTP 请求失败时按服务器的错误类别或连接错误分类
+fn classify_request_error(error: &anyhow::Error) -> Failure {
+    if let Some(server_error) = client::server_error(error) {
+        return match server_error.code {
+            ErrorCode::Unauthorized | ErrorCode::Forbidden => Failure::Auth,
+            ErrorCode::IncompatibleProtocol => Failure::Protocol,
+            _ => Failure::NotFontsync,
+        };
+    }
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) if e.is_connect() => Failure::Refused,
+        Some(e) if e.is_timeout() => Failure::Unreachable,
+        _ if error.to_string().contains("timed out") => Failure::Unreachable,
+        _ => Failure::NotFontsync,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_the_first_failing_step() {
+        let report = run("ftp://fonts.example.com", "probe-test").await;
+        assert_eq!(report.failure().map(|(f, _)| f), Some(Failure::InvalidUrl));
+
+        // 绑定后立即释放的端口上没有监听者
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let report = run(&format!("http://127.0.0.1:{}", port), "probe-test").await;
+        assert_eq!(report.failure().map(|(f, _)| f), Some(Failure::Refused));
+        assert_eq!(report.checks.last().unwrap().step, "tcp");
+        assert!(report.to_string().contains("hint: check that the server is running"));
+        assert!(report.summary().starts_with("connection refused"));
+
+        let report = run("http://fontsync.invalid:8080", "probe-test").await;
+        assert_eq!(report.failure().map(|(f, _)| f), Some(Failure::Dns));
+    }
+}
@@ -0,0 +1,260 @@
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::sync_report::FileAction;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Upload,
+    Download,
+    Install,
+}
+
+// 命令行的进度显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// 终端进度条
+    #[default]
+    Bar,
+    /// 每行一个 JSON 事件，写到标准输出
+    Json,
+    /// 不显示进度
+    None,
+}
+
+// 多文件操作的进度回调，上传、下载与安装过程中依次调用；实现需可在并发任务间共享
+pub trait ProgressReporter: Send + Sync {
+    // 开始一批操作，files 为待处理的文件数
+    fn started(&self, operation: Operation, files: usize);
+    // 单个文件已传输的字节数，total 未知时为 0
+    fn file_progress(&self, operation: Operation, name: &str, done: u64, total: u64);
+    // 单个文件处理完成，action 与同步报告中的记录一致
+    fn file_finished(&self, operation: Operation, name: &str, action: FileAction);
+    // 服务器繁忙（429 或 503），队列暂停 retry_in 后重试该文件
+    fn server_busy(&self, operation: Operation, name: &str, retry_in: Duration);
+    fn finished(&self, operation: Operation);
+}
+
+// 回调对应的事件，JSON 行输出与发往界面线程的消息使用同一结构
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    Started { operation: Operation, files: usize },
+    FileProgress { operation: Operation, name: String, done: u64, total: u64 },
+    FileFinished { operation: Operation, name: String, action: FileAction },
+    // retry_in 为秒数
+    ServerBusy { operation: Operation, name: String, retry_in: u64 },
+    Finished { operation: Operation },
+}
+
+// 以事件形式转发回调的实现只需提供 emit
+trait EventSink: Send + Sync {
+    fn emit(&self, event: ProgressEvent);
+}
+
+impl<T: EventSink> ProgressReporter for T {
+    fn started(&self, operation: Operation, files: usize) {
+        self.emit(ProgressEvent::Started { operation, files });
+    }
+
+    fn file_progress(&self, operation: Operation, name: &str, done: u64, total: u64) {
+        self.emit(ProgressEvent::FileProgress { operation, name: name.to_string(), done, total });
+    }
+
+    fn file_finished(&self, operation: Operation, name: &str, action: FileAction) {
+        self.emit(ProgressEvent::FileFinished { operation, name: name.to_string(), action });
+    }
+
+    fn server_busy(&self, operation: Operation, name: &str, retry_in: Duration) {
+        self.emit(ProgressEvent::ServerBusy { operation, name: name.to_string(), retry_in: retry_in.as_secs() });
+    }
+
+    fn finished(&self, operation: Operation) {
+        self.emit(ProgressEvent::Finished { operation });
+    }
+}
+
+// 可克隆的进度回调，默认不报告
+#[derive(Clone)]
+pub struct Progress(Arc<dyn ProgressReporter>);
+
+impl Progress {
+    pub fn new(reporter: impl ProgressReporter + 'static) -> Self {
+        Self(Arc::new(reporter))
+    }
+
+    pub fn none() -> Self {
+        Self::new(NoProgress)
+    }
+
+    // 终端中的 indicatif 进度条，输出不是终端时不显示
+    pub fn bars() -> Self {
+        Self::new(BarProgress::default())
+    }
+
+    // 每个事件一行 JSON 写到标准输出
+    pub fn json_lines() -> Self {
+        Self::new(JsonLinesProgress)
+    }
+
+    // 事件发往 GUI 或 TUI 的界面线程；接收端关闭后丢弃
+    pub fn channel(sender: std::sync::mpsc::Sender<ProgressEvent>) -> Self {
+        Self::new(ChannelProgress(sender))
+    }
+}
+
+impl From<ProgressMode> for Progress {
+    fn from(mode: ProgressMode) -> Self {
+        match mode {
+            ProgressMode::Bar => Self::bars(),
+            ProgressMode::Json => Self::json_lines(),
+            ProgressMode::None => Self::none(),
+        }
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
+impl std::ops::Deref for Progress {
+    type Target = dyn ProgressReporter;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn started(&self, _: Operation, _: usize) {}
+    fn file_progress(&self, _: Operation, _: &str, _: u64, _: u64) {}
+    fn file_finished(&self, _: Operation, _: &str, _: FileAction) {}
+    fn server_busy(&self, _: Operation, _: &str, _: Duration) {}
+    fn finished(&self, _: Operation) {}
+}
+
+// 每批操作一个按文件数推进的进度条，消息中显示当前文件的传输字节数
+#[derive(Default)]
+struct BarProgress {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl ProgressReporter for BarProgress {
+    fn started(&self, operation: Operation, files: usize) {
+        let bar = ProgressBar::new(files as u64);
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} {prefix} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        bar.set_prefix(match operation {
+            Operation::Upload => "Uploading",
+            Operation::Download => "Downloading",
+            Operation::Install => "Installing",
+        });
+        if let Some(previous) = self.bar.lock().replace(bar) {
+            previous.finish_and_clear();
+        }
+    }
+
+    fn file_progress(&self, _: Operation, name: &str, done: u64, total: u64) {
+        if let Some(bar) = self.bar.lock().as_ref() {
+            match total {
+                0 => bar.set_message(format!("{} {}", name, HumanBytes(done))),
+                _ => bar.set_message(format!("{} {}/{}", name, HumanBytes(done), HumanBytes(total))),
+            }
+        }
+    }
+
+    fn file_finished(&self, _: Operation, name: &str, _: FileAction) {
+        if let Some(bar) = self.bar.lock().as_ref() {
+            bar.set_message(name.to_string());
+            bar.inc(1);
+        }
+    }
+
+    fn server_busy(&self, _: Operation, name: &str, retry_in: Duration) {
+        if let Some(bar) = self.bar.lock().as_ref() {
+            bar.set_message(format!("{}: server busy, retrying in {}s", name, retry_in.as_secs()));
+        }
+    }
+
+    fn finished(&self, _: Operation) {
+        if let Some(bar) = self.bar.lock().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+struct JsonLinesProgress;
+
+impl EventSink for JsonLinesProgress {
+    fn emit(&self, event: ProgressEvent) {
+        if let Ok(line) = serde_json::to_string(&event) {
+            println!("{}", line);
+        }
+    }
+}
+
+struct ChannelProgress(std::sync::mpsc::Sender<ProgressEvent>);
+
+impl EventSink for ChannelProgress {
+    fn emit(&self, event: ProgressEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<ProgressEvent>>);
+
+    impl EventSink for Arc<Collect> {
+        fn emit(&self, event: ProgressEvent) {
+            self.0.lock().push(event);
+        }
+    }
+
+    #[test]
+    fn callbacks_are_forwarded_as_events() {
+        let collected = Arc::new(Collect::default());
+        let progress = Progress::new(collected.clone());
+        progress.started(Operation::Download, 2);
+        progress.file_progress(Operation::Download, "a.ttf", 512, 1024);
+        progress.file_finished(Operation::Download, "a.ttf", FileAction::Downloaded);
+        progress.server_busy(Operation::Download, "b.ttf", Duration::from_secs(30));
+        progress.finished(Operation::Download);
+
+        let events = collected.0.lock().clone();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[2],
+            ProgressEvent::FileFinished { operation: Operation::Download, name: "a.ttf".to_string(), action: FileAction::Downloaded }
+        );
+        assert_eq!(
+            serde_json::to_string(&events[1]).unwrap(),
+            r#"{"event":"file_progress","operation":"download","name":"a.ttf","done":512,"total":1024}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&events[3]).unwrap(),
+            r#"{"event":"server_busy","operation":"download","name":"b.ttf","retry_in":30}"#
+        );
+    }
+}
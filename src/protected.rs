@@ -0,0 +1,198 @@
+use log::warn;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::client_state::ClientState;
+use crate::ignore::glob_matches;
+
+// 用户追加的受保护目录（绝对路径）与字体文件名（glob，不区分大小写），每行一条
+const PROTECTED_FILE: &str = "protected";
+
+// 系统关键字体，任何目录中的同名文件都不会被删除或覆盖
+const DEFAULT_FONTS: &[&str] = &[
+    // Windows
+    "segoeui*.ttf",
+    "seguisym.ttf",
+    "seguiemj.ttf",
+    "arial*.ttf",
+    "tahoma*.ttf",
+    "times*.ttf",
+    "cour*.ttf",
+    "consola*.ttf",
+    "marlett.ttf",
+    "msyh*.ttc",
+    "simsun.ttc",
+    "simhei.ttf",
+    "msgothic.ttc",
+    "malgun*.ttf",
+    // macOS
+    "sfns*.ttf",
+    "sfcompact*.ttf",
+    "sfnsmono*.ttf",
+    "helvetica*.ttc",
+    "lucidagrande.ttc",
+    "pingfang.ttc",
+    "applecoloremoji.ttc",
+    "menlo.ttc",
+    // Linux
+    "dejavu*.ttf",
+    "liberation*.ttf",
+    "noto*.ttc",
+    "notocoloremoji.ttf",
+];
+
+// 删除或安装因目标受保护而被跳过
+#[derive(Debug)]
+pub struct ProtectedPathError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for ProtectedPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is protected ({})", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for ProtectedPathError {}
+
+pub fn is_protected_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.is::<ProtectedPathError>())
+}
+
+// 内置规则不能被配置覆盖，配置文件只能追加
+#[derive(Debug, Clone)]
+pub struct ProtectedPaths {
+    dirs: Vec<PathBuf>,
+    fonts: Vec<String>,
+}
+
+impl ProtectedPaths {
+    pub fn config_path() -> PathBuf {
+        ClientState::state_dir().join(PROTECTED_FILE)
+    }
+
+    pub fn load() -> Self {
+        let mut paths = Self::defaults();
+        match fs::read_to_string(Self::config_path()) {
+            Ok(content) => paths.extend(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to read protected path list {:?}: {}", Self::config_path(), e),
+        }
+        paths
+    }
+
+    fn defaults() -> Self {
+        let mut dirs = Vec::new();
+
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(win_dir) = std::env::var_os("WINDIR") {
+                dirs.push(PathBuf::from(win_dir).join("Fonts"));
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            dirs.push(PathBuf::from("/usr/share/fonts"));
+            dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            dirs.push(PathBuf::from("/System/Library/Fonts"));
+            dirs.push(PathBuf::from("/Library/Fonts"));
+        }
+
+        Self {
+            dirs,
+            fonts: DEFAULT_FONTS.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    fn extend(&mut self, content: &str) {
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let path = Path::new(line);
+            if path.is_absolute() {
+                self.dirs.push(path.to_path_buf());
+            } else {
+                self.fonts.push(line.to_lowercase());
+            }
+        }
+    }
+
+    fn protected_dir(&self, path: &Path) -> Option<&PathBuf> {
+        // 通过符号链接或 .. 指向受保护目录的路径同样拦截
+        let resolved = path
+            .parent()
+            .and_then(|p| fs::canonicalize(p).ok())
+            .zip(path.file_name())
+            .map(|(parent, name)| parent.join(name));
+        self.dirs.iter().find(|dir| {
+            let canonical = fs::canonicalize(dir).ok();
+            [Some(path.to_path_buf()), resolved.clone()].into_iter().flatten().any(|p| {
+                p.starts_with(dir) || canonical.as_ref().is_some_and(|c| p.starts_with(c))
+            })
+        })
+    }
+
+    fn protected_name(&self, path: &Path) -> Option<&String> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        self.fonts.iter().find(|pattern| glob_matches(pattern, &name))
+    }
+
+    // 删除：受保护目录中的任何文件与关键字体都不删除
+    pub fn check_removal(&self, path: &Path) -> Result<(), ProtectedPathError> {
+        let reason = if let Some(dir) = self.protected_dir(path) {
+            format!("inside protected directory {:?}", dir)
+        } else if let Some(pattern) = self.protected_name(path) {
+            format!("matches protected font '{}'", pattern)
+        } else {
+            return Ok(());
+        };
+        Err(ProtectedPathError { path: path.to_path_buf(), reason })
+    }
+
+    // 安装：Windows 的安装目标本身就是系统字体目录，只拦截覆盖其中已有文件与关键字体
+    pub fn check_install(&self, target: &Path) -> Result<(), ProtectedPathError> {
+        let reason = if let Some(pattern) = self.protected_name(target) {
+            format!("matches protected font '{}'", pattern)
+        } else if let Some(dir) = self.protected_dir(target).filter(|_| target.exists()) {
+            format!("would overwrite a font in protected directory {:?}", dir)
+        } else {
+            return Ok(());
+        };
+        Err(ProtectedPathError { path: target.to_path_buf(), reason })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_protected_directories_and_fonts() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let system = dir.path().join("system");
+        fs::create_dir_all(&system).unwrap();
+        fs::create_dir_all(dir.path().join("other")).unwrap();
+        fs::write(system.join("Existing.ttf"), b"font").unwrap();
+
+        let mut paths = ProtectedPaths { dirs: Vec::new(), fonts: Vec::new() };
+        paths.extend(&format!("# 公司字体\n{}\nCorpSans-*.otf\n", system.display()));
+        paths.fonts.extend(DEFAULT_FONTS.iter().map(|f| f.to_string()));
+
+        assert!(paths.check_removal(&system.join("Existing.ttf")).is_err());
+        assert!(paths.check_removal(&dir.path().join("other/../system/Existing.ttf")).is_err());
+        assert!(paths.check_removal(&dir.path().join("CorpSans-Bold.OTF")).is_err());
+        assert!(paths.check_removal(&dir.path().join("SegoeUI.ttf")).is_err());
+        assert!(paths.check_removal(&dir.path().join("Custom.ttf")).is_ok());
+
+        // 新字体可以安装到受保护目录，但不能覆盖其中已有的字体
+        assert!(paths.check_install(&system.join("New.ttf")).is_ok());
+        let error = paths.check_install(&system.join("Existing.ttf")).unwrap_err();
+        assert!(is_protected_error(&anyhow::Error::new(error)));
+        assert!(paths.check_install(&dir.path().join("arialbd.ttf")).is_err());
+    }
+}
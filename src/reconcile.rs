@@ -0,0 +1,195 @@
+use anyhow::Result;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::api::{self, FontQuery};
+use crate::client::{ApiClient, SyncOptions};
+use crate::client_state::ClientState;
+use crate::font_monitor::FontInfo;
+use crate::offline_queue::{OfflineQueue, PendingChange, PendingOp};
+
+// 监控未运行期间任一方发生的变更
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reconciliation {
+    // 本地新增或修改，base_sha256 为上次同步时的内容
+    Upload { path: PathBuf, base_sha256: Option<String> },
+    // 上次运行时监控到的文件已被删除，sha256 为删除前的内容
+    Remove { filename: String, sha256: String },
+    // 服务器上有而本地没有，或只有服务器修改过
+    Download { filename: String, sha256: String },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReconcileSummary {
+    pub queued: usize,
+    pub downloads: usize,
+}
+
+// 比较首次扫描、上次运行时的缓存与服务器上的字体，按文件名配对；
+// 双方内容一致或只有服务器删除过的字体不需要处理
+pub fn plan(
+    local: &[FontInfo],
+    previous: &[FontInfo],
+    server: &[api::FontInfo],
+    synced: &HashMap<String, String>,
+) -> Vec<Reconciliation> {
+    let mut local_by_name: HashMap<&str, &FontInfo> = HashMap::new();
+    for font in local {
+        if let Some(name) = font.path.file_name().and_then(|n| n.to_str()) {
+            local_by_name.entry(name).or_insert(font);
+        }
+    }
+    let server_by_name: HashMap<&str, &api::FontInfo> = server.iter().map(|f| (f.name.as_str(), f)).collect();
+
+    let mut plan = Vec::new();
+    let mut names: Vec<&str> = local_by_name.keys().copied().collect();
+    names.sort_unstable();
+    for name in names {
+        let font = local_by_name[name];
+        let base = synced.get(name);
+        match server_by_name.get(name) {
+            Some(remote) if remote.content_sha256() == font.sha256 => {}
+            // 服务器内容仍是上次同步的版本，本地改过
+            Some(remote) if base.is_some_and(|b| b == remote.content_sha256()) => {
+                plan.push(Reconciliation::Upload { path: font.path.clone(), base_sha256: base.cloned() });
+            }
+            // 本地仍是上次同步的版本，只有服务器改过
+            Some(remote) if base == Some(&font.sha256) => {
+                plan.push(Reconciliation::Download { filename: name.to_string(), sha256: remote.sha256.clone() });
+            }
+            // 双方都改过，由离线队列按冲突处理
+            Some(_) => plan.push(Reconciliation::Upload { path: font.path.clone(), base_sha256: base.cloned() }),
+            // 服务器上已删除的字体由事件回放处理，不重新上传
+            None if base == Some(&font.sha256) => {}
+            None => plan.push(Reconciliation::Upload { path: font.path.clone(), base_sha256: None }),
+        }
+    }
+
+    // 只有上次运行时确实监控到、现在已不存在的文件才算本地删除，
+    // 其余服务器字体可能只是不在本次监控的目录中
+    let removed: HashMap<&str, &FontInfo> = previous
+        .iter()
+        .filter(|font| !font.path.exists())
+        .filter_map(|font| Some((font.path.file_name()?.to_str()?, font)))
+        .collect();
+    for remote in server {
+        if local_by_name.contains_key(remote.name.as_str()) {
+            continue;
+        }
+        match removed.get(remote.name.as_str()) {
+            Some(font) => plan.push(Reconciliation::Remove { filename: remote.name.clone(), sha256: font.sha256.clone() }),
+            None => plan.push(Reconciliation::Download { filename: remote.name.clone(), sha256: remote.sha256.clone() }),
+        }
+    }
+    plan
+}
+
+// 上传与删除写入离线队列，由监控循环随后提交；返回需要下载的字体名称与哈希
+pub async fn reconcile(
+    server_url: &str,
+    local: &[FontInfo],
+    previous: &[FontInfo],
+    options: &SyncOptions,
+    uploads: bool,
+) -> Result<(ReconcileSummary, Vec<(String, String)>)> {
+    let api = ApiClient::new(server_url)?;
+    let server = api.list_fonts_hashed(&FontQuery::default()).await?.fonts;
+    let synced = ClientState::load()
+        .server(server_url)
+        .map(|s| s.synced.clone())
+        .unwrap_or_default();
+    // 与完整同步相同，只下载订阅范围内的字体
+    let subscription = options.subscription();
+    let wanted: HashSet<&str> = server
+        .iter()
+        .filter(|font| subscription.matches_font(font))
+        .map(|font| font.name.as_str())
+        .collect();
+
+    let mut summary = ReconcileSummary::default();
+    let mut queue = OfflineQueue::load();
+    let mut downloads = Vec::new();
+    for action in plan(local, previous, &server, &synced) {
+        let change = match action {
+            // 只下载的映射不提交本地变更
+            Reconciliation::Upload { .. } | Reconciliation::Remove { .. } if !uploads => continue,
+            Reconciliation::Upload { path, base_sha256 } => {
+                let Some(filename) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else { continue };
+                PendingChange::new(filename, PendingOp::Upload { path, base_sha256 })
+            }
+            Reconciliation::Remove { filename, sha256 } => {
+                PendingChange::new(filename, PendingOp::Remove { sha256: Some(sha256) })
+            }
+            Reconciliation::Download { filename, sha256 } => {
+                if wanted.contains(filename.as_str()) {
+                    downloads.push((filename, sha256));
+                }
+                continue;
+            }
+        };
+        info!("Queued '{}' changed while the monitor was not running", change.filename);
+        queue.push(server_url, change);
+        summary.queued += 1;
+    }
+    if summary.queued > 0 {
+        queue.save()?;
+    }
+    summary.downloads = downloads.len();
+    Ok((summary, downloads))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn local(dir: &std::path::Path, name: &str, sha256: &str) -> FontInfo {
+        FontInfo { path: dir.join(name), sha256: sha256.to_string(), size: 1, modified: SystemTime::UNIX_EPOCH }
+    }
+
+    fn remote(name: &str, sha256: &str) -> api::FontInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "size": 1, "mime_type": "font/ttf", "sha256": sha256, "modified": 1
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn offline_changes_on_either_side_are_planned() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let local_fonts = [
+            local(dir, "same.ttf", "s"),
+            local(dir, "edited.ttf", "e2"),
+            local(dir, "stale.ttf", "t1"),
+            local(dir, "new.ttf", "n"),
+            local(dir, "deleted-on-server.ttf", "d"),
+        ];
+        // 上次运行时存在、现在已删除的文件，以及从未监控到的服务器字体
+        let previous = [local(dir, "gone.ttf", "g"), local(dir, "same.ttf", "s")];
+        let server = [
+            remote("same.ttf", "s"),
+            remote("edited.ttf", "e1"),
+            remote("stale.ttf", "t2"),
+            remote("gone.ttf", "g"),
+            remote("elsewhere.ttf", "x"),
+        ];
+        let synced: HashMap<String, String> = [("edited.ttf", "e1"), ("stale.ttf", "t1"), ("deleted-on-server.ttf", "d")]
+            .into_iter()
+            .map(|(name, sha256)| (name.to_string(), sha256.to_string()))
+            .collect();
+
+        let plan = plan(&local_fonts, &previous, &server, &synced);
+        assert_eq!(
+            plan,
+            [
+                Reconciliation::Upload { path: dir.join("edited.ttf"), base_sha256: Some("e1".to_string()) },
+                Reconciliation::Upload { path: dir.join("new.ttf"), base_sha256: None },
+                Reconciliation::Download { filename: "stale.ttf".to_string(), sha256: "t2".to_string() },
+                Reconciliation::Remove { filename: "gone.ttf".to_string(), sha256: "g".to_string() },
+                Reconciliation::Download { filename: "elsewhere.ttf".to_string(), sha256: "x".to_string() },
+            ]
+        );
+    }
+}
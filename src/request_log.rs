@@ -0,0 +1,160 @@
+use log::info;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use warp::hyper::body::HttpBody;
+use warp::hyper::header::HeaderValue;
+use warp::hyper::service::Service;
+use warp::hyper::{Body, Request, Response};
+
+use crate::identity::CLIENT_ID_HEADER;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// 访问日志单独使用该目标，可通过 RUST_LOG=fontsync::access=off 关闭
+const ACCESS_LOG_TARGET: &str = "fontsync::access";
+// 客户端或反向代理传入的 ID 超过该长度时重新生成
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// 连接的远端地址，由服务放入请求扩展；自定义服务下 warp::addr::remote 始终为空
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+pub fn new_request_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+// 沿用请求头中合法的 ID 以便与上游日志关联，否则重新生成
+pub fn request_id_from(header: Option<&HeaderValue>) -> String {
+    header
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id)
+}
+
+// 正在处理的 HTTP 请求的 ID；后台任务与 spawn_blocking 中为空
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+// 处理单个 HTTP 请求：分配 ID 并写入请求头与响应头，处理期间可由 current 取得，完成后记录访问日志
+pub async fn serve<S>(mut service: S, remote: Option<SocketAddr>, mut request: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let started = Instant::now();
+    let request_id = request_id_from(request.headers().get(REQUEST_ID_HEADER));
+    let header = HeaderValue::from_str(&request_id).expect("request IDs are visible ASCII");
+    request.headers_mut().insert(REQUEST_ID_HEADER, header.clone());
+    if let Some(addr) = remote {
+        request.extensions_mut().insert(RemoteAddr(addr));
+    }
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let client_id = request.headers().get(CLIENT_ID_HEADER).and_then(|value| value.to_str().ok()).map(str::to_string);
+
+    futures::future::poll_fn(|cx| service.poll_ready(cx)).await?;
+    let mut response = REQUEST_ID.scope(request_id.clone(), service.call(request)).await?;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+
+    let entry = AccessEntry {
+        request_id: &request_id,
+        method: &method,
+        path: &path,
+        status: Some(response.status().as_u16()),
+        duration: started.elapsed(),
+        // 流式响应的长度事先未知
+        bytes: response.body().size_hint().exact(),
+        client_id: client_id.as_deref(),
+        remote,
+    };
+    info!(target: ACCESS_LOG_TARGET, "{}", entry);
+    Ok(response)
+}
+
+// WebSocket 入站消息与 HTTP 请求使用同一格式记录，method 为 WS，path 为消息类型
+pub fn log_ws_message(request_id: &str, kind: &str, bytes: u64, duration: Duration, client_id: &str, remote: SocketAddr) {
+    let entry = AccessEntry {
+        request_id,
+        method: "WS",
+        path: kind,
+        status: None,
+        duration,
+        bytes: Some(bytes),
+        client_id: Some(client_id),
+        remote: Some(remote),
+    };
+    info!(target: ACCESS_LOG_TARGET, "{}", entry);
+}
+
+// 一条 key=value 形式的访问日志，未知的字段记为 -
+struct AccessEntry<'a> {
+    request_id: &'a str,
+    method: &'a str,
+    path: &'a str,
+    status: Option<u16>,
+    duration: Duration,
+    bytes: Option<u64>,
+    client_id: Option<&'a str>,
+    remote: Option<SocketAddr>,
+}
+
+impl std::fmt::Display for AccessEntry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn or_dash(value: Option<impl ToString>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())
+        }
+        write!(
+            f,
+            "request_id={} method={} path={} status={} duration_ms={:.1} bytes={} client_id={} remote={}",
+            self.request_id,
+            self.method,
+            self.path,
+            or_dash(self.status),
+            self.duration.as_secs_f64() * 1000.0,
+            or_dash(self.bytes),
+            // 客户端 ID 来自请求头，去掉空白以免破坏字段分隔
+            or_dash(self.client_id.map(|id| id.replace(char::is_whitespace, "_"))),
+            or_dash(self.remote),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incoming_ids_are_kept_only_when_well_formed() {
+        assert_eq!(request_id_from(Some(&HeaderValue::from_static("proxy-42.a_b"))), "proxy-42.a_b");
+        for header in [Some(HeaderValue::from_static("has space")), Some(HeaderValue::from_static("")), None] {
+            let id = request_id_from(header.as_ref());
+            assert_eq!(id.len(), 32);
+            assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+        assert_ne!(request_id_from(Some(&HeaderValue::from_str(&"a".repeat(65)).unwrap())), "a".repeat(65));
+
+        let entry = AccessEntry {
+            request_id: "abc",
+            method: "GET",
+            path: "/fonts",
+            status: Some(200),
+            duration: Duration::from_micros(1500),
+            bytes: None,
+            client_id: Some("laptop one"),
+            remote: None,
+        };
+        assert_eq!(
+            entry.to_string(),
+            "request_id=abc method=GET path=/fonts status=200 duration_ms=1.5 bytes=- client_id=laptop_one remote=-"
+        );
+    }
+}
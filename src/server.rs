@@ -1,460 +1,5532 @@
 use anyhow::{Context, Result};
 use bytes::Buf;
 use futures::StreamExt;
-use log::{error, info, warn};
-use serde::{Deserialize, Serialize};
+use log::{debug, error, info, warn};
+use parking_lot::RwLock;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::convert::Infallible;
 use std::fs;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use tokio::fs::{create_dir_all, File};
 use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{broadcast, Mutex, OwnedMutexGuard, Semaphore};
+use tokio::time::Instant;
 use warp::{
     hyper::StatusCode,
     multipart::{FormData, Part},
     Filter, Rejection, Reply,
 };
 
-use crate::utils::{calculate_sha256, get_font_mime_type, is_font_file};
-use crate::websocket_server::{create_font_added_event, WebSocketServer};
+use crate::access::{tokens_match, AccessTokens, Role};
+use crate::api::{
+    self, BatchUploadResponse, BatchUploadResult, BlockList, BlockRule, ClientCommand, ClientList, ErrorCode, ErrorResponse, FontActionResponse, FontHashes, FontInfo, FontList, FontQuery,
+    FontStatus, IntegrityReport, LargestFont, MaintenanceStatus, Attribution, PendingList, PendingUpload, ServerStats, RenameRequest, Subscription, TagsRequest, TagsResponse, TombstoneList, TrashEntry, TrashList,
+    VersionInfo, MAX_BATCH_UPLOADS,
+};
+use crate::blob_store::BlobStore;
+use crate::blocklist::Blocklist;
+use crate::coverage;
+use crate::delta;
+use crate::discovery;
+use crate::hashing;
+use crate::dashboard;
+use crate::event_log::{self, EventLog, EventRecord};
+use crate::font_metadata::{self, EmbeddingPermission, FontDescriptor};
+use crate::metadata_store::{self, MetadataStore};
+use crate::instancer;
+use crate::integrity::IntegrityState;
+use crate::moderation::PendingUploads;
+use crate::preview;
+use crate::request_log::{self, RemoteAddr};
+use crate::subset::{self, SubsetFormat};
+use crate::signing::{Manifest, ManifestEntry, PublicKeyInfo, ServerSigner};
+use crate::identity::{ClientIdentity, CLIENT_ID_HEADER, HOSTNAME_HEADER, OS_HEADER};
+use crate::utils::{
+    calculate_sha256, decode_path_segment, format_file_size, get_file_timestamp, get_font_mime_type, is_font_file,
+    sanitize_filename, set_file_timestamp, FontSort,
+};
+use crate::webfont::{self, WebFace, WebFormat};
+use crate::websocket_server::{
+    create_font_added_event, create_font_modified_event, create_font_removed_event, create_font_renamed_event,
+    create_fonts_added_event, FontDetails, WebSocketMessage,
+    WebSocketServer, WsFeatures, WS_FEATURES_HEADER,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
-struct FontInfo {
-    name: String,
-    size: u64,
-    mime_type: String,
-    sha256: String,
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 100 * 1024 * 1024;
+pub const DEFAULT_MAX_CONCURRENT_UPLOADS: usize = 4;
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+pub const DEFAULT_SCRUB_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// 无引用数据的回收间隔
+const BLOB_GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// 维护模式下建议客户端重试的间隔
+const MAINTENANCE_RETRY_AFTER: Duration = Duration::from_secs(60);
+pub const DEFAULT_MAINTENANCE_MESSAGE: &str = "Server is in read-only maintenance mode";
+// 统计信息中列出的最大字体与最近事件数
+const STATS_LARGEST: usize = 10;
+const STATS_RECENT_EVENTS: usize = 20;
+
+// 服务器端存储策略
+#[derive(Debug, Clone)]
+pub struct ServerPolicy {
+    // 拒绝存储 fsType 为 Restricted License 的字体
+    pub refuse_restricted: bool,
+    // 设置时在同一主机的该端口上另外提供 gRPC 接口
+    pub grpc_port: Option<u16>,
+    pub blocklist: Arc<Blocklist>,
+    // 管理接口的 Bearer 令牌，未设置时管理接口关闭
+    pub admin_token: Option<String>,
+    // 上传请求体的最大字节数
+    pub max_upload_size: u64,
+    // 同时处理的上传数，超出的请求排队等待
+    pub upload_slots: Arc<Semaphore>,
+    // 从收到请求到接收完请求体的时限，包括排队时间
+    pub upload_timeout: Duration,
+    // 维护模式的提示信息，设置时所有修改请求返回 503，可通过管理接口切换
+    pub maintenance: Arc<RwLock<Option<String>>>,
+    // 按角色授权的访问令牌，为空时字体接口允许匿名访问
+    pub tokens: Arc<AccessTokens>,
+    // 设置时上传需管理员批准后才生效
+    pub moderation: Option<Arc<PendingUploads>>,
+    // 每个客户端（按 client_id）上传的字体总大小上限
+    pub client_quota: Option<u64>,
+    // 定期完整性检查的间隔，None 时只能通过管理接口手动运行
+    pub scrub_interval: Option<Duration>,
+    // 检查发现损坏时从完好副本或备份目录恢复
+    pub scrub_repair: bool,
+    pub backup_dir: Option<PathBuf>,
+    pub integrity: Arc<IntegrityState>,
+    // 应答局域网发现的 UDP 广播，便于客户端找到服务器
+    pub discoverable: bool,
+    // 同名文件的上传依次处理
+    pub upload_locks: Arc<FileLocks>,
+    // 按优先顺序排列的全部字体目录（包括上传目录），为空时只有上传目录；
+    // 同名字体取排在前面的目录中的文件，上传目录以外的目录只读
+    pub font_dirs: Arc<Vec<PathBuf>>,
+}
+
+impl Default for ServerPolicy {
+    fn default() -> Self {
+        Self {
+            refuse_restricted: false,
+            blocklist: Arc::default(),
+            admin_token: None,
+            grpc_port: None,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            upload_slots: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_UPLOADS)),
+            upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+            maintenance: Arc::default(),
+            tokens: Arc::default(),
+            moderation: None,
+            client_quota: None,
+            scrub_interval: None,
+            scrub_repair: false,
+            backup_dir: None,
+            integrity: Arc::default(),
+            discoverable: false,
+            upload_locks: Arc::default(),
+            font_dirs: Arc::default(),
+        }
+    }
+}
+
+// 按文件名串行化上传，避免同名的两个上传交错检查和替换
+#[derive(Debug, Default)]
+pub struct FileLocks {
+    locks: parking_lot::Mutex<HashMap<String, Weak<Mutex<()>>>>,
+}
+
+impl FileLocks {
+    pub async fn lock(&self, filename: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock();
+            // 顺便清理已无人持有的锁
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            match locks.get(filename).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(Mutex::new(()));
+                    locks.insert(filename.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+}
+
+// 认证失败，由路由末尾的 recover 转为 JSON 错误
+#[derive(Debug)]
+pub(crate) struct Denied {
+    pub(crate) status: StatusCode,
+    pub(crate) error: &'static str,
+    pub(crate) message: String,
+}
+
+impl warp::reject::Reject for Denied {}
+
+impl ServerPolicy {
+    // 检查请求令牌的角色；未配置令牌文件时匿名请求可访问字体接口，管理接口始终需要管理员令牌
+    pub(crate) fn authorize(&self, authorization: Option<&str>, required: Role, admin_api: bool) -> Result<(), Denied> {
+        if admin_api && self.admin_token.is_none() && !self.tokens.has_admin() {
+            return Err(Denied {
+                status: StatusCode::FORBIDDEN,
+                error: "Admin API disabled",
+                message: "Start the server with --admin-token or an admin entry in --tokens to enable admin endpoints"
+                    .to_string(),
+            });
+        }
+        let anonymous_allowed = !admin_api && self.tokens.is_open();
+        let granted = match bearer_token(authorization) {
+            Some(token) if self.admin_token.as_deref().is_some_and(|expected| tokens_match(token, expected)) => {
+                Some(Role::Admin)
+            }
+            Some(token) => match self.tokens.lookup(token) {
+                Some(entry) => {
+                    debug!("Request authorized as {} ({})", entry.name.as_deref().unwrap_or("unnamed token"), entry.role);
+                    Some(entry.role)
+                }
+                // 开放模式下忽略为其他服务器保存的令牌
+                None if anonymous_allowed => None,
+                None => {
+                    return Err(Denied {
+                        status: StatusCode::UNAUTHORIZED,
+                        error: "Unauthorized",
+                        message: "Invalid token".to_string(),
+                    })
+                }
+            },
+            None => None,
+        };
+        match granted {
+            Some(role) if role >= required => Ok(()),
+            Some(role) => Err(Denied {
+                status: StatusCode::FORBIDDEN,
+                error: "Forbidden",
+                message: format!("Token role '{}' is not allowed to do this; requires '{}'", role, required),
+            }),
+            None if anonymous_allowed => Ok(()),
+            None => Err(Denied {
+                status: StatusCode::UNAUTHORIZED,
+                error: "Unauthorized",
+                message: format!("A token with role '{}' is required", required),
+            }),
+        }
+    }
+
+    // 字体所在的文件；各目录中都没有时为上传目录中的路径
+    pub(crate) fn font_path(&self, font_dir: &Path, filename: &str) -> PathBuf {
+        self.font_dirs
+            .iter()
+            .map(|dir| dir.join(filename))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| font_dir.join(filename))
+    }
+
+    // 位于只读目录中的字体不能替换、删除或改名
+    fn is_read_only(&self, font_dir: &Path, filename: &str) -> bool {
+        self.font_path(font_dir, filename).parent() != Some(font_dir)
+    }
+
+    // 全部目录中的字体文件，同名时只保留排在前面的目录中的文件
+    fn font_files(&self, font_dir: &Path) -> Result<BTreeMap<String, PathBuf>> {
+        let dirs: Vec<&Path> = if self.font_dirs.is_empty() {
+            vec![font_dir]
+        } else {
+            self.font_dirs.iter().map(PathBuf::as_path).collect()
+        };
+        let mut files = BTreeMap::new();
+        for dir in dirs.into_iter().filter(|dir| dir.exists()) {
+            let entries = fs::read_dir(dir).with_context(|| format!("Failed to read font directory {:?}", dir))?;
+            for entry in entries {
+                let path = entry.context("Failed to read directory entry")?.path();
+                let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+                    continue;
+                };
+                if path.is_file() && is_font_file(&path) {
+                    files.entry(name).or_insert(path);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    pub(crate) fn attribution(&self, identity: Option<ClientIdentity>, authorization: Option<&str>) -> Attribution {
+        Attribution {
+            client_id: identity.as_ref().map(|i| i.client_id.clone()),
+            hostname: identity.map(|i| i.hostname),
+            token: bearer_token(authorization).and_then(|t| self.tokens.lookup(t)).and_then(|e| e.name.clone()),
+        }
+    }
+
+    // 上传后超出客户端或令牌配额时返回说明；替换同名字体时不计入旧文件
+    fn quota_exceeded(
+        &self,
+        font_dir: &Path,
+        metadata: &MetadataStore,
+        uploaded_by: &Attribution,
+        filename: &str,
+        size: u64,
+    ) -> Option<String> {
+        let usage = |matches: &dyn Fn(&Attribution) -> bool| -> u64 {
+            metadata
+                .uploaded_by(matches)
+                .iter()
+                .filter(|name| name.as_str() != filename)
+                .filter_map(|name| fs::metadata(font_dir.join(name)).ok())
+                .map(|m| m.len())
+                .sum()
+        };
+        let limits = [
+            uploaded_by.client_id.as_ref().zip(self.client_quota).map(|(id, quota)| {
+                (format!("client {}", id), quota, usage(&|a| a.client_id.as_ref() == Some(id)))
+            }),
+            uploaded_by.token.as_ref().and_then(|name| self.tokens.quota(name).map(|quota| (name, quota))).map(
+                |(name, quota)| (format!("token '{}'", name), quota, usage(&|a| a.token.as_ref() == Some(name))),
+            ),
+        ];
+        limits.into_iter().flatten().find(|(_, quota, used)| used + size > *quota).map(|(owner, quota, used)| {
+            format!(
+                "Uploading {} would exceed the {} quota of {} ({} used)",
+                format_file_size(size),
+                owner,
+                format_file_size(quota),
+                format_file_size(used)
+            )
+        })
+    }
+
+    // 维护模式下修改请求得到的错误，附带建议的重试间隔
+    pub(crate) fn maintenance_error(&self) -> Option<UploadError> {
+        let message = self.maintenance.read().clone()?;
+        Some((
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorResponse::new(ErrorCode::Maintenance, "Maintenance", message)
+                .with_details(serde_json::json!({ "retry_after": MAINTENANCE_RETRY_AFTER.as_secs() })),
+        ))
+    }
+
+    fn maintenance_status(&self) -> MaintenanceStatus {
+        let message = self.maintenance.read().clone();
+        MaintenanceStatus { enabled: message.is_some(), message }
+    }
+}
+
+// 解析 --host：IP 字面量（IPv6 可加方括号）直接使用，主机名解析出的全部地址都监听。
+// IPv6 地址排在前面，通配地址 :: 在多数系统上同时接受 IPv4 连接
+pub async fn resolve_bind_addrs(hosts: &[String], port: u16) -> Result<Vec<SocketAddr>> {
+    let mut addrs = Vec::new();
+    for host in hosts {
+        let literal = host.trim().trim_start_matches('[').trim_end_matches(']');
+        let resolved: Vec<SocketAddr> = match literal.parse::<std::net::IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((literal, port))
+                .await
+                .with_context(|| format!("Failed to resolve host '{}'", host))?
+                .collect(),
+        };
+        for addr in resolved {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    if addrs.is_empty() {
+        anyhow::bail!("No addresses to listen on");
+    }
+    addrs.sort_by_key(|addr| addr.is_ipv4());
+    Ok(addrs)
+}
+
+// --listen 的取值，目前只支持 unix:/path/to/socket
+pub fn parse_listen_address(value: &str) -> Result<PathBuf> {
+    match value.strip_prefix("unix:") {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => anyhow::bail!("Expected unix:/path/to/socket, got '{}'", value),
+    }
+}
+
+// 由 --font-dir 与 --upload-dir 得到上传目录与 ServerPolicy::font_dirs；
+// 上传目录默认为第一个目录，只有一个目录时 font_dirs 为空
+pub fn resolve_font_dirs(font_dirs: &[String], upload_dir: Option<&str>) -> Result<(String, Vec<PathBuf>)> {
+    let dirs: Vec<PathBuf> = font_dirs.iter().map(PathBuf::from).collect();
+    let Some(first) = font_dirs.first() else {
+        anyhow::bail!("At least one --font-dir is required");
+    };
+    if let Some(dir) = dirs.iter().enumerate().find_map(|(index, dir)| dirs[..index].contains(dir).then_some(dir)) {
+        anyhow::bail!("--font-dir {:?} is given more than once", dir);
+    }
+    let upload_dir = upload_dir.unwrap_or(first);
+    if !dirs.contains(&PathBuf::from(upload_dir)) {
+        anyhow::bail!("--upload-dir {} must be one of the --font-dir values", upload_dir);
+    }
+    // 只读目录不会自动创建
+    if let Some(missing) = dirs.iter().find(|dir| dir.as_path() != Path::new(upload_dir) && !dir.is_dir()) {
+        anyhow::bail!("Font directory {:?} does not exist", missing);
+    }
+    if dirs.len() == 1 {
+        return Ok((upload_dir.to_string(), Vec::new()));
+    }
+    Ok((upload_dir.to_string(), dirs))
+}
+
+// 删除上次运行遗留的套接字文件；仍有服务器在监听时报错
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            anyhow::bail!("Another server is already listening on {:?}", path);
+        }
+        fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {:?}", path))?;
+    }
+    tokio::net::UnixListener::bind(path).with_context(|| format!("Failed to bind Unix socket {:?}", path))
+}
+
+type Routes = warp::filters::BoxedFilter<(warp::reply::Response,)>;
+
+// 在 TCP 地址上提供路由；每个请求经 request_log 分配 ID 并记录访问日志，对端地址经请求扩展传给路由
+fn serve_tcp(
+    routes: Routes,
+    addr: SocketAddr,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(SocketAddr, futures::future::BoxFuture<'static, ()>)> {
+    use warp::hyper::server::conn::{AddrIncoming, AddrStream};
+    use warp::hyper::service::{make_service_fn, service_fn};
+
+    let mut incoming = AddrIncoming::bind(&addr)?;
+    // 与 warp::serve 相同，关闭 Nagle 算法
+    incoming.set_nodelay(true);
+    let bound_addr = incoming.local_addr();
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote = conn.remote_addr();
+        let service = service.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| request_log::serve(service.clone(), Some(remote), request))) }
+    });
+    let server = warp::hyper::Server::builder(incoming).serve(make_service).with_graceful_shutdown(shutdown);
+    Ok((
+        bound_addr,
+        Box::pin(async move {
+            if let Err(e) = server.await {
+                error!("HTTP server error on {}: {}", bound_addr, e);
+            }
+        }),
+    ))
+}
+
+#[cfg(unix)]
+fn serve_unix(
+    routes: Routes,
+    listener: tokio::net::UnixListener,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> futures::future::BoxFuture<'static, ()> {
+    use warp::hyper::server::accept;
+    use warp::hyper::service::{make_service_fn, service_fn};
+
+    let incoming = futures::stream::poll_fn(move |cx| {
+        listener.poll_accept(cx).map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+    });
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |_: &tokio::net::UnixStream| {
+        let service = service.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| request_log::serve(service.clone(), None, request))) }
+    });
+    let server = warp::hyper::Server::builder(accept::from_stream(incoming)).serve(make_service).with_graceful_shutdown(shutdown);
+    Box::pin(async move {
+        if let Err(e) = server.await {
+            error!("HTTP server error on Unix socket: {}", e);
+        }
+    })
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct FontList {
-    fonts: Vec<FontInfo>,
+pub async fn start_server(
+    hosts: Vec<String>,
+    unix_sockets: Vec<PathBuf>,
+    port: u16,
+    font_dir: String,
+    ws_enabled: bool,
+    legacy_ws_port: bool,
+    policy: ServerPolicy,
+) -> Result<()> {
+    let font_dir_path = PathBuf::from(&font_dir);
+    
+    // 字体目录不存在时创建
+    if !font_dir_path.exists() {
+        create_dir_all(&font_dir_path)
+            .await
+            .context("Failed to create font directory")?;
+        info!("Created font directory: {}", font_dir);
+    }
+
+    let event_log = Arc::new(EventLog::open(&font_dir_path)?);
+    let signer = Arc::new(ServerSigner::load_or_create(&font_dir_path)?);
+    let metadata = Arc::new(MetadataStore::open(&font_dir_path)?);
+    let blobs = Arc::new(BlobStore::open(&font_dir_path)?);
+    info!("Manifest signing key: {}", signer.public_key()?);
+    let ws_server = if ws_enabled {
+        Some(Arc::new(WebSocketServer::new()))
+    } else {
+        None
+    };
+    hashing::spawn_startup_hashing(&font_dir_path, Arc::clone(&metadata), Arc::clone(&blobs), ws_server.clone());
+    spawn_blob_gc(Arc::clone(&blobs));
+    if let Some(interval) = policy.scrub_interval {
+        spawn_integrity_scrub(font_dir_path.clone(), Arc::clone(&blobs), policy.clone(), interval);
+    }
+    let font_dir_arc = Arc::new(font_dir_path);
+    let discoverable = policy.discoverable;
+    // 旧版独立 WebSocket 端口同样检查令牌
+    let legacy_policy = policy.clone();
+
+    #[cfg(feature = "grpc")]
+    let grpc = policy.grpc_port.map(|grpc_port| {
+        let service = crate::grpc::FontSyncService::new(
+            Arc::clone(&font_dir_arc),
+            ws_server.clone(),
+            Arc::clone(&event_log),
+            Arc::clone(&metadata),
+            Arc::clone(&blobs),
+            policy.clone(),
+        );
+        (grpc_port, service)
+    });
+
+    let routes = build_routes(font_dir_arc, ws_server.clone(), event_log, signer, metadata, blobs, policy)
+        .map(Reply::into_response)
+        .boxed();
+
+    let addrs = if hosts.is_empty() {
+        Vec::new()
+    } else {
+        resolve_bind_addrs(&hosts, port).await?
+    };
+
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let shutdown_trigger = shutdown.clone();
+    tokio::spawn(async move {
+        let _ = tokio::signal::ctrl_c().await;
+        shutdown_trigger.cancel();
+    });
+
+    let mut servers: Vec<futures::future::BoxFuture<'static, ()>> = Vec::new();
+    let mut bound_addrs = Vec::new();
+    let mut dual_stack = false;
+    for addr in addrs {
+        match serve_tcp(routes.clone(), addr, shutdown.clone().cancelled_owned()) {
+            Ok((bound_addr, server)) => {
+                info!("HTTP server listening on http://{}", bound_addr);
+                dual_stack |= addr.is_ipv6() && addr.ip().is_unspecified();
+                bound_addrs.push(bound_addr);
+                servers.push(server);
+            }
+            // 双栈的 :: 已占用同一端口的 IPv4 地址
+            Err(e) if dual_stack && addr.is_ipv4() => {
+                info!("Not binding {} separately, the dual-stack IPv6 listener already accepts it ({})", addr, e);
+            }
+            Err(e) => return Err(anyhow::anyhow!("Failed to bind HTTP server on {}: {}", addr, e)),
+        }
+    }
+
+    // gRPC 接口监听与 HTTP 相同的地址
+    #[cfg(feature = "grpc")]
+    if let Some((grpc_port, service)) = grpc {
+        for bound_addr in &bound_addrs {
+            let addr = SocketAddr::new(bound_addr.ip(), grpc_port);
+            servers.push(service.clone().serve(addr, shutdown.clone().cancelled_owned())?);
+            info!("gRPC server listening on {}", addr);
+        }
+    }
+
+    for path in &unix_sockets {
+        #[cfg(unix)]
+        {
+            let listener = bind_unix_socket(path)?;
+            info!("HTTP server listening on unix:{}", path.display());
+            servers.push(serve_unix(routes.clone(), listener, shutdown.clone().cancelled_owned()));
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!("Cannot listen on {:?}: Unix domain sockets are not supported on this platform", path);
+    }
+
+    if let Some(ws_server) = ws_server {
+        for bound_addr in &bound_addrs {
+            info!("WebSocket endpoint available at ws://{}/ws", bound_addr);
+
+            if legacy_ws_port {
+                let ws_addr = SocketAddr::new(bound_addr.ip(), port + 1);
+                let ws_server = Arc::clone(&ws_server);
+                let policy = legacy_policy.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = ws_server.listen(ws_addr, policy).await {
+                        error!("WebSocket server error on {}: {}", ws_addr, e);
+                    }
+                });
+            }
+        }
+    }
+
+    // 只监听回环地址时其他机器无法连接，不必应答
+    match bound_addrs.iter().find(|addr| !addr.ip().is_loopback()) {
+        Some(addr) if discoverable => discovery::spawn_responder(addr.port(), shutdown.clone()),
+        None if discoverable => warn!("LAN discovery disabled: the server only listens on loopback addresses"),
+        _ => {}
+    }
+
+    futures::future::join_all(servers).await;
+    for path in &unix_sockets {
+        let _ = fs::remove_file(path);
+    }
+
+    Ok(())
 }
 
-pub async fn start_server(host: String, port: u16, font_dir: String, ws_enabled: bool) -> Result<()> {
-    let font_dir_path = PathBuf::from(&font_dir);
-    
-    // 字体目录不存在时创建
-    if !font_dir_path.exists() {
-        create_dir_all(&font_dir_path)
+fn build_routes(
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    signer: Arc<ServerSigner>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // 路由
+    let font_dir_filter = warp::any().map(move || Arc::clone(&font_dir));
+    let ws_server_filter = warp::any().map(move || ws_server.clone());
+    let event_log_filter = warp::any().map(move || Arc::clone(&event_log));
+    let signer_filter = warp::any().map(move || Arc::clone(&signer));
+    let metadata_filter = warp::any().map(move || Arc::clone(&metadata));
+    let blobs_filter = warp::any().map(move || Arc::clone(&blobs));
+    let max_upload_size = policy.max_upload_size;
+    let reader = require_role(Role::Reader, false, policy.clone());
+    let uploader = require_role(Role::Uploader, false, policy.clone());
+    let admin = require_role(Role::Admin, false, policy.clone());
+    let admin_api = require_role(Role::Admin, true, policy.clone());
+    let policy_filter = warp::any().map(move || policy.clone());
+    let identity_filter = warp::header::optional::<String>(CLIENT_ID_HEADER)
+        .and(warp::header::optional::<String>(HOSTNAME_HEADER))
+        .and(warp::header::optional::<String>(OS_HEADER))
+        .map(ClientIdentity::from_headers);
+    let attribution_filter = identity_filter
+        .and(warp::header::optional::<String>("authorization"))
+        .and(policy_filter.clone())
+        .map(|identity: Option<ClientIdentity>, authorization: Option<String>, policy: ServerPolicy| {
+            policy.attribution(identity, authorization.as_deref())
+        });
+
+    let list_fonts = warp::path!("fonts")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<FontQuery>())
+        .and(font_dir_filter.clone())
+        .and(metadata_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(list_fonts_handler);
+
+    let font_hashes = warp::path!("fonts" / "hashes")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<FontQuery>())
+        .and(font_dir_filter.clone())
+        .and(metadata_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(font_hashes_handler);
+
+    let download_font = warp::path!("fonts" / String)
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(download_font_handler);
+
+    let download_blob = warp::path!("blobs" / String)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(blobs_filter.clone())
+        .and_then(download_blob_handler);
+
+    let delete_font = warp::path!("fonts" / String)
+        .map(font_name)
+        .and(warp::delete())
+        .and(admin.clone())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(delete_font_handler);
+
+    let list_trash = warp::path!("trash")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and_then(list_trash_handler);
+
+    let restore_font = warp::path!("trash" / String / "restore")
+        .map(font_name)
+        .and(warp::post())
+        .and(admin.clone())
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(restore_font_handler);
+
+    let rename_font = warp::path!("fonts" / String / "rename")
+        .map(font_name)
+        .and(warp::post())
+        .and(admin.clone())
+        .and(warp::body::json::<RenameRequest>())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(rename_font_handler);
+
+    let list_tombstones = warp::path!("tombstones")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(metadata_filter.clone())
+        .map(|metadata: Arc<MetadataStore>| {
+            warp::reply::json(&TombstoneList { tombstones: metadata.tombstones() })
+        });
+
+    let list_clients = warp::path!("clients")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(ws_server_filter.clone())
+        .map(|ws_server: Option<Arc<WebSocketServer>>| {
+            let clients = ws_server.map(|s| s.connected_clients()).unwrap_or_default();
+            warp::reply::json(&ClientList { clients })
+        });
+
+    let upload_font = warp::path!("fonts")
+        .and(warp::post())
+        .and(uploader.clone())
+        .and(warp::multipart::form().max_length(max_upload_size))
+        .and(warp::query::<UploadQuery>())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(attribution_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(upload_font_handler)
+        .recover(move |rejection| recover_payload_too_large(rejection, max_upload_size));
+
+    // 整批的请求体上限按单个文件的上限计算
+    let max_batch_size = max_upload_size.saturating_mul(MAX_BATCH_UPLOADS as u64);
+    let upload_batch = warp::path!("fonts" / "batch")
+        .and(warp::post())
+        .and(uploader.clone())
+        .and(warp::multipart::form().max_length(max_batch_size))
+        .and(warp::query::<UploadQuery>())
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(attribution_filter)
+        .and(policy_filter.clone())
+        .and_then(upload_batch_handler)
+        .recover(move |rejection| recover_payload_too_large(rejection, max_batch_size));
+
+    // 上传接口同样单独装箱，避免路由类型嵌套过深
+    let upload_routes = upload_font.or(upload_batch).boxed();
+    let change_routes = delete_font.or(rename_font).or(restore_font).boxed();
+
+    let list_blocklist = warp::path!("admin" / "blocklist")
+        .and(warp::get())
+        .and(admin_api.clone())
+        .and(policy_filter.clone())
+        .map(|policy: ServerPolicy| warp::reply::json(&BlockList { rules: policy.blocklist.list() }));
+
+    let add_block_rule = warp::path!("admin" / "blocklist")
+        .and(warp::post())
+        .and(admin_api.clone())
+        .and(warp::body::json::<BlockRule>())
+        .and(policy_filter.clone())
+        .and_then(add_block_rule_handler);
+
+    let remove_block_rule = warp::path!("admin" / "blocklist" / String)
+        .and(warp::delete())
+        .and(admin_api.clone())
+        .and(policy_filter.clone())
+        .and_then(remove_block_rule_handler);
+
+    let get_maintenance = warp::path!("admin" / "maintenance")
+        .and(warp::get())
+        .and(admin_api.clone())
+        .and(policy_filter.clone())
+        .map(|policy: ServerPolicy| warp::reply::json(&policy.maintenance_status()));
+
+    let set_maintenance = warp::path!("admin" / "maintenance")
+        .and(warp::put())
+        .and(admin_api.clone())
+        .and(warp::body::json::<MaintenanceStatus>())
+        .and(policy_filter.clone())
+        .map(|status: MaintenanceStatus, policy: ServerPolicy| {
+            let message = status.enabled.then(|| {
+                status
+                    .message
+                    .filter(|m| !m.trim().is_empty())
+                    .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string())
+            });
+            match &message {
+                Some(message) => info!("Entering maintenance mode: {}", message),
+                None => info!("Leaving maintenance mode"),
+            }
+            *policy.maintenance.write() = message;
+            warp::reply::json(&policy.maintenance_status())
+        });
+
+    let get_integrity = warp::path!("admin" / "integrity")
+        .and(warp::get())
+        .and(admin_api.clone())
+        .and(policy_filter.clone())
+        .map(|policy: ServerPolicy| warp::reply::json(&policy.integrity.status()));
+
+    let send_command = warp::path!("admin" / "clients" / String / "commands")
+        .and(warp::post())
+        .and(admin_api.clone())
+        .and(warp::body::json::<ClientCommand>())
+        .and(ws_server_filter.clone())
+        .and_then(send_command_handler);
+
+    let get_command = warp::path!("admin" / "commands" / String)
+        .and(warp::get())
+        .and(admin_api.clone())
+        .and(ws_server_filter.clone())
+        .map(|id: String, ws_server: Option<Arc<WebSocketServer>>| {
+            match ws_server.and_then(|s| s.command_status(&decode_segment(&id))) {
+                Some(status) => Box::new(warp::reply::json(&status)) as Box<dyn Reply>,
+                None => error_reply(StatusCode::NOT_FOUND, "Command not found", format!("Unknown or expired command '{}'", id)),
+            }
+        });
+
+    let run_integrity = warp::path!("admin" / "integrity")
+        .and(warp::post())
+        .and(admin_api.clone())
+        .and(font_dir_filter.clone())
+        .and(blobs_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(run_integrity_handler);
+
+    let metrics = warp::path!("metrics")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(policy_filter.clone())
+        .and(ws_server_filter.clone())
+        .map(|policy: ServerPolicy, ws_server: Option<Arc<WebSocketServer>>| {
+            let mut body = policy.integrity.metrics();
+            if let Some(ws_server) = ws_server {
+                body.push_str(&ws_server.metrics());
+            }
+            warp::reply::with_header(body, "content-type", "text/plain; version=0.0.4")
+        });
+
+    let list_pending = warp::path!("admin" / "pending")
+        .and(warp::get())
+        .and(admin_api.clone())
+        .and(policy_filter.clone())
+        .map(|policy: ServerPolicy| {
+            let uploads = policy.moderation.map(|pending| pending.list()).unwrap_or_default();
+            warp::reply::json(&PendingList { uploads })
+        });
+
+    let approve_pending = warp::path!("admin" / "pending" / String / "approve")
+        .map(font_name)
+        .and(warp::post())
+        .and(admin_api.clone())
+        .and(font_dir_filter.clone())
+        .and(ws_server_filter.clone())
+        .and(event_log_filter.clone())
+        .and(metadata_filter.clone())
+        .and(blobs_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(approve_pending_handler);
+
+    let reject_pending = warp::path!("admin" / "pending" / String)
+        .map(font_name)
+        .and(warp::delete())
+        .and(admin_api)
+        .and(policy_filter.clone())
+        .and_then(reject_pending_handler);
+
+    // 维护模式下拦截修改请求；其余请求交给后续路由
+    let maintenance_guard = warp::method()
+        .and(warp::path::full())
+        .and(policy_filter.clone())
+        .and_then(maintenance_guard);
+
+    let font_signature = warp::path!("fonts" / String / "signature")
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(font_signature_handler);
+
+    let font_delta = warp::path!("fonts" / String / "delta")
+        .map(font_name)
+        .and(warp::post())
+        .and(reader.clone())
+        .and(warp::body::content_length_limit(max_upload_size))
+        .and(warp::body::bytes())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(font_delta_handler);
+
+    let get_sha256 = warp::path!("fonts" / String / "sha256")
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(get_sha256_handler);
+
+    let font_preview = warp::path!("fonts" / String / "preview.png")
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<PreviewQuery>())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(preview_handler);
+
+    let font_instance = warp::path!("fonts" / String / "instance")
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(instance_handler);
+
+    let font_subset = warp::path!("fonts" / String / "subset")
+        .map(font_name)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<SubsetQuery>())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(subset_handler);
+
+    let webfont_css = warp::path!("webfonts" / String)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<WebFontQuery>())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(webfont_handler);
+
+    let webfont_file = warp::path!("webfonts" / "files" / String)
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(webfont_file_handler);
+
+    let set_tags = warp::path!("fonts" / String / "tags")
+        .map(font_name)
+        .and(warp::put())
+        .and(uploader.clone())
+        .and(warp::body::json::<TagsRequest>())
+        .and(font_dir_filter.clone())
+        .and(metadata_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(set_tags_handler);
+
+    let stats = warp::path!("stats")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(metadata_filter.clone())
+        .and(event_log_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(stats_handler);
+
+    let list_events = warp::path!("events")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::query::<EventsQuery>())
+        .and(event_log_filter.clone())
+        .and_then(list_events_handler);
+
+    let event_stream = warp::path!("events" / "stream")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(warp::header::optional::<u64>("last-event-id"))
+        .and(event_log_filter.clone())
+        .map(|last_event_id: Option<u64>, event_log: Arc<EventLog>| {
+            warp::sse::reply(warp::sse::keep_alive().stream(sse_events(event_log, last_event_id)))
+        });
+
+    let manifest = warp::path!("manifest")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(font_dir_filter.clone())
+        .and(metadata_filter)
+        .and(signer_filter.clone())
+        .and(policy_filter.clone())
+        .and_then(manifest_handler);
+
+    let signing_key = warp::path!("signing-key")
+        .and(warp::get())
+        .and(reader.clone())
+        .and(signer_filter)
+        .and_then(signing_key_handler);
+
+    let openapi = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&api::openapi()));
+
+    // 不需要令牌，协议不兼容的客户端也可查询
+    let healthz = warp::path!("healthz")
+        .and(warp::get())
+        .map(|| warp::reply::json(&api::HealthStatus::ok()));
+
+    let version = warp::path!("version")
+        .and(warp::get())
+        .and(policy_filter)
+        .map(|policy: ServerPolicy| warp::reply::json(&VersionInfo { grpc_port: policy.grpc_port, ..VersionInfo::current() }));
+
+    let version_guard = warp::header::optional::<String>(api::PROTOCOL_HEADER)
+        .and(warp::header::optional::<String>(api::VERSION_HEADER))
+        .and_then(version_guard);
+
+    let websocket = warp::path!("ws")
+        .and(warp::ws())
+        .and(reader.clone())
+        .and(warp::ext::optional::<RemoteAddr>().map(|remote: Option<RemoteAddr>| remote.map(|RemoteAddr(addr)| addr)))
+        .and(identity_filter)
+        .and(warp::header::optional::<String>(WS_FEATURES_HEADER))
+        .and(warp::header::optional::<String>(api::SUBSCRIPTION_HEADER))
+        .and(ws_server_filter.clone())
+        .and_then(websocket_handler);
+
+    // 管理接口单独组合并装箱，避免路由类型嵌套过深
+    let admin_routes = list_blocklist
+        .or(add_block_rule)
+        .or(remove_block_rule)
+        .or(get_maintenance)
+        .or(set_maintenance)
+        .or(get_integrity)
+        .or(run_integrity)
+        .or(send_command)
+        .or(get_command)
+        .or(list_pending)
+        .or(approve_pending)
+        .or(reject_pending)
+        .boxed();
+
+    // /fonts/hashes 需在下载路由之前匹配
+    healthz
+        .or(version)
+        .or(version_guard)
+        .or(maintenance_guard)
+        .or(list_fonts)
+        .or(font_hashes)
+        .or(download_font)
+        .or(download_blob)
+        .or(change_routes)
+        .or(upload_routes)
+        .or(get_sha256)
+        .or(font_signature)
+        .or(font_delta)
+        .or(font_preview)
+        .or(font_instance)
+        .or(font_subset)
+        .or(set_tags)
+        .or(webfont_file)
+        .or(webfont_css)
+        .or(stats)
+        .or(metrics)
+        .or(list_events)
+        .or(event_stream)
+        .or(manifest)
+        .or(signing_key)
+        .or(list_trash)
+        .or(list_tombstones)
+        .or(list_clients)
+        .or(admin_routes)
+        .or(openapi)
+        .or(dashboard::routes())
+        .or(websocket)
+        .recover(|rejection: Rejection| async move { Ok::<_, Infallible>(rejection_reply(rejection)) })
+        .with(warp::reply::with::header(api::PROTOCOL_HEADER, api::PROTOCOL_VERSION.to_string()))
+        .with(warp::cors().allow_any_origin())
+}
+
+pub async fn start_server_with_websocket(
+    hosts: Vec<String>,
+    unix_sockets: Vec<PathBuf>,
+    port: u16,
+    font_dir: String,
+    ws_enabled: bool,
+    legacy_ws_port: bool,
+    policy: ServerPolicy,
+) -> Result<()> {
+    start_server(hosts, unix_sockets, port, font_dir, ws_enabled, legacy_ws_port, policy).await
+}
+
+async fn websocket_handler(
+    ws: warp::ws::Ws,
+    remote: Option<SocketAddr>,
+    identity: Option<ClientIdentity>,
+    features: Option<String>,
+    subscription: Option<String>,
+    ws_server: Option<Arc<WebSocketServer>>,
+) -> Result<Box<dyn Reply>, Rejection> {
+    // 未启用 WebSocket 时按普通 404 处理
+    let Some(server) = ws_server else {
+        return Err(warp::reject::not_found());
+    };
+
+    // Unix 套接字上的连接没有对端地址，从丢弃前缀 100::/64 中为每个连接分配唯一的地址作为会话键
+    let addr = remote.unwrap_or_else(|| {
+        static NEXT_LOCAL: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        let id = NEXT_LOCAL.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        SocketAddr::from((std::net::Ipv6Addr::from((0x0100u128 << 112) | id as u128), 0))
+    });
+    Ok(Box::new(ws.on_upgrade(move |socket| async move {
+        let features = WsFeatures::parse(features.as_deref());
+        server.handle_upgrade(socket, addr, identity, features, Subscription::parse(subscription.as_deref())).await;
+    })))
+}
+
+// 查询参数定义在 api 模块，过滤逻辑只在服务器端使用
+impl FontQuery {
+    fn matches(
+        &self,
+        font: &FontInfo,
+        font_dir: &Path,
+        policy: &ServerPolicy,
+        tags: &BTreeSet<String>,
+        formats: &[String],
+        covers: &[u32],
+    ) -> bool {
+        if !tags.is_empty() && font.tags.is_disjoint(tags) {
+            return false;
+        }
+        if let Some(q) = self.q.as_deref().map(str::to_lowercase).filter(|q| !q.is_empty()) {
+            let in_family = font
+                .family
+                .as_deref()
+                .is_some_and(|family| family.to_lowercase().contains(&q));
+            if !font.name.to_lowercase().contains(&q) && !in_family {
+                return false;
+            }
+        }
+        if !formats.is_empty() {
+            let extension = Path::new(&font.name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(str::to_lowercase)
+                .unwrap_or_default();
+            if !formats.contains(&extension) {
+                return false;
+            }
+        }
+        if self.min_size.is_some_and(|min_size| font.size < min_size) {
+            return false;
+        }
+        // 码位查询需要读取字体文件，放在最后
+        covers.is_empty()
+            || fs::read(policy.font_path(font_dir, &font.name))
+                .is_ok_and(|data| coverage::supports_all(&data, covers))
+    }
+
+    // 依次过滤、排序、分页，total 为分页前的数量
+    fn apply(&self, font_list: FontList, font_dir: &Path, policy: &ServerPolicy, covers: &[u32]) -> FontList {
+        let tags = metadata_store::normalize_tags(self.tag.as_deref().unwrap_or("").split(','));
+        let formats: Vec<String> = self
+            .format
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(|f| f.trim().trim_start_matches('.').to_lowercase())
+            .filter(|f| !f.is_empty())
+            .collect();
+
+        let mut fonts: Vec<FontInfo> = font_list
+            .fonts
+            .into_iter()
+            .filter(|font| self.matches(font, font_dir, policy, &tags, &formats, covers))
+            .collect();
+        match self.sort.unwrap_or_default() {
+            FontSort::Name => fonts.sort_by(|a, b| a.name.cmp(&b.name)),
+            FontSort::Size => fonts.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name))),
+            FontSort::Modified => {
+                fonts.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)))
+            }
+        }
+
+        let total = fonts.len();
+        let fonts = fonts
+            .into_iter()
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        FontList { fonts, total: Some(total) }
+    }
+}
+
+async fn list_fonts_handler(
+    query: FontQuery,
+    font_dir: Arc<PathBuf>,
+    metadata: Arc<MetadataStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    Ok(match query_fonts(&query, &font_dir, &metadata, &policy).await {
+        Ok(font_list) => Box::new(warp::reply::json(&font_list)),
+        Err((status, response)) => detailed_error_reply(status, response),
+    })
+}
+
+// GET /fonts 与 gRPC 的 List 共用：列出字体及等待审核的新字体，再按查询过滤
+pub(crate) async fn query_fonts(
+    query: &FontQuery,
+    font_dir: &Path,
+    metadata: &MetadataStore,
+    policy: &ServerPolicy,
+) -> Result<FontList, UploadError> {
+    let covers = match query.covers.as_deref().map(coverage::parse_codepoints).transpose() {
+        Ok(covers) => covers.unwrap_or_default(),
+        Err(e) => {
+            return Err(upload_error(StatusCode::BAD_REQUEST, "Invalid covers parameter", e.to_string()));
+        }
+    };
+
+    match list_fonts_impl(font_dir, metadata, policy, true).await {
+        Ok(mut font_list) => {
+            for font in &mut font_list.fonts {
+                let stored = metadata.get(&font.name);
+                font.tags = stored.tags;
+                font.uploaded_by = stored.uploaded_by;
+            }
+            // 等待审核的新字体单独列出，替换已有字体的提交在批准前不显示
+            if let Some(pending) = &policy.moderation {
+                let existing: BTreeSet<String> = font_list.fonts.iter().map(|f| f.name.clone()).collect();
+                let awaiting = pending.list().into_iter().filter(|u| !existing.contains(&u.name));
+                font_list.fonts.extend(awaiting.map(|upload| FontInfo {
+                    mime_type: get_font_mime_type(Path::new(&upload.name)),
+                    sha256: upload.sha256,
+                    size: upload.size,
+                    status: Some(FontStatus::AwaitingApproval),
+                    modified: upload.modified,
+                    plaintext_sha256: upload.plaintext_sha256,
+                    embedding: None,
+                    restricted: false,
+                    family: None,
+                    style: None,
+                    version: None,
+                    unicode_ranges: Vec::new(),
+                    tags: BTreeSet::new(),
+                    uploaded_by: Some(upload.uploaded_by),
+                    name: upload.name,
+                }));
+            }
+            Ok(query.apply(font_list, font_dir, policy, &covers))
+        }
+        Err(e) => {
+            error!("Failed to list fonts: {}", e);
+            Err(upload_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list fonts", e.to_string()))
+        }
+    }
+}
+
+// lazy 为 true 时后台尚未计算完的字体不等待，返回待定条目
+async fn list_fonts_impl(
+    font_dir: &Path,
+    store: &MetadataStore,
+    policy: &ServerPolicy,
+    lazy: bool,
+) -> Result<FontList> {
+    let mut fonts = Vec::new();
+
+    for (name, path) in policy.font_files(font_dir)? {
+        let metadata = fs::metadata(&path).context("Failed to get file metadata")?;
+
+        let mime_type = get_font_mime_type(&path);
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        if lazy && store.is_hash_pending(&name) {
+            fonts.push(FontInfo {
+                name,
+                size: metadata.len(),
+                mime_type,
+                sha256: String::new(),
+                status: Some(FontStatus::Hashing),
+                modified: Some(modified),
+                plaintext_sha256: None,
+                embedding: None,
+                restricted: false,
+                family: None,
+                style: None,
+                version: None,
+                unicode_ranges: Vec::new(),
+                tags: BTreeSet::new(),
+                uploaded_by: None,
+            });
+            continue;
+        }
+
+        let sha256 = store.file_sha256(&name, &path)
+            .unwrap_or_else(|e| {
+                error!("Failed to calculate SHA256 for {:?}: {}", path, e);
+                String::new()
+            });
+
+        let plaintext_sha256 = read_plaintext_sha256(font_dir, &name);
+        // 加密字体无法解析
+        let (embedding, descriptor, unicode_ranges) = match plaintext_sha256 {
+            None => {
+                let data = fs::read(&path).unwrap_or_default();
+                (
+                    font_metadata::read_embedding_permission(&data),
+                    font_metadata::read_descriptor(&data),
+                    coverage::supported_ranges(&data),
+                )
+            }
+            Some(_) => (None, FontDescriptor::default(), Vec::new()),
+        };
+
+        fonts.push(FontInfo {
+            name,
+            size: metadata.len(),
+            mime_type,
+            sha256,
+            status: None,
+            modified: Some(modified),
+            plaintext_sha256,
+            restricted: embedding.is_some_and(EmbeddingPermission::is_restricted),
+            embedding,
+            family: descriptor.family,
+            style: descriptor.style,
+            version: descriptor.version,
+            unicode_ranges,
+            tags: BTreeSet::new(),
+            uploaded_by: None,
+        });
+    }
+
+    if let Err(e) = store.flush_hashes() {
+        warn!("Failed to save hash cache: {}", e);
+    }
+    Ok(FontList { total: Some(fonts.len()), fonts })
+}
+
+// 尚未计算完哈希的字体同样计入，家族名待解析后才统计
+async fn stats_handler(
+    font_dir: Arc<PathBuf>,
+    metadata: Arc<MetadataStore>,
+    event_log: Arc<EventLog>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let mut fonts = match list_fonts_impl(&font_dir, &metadata, &policy, true).await {
+        Ok(font_list) => font_list.fonts,
+        Err(e) => {
+            error!("Failed to collect statistics: {}", e);
+            return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to collect statistics", e.to_string()));
+        }
+    };
+
+    let mut stats = ServerStats {
+        total_fonts: fonts.len(),
+        total_bytes: fonts.iter().map(|f| f.size).sum(),
+        recent_activity: event_log.recent(STATS_RECENT_EVENTS),
+        uploads_by_client: event_log.upload_counts(),
+        ..Default::default()
+    };
+    for font in &fonts {
+        let format = Path::new(&font.name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        *stats.formats.entry(format).or_insert(0) += 1;
+        if let Some(family) = &font.family {
+            *stats.families.entry(family.clone()).or_insert(0) += 1;
+        }
+    }
+    fonts.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    stats.largest = fonts
+        .into_iter()
+        .take(STATS_LARGEST)
+        .map(|f| LargestFont { name: f.name, size: f.size })
+        .collect();
+
+    Ok(Box::new(warp::reply::json(&stats)))
+}
+
+// 只返回文件名与内容哈希，哈希来自缓存，不解析字体
+async fn font_hashes_handler(
+    query: FontQuery,
+    font_dir: Arc<PathBuf>,
+    metadata: Arc<MetadataStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let tags: Vec<&str> = query
+        .tag
+        .as_deref()
+        .map(|t| t.split(',').map(str::trim).filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default();
+    let files = match policy.font_files(&font_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to read font directory: {:#}", e);
+            return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list fonts", e.to_string()));
+        }
+    };
+
+    let mut hashes = BTreeMap::new();
+    let mut pending = BTreeSet::new();
+    for (name, path) in files {
+        if !tags.is_empty() && !metadata.get(&name).tags.iter().any(|t| tags.contains(&t.as_str())) {
+            continue;
+        }
+        let sha256 = match read_plaintext_sha256(&font_dir, &name) {
+            Some(sha256) => sha256,
+            None if metadata.is_hash_pending(&name) => {
+                pending.insert(name);
+                continue;
+            }
+            None => match metadata.file_sha256(&name, &path) {
+                Ok(sha256) => sha256,
+                Err(e) => {
+                    error!("Failed to calculate SHA256 for {:?}: {}", path, e);
+                    continue;
+                }
+            },
+        };
+        hashes.insert(name, sha256);
+    }
+    if let Err(e) = metadata.flush_hashes() {
+        warn!("Failed to save hash cache: {}", e);
+    }
+    Ok(Box::new(warp::reply::json(&FontHashes { pending, ..FontHashes::new(hashes) })))
+}
+
+async fn download_font_handler(
+    filename: String,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+
+    if !font_path.exists() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+
+    match File::open(&font_path).await {
+        Ok(file) => {
+            // 获取文件大小用于 Content-Length
+            let metadata = match tokio::fs::metadata(&font_path).await {
+                Ok(m) => m,
+                Err(e) => return Ok(error_reply(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read font",
+                    format!("Failed to get metadata for font '{}': {}", filename, e),
+                )),
+            };
+            
+            // 确定内容类型
+            let content_type = get_font_mime_type(&font_path);
+
+            let stream = tokio_util::io::ReaderStream::new(file);
+            let body = warp::hyper::Body::wrap_stream(stream);
+            
+            let mut response = warp::reply::Response::new(body);
+            response.headers_mut().insert(
+                "Content-Type",
+                content_type.parse().unwrap_or_else(|_| "application/octet-stream".parse().unwrap()),
+            );
+            response.headers_mut().insert(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", filename)
+                    .parse()
+                    .unwrap(),
+            );
+            response.headers_mut().insert(
+                "Content-Length",
+                metadata.len().to_string().parse().unwrap(),
+            );
+            if let Some(modified) = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            {
+                response
+                    .headers_mut()
+                    .insert(api::MODIFIED_HEADER, modified.as_secs().to_string().parse().unwrap());
+            }
+
+            Ok(Box::new(response))
+        }
+        Err(e) => {
+            error!("Failed to open font file '{}': {}", filename, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", format!("Failed to open font file: {}", e)))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_font_handler(
+    mut form: FormData,
+    query: UploadQuery,
+    if_match: Option<String>,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    uploaded_by: Attribution,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let deadline = Instant::now() + policy.upload_timeout;
+    // 超过并发上限时排队，排队时间同样计入时限
+    let Ok(Ok(_permit)) = tokio::time::timeout_at(deadline, Arc::clone(&policy.upload_slots).acquire_owned()).await else {
+        return Ok(upload_timeout_reply(&policy));
+    };
+    let mut options = UploadOptions { overwrite: query.overwrite, ..Default::default() };
+    if let Some(header) = if_match {
+        match parse_if_match(&header) {
+            Some(expected) => options.if_match = Some(expected),
+            None => return Ok(invalid_if_match_reply()),
+        }
+    }
+
+    loop {
+        let part = match tokio::time::timeout_at(deadline, form.next()).await {
+            Ok(Some(part)) => part,
+            Ok(None) => break,
+            Err(_) => return Ok(upload_timeout_reply(&policy)),
+        };
+        match part {
+            Ok(p) if p.name() == "font" => {
+                let filename = p.filename().unwrap_or("unknown_font").to_string();
+                let body = part_body(p);
+                let received =
+                    receive_font(&filename, body, &options, deadline, &font_dir, &metadata, &blobs, &uploaded_by, &policy)
+                        .await;
+                return Ok(match received {
+                    Ok((status, response, event)) => {
+                        if let Some(event) = event {
+                            publish_event(&event_log, ws_server.as_ref(), event, Some(&uploaded_by));
+                        }
+                        Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                    }
+                    Err((status, response)) => detailed_error_reply(status, response),
+                });
+            }
+            Ok(p) => {
+                if let Err((status, response)) = options.read(p, deadline).await {
+                    return Ok(detailed_error_reply(status, response));
+                }
+            }
+            Err(e) => {
+                error!("Error processing multipart form: {}", e);
+                return Ok(error_reply(StatusCode::BAD_REQUEST, "Error processing form", e.to_string()));
+            }
+        }
+    }
+
+    Ok(error_reply(StatusCode::BAD_REQUEST, "No font file found in upload", "No font file provided".to_string()))
+}
+
+// 一次请求上传多个字体，每个 font 部分之前的选项只作用于该字体；
+// 各字体分别检查并返回结果，新增的字体合并为一条 FontsAdded 通知。
+// atomic 时全部字体检查通过后才一起生效，任一失败则整批不生效
+#[allow(clippy::too_many_arguments)]
+async fn upload_batch_handler(
+    mut form: FormData,
+    query: UploadQuery,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    uploaded_by: Attribution,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let deadline = Instant::now() + policy.upload_timeout;
+    // 整批只占用一个并发名额
+    let Ok(Ok(_permit)) = tokio::time::timeout_at(deadline, Arc::clone(&policy.upload_slots).acquire_owned()).await else {
+        return Ok(upload_timeout_reply(&policy));
+    };
+    // 查询参数中的 overwrite 适用于整批
+    let defaults = || UploadOptions { overwrite: query.overwrite, ..Default::default() };
+    let mut options = defaults();
+    let mut results = Vec::new();
+    let mut staged: Vec<(String, Result<StagedFont, UploadError>)> = Vec::new();
+    let mut events = Vec::new();
+    let mut complete = true;
+
+    // 中途超时或表单出错时，已处理的字体照常生效（atomic 时整批丢弃），其余字体不出现在结果中
+    loop {
+        let received = results.len() + staged.len();
+        let part = match tokio::time::timeout_at(deadline, form.next()).await {
+            Ok(Some(Ok(part))) => part,
+            Ok(None) => break,
+            Ok(Some(Err(e))) => {
+                warn!("Error processing batch upload from {}: {}", uploaded_by, e);
+                complete = false;
+                break;
+            }
+            Err(_) => {
+                warn!("Batch upload from {} timed out after {} fonts", uploaded_by, received);
+                complete = false;
+                break;
+            }
+        };
+        if part.name() != "font" {
+            if let Err((status, response)) = options.read(part, deadline).await {
+                return Ok(detailed_error_reply(status, response));
+            }
+            continue;
+        }
+        if received == MAX_BATCH_UPLOADS {
+            return Ok(error_reply(
+                StatusCode::BAD_REQUEST,
+                "Too many fonts",
+                format!("A batch upload may contain at most {} fonts", MAX_BATCH_UPLOADS),
+            ));
+        }
+
+        let filename = sanitize_filename(part.filename().unwrap_or("unknown_font"));
+        let font_options = std::mem::replace(&mut options, defaults());
+        if query.atomic {
+            // 本批已持有同名文件的上传锁
+            let result = if staged.iter().any(|(name, _)| *name == filename) {
+                Err(upload_error(
+                    StatusCode::BAD_REQUEST,
+                    "Duplicate font",
+                    format!("'{}' appears more than once in the batch", filename),
+                ))
+            } else {
+                let reserved = staged.iter().filter_map(|(_, font)| font.as_ref().ok()).map(|font| font.upload.size).sum();
+                stage_font(&filename, part_body(part), &font_options, deadline, &font_dir, &metadata, &uploaded_by, &policy, reserved).await
+            };
+            let timed_out = matches!(&result, Err((status, _)) if *status == StatusCode::REQUEST_TIMEOUT);
+            staged.push((filename, result));
+            if timed_out {
+                complete = false;
+                break;
+            }
+            continue;
+        }
+        let result = receive_font(&filename, part_body(part), &font_options, deadline, &font_dir, &metadata, &blobs, &uploaded_by, &policy).await;
+        let result = batch_result(filename, result, &mut events);
+        let timed_out = result.status == StatusCode::REQUEST_TIMEOUT.as_u16();
+        results.push(result);
+        if timed_out {
+            break;
+        }
+    }
+
+    if query.atomic && !staged.is_empty() {
+        let committed = complete && staged.iter().all(|(_, font)| font.is_ok());
+        if !committed {
+            info!("Atomic batch upload from {} failed, discarding {} fonts", uploaded_by, staged.len());
+        }
+        for (filename, font) in staged {
+            let result = match font {
+                Ok(font) if committed => apply_staged(font, &font_dir, &metadata, &blobs, &policy),
+                Ok(font) => {
+                    font.discard();
+                    Err(upload_error(
+                        StatusCode::FAILED_DEPENDENCY,
+                        "Batch aborted",
+                        format!("'{}' was not stored because the rest of the batch failed", filename),
+                    ))
+                }
+                Err(e) => Err(e),
+            };
+            results.push(batch_result(filename, result, &mut events));
+        }
+    }
+
+    if results.is_empty() {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, "No font file found in upload", "No font file provided".to_string()));
+    }
+    info!("Batch upload from {}: {} fonts, {} changed", uploaded_by, results.len(), events.len());
+    publish_events(&event_log, ws_server.as_ref(), events, Some(&uploaded_by));
+    Ok(Box::new(warp::reply::json(&BatchUploadResponse { results })))
+}
+
+// 单个字体的结果；生效的字体的事件加入 events 供整批发布
+fn batch_result(
+    filename: String,
+    result: Result<(StatusCode, FontActionResponse, Option<WebSocketMessage>), UploadError>,
+    events: &mut Vec<WebSocketMessage>,
+) -> BatchUploadResult {
+    match result {
+        Ok((status, response, event)) => {
+            events.extend(event);
+            BatchUploadResult { filename, status: status.as_u16(), response: Some(response), error: None }
+        }
+        Err((status, response)) => BatchUploadResult { filename, status: status.as_u16(), response: None, error: Some(response) },
+    }
+}
+
+// 超出大小限制时返回带限制说明的 JSON，其余拒绝交给后续路由
+async fn recover_payload_too_large(rejection: Rejection, limit: u64) -> Result<Box<dyn Reply>, Rejection> {
+    if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        Ok(detailed_error_reply(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorResponse::new(
+                ErrorCode::PayloadTooLarge,
+                "Upload too large",
+                format!("Server accepts uploads up to {}", format_file_size(limit)),
+            )
+            .with_details(serde_json::json!({ "max_size": limit })),
+        ))
+    } else {
+        Err(rejection)
+    }
+}
+
+// 失败时的状态码与错误内容
+pub(crate) type UploadError = (StatusCode, ErrorResponse);
+
+pub(crate) fn upload_error(status: StatusCode, error: &str, message: String) -> UploadError {
+    (status, ErrorResponse::new(ErrorCode::for_status(status.as_u16()), error, message))
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct UploadQuery {
+    #[serde(default)]
+    overwrite: bool,
+    // 仅批量上传：全部检查通过后才一起生效
+    #[serde(default)]
+    atomic: bool,
+}
+
+// 上传中位于 font 部分之前的选项
+#[derive(Default)]
+pub(crate) struct UploadOptions {
+    // 加密上传时的明文哈希
+    pub(crate) plaintext_sha256: Option<String>,
+    // 显式重新添加已删除的字体
+    pub(crate) readd: bool,
+    // 允许替换服务器上同名的不同字体
+    pub(crate) overwrite: bool,
+    // 客户端文件的原始修改时间
+    pub(crate) modified: Option<u64>,
+    // 设置时 font 部分是针对该哈希版本的增量
+    pub(crate) delta_base: Option<String>,
+    // If-Match 中的版本哈希，为空表示 *（字体须已存在）；只用于单个上传
+    pub(crate) if_match: Option<Vec<String>>,
+}
+
+impl UploadOptions {
+    // 读取一个选项部分，未知的部分忽略
+    async fn read(&mut self, p: Part, deadline: Instant) -> Result<(), UploadError> {
+        match p.name() {
+            "readd" => {
+                self.readd = tokio::time::timeout_at(deadline, read_part_text(p))
+                    .await
+                    .is_ok_and(|value| value.is_ok_and(|value| matches!(value.trim(), "true" | "1")));
+            }
+            "overwrite" => {
+                self.overwrite = tokio::time::timeout_at(deadline, read_part_text(p))
+                    .await
+                    .is_ok_and(|value| value.is_ok_and(|value| matches!(value.trim(), "true" | "1")));
+            }
+            "modified" => {
+                self.modified = tokio::time::timeout_at(deadline, read_part_text(p))
+                    .await
+                    .ok()
+                    .and_then(|value| value.ok())
+                    .and_then(|value| value.trim().parse().ok());
+            }
+            "delta_base" => match tokio::time::timeout_at(deadline, read_part_text(p)).await {
+                Ok(Ok(value)) if is_sha256_hex(value.trim()) => self.delta_base = Some(value.trim().to_lowercase()),
+                _ => {
+                    return Err(upload_error(
+                        StatusCode::BAD_REQUEST,
+                        "Invalid delta_base",
+                        "delta_base must be a hex SHA256 digest".to_string(),
+                    ));
+                }
+            },
+            "plaintext_sha256" => match tokio::time::timeout_at(deadline, read_part_text(p)).await {
+                Ok(Ok(value)) if is_sha256_hex(value.trim()) => {
+                    self.plaintext_sha256 = Some(value.trim().to_lowercase());
+                }
+                _ => {
+                    return Err(upload_error(
+                        StatusCode::BAD_REQUEST,
+                        "Invalid plaintext_sha256",
+                        "plaintext_sha256 must be a hex SHA256 digest".to_string(),
+                    ));
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// 接收并检查一个 font 部分，通过后生效；返回的事件由调用方发布
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn receive_font(
+    filename: &str,
+    body: UploadBody,
+    options: &UploadOptions,
+    deadline: Instant,
+    font_dir: &Path,
+    metadata: &MetadataStore,
+    blobs: &BlobStore,
+    uploaded_by: &Attribution,
+    policy: &ServerPolicy,
+) -> Result<(StatusCode, FontActionResponse, Option<WebSocketMessage>), UploadError> {
+    let staged = stage_font(filename, body, options, deadline, font_dir, metadata, uploaded_by, policy, 0).await?;
+    apply_staged(staged, font_dir, metadata, blobs, policy)
+}
+
+// 通过检查、尚未生效的上传；持有该文件的上传锁直到生效或丢弃
+struct StagedFont {
+    tmp_path: PathBuf,
+    upload: PendingUpload,
+    embedding: Option<EmbeddingPermission>,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl StagedFont {
+    fn discard(self) {
+        let _ = fs::remove_file(&self.tmp_path);
+    }
+}
+
+// 接收一个 font 部分到临时文件并完成全部检查；reserved 为同一事务中已暂存、尚未计入配额的大小
+#[allow(clippy::too_many_arguments)]
+async fn stage_font(
+    filename: &str,
+    body: UploadBody,
+    options: &UploadOptions,
+    deadline: Instant,
+    font_dir: &Path,
+    metadata: &MetadataStore,
+    uploaded_by: &Attribution,
+    policy: &ServerPolicy,
+    reserved: u64,
+) -> Result<StagedFont, UploadError> {
+    let uploader = uploaded_by.to_string();
+    let plaintext_sha256 = &options.plaintext_sha256;
+    let filename = sanitize_filename(filename);
+    let font_path = font_dir.join(&filename);
+    if policy.is_read_only(font_dir, &filename) {
+        return Err(upload_error(StatusCode::FORBIDDEN, "Read-only font", read_only_message(&filename)));
+    }
+
+    // 持有到生效或丢弃为止，覆盖版本检查与替换
+    let Ok(guard) = tokio::time::timeout_at(deadline, policy.upload_locks.lock(&filename)).await else {
+        warn!("Upload of '{}' from {} timed out waiting for another upload of the same file", filename, uploader);
+        return Err(upload_timeout_error(policy));
+    };
+
+    // 先写入临时文件，检查通过后再替换目标文件
+    let tmp_path = font_dir
+        .join(".fontsync")
+        .join("tmp")
+        .join(uuid::Uuid::new_v4().to_string());
+
+    let Ok(saved) = tokio::time::timeout_at(deadline, save_body_to_file(body, &tmp_path, policy.max_upload_size)).await else {
+        let _ = fs::remove_file(&tmp_path);
+        warn!("Upload of '{}' from {} timed out", filename, uploader);
+        return Err(upload_timeout_error(policy));
+    };
+    // 增量上传先还原出完整文件，之后与普通上传相同
+    let saved = match (saved, &options.delta_base) {
+        (Ok(_), Some(base)) => match apply_uploaded_delta(&font_path, &tmp_path, base) {
+            Ok(Some(rebuilt)) => Ok(rebuilt),
+            Ok(None) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(upload_error(
+                    StatusCode::CONFLICT,
+                    "Delta base mismatch",
+                    format!("'{}' on the server is not the version the delta was made against", filename),
+                ));
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(upload_error(StatusCode::BAD_REQUEST, "Invalid delta", format!("{:#}", e)));
+            }
+        },
+        (saved, _) => saved,
+    };
+    let (sha256, size) = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            error!("Failed to save font '{}': {}", filename, e);
+            let _ = fs::remove_file(&tmp_path);
+            return Err(upload_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save font", e.to_string()));
+        }
+    };
+    // 批量上传的请求体上限是整批的，单个文件仍按单次上传的上限检查
+    if size > policy.max_upload_size {
+        let _ = fs::remove_file(&tmp_path);
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorResponse::new(
+                ErrorCode::PayloadTooLarge,
+                "Upload too large",
+                format!("Server accepts uploads up to {}", format_file_size(policy.max_upload_size)),
+            )
+            .with_details(serde_json::json!({ "max_size": policy.max_upload_size })),
+        ));
+    }
+
+    // 加密内容无法解析 fsType
+    let embedding = if plaintext_sha256.is_none() {
+        font_metadata::embedding_permission(&tmp_path)
+    } else {
+        None
+    };
+    if embedding.is_some_and(EmbeddingPermission::is_restricted) {
+        warn!("Font '{}' from {} has a Restricted License embedding permission", filename, uploader);
+        if policy.refuse_restricted {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(upload_error(
+                StatusCode::FORBIDDEN,
+                "Restricted license",
+                format!("Server refuses to store '{}': its fsType forbids redistribution", filename),
+            ));
+        }
+    }
+
+    let content_sha256 = plaintext_sha256.clone().unwrap_or_else(|| sha256.clone());
+    let family = if plaintext_sha256.is_none() {
+        font_metadata::descriptor(&tmp_path).family
+    } else {
+        None
+    };
+    if let Some(entry) = policy.blocklist.find(&filename, family.as_deref(), &[&sha256, &content_sha256]) {
+        let _ = fs::remove_file(&tmp_path);
+        warn!("Refused blocklisted font '{}' from {} (rule {})", filename, uploader, entry.id);
+        return Err((
+            StatusCode::FORBIDDEN,
+            ErrorResponse::new(
+                ErrorCode::Blocked,
+                "Font blocked",
+                format!("Server refuses to store '{}': {}", filename, entry.rule.reason),
+            )
+            .with_details(serde_json::json!({ "rule": entry.id, "reason": entry.rule.reason })),
+        ));
+    }
+
+    if !options.readd && metadata.get(&filename).deleted.is_some_and(|t| t.sha256 == content_sha256) {
+        let _ = fs::remove_file(&tmp_path);
+        info!("Refused to resurrect deleted font '{}' from {}", filename, uploader);
+        return Err(upload_error(
+            StatusCode::CONFLICT,
+            "Font deleted",
+            format!("'{}' was deleted; upload with readd to add it again", filename),
+        ));
+    }
+
+    if let Some(message) = policy.quota_exceeded(font_dir, metadata, uploaded_by, &filename, size + reserved) {
+        let _ = fs::remove_file(&tmp_path);
+        warn!("Refused '{}' from {}: {}", filename, uploader, message);
+        return Err(upload_error(StatusCode::INSUFFICIENT_STORAGE, "Quota exceeded", message));
+    }
+
+    // 未显式要求覆盖（overwrite、If-Match 或增量的 delta_base）时不替换同名的不同字体，相同内容照常按未变化处理
+    if !options.overwrite && options.if_match.is_none() && options.delta_base.is_none() {
+        let current = current_sha256s(font_dir, &filename);
+        if !current.is_empty() && !current.contains(&content_sha256) {
+            let _ = fs::remove_file(&tmp_path);
+            info!("Refused '{}' from {}: a different font with that name exists", filename, uploader);
+            return Err((
+                StatusCode::CONFLICT,
+                ErrorResponse::new(
+                    ErrorCode::AlreadyExists,
+                    "Font exists",
+                    format!("'{}' already exists on the server; upload with overwrite to replace it", filename),
+                )
+                .with_details(serde_json::json!({ "current_sha256": current.first() })),
+            ));
+        }
+    }
+
+    // 在生效前比较，避免覆盖客户端上次同步后他人上传的版本
+    if let Some(expected) = &options.if_match {
+        let current = current_sha256s(font_dir, &filename);
+        if !if_match_satisfied(expected, &current) {
+            let _ = fs::remove_file(&tmp_path);
+            info!("Refused '{}' from {}: the stored version changed since it was last synced", filename, uploader);
+            return Err((StatusCode::PRECONDITION_FAILED, version_mismatch(&filename, &current, "upload replaces")));
+        }
+    }
+
+    let upload = PendingUpload {
+        name: filename.clone(),
+        sha256: sha256.clone(),
+        size,
+        plaintext_sha256: plaintext_sha256.clone(),
+        modified: options.modified,
+        uploaded_by: uploaded_by.clone(),
+        submitted_at: unix_now(),
+        replaces: calculate_sha256(&font_dir.join(&filename)).ok(),
+    };
+    Ok(StagedFont { tmp_path, upload, embedding, _guard: guard })
+}
+
+// 暂存的上传生效；需要审核时转入待审核队列
+fn apply_staged(
+    staged: StagedFont,
+    font_dir: &Path,
+    metadata: &MetadataStore,
+    blobs: &BlobStore,
+    policy: &ServerPolicy,
+) -> Result<(StatusCode, FontActionResponse, Option<WebSocketMessage>), UploadError> {
+    let StagedFont { tmp_path, upload, embedding, _guard } = staged;
+    let uploader = upload.uploaded_by.to_string();
+    let filename = upload.name.clone();
+    let sha256 = upload.sha256.clone();
+    let size = upload.size;
+
+    // 需要审核时先暂存，管理员批准后才生效
+    if let Some(pending) = &policy.moderation {
+        if let Err(e) = pending.submit(&tmp_path, upload) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(store_failed_error(&filename, &e));
+        }
+        info!("Font '{}' (SHA256: {}) from {} is awaiting approval", filename, sha256, uploader);
+        let response = FontActionResponse {
+            success: true,
+            filename,
+            action: "awaiting_approval".to_string(),
+            sha256: Some(sha256),
+            size: Some(size),
+            embedding,
+            modified: None,
+            message: Some("Upload is awaiting admin approval".to_string()),
+        };
+        return Ok((StatusCode::ACCEPTED, response, None));
+    }
+
+    let (action, event) = match commit_upload(font_dir, metadata, blobs, &tmp_path, &upload) {
+        Ok(committed) => committed,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(store_failed_error(&filename, &e));
+        }
+    };
+    info!("Uploaded font: {} (SHA256: {}) from {}", filename, sha256, uploader);
+
+    let response = FontActionResponse {
+        success: true,
+        modified: get_file_timestamp(&font_dir.join(&filename)).ok(),
+        filename,
+        action: action.to_string(),
+        sha256: Some(sha256),
+        size: Some(size),
+        embedding,
+        message: Some("Successfully uploaded".to_string()),
+    };
+    Ok((StatusCode::OK, response, event))
+}
+
+// 通过检查的上传生效：链接到数据存储并保留修改时间，返回 added、modified 或 unchanged 与待发布的事件
+fn commit_upload(
+    font_dir: &Path,
+    metadata: &MetadataStore,
+    blobs: &BlobStore,
+    tmp_path: &Path,
+    upload: &PendingUpload,
+) -> Result<(&'static str, Option<WebSocketMessage>)> {
+    let filename = &upload.name;
+    let font_path = font_dir.join(filename);
+    let content_sha256 = upload.plaintext_sha256.as_deref().unwrap_or(&upload.sha256);
+
+    // 记录覆盖前的内容哈希（加密字体取明文哈希），用于区分新增与修改
+    let previous_sha256 = if font_path.exists() {
+        read_plaintext_sha256(font_dir, filename).or_else(|| calculate_sha256(&font_path).ok())
+    } else {
+        None
+    };
+
+    // 相同内容只保存一份，文件名链接到该数据
+    blobs.store(tmp_path, &upload.sha256, filename, &font_path)?;
+
+    // 保留客户端文件的修改时间，未来的时间按当前时间处理
+    if let Some(original) = upload.modified
+        && let Err(e) = set_file_timestamp(&font_path, original.min(unix_now()))
+    {
+        warn!("Failed to preserve modified time of '{}': {}", filename, e);
+    }
+    // 修改时间可能回到旧值，哈希缓存不能再按大小与时间判断
+    metadata.forget_sha256(filename);
+
+    if let Err(e) = write_plaintext_sha256(font_dir, filename, upload.plaintext_sha256.as_deref()) {
+        error!("Failed to record plaintext SHA256 for '{}': {}", filename, e);
+    }
+    if let Err(e) = metadata.clear_tombstone(filename) {
+        error!("Failed to clear tombstone for '{}': {}", filename, e);
+    }
+    if let Err(e) = metadata.set_uploaded_by(filename, upload.uploaded_by.clone()) {
+        error!("Failed to record uploader of '{}': {}", filename, e);
+    }
+
+    let (action, event) = match previous_sha256 {
+        None => {
+            let details = font_event_details(font_dir, metadata, filename);
+            ("added", Some(create_font_added_event(filename.clone(), upload.sha256.clone(), upload.size, details)))
+        }
+        Some(previous) if previous != content_sha256 => {
+            let details = font_event_details(font_dir, metadata, filename);
+            ("modified", Some(create_font_modified_event(filename.clone(), upload.sha256.clone(), upload.size, details)))
+        }
+        Some(_) => ("unchanged", None),
+    };
+    Ok((action, event))
+}
+
+// 新增与修改通知附带的字体信息：修改时间、格式、标签，未加密的字体还有家族与样式
+fn font_event_details(font_dir: &Path, metadata: &MetadataStore, filename: &str) -> FontDetails {
+    let path = font_dir.join(filename);
+    let descriptor = match read_plaintext_sha256(font_dir, filename) {
+        None => fs::read(&path).map(|data| font_metadata::read_descriptor(&data)).unwrap_or_default(),
+        Some(_) => FontDescriptor::default(),
+    };
+    FontDetails {
+        modified: get_file_timestamp(&path).ok(),
+        family: descriptor.family,
+        style: descriptor.style,
+        format: path.extension().map(|ext| ext.to_string_lossy().to_lowercase()),
+        tags: Some(metadata.get(filename).tags),
+    }
+}
+
+// 批准后按普通上传生效并广播 FontAdded 或 FontModified
+async fn approve_pending_handler(
+    filename: String,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    // 先加锁再读取，避免批准期间同名的新提交或上传改变待审核内容与存储的版本
+    let _guard = policy.upload_locks.lock(&filename).await;
+    let Some((upload, data_path)) = policy.moderation.as_ref().and_then(|pending| pending.get(&filename)) else {
+        return Ok(pending_not_found(&filename));
+    };
+    // 提交时的覆盖与 If-Match 检查针对当时的版本，之后被替换或删除时不再覆盖
+    let current = calculate_sha256(&font_dir.join(&filename)).ok();
+    if current != upload.replaces && current.as_ref() != Some(&upload.sha256) {
+        info!("Refused to approve '{}': the stored font changed after it was submitted", filename);
+        return Ok(detailed_error_reply(
+            StatusCode::CONFLICT,
+            ErrorResponse::new(
+                ErrorCode::Conflict,
+                "Font changed",
+                format!("'{}' on the server changed after this upload was submitted", filename),
+            )
+            .with_details(serde_json::json!({ "current_sha256": current, "submitted_against": upload.replaces })),
+        ));
+    }
+    let action = match commit_upload(&font_dir, &metadata, &blobs, &data_path, &upload) {
+        Ok((action, event)) => {
+            if let Some(event) = event {
+                publish_event(&event_log, ws_server.as_ref(), event, Some(&upload.uploaded_by));
+            }
+            action
+        }
+        Err(e) => return Ok(store_failed_reply(&filename, &e)),
+    };
+    if let Some(pending) = &policy.moderation
+        && let Err(e) = pending.remove(&filename)
+    {
+        warn!("Failed to remove approved upload '{}' from the queue: {:#}", filename, e);
+    }
+    info!("Approved font '{}' (SHA256: {}) from {}", filename, upload.sha256, upload.uploaded_by);
+
+    Ok(Box::new(warp::reply::json(&FontActionResponse {
+        success: true,
+        action: action.to_string(),
+        sha256: Some(upload.sha256),
+        size: Some(upload.size),
+        embedding: None,
+        modified: get_file_timestamp(&font_dir.join(&filename)).ok(),
+        message: Some("Upload approved".to_string()),
+        filename,
+    })))
+}
+
+async fn reject_pending_handler(filename: String, policy: ServerPolicy) -> Result<Box<dyn Reply>, Rejection> {
+    let removed = match &policy.moderation {
+        Some(pending) => pending.remove(&filename),
+        None => Ok(None),
+    };
+    match removed {
+        Ok(Some(upload)) => {
+            info!("Rejected font '{}' from {}", filename, upload.uploaded_by);
+            Ok(Box::new(warp::reply::json(&upload)))
+        }
+        Ok(None) => Ok(pending_not_found(&filename)),
+        Err(e) => {
+            error!("Failed to reject upload '{}': {:#}", filename, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to reject upload", e.to_string()))
+        }
+    }
+}
+
+fn read_only_message(filename: &str) -> String {
+    format!("'{}' is in a read-only font directory", filename)
+}
+
+fn pending_not_found(filename: &str) -> Box<dyn Reply> {
+    error_reply(StatusCode::NOT_FOUND, "Upload not found", format!("No pending upload named '{}'", filename))
+}
+
+fn store_failed_reply(filename: &str, e: &anyhow::Error) -> Box<dyn Reply> {
+    let (status, response) = store_failed_error(filename, e);
+    detailed_error_reply(status, response)
+}
+
+fn store_failed_error(filename: &str, e: &anyhow::Error) -> UploadError {
+    error!("Failed to store font '{}': {:#}", filename, e);
+    upload_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save font", format!("{:#}", e))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// 按内容哈希获取字体数据，与文件名无关
+async fn download_blob_handler(sha256: String, blobs: Arc<BlobStore>) -> Result<Box<dyn Reply>, Rejection> {
+    let Some(path) = blobs.blob_path(&sha256) else {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Blob not found", format!("No blob with SHA256 {}", sha256)));
+    };
+    match File::open(&path).await {
+        Ok(file) => {
+            let size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+            let body = warp::hyper::Body::wrap_stream(tokio_util::io::ReaderStream::new(file));
+            let mut response = warp::reply::Response::new(body);
+            response.headers_mut().insert("Content-Type", "application/octet-stream".parse().unwrap());
+            response.headers_mut().insert("Content-Length", size.to_string().parse().unwrap());
+            Ok(Box::new(response))
+        }
+        Err(e) => {
+            error!("Failed to open blob {}: {}", sha256, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to open blob", e.to_string()))
+        }
+    }
+}
+
+// 定期删除没有文件名引用的数据
+fn spawn_blob_gc(blobs: Arc<BlobStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + BLOB_GC_INTERVAL, BLOB_GC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let blobs = Arc::clone(&blobs);
+            match tokio::task::spawn_blocking(move || blobs.collect_garbage()).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Blob garbage collection failed: {:#}", e),
+                Err(e) => error!("Blob garbage collection panicked: {}", e),
+            }
+        }
+    });
+}
+
+// 定期重新计算存储文件的哈希
+fn spawn_integrity_scrub(font_dir: PathBuf, blobs: Arc<BlobStore>, policy: ServerPolicy, interval: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+        loop {
+            interval.tick().await;
+            let (font_dir, blobs, policy) = (font_dir.clone(), Arc::clone(&blobs), policy.clone());
+            match tokio::task::spawn_blocking(move || run_integrity_scrub(&font_dir, &blobs, &policy)).await {
+                Ok(_) => {}
+                Err(e) => error!("Integrity scrub panicked: {}", e),
+            }
+        }
+    });
+}
+
+fn run_integrity_scrub(font_dir: &Path, blobs: &BlobStore, policy: &ServerPolicy) -> Option<IntegrityReport> {
+    let report = policy.integrity.run(font_dir, blobs, policy.backup_dir.as_deref(), policy.scrub_repair)?;
+    if report.issues.is_empty() {
+        info!("Integrity scrub checked {} files, no mismatches", report.checked);
+    } else {
+        warn!(
+            "Integrity scrub checked {} files: {} mismatches, {} not repaired",
+            report.checked,
+            report.issues.len(),
+            report.unrepaired()
+        );
+    }
+    Some(report)
+}
+
+async fn run_integrity_handler(
+    font_dir: Arc<PathBuf>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let result = tokio::task::spawn_blocking(move || run_integrity_scrub(&font_dir, &blobs, &policy)).await;
+    match result {
+        Ok(Some(report)) => Ok(Box::new(warp::reply::json(&report))),
+        Ok(None) => Ok(error_reply(
+            StatusCode::CONFLICT,
+            "Scrub already running",
+            "An integrity scrub is already in progress".to_string(),
+        )),
+        Err(e) => {
+            error!("Integrity scrub panicked: {}", e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Integrity scrub failed", e.to_string()))
+        }
+    }
+}
+
+fn upload_timeout_reply(policy: &ServerPolicy) -> Box<dyn Reply> {
+    let (status, response) = upload_timeout_error(policy);
+    detailed_error_reply(status, response)
+}
+
+pub(crate) fn upload_timeout_error(policy: &ServerPolicy) -> UploadError {
+    upload_error(
+        StatusCode::REQUEST_TIMEOUT,
+        "Upload timed out",
+        format!("Upload did not complete within {} seconds", policy.upload_timeout.as_secs()),
+    )
+}
+
+async fn send_command_handler(
+    client_id: String,
+    command: ClientCommand,
+    ws_server: Option<Arc<WebSocketServer>>,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let client_id = decode_segment(&client_id);
+    match ws_server.and_then(|s| s.send_command(&client_id, command)) {
+        Some(status) => {
+            info!("Sent {:?} to client {} ({} connections)", status.command, client_id, status.connections);
+            Ok(Box::new(warp::reply::with_status(warp::reply::json(&status), StatusCode::ACCEPTED)))
+        }
+        None => Ok(error_reply(
+            StatusCode::NOT_FOUND,
+            "Client not connected",
+            format!("Client '{}' is not connected over WebSocket", client_id),
+        )),
+    }
+}
+
+async fn add_block_rule_handler(rule: BlockRule, policy: ServerPolicy) -> Result<Box<dyn Reply>, Rejection> {
+    match policy.blocklist.add(rule) {
+        Ok(entry) => {
+            info!("Added blocklist rule {}: {:?}", entry.id, entry.rule);
+            Ok(Box::new(warp::reply::with_status(warp::reply::json(&entry), StatusCode::CREATED)))
+        }
+        Err(e) => Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid rule", format!("{:#}", e))),
+    }
+}
+
+async fn remove_block_rule_handler(id: String, policy: ServerPolicy) -> Result<Box<dyn Reply>, Rejection> {
+    if policy.blocklist.list().iter().any(|e| e.id == id && e.configured) {
+        return Ok(error_reply(
+            StatusCode::CONFLICT,
+            "Configured rule",
+            format!("Rule '{}' comes from the server blocklist file; edit that file instead", id),
+        ));
+    }
+    match policy.blocklist.remove(&id) {
+        Ok(Some(entry)) => {
+            info!("Removed blocklist rule {}", entry.id);
+            Ok(Box::new(warp::reply::json(&entry)))
+        }
+        Ok(None) => Ok(error_reply(StatusCode::NOT_FOUND, "Rule not found", format!("Rule '{}' not found", id))),
+        Err(e) => {
+            error!("Failed to remove blocklist rule '{}': {}", id, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update blocklist", e.to_string()))
+        }
+    }
+}
+
+// 删除的字体移入回收站，可通过 restore 恢复
+fn trash_dir(font_dir: &Path) -> PathBuf {
+    font_dir.join(".fontsync").join("trash")
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn delete_font_handler(
+    filename: String,
+    if_match: Option<String>,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = font_dir.join(&filename);
+    if policy.is_read_only(&font_dir, &filename) {
+        return Ok(error_reply(StatusCode::FORBIDDEN, "Read-only font", read_only_message(&filename)));
+    }
+    if !font_path.is_file() {
+        return Ok(error_reply(
+            StatusCode::NOT_FOUND,
+            "Font not found",
+            format!("Font '{}' not found", filename),
+        ));
+    }
+    if let Err(reply) = check_if_match(if_match.as_deref(), &font_dir, &filename, "delete removes") {
+        return Ok(reply);
+    }
+
+    let content_sha256 =
+        read_plaintext_sha256(&font_dir, &filename).or_else(|| metadata.file_sha256(&filename, &font_path).ok());
+    let trash_path = trash_dir(&font_dir).join(&filename);
+    let moved = fs::create_dir_all(trash_dir(&font_dir)).and_then(|_| fs::rename(&font_path, &trash_path));
+    if let Err(e) = moved {
+        error!("Failed to move font '{}' to trash: {}", filename, e);
+        return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete font", e.to_string()));
+    }
+    // 修改时间记为删除时间，供回收站列表显示
+    if let Ok(file) = fs::File::options().write(true).open(&trash_path) {
+        let _ = file.set_modified(std::time::SystemTime::now());
+    }
+
+    info!("Moved font '{}' to trash", filename);
+    // 回收站中的文件仍是数据的一个链接，数据本身由垃圾回收删除
+    if let Err(e) = blobs.unlink(&filename) {
+        error!("Failed to update name table for '{}': {}", filename, e);
+    }
+    metadata.forget_sha256(&filename);
+    if let Err(e) = metadata.flush_hashes() {
+        warn!("Failed to save hash cache: {}", e);
+    }
+    // 记录删除，避免仍持有该字体的客户端在同步时重新上传
+    if let Some(sha256) = &content_sha256
+        && let Err(e) = metadata.set_tombstone(&filename, sha256)
+    {
+        error!("Failed to record tombstone for '{}': {}", filename, e);
+    }
+    publish_event(
+        &event_log,
+        ws_server.as_ref(),
+        create_font_removed_event(filename.clone(), content_sha256.clone()),
+        None,
+    );
+
+    Ok(Box::new(warp::reply::json(&FontActionResponse {
+        success: true,
+        filename,
+        action: "deleted".to_string(),
+        sha256: content_sha256,
+        size: None,
+        embedding: None,
+        modified: None,
+        message: None,
+    })))
+}
+
+async fn list_trash_handler(font_dir: Arc<PathBuf>) -> Result<Box<dyn Reply>, Rejection> {
+    let mut fonts = Vec::new();
+    if let Ok(entries) = fs::read_dir(trash_dir(&font_dir)) {
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let deleted_at = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            fonts.push(TrashEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                deleted_at,
+            });
+        }
+    }
+    fonts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Box::new(warp::reply::json(&TrashList { fonts })))
+}
+
+async fn restore_font_handler(
+    filename: String,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let trash_path = trash_dir(&font_dir).join(&filename);
+    let font_path = font_dir.join(&filename);
+    if !trash_path.is_file() {
+        return Ok(error_reply(
+            StatusCode::NOT_FOUND,
+            "Font not in trash",
+            format!("Font '{}' is not in the trash", filename),
+        ));
+    }
+    if font_path.exists() || policy.font_path(&font_dir, &filename).is_file() {
+        return Ok(error_reply(
+            StatusCode::CONFLICT,
+            "Font exists",
+            format!("A font named '{}' already exists", filename),
+        ));
+    }
+
+    if let Err(e) = fs::rename(&trash_path, &font_path) {
+        error!("Failed to restore font '{}': {}", filename, e);
+        return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore font", e.to_string()));
+    }
+
+    let sha256 = calculate_sha256(&font_path).unwrap_or_default();
+    let size = fs::metadata(&font_path).map(|m| m.len()).unwrap_or(0);
+    info!("Restored font '{}' from trash", filename);
+    if let Err(e) = blobs.adopt(&filename, &sha256, &font_path) {
+        error!("Failed to store restored font '{}': {:#}", filename, e);
+    }
+    if let Err(e) = metadata.clear_tombstone(&filename) {
+        error!("Failed to clear tombstone for '{}': {}", filename, e);
+    }
+    publish_event(
+        &event_log,
+        ws_server.as_ref(),
+        create_font_added_event(filename.clone(), sha256.clone(), size, font_event_details(&font_dir, &metadata, &filename)),
+        None,
+    );
+
+    Ok(Box::new(warp::reply::json(&FontActionResponse {
+        success: true,
+        filename,
+        action: "restored".to_string(),
+        sha256: Some(sha256),
+        size: Some(size),
+        embedding: None,
+        modified: get_file_timestamp(&font_path).ok(),
+        message: None,
+    })))
+}
+
+// 改名保留内容与元数据；旧客户端看到的是原名称删除与新名称新增
+#[allow(clippy::too_many_arguments)]
+async fn rename_font_handler(
+    filename: String,
+    request: RenameRequest,
+    if_match: Option<String>,
+    font_dir: Arc<PathBuf>,
+    ws_server: Option<Arc<WebSocketServer>>,
+    event_log: Arc<EventLog>,
+    metadata: Arc<MetadataStore>,
+    blobs: Arc<BlobStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let to = sanitize_filename(&request.to);
+    let font_path = font_dir.join(&filename);
+    let new_path = font_dir.join(&to);
+    if policy.is_read_only(&font_dir, &filename) {
+        return Ok(error_reply(StatusCode::FORBIDDEN, "Read-only font", read_only_message(&filename)));
+    }
+    if !font_path.is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+    if let Err(reply) = check_if_match(if_match.as_deref(), &font_dir, &filename, "rename moves") {
+        return Ok(reply);
+    }
+    if !is_font_file(&new_path) {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid name", format!("'{}' is not a font file name", to)));
+    }
+    if to == filename || new_path.exists() || policy.font_path(&font_dir, &to).is_file() {
+        return Ok(error_reply(StatusCode::CONFLICT, "Font exists", format!("A font named '{}' already exists", to)));
+    }
+    if let Some(entry) = policy.blocklist.find(&to, None, &[]) {
+        return Ok(error_reply(
+            StatusCode::FORBIDDEN,
+            "Font blocked",
+            format!("Server refuses to store '{}': {}", to, entry.rule.reason),
+        ));
+    }
+
+    let plaintext_sha256 = read_plaintext_sha256(&font_dir, &filename);
+    let sha256 = match metadata.file_sha256(&filename, &font_path) {
+        Ok(sha256) => sha256,
+        Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    };
+    let content_sha256 = plaintext_sha256.clone().unwrap_or_else(|| sha256.clone());
+    // 先建立硬链接再删除原名称，目标在检查之后被并发创建时不会被覆盖
+    let renamed = match fs::hard_link(&font_path, &new_path) {
+        Ok(()) => fs::remove_file(&font_path),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Ok(error_reply(StatusCode::CONFLICT, "Font exists", format!("A font named '{}' already exists", to)));
+        }
+        // 不支持硬链接的文件系统退回普通改名
+        Err(_) => fs::rename(&font_path, &new_path),
+    };
+    if let Err(e) = renamed {
+        error!("Failed to rename font '{}' to '{}': {}", filename, to, e);
+        return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to rename font", e.to_string()));
+    }
+    info!("Renamed font '{}' to '{}'", filename, to);
+
+    if let Err(e) = write_plaintext_sha256(&font_dir, &to, plaintext_sha256.as_deref())
+        .and_then(|_| write_plaintext_sha256(&font_dir, &filename, None))
+    {
+        error!("Failed to move plaintext hash of '{}': {}", filename, e);
+    }
+    if let Err(e) = blobs.unlink(&filename).and_then(|_| blobs.adopt(&to, &sha256, &new_path)) {
+        error!("Failed to update name table for '{}': {:#}", to, e);
+    }
+    metadata.forget_sha256(&filename);
+    if let Err(e) = metadata.flush_hashes() {
+        warn!("Failed to save hash cache: {}", e);
+    }
+    if let Err(e) = metadata.rename(&filename, &to, Some(&content_sha256)) {
+        error!("Failed to move metadata of '{}': {}", filename, e);
+    }
+
+    let size = fs::metadata(&new_path).map(|m| m.len()).unwrap_or(0);
+    publish_event(
+        &event_log,
+        ws_server.as_ref(),
+        create_font_renamed_event(filename.clone(), to.clone(), sha256.clone(), size),
+        None,
+    );
+
+    Ok(Box::new(warp::reply::json(&FontActionResponse {
+        success: true,
+        filename: to,
+        action: "renamed".to_string(),
+        sha256: Some(sha256),
+        size: Some(size),
+        embedding: None,
+        modified: get_file_timestamp(&new_path).ok(),
+        message: Some(format!("Renamed from '{}'", filename)),
+    })))
+}
+
+// 写入事件日志并广播 WebSocket 通知
+pub(crate) fn publish_event(
+    event_log: &EventLog,
+    ws_server: Option<&Arc<WebSocketServer>>,
+    event: WebSocketMessage,
+    actor: Option<&Attribution>,
+) {
+    let event = record_event(event_log, event, actor);
+
+    // 广播 WebSocket 通知
+    if let Some(server) = ws_server {
+        server.broadcast_font_event(event);
+        info!("Broadcasted font event via WebSocket");
+    }
+}
+
+// 批量操作的事件逐条写入事件日志；多个新增合并为一条 FontsAdded 广播，其余逐条广播
+fn publish_events(
+    event_log: &EventLog,
+    ws_server: Option<&Arc<WebSocketServer>>,
+    events: Vec<WebSocketMessage>,
+    actor: Option<&Attribution>,
+) {
+    let mut added = Vec::new();
+    for event in events {
+        match record_event(event_log, event, actor) {
+            event @ WebSocketMessage::FontAdded { .. } => added.push(event),
+            event => {
+                if let Some(server) = ws_server {
+                    server.broadcast_font_event(event);
+                }
+            }
+        }
+    }
+    if let Some(server) = ws_server {
+        match added.len() {
+            0 => return,
+            1 => server.broadcast_font_event(added.remove(0)),
+            _ => server.broadcast_font_event(create_fonts_added_event(added)),
+        }
+        info!("Broadcasted batch font events via WebSocket");
+    }
+}
+
+// 写入事件日志，供离线客户端追赶与审计，并记下所属的请求 ID；返回附加了序号的事件
+fn record_event(event_log: &EventLog, event: WebSocketMessage, actor: Option<&Attribution>) -> WebSocketMessage {
+    match event_log.append(event.clone(), actor.cloned(), request_log::current()) {
+        Ok(record) => record.event,
+        Err(e) => {
+            warn!("Failed to record font event: {}", e);
+            event
+        }
+    }
+}
+
+async fn read_part_text(part: Part) -> Result<String> {
+    let mut data = Vec::new();
+    let mut stream = part.stream();
+    while let Some(item) = stream.next().await {
+        data.extend_from_slice(item?.chunk());
+    }
+    String::from_utf8(data).context("Form field is not valid UTF-8")
+}
+
+// 将临时文件中的增量应用到当前版本并原地替换为结果；当前版本不是 base 时返回 None
+fn apply_uploaded_delta(font_path: &Path, tmp_path: &Path, base: &str) -> Result<Option<(String, u64)>> {
+    let Ok(current) = fs::read(font_path) else {
+        return Ok(None);
+    };
+    if hex::encode(Sha256::digest(&current)) != base {
+        return Ok(None);
+    }
+    let patch = fs::read(tmp_path).context("Failed to read uploaded delta")?;
+    let rebuilt = delta::apply(&current, &patch)?;
+    fs::write(tmp_path, &rebuilt).context("Failed to write rebuilt font")?;
+    Ok(Some((hex::encode(Sha256::digest(&rebuilt)), rebuilt.len() as u64)))
+}
+
+// 当前版本各块的签名，客户端据此计算增量上传
+async fn font_signature_handler(
+    filename: String,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+    if !font_path.is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+    let signature = tokio::task::spawn_blocking(move || fs::read(&font_path).map(|data| delta::Signature::of(&data).encode()))
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|r| r);
+    match signature {
+        Ok(signature) => Ok(Box::new(warp::reply::with_header(signature, "Content-Type", "application/octet-stream"))),
+        Err(e) => Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    }
+}
+
+// 客户端提交旧版本的签名，增量足够小时返回增量，否则返回完整文件
+async fn font_delta_handler(
+    filename: String,
+    body: bytes::Bytes,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+    if !font_path.is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+    let signature = match delta::Signature::decode(&body) {
+        Ok(signature) => signature,
+        Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid signature", format!("{:#}", e))),
+    };
+    let modified = get_file_timestamp(&font_path).ok();
+    let built = tokio::task::spawn_blocking(move || {
+        fs::read(&font_path).map(|data| {
+            let patch = signature.diff(&data);
+            if delta::is_worthwhile(patch.len(), data.len()) {
+                ("delta", patch)
+            } else {
+                ("full", data)
+            }
+        })
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .and_then(|r| r);
+
+    match built {
+        Ok((kind, body)) => {
+            debug!("Serving '{}' as {} ({} bytes)", filename, kind, body.len());
+            let mut response = warp::reply::Response::new(body.into());
+            let headers = response.headers_mut();
+            headers.insert("Content-Type", "application/octet-stream".parse().unwrap());
+            headers.insert(api::DELTA_HEADER, kind.parse().unwrap());
+            if let Some(modified) = modified {
+                headers.insert(api::MODIFIED_HEADER, modified.to_string().parse().unwrap());
+            }
+            Ok(Box::new(response))
+        }
+        Err(e) => Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    }
+}
+
+// If-Match 的取值：* 返回空列表，否则为逗号分隔的（可带引号或 W/ 前缀的）SHA256；格式不符时返回 None
+pub(crate) fn parse_if_match(header: &str) -> Option<Vec<String>> {
+    if header.trim() == "*" {
+        return Some(Vec::new());
+    }
+    header
+        .split(',')
+        .map(|tag| {
+            let tag = tag.trim();
+            let tag = tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"');
+            is_sha256_hex(tag).then(|| tag.to_lowercase())
+        })
+        .collect()
+}
+
+// expected 为空表示 *，只要求字体存在
+fn if_match_satisfied(expected: &[String], current: &[String]) -> bool {
+    match expected.is_empty() {
+        true => !current.is_empty(),
+        false => current.iter().any(|sha256| expected.contains(sha256)),
+    }
+}
+
+fn invalid_if_match_reply() -> Box<dyn Reply> {
+    error_reply(StatusCode::BAD_REQUEST, "Invalid If-Match", "If-Match must be * or a list of SHA256 digests".to_string())
+}
+
+// action 如 "upload replaces"，补全说明中的动作
+fn version_mismatch(filename: &str, current: &[String], action: &str) -> ErrorResponse {
+    ErrorResponse::new(
+        ErrorCode::PreconditionFailed,
+        "Version mismatch",
+        format!("'{}' on the server is not the version this {}", filename, action),
+    )
+    .with_details(serde_json::json!({ "current_sha256": current.first() }))
+}
+
+// 删除与改名前按 If-Match 检查服务器上的当前版本：格式不符时为 400，版本不符时为 412
+fn check_if_match(header: Option<&str>, font_dir: &Path, filename: &str, action: &str) -> Result<(), Box<dyn Reply>> {
+    let Some(header) = header else {
+        return Ok(());
+    };
+    let Some(expected) = parse_if_match(header) else {
+        return Err(invalid_if_match_reply());
+    };
+    let current = current_sha256s(font_dir, filename);
+    if if_match_satisfied(&expected, &current) {
+        return Ok(());
+    }
+    info!("Refused to change '{}': the stored version changed since it was last synced", filename);
+    Err(detailed_error_reply(StatusCode::PRECONDITION_FAILED, version_mismatch(filename, &current, action)))
+}
+
+// 服务器上当前版本的哈希：存储内容的哈希，加密字体另有明文哈希；字体不存在时为空
+fn current_sha256s(font_dir: &Path, filename: &str) -> Vec<String> {
+    let font_path = font_dir.join(filename);
+    if !font_path.is_file() {
+        return Vec::new();
+    }
+    read_plaintext_sha256(font_dir, filename).into_iter().chain(calculate_sha256(&font_path).ok()).collect()
+}
+
+pub(crate) fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// 加密字体的明文哈希保存在旁路文件中，服务器本身无法解密内容
+fn plaintext_sha256_path(font_dir: &Path, filename: &str) -> PathBuf {
+    font_dir
+        .join(".fontsync")
+        .join("plaintext")
+        .join(format!("{}.sha256", filename))
+}
+
+fn read_plaintext_sha256(font_dir: &Path, filename: &str) -> Option<String> {
+    fs::read_to_string(plaintext_sha256_path(font_dir, filename))
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+// 未加密上传会清除旧的明文哈希
+fn write_plaintext_sha256(font_dir: &Path, filename: &str, sha256: Option<&str>) -> Result<()> {
+    let path = plaintext_sha256_path(font_dir, filename);
+    match sha256 {
+        Some(sha256) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, sha256)?;
+        }
+        None => {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 上传的文件内容：multipart 的 font 部分或 gRPC 的数据块
+pub(crate) type UploadBody = futures::stream::BoxStream<'static, Result<bytes::Bytes>>;
+
+fn part_body(part: Part) -> UploadBody {
+    part.stream()
+        .map(|item| item.map(|mut data| data.copy_to_bytes(data.remaining())).map_err(anyhow::Error::from))
+        .boxed()
+}
+
+// 超过 limit 后不再继续接收，返回的大小大于 limit，由调用方拒绝
+async fn save_body_to_file(mut body: UploadBody, path: &Path, limit: u64) -> Result<(String, u64)> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).await?;
+    }
+    let mut file = BufWriter::new(File::create(path).await?);
+    let mut size = 0u64;
+    
+    while let Some(item) = body.next().await {
+        let bytes = item?;
+        file.write_all(&bytes).await?;
+        size += bytes.len() as u64;
+        if size > limit {
+            break;
+        }
+    }
+    
+    file.flush().await?;
+    // 落盘后再替换目标文件，避免断电后留下不完整的字体
+    file.get_ref().sync_all().await?;
+    
+    // 保存后计算 SHA256
+    let sha256 = calculate_sha256(path)?;
+    Ok((sha256, size))
+}
+
+async fn get_sha256_handler(
+    filename: String,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+
+    if !font_path.exists() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+
+    match calculate_sha256(&font_path) {
+        Ok(sha256) => Ok(Box::new(warp::reply::json(&serde_json::json!({
+            "filename": filename,
+            "sha256": sha256,
+        })))),
+        Err(e) => {
+            error!("Failed to calculate SHA256 for '{}': {}", filename, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to calculate SHA256", e.to_string()))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PreviewQuery {
+    text: Option<String>,
+    size: Option<f32>,
+}
+
+fn bearer_token(authorization: Option<&str>) -> Option<&str> {
+    authorization
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+// 在路径与方法匹配之后检查令牌角色，失败时以 Denied 拒绝
+fn require_role(
+    role: Role,
+    admin_api: bool,
+    policy: ServerPolicy,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let result = policy.authorize(authorization.as_deref(), role, admin_api);
+            async move { result.map_err(warp::reject::custom) }
+        })
+        .untuple_one()
+}
+
+// 增量计算与管理接口不修改字体，维护期间仍然可用
+async fn maintenance_guard(
+    method: warp::http::Method,
+    path: warp::path::FullPath,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    use warp::http::Method;
+
+    let mutation = matches!(method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+    let exempt = path.as_str().starts_with("/admin/") || path.as_str().ends_with("/delta");
+    match policy.maintenance_error() {
+        Some((status, response)) if mutation && !exempt => Ok(Box::new(warp::reply::with_header(
+            detailed_error_reply(status, response),
+            "retry-after",
+            MAINTENANCE_RETRY_AFTER.as_secs().to_string(),
+        ))),
+        _ => Err(warp::reject::not_found()),
+    }
+}
+
+// 协议版本不受支持的客户端得到 426 与升级说明；不带协议头部的旧客户端照常处理
+async fn version_guard(protocol: Option<String>, client_version: Option<String>) -> Result<Box<dyn Reply>, Rejection> {
+    let Some(protocol) = protocol else {
+        return Err(warp::reject::not_found());
+    };
+    let server = VersionInfo::current();
+    let client = client_version.unwrap_or_else(|| "of unknown version".to_string());
+    let supported = format!("{}-{}", api::MIN_PROTOCOL_VERSION, api::PROTOCOL_VERSION);
+    let message = match protocol.trim().parse::<u32>() {
+        Ok(protocol) if server.supports(protocol) => return Err(warp::reject::not_found()),
+        Ok(protocol) if protocol > api::PROTOCOL_VERSION => format!(
+            "Client {} uses protocol {}, but server {} supports protocols {}; upgrade the server",
+            client, protocol, server.version, supported
+        ),
+        Ok(protocol) => format!(
+            "Client {} uses protocol {}, which server {} no longer supports (protocols {}); upgrade the client",
+            client, protocol, server.version, supported
+        ),
+        Err(_) => format!("Invalid protocol version '{}'", protocol),
+    };
+    warn!("Rejected incompatible client: {}", message);
+    Ok(error_reply(StatusCode::UPGRADE_REQUIRED, "Incompatible protocol", message))
+}
+
+// 未被处理的拒绝也以统一的 JSON 错误返回，而不是 warp 默认的纯文本
+fn rejection_reply(rejection: Rejection) -> Box<dyn Reply> {
+    use warp::filters::body::BodyDeserializeError;
+    use warp::reject;
+
+    if let Some(denied) = rejection.find::<Denied>() {
+        return error_reply(denied.status, denied.error, denied.message.clone());
+    }
+    let (status, error, message) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found", "No such endpoint".to_string())
+    } else if let Some(e) = rejection.find::<BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, "Invalid request body", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, "Invalid query", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::InvalidHeader>() {
+        (StatusCode::BAD_REQUEST, "Invalid header", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::MissingHeader>() {
+        (StatusCode::BAD_REQUEST, "Missing header", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::MethodNotAllowed>() {
+        (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::LengthRequired>() {
+        (StatusCode::LENGTH_REQUIRED, "Length required", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::PayloadTooLarge>() {
+        (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large", e.to_string())
+    } else if let Some(e) = rejection.find::<reject::UnsupportedMediaType>() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type", e.to_string())
+    } else {
+        error!("Unhandled rejection: {:?}", rejection);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal error", format!("{:?}", rejection))
+    };
+    error_reply(status, error, message)
+}
+
+// 错误类别由状态码决定；需要特定类别或附加数据时用 detailed_error_reply
+fn error_reply(status: StatusCode, error: &str, message: String) -> Box<dyn Reply> {
+    detailed_error_reply(status, ErrorResponse::new(ErrorCode::for_status(status.as_u16()), error, message))
+}
+
+fn detailed_error_reply(status: StatusCode, response: ErrorResponse) -> Box<dyn Reply> {
+    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+}
+
+// 渲染结果按字体内容、字号与文字缓存在 .fontsync/previews 下
+async fn preview_handler(
+    filename: String,
+    query: PreviewQuery,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+    if !font_path.is_file() {
+        return Ok(error_reply(
+            StatusCode::NOT_FOUND,
+            "Font not found",
+            format!("Font '{}' not found", filename),
+        ));
+    }
+    if read_plaintext_sha256(&font_dir, &filename).is_some() {
+        return Ok(error_reply(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Font is encrypted",
+            "End-to-end encrypted fonts cannot be previewed on the server".to_string(),
+        ));
+    }
+
+    let text = query
+        .text
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| preview::DEFAULT_TEXT.to_string());
+    if text.chars().count() > preview::MAX_TEXT_CHARS {
+        return Ok(error_reply(
+            StatusCode::BAD_REQUEST,
+            "Text too long",
+            format!("Preview text is limited to {} characters", preview::MAX_TEXT_CHARS),
+        ));
+    }
+    let size = query.size.unwrap_or(preview::DEFAULT_SIZE);
+    if !size.is_finite() {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid size", format!("Invalid size: {}", size)));
+    }
+    let size = size.clamp(preview::MIN_SIZE, preview::MAX_SIZE);
+
+    let font_sha256 = match calculate_sha256(&font_path) {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            error!("Failed to calculate SHA256 for '{}': {}", filename, e);
+            return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string()));
+        }
+    };
+    let cache_key = hex::encode(Sha256::digest(format!("{}\n{}\n{}", font_sha256, size, text)));
+    let cache_path = font_dir.join(".fontsync").join("previews").join(format!("{}.png", cache_key));
+
+    let png = match fs::read(&cache_path) {
+        Ok(png) => png,
+        Err(_) => {
+            let data = match fs::read(&font_path) {
+                Ok(data) => data,
+                Err(e) => {
+                    return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string()));
+                }
+            };
+            let png = match preview::render_png(&data, &text, size) {
+                Ok(png) => png,
+                Err(e) => {
+                    return Ok(error_reply(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Cannot render font", format!("{:#}", e)));
+                }
+            };
+            if let Err(e) = write_cache_file(&cache_path, &png) {
+                warn!("Failed to cache preview for '{}': {}", filename, e);
+            }
+            png
+        }
+    };
+
+    Ok(Box::new(warp::reply::with_header(png, "content-type", "image/png")))
+}
+
+// GET /fonts/{name}/instance?wght=700 或 ?instance=Bold 从可变字体生成静态实例
+async fn instance_handler(
+    filename: String,
+    query: HashMap<String, String>,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+    if !font_path.is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+    if read_plaintext_sha256(&font_dir, &filename).is_some() {
+        return Ok(error_reply(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Font is encrypted",
+            "End-to-end encrypted fonts cannot be instanced on the server".to_string(),
+        ));
+    }
+    let data = match fs::read(&font_path) {
+        Ok(data) => data,
+        Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    };
+    if let Err(e) = instancer::axes(&data) {
+        return Ok(error_reply(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Not a variable font", format!("{:#}", e)));
+    }
+
+    let result = match query.get("instance") {
+        Some(name) => instancer::instantiate_named(&data, name),
+        None => {
+            let mut location = Vec::new();
+            for (tag, value) in &query {
+                match instancer::parse_axis(&format!("{}={}", tag, value)) {
+                    Ok(axis) => location.push(axis),
+                    Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid axis", e)),
+                }
+            }
+            instancer::instantiate(&data, &location)
+        }
+    };
+    let instance = match result {
+        Ok(instance) => instance,
+        Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, "Cannot instance font", format!("{:#}", e))),
+    };
+    // 头部只能使用 ASCII 文件名
+    let ascii_name: String = instance.file_name().chars().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.')).collect();
+    let disposition = format!("attachment; filename=\"{}\"", ascii_name);
+    Ok(Box::new(warp::reply::with_header(
+        warp::reply::with_header(instance.data, "content-type", "font/ttf"),
+        "content-disposition",
+        disposition,
+    )))
+}
+
+#[derive(Deserialize, Debug)]
+struct SubsetQuery {
+    text: Option<String>,
+    unicodes: Option<String>,
+    format: Option<SubsetFormat>,
+}
+
+// GET /fonts/{name}/subset?text=...&unicodes=U+0020-007E&format=woff2 生成只含所需字符的子集，结果缓存在 .fontsync/subsets 下
+async fn subset_handler(
+    filename: String,
+    query: SubsetQuery,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let font_path = policy.font_path(&font_dir, &filename);
+    if !font_path.is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+    if read_plaintext_sha256(&font_dir, &filename).is_some() {
+        return Ok(error_reply(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Font is encrypted",
+            "End-to-end encrypted fonts cannot be subset on the server".to_string(),
+        ));
+    }
+    let codepoints = match subset::requested_codepoints(query.text.as_deref(), query.unicodes.as_deref()) {
+        Ok(codepoints) => codepoints,
+        Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid subset", format!("{:#}", e))),
+    };
+    let format = query.format.unwrap_or_default();
+
+    let font_sha256 = match calculate_sha256(&font_path) {
+        Ok(sha256) => sha256,
+        Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    };
+    let mut key = Sha256::new();
+    key.update(format!("{}\n{}\n", font_sha256, format.extension()));
+    for codepoint in &codepoints {
+        key.update(codepoint.to_be_bytes());
+    }
+    let cache_path = font_dir.join(".fontsync").join("subsets").join(hex::encode(key.finalize()));
+
+    let body = match fs::read(&cache_path) {
+        Ok(body) => body,
+        Err(_) => {
+            let data = match fs::read(&font_path) {
+                Ok(data) => data,
+                Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+            };
+            let body = match subset::subset(&data, &codepoints).and_then(|font| format.encode(font)) {
+                Ok(body) => body,
+                Err(e) => return Ok(error_reply(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Cannot subset font", format!("{:#}", e))),
+            };
+            if let Err(e) = write_cache_file(&cache_path, &body) {
+                warn!("Failed to cache subset of '{}': {}", filename, e);
+            }
+            body
+        }
+    };
+    let ascii_name: String = subset::subset_filename(&filename, format)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+        .collect();
+    Ok(Box::new(warp::reply::with_header(
+        webfont_reply(body, format.mime_type(), "no-cache"),
+        "content-disposition",
+        format!("attachment; filename=\"{}\"", ascii_name),
+    )))
+}
+
+fn write_cache_file(cache_path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create cache directory")?;
+    }
+    let tmp_path = cache_path.with_extension("tmp");
+    fs::write(&tmp_path, data).context("Failed to write cache file")?;
+    fs::rename(&tmp_path, cache_path).context("Failed to store cache file")?;
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct WebFontQuery {
+    format: Option<WebFormat>,
+}
+
+// 字体文件的 URL 带有内容哈希，可以长期缓存
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+// 网页字体允许跨域加载
+fn webfont_reply(body: Vec<u8>, content_type: &str, cache_control: &str) -> Box<dyn Reply> {
+    let reply = warp::reply::with_header(body, "content-type", content_type);
+    let reply = warp::reply::with_header(reply, "cache-control", cache_control);
+    Box::new(warp::reply::with_header(reply, "access-control-allow-origin", "*"))
+}
+
+struct FamilyFont {
+    name: String,
+    family: String,
+    data: Vec<u8>,
+    sha256: String,
+    weight: u16,
+    italic: bool,
+}
+
+// 按家族名（不区分大小写）查找可解析的字体，加密字体与 WOFF 文件不参与
+fn find_family_fonts(font_dir: &Path, policy: &ServerPolicy, family: &str) -> Result<Vec<FamilyFont>> {
+    let mut fonts = Vec::new();
+    for (name, path) in policy.font_files(font_dir)? {
+        if read_plaintext_sha256(font_dir, &name).is_some() {
+            continue;
+        }
+        let data = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let Some(found) = font_metadata::read_descriptor(&data).family else {
+            continue;
+        };
+        if !found.eq_ignore_ascii_case(family) {
+            continue;
+        }
+        let (weight, italic) = webfont::face_style(&data).unwrap_or((400, false));
+        fonts.push(FamilyFont {
+            name,
+            family: found,
+            sha256: hex::encode(Sha256::digest(&data)),
+            data,
+            weight,
+            italic,
+        });
+    }
+    fonts.sort_by(|a, b| (a.weight, a.italic, &a.name).cmp(&(b.weight, b.italic, &b.name)));
+    Ok(fonts)
+}
+
+fn decode_segment(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+// warp 不解码路径参数；字体名按上传时的规则规范化，与保存的文件名一致
+fn font_name(segment: String) -> String {
+    decode_path_segment(&segment)
+}
+
+// 样式表中依次引用的文件：转换的格式在原文件名后追加扩展名，WOFF2 之后以 WOFF 兜底
+fn webfont_sources(font: &FamilyFont, format: WebFormat) -> Vec<(String, WebFormat, &'static str)> {
+    let converted = |format: WebFormat, css_format| {
+        (format!("{}.{}", font.name, format.extension().unwrap_or_default()), format, css_format)
+    };
+    match format {
+        WebFormat::Woff2 => vec![converted(WebFormat::Woff2, "woff2"), converted(WebFormat::Woff, "woff")],
+        WebFormat::Woff => vec![converted(WebFormat::Woff, "woff")],
+        WebFormat::Original => vec![(font.name.clone(), WebFormat::Original, webfont::css_format(&font.name))],
+    }
+}
+
+// GET /webfonts/{family}.css 生成 @font-face 样式表，{family}.zip 打包离线使用的字体包
+async fn webfont_handler(
+    file: String,
+    query: WebFontQuery,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let file = decode_segment(&file);
+    let (family, kit) = match (file.strip_suffix(".css"), file.strip_suffix(".zip")) {
+        (Some(family), _) => (family, false),
+        (_, Some(family)) => (family, true),
+        _ => return Err(warp::reject::not_found()),
+    };
+    let format = query.format.unwrap_or_default();
+
+    let fonts = match find_family_fonts(&font_dir, &policy, family) {
+        Ok(fonts) if !fonts.is_empty() => fonts,
+        Ok(_) => {
+            return Ok(error_reply(
+                StatusCode::NOT_FOUND,
+                "Family not found",
+                format!("No fonts found for family '{}'", family),
+            ));
+        }
+        Err(e) => {
+            error!("Failed to collect fonts for family '{}': {}", family, e);
+            return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read fonts", e.to_string()));
+        }
+    };
+
+    if !kit {
+        let faces: Vec<WebFace> = fonts
+            .iter()
+            .map(|font| WebFace {
+                family: font.family.clone(),
+                weight: font.weight,
+                italic: font.italic,
+                sources: webfont_sources(font, format)
+                    .into_iter()
+                    .map(|(filename, _, css_format)| {
+                        let url = format!(
+                            "/webfonts/files/{}?v={}",
+                            utf8_percent_encode(&filename, NON_ALPHANUMERIC),
+                            &font.sha256[..16]
+                        );
+                        (url, css_format)
+                    })
+                    .collect(),
+            })
+            .collect();
+        let css = webfont::font_face_css(&faces);
+        return Ok(webfont_reply(css.into_bytes(), "text/css; charset=utf-8", "no-cache"));
+    }
+
+    // 字体包内的样式表使用相对路径引用 fonts/ 目录
+    let mut faces = Vec::new();
+    let mut entries = Vec::new();
+    for font in &fonts {
+        let mut sources = Vec::new();
+        for (filename, format, css_format) in webfont_sources(font, format) {
+            match format.convert(&font.data) {
+                Ok(data) => {
+                    sources.push((format!("fonts/{}", utf8_percent_encode(&filename, NON_ALPHANUMERIC)), css_format));
+                    entries.push((format!("fonts/{}", filename), data));
+                }
+                Err(e) => warn!("Skipping {} of '{}' in webfont kit: {}", css_format, font.name, e),
+            }
+        }
+        if !sources.is_empty() {
+            faces.push(WebFace { family: font.family.clone(), weight: font.weight, italic: font.italic, sources });
+        }
+    }
+    entries.insert(0, ("stylesheet.css".to_string(), webfont::font_face_css(&faces).into_bytes()));
+
+    let archive = match webfont::zip_archive(&entries) {
+        Ok(archive) => archive,
+        Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build kit", e.to_string())),
+    };
+    let kit_name: String = fonts[0]
+        .family
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    Ok(Box::new(warp::reply::with_header(
+        warp::reply::with_header(archive, "content-type", "application/zip"),
+        "content-disposition",
+        format!("attachment; filename=\"{}-webfont-kit.zip\"", kit_name),
+    )))
+}
+
+// 原字体直接返回；{name}.woff 与 {name}.woff2 在原字体不存在时按需转换并缓存在 .fontsync/webfonts 下
+async fn webfont_file_handler(
+    file: String,
+    font_dir: Arc<PathBuf>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let file = decode_segment(&file);
+    if file.contains(['/', '\\']) || file.starts_with('.') {
+        return Err(warp::reject::not_found());
+    }
+
+    let converted = [WebFormat::Woff2, WebFormat::Woff]
+        .into_iter()
+        .filter(|_| !policy.font_path(&font_dir, &file).is_file())
+        .find_map(|format| {
+            let source = file.strip_suffix(format.extension()?)?.strip_suffix('.')?;
+            Some((source, format))
+        });
+    let (source, format) = converted.unwrap_or((file.as_str(), WebFormat::Original));
+    let source_path = policy.font_path(&font_dir, source);
+    if !source_path.is_file() || !is_font_file(&source_path) || read_plaintext_sha256(&font_dir, source).is_some() {
+        return Ok(error_reply(
+            StatusCode::NOT_FOUND,
+            "Font not found",
+            format!("Font '{}' not found", file),
+        ));
+    }
+    let data = match fs::read(&source_path) {
+        Ok(data) => data,
+        Err(e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read font", e.to_string())),
+    };
+    let Some(extension) = format.extension() else {
+        return Ok(webfont_reply(data, &get_font_mime_type(&source_path), IMMUTABLE_CACHE));
+    };
+
+    let cache_path = font_dir
+        .join(".fontsync")
+        .join("webfonts")
+        .join(format!("{}.{}", hex::encode(Sha256::digest(&data)), extension));
+    let converted = match fs::read(&cache_path) {
+        Ok(converted) => converted,
+        Err(_) => match format.convert(&data) {
+            Ok(converted) => {
+                if let Err(e) = write_cache_file(&cache_path, &converted) {
+                    warn!("Failed to cache {} for '{}': {}", extension, source, e);
+                }
+                converted
+            }
+            Err(e) => {
+                return Ok(error_reply(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Cannot convert font", format!("{:#}", e)));
+            }
+        },
+    };
+    Ok(webfont_reply(converted, &format!("font/{}", extension), IMMUTABLE_CACHE))
+}
+
+// 替换字体的全部标签，空列表表示清除
+async fn set_tags_handler(
+    filename: String,
+    request: TagsRequest,
+    font_dir: Arc<PathBuf>,
+    metadata: Arc<MetadataStore>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    if !policy.font_path(&font_dir, &filename).is_file() {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Font not found", format!("Font '{}' not found", filename)));
+    }
+
+    match metadata.set_tags(&filename, request.tags) {
+        Ok(tags) => {
+            info!("Updated tags for '{}': {:?}", filename, tags);
+            Ok(Box::new(warp::reply::json(&TagsResponse { name: filename, tags })))
+        }
+        Err(e) => {
+            error!("Failed to update tags for '{}': {}", filename, e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update tags", e.to_string()))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct EventsQuery {
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+async fn list_events_handler(
+    query: EventsQuery,
+    event_log: Arc<EventLog>,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let limit = query.limit.unwrap_or(event_log::DEFAULT_PAGE_SIZE);
+    let page = event_log.since(query.since.unwrap_or(0), Some(limit));
+    Ok(Box::new(warp::reply::json(&page)))
+}
+
+// SSE 事件流，数据与 WebSocket 广播相同
+fn sse_events(
+    event_log: Arc<EventLog>,
+    last_event_id: Option<u64>,
+) -> impl futures::Stream<Item = Result<warp::sse::Event, Infallible>> {
+    follow_events(event_log, last_event_id).filter_map(|(seq, message)| async move {
+        let event = match seq {
+            Some(seq) => warp::sse::Event::default().id(seq.to_string()),
+            None => warp::sse::Event::default(),
+        };
+        match event.json_data(&message) {
+            Ok(event) => Some(Ok(event)),
+            Err(e) => {
+                warn!("Failed to encode event {:?}: {}", seq, e);
+                None
+            }
+        }
+    })
+}
+
+// 先回放 last_event_id 之后的事件，再推送新事件，SSE 与 gRPC 的 WatchEvents 共用。
+// 订阅者落后过多时从事件日志补齐；last_event_id 之后的事件已被清理时先给出不带序号的 ResyncRequired
+pub(crate) fn follow_events(
+    event_log: Arc<EventLog>,
+    last_event_id: Option<u64>,
+) -> impl futures::Stream<Item = (Option<u64>, WebSocketMessage)> {
+    // 先订阅再读取日志，避免两者之间的事件丢失
+    let receiver = event_log.subscribe();
+    let backlog = event_log.since(last_event_id.unwrap_or(u64::MAX), None);
+    let last_seq = last_event_id.unwrap_or(backlog.latest_seq);
+    let resync = backlog.truncated.then(|| {
+        let oldest = backlog.events.first().map_or(backlog.latest_seq + 1, |r| r.seq);
+        (None, WebSocketMessage::ResyncRequired { dropped: oldest.saturating_sub(last_seq + 1) })
+    });
+    let pending: VecDeque<EventRecord> = backlog.events.into();
+
+    let events = futures::stream::unfold(
+        (event_log, receiver, pending, last_seq),
+        |(event_log, mut receiver, mut pending, mut last_seq)| async move {
+            loop {
+                if let Some(record) = pending.pop_front() {
+                    if record.seq <= last_seq {
+                        continue;
+                    }
+                    last_seq = record.seq;
+                    return Some(((Some(record.seq), record.event), (event_log, receiver, pending, last_seq)));
+                }
+                match receiver.recv().await {
+                    Ok(record) => pending.push_back(record),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        pending.extend(event_log.since(last_seq, None).events);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    futures::stream::iter(resync).chain(events)
+}
+
+// 签名清单覆盖所有字体的哈希，客户端据此校验下载内容
+async fn manifest_handler(
+    font_dir: Arc<PathBuf>,
+    metadata: Arc<MetadataStore>,
+    signer: Arc<ServerSigner>,
+    policy: ServerPolicy,
+) -> Result<Box<dyn Reply>, Rejection> {
+    // 签名清单必须覆盖全部字体，待定的哈希在此直接计算
+    let signed = list_fonts_impl(&font_dir, &metadata, &policy, false).await.and_then(|font_list| {
+        let manifest = Manifest {
+            generated_at: chrono::Utc::now().timestamp() as u64,
+            fonts: font_list
+                .fonts
+                .into_iter()
+                .map(|font| ManifestEntry {
+                    name: font.name,
+                    sha256: font.sha256,
+                    size: font.size,
+                })
+                .collect(),
+        };
+        signer.sign_manifest(&manifest)
+    });
+
+    match signed {
+        Ok(signed) => Ok(Box::new(warp::reply::json(&signed))),
+        Err(e) => {
+            error!("Failed to build signed manifest: {}", e);
+            Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build manifest", e.to_string()))
+        }
+    }
+}
+
+async fn signing_key_handler(signer: Arc<ServerSigner>) -> Result<Box<dyn Reply>, Rejection> {
+    match signer.public_key() {
+        Ok(public_key) => Ok(Box::new(warp::reply::json(&PublicKeyInfo {
+            algorithm: "ed25519".to_string(),
+            public_key,
+        }))),
+        Err(e) => Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read signing key", e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_bind_addrs, start_server};
+    use crate::client::{self, SyncOptions};
+    use crate::e2e::TeamKey;
+    use crate::event_log::EventLog;
+    use crate::sync_report::SyncReport;
+    use crate::websocket_server::WebSocketMessage;
+    use std::path::PathBuf;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+    use warp::{Filter, Reply};
+
+    #[tokio::test]
+    async fn start_server_returns_error_when_port_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind test port");
+        let port = listener.local_addr().expect("local addr").port();
+        let temp_dir = tempfile::tempdir().expect("temp dir");
+
+        let result = start_server(
+            vec!["127.0.0.1".to_string()],
+            Vec::new(),
+            port,
+            temp_dir.path().to_string_lossy().to_string(),
+            false,
+            false,
+            super::ServerPolicy::default(),
+        )
+        .await;
+
+        assert!(result.is_err(), "expected error when port is in use");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_over_unix_socket() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("local.ttf"), b"local font").unwrap();
+        let socket = server_dir.path().join("fontsync.sock");
+        // 遗留的套接字文件会被替换
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        let server = tokio::spawn(start_server(
+            Vec::new(),
+            vec![socket.clone()],
+            0,
+            server_dir.path().to_string_lossy().to_string(),
+            true,
+            false,
+            super::ServerPolicy::default(),
+        ));
+        while std::os::unix::net::UnixStream::connect(&socket).is_err() {
+            assert!(!server.is_finished(), "server exited early");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let server_url = format!("unix://{}", socket.display());
+        let api = client::ApiClient::new(&server_url).expect("api client");
+        let listed = api.list_fonts_hashed(&Default::default()).await.expect("list over unix socket");
+        assert_eq!(listed.fonts.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["local.ttf"]);
+        let (data, _) = api.fetch_font("local.ttf").await.expect("download over unix socket");
+        assert_eq!(&data[..], b"local font");
+        let ws_url = crate::websocket_client::check_connection(&server_url, "unix-client").await.expect("websocket over unix socket");
+        assert_eq!(ws_url, "ws://localhost/ws");
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn resolves_multiple_bind_addresses() {
+        let hosts = ["127.0.0.1", "[::1]", "::", "localhost", "127.0.0.1"].map(String::from);
+        let addrs = resolve_bind_addrs(&hosts, 8080).await.expect("resolve");
+        assert_eq!(addrs[0], "[::1]:8080".parse::<std::net::SocketAddr>().unwrap());
+        assert_eq!(addrs[1], "[::]:8080".parse::<std::net::SocketAddr>().unwrap());
+        assert_eq!(addrs.iter().filter(|a| a.to_string() == "127.0.0.1:8080").count(), 1);
+        assert!(addrs.iter().skip_while(|a| a.is_ipv6()).all(|a| a.is_ipv4()));
+        assert!(resolve_bind_addrs(&["no such host.invalid".to_string()], 8080).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_uploads_of_one_file_are_serialized() {
+        let locks = super::FileLocks::default();
+        let guard = locks.lock("a.ttf").await;
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), locks.lock("a.ttf")).await.is_err());
+        drop(tokio::time::timeout(std::time::Duration::from_millis(50), locks.lock("b.ttf")).await.expect("other file"));
+        drop(guard);
+        drop(locks.lock("a.ttf").await);
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let versions: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 64 * 1024]).collect();
+        futures::future::join_all(versions.iter().map(|data| post_font(&server_url, "same.ttf", data))).await;
+
+        let stored = std::fs::read(server_dir.path().join("same.ttf")).expect("stored font");
+        assert!(versions.contains(&stored));
+        let tmp = server_dir.path().join(".fontsync").join("tmp");
+        assert_eq!(std::fs::read_dir(&tmp).map(|entries| entries.count()).unwrap_or(0), 0);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn sync_upload_and_download_smoke() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let font_path = local_dir.path().join("test.ttf");
+        tokio::fs::write(&font_path, b"dummy font data")
+            .await
+            .expect("write font");
+
+        let (uploaded, _) = client::upload_local_fonts(
+            &server_url,
+            local_dir.path(),
+            &SyncOptions::default(),
+            &mut SyncReport::default(),
+        )
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 1);
+        assert!(server_dir.path().join("test.ttf").exists());
+
+        let listed = client::ApiClient::new(&server_url).unwrap().list_fonts(&Default::default())
+            .await
+            .expect("list server fonts");
+        assert_eq!(listed.fonts.len(), 1);
+        assert_eq!(listed.fonts[0].name, "test.ttf");
+
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let _ = client::download_server_fonts(
+            &server_url,
+            download_dir.path(),
+            &SyncOptions::default(),
+            &mut SyncReport::default(),
+        )
+            .await
+            .expect("download server fonts");
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn normalized_uploads_match_local_files_by_content() {
+        use crate::font_metadata::tests::{name_table, sfnt_with_tables};
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let messy_name = format!("font({}).ttf", uuid::Uuid::new_v4());
+        let font = sfnt_with_tables(&[(b"name", name_table(&[(1, "Normalize Test"), (2, "Bold")]))]);
+        tokio::fs::write(local_dir.path().join(&messy_name), &font).await.expect("write font");
+
+        let options = SyncOptions { normalize_names: true, ..SyncOptions::default() };
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 1);
+        assert!(server_dir.path().join("NormalizeTest-Bold.ttf").exists());
+        assert!(!server_dir.path().join(&messy_name).exists());
+
+        // 本地仍以原名称保存，按内容匹配后不再下载
+        let mut report = SyncReport::default();
+        let (downloaded, skipped) = client::download_server_fonts(&server_url, local_dir.path(), &options, &mut report)
+            .await
+            .expect("download server fonts");
+        assert_eq!((downloaded, skipped), (0, 1));
+        assert!(!local_dir.path().join("NormalizeTest-Bold.ttf").exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn pinned_fonts_keep_their_local_version() {
+        use crate::client_state::ClientState;
+        use crate::font_metadata::tests::{name_table, sfnt_with_tables};
+        use crate::sync_report::FileAction;
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let font = format!("Pinned{}.ttf", uuid::Uuid::new_v4().simple());
+        let version = |version: &str| sfnt_with_tables(&[(b"name", name_table(&[(1, "Pinned"), (5, version)]))]);
+        post_font(&server_url, &font, &version("Version 2.000")).await;
+        ClientState::pin(&font, None).expect("pin font");
+
+        // 固定的字体不自动下载，单独记录在报告中
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let mut report = SyncReport::default();
+        let (downloaded, skipped) = client::download_server_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut report)
+            .await
+            .expect("download server fonts");
+        assert_eq!((downloaded, skipped), (0, 1));
+        assert!(!local_dir.path().join(&font).exists());
+        assert_eq!(report.count(FileAction::Pinned), 1);
+        assert_eq!(report.files[0].direction, crate::utils::SyncDirection::Download);
+
+        // 本地的旧版本也不会替换服务器上的版本
+        tokio::fs::write(local_dir.path().join(&font), version("Version 1.000")).await.expect("write font");
+        let mut report = SyncReport::default();
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut report)
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 0);
+        assert_eq!(report.count(FileAction::Pinned), 1);
+        assert_eq!(std::fs::read(server_dir.path().join(&font)).unwrap(), version("Version 2.000"));
+
+        // 固定到服务器上的版本后照常同步
+        let server_sha = crate::utils::calculate_sha256(&server_dir.path().join(&font)).expect("hash server font");
+        ClientState::pin(&font, Some(server_sha)).expect("pin font");
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let mut report = SyncReport::default();
+        let (downloaded, _) = client::download_server_fonts(&server_url, download_dir.path(), &SyncOptions::default(), &mut report)
+            .await
+            .expect("download server fonts");
+        assert_eq!(downloaded, 1);
+        assert_eq!(report.count(FileAction::Pinned), 0);
+
+        ClientState::unpin(&font).expect("unpin font");
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn same_face_under_another_name_is_a_conflict() {
+        use crate::font_metadata::tests::{name_table, sfnt_with_tables};
+        use crate::utils::{ConflictPolicy, ConflictResolution};
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let family = format!("Identity {}", id);
+        let (old_name, new_name) = (format!("Identity{}-Regular.ttf", id), format!("Identity {} Regular.ttf", id));
+        let version = |version: &str| {
+            sfnt_with_tables(&[(b"name", name_table(&[(1, &family), (2, "Regular"), (5, version)]))])
+        };
+        post_font(&server_url, &old_name, &version("Version 1.000")).await;
+
+        // 内容相同时视为已同步，不以新名称重复上传
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        tokio::fs::write(local_dir.path().join(&new_name), version("Version 1.000")).await.expect("write font");
+        let (uploaded, skipped) = client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+            .await
+            .expect("upload local fonts");
+        assert_eq!((uploaded, skipped), (0, 1));
+        assert!(!server_dir.path().join(&new_name).exists());
+
+        // 版本不同时按冲突处理，默认跳过
+        tokio::fs::write(local_dir.path().join(&new_name), version("Version 2.000")).await.expect("write font");
+        let mut report = SyncReport::default();
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut report)
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 0);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].resolution, ConflictResolution::Skip);
+
+        // 覆盖服务器时新名称替换旧名称
+        let options = SyncOptions { on_conflict: ConflictPolicy::OverwriteRemote, ..SyncOptions::default() };
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 1);
+        assert!(server_dir.path().join(&new_name).exists());
+        assert!(!server_dir.path().join(&old_name).exists());
+
+        // 另一客户端的旧版本以其他名称保存，覆盖本地时替换
+        let other_dir = tempfile::tempdir().expect("other temp dir");
+        tokio::fs::write(other_dir.path().join(&old_name), version("Version 1.000")).await.expect("write font");
+        let options = SyncOptions { on_conflict: ConflictPolicy::OverwriteLocal, ..SyncOptions::default() };
+        let mut report = SyncReport::default();
+        let (downloaded, _) = client::download_server_fonts(&server_url, other_dir.path(), &options, &mut report)
+            .await
+            .expect("download server fonts");
+        assert_eq!(downloaded, 1);
+        assert_eq!(report.conflicts[0].resolution, ConflictResolution::Overwrite);
+        assert!(other_dir.path().join(&new_name).exists());
+        assert!(!other_dir.path().join(&old_name).exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn fonts_are_renamed_in_place() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
+
+        let font = crate::font_metadata::tests::square_font();
+        post_font(&server_url, "a.ttf", &font).await;
+        post_font(&server_url, "c.ttf", b"other").await;
+        let response = reqwest::Client::new()
+            .put(format!("{}/fonts/a.ttf/tags", server_url))
+            .json(&serde_json::json!({ "tags": ["brand"] }))
+            .send()
+            .await
+            .expect("set tags");
+        assert!(response.status().is_success());
+
+        let renamed = api.rename_font("a.ttf", "b.ttf", None).await.expect("rename font");
+        assert_eq!((renamed.filename.as_str(), renamed.action.as_str()), ("b.ttf", "renamed"));
+        assert!(!server_dir.path().join("a.ttf").exists());
+        assert_eq!(std::fs::read(server_dir.path().join("b.ttf")).unwrap(), font);
+
+        // 标签随字体转到新名称，原名称记录为删除
+        let listed = api.list_fonts(&crate::api::FontQuery::default()).await.expect("list fonts");
+        let b = listed.fonts.iter().find(|f| f.name == "b.ttf").expect("renamed font listed");
+        assert!(b.tags.contains("brand"));
+        let tombstones = api.tombstones().await.expect("tombstones");
+        assert!(tombstones.tombstones.iter().any(|t| t.name == "a.ttf"));
+        let events = api.events(0, None).await.expect("events");
+        assert!(events.events.iter().any(|record| matches!(
+            &record.event,
+            WebSocketMessage::FontRenamed { from, to, .. } if from == "a.ttf" && to == "b.ttf"
+        )));
+
+        let taken = api.rename_font("b.ttf", "c.ttf", None).await.unwrap_err();
+        assert_eq!(client::server_error(&taken).map(|e| e.code), Some(crate::api::ErrorCode::Conflict));
+        let missing = api.rename_font("a.ttf", "d.ttf", None).await.unwrap_err();
+        assert_eq!(client::server_error(&missing).map(|e| e.code), Some(crate::api::ErrorCode::NotFound));
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn atomic_batch_uploads_store_all_or_nothing() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { max_upload_size: 1024, ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let url = format!("http://{}/fonts/batch?atomic=true", addr);
+
+        let batch = |fonts: &[(&str, Vec<u8>)]| {
+            fonts.iter().fold(reqwest::multipart::Form::new(), |form, (name, data)| {
+                form.part("font", reqwest::multipart::Part::bytes(data.clone()).file_name(name.to_string()))
+            })
+        };
+        let post = |form| async {
+            let response = reqwest::Client::new().post(&url).multipart(form).send().await.expect("batch upload");
+            response.json::<crate::api::BatchUploadResponse>().await.expect("batch response")
+        };
+
+        // 一个字体失败时整批不生效，通过检查的字体报告 424
+        let response = post(batch(&[("a.ttf", vec![b'a'; 16]), ("big.ttf", vec![b'b'; 2048])])).await;
+        let statuses: Vec<_> = response.results.iter().map(|r| (r.filename.as_str(), r.status)).collect();
+        assert_eq!(statuses, [("a.ttf", 424), ("big.ttf", 413)]);
+        assert_eq!(response.results[0].error.as_ref().map(|e| e.code), Some(crate::api::ErrorCode::Aborted));
+        assert!(!server_dir.path().join("a.ttf").exists());
+
+        // 同名字体在一批中出现两次视为失败
+        let response = post(batch(&[("a.ttf", vec![b'a'; 16]), ("a.ttf", vec![b'A'; 16])])).await;
+        let statuses: Vec<_> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [424, 400]);
+        assert!(!server_dir.path().join("a.ttf").exists());
+
+        let response = post(batch(&[("a.ttf", vec![b'a'; 16]), ("c.ttf", vec![b'c'; 16])])).await;
+        let statuses: Vec<_> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [200, 200]);
+        assert!(server_dir.path().join("a.ttf").exists() && server_dir.path().join("c.ttf").exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn batch_uploads_report_each_font() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let policy = super::ServerPolicy { max_upload_size: 1024, ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let api = client::ApiClient::new(&format!("http://{}", addr)).unwrap();
+
+        let fonts: Vec<_> = [("a.ttf", vec![b'a'; 16]), ("big.ttf", vec![b'b'; 2048]), ("c.ttf", vec![b'c'; 16])]
+            .into_iter()
+            .map(|(name, data)| {
+                let path = local_dir.path().join(name);
+                std::fs::write(&path, &data).unwrap();
+                (path, name.to_string(), crate::utils::calculate_sha256(&local_dir.path().join(name)).unwrap())
+            })
+            .collect();
+        let response = api.upload_fonts(&fonts, None, false).await.expect("batch upload").expect("batch supported");
+
+        // 超出单个文件上限的字体被拒绝，不影响同一批中的其他字体
+        let statuses: Vec<_> = response.results.iter().map(|r| (r.filename.as_str(), r.status)).collect();
+        assert_eq!(statuses, [("a.ttf", 200), ("big.ttf", 413), ("c.ttf", 200)]);
+        assert_eq!(response.results[0].response.as_ref().map(|r| r.action.as_str()), Some("added"));
+        assert!(!server_dir.path().join("big.ttf").exists());
+
+        // 事件日志中仍是逐条的新增事件
+        let events = api.events(0, None).await.expect("events");
+        let added: Vec<_> = events
+            .events
+            .iter()
+            .filter_map(|record| match &record.event {
+                WebSocketMessage::FontAdded { filename, .. } => Some(filename.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(added, ["a.ttf", "c.ttf"]);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn non_ascii_names_round_trip() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let id = uuid::Uuid::new_v4();
+        let cjk_name = format!("思源黑体 #{}.ttf", id);
+        // macOS 常见的 NFD 文件名，服务器上保存为 NFC
+        let nfd_name = format!("\u{304B}\u{3099}{}.ttf", id);
+        let font = crate::font_metadata::tests::square_font();
+        tokio::fs::write(local_dir.path().join(&cjk_name), &font).await.expect("write font");
+        tokio::fs::write(local_dir.path().join(&nfd_name), &font).await.expect("write font");
+
+        let options = SyncOptions::default();
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("upload local fonts");
+        assert_eq!(uploaded, 2);
+        let nfc_name = format!("\u{304C}{}.ttf", id);
+        assert!(server_dir.path().join(&cjk_name).exists());
+        assert!(server_dir.path().join(&nfc_name).exists());
+
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let (downloaded, _) = client::download_server_fonts(&server_url, download_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("download server fonts");
+        assert_eq!(downloaded, 2);
+        assert_eq!(tokio::fs::read(download_dir.path().join(&cjk_name)).await.expect("read font"), font);
+        assert!(download_dir.path().join(&nfc_name).exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn encrypted_sync_round_trip() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let options = SyncOptions {
+            e2e_key: Some(TeamKey::generate().expect("team key")),
+            ..SyncOptions::default()
+        };
+        let plaintext = b"licensed font data";
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        tokio::fs::write(local_dir.path().join("secret.ttf"), plaintext)
+            .await
+            .expect("write font");
+        client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("upload local fonts");
+
+        // 服务器只保存密文，但记录明文哈希
+        let stored = std::fs::read(server_dir.path().join("secret.ttf")).expect("stored font");
+        assert!(crate::e2e::is_encrypted(&stored));
+        let listed = client::ApiClient::new(&server_url).unwrap().list_fonts(&Default::default())
+            .await
+            .expect("list server fonts");
+        let expected_sha256 = crate::utils::calculate_sha256(&local_dir.path().join("secret.ttf"))
+            .expect("local sha256");
+        assert_eq!(listed.fonts[0].content_sha256(), expected_sha256);
+
+        // 再次上传相同内容应被跳过
+        let (uploaded, _) =
+            client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+                .await
+                .expect("upload again");
+        assert_eq!(uploaded, 0);
+
+        // 没有密钥的客户端跳过加密字体
+        let plain_dir = tempfile::tempdir().expect("plain temp dir");
+        let (downloaded, _) = client::download_server_fonts(
+            &server_url,
+            plain_dir.path(),
+            &SyncOptions::default(),
+            &mut SyncReport::default(),
+        )
+        .await
+        .expect("download without key");
+        assert_eq!(downloaded, 0);
+
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let (downloaded, _) =
+            client::download_server_fonts(&server_url, download_dir.path(), &options, &mut SyncReport::default())
+                .await
+                .expect("download with key");
+        assert_eq!(downloaded, 1);
+        let restored = std::fs::read(download_dir.path().join("secret.ttf")).expect("restored font");
+        assert_eq!(restored, plaintext);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn signed_manifest_gates_downloads() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "signed.ttf", b"signed font").await;
+
+        let server_key = super::ServerSigner::load_or_create(server_dir.path())
+            .expect("signing key")
+            .public_key()
+            .expect("public key");
+        let trusted = SyncOptions {
+            require_signed: true,
+            trusted_signing_key: Some(server_key),
+            ..SyncOptions::default()
+        };
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let (downloaded, _) =
+            client::download_server_fonts(&server_url, download_dir.path(), &trusted, &mut SyncReport::default())
+                .await
+                .expect("download with trusted key");
+        assert_eq!(downloaded, 1);
+
+        // 清单签名公钥与预期不符时拒绝同步
+        let other_dir = tempfile::tempdir().expect("other temp dir");
+        let untrusted = SyncOptions {
+            trusted_signing_key: Some(
+                super::ServerSigner::load_or_create(other_dir.path())
+                    .expect("other key")
+                    .public_key()
+                    .expect("public key"),
+            ),
+            ..trusted
+        };
+        let result = client::download_server_fonts(
+            &server_url,
+            other_dir.path(),
+            &untrusted,
+            &mut SyncReport::default(),
+        )
+        .await;
+        assert!(result.is_err());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn restricted_fonts_are_tagged_or_refused() {
+        let restricted = crate::font_metadata::tests::sfnt_with_fs_type(0x0002);
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "restricted.ttf", &restricted).await;
+        let listed = client::ApiClient::new(&server_url).unwrap().list_fonts(&Default::default())
+            .await
+            .expect("list server fonts");
+        assert!(listed.fonts[0].restricted);
+        let _ = shutdown.send(());
+
+        let strict_dir = tempfile::tempdir().expect("strict temp dir");
+        let policy = super::ServerPolicy { refuse_restricted: true, ..Default::default() };
+        let (addr, shutdown) =
+            start_test_http_server_with_policy(strict_dir.path().to_path_buf(), policy).await;
+        let part = reqwest::multipart::Part::bytes(restricted).file_name("restricted.ttf");
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/fonts", addr))
+            .multipart(reqwest::multipart::Form::new().part("font", part))
+            .send()
+            .await
+            .expect("post font");
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(!strict_dir.path().join("restricted.ttf").exists());
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn serves_fonts_from_several_directories() {
+        let upload_dir = tempfile::tempdir().expect("upload temp dir");
+        let shared_dir = tempfile::tempdir().expect("shared temp dir");
+        std::fs::write(upload_dir.path().join("a.ttf"), b"uploaded a").unwrap();
+        std::fs::write(shared_dir.path().join("a.ttf"), b"shared a").unwrap();
+        std::fs::write(shared_dir.path().join("b.ttf"), b"shared b").unwrap();
+        // 共享目录排在前面，同名字体取共享目录中的文件
+        let font_dirs = vec![shared_dir.path().to_path_buf(), upload_dir.path().to_path_buf()];
+        let policy = super::ServerPolicy { font_dirs: Arc::new(font_dirs), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(upload_dir.path().to_path_buf(), policy).await;
+        let api = client::ApiClient::new(&format!("http://{}", addr)).expect("api client");
+
+        let listed = api.list_fonts_hashed(&Default::default()).await.expect("list");
+        let names: Vec<&str> = listed.fonts.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["a.ttf", "b.ttf"]);
+        assert_eq!(listed.fonts[0].sha256, crate::utils::calculate_sha256(&shared_dir.path().join("a.ttf")).unwrap());
+        assert_eq!(api.font_hashes(&[]).await.expect("hashes").fonts.len(), 2);
+        assert_eq!(&api.fetch_font("b.ttf").await.expect("download").0[..], b"shared b");
+
+        // 只读目录中的字体不能删除或替换，新字体写入上传目录
+        let error = api.delete_font("b.ttf", None).await.expect_err("read-only delete");
+        assert!(error.to_string().contains("read-only"), "{}", error);
+        assert!(shared_dir.path().join("b.ttf").exists());
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let local = local_dir.path().join("b.ttf");
+        std::fs::write(&local, b"changed b").unwrap();
+        api.upload_font(&local, "b.ttf", "00", None, true).await.expect_err("read-only upload");
+        api.upload_font(&local, "c.ttf", "00", None, false).await.expect("upload");
+        assert!(upload_dir.path().join("c.ttf").exists());
+        let _ = shutdown.send(());
+    }
+
+    #[test]
+    fn resolves_upload_and_read_only_directories() {
+        let shared_dir = tempfile::tempdir().expect("shared temp dir");
+        let shared = shared_dir.path().to_string_lossy().into_owned();
+        let dirs = ["./missing-fonts".to_string(), shared.clone()];
+
+        let (upload, font_dirs) = super::resolve_font_dirs(&dirs, None).unwrap();
+        assert_eq!(upload, "./missing-fonts");
+        assert_eq!(font_dirs, [PathBuf::from("./missing-fonts"), PathBuf::from(&shared)]);
+        assert_eq!(super::resolve_font_dirs(&dirs[..1], None).unwrap().1, Vec::<PathBuf>::new());
+        assert!(super::resolve_font_dirs(&dirs, Some("./other")).is_err());
+        // 上传目录以外的目录必须已存在
+        assert!(super::resolve_font_dirs(&dirs, Some(&shared)).is_err());
+        assert!(super::resolve_font_dirs(&[shared.clone(), shared], None).is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_uploads_are_rejected_with_limit() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { max_upload_size: 1024, ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let api = client::ApiClient::new(&format!("http://{}", addr)).expect("api client");
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let large = local_dir.path().join("large.ttc");
+        std::fs::write(&large, vec![0u8; 4096]).expect("write font");
+        let error = api.upload_font(&large, "large.ttc", "00", None, false).await.expect_err("too large");
+        assert!(error.to_string().contains("upload size limit"), "{}", error);
+        assert!(error.to_string().contains("1.00 KB"), "{}", error);
+
+        let small = local_dir.path().join("small.ttf");
+        std::fs::write(&small, b"small font").expect("write font");
+        api.upload_font(&small, "small.ttf", "00", None, false).await.expect("small upload");
+        assert!(server_dir.path().join("small.ttf").exists());
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn modified_fonts_transfer_as_deltas() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
+
+        let original: Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        post_font(&server_url, "variable.ttf", &original).await;
+        let base_sha256 = crate::utils::calculate_sha256(&server_dir.path().join("variable.ttf")).unwrap();
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let local = local_dir.path().join("variable.ttf");
+        let mut edited = original.clone();
+        edited[50_000..50_004].copy_from_slice(b"gvar");
+        std::fs::write(&local, &edited).unwrap();
+        let edited_sha256 = crate::utils::calculate_sha256(&local).unwrap();
+
+        let response = api
+            .upload_changed_font(&local, "variable.ttf", &edited_sha256, None, false, Some(&base_sha256))
+            .await
+            .expect("delta upload");
+        assert_eq!(response.action, "modified");
+        assert_eq!(std::fs::read(server_dir.path().join("variable.ttf")).unwrap(), edited);
+
+        // 服务器上已不是客户端所知的版本时按冲突拒绝，不覆盖
+        std::fs::write(&local, b"rewritten").unwrap();
+        let sha256 = crate::utils::calculate_sha256(&local).unwrap();
+        let error = api
+            .upload_changed_font(&local, "variable.ttf", &sha256, None, false, Some(&base_sha256))
+            .await
+            .expect_err("stale version");
+        assert!(client::is_version_conflict(&error), "{:#}", error);
+        let details = client::server_error(&error).unwrap().details.clone().unwrap();
+        assert_eq!(details["current_sha256"], edited_sha256.as_str());
+        assert_eq!(std::fs::read(server_dir.path().join("variable.ttf")).unwrap(), edited);
+
+        // 增量不划算时退回完整上传
+        api.upload_changed_font(&local, "variable.ttf", &sha256, None, false, Some(&edited_sha256))
+            .await
+            .expect("fallback upload");
+        assert_eq!(std::fs::read(server_dir.path().join("variable.ttf")).unwrap(), b"rewritten");
+
+        // 下载方向：服务器上的新版本相对本地旧版本只有少量变化
+        post_font(&server_url, "variable.ttf", &edited).await;
+        std::fs::write(&local, &original).unwrap();
+        let (rebuilt, _) = api.fetch_font_delta("variable.ttf", &original).await.expect("delta download");
+        assert_eq!(rebuilt, edited);
+        let signature = crate::delta::Signature::of(&original).encode();
+        let raw = reqwest::Client::new()
+            .post(format!("{}/fonts/variable.ttf/delta", server_url))
+            .body(signature)
+            .send()
+            .await
+            .expect("delta");
+        assert_eq!(raw.headers()[crate::api::DELTA_HEADER], "delta");
+        assert!(raw.bytes().await.unwrap().len() < edited.len() / 10);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn identical_uploads_share_one_blob() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "a.ttf", b"shared font").await;
+        post_font(&server_url, "copy.ttf", b"shared font").await;
+        let blobs_dir = server_dir.path().join(".fontsync/blobs");
+        assert_eq!(std::fs::read_dir(&blobs_dir).unwrap().count(), 1);
+
+        let sha256 = crate::utils::calculate_sha256(&server_dir.path().join("copy.ttf")).unwrap();
+        let response = reqwest::get(format!("{}/blobs/{}", server_url, sha256)).await.expect("blob");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"shared font");
+        let missing = reqwest::get(format!("{}/blobs/{}", server_url, "0".repeat(64))).await.expect("blob");
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // 删除一个名称后数据仍被另一个名称引用
+        let api = client::ApiClient::new(&server_url).unwrap();
+        api.delete_font("a.ttf", None).await.expect("delete");
+        let blobs = super::BlobStore::open(server_dir.path()).expect("blobs");
+        assert_eq!(blobs.collect_garbage().unwrap().removed, 0);
+        api.delete_font("copy.ttf", None).await.expect("delete");
+        let blobs = super::BlobStore::open(server_dir.path()).expect("blobs");
+        assert_eq!(blobs.collect_garbage().unwrap().removed, 1);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn pending_hashes_are_listed_lazily() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join("a.ttf"), b"font a").unwrap();
+        let store = crate::metadata_store::MetadataStore::open(dir.path()).expect("store");
+        store.mark_hash_pending(["a.ttf".to_string()]);
+
+        let lazy = super::list_fonts_impl(dir.path(), &store, &super::ServerPolicy::default(), true).await.expect("list");
+        assert!(lazy.fonts[0].is_pending());
+        assert!(serde_json::to_value(&lazy).unwrap()["fonts"][0]["sha256"].is_null());
+
+        // 需要完整哈希时直接计算，之后不再是待定状态
+        let full = super::list_fonts_impl(dir.path(), &store, &super::ServerPolicy::default(), false).await.expect("list");
+        assert_eq!(full.fonts[0].sha256, crate::utils::calculate_sha256(&dir.path().join("a.ttf")).unwrap());
+        assert!(!store.is_hash_pending("a.ttf"));
+    }
+
+    #[tokio::test]
+    async fn modification_times_survive_upload_and_download() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let original = 1_600_000_000;
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let font_path = local_dir.path().join("old.ttf");
+        std::fs::write(&font_path, b"old font").unwrap();
+        crate::utils::set_file_timestamp(&font_path, original).unwrap();
+
+        let api = client::ApiClient::new(&server_url).unwrap();
+        let response = api.upload_font(&font_path, "old.ttf", "", None, false).await.expect("upload");
+        assert_eq!(response.modified, Some(original));
+        assert_eq!(crate::utils::get_file_timestamp(&server_dir.path().join("old.ttf")).unwrap(), original);
+        let listed = api.list_fonts(&Default::default()).await.expect("list");
+        assert_eq!(listed.fonts[0].modified, Some(original));
+
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        client::download_server_fonts(&server_url, download_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+            .await
+            .expect("download");
+        assert_eq!(crate::utils::get_file_timestamp(&download_dir.path().join("old.ttf")).unwrap(), original);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn font_hashes_drive_download_preflight() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "a.ttf", b"font a").await;
+        post_font(&server_url, "b.ttf", b"font b").await;
+        let api = client::ApiClient::new(&server_url).unwrap();
+
+        let hashes = api.font_hashes(&[]).await.expect("hashes");
+        assert_eq!(hashes.fonts.len(), 2);
+        let expected = crate::utils::calculate_sha256(&server_dir.path().join("a.ttf")).unwrap();
+        assert_eq!(hashes.fonts["a.ttf"], expected);
+
+        let download_dir = tempfile::tempdir().expect("download temp dir");
+        let (downloaded, _) =
+            client::download_server_fonts(&server_url, download_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+                .await
+                .expect("download");
+        assert_eq!(downloaded, 2);
+        // 本地已一致时预检直接返回
+        let (downloaded, skipped) =
+            client::download_server_fonts(&server_url, download_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+                .await
+                .expect("preflight");
+        assert_eq!((downloaded, skipped), (0, 2));
+
+        post_font(&server_url, "b.ttf", b"font b v2").await;
+        let changed = api.font_hashes(&[]).await.expect("hashes");
+        assert_ne!(changed.digest, hashes.digest);
+        assert_eq!(changed.fonts["a.ttf"], expected);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn tagged_fonts_filter_listing_and_sync() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "brand.ttf", b"brand font").await;
+        post_font(&server_url, "other.ttf", b"other font").await;
+
+        let http = reqwest::Client::new();
+        let response = http
+            .put(format!("{}/fonts/brand.ttf/tags", server_url))
+            .json(&serde_json::json!({"tags": ["brand2024", " ui "]}))
+            .send()
+            .await
+            .expect("put tags");
+        assert!(response.status().is_success());
+        let response = http
+            .put(format!("{}/fonts/missing.ttf/tags", server_url))
+            .json(&serde_json::json!({"tags": ["brand2024"]}))
+            .send()
+            .await
+            .expect("put tags");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let tagged = client::ApiClient::new(&server_url).unwrap().list_fonts_tagged(&["brand2024".to_string()])
+            .await
+            .expect("list tagged fonts");
+        assert_eq!(tagged.fonts.len(), 1);
+        assert_eq!(tagged.fonts[0].tags.iter().collect::<Vec<_>>(), vec!["brand2024", "ui"]);
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let options = SyncOptions {
+            tags: vec!["brand2024".to_string()],
+            ..SyncOptions::default()
+        };
+        client::download_server_fonts(&server_url, local_dir.path(), &options, &mut SyncReport::default())
+            .await
+            .expect("download tagged fonts");
+        assert!(local_dir.path().join("brand.ttf").exists());
+        assert!(!local_dir.path().join("other.ttf").exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn font_list_supports_search_and_pagination() {
+        use crate::font_metadata::tests::{cmap_table, name_table, sfnt_with_tables};
+
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let noto = sfnt_with_tables(&[
+            (b"cmap", cmap_table(&[(0x0020, 0x017F), (0x4E2D, 0x4E2D)])),
+            (b"name", name_table(&[(1, "Noto Sans")])),
+        ]);
+        post_font(&server_url, "a-regular.ttf", &noto).await;
+        post_font(&server_url, "b.otf", b"an otf font, quite a bit longer").await;
+        post_font(&server_url, "c.woff2", b"woff2").await;
+
+        let by_family = client::ApiClient::new(&server_url).unwrap().list_fonts(
+            &crate::api::FontQuery { q: Some("noto".to_string()), ..Default::default() },
+        )
+        .await
+        .expect("search fonts");
+        assert_eq!(by_family.fonts.len(), 1);
+        assert_eq!(by_family.fonts[0].family.as_deref(), Some("Noto Sans"));
+        assert_eq!(by_family.fonts[0].unicode_ranges, vec!["Latin"]);
+
+        let covering = client::ApiClient::new(&server_url).unwrap().list_fonts(
+            &crate::api::FontQuery { covers: Some("U+4E2D".to_string()), ..Default::default() },
+        )
+        .await
+        .expect("filter by coverage");
+        let names: Vec<_> = covering.fonts.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["a-regular.ttf"]);
+
+        let formats = client::ApiClient::new(&server_url).unwrap().list_fonts(
+            &crate::api::FontQuery { format: Some("otf,.woff2".to_string()), min_size: Some(10), ..Default::default() },
+        )
+        .await
+        .expect("filter fonts");
+        let names: Vec<_> = formats.fonts.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["b.otf"]);
+
+        let page = client::ApiClient::new(&server_url).unwrap().list_fonts(
+            &crate::api::FontQuery {
+                sort: Some(crate::utils::FontSort::Size),
+                offset: Some(1),
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("page fonts");
+        assert_eq!(page.total, Some(3));
+        assert_eq!(page.fonts.len(), 1);
+        assert_eq!(page.fonts[0].name, "b.otf");
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn preview_is_rendered_and_cached() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "square.ttf", &crate::font_metadata::tests::square_font()).await;
+        post_font(&server_url, "broken.ttf", b"not a font").await;
+
+        let url = format!("{}/fonts/square.ttf/preview.png?text=AA&size=32", server_url);
+        for _ in 0..2 {
+            let response = reqwest::get(&url).await.expect("get preview");
+            assert_eq!(response.headers()["content-type"], "image/png");
+            let png = response.bytes().await.expect("preview body");
+            assert!(image::load_from_memory(&png).is_ok());
+        }
+        let cached = std::fs::read_dir(server_dir.path().join(".fontsync/previews"))
+            .expect("preview cache")
+            .count();
+        assert_eq!(cached, 1);
+
+        let response = reqwest::get(format!("{}/fonts/broken.ttf/preview.png", server_url))
+            .await
+            .expect("get preview");
+        assert_eq!(response.status(), reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn variable_fonts_are_instanced() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "flex.ttf", &crate::instancer::tests::variable_font()).await;
+        post_font(&server_url, "square.ttf", &crate::font_metadata::tests::square_font()).await;
+
+        let response = reqwest::get(format!("{}/fonts/flex.ttf/instance?instance=Black", server_url)).await.expect("get instance");
+        assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"Flex-Black.ttf\"");
+        let data = response.bytes().await.expect("instance body");
+        assert_eq!(crate::webfont::face_style(&data), Some((900, false)));
+
+        let status = |query: &'static str| {
+            let url = format!("{}/fonts/{}", server_url, query);
+            async move { reqwest::get(url).await.expect("get instance").status() }
+        };
+        assert_eq!(status("flex.ttf/instance?wght=700").await, reqwest::StatusCode::OK);
+        assert_eq!(status("flex.ttf/instance?wght=2000").await, reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(status("square.ttf/instance?wght=700").await, reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn subsets_are_generated_and_cached() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "square.ttf", &crate::font_metadata::tests::square_font()).await;
+
+        let url = format!("{}/fonts/square.ttf/subset?text=A&format=woff2", server_url);
+        for _ in 0..2 {
+            let response = reqwest::get(&url).await.expect("get subset");
+            assert_eq!(response.headers()["content-type"], "font/woff2");
+            assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"square.subset.woff2\"");
+            assert_eq!(&response.bytes().await.expect("subset body")[0..4], b"wOF2");
+        }
+        let cached = std::fs::read_dir(server_dir.path().join(".fontsync/subsets")).expect("subset cache").count();
+        assert_eq!(cached, 1);
+
+        let status = |query: &'static str| {
+            let url = format!("{}/fonts/square.ttf/subset?{}", server_url, query);
+            async move { reqwest::get(url).await.expect("get subset").status() }
+        };
+        assert_eq!(status("unicodes=U%2B0041").await, reqwest::StatusCode::OK);
+        assert_eq!(status("format=woff").await, reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(status("text=Z").await, reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn webfont_stylesheet_references_cached_woff() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "square.ttf", &crate::font_metadata::tests::square_font()).await;
+
+        let css = reqwest::get(format!("{}/webfonts/square.css", server_url))
+            .await
+            .expect("get stylesheet")
+            .text()
+            .await
+            .expect("stylesheet body");
+        assert!(css.contains("font-family: \"Square\";"));
+        assert!(css.contains("font-weight: 400;"));
+        let url = css
+            .split("url(\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("font url");
+        assert!(url.starts_with("/webfonts/files/square%2Ettf%2Ewoff?v="));
+
+        let response = reqwest::get(format!("{}{}", server_url, url)).await.expect("get woff");
+        assert_eq!(response.headers()["content-type"], "font/woff");
+        assert!(response.headers()["cache-control"].to_str().unwrap().contains("immutable"));
+        assert!(response.bytes().await.expect("woff body").starts_with(b"wOFF"));
+        assert!(server_dir.path().join(".fontsync/webfonts").read_dir().expect("woff cache").next().is_some());
+
+        // WOFF2 排在 WOFF 之前，不支持 WOFF2 的浏览器使用后者
+        let css = reqwest::get(format!("{}/webfonts/square.css?format=woff2", server_url))
+            .await
+            .expect("get woff2 stylesheet")
+            .text()
+            .await
+            .expect("stylesheet body");
+        let urls: Vec<&str> = css.split("url(\"").skip(1).filter_map(|rest| rest.split('"').next()).collect();
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].starts_with("/webfonts/files/square%2Ettf%2Ewoff2?v="));
+        assert!(urls[1].starts_with("/webfonts/files/square%2Ettf%2Ewoff?v="));
+        assert!(css.contains("format(\"woff2\"), url("));
+        let response = reqwest::get(format!("{}{}", server_url, urls[0])).await.expect("get woff2");
+        assert_eq!(response.headers()["content-type"], "font/woff2");
+        assert!(response.bytes().await.expect("woff2 body").starts_with(b"wOF2"));
+
+        let kit = reqwest::get(format!("{}/webfonts/Square.zip", server_url)).await.expect("get kit");
+        assert_eq!(kit.headers()["content-type"], "application/zip");
+        assert!(kit.bytes().await.expect("kit body").starts_with(b"PK\x03\x04"));
+
+        let missing = reqwest::get(format!("{}/webfonts/Missing.css", server_url)).await.expect("get missing");
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn deleted_fonts_can_be_restored_from_trash() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "gone.ttf", b"deleted font data").await;
+
+        let http = reqwest::Client::new();
+        let response = http
+            .delete(format!("{}/fonts/gone.ttf", server_url))
+            .send()
+            .await
+            .expect("delete font");
+        assert!(response.status().is_success());
+        assert!(!server_dir.path().join("gone.ttf").exists());
+
+        let trash: serde_json::Value = reqwest::get(format!("{}/trash", server_url))
+            .await
+            .expect("list trash")
+            .json()
+            .await
+            .expect("trash json");
+        assert_eq!(trash["fonts"][0]["name"], "gone.ttf");
+
+        let response = http
+            .post(format!("{}/trash/gone.ttf/restore", server_url))
+            .send()
+            .await
+            .expect("restore font");
+        assert!(response.status().is_success());
+        assert_eq!(
+            std::fs::read(server_dir.path().join("gone.ttf")).expect("restored font"),
+            b"deleted font data"
+        );
+        let response = http
+            .post(format!("{}/trash/gone.ttf/restore", server_url))
+            .send()
+            .await
+            .expect("restore again");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let spec: serde_json::Value = reqwest::get(format!("{}/openapi.json", server_url))
+            .await
+            .expect("get openapi")
+            .json()
+            .await
+            .expect("openapi json");
+        assert!(spec["paths"]["/trash/{name}/restore"]["post"].is_object());
+
+        for path in ["ui", "ui/"] {
+            let response = reqwest::get(format!("{}/{}", server_url, path)).await.expect("get ui");
+            assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+        }
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn deleted_fonts_are_only_readded_explicitly() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = crate::client::ApiClient::new(&server_url).expect("api client");
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let local = local_dir.path().join("gone.ttf");
+        std::fs::write(&local, b"deleted font data").expect("write font");
+        let sha256 = crate::utils::calculate_sha256(&local).expect("sha256");
+        api.upload_font(&local, "gone.ttf", &sha256, None, false).await.expect("upload");
+        let deleted = api.delete_font("gone.ttf", None).await.expect("delete");
+        assert_eq!(deleted.sha256.as_deref(), Some(sha256.as_str()));
+
+        let tombstones = api.tombstones().await.expect("tombstones").tombstones;
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!((tombstones[0].name.as_str(), tombstones[0].tombstone.sha256.as_str()), ("gone.ttf", sha256.as_str()));
+        let events = api.events(0, None).await.expect("events").events;
+        assert!(matches!(
+            &events.last().expect("removal event").event,
+            WebSocketMessage::FontRemoved { sha256: Some(removed), .. } if *removed == sha256
+        ));
+
+        // 同步客户端重新上传相同内容会被拒绝
+        let error = api.upload_font(&local, "gone.ttf", &sha256, None, false).await.expect_err("resurrected");
+        assert!(error.to_string().contains("Font deleted"));
+        assert!(!server_dir.path().join("gone.ttf").exists());
+
+        // 显式重新添加后删除记录被清除
+        api.upload_font(&local, "gone.ttf", &sha256, None, true).await.expect("readd");
+        assert!(server_dir.path().join("gone.ttf").exists());
+        assert!(api.tombstones().await.expect("tombstones").tombstones.is_empty());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn blocklisted_fonts_are_refused() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { admin_token: Some("secret".to_string()), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let http = reqwest::Client::new();
+        let rules_url = format!("http://{}/admin/blocklist", addr);
+        let rule = serde_json::json!({ "pattern": "*pirated*", "reason": "Unlicensed copy" });
+
+        let response = http.post(&rules_url).json(&rule).send().await.expect("unauthorized");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = http.post(&rules_url).bearer_auth("secret").json(&rule).send().await.expect("add rule");
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let entry: crate::api::BlockEntry = response.json().await.expect("rule");
+
+        let part = reqwest::multipart::Part::bytes(b"font".to_vec()).file_name("Pirated-Bold.ttf");
+        let response = http
+            .post(format!("http://{}/fonts", addr))
+            .multipart(reqwest::multipart::Form::new().part("font", part))
+            .send()
+            .await
+            .expect("post font");
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        let error: crate::api::ErrorResponse = response.json().await.expect("error body");
+        assert!(error.message.unwrap_or_default().contains("Unlicensed copy"));
+        assert!(!server_dir.path().join("Pirated-Bold.ttf").exists());
+
+        let response = http
+            .delete(format!("{}/{}", rules_url, entry.id))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .expect("remove rule");
+        assert!(response.status().is_success());
+        post_font(&format!("http://{}", addr), "Pirated-Bold.ttf", b"font").await;
+        assert!(server_dir.path().join("Pirated-Bold.ttf").exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode_blocks_mutations() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("kept.ttf"), b"kept font").unwrap();
+        let policy = super::ServerPolicy {
+            admin_token: Some("secret".to_string()),
+            maintenance: Arc::new(parking_lot::RwLock::new(Some("Backup in progress".to_string()))),
+            ..Default::default()
+        };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
+
+        // 下载不受影响
+        assert_eq!(api.fetch_font("kept.ttf").await.expect("download").0.as_ref(), b"kept font");
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let local = local_dir.path().join("new.ttf");
+        std::fs::write(&local, b"new font").unwrap();
+        let sha256 = crate::utils::calculate_sha256(&local).unwrap();
+        let error = api.upload_font(&local, "new.ttf", &sha256, None, false).await.unwrap_err();
+        let server_error = client::server_error(&error).expect("typed error");
+        assert_eq!(server_error.code, crate::api::ErrorCode::Maintenance);
+        assert!(server_error.retryable && server_error.details.is_some());
+        assert!(crate::offline_queue::is_unreachable(&error));
+        assert!(format!("{:#}", error).contains("Backup in progress"));
+        let response = reqwest::Client::new()
+            .delete(format!("{}/fonts/kept.ttf", server_url))
+            .send()
+            .await
+            .expect("delete");
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key("retry-after"));
+        assert!(server_dir.path().join("kept.ttf").exists());
+
+        // 管理接口关闭维护模式后恢复写入
+        let response = reqwest::Client::new()
+            .put(format!("{}/admin/maintenance", server_url))
+            .bearer_auth("secret")
+            .json(&crate::api::MaintenanceStatus { enabled: false, message: None })
+            .send()
+            .await
+            .expect("toggle");
+        let status: crate::api::MaintenanceStatus = response.json().await.expect("status");
+        assert!(!status.enabled);
+        api.upload_font(&local, "new.ttf", &sha256, None, false).await.expect("upload");
+        assert!(server_dir.path().join("new.ttf").exists());
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn token_roles_gate_routes() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("kept.ttf"), b"kept font").unwrap();
+        let tokens = crate::access::AccessTokens::new(vec![
+            crate::access::TokenEntry { token: "read".to_string(), role: crate::access::Role::Reader, name: None, quota: None },
+            crate::access::TokenEntry { token: "up".to_string(), role: crate::access::Role::Uploader, name: None, quota: None },
+            crate::access::TokenEntry { token: "root".to_string(), role: crate::access::Role::Admin, name: None, quota: None },
+        ])
+        .unwrap();
+        let policy = super::ServerPolicy { tokens: Arc::new(tokens), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let http = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+        let upload = |token: &'static str| {
+            let part = reqwest::multipart::Part::bytes(b"new font".to_vec()).file_name("new.ttf");
+            http.post(url("/fonts")).bearer_auth(token).multipart(reqwest::multipart::Form::new().part("font", part))
+        };
+
+        let response = http.get(url("/fonts")).send().await.expect("anonymous list");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = http.get(url("/fonts")).bearer_auth("wrong").send().await.expect("invalid token");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = http.get(url("/fonts/kept.ttf")).bearer_auth("read").send().await.expect("download");
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"kept font");
+
+        // 读取者不能上传，上传者不能删除
+        assert_eq!(upload("read").send().await.expect("reader upload").status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(upload("up").send().await.expect("uploader upload").status().is_success());
+        let response = http.delete(url("/fonts/new.ttf")).bearer_auth("up").send().await.expect("uploader delete");
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        let response = http.get(url("/admin/blocklist")).bearer_auth("up").send().await.expect("uploader admin");
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        let response = http.delete(url("/fonts/new.ttf")).bearer_auth("root").send().await.expect("admin delete");
+        assert!(response.status().is_success());
+        let response = http.get(url("/admin/blocklist")).bearer_auth("root").send().await.expect("admin api");
+        assert!(response.status().is_success());
+
+        // 未知路径仍返回 404，WebSocket 握手同样需要令牌
+        let response = http.get(url("/missing")).send().await.expect("missing");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = http
+            .get(url("/ws"))
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .send()
+            .await
+            .expect("anonymous handshake");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn uploads_wait_for_approval() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let pending = Arc::new(super::PendingUploads::open(server_dir.path()).expect("pending"));
+        let policy = super::ServerPolicy {
+            admin_token: Some("secret".to_string()),
+            moderation: Some(pending),
+            ..Default::default()
+        };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
+        let http = reqwest::Client::new();
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        for name in ["good.ttf", "bad.ttf"] {
+            std::fs::write(local_dir.path().join(name), format!("{} data", name)).unwrap();
+        }
+        client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+            .await
+            .expect("upload");
+        assert!(!server_dir.path().join("good.ttf").exists());
+
+        // 列表中标记为等待审核，同步时忽略
+        let listed = api.list_fonts(&Default::default()).await.expect("list");
+        assert_eq!(listed.fonts.len(), 2);
+        assert!(listed.fonts.iter().all(|f| f.is_awaiting_approval()));
+        assert!(api.list_fonts_hashed(&Default::default()).await.expect("hashed").fonts.is_empty());
+        assert_eq!(api.events(0, None).await.expect("events").events.len(), 0);
+
+        let admin = |request: reqwest::RequestBuilder| request.bearer_auth("secret").send();
+        let list: crate::api::PendingList =
+            admin(http.get(format!("{}/admin/pending", server_url))).await.unwrap().json().await.unwrap();
+        assert_eq!(list.uploads.len(), 2);
+
+        let response = admin(http.post(format!("{}/admin/pending/good.ttf/approve", server_url))).await.unwrap();
+        let approved: crate::api::FontActionResponse = response.json().await.expect("approved");
+        assert_eq!(approved.action, "added");
+        assert_eq!(std::fs::read(server_dir.path().join("good.ttf")).unwrap(), b"good.ttf data");
+        let response = admin(http.delete(format!("{}/admin/pending/bad.ttf", server_url))).await.unwrap();
+        assert!(response.status().is_success());
+        let response = admin(http.delete(format!("{}/admin/pending/bad.ttf", server_url))).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // 批准后按普通上传记录事件
+        let events = api.events(0, None).await.expect("events");
+        assert_eq!(events.events.len(), 1);
+        let listed = api.list_fonts(&Default::default()).await.expect("list");
+        assert_eq!(listed.fonts.len(), 1);
+        assert!(!listed.fonts[0].is_awaiting_approval());
+        assert!(!server_dir.path().join("bad.ttf").exists());
+
+        // 提交后存储的版本被替换时拒绝批准，提交保留在队列中
+        post_font(&server_url, "late.ttf", b"submitted").await;
+        assert!(!server_dir.path().join("late.ttf").exists());
+        std::fs::write(server_dir.path().join("late.ttf"), b"stored meanwhile").unwrap();
+        let response = admin(http.post(format!("{}/admin/pending/late.ttf/approve", server_url))).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+        assert_eq!(std::fs::read(server_dir.path().join("late.ttf")).unwrap(), b"stored meanwhile");
+        let list: crate::api::PendingList =
+            admin(http.get(format!("{}/admin/pending", server_url))).await.unwrap().json().await.unwrap();
+        assert_eq!(list.uploads.iter().map(|u| u.name.as_str()).collect::<Vec<_>>(), ["late.ttf"]);
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn quotas_limit_uploads_per_client() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { client_quota: Some(20), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let api = client::ApiClient::new(&format!("http://{}", addr)).unwrap();
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let upload = |name: &'static str, data: &'static [u8], replaces: Option<String>| {
+            let path = local_dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            let sha256 = crate::utils::calculate_sha256(&path).unwrap();
+            let api = &api;
+            async move { api.upload_changed_font(&path, name, &sha256, None, false, replaces.as_deref()).await }
+        };
+        let first = upload("a.ttf", b"twelve bytes", None).await.expect("first upload");
+        // 替换自己上传的同名字体时旧文件不计入
+        upload("a.ttf", b"twelve Bytes", first.sha256).await.expect("replacement");
+        let error = upload("b.ttf", b"twelve bytes", None).await.expect_err("over quota");
+        assert!(error.to_string().contains("quota"), "{}", error);
+        assert!(!server_dir.path().join("b.ttf").exists());
+
+        // 列表与事件记录上传者
+        let identity = crate::identity::ClientIdentity::current();
+        let listed = api.list_fonts(&Default::default()).await.expect("list");
+        let uploaded_by = listed.fonts[0].uploaded_by.clone().expect("uploader");
+        assert_eq!(uploaded_by.client_id.as_deref(), Some(identity.client_id.as_str()));
+        let events = api.events(0, None).await.expect("events");
+        assert_eq!(events.events[0].actor.as_ref(), Some(&uploaded_by));
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_returned_and_recorded_with_events() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("old.ttf"), b"old font").unwrap();
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let http = reqwest::Client::new();
+
+        let response = http.get(format!("http://{}/fonts", addr)).send().await.expect("list");
+        let generated = response.headers()[crate::request_log::REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        assert_eq!(generated.len(), 32);
+
+        // 沿用调用方传入的 ID，并写入该请求产生的事件
+        let response = http
+            .delete(format!("http://{}/fonts/old.ttf", addr))
+            .header(crate::request_log::REQUEST_ID_HEADER, "trace-1")
+            .send()
+            .await
+            .expect("delete");
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()[crate::request_log::REQUEST_ID_HEADER], "trace-1");
+        let api = client::ApiClient::new(&format!("http://{}", addr)).unwrap();
+        let events = api.events(0, None).await.expect("events");
+        assert_eq!(events.events.len(), 1);
+        assert_eq!(events.events[0].request_id.as_deref(), Some("trace-1"));
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn stats_summarize_fonts_and_uploads() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("big.otf"), vec![0u8; 64]).unwrap();
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        for name in ["a.ttf", "b.TTF"] {
+            std::fs::write(local_dir.path().join(name), name).unwrap();
+        }
+        client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+            .await
+            .expect("upload");
+
+        let stats = api.stats().await.expect("stats");
+        assert_eq!(stats.total_fonts, 3);
+        assert_eq!(stats.total_bytes, 64 + 5 + 5);
+        assert_eq!(stats.formats.get("ttf"), Some(&2));
+        assert_eq!(stats.formats.get("otf"), Some(&1));
+        assert_eq!(stats.largest[0].name, "big.otf");
+        assert_eq!(stats.recent_activity.len(), 2);
+        let client_id = &crate::identity::ClientIdentity::current().client_id;
+        assert_eq!(stats.uploads_by_client.get(client_id), Some(&2));
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn integrity_scrub_reports_mismatches() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { admin_token: Some("secret".to_string()), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let server_url = format!("http://{}", addr);
+        let http = reqwest::Client::new();
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        std::fs::write(local_dir.path().join("a.ttf"), b"font a").unwrap();
+        client::upload_local_fonts(&server_url, local_dir.path(), &SyncOptions::default(), &mut SyncReport::default())
+            .await
+            .expect("upload");
+        std::fs::write(server_dir.path().join("a.ttf"), b"font b").unwrap();
+
+        let response = http.post(format!("{}/admin/integrity", server_url)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let report: crate::api::IntegrityReport = http
+            .post(format!("{}/admin/integrity", server_url))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .expect("report");
+        assert!(report.issues.iter().any(|i| i.file == "a.ttf" && !i.repaired));
+
+        let status: crate::api::IntegrityStatus = http
+            .get(format!("{}/admin/integrity", server_url))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .expect("status");
+        assert_eq!(status.last_report.map(|r| r.unrepaired()), Some(report.unrepaired()));
+        let metrics = http.get(format!("{}/metrics", server_url)).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains(&format!("fontsync_integrity_mismatches {}", report.issues.len())));
+
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn cancelled_sync_starts_no_transfers() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        std::fs::write(server_dir.path().join("remote.ttf"), b"remote font").unwrap();
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        std::fs::write(local_dir.path().join("local.ttf"), b"local font").unwrap();
+        let options = SyncOptions::default();
+        options.cancel.cancel();
+        let mut report = SyncReport::default();
+        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut report)
             .await
-            .context("Failed to create font directory")?;
-        info!("Created font directory: {}", font_dir);
+            .expect("upload");
+        let (downloaded, _) = client::download_server_fonts(&server_url, local_dir.path(), &options, &mut report)
+            .await
+            .expect("download");
+
+        assert_eq!((uploaded, downloaded), (0, 0));
+        assert!(report.files.is_empty());
+        assert!(!server_dir.path().join("local.ttf").exists());
+        assert!(!local_dir.path().join("remote.ttf").exists());
+
+        let _ = shutdown.send(());
     }
 
-    let font_dir_arc = Arc::new(font_dir_path);
-    let ws_server_data = if ws_enabled {
-        let ws_addr: SocketAddr = format!("{}:{}", host, port + 1).parse()
-            .context("Failed to parse WebSocket address")?;
-        let ws_server = Arc::new(WebSocketServer::new(ws_addr));
-        Some((ws_server, ws_addr))
-    } else {
-        None
-    };
+    #[tokio::test]
+    async fn event_stream_delivers_and_resumes_events() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
 
-    // 路由
-    let font_dir_filter = warp::any().map(move || Arc::clone(&font_dir_arc));
-    let ws_server_opt = ws_server_data.as_ref().map(|(server, _)| Arc::clone(server));
-    let ws_server_filter = warp::any().map(move || ws_server_opt.clone());
+        // 读取流中的下一个事件
+        async fn next_event(response: &mut reqwest::Response, parser: &mut crate::sse::SseParser) -> crate::sse::SseEvent {
+            loop {
+                let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), response.chunk())
+                    .await
+                    .expect("event timeout")
+                    .expect("read chunk")
+                    .expect("stream open");
+                if let Some(event) = parser.push(&chunk).into_iter().next() {
+                    return event;
+                }
+            }
+        }
 
-    let list_fonts = warp::path!("fonts")
-        .and(warp::get())
-        .and(font_dir_filter.clone())
-        .and_then(list_fonts_handler);
+        let mut live = api.event_stream(None).await.expect("open stream");
+        post_font(&server_url, "first.ttf", b"first font").await;
+        post_font(&server_url, "second.ttf", b"second font").await;
 
-    let download_font = warp::path!("fonts" / String)
-        .and(warp::get())
-        .and(font_dir_filter.clone())
-        .and_then(download_font_handler);
+        let mut parser = crate::sse::SseParser::default();
+        let event = next_event(&mut live, &mut parser).await;
+        assert_eq!(event.id.as_deref(), Some("1"));
+        let message: WebSocketMessage = serde_json::from_str(&event.data).expect("event json");
+        assert!(matches!(message, WebSocketMessage::FontAdded { ref filename, seq: Some(1), .. } if filename == "first.ttf"));
 
-    let upload_font = warp::path!("fonts")
-        .and(warp::post())
-        .and(warp::multipart::form().max_length(100 * 1024 * 1024)) // 100MB 限制
-        .and(font_dir_filter.clone())
-        .and(ws_server_filter.clone())
-        .and_then(upload_font_handler);
+        // 从 Last-Event-ID 续传时只回放之后的事件
+        let mut resumed = api.event_stream(Some(1)).await.expect("resume stream");
+        let event = next_event(&mut resumed, &mut crate::sse::SseParser::default()).await;
+        assert_eq!(event.id.as_deref(), Some("2"));
 
-    let get_sha256 = warp::path!("fonts" / String / "sha256")
-        .and(warp::get())
-        .and(font_dir_filter.clone())
-        .and_then(get_sha256_handler);
-    
-    let routes = list_fonts
-        .or(download_font)
-        .or(upload_font)
-        .or(get_sha256)
-        .with(warp::cors().allow_any_origin())
-        .with(warp::log("fontsync::server"));
+        let _ = shutdown.send(());
+    }
 
-    let addr: std::net::SocketAddr = format!("{}:{}", host, port)
-        .parse()
-        .context("Failed to parse socket address")?;
+    #[tokio::test]
+    async fn upload_is_recorded_in_event_log() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
 
-    let shutdown = async {
-        let _ = tokio::signal::ctrl_c().await;
-    };
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        tokio::fs::write(local_dir.path().join("event.ttf"), b"event font data")
+            .await
+            .expect("write font");
+        client::upload_local_fonts(
+            &server_url,
+            local_dir.path(),
+            &SyncOptions::default(),
+            &mut SyncReport::default(),
+        )
+            .await
+            .expect("upload local fonts");
 
-    let (bound_addr, server) = warp::serve(routes)
-        .try_bind_with_graceful_shutdown(addr, shutdown)
-        .map_err(|e| anyhow::anyhow!("Failed to bind HTTP server: {}", e))?;
+        let page = client::ApiClient::new(&server_url).unwrap().events(0, None)
+            .await
+            .expect("list events");
+        assert_eq!(page.latest_seq, 1);
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].seq, 1);
 
-    info!("HTTP server listening on http://{}", bound_addr);
+        let empty = client::ApiClient::new(&server_url).unwrap().events(1, None)
+            .await
+            .expect("list events since latest");
+        assert!(empty.events.is_empty());
 
-    if let Some((ws_server, ws_addr)) = ws_server_data {
-        let ws_server_clone = Arc::clone(&ws_server);
-        tokio::spawn(async move {
-            if let Err(e) = ws_server_clone.start().await {
-                error!("WebSocket server error: {}", e);
-            }
-        });
-        info!("WebSocket server listening on ws://{}", ws_addr);
+        // 重新打开日志后序号应保持连续
+        let reopened = EventLog::open(server_dir.path()).expect("reopen event log");
+        assert_eq!(reopened.since(0, None).latest_seq, 1);
+
+        let _ = shutdown.send(());
     }
 
-    server.await;
+    #[tokio::test]
+    async fn overwrite_upload_emits_font_modified() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
 
-    Ok(())
-}
+        post_font(&server_url, "overwrite.ttf", b"first version").await;
+        post_font(&server_url, "overwrite.ttf", b"second version!").await;
+        post_font(&server_url, "overwrite.ttf", b"second version!").await;
 
-pub async fn start_server_with_websocket(host: String, port: u16, font_dir: String, ws_enabled: bool) -> Result<()> {
-    start_server(host, port, font_dir, ws_enabled).await
-}
+        let page = client::ApiClient::new(&server_url).unwrap().events(0, None)
+            .await
+            .expect("list events");
+        assert_eq!(page.events.len(), 2, "identical re-upload should not emit an event");
+        assert!(matches!(
+            &page.events[0].event,
+            WebSocketMessage::FontAdded { size: 13, .. }
+        ));
+        assert!(matches!(
+            &page.events[1].event,
+            WebSocketMessage::FontModified { size: 15, .. }
+        ));
 
-async fn list_fonts_handler(
-    font_dir: Arc<PathBuf>,
-) -> Result<Box<dyn Reply>, Rejection> {
-    match list_fonts_impl(&font_dir).await {
-        Ok(font_list) => Ok(Box::new(warp::reply::json(&font_list))),
-        Err(e) => {
-            error!("Failed to list fonts: {}", e);
-            Ok(Box::new(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({"error": e.to_string()})),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )))
-        }
+        let _ = shutdown.send(());
     }
-}
-
-async fn list_fonts_impl(font_dir: &Path) -> Result<FontList> {
-    let mut fonts = Vec::new();
 
-    if !font_dir.exists() {
-        return Ok(FontList { fonts });
-    }
+    #[tokio::test]
+    async fn replacing_a_font_requires_overwrite() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let api = client::ApiClient::new(&server_url).unwrap();
 
-    let entries = fs::read_dir(font_dir).context("Failed to read font directory")?;
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        let local = local_dir.path().join("clobber.ttf");
+        std::fs::write(&local, b"first version").unwrap();
+        let first_sha256 = crate::utils::calculate_sha256(&local).unwrap();
+        api.upload_font(&local, "clobber.ttf", &first_sha256, None, false).await.expect("first upload");
+        // 相同内容照常按未变化处理
+        let response = api.upload_font(&local, "clobber.ttf", &first_sha256, None, false).await.expect("same content");
+        assert_eq!(response.action, "unchanged");
 
-    for entry in entries {
-        let entry = entry.context("Failed to read directory entry")?;
-        let path = entry.path();
+        std::fs::write(&local, b"second version").unwrap();
+        let sha256 = crate::utils::calculate_sha256(&local).unwrap();
+        let error = api.upload_font(&local, "clobber.ttf", &sha256, None, false).await.expect_err("clobbered");
+        assert!(client::is_version_conflict(&error), "{:#}", error);
+        let details = client::server_error(&error).unwrap().details.clone().unwrap();
+        assert_eq!(details["current_sha256"], first_sha256.as_str());
+        assert_eq!(std::fs::read(server_dir.path().join("clobber.ttf")).unwrap(), b"first version");
 
-        if path.is_file() && is_font_file(&path) {
-            let metadata = fs::metadata(&path).context("Failed to get file metadata")?;
-            let name = path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown")
-                .to_string();
+        post_font(&server_url, "clobber.ttf", b"second version").await;
+        assert_eq!(std::fs::read(server_dir.path().join("clobber.ttf")).unwrap(), b"second version");
 
-            let mime_type = get_font_mime_type(&path);
+        let _ = shutdown.send(());
+    }
 
-            let sha256 = calculate_sha256(&path)
-                .unwrap_or_else(|e| {
-                    error!("Failed to calculate SHA256 for {:?}: {}", path, e);
-                    String::new()
-                });
+    #[test]
+    fn if_match_headers_are_parsed() {
+        let a = "ab".repeat(32);
+        let b = "CD".repeat(32);
+        assert_eq!(super::parse_if_match(" * "), Some(Vec::new()));
+        assert_eq!(super::parse_if_match(&a), Some(vec![a.clone()]));
+        // 弱校验前缀与大写哈希
+        assert_eq!(super::parse_if_match(&format!("W/\"{}\"", b)), Some(vec![b.to_lowercase()]));
+        assert_eq!(
+            super::parse_if_match(&format!("\"{}\", W/\"{}\"", a, b)),
+            Some(vec![a.clone(), b.to_lowercase()])
+        );
+        assert_eq!(super::parse_if_match("\"abc\""), None);
+        assert_eq!(super::parse_if_match(&format!("\"{}\", *", a)), None);
+        assert_eq!(super::parse_if_match(""), None);
 
-            fonts.push(FontInfo {
-                name,
-                size: metadata.len(),
-                mime_type,
-                sha256,
-            });
-        }
+        let current = vec![a.clone()];
+        assert!(super::if_match_satisfied(&[], &current));
+        assert!(!super::if_match_satisfied(&[], &[]));
+        assert!(super::if_match_satisfied(&[b.to_lowercase(), a.clone()], &current));
+        assert!(!super::if_match_satisfied(&[b.to_lowercase()], &current));
     }
 
-    Ok(FontList { fonts })
-}
+    #[tokio::test]
+    async fn if_match_guards_upload_delete_and_rename() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        let http = reqwest::Client::new();
+        post_font(&server_url, "a.ttf", b"first version").await;
+        let current = crate::utils::calculate_sha256(&server_dir.path().join("a.ttf")).unwrap();
+        let stale = format!("\"{}\"", "0".repeat(64));
 
-async fn download_font_handler(
-    filename: String,
-    font_dir: Arc<PathBuf>,
-) -> Result<Box<dyn Reply>, Rejection> {
-    let font_path = font_dir.join(&filename);
+        let upload = |if_match: String, data: &'static [u8]| {
+            let part = reqwest::multipart::Part::bytes(data).file_name("a.ttf");
+            http.post(format!("{}/fonts", server_url))
+                .header("if-match", if_match)
+                .multipart(reqwest::multipart::Form::new().part("font", part))
+                .send()
+        };
+        let assert_mismatch = |response: reqwest::Response| async move {
+            assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["code"], "precondition_failed");
+            body["details"]["current_sha256"].as_str().unwrap().to_string()
+        };
 
-    if !font_path.exists() {
-        return Ok(Box::new(warp::reply::with_status(
-            format!("Font '{}' not found", filename),
-            StatusCode::NOT_FOUND,
-        )));
-    }
+        let response = upload("not-a-hash".to_string(), b"second version").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(assert_mismatch(upload(stale.clone(), b"second version").await.unwrap()).await, current);
+        assert_eq!(std::fs::read(server_dir.path().join("a.ttf")).unwrap(), b"first version");
+        // * 只要求字体已存在
+        let response = http
+            .post(format!("{}/fonts", server_url))
+            .header("if-match", "*")
+            .multipart(reqwest::multipart::Form::new().part("font", reqwest::multipart::Part::bytes(b"new".to_vec()).file_name("new.ttf")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        let response = upload(format!("\"{}\", W/\"{}\"", "0".repeat(64), current), b"second version").await.unwrap();
+        assert!(response.status().is_success());
+        let current = crate::utils::calculate_sha256(&server_dir.path().join("a.ttf")).unwrap();
 
-    match File::open(&font_path).await {
-        Ok(file) => {
-            // 获取文件大小用于 Content-Length
-            let metadata = match tokio::fs::metadata(&font_path).await {
-                Ok(m) => m,
-                Err(_) => return Ok(Box::new(warp::reply::with_status(
-                    format!("Failed to get metadata for font '{}'", filename),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                ))),
-            };
-            
-            // 确定内容类型
-            let content_type = get_font_mime_type(&font_path);
+        let rename = |if_match: String| {
+            http.post(format!("{}/fonts/a.ttf/rename", server_url))
+                .header("if-match", if_match)
+                .json(&serde_json::json!({ "to": "b.ttf" }))
+                .send()
+        };
+        let response = rename("W/".to_string()).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(assert_mismatch(rename(stale.clone()).await.unwrap()).await, current);
+        assert!(server_dir.path().join("a.ttf").exists());
+        assert!(rename(format!("\"{}\"", current)).await.unwrap().status().is_success());
 
-            let stream = tokio_util::io::ReaderStream::new(file);
-            let body = warp::hyper::Body::wrap_stream(stream);
-            
-            let mut response = warp::reply::Response::new(body);
-            response.headers_mut().insert(
-                "Content-Type",
-                content_type.parse().unwrap_or_else(|_| "application/octet-stream".parse().unwrap()),
-            );
-            response.headers_mut().insert(
-                "Content-Disposition",
-                format!("attachment; filename=\"{}\"", filename)
-                    .parse()
-                    .unwrap(),
-            );
-            response.headers_mut().insert(
-                "Content-Length",
-                metadata.len().to_string().parse().unwrap(),
-            );
+        let api = client::ApiClient::new(&server_url).unwrap();
+        let error = api.delete_font("b.ttf", Some(&"0".repeat(64))).await.expect_err("stale delete");
+        assert!(client::is_version_conflict(&error), "{:#}", error);
+        assert!(server_dir.path().join("b.ttf").exists());
+        let response = http.delete(format!("{}/fonts/b.ttf", server_url)).header("if-match", "a, b").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        api.delete_font("b.ttf", Some(&current)).await.expect("delete current version");
+        assert!(!server_dir.path().join("b.ttf").exists());
 
-            Ok(Box::new(response))
-        }
-        Err(e) => {
-            error!("Failed to open font file '{}': {}", filename, e);
-            Ok(Box::new(warp::reply::with_status(
-                format!("Failed to open font file: {}", e),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )))
-        }
+        let _ = shutdown.send(());
     }
-}
 
-async fn upload_font_handler(
-    mut form: FormData,
-    font_dir: Arc<PathBuf>,
-    ws_server: Option<Arc<WebSocketServer>>,
-) -> Result<Box<dyn Reply>, Rejection> {
-    while let Some(part) = form.next().await {
-        match part {
-            Ok(p) => {
-                if p.name() == "font" {
-                    let filename = p.filename().unwrap_or("unknown_font").to_string();
-                    let font_path = font_dir.join(&filename);
-
-                    match save_part_to_file(p, &font_path).await {
-                        Ok(sha256) => {
-                            info!("Uploaded font: {} (SHA256: {})", filename, sha256);
-                            
-                            // 广播 WebSocket 通知
-                            if let Some(server) = ws_server {
-                                let event = create_font_added_event(filename.clone(), sha256.clone(), 0);
-                                if let Err(e) = server.broadcast_font_event(event) {
-                                    warn!("Failed to broadcast WebSocket event: {}", e);
-                                } else {
-                                    info!("Broadcasted font upload event via WebSocket");
-                                }
-                            }
-                            
-                            return Ok(Box::new(warp::reply::with_status(
-                                warp::reply::json(&serde_json::json!({
-                                    "success": true,
-                                    "filename": filename,
-                                    "sha256": sha256,
-                                    "message": "Successfully uploaded"
-                                })),
-                                StatusCode::OK,
-                            )));
-                        }
-                        Err(e) => {
-                            error!("Failed to save font '{}': {}", filename, e);
-                            return Ok(Box::new(warp::reply::with_status(
-                                warp::reply::json(&serde_json::json!({
-                                    "error": e.to_string(),
-                                    "message": "Failed to save font"
-                                })),
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                            )));
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Error processing multipart form: {}", e);
-                return Ok(Box::new(warp::reply::with_status(
-                    warp::reply::json(&serde_json::json!({
-                        "error": e.to_string(),
-                        "message": "Error processing form"
-                    })),
-                    StatusCode::BAD_REQUEST,
-                )));
+    // 第一次报告上传进度时（请求发出之前）由“其他客户端”改写服务器上的字体
+    struct ChangeOnUpload(PathBuf);
+
+    impl crate::progress::ProgressReporter for ChangeOnUpload {
+        fn started(&self, _: crate::progress::Operation, _: usize) {}
+        fn file_progress(&self, _: crate::progress::Operation, _: &str, done: u64, _: u64) {
+            if done == 0 {
+                std::fs::write(&self.0, b"someone else's edit").unwrap();
             }
         }
+        fn file_finished(&self, _: crate::progress::Operation, _: &str, _: crate::sync_report::FileAction) {}
+        fn server_busy(&self, _: crate::progress::Operation, _: &str, _: std::time::Duration) {}
+        fn finished(&self, _: crate::progress::Operation) {}
     }
 
-    Ok(Box::new(warp::reply::with_status(
-        warp::reply::json(&serde_json::json!({
-            "error": "No font file found in upload",
-            "message": "No font file provided"
-        })),
-        StatusCode::BAD_REQUEST,
-    )))
-}
+    #[tokio::test]
+    async fn sync_does_not_overwrite_fonts_changed_during_upload() {
+        use crate::utils::{ConflictPolicy, ConflictResolution};
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
+        post_font(&server_url, "shared.ttf", b"server version").await;
 
-async fn save_part_to_file(part: Part, path: &Path) -> Result<String> {
-    let mut file = BufWriter::new(File::create(path).await?);
-    
-    let mut stream = part.stream();
-    while let Some(item) = stream.next().await {
-        let data = item?;
-        let bytes = data.chunk();
-        file.write_all(bytes).await?;
-    }
-    
-    file.flush().await?;
-    
-    // 保存后计算 SHA256
-    let sha256 = calculate_sha256(path)?;
-    Ok(sha256)
-}
+        let local_dir = tempfile::tempdir().expect("local temp dir");
+        std::fs::write(local_dir.path().join("shared.ttf"), b"a completely different local version").unwrap();
+        let options = SyncOptions { on_conflict: ConflictPolicy::OverwriteRemote, ..SyncOptions::default() };
+        let progress = crate::progress::Progress::new(ChangeOnUpload(server_dir.path().join("shared.ttf")));
+        let mut report = SyncReport::with_progress(progress);
+        let (uploaded, skipped) = client::upload_local_fonts(&server_url, local_dir.path(), &options, &mut report)
+            .await
+            .expect("upload local fonts");
 
-async fn get_sha256_handler(
-    filename: String,
-    font_dir: Arc<PathBuf>,
-) -> Result<Box<dyn Reply>, Rejection> {
-    let font_path = font_dir.join(&filename);
+        // 服务器以 412 拒绝后记为冲突并跳过，不覆盖他人的修改
+        assert_eq!((uploaded, skipped), (0, 1));
+        assert_eq!(std::fs::read(server_dir.path().join("shared.ttf")).unwrap(), b"someone else's edit");
+        assert_eq!(report.count(crate::sync_report::FileAction::Skipped), 1);
+        assert_eq!(report.count(crate::sync_report::FileAction::Failed), 0);
+        let last = report.conflicts.last().unwrap();
+        assert_eq!((last.filename.as_str(), &last.resolution), ("shared.ttf", &ConflictResolution::Skip));
 
-    if !font_path.exists() {
-        return Ok(Box::new(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": "Font not found",
-                "message": format!("Font '{}' not found", filename)
-            })),
-            StatusCode::NOT_FOUND,
-        )));
+        let _ = shutdown.send(());
     }
 
-    match calculate_sha256(&font_path) {
-        Ok(sha256) => Ok(Box::new(warp::reply::json(&serde_json::json!({
-            "filename": filename,
-            "sha256": sha256,
-        })))),
-        Err(e) => {
-            error!("Failed to calculate SHA256 for '{}': {}", filename, e);
-            Ok(Box::new(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": e.to_string(),
-                    "message": "Failed to calculate SHA256"
-                })),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )))
-        }
+    async fn post_font(server_url: &str, filename: &str, data: &[u8]) {
+        let part = reqwest::multipart::Part::bytes(data.to_vec()).file_name(filename.to_string());
+        let form = reqwest::multipart::Form::new().part("font", part);
+        let response = reqwest::Client::new()
+            .post(format!("{}/fonts?overwrite=true", server_url))
+            .multipart(form)
+            .send()
+            .await
+            .expect("post font");
+        assert!(response.status().is_success());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::start_server;
-    use crate::client;
-    use crate::websocket_server::WebSocketServer;
-    use std::path::PathBuf;
-    use std::net::TcpListener;
-    use std::sync::Arc;
-    use tokio::sync::oneshot;
-    use warp::Filter;
 
     #[tokio::test]
-    async fn start_server_returns_error_when_port_in_use() {
-        let listener = TcpListener::bind("127.0.0.1:0").expect("bind test port");
-        let port = listener.local_addr().expect("local addr").port();
-        let temp_dir = tempfile::tempdir().expect("temp dir");
+    async fn connectivity_probe_passes_against_a_running_server() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
+        let server_url = format!("http://{}", addr);
 
-        let result = start_server(
-            "127.0.0.1".to_string(),
-            port,
-            temp_dir.path().to_string_lossy().to_string(),
-            false,
-        )
-        .await;
+        let health = client::ApiClient::new(&server_url).unwrap().health().await.expect("healthz");
+        assert_eq!(health, crate::api::HealthStatus::ok());
 
-        assert!(result.is_err(), "expected error when port is in use");
+        let report = crate::probe::run(&server_url, "probe-test").await;
+        assert_eq!(report.failure(), None, "{}", report);
+        // 测试服务器没有 WebSocket 服务，只是降级为 SSE
+        let warnings: Vec<_> = report.warnings().map(|(failure, _)| failure).collect();
+        assert_eq!(warnings, [crate::probe::Failure::WebSocket], "{}", report);
+        let steps: Vec<_> = report.checks.iter().map(|c| c.step).collect();
+        assert_eq!(steps, ["url", "dns", "tcp", "tls", "healthz", "protocol", "auth", "websocket"]);
+
+        let _ = shutdown.send(());
     }
 
     #[tokio::test]
-    async fn sync_upload_and_download_smoke() {
+    async fn rejects_incompatible_protocol_versions() {
         let server_dir = tempfile::tempdir().expect("server temp dir");
         let (addr, shutdown) = start_test_http_server(server_dir.path().to_path_buf()).await;
         let server_url = format!("http://{}", addr);
 
-        let local_dir = tempfile::tempdir().expect("local temp dir");
-        let font_path = local_dir.path().join("test.ttf");
-        tokio::fs::write(&font_path, b"dummy font data")
-            .await
-            .expect("write font");
+        let api = client::ApiClient::new(&server_url).unwrap();
+        assert_eq!(api.version().await.expect("version"), crate::api::VersionInfo::current());
+        api.list_fonts(&Default::default()).await.expect("compatible client");
+
+        let http = reqwest::Client::new();
+        // 不带协议头部的旧客户端照常处理
+        let response = http.get(format!("{}/fonts", server_url)).send().await.expect("old client");
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()[crate::api::PROTOCOL_HEADER], crate::api::PROTOCOL_VERSION.to_string().as_str());
 
-        let (uploaded, _) = client::upload_local_fonts(&server_url, local_dir.path(), false)
+        let future = crate::api::PROTOCOL_VERSION + 1;
+        let response = http
+            .get(format!("{}/fonts", server_url))
+            .header(crate::api::PROTOCOL_HEADER, future)
+            .header(crate::api::VERSION_HEADER, "9.0.0")
+            .send()
             .await
-            .expect("upload local fonts");
-        assert_eq!(uploaded, 1);
-        assert!(server_dir.path().join("test.ttf").exists());
+            .expect("newer client");
+        assert_eq!(response.status(), reqwest::StatusCode::UPGRADE_REQUIRED);
+        let error: crate::api::ErrorResponse = response.json().await.expect("error body");
+        let message = error.message.unwrap();
+        assert!(message.contains("9.0.0") && message.contains("upgrade the server"), "{}", message);
 
-        let listed = client::get_server_fonts_with_sha256(&server_url)
+        // 不兼容的客户端仍可查询服务器版本
+        let response = http
+            .get(format!("{}/version", server_url))
+            .header(crate::api::PROTOCOL_HEADER, future)
+            .send()
             .await
-            .expect("list server fonts");
-        assert_eq!(listed.fonts.len(), 1);
-        assert_eq!(listed.fonts[0].name, "test.ttf");
+            .expect("version");
+        assert!(response.status().is_success());
 
-        let download_dir = tempfile::tempdir().expect("download temp dir");
-        let _ = client::download_server_fonts(&server_url, download_dir.path(), false)
+        let _ = shutdown.send(());
+    }
+
+    #[tokio::test]
+    async fn every_error_uses_the_json_envelope() {
+        let server_dir = tempfile::tempdir().expect("server temp dir");
+        let policy = super::ServerPolicy { admin_token: Some("secret".to_string()), ..Default::default() };
+        let (addr, shutdown) = start_test_http_server_with_policy(server_dir.path().to_path_buf(), policy).await;
+        let server_url = format!("http://{}", addr);
+        let http = reqwest::Client::new();
+
+        let response = http.get(format!("{}/no/such/endpoint", server_url)).send().await.expect("unknown path");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let error: crate::api::ErrorResponse = response.json().await.expect("json 404");
+        assert_eq!((error.code, error.retryable), (crate::api::ErrorCode::NotFound, false));
+
+        let response = http
+            .post(format!("{}/admin/blocklist", server_url))
+            .bearer_auth("secret")
+            .header("content-type", "application/json")
+            .body("{ not json")
+            .send()
             .await
-            .expect("download server fonts");
+            .expect("bad body");
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let error: crate::api::ErrorResponse = response.json().await.expect("json 400");
+        assert_eq!(error.code, crate::api::ErrorCode::BadRequest);
+
+        // 客户端得到带操作说明的类型化错误
+        let api = client::ApiClient::new(&server_url).unwrap();
+        let error = api.fetch_font("missing.ttf").await.expect_err("missing font");
+        assert_eq!(client::server_error(&error).map(|e| e.code), Some(crate::api::ErrorCode::NotFound));
+        assert!(!client::is_retryable_error(&error));
 
         let _ = shutdown.send(());
     }
 
     async fn start_test_http_server(font_dir: PathBuf) -> (std::net::SocketAddr, oneshot::Sender<()>) {
-        let font_dir_arc = Arc::new(font_dir);
-        let ws_server: Option<Arc<WebSocketServer>> = None;
-
-        let font_dir_filter = warp::any().map(move || Arc::clone(&font_dir_arc));
-        let ws_server_filter = warp::any().map(move || ws_server.clone());
-
-        let list_fonts = warp::path!("fonts")
-            .and(warp::get())
-            .and(font_dir_filter.clone())
-            .and_then(super::list_fonts_handler);
-
-        let download_font = warp::path!("fonts" / String)
-            .and(warp::get())
-            .and(font_dir_filter.clone())
-            .and_then(super::download_font_handler);
-
-        let upload_font = warp::path!("fonts")
-            .and(warp::post())
-            .and(warp::multipart::form().max_length(100 * 1024 * 1024))
-            .and(font_dir_filter.clone())
-            .and(ws_server_filter.clone())
-            .and_then(super::upload_font_handler);
-
-        let get_sha256 = warp::path!("fonts" / String / "sha256")
-            .and(warp::get())
-            .and(font_dir_filter.clone())
-            .and_then(super::get_sha256_handler);
-
-        let routes = list_fonts
-            .or(download_font)
-            .or(upload_font)
-            .or(get_sha256)
-            .with(warp::cors().allow_any_origin());
+        start_test_http_server_with_policy(font_dir, super::ServerPolicy::default()).await
+    }
+
+    async fn start_test_http_server_with_policy(
+        font_dir: PathBuf,
+        policy: super::ServerPolicy,
+    ) -> (std::net::SocketAddr, oneshot::Sender<()>) {
+        let event_log = Arc::new(EventLog::open(&font_dir).expect("open event log"));
+        let signer = Arc::new(super::ServerSigner::load_or_create(&font_dir).expect("signing key"));
+        let metadata = Arc::new(super::MetadataStore::open(&font_dir).expect("metadata store"));
+        let blobs = Arc::new(super::BlobStore::open(&font_dir).expect("blob store"));
+        let routes = super::build_routes(Arc::new(font_dir), None, event_log, signer, metadata, blobs, policy);
 
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
-        let (addr, server) = warp::serve(routes)
-            .bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
-                let _ = shutdown_rx.await;
-            });
+        let (addr, server) = super::serve_tcp(routes.map(Reply::into_response).boxed(), ([127, 0, 0, 1], 0).into(), async {
+            let _ = shutdown_rx.await;
+        })
+        .expect("bind test server");
 
         tokio::spawn(server);
         (addr, shutdown_tx)
@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use log::info;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const KEY_FILE: &str = "signing.pem";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub generated_at: u64,
+    pub fonts: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn hashes(&self) -> HashMap<String, String> {
+        self.fonts
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.sha256.clone()))
+            .collect()
+    }
+}
+
+// 签名针对 payload 原始文本，避免客户端重新序列化带来的差异
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub payload: String,
+    pub signature: String,
+    pub public_key: String,
+}
+
+impl SignedManifest {
+    // 校验签名并解析清单，trusted_key 为空时使用清单自带公钥
+    pub fn verify(&self, trusted_key: Option<&str>) -> Result<Manifest> {
+        let public_key = trusted_key.unwrap_or(&self.public_key);
+        if public_key != self.public_key {
+            return Err(anyhow::anyhow!(
+                "Manifest was signed by an untrusted key ({})",
+                self.public_key
+            ));
+        }
+
+        verify_signature(public_key, self.payload.as_bytes(), &self.signature)?;
+        serde_json::from_str(&self.payload).context("Failed to parse signed manifest")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicKeyInfo {
+    pub algorithm: String,
+    pub public_key: String,
+}
+
+// 服务器端 ed25519 签名密钥，首次启动时生成
+pub struct ServerSigner {
+    key: PKey<Private>,
+}
+
+impl ServerSigner {
+    pub fn load_or_create(font_dir: &Path) -> Result<Self> {
+        let key_dir = font_dir.join(".fontsync");
+        let key_path = key_dir.join(KEY_FILE);
+
+        if key_path.exists() {
+            let pem = fs::read(&key_path).context("Failed to read signing key")?;
+            let key = PKey::private_key_from_pem(&pem).context("Failed to parse signing key")?;
+            return Ok(Self { key });
+        }
+
+        let key = PKey::generate_ed25519().context("Failed to generate signing key")?;
+        let pem = key
+            .private_key_to_pem_pkcs8()
+            .context("Failed to encode signing key")?;
+        fs::create_dir_all(&key_dir).context("Failed to create metadata directory")?;
+        fs::write(&key_path, pem).context("Failed to write signing key")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict signing key permissions")?;
+        }
+
+        let signer = Self { key };
+        info!("Generated manifest signing key: {}", signer.public_key()?);
+        Ok(signer)
+    }
+
+    // 公钥以 base64 编码的 32 字节原始形式发布
+    pub fn public_key(&self) -> Result<String> {
+        let raw = self.key.raw_public_key().context("Failed to export public key")?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(raw))
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<String> {
+        let mut signer = Signer::new_without_digest(&self.key).context("Failed to create signer")?;
+        let signature = signer
+            .sign_oneshot_to_vec(data)
+            .context("Failed to sign data")?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(signature))
+    }
+
+    pub fn sign_manifest(&self, manifest: &Manifest) -> Result<SignedManifest> {
+        let payload = serde_json::to_string(manifest).context("Failed to serialize manifest")?;
+        let signature = self.sign(payload.as_bytes())?;
+        Ok(SignedManifest {
+            payload,
+            signature,
+            public_key: self.public_key()?,
+        })
+    }
+}
+
+pub fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> Result<()> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let raw_key = engine
+        .decode(public_key.trim())
+        .context("Public key is not valid base64")?;
+    let key = PKey::public_key_from_raw_bytes(&raw_key, Id::ED25519)
+        .context("Public key is not a valid ed25519 key")?;
+    let signature = engine
+        .decode(signature.trim())
+        .context("Signature is not valid base64")?;
+
+    let mut verifier = Verifier::new_without_digest(&key).context("Failed to create verifier")?;
+    if verifier
+        .verify_oneshot(&signature, data)
+        .context("Failed to verify signature")?
+    {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid signature"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        Manifest {
+            generated_at: 1,
+            fonts: vec![ManifestEntry {
+                name: "a.ttf".to_string(),
+                sha256: "00".repeat(32),
+                size: 3,
+            }],
+        }
+    }
+
+    #[test]
+    fn signed_manifest_verifies_and_detects_tampering() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let signer = ServerSigner::load_or_create(dir.path()).expect("signer");
+        let signed = signer.sign_manifest(&manifest()).expect("sign");
+
+        let verified = signed.verify(None).expect("verify");
+        assert_eq!(verified.fonts[0].name, "a.ttf");
+
+        // 重新加载后应使用同一密钥
+        let reloaded = ServerSigner::load_or_create(dir.path()).expect("reload");
+        assert_eq!(reloaded.public_key().unwrap(), signer.public_key().unwrap());
+
+        let mut tampered = signed.clone();
+        tampered.payload = tampered.payload.replace("a.ttf", "b.ttf");
+        assert!(tampered.verify(None).is_err());
+
+        let other_dir = tempfile::tempdir().expect("temp dir");
+        let other = ServerSigner::load_or_create(other_dir.path()).expect("other signer");
+        assert!(signed.verify(Some(&other.public_key().unwrap())).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+// Server-Sent Events 的增量解析，输入可在任意字节处切分
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    pub id: Option<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    current: SseEvent,
+    has_data: bool,
+}
+
+impl SseParser {
+    // 追加收到的数据，返回其中已完整的事件
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // 空行结束一个事件；没有 data 字段的事件（如心跳注释）不分发，事件类型字段不使用
+        if line.is_empty() {
+            let event = std::mem::take(&mut self.current);
+            return std::mem::take(&mut self.has_data).then_some(event);
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "id" => self.current.id = Some(value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.current.data.push('\n');
+                }
+                self.current.data.push_str(value);
+                self.has_data = true;
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events_split_across_chunks() {
+        let mut parser = SseParser::default();
+        let text = "ta: {\"a\":\ndata:1}\n\nevent: x\ndata:中文\n\n".as_bytes();
+        // 在多字节字符中间切分
+        let split = text.len() - 4;
+        assert!(parser.push(b":keep-alive\n\nid: 7\r\nda").is_empty());
+        let mut events = parser.push(&text[..split]);
+        events.extend(parser.push(&text[split..]));
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent { id: Some("7".to_string()), data: "{\"a\":\n1}".to_string() },
+                SseEvent { id: None, data: "中文".to_string() },
+            ]
+        );
+    }
+}
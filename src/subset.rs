@@ -0,0 +1,458 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use ttf_parser::gsub::SubstitutionSubtable;
+use ttf_parser::{GlyphId, Tag};
+
+use crate::instancer::build_sfnt;
+use crate::webfont;
+
+// 一次子集化最多包含的码位
+pub const MAX_CODEPOINTS: usize = 65_536;
+
+// 子集字体的输出格式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SubsetFormat {
+    #[default]
+    Ttf,
+    Woff,
+    Woff2,
+}
+
+impl SubsetFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubsetFormat::Ttf => "ttf",
+            SubsetFormat::Woff => "woff",
+            SubsetFormat::Woff2 => "woff2",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            SubsetFormat::Ttf => "font/ttf",
+            SubsetFormat::Woff => "font/woff",
+            SubsetFormat::Woff2 => "font/woff2",
+        }
+    }
+
+    pub fn encode(self, font: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            SubsetFormat::Ttf => Ok(font),
+            SubsetFormat::Woff => webfont::to_woff(&font),
+            SubsetFormat::Woff2 => webfont::to_woff2(&font),
+        }
+    }
+}
+
+// 解析 "U+0041-005A,U+4E00,20-7E" 形式的码位范围
+pub fn parse_unicodes(spec: &str) -> Result<BTreeSet<u32>> {
+    let parse = |value: &str| -> Result<u32> {
+        let hex = value.trim().trim_start_matches(['U', 'u']).trim_start_matches('+');
+        let codepoint = u32::from_str_radix(hex, 16).with_context(|| format!("Invalid code point '{}'", value.trim()))?;
+        if codepoint > 0x10FFFF {
+            bail!("Code point '{}' is outside the Unicode range", value.trim());
+        }
+        Ok(codepoint)
+    };
+    let mut codepoints = BTreeSet::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(part)?, parse(part)?),
+        };
+        if start > end {
+            bail!("Invalid range '{}'", part);
+        }
+        if codepoints.len() + (end - start) as usize >= MAX_CODEPOINTS {
+            bail!("Subsets are limited to {} code points", MAX_CODEPOINTS);
+        }
+        codepoints.extend(start..=end);
+    }
+    Ok(codepoints)
+}
+
+// 合并文字与 Unicode 范围中的码位，两者都为空时报错
+pub fn requested_codepoints(text: Option<&str>, unicodes: Option<&str>) -> Result<BTreeSet<u32>> {
+    let mut codepoints = match unicodes {
+        Some(spec) => parse_unicodes(spec)?,
+        None => BTreeSet::new(),
+    };
+    codepoints.extend(text.unwrap_or_default().chars().map(u32::from));
+    if codepoints.is_empty() {
+        bail!("Specify the characters to keep with text or unicodes");
+    }
+    if codepoints.len() > MAX_CODEPOINTS {
+        bail!("Subsets are limited to {} code points", MAX_CODEPOINTS);
+    }
+    Ok(codepoints)
+}
+
+// 子集文件名：<原文件名主干>.subset.<格式扩展名>
+pub fn subset_filename(filename: &str, format: SubsetFormat) -> String {
+    let stem = std::path::Path::new(filename).file_stem().and_then(|s| s.to_str()).unwrap_or("font");
+    format!("{}.subset.{}", stem, format.extension())
+}
+
+// 只保留映射到这些码位的字形及其组合部件与 GSUB 替换结果。字形编号不变，
+// 其余字形的轮廓被清空，因此 hmtx、post、GDEF/GPOS/GSUB 等按编号索引的表无需改写；cmap 只保留请求的码位
+pub fn subset(data: &[u8], codepoints: &BTreeSet<u32>) -> Result<Vec<u8>> {
+    if data.starts_with(b"ttcf") {
+        bail!("Font collections cannot be subset");
+    }
+    let face = ttf_parser::Face::parse(data, 0).context("Unsupported font format")?;
+    let raw = face.raw_face();
+    let (Some(glyf), Some(loca), Some(head)) = (
+        raw.table(Tag::from_bytes(b"glyf")),
+        raw.table(Tag::from_bytes(b"loca")),
+        raw.table(Tag::from_bytes(b"head")),
+    ) else {
+        bail!("Only fonts with TrueType outlines (glyf) can be subset");
+    };
+    // head.indexToLocFormat 为 0 时 loca 存储偏移的一半
+    let short_loca = head.get(50..52) == Some(&[0, 0]);
+
+    let mut mapping = BTreeMap::new();
+    for &codepoint in codepoints {
+        if let Some(glyph) = char::from_u32(codepoint).and_then(|c| face.glyph_index(c)) {
+            mapping.insert(codepoint, glyph.0);
+        }
+    }
+    if mapping.is_empty() {
+        bail!("The font maps none of the requested characters");
+    }
+
+    let glyph_range = |id: u16| -> Option<&[u8]> {
+        let offset = |index: usize| -> Option<usize> {
+            if short_loca {
+                loca.get(index * 2..index * 2 + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize * 2)
+            } else {
+                loca.get(index * 4..index * 4 + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+            }
+        };
+        let (start, end) = (offset(id as usize)?, offset(id as usize + 1)?);
+        if start >= end { None } else { glyf.get(start..end) }
+    };
+    let mut keep: BTreeSet<u16> = mapping.values().copied().collect();
+    keep.insert(0);
+    loop {
+        let before = keep.len();
+        close_over_gsub(&face, &mut keep);
+        for id in keep.clone() {
+            if let Some(glyph) = glyph_range(id) {
+                keep.extend(composite_components(glyph));
+            }
+        }
+        if keep.len() == before {
+            break;
+        }
+    }
+
+    // 长格式 loca，未保留的字形长度为 0
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::new();
+    for id in 0..face.number_of_glyphs() {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&id)
+            && let Some(glyph) = glyph_range(id)
+        {
+            new_glyf.extend_from_slice(glyph);
+            new_glyf.resize((new_glyf.len() + 3) & !3, 0);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut tables = Vec::new();
+    for record in raw.table_records {
+        let tag = record.tag.to_bytes();
+        let table = raw.table(record.tag).context("Table extends past end of font")?;
+        let table = match &tag {
+            b"glyf" => new_glyf.clone(),
+            b"loca" => new_loca.clone(),
+            b"cmap" => cmap_table(&mapping),
+            b"gvar" => subset_gvar(table, &keep).context("Malformed gvar table")?,
+            b"head" => {
+                let mut head = table.to_vec();
+                if head.len() < 54 {
+                    bail!("Malformed head table");
+                }
+                head[8..12].fill(0);
+                head[50..52].copy_from_slice(&1u16.to_be_bytes());
+                head
+            }
+            // 签名在修改后失效
+            b"DSIG" => continue,
+            _ => table.to_vec(),
+        };
+        tables.push((tag, table));
+    }
+    Ok(build_sfnt(tables))
+}
+
+// 加入 GSUB 中可由已保留字形替换得到的字形；连字只在所有组成部分都保留时加入
+fn close_over_gsub(face: &ttf_parser::Face, keep: &mut BTreeSet<u16>) {
+    let Some(gsub) = face.tables().gsub else {
+        return;
+    };
+    for lookup in gsub.lookups {
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            let mut added = Vec::new();
+            for &id in keep.iter() {
+                let glyph = GlyphId(id);
+                let Some(index) = subtable.coverage().get(glyph) else {
+                    continue;
+                };
+                match &subtable {
+                    SubstitutionSubtable::Single(ttf_parser::gsub::SingleSubstitution::Format1 { delta, .. }) => {
+                        added.push(id.wrapping_add(*delta as u16));
+                    }
+                    SubstitutionSubtable::Single(ttf_parser::gsub::SingleSubstitution::Format2 { substitutes, .. })
+                    | SubstitutionSubtable::ReverseChainSingle(ttf_parser::gsub::ReverseChainSingleSubstitution {
+                        substitutes,
+                        ..
+                    }) => added.extend(substitutes.get(index).map(|g| g.0)),
+                    SubstitutionSubtable::Multiple(multiple) => {
+                        if let Some(sequence) = multiple.sequences.get(index) {
+                            added.extend(sequence.substitutes.into_iter().map(|g| g.0));
+                        }
+                    }
+                    SubstitutionSubtable::Alternate(alternate) => {
+                        if let Some(set) = alternate.alternate_sets.get(index) {
+                            added.extend(set.alternates.into_iter().map(|g| g.0));
+                        }
+                    }
+                    SubstitutionSubtable::Ligature(ligature) => {
+                        if let Some(set) = ligature.ligature_sets.get(index) {
+                            for ligature in set {
+                                if ligature.components.into_iter().all(|g| keep.contains(&g.0)) {
+                                    added.push(ligature.glyph.0);
+                                }
+                            }
+                        }
+                    }
+                    // 上下文替换调用的查找本身也在列表中
+                    SubstitutionSubtable::Context(_) | SubstitutionSubtable::ChainContext(_) => {}
+                }
+            }
+            keep.extend(added.into_iter().filter(|&id| id < face.number_of_glyphs()));
+        }
+    }
+}
+
+// 组合字形引用的部件字形
+fn composite_components(glyph: &[u8]) -> Vec<u16> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let u16_at = |at: usize| glyph.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let mut components = Vec::new();
+    if u16_at(0).is_none_or(|contours| (contours as i16) >= 0) {
+        return components;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(component)) = (u16_at(at), u16_at(at + 2)) {
+        components.push(component);
+        at += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            at += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            at += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            at += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    components
+}
+
+// Windows 平台的格式 4（BMP）与格式 12（全部码位）子表，连续码位映射到连续字形时合并为一段；
+// 段数过多超出格式 4 的长度上限时只写格式 12
+fn cmap_table(mapping: &BTreeMap<u32, u16>) -> Vec<u8> {
+    let mut groups: Vec<(u32, u32, u16)> = Vec::new();
+    for (&codepoint, &glyph) in mapping {
+        if let Some((start, end, start_glyph)) = groups.last_mut()
+            && *end + 1 == codepoint
+            && *start_glyph as u32 + (codepoint - *start) == glyph as u32
+        {
+            *end = codepoint;
+            continue;
+        }
+        groups.push((codepoint, codepoint, glyph));
+    }
+
+    let mut subtables: Vec<(u16, Vec<u8>)> = Vec::new();
+    let bmp: Vec<_> = groups.iter().filter(|g| g.0 < 0xFFFF).map(|&(start, end, glyph)| (start, end.min(0xFFFE), glyph)).collect();
+    if 16 + 8 * (bmp.len() + 1) <= 0xFFFF {
+        let seg_count = bmp.len() as u16 + 1;
+        let entry_selector = 15 - seg_count.leading_zeros() as u16;
+        let search_range = 2 * (1u16 << entry_selector);
+        let mut format4 = Vec::new();
+        for value in [4, 16 + 8 * seg_count, 0, seg_count * 2, search_range, entry_selector, seg_count * 2 - search_range] {
+            format4.extend_from_slice(&value.to_be_bytes());
+        }
+        for &(_, end, _) in &bmp {
+            format4.extend_from_slice(&(end as u16).to_be_bytes());
+        }
+        format4.extend_from_slice(&[0xFF, 0xFF, 0, 0]);
+        for &(start, _, _) in &bmp {
+            format4.extend_from_slice(&(start as u16).to_be_bytes());
+        }
+        format4.extend_from_slice(&0xFFFFu16.to_be_bytes());
+        for &(start, _, glyph) in &bmp {
+            format4.extend_from_slice(&glyph.wrapping_sub(start as u16).to_be_bytes());
+        }
+        // 结束段映射到字形 0，所有段的 idRangeOffset 为 0
+        format4.extend_from_slice(&1u16.to_be_bytes());
+        format4.resize(format4.len() + 2 * seg_count as usize, 0);
+        subtables.push((1, format4));
+    }
+
+    let mut format12 = Vec::new();
+    format12.extend_from_slice(&12u16.to_be_bytes());
+    format12.extend_from_slice(&0u16.to_be_bytes());
+    for value in [16 + 12 * groups.len() as u32, 0, groups.len() as u32] {
+        format12.extend_from_slice(&value.to_be_bytes());
+    }
+    for &(start, end, glyph) in &groups {
+        for value in [start, end, glyph as u32] {
+            format12.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+    subtables.push((10, format12));
+
+    let mut cmap = Vec::new();
+    cmap.extend_from_slice(&0u16.to_be_bytes());
+    cmap.extend_from_slice(&(subtables.len() as u16).to_be_bytes());
+    let mut offset = 4 + 8 * subtables.len() as u32;
+    for (encoding, table) in &subtables {
+        cmap.extend_from_slice(&3u16.to_be_bytes());
+        cmap.extend_from_slice(&encoding.to_be_bytes());
+        cmap.extend_from_slice(&offset.to_be_bytes());
+        offset += table.len() as u32;
+    }
+    for (_, table) in &subtables {
+        cmap.extend_from_slice(table);
+    }
+    cmap
+}
+
+// 去掉未保留字形的变化数据，偏移改为长格式
+fn subset_gvar(table: &[u8], keep: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let u16_at = |at: usize| table.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let u32_at = |at: usize| table.get(at..at + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+    let axis_count = u16_at(4)? as usize;
+    let shared_count = u16_at(6)? as usize;
+    let shared_offset = u32_at(8)? as usize;
+    let glyph_count = u16_at(12)? as usize;
+    let long_offsets = u16_at(14)? & 1 != 0;
+    let data_offset = u32_at(16)? as usize;
+    let offset = |id: usize| if long_offsets { u32_at(20 + id * 4) } else { u16_at(20 + id * 2).map(|o| o as u32 * 2) };
+    let shared = table.get(shared_offset..shared_offset + shared_count * axis_count * 2)?;
+
+    let mut data = Vec::new();
+    let mut offsets = Vec::with_capacity((glyph_count + 1) * 4);
+    for id in 0..glyph_count {
+        offsets.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        if keep.contains(&(id as u16)) {
+            let (start, end) = (data_offset + offset(id)? as usize, data_offset + offset(id + 1)? as usize);
+            data.extend_from_slice(table.get(start..end)?);
+        }
+    }
+    offsets.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let new_shared_offset = 20 + offsets.len();
+    let mut gvar = table.get(0..20)?.to_vec();
+    gvar[8..12].copy_from_slice(&(new_shared_offset as u32).to_be_bytes());
+    gvar[14..16].copy_from_slice(&(u16_at(14)? | 1).to_be_bytes());
+    gvar[16..20].copy_from_slice(&((new_shared_offset + shared.len()) as u32).to_be_bytes());
+    gvar.extend_from_slice(&offsets);
+    gvar.extend_from_slice(shared);
+    gvar.extend_from_slice(&data);
+    Some(gvar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_metadata::tests::{cmap_table as test_cmap, name_table, sfnt_with_tables};
+
+    // A、B 为方块，C 是引用 B 的组合字形
+    fn composite_font() -> Vec<u8> {
+        let be16 = |values: &[i16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0u8; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&4u16.to_be_bytes());
+        let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+        maxp.extend_from_slice(&4u16.to_be_bytes());
+
+        let mut square = be16(&[1, 100, 0, 900, 800, 3, 0]);
+        square.extend_from_slice(&[0x01; 4]);
+        square.extend(be16(&[100, 800, 0, -800, 0, 0, 800, 0]));
+        square.resize(36, 0);
+        let mut glyf = square.clone();
+        glyf.extend_from_slice(&square);
+        // ARG_1_AND_2_ARE_WORDS | ARGS_ARE_XY_VALUES，部件为字形 2
+        glyf.extend(be16(&[-1, 100, 0, 900, 800, 0x0003, 2, 0, 0]));
+
+        sfnt_with_tables(&[
+            (b"cmap", test_cmap(&[(0x41, 0x43)])),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", be16(&[1000, 0, 1000, 100, 1000, 100, 1000, 100])),
+            (b"loca", be16(&[0, 0, 18, 36, 45])),
+            (b"maxp", maxp),
+            (b"name", name_table(&[(1, "Parts"), (2, "Regular")])),
+        ])
+    }
+
+    #[test]
+    fn subsets_glyphs_and_components() {
+        let font = composite_font();
+        let subset_font = subset(&font, &BTreeSet::from(['C' as u32, 'Z' as u32])).expect("subset");
+        let face = ttf_parser::Face::parse(&subset_font, 0).expect("subset parses");
+        assert_eq!(face.glyph_index('A'), None);
+        assert_eq!(face.glyph_index('C'), Some(GlyphId(3)));
+        assert_eq!(face.glyph_bounding_box(GlyphId(3)).map(|b| b.x_max), Some(900));
+        // B 作为部件保留，但不再有码位映射
+        assert!(face.glyph_bounding_box(GlyphId(2)).is_some());
+        assert_eq!(face.glyph_index('B'), None);
+        assert!(face.glyph_bounding_box(GlyphId(1)).is_none());
+        let glyf_len = |data: &[u8]| ttf_parser::RawFace::parse(data, 0).unwrap().table(Tag::from_bytes(b"glyf")).unwrap().len();
+        assert_eq!(glyf_len(&subset_font), 36 + 20);
+        assert!(subset(&font, &BTreeSet::from(['Z' as u32])).is_err());
+
+        // 可变字体保留字形的变化数据
+        let variable = crate::instancer::tests::variable_font();
+        let subset_font = subset(&variable, &BTreeSet::from(['A' as u32])).expect("subset variable font");
+        let mut face = ttf_parser::Face::parse(&subset_font, 0).expect("subset parses");
+        face.set_variation(Tag::from_bytes(b"wght"), 900.0);
+        assert_eq!(face.glyph_bounding_box(GlyphId(1)).map(|b| b.x_max), Some(1000));
+
+        let woff2 = SubsetFormat::Woff2.encode(subset_font).expect("woff2");
+        assert_eq!(&woff2[0..4], b"wOF2");
+    }
+
+    #[test]
+    fn parses_unicode_ranges() {
+        assert_eq!(requested_codepoints(Some("AA"), Some("42")).unwrap(), BTreeSet::from([0x41, 0x42]));
+        assert!(requested_codepoints(Some(""), None).is_err());
+        assert_eq!(subset_filename("思源黑体.ttf", SubsetFormat::Woff2), "思源黑体.subset.woff2");
+        assert_eq!(parse_unicodes("U+0041-0043, u+4E00,20").unwrap(), BTreeSet::from([0x20, 0x41, 0x42, 0x43, 0x4E00]));
+        assert!(parse_unicodes("U+0043-0041").is_err());
+        assert!(parse_unicodes("U+110000").is_err());
+        assert!(parse_unicodes("zz").is_err());
+        assert!(parse_unicodes("0-10FFFF").is_err());
+        assert!(parse_unicodes("").unwrap().is_empty());
+    }
+}
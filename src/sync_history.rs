@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::client_state::ClientState;
+use crate::sync_report::{ConflictRecord, FileAction, FileRecord, SyncReport};
+
+const HISTORY_FILE: &str = "history.jsonl";
+// 超出后丢弃最早的记录
+const MAX_RUNS: usize = 200;
+
+// 一次同步的记录。内容相同而跳过的文件只计数，避免历史随字体数量膨胀
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncRun {
+    pub server_url: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub files: Vec<FileRecord>,
+    #[serde(default)]
+    pub conflicts: Vec<ConflictRecord>,
+    #[serde(default)]
+    pub skipped: usize,
+}
+
+impl SyncRun {
+    pub fn new(server_url: &str, started_at: u64, report: &SyncReport, cancelled: bool) -> Self {
+        Self {
+            server_url: server_url.trim_end_matches('/').to_string(),
+            started_at,
+            finished_at: chrono::Utc::now().timestamp() as u64,
+            cancelled,
+            files: report.files.iter().filter(|f| f.action != FileAction::Skipped).cloned().collect(),
+            conflicts: report.conflicts.clone(),
+            skipped: report.count(FileAction::Skipped),
+        }
+    }
+
+    pub fn count(&self, action: FileAction) -> usize {
+        self.files.iter().filter(|f| f.action == action).count()
+    }
+}
+
+fn history_path() -> PathBuf {
+    ClientState::state_dir().join(HISTORY_FILE)
+}
+
+// 记录失败不影响同步结果，只记录警告
+pub fn record(server_url: &str, started_at: u64, report: &SyncReport, cancelled: bool) {
+    let run = SyncRun::new(server_url, started_at, report, cancelled);
+    if let Err(e) = append(&history_path(), &run) {
+        warn!("Failed to record sync history: {:#}", e);
+    }
+}
+
+// 按时间先后排列，无法解析的行被忽略
+pub fn load() -> Vec<SyncRun> {
+    load_from(&history_path())
+}
+
+fn append(path: &Path, run: &SyncRun) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create state directory")?;
+    }
+    let line = serde_json::to_string(run).context("Failed to serialize sync run")?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write {:?}", path))?;
+
+    let runs = load_from(path);
+    if runs.len() > MAX_RUNS {
+        let mut content = String::new();
+        for run in &runs[runs.len() - MAX_RUNS..] {
+            content.push_str(&serde_json::to_string(run).context("Failed to serialize sync run")?);
+            content.push('\n');
+        }
+        let tmp_path = path.with_extension("jsonl.tmp");
+        fs::write(&tmp_path, content).context("Failed to write sync history")?;
+        fs::rename(&tmp_path, path).context("Failed to replace sync history")?;
+    }
+    Ok(())
+}
+
+fn load_from(path: &Path) -> Vec<SyncRun> {
+    fs::read_to_string(path)
+        .map(|content| content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{ConflictResolution, SyncDirection};
+    use std::time::Duration;
+
+    #[test]
+    fn appends_and_prunes_runs() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join(HISTORY_FILE);
+
+        let mut report = SyncReport::default();
+        report.record_skipped("same.ttf", SyncDirection::Upload);
+        report.record_transfer("a.ttf", SyncDirection::Download, FileAction::Downloaded, 10, Duration::ZERO);
+        report.record_installed(&crate::font_installer::describe(Path::new("a.ttf"), "a.ttf"));
+        report.record_conflict("b.ttf", SyncDirection::Upload, ConflictResolution::Rename, Some("b-1.ttf".to_string()));
+        append(&path, &SyncRun::new("http://server/", 1, &report, false)).unwrap();
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"not json\n").unwrap();
+
+        let runs = load_from(&path);
+        assert_eq!(runs.len(), 1);
+        let run = &runs[0];
+        assert_eq!((run.server_url.as_str(), run.skipped), ("http://server", 1));
+        assert_eq!((run.count(FileAction::Downloaded), run.count(FileAction::Installed)), (1, 1));
+        assert_eq!(run.conflicts[0].renamed_to.as_deref(), Some("b-1.ttf"));
+
+        for started_at in 2..=MAX_RUNS as u64 + 5 {
+            append(&path, &SyncRun::new("http://server", started_at, &SyncReport::default(), false)).unwrap();
+        }
+        let runs = load_from(&path);
+        assert_eq!(runs.len(), MAX_RUNS);
+        assert_eq!(runs.last().unwrap().started_at, MAX_RUNS as u64 + 5);
+    }
+}
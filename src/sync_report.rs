@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::api::InstalledFont;
+use crate::i18n::{self, t};
+use crate::progress::{Operation, Progress};
+use crate::utils::{ConflictResolution, SyncDirection};
+
+// sync 命令的退出码：0 表示有传输且全部成功，致命错误为 1
+pub const EXIT_PARTIAL_FAILURE: i32 = 2;
+pub const EXIT_NOTHING_TO_DO: i32 = 3;
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+// 单个冲突的处理结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConflictRecord {
+    pub filename: String,
+    pub direction: SyncDirection,
+    pub resolution: ConflictResolution,
+    pub renamed_to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    Uploaded,
+    // 上传成功但需管理员批准
+    AwaitingApproval,
+    Downloaded,
+    // 下载后安装到系统
+    Installed,
+    Skipped,
+    // 固定了版本，与服务器不同也不同步
+    Pinned,
+    Failed,
+    // 同步取消时正在进行的传输
+    Aborted,
+}
+
+impl FileAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileAction::Uploaded => "uploaded",
+            FileAction::AwaitingApproval => "awaiting_approval",
+            FileAction::Downloaded => "downloaded",
+            FileAction::Installed => "installed",
+            FileAction::Skipped => "skipped",
+            FileAction::Pinned => "pinned",
+            FileAction::Failed => "failed",
+            FileAction::Aborted => "aborted",
+        }
+    }
+
+    fn is_transfer(self) -> bool {
+        matches!(self, FileAction::Uploaded | FileAction::AwaitingApproval | FileAction::Downloaded)
+    }
+}
+
+// 单个文件的处理结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileRecord {
+    pub filename: String,
+    // 处理完成的 Unix 秒
+    #[serde(default)]
+    pub timestamp: u64,
+    pub direction: SyncDirection,
+    pub action: FileAction,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // 安装的字体从 name 表读取的家族与样式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+}
+
+// 一次同步过程中的决策记录
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub conflicts: Vec<ConflictRecord>,
+    pub files: Vec<FileRecord>,
+    // 每条文件记录同时作为进度报告，安装结果由安装器逐个报告
+    pub progress: Progress,
+}
+
+impl SyncReport {
+    pub fn with_progress(progress: Progress) -> Self {
+        Self { progress, ..Self::default() }
+    }
+
+    pub fn record_conflict(
+        &mut self,
+        filename: &str,
+        direction: SyncDirection,
+        resolution: ConflictResolution,
+        renamed_to: Option<String>,
+    ) {
+        self.conflicts.push(ConflictRecord {
+            filename: filename.to_string(),
+            direction,
+            resolution,
+            renamed_to,
+        });
+    }
+
+    pub fn record_transfer(
+        &mut self,
+        filename: &str,
+        direction: SyncDirection,
+        action: FileAction,
+        bytes: u64,
+        duration: Duration,
+    ) {
+        self.record(filename, direction, action, bytes, duration, None);
+    }
+
+    pub fn record_skipped(&mut self, filename: &str, direction: SyncDirection) {
+        self.record(filename, direction, FileAction::Skipped, 0, Duration::ZERO, None);
+    }
+
+    pub fn record_installed(&mut self, font: &InstalledFont) {
+        self.record(&font.filename, SyncDirection::Download, FileAction::Installed, 0, Duration::ZERO, None);
+        if let Some(record) = self.files.last_mut() {
+            record.family = font.family.clone();
+            record.style = font.style.clone();
+        }
+    }
+
+    pub fn installed_fonts(&self) -> Vec<InstalledFont> {
+        self.files
+            .iter()
+            .filter(|f| f.action == FileAction::Installed)
+            .map(|f| InstalledFont { filename: f.filename.clone(), family: f.family.clone(), style: f.style.clone() })
+            .collect()
+    }
+
+    pub fn record_pinned(&mut self, filename: &str, direction: SyncDirection) {
+        self.record(filename, direction, FileAction::Pinned, 0, Duration::ZERO, None);
+    }
+
+    pub fn record_failed(&mut self, filename: &str, direction: SyncDirection, duration: Duration, error: impl ToString) {
+        self.record(filename, direction, FileAction::Failed, 0, duration, Some(error.to_string()));
+    }
+
+    pub fn record_aborted(&mut self, filename: &str, direction: SyncDirection) {
+        self.record(filename, direction, FileAction::Aborted, 0, Duration::ZERO, None);
+    }
+
+    fn record(
+        &mut self,
+        filename: &str,
+        direction: SyncDirection,
+        action: FileAction,
+        bytes: u64,
+        duration: Duration,
+        error: Option<String>,
+    ) {
+        if action != FileAction::Installed {
+            let operation = match direction {
+                SyncDirection::Upload => Operation::Upload,
+                SyncDirection::Download => Operation::Download,
+            };
+            self.progress.file_finished(operation, filename, action);
+        }
+        self.files.push(FileRecord {
+            filename: filename.to_string(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            direction,
+            action,
+            bytes,
+            duration_ms: duration.as_millis() as u64,
+            error,
+            family: None,
+            style: None,
+        });
+    }
+
+    pub fn count(&self, action: FileAction) -> usize {
+        self.files.iter().filter(|f| f.action == action).count()
+    }
+
+    // 如 "2 uploaded, 1 downloaded, 1 installed, 5 skipped, 0 pinned, 0 failed"
+    pub fn summary(&self) -> String {
+        t!(
+            "sync.summary",
+            uploaded = self.count(FileAction::Uploaded) + self.count(FileAction::AwaitingApproval),
+            downloaded = self.count(FileAction::Downloaded),
+            installed = self.count(FileAction::Installed),
+            skipped = self.count(FileAction::Skipped),
+            pinned = self.count(FileAction::Pinned),
+            failed = self.count(FileAction::Failed),
+        )
+    }
+
+    pub fn exit_code(&self, cancelled: bool) -> i32 {
+        if cancelled {
+            EXIT_INTERRUPTED
+        } else if self.count(FileAction::Failed) > 0 {
+            EXIT_PARTIAL_FAILURE
+        } else if !self.files.iter().any(|f| f.action.is_transfer()) {
+            EXIT_NOTHING_TO_DO
+        } else {
+            0
+        }
+    }
+
+    // 扩展名为 .csv 时写出 CSV，否则写出 JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_csv = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+        let content = if is_csv {
+            let mut out = String::from("filename,direction,action,bytes,duration_ms,error\n");
+            for file in &self.files {
+                out.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    csv_field(&file.filename),
+                    match file.direction {
+                        SyncDirection::Upload => "upload",
+                        SyncDirection::Download => "download",
+                    },
+                    file.action.as_str(),
+                    file.bytes,
+                    file.duration_ms,
+                    csv_field(file.error.as_deref().unwrap_or_default())
+                ));
+            }
+            out
+        } else {
+            serde_json::to_string_pretty(&serde_json::json!({ "files": self.files }))
+                .context("Failed to serialize sync report")?
+        };
+        fs::write(path, content).with_context(|| format!("Failed to write sync report {:?}", path))
+    }
+
+    // 同步被中断时列出已完成与已中止的传输
+    fn cancelled_lines(&self) -> Vec<String> {
+        let completed: Vec<_> = self.files.iter().filter(|f| f.action.is_transfer()).collect();
+        let aborted: Vec<_> = self.files.iter().filter(|f| f.action == FileAction::Aborted).collect();
+        let mut lines = vec![t!("sync.cancelled_transfers", completed = completed.len(), aborted = aborted.len())];
+        lines.extend(completed.iter().map(|file| format!("  completed {:?} '{}'", file.direction, file.filename)));
+        lines.extend(aborted.iter().map(|file| format!("  aborted   {:?} '{}'", file.direction, file.filename)));
+        lines
+    }
+
+    // 安装的字体、固定的字体与冲突的处理结果，每行一条
+    fn detail_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let installed = self.installed_fonts();
+        if !installed.is_empty() {
+            lines.push(t!("sync.installed", count = installed.len()));
+            lines.extend(installed.iter().map(|font| format!("  {} ({})", font.label(), font.filename)));
+        }
+        let pinned: Vec<_> = self.files.iter().filter(|f| f.action == FileAction::Pinned).collect();
+        if !pinned.is_empty() {
+            lines.push(t!("sync.pinned", count = pinned.len()));
+            lines.extend(pinned.iter().map(|file| format!("  {:?} '{}'", file.direction, file.filename)));
+        }
+        if !self.conflicts.is_empty() {
+            lines.push(t!("sync.conflicts", count = self.conflicts.len()));
+            for conflict in &self.conflicts {
+                let resolution = i18n::resolution(&conflict.resolution);
+                lines.push(match &conflict.renamed_to {
+                    Some(new_name) => format!("  {:?} '{}': {} -> '{}'", conflict.direction, conflict.filename, resolution, new_name),
+                    None => format!("  {:?} '{}': {}", conflict.direction, conflict.filename, resolution),
+                });
+            }
+        }
+        lines
+    }
+
+    pub fn log_summary(&self) {
+        for line in self.detail_lines() {
+            info!("{}", line);
+        }
+    }
+
+    // sync 结束时直接输出的汇总：取消时先列出传输情况，再是明细，最后一行为统计
+    pub fn summary_text(&self, cancelled: bool) -> String {
+        let mut lines = if cancelled { self.cancelled_lines() } else { Vec::new() };
+        lines.extend(self.detail_lines());
+        lines.push(if cancelled {
+            t!("sync.cancelled", summary = self.summary())
+        } else {
+            t!("sync.complete", summary = self.summary())
+        });
+        lines.join("\n")
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_reports_and_picks_exit_codes() {
+        let mut report = SyncReport::default();
+        assert_eq!(report.exit_code(false), EXIT_NOTHING_TO_DO);
+        report.record_skipped("same.ttf", SyncDirection::Upload);
+        assert_eq!(report.exit_code(false), EXIT_NOTHING_TO_DO);
+        report.record_pinned("old.ttf", SyncDirection::Download);
+        assert_eq!(report.exit_code(false), EXIT_NOTHING_TO_DO);
+        report.record_transfer("a.ttf", SyncDirection::Download, FileAction::Downloaded, 10, Duration::from_millis(5));
+        assert_eq!(report.exit_code(false), 0);
+        report.record_failed("b, c.ttf", SyncDirection::Upload, Duration::ZERO, "server said \"no\"");
+        assert_eq!(report.exit_code(false), EXIT_PARTIAL_FAILURE);
+        assert_eq!(report.exit_code(true), EXIT_INTERRUPTED);
+
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("report.csv");
+        report.write(&csv).unwrap();
+        let content = fs::read_to_string(&csv).unwrap();
+        assert!(content.contains("a.ttf,download,downloaded,10,5,\n"));
+        assert!(content.contains("\"b, c.ttf\",upload,failed,0,0,\"server said \"\"no\"\"\"\n"));
+
+        let json = dir.path().join("report.json");
+        report.write(&json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(value["files"][1]["action"], "pinned");
+        assert_eq!(value["files"][2]["action"], "downloaded");
+        assert_eq!(value["files"][3]["direction"], "upload");
+
+        let font = InstalledFont { filename: "a.ttf".to_string(), family: Some("Noto Sans".to_string()), style: Some("Bold".to_string()) };
+        report.record_installed(&font);
+        assert_eq!(report.installed_fonts(), vec![font]);
+        report.write(&json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(value["files"][4]["family"], "Noto Sans");
+        assert!(value["files"][3].get("family").is_none());
+        assert_eq!(report.summary(), "0 uploaded, 1 downloaded, 1 installed, 1 skipped, 1 pinned, 1 failed");
+    }
+
+    #[test]
+    fn summary_text_lists_details_before_the_totals() {
+        let mut report = SyncReport::default();
+        report.record_pinned("old.ttf", SyncDirection::Download);
+        report.record_transfer("a.ttf", SyncDirection::Download, FileAction::Downloaded, 10, Duration::ZERO);
+        report.record_aborted("b.ttf", SyncDirection::Upload);
+
+        let text = report.summary_text(false);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "Pinned fonts kept at their local version (1):");
+        assert_eq!(lines[1], "  Download 'old.ttf'");
+        assert_eq!(lines.last().unwrap(), &format!("Synchronization complete: {}", report.summary()));
+
+        // 取消时先列出已完成与已中止的传输
+        let text = report.summary_text(true);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "Sync cancelled: 1 transfers completed, 1 aborted");
+        assert_eq!(lines[1], "  completed Download 'a.ttf'");
+        assert_eq!(lines[2], "  aborted   Upload 'b.ttf'");
+        assert!(lines.last().unwrap().starts_with("Synchronization cancelled: "));
+    }
+}
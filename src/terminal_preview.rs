@@ -0,0 +1,243 @@
+use anyhow::Result;
+use std::fmt::Write;
+
+use crate::preview::{self, Bitmap};
+
+// 覆盖率达到一半时点亮半块或盲文点
+const THRESHOLD: f32 = 0.5;
+// sixel 使用的灰阶数
+const SIXEL_LEVELS: usize = 16;
+
+// 终端中的显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TerminalMode {
+    /// 支持 sixel 的终端显示图像，否则使用半块字符
+    #[default]
+    Auto,
+    /// Unicode 半块字符，每个字符 1×2 像素
+    Blocks,
+    /// 盲文点字符，每个字符 2×4 像素
+    Braille,
+    /// sixel 灰度图像
+    Sixel,
+}
+
+impl TerminalMode {
+    // 按 TERM 与 TERM_PROGRAM 判断是否支持 sixel，无法确定时使用半块字符
+    pub fn resolve(self, env: impl Fn(&str) -> Option<String>) -> TerminalMode {
+        if self != TerminalMode::Auto {
+            return self;
+        }
+        let term = env("TERM").unwrap_or_default();
+        let program = env("TERM_PROGRAM").unwrap_or_default();
+        if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") || program == "WezTerm" {
+            TerminalMode::Sixel
+        } else {
+            TerminalMode::Blocks
+        }
+    }
+
+    // 字符单元对应的像素宽度；sixel 按常见的 8 像素宽字符估算
+    fn pixels_per_column(self) -> usize {
+        match self {
+            TerminalMode::Braille => 2,
+            TerminalMode::Sixel => 8,
+            TerminalMode::Auto | TerminalMode::Blocks => 1,
+        }
+    }
+
+    // 半块约 10 行、盲文约 8 行高
+    pub fn default_size(self) -> f32 {
+        match self {
+            TerminalMode::Auto | TerminalMode::Blocks => 20.0,
+            TerminalMode::Braille => 32.0,
+            TerminalMode::Sixel => 48.0,
+        }
+    }
+}
+
+// 按终端宽度折行后逐行渲染；mode 不能为 Auto
+pub fn render(data: &[u8], text: &str, size: f32, mode: TerminalMode, columns: usize) -> Result<String> {
+    let padding = (size * 0.25).ceil();
+    let available = (columns * mode.pixels_per_column()) as f32 - padding * 2.0;
+    let mut output = String::new();
+    for line in wrap(data, text, size, available)? {
+        let bitmap = trim_vertical_padding(preview::render(data, &line, size)?, padding as usize);
+        let rows = match mode {
+            TerminalMode::Sixel => {
+                output.push_str(&sixel(&bitmap));
+                continue;
+            }
+            TerminalMode::Braille => braille(&bitmap),
+            TerminalMode::Auto | TerminalMode::Blocks => half_blocks(&bitmap),
+        };
+        // 字符方式下去掉每行上下的空白行，行间只留一个空行
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(rows.trim_start_matches('\n').trim_end_matches('\n'));
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+// 优先在空白处折行，单词本身过宽时（如没有空格的中文）按字符折行
+fn wrap(data: &[u8], text: &str, size: f32, available: f32) -> Result<Vec<String>> {
+    let fits = |line: &str| -> Result<bool> { Ok(preview::text_width(data, line, size)? <= available) };
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if fits(&candidate)? {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            let candidate = format!("{}{}", line, c);
+            if !line.is_empty() && !fits(&candidate)? {
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+// 行与行之间不需要 PNG 预览那样的上下留白
+fn trim_vertical_padding(bitmap: Bitmap, padding: usize) -> Bitmap {
+    let padding = padding.min(bitmap.height.saturating_sub(1) / 2);
+    let height = bitmap.height - padding * 2;
+    let start = padding * bitmap.width;
+    let coverage = bitmap.coverage[start..start + height * bitmap.width].to_vec();
+    Bitmap { width: bitmap.width, height, coverage }
+}
+
+fn half_blocks(bitmap: &Bitmap) -> String {
+    let mut output = String::new();
+    for y in (0..bitmap.height).step_by(2) {
+        let row: String = (0..bitmap.width)
+            .map(|x| match (bitmap.at(x, y) >= THRESHOLD, bitmap.at(x, y + 1) >= THRESHOLD) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            })
+            .collect();
+        output.push_str(row.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+fn braille(bitmap: &Bitmap) -> String {
+    // 盲文点在 2×4 单元中对应的位
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut output = String::new();
+    for y in (0..bitmap.height).step_by(4) {
+        let row: String = (0..bitmap.width)
+            .step_by(2)
+            .map(|x| {
+                let bits = (0..4)
+                    .flat_map(|dy| (0..2).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| bitmap.at(x + dx, y + dy) >= THRESHOLD)
+                    .fold(0, |bits, (dx, dy)| bits | DOTS[dy][dx]);
+                if bits == 0 {
+                    ' '
+                } else {
+                    char::from_u32(0x2800 + bits).unwrap_or(' ')
+                }
+            })
+            .collect();
+        output.push_str(row.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+// 白底黑字的灰度 sixel 图像，每 6 行像素为一个带，带内按灰阶逐色绘制
+fn sixel(bitmap: &Bitmap) -> String {
+    let level = |x: usize, y: usize| (bitmap.at(x, y) * (SIXEL_LEVELS - 1) as f32).round() as usize;
+    let mut output = format!("\x1bPq\"1;1;{};{}", bitmap.width, bitmap.height);
+    for index in 0..SIXEL_LEVELS {
+        let value = 100 - index * 100 / (SIXEL_LEVELS - 1);
+        let _ = write!(output, "#{};2;{};{};{}", index, value, value, value);
+    }
+    for band in (0..bitmap.height).step_by(6) {
+        for index in 0..SIXEL_LEVELS {
+            let row: Vec<u8> = (0..bitmap.width)
+                .map(|x| {
+                    (0..6)
+                        .filter(|&dy| band + dy < bitmap.height && level(x, band + dy) == index)
+                        .fold(0u8, |bits, dy| bits | (1 << dy))
+                })
+                .collect();
+            if row.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            let _ = write!(output, "#{}", index);
+            // 连续相同的列按 !<次数><字符> 压缩
+            let mut x = 0;
+            while x < row.len() {
+                let run = row[x..].iter().take_while(|&&bits| bits == row[x]).count();
+                let c = char::from(63 + row[x]);
+                if run > 3 {
+                    let _ = write!(output, "!{}{}", run, c);
+                } else {
+                    output.extend(std::iter::repeat_n(c, run));
+                }
+                x += run;
+            }
+            output.push('$');
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(rows: &[&str]) -> Bitmap {
+        let width = rows[0].len();
+        let coverage = rows.iter().flat_map(|row| row.chars().map(|c| if c == '#' { 1.0 } else { 0.0 })).collect();
+        Bitmap { width, height: rows.len(), coverage }
+    }
+
+    #[test]
+    fn encodes_bitmaps_for_the_terminal() {
+        let image = bitmap(&["#.#.", "##..", "..#.", "...."]);
+        assert_eq!(half_blocks(&image), "█▄▀\n  ▀\n");
+        assert_eq!(braille(&image), "\u{2813}\u{2805}\n");
+        let sixel = sixel(&image);
+        assert!(sixel.starts_with("\x1bPq\"1;1;4;4") && sixel.ends_with("\x1b\\\n"));
+        assert!(sixel.contains("#15BAD?$"));
+
+        let env = |term: &'static str| move |name: &str| (name == "TERM").then(|| term.to_string());
+        assert_eq!(TerminalMode::Auto.resolve(env("xterm-256color")), TerminalMode::Blocks);
+        assert_eq!(TerminalMode::Auto.resolve(env("foot")), TerminalMode::Sixel);
+        assert_eq!(TerminalMode::Braille.resolve(env("foot")), TerminalMode::Braille);
+    }
+
+    #[test]
+    fn wraps_text_to_the_terminal_width() {
+        // 测试字体中每个字形（包括未映射的空格）宽一个字身
+        let font = crate::font_metadata::tests::square_font();
+        assert_eq!(wrap(&font, "AA AA A", 10.0, 50.0).unwrap(), ["AA AA", "A"]);
+        assert_eq!(wrap(&font, "AAAAA", 10.0, 25.0).unwrap(), ["AA", "AA", "A"]);
+
+        let output = render(&font, "A", 8.0, TerminalMode::Blocks, 80).unwrap();
+        assert!(output.contains('█'));
+        assert_eq!(output.lines().count(), 3);
+        let output = render(&font, "A A", 8.0, TerminalMode::Blocks, 14).unwrap();
+        assert_eq!(output.lines().count(), 7);
+    }
+}
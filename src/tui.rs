@@ -0,0 +1,853 @@
+use anyhow::{Context, Result};
+use log::{error, info};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Gauge, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{FontInfo, FontQuery};
+use crate::client::{self, ApiClient, SyncOptions};
+use crate::client_state::ClientState;
+use crate::ignore::IgnoreRules;
+use crate::progress::{Operation, Progress, ProgressEvent};
+use crate::sync_report::{FileAction, SyncReport};
+use crate::utils::{self, format_file_size, ChangeOrigin};
+
+// 字体在本地与服务器两侧的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowStatus {
+    LocalOnly,
+    ServerOnly,
+    Identical,
+    // 自上次同步后只有本地修改
+    LocalChanged,
+    // 自上次同步后只有服务器修改
+    ServerChanged,
+    Conflict,
+    // 固定了版本，与服务器内容不同
+    Pinned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowAction {
+    Upload,
+    Download,
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+struct LocalFont {
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+}
+
+// 开始传输后每个选中字体的进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Queued,
+    Running { done: u64, total: u64 },
+    Done,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+struct FontRow {
+    name: String,
+    local: Option<LocalFont>,
+    remote_size: Option<u64>,
+    remote_sha256: Option<String>,
+    // 端到端加密字体的明文哈希
+    remote_plaintext_sha256: Option<String>,
+    status: RowStatus,
+    action: RowAction,
+    transfer: Option<Transfer>,
+}
+
+impl FontRow {
+    fn default_action(status: RowStatus) -> RowAction {
+        match status {
+            RowStatus::LocalOnly | RowStatus::LocalChanged => RowAction::Upload,
+            RowStatus::ServerOnly | RowStatus::ServerChanged => RowAction::Download,
+            // 冲突默认跳过，由用户逐个决定
+            RowStatus::Identical | RowStatus::Conflict | RowStatus::Pinned => RowAction::Skip,
+        }
+    }
+
+    // 只允许对存在源文件的一侧执行传输
+    fn set_action(&mut self, action: RowAction) {
+        let allowed = match action {
+            RowAction::Upload => self.local.is_some(),
+            RowAction::Download => self.remote_sha256.is_some(),
+            RowAction::Skip => true,
+        };
+        if allowed {
+            self.action = action;
+        }
+    }
+}
+
+pub async fn run_tui(
+    server_url: String,
+    local_dir: String,
+    install: bool,
+    options: SyncOptions,
+) -> Result<()> {
+    if !console::Term::stdout().is_term() {
+        return Err(anyhow::anyhow!("The TUI requires an interactive terminal"));
+    }
+
+    let local_dir = PathBuf::from(local_dir);
+    if !local_dir.exists() {
+        tokio::fs::create_dir_all(&local_dir)
+            .await
+            .context("Failed to create local directory")?;
+    }
+
+    let local_fonts = scan_local_fonts(&local_dir, options.follow_symlinks);
+    let server_fonts = ApiClient::new(&server_url)?.list_fonts_hashed(&FontQuery::default()).await?;
+    let state = ClientState::load();
+    let last_synced = state
+        .server(&server_url)
+        .map(|s| s.synced.clone())
+        .unwrap_or_default();
+    let rows = build_rows(local_fonts, server_fonts.fonts, &last_synced, &state);
+
+    // 界面在独立线程中读取按键并重绘，传输在运行时中进行，两者经通道交换选择结果与进度
+    let title = format!("fontsync tui  server: {}  local: {}", server_url, local_dir.display());
+    let (selection_tx, selection_rx) = oneshot::channel();
+    let (progress_tx, progress_rx) = mpsc::channel();
+    let (update_tx, update_rx) = mpsc::channel();
+    let cancel = options.cancel.clone();
+    let ui = tokio::task::spawn_blocking(move || {
+        run_terminal(App::new(title, rows), selection_tx, progress_rx, update_rx, cancel)
+    });
+
+    let Ok(Some(rows)) = selection_rx.await else {
+        ui.await.context("TUI thread panicked")??;
+        info!("Quit without applying changes");
+        return Ok(());
+    };
+
+    let progress = Progress::channel(progress_tx);
+    let summary = match apply_actions(&server_url, &local_dir, &rows, &options, &progress, &update_tx).await {
+        Ok((uploaded, downloaded, failed)) => {
+            let mut summary = format!("Sync complete: {} uploaded, {} downloaded, {} failed", uploaded, downloaded, failed);
+            if install && downloaded > 0 && !options.cancel.is_cancelled() {
+                let _ = update_tx.send(Update::Message("Installing downloaded fonts...".to_string()));
+                let mut report = SyncReport::with_progress(progress.clone());
+                match client::install_downloaded_fonts(&local_dir, &options, &mut report).await {
+                    Ok((installed, failed)) => {
+                        summary = format!("{}; installed {}, {} failed to install", summary, installed, failed)
+                    }
+                    Err(e) => summary = format!("{}; installation failed: {:#}", summary, e),
+                }
+            }
+            summary
+        }
+        Err(e) => format!("Sync failed: {:#}", e),
+    };
+    let _ = update_tx.send(Update::Finished(summary.clone()));
+    ui.await.context("TUI thread panicked")??;
+    println!("{}", summary);
+
+    Ok(())
+}
+
+fn scan_local_fonts(local_dir: &Path, follow_symlinks: bool) -> Vec<(String, LocalFont)> {
+    let mut fonts = Vec::new();
+    let ignore = IgnoreRules::for_dir(local_dir);
+
+    for path in utils::font_files(local_dir, follow_symlinks, &mut HashSet::new()) {
+        let path = path.as_path();
+        if ignore.is_ignored(path) {
+            continue;
+        }
+
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        match utils::calculate_sha256(path) {
+            Ok(sha256) => fonts.push((
+                name,
+                LocalFont {
+                    path: path.to_path_buf(),
+                    size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                    sha256,
+                },
+            )),
+            Err(e) => error!("Failed to calculate SHA256 for '{}': {}", name, e),
+        }
+    }
+
+    fonts
+}
+
+fn build_rows(
+    local_fonts: Vec<(String, LocalFont)>,
+    server_fonts: Vec<FontInfo>,
+    last_synced: &HashMap<String, String>,
+    state: &ClientState,
+) -> Vec<FontRow> {
+    let mut rows: BTreeMap<String, FontRow> = BTreeMap::new();
+
+    for (name, local) in local_fonts {
+        rows.entry(name.clone()).or_insert(FontRow {
+            name,
+            local: Some(local),
+            remote_size: None,
+            remote_sha256: None,
+            remote_plaintext_sha256: None,
+            status: RowStatus::LocalOnly,
+            action: RowAction::Upload,
+            transfer: None,
+        });
+    }
+
+    for font in server_fonts {
+        let row = rows.entry(font.name.clone()).or_insert(FontRow {
+            name: font.name.clone(),
+            local: None,
+            remote_size: None,
+            remote_sha256: None,
+            remote_plaintext_sha256: None,
+            status: RowStatus::ServerOnly,
+            action: RowAction::Download,
+            transfer: None,
+        });
+        row.remote_size = Some(font.size);
+        row.remote_plaintext_sha256 = font.plaintext_sha256;
+        row.remote_sha256 = Some(font.sha256);
+    }
+
+    rows.into_values()
+        .map(|mut row| {
+            let remote_content = row.remote_plaintext_sha256.as_ref().or(row.remote_sha256.as_ref());
+            row.status = match (&row.local, remote_content) {
+                (Some(local), Some(remote)) if &local.sha256 == remote => RowStatus::Identical,
+                (_, Some(remote)) if state.holds_pinned(&row.name, remote) => RowStatus::Pinned,
+                (Some(local), Some(remote)) => {
+                    match utils::classify_change(last_synced.get(&row.name).map(String::as_str), &local.sha256, remote) {
+                        ChangeOrigin::LocalOnly => RowStatus::LocalChanged,
+                        ChangeOrigin::RemoteOnly => RowStatus::ServerChanged,
+                        ChangeOrigin::Both => RowStatus::Conflict,
+                    }
+                }
+                (Some(_), None) => RowStatus::LocalOnly,
+                _ => RowStatus::ServerOnly,
+            };
+            row.action = FontRow::default_action(row.status);
+            row
+        })
+        .collect()
+}
+
+// 传输任务发给界面的消息
+#[derive(Debug)]
+enum Update {
+    // 错误与提示，显示在日志区
+    Message(String),
+    // 全部结束，附带汇总
+    Finished(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Phase {
+    Selecting,
+    Transferring,
+    Finished(String),
+}
+
+// 选择阶段按键的结果
+enum Choice {
+    Apply,
+    Quit,
+}
+
+// 日志区保留的行数
+const LOG_LINES: usize = 200;
+
+struct App {
+    title: String,
+    rows: Vec<FontRow>,
+    table: TableState,
+    phase: Phase,
+    log: Vec<String>,
+    // 服务器繁忙等临时状态，显示在总进度条上
+    status: Option<String>,
+}
+
+impl App {
+    fn new(title: String, rows: Vec<FontRow>) -> Self {
+        let table = TableState::default().with_selected((!rows.is_empty()).then_some(0));
+        Self { title, rows, table, phase: Phase::Selecting, log: Vec::new(), status: None }
+    }
+
+    fn selected_row(&mut self) -> Option<&mut FontRow> {
+        self.table.selected().and_then(|index| self.rows.get_mut(index))
+    }
+
+    fn handle_selection_key(&mut self, code: KeyCode, page: usize) -> Option<Choice> {
+        let last = self.rows.len().saturating_sub(1);
+        let selected = self.table.selected().unwrap_or(0);
+        let select = |index: usize| Some(index.min(last));
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.table.select(select(selected.saturating_sub(1))),
+            KeyCode::Down | KeyCode::Char('j') => self.table.select(select(selected + 1)),
+            KeyCode::PageUp => self.table.select(select(selected.saturating_sub(page))),
+            KeyCode::PageDown => self.table.select(select(selected + page)),
+            KeyCode::Home => self.table.select(select(0)),
+            KeyCode::End => self.table.select(select(last)),
+            // 跳到下一个冲突，到末尾后从头查找
+            KeyCode::Char('n') | KeyCode::Tab => {
+                let next = (1..=self.rows.len())
+                    .map(|step| (selected + step) % self.rows.len())
+                    .find(|&index| self.rows[index].status == RowStatus::Conflict);
+                if next.is_some() {
+                    self.table.select(next);
+                }
+            }
+            KeyCode::Char('u') => self.selected_row().into_iter().for_each(|row| row.set_action(RowAction::Upload)),
+            KeyCode::Char('d') => self.selected_row().into_iter().for_each(|row| row.set_action(RowAction::Download)),
+            KeyCode::Char('s') | KeyCode::Char(' ') => {
+                self.selected_row().into_iter().for_each(|row| row.set_action(RowAction::Skip))
+            }
+            KeyCode::Char('a') => {
+                self.selected_row().into_iter().for_each(|row| row.action = FontRow::default_action(row.status))
+            }
+            KeyCode::Enter => return Some(Choice::Apply),
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Choice::Quit),
+            _ => {}
+        }
+        None
+    }
+
+    // 开始传输：选中的字体排队，其余保持原样
+    fn start_transfers(&mut self) {
+        for row in &mut self.rows {
+            row.transfer = (row.action != RowAction::Skip).then_some(Transfer::Queued);
+        }
+        self.phase = Phase::Transferring;
+    }
+
+    fn apply_progress(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Started { operation: Operation::Install, files } => {
+                self.status = Some(format!("Installing {} fonts", files));
+            }
+            ProgressEvent::FileProgress { operation, name, done, total } if operation != Operation::Install => {
+                self.status = None;
+                if let Some(row) = self.rows.iter_mut().find(|row| row.name == name) {
+                    row.transfer = Some(Transfer::Running { done, total });
+                }
+            }
+            ProgressEvent::FileFinished { operation, name, action } if operation != Operation::Install => {
+                if let Some(row) = self.rows.iter_mut().find(|row| row.name == name) {
+                    row.transfer = Some(match action {
+                        FileAction::Failed | FileAction::Aborted => Transfer::Failed,
+                        FileAction::Skipped => Transfer::Skipped,
+                        _ => Transfer::Done,
+                    });
+                }
+            }
+            ProgressEvent::ServerBusy { name, retry_in, .. } => {
+                self.status = Some(format!("{}: server busy, retrying in {}s", name, retry_in));
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_update(&mut self, update: Update) {
+        match update {
+            Update::Message(message) => self.push_log(message),
+            Update::Finished(summary) => {
+                self.push_log(summary.clone());
+                self.status = None;
+                self.phase = Phase::Finished(summary);
+            }
+        }
+    }
+
+    fn push_log(&mut self, message: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.remove(0);
+        }
+        self.log.push(message);
+    }
+
+    // 已结束与全部排队的传输数
+    fn transfer_counts(&self) -> (usize, usize) {
+        let queued = self.rows.iter().filter_map(|row| row.transfer);
+        let finished = queued.clone().filter(|t| matches!(t, Transfer::Done | Transfer::Skipped | Transfer::Failed));
+        (finished.count(), queued.count())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let log_height = match self.phase {
+            Phase::Selecting => 0,
+            _ => 6,
+        };
+        let gauge_height = match self.phase {
+            Phase::Selecting => 0,
+            _ => 1,
+        };
+        let [title_area, table_area, gauge_area, log_area, summary_area, help_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(gauge_height),
+            Constraint::Length(log_height),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(Line::from(self.title.as_str()).bold(), title_area);
+
+        let header = Row::new(["Font", "Local", "Server", "Status", "Action", "Progress"]).bold();
+        let rows = self.rows.iter().map(|row| {
+            let local = row.local.as_ref().map(|l| format_file_size(l.size)).unwrap_or_else(|| "-".to_string());
+            let remote = row.remote_size.map(format_file_size).unwrap_or_else(|| "-".to_string());
+            Row::new([
+                Cell::from(row.name.as_str()),
+                Cell::from(local),
+                Cell::from(remote),
+                Cell::from(status_span(row.status)),
+                Cell::from(action_span(row.action)),
+                Cell::from(transfer_span(row.transfer)),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(12),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(11),
+                Constraint::Length(8),
+                Constraint::Length(18),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered())
+        .row_highlight_style(Style::new().reversed())
+        .highlight_symbol("> ");
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        if gauge_height > 0 {
+            let (finished, total) = self.transfer_counts();
+            let ratio = if total == 0 { 1.0 } else { finished as f64 / total as f64 };
+            let label = match &self.status {
+                Some(status) => format!("{}/{} files  {}", finished, total, status),
+                None => format!("{}/{} files", finished, total),
+            };
+            frame.render_widget(Gauge::default().ratio(ratio).label(label).cyan(), gauge_area);
+        }
+        if log_height > 0 {
+            let visible = (log_height as usize).saturating_sub(2);
+            let lines: Vec<Line> = self.log.iter().rev().take(visible).rev().map(|l| Line::from(l.as_str())).collect();
+            frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Log")), log_area);
+        }
+
+        let count = |action| self.rows.iter().filter(|r| r.action == action).count();
+        let status_count = |status| self.rows.iter().filter(|r| r.status == status).count();
+        frame.render_widget(
+            Line::from(format!(
+                "{} upload, {} download, {} conflict, {} pinned",
+                count(RowAction::Upload),
+                count(RowAction::Download),
+                status_count(RowStatus::Conflict),
+                status_count(RowStatus::Pinned)
+            )),
+            summary_area,
+        );
+        let help = match self.phase {
+            Phase::Selecting => {
+                "[↑/↓] move  [n] next conflict  [u] upload  [d] download  [s] skip  [a] default  [Enter] apply  [q] quit"
+            }
+            Phase::Transferring => "[q] cancel after the current file",
+            Phase::Finished(_) => "[Enter/q] exit",
+        };
+        frame.render_widget(Line::from(help).dim(), help_area);
+    }
+}
+
+fn status_span(status: RowStatus) -> Span<'static> {
+    match status {
+        RowStatus::LocalOnly => Span::raw("local"),
+        RowStatus::ServerOnly => Span::raw("server"),
+        RowStatus::Identical => Span::raw("same").dim(),
+        RowStatus::LocalChanged => Span::raw("local edit"),
+        RowStatus::ServerChanged => Span::raw("server edit"),
+        RowStatus::Conflict => Span::raw("CONFLICT").yellow(),
+        RowStatus::Pinned => Span::raw("pinned").cyan(),
+    }
+}
+
+fn action_span(action: RowAction) -> Span<'static> {
+    match action {
+        RowAction::Upload => Span::raw("upload").green(),
+        RowAction::Download => Span::raw("download").cyan(),
+        RowAction::Skip => Span::raw("skip").dim(),
+    }
+}
+
+fn transfer_span(transfer: Option<Transfer>) -> Span<'static> {
+    match transfer {
+        None => Span::raw(""),
+        Some(Transfer::Queued) => Span::raw("queued").dim(),
+        Some(Transfer::Running { done, total: 0 }) => Span::raw(format_file_size(done)),
+        Some(Transfer::Running { done, total }) => {
+            Span::raw(format!("{:>3}% {}", done * 100 / total.max(1), format_file_size(total))).cyan()
+        }
+        Some(Transfer::Done) => Span::raw("done").green(),
+        Some(Transfer::Skipped) => Span::raw("skipped").dim(),
+        Some(Transfer::Failed) => Span::raw("failed").red(),
+    }
+}
+
+// 在备用屏幕中运行界面直到用户退出；选择阶段结束时经 selection 发出选中的行（放弃时为 None）
+fn run_terminal(
+    mut app: App,
+    selection: oneshot::Sender<Option<Vec<FontRow>>>,
+    progress: mpsc::Receiver<ProgressEvent>,
+    updates: mpsc::Receiver<Update>,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let mut terminal = ratatui::try_init().context("Failed to initialize the terminal")?;
+    let mut selection = Some(selection);
+    let result = (|| -> Result<()> {
+        loop {
+            while let Ok(event) = progress.try_recv() {
+                app.apply_progress(event);
+            }
+            while let Ok(update) = updates.try_recv() {
+                app.apply_update(update);
+            }
+            let size = terminal.draw(|frame| app.draw(frame))?.area;
+
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match app.phase {
+                Phase::Selecting => {
+                    // 表格之外的标题、表头、边框与底部各占若干行
+                    let page = (size.height as usize).saturating_sub(6).max(1);
+                    match app.handle_selection_key(key.code, page) {
+                        Some(Choice::Apply) => {
+                            app.start_transfers();
+                            if let Some(selection) = selection.take() {
+                                let _ = selection.send(Some(app.rows.clone()));
+                            }
+                        }
+                        Some(Choice::Quit) => {
+                            if let Some(selection) = selection.take() {
+                                let _ = selection.send(None);
+                            }
+                            return Ok(());
+                        }
+                        None => {}
+                    }
+                }
+                Phase::Transferring => {
+                    if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) && !cancel.is_cancelled() {
+                        cancel.cancel();
+                        app.push_log("Cancelling after the current file...".to_string());
+                    }
+                }
+                Phase::Finished(_) => {
+                    if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+// 依次执行选定的传输，返回上传、下载成功与失败的数量；进度与错误发往界面
+async fn apply_actions(
+    server_url: &str,
+    local_dir: &Path,
+    rows: &[FontRow],
+    options: &SyncOptions,
+    progress: &Progress,
+    updates: &mpsc::Sender<Update>,
+) -> Result<(usize, usize, usize)> {
+    let message = |text: String| {
+        let _ = updates.send(Update::Message(text));
+    };
+    let e2e_key = options.e2e_key.as_ref();
+    let signed_hashes = client::verified_manifest(server_url, options).await?;
+    let api = ApiClient::new(server_url)?.with_progress(progress.clone());
+    let mut uploaded = 0;
+    let mut downloaded = 0;
+    let mut failed = 0;
+    let mut synced = Vec::new();
+
+    for row in rows.iter().filter(|r| r.action != RowAction::Skip) {
+        if options.cancel.is_cancelled() {
+            message("Cancelled".to_string());
+            break;
+        }
+        match row.action {
+            RowAction::Upload => {
+                let Some(local) = &row.local else { continue };
+                let uploaded_font = api
+                    .upload_changed_font(&local.path, &row.name, &local.sha256, e2e_key, true, row.remote_sha256.as_deref())
+                    .await;
+                match uploaded_font {
+                    Ok(_) => {
+                        uploaded += 1;
+                        synced.push((row.name.clone(), local.sha256.clone()));
+                        progress.file_finished(Operation::Upload, &row.name, FileAction::Uploaded);
+                    }
+                    Err(e) => {
+                        message(format!("Failed to upload '{}': {}", row.name, e));
+                        progress.file_finished(Operation::Upload, &row.name, FileAction::Failed);
+                        failed += 1;
+                    }
+                }
+            }
+            RowAction::Download => {
+                let Some(expected) = &row.remote_sha256 else { continue };
+                let fail = |text: String| {
+                    message(text);
+                    progress.file_finished(Operation::Download, &row.name, FileAction::Failed);
+                };
+                if let Some(hashes) = &signed_hashes
+                    && hashes.get(&row.name) != Some(expected)
+                {
+                    fail(format!("Refusing '{}': does not match the signed manifest", row.name));
+                    failed += 1;
+                    continue;
+                }
+                if row.remote_plaintext_sha256.is_some() && e2e_key.is_none() {
+                    message(format!("Skipping '{}': end-to-end encrypted and no team key", row.name));
+                    progress.file_finished(Operation::Download, &row.name, FileAction::Skipped);
+                    continue;
+                }
+                // 覆盖时写回原位置，否则写入本地目录
+                let target = row
+                    .local
+                    .as_ref()
+                    .map(|l| l.path.clone())
+                    .unwrap_or_else(|| local_dir.join(&row.name));
+                let fetched = match &row.local {
+                    Some(local) if row.remote_plaintext_sha256.is_none() => {
+                        api.download_font_from_base(&row.name, &local.path, &target, expected).await
+                    }
+                    _ => api.download_font(&row.name, &target, expected).await,
+                };
+                if let Err(e) = fetched {
+                    fail(format!("Failed to download '{}': {}", row.name, e));
+                    failed += 1;
+                    continue;
+                }
+                match utils::calculate_sha256(&target) {
+                    Ok(actual) if &actual == expected => {
+                        let decrypted = match (&row.remote_plaintext_sha256, e2e_key) {
+                            (Some(plaintext_sha256), Some(key)) => {
+                                client::decrypt_downloaded_font(&target, key, plaintext_sha256)
+                            }
+                            _ => Ok(()),
+                        };
+                        match decrypted {
+                            Ok(()) => {
+                                downloaded += 1;
+                                let content = row.remote_plaintext_sha256.as_ref().unwrap_or(expected);
+                                synced.push((row.name.clone(), content.clone()));
+                                progress.file_finished(Operation::Download, &row.name, FileAction::Downloaded);
+                            }
+                            Err(e) => {
+                                fail(format!("Failed to decrypt '{}': {}", row.name, e));
+                                let _ = fs::remove_file(&target);
+                                failed += 1;
+                            }
+                        }
+                    }
+                    Ok(actual) => {
+                        fail(format!("SHA256 mismatch for '{}': expected={}, got={}", row.name, expected, actual));
+                        let _ = fs::remove_file(&target);
+                        failed += 1;
+                    }
+                    Err(e) => {
+                        fail(format!("Failed to verify '{}': {}", row.name, e));
+                        failed += 1;
+                    }
+                }
+            }
+            RowAction::Skip => {}
+        }
+    }
+
+    if let Err(e) = ClientState::record_synced(server_url, synced) {
+        error!("Failed to save client state: {}", e);
+    }
+    Ok((uploaded, downloaded, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_state::FontPin;
+
+    fn local(sha256: &str) -> LocalFont {
+        LocalFont {
+            path: PathBuf::from("unused"),
+            size: 1,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    fn remote(name: &str, sha256: &str) -> FontInfo {
+        FontInfo {
+            name: name.to_string(),
+            size: 1,
+            mime_type: "font/ttf".to_string(),
+            sha256: sha256.to_string(),
+            status: None,
+            modified: None,
+            plaintext_sha256: None,
+            embedding: None,
+            restricted: false,
+            family: None,
+            style: None,
+            version: None,
+            unicode_ranges: Vec::new(),
+            tags: Default::default(),
+            uploaded_by: None,
+        }
+    }
+
+    #[test]
+    fn build_rows_assigns_default_actions() {
+        let last_synced = HashMap::from([
+            ("e.ttf".to_string(), "5".to_string()),
+            ("f.ttf".to_string(), "6".to_string()),
+        ]);
+        let rows = build_rows(
+            vec![
+                ("a.ttf".to_string(), local("1")),
+                ("b.ttf".to_string(), local("2")),
+                ("c.ttf".to_string(), local("3")),
+                ("e.ttf".to_string(), local("5-edited")),
+                ("f.ttf".to_string(), local("6")),
+                ("g.ttf".to_string(), local("7")),
+                ("h.ttf".to_string(), local("8")),
+            ],
+            vec![
+                remote("b.ttf", "2"),
+                remote("c.ttf", "x"),
+                remote("d.ttf", "4"),
+                remote("e.ttf", "5"),
+                remote("f.ttf", "6-edited"),
+                remote("g.ttf", "7-edited"),
+                remote("h.ttf", "8-edited"),
+            ],
+            &last_synced,
+            &ClientState {
+                pins: BTreeMap::from([
+                    ("g.ttf".to_string(), FontPin { sha256: None, pinned_at: 1 }),
+                    // 服务器上恰好是固定的版本时照常同步
+                    ("h.ttf".to_string(), FontPin { sha256: Some("8-edited".to_string()), pinned_at: 1 }),
+                ]),
+                ..Default::default()
+            },
+        );
+
+        let summary: Vec<_> = rows.iter().map(|r| (r.name.as_str(), r.status, r.action)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a.ttf", RowStatus::LocalOnly, RowAction::Upload),
+                ("b.ttf", RowStatus::Identical, RowAction::Skip),
+                ("c.ttf", RowStatus::Conflict, RowAction::Skip),
+                ("d.ttf", RowStatus::ServerOnly, RowAction::Download),
+                ("e.ttf", RowStatus::LocalChanged, RowAction::Upload),
+                ("f.ttf", RowStatus::ServerChanged, RowAction::Download),
+                ("g.ttf", RowStatus::Pinned, RowAction::Skip),
+                ("h.ttf", RowStatus::Conflict, RowAction::Skip),
+            ]
+        );
+
+        // 服务器没有的字体不能选择下载
+        let mut row = rows[0].clone();
+        row.set_action(RowAction::Download);
+        assert_eq!(row.action, RowAction::Upload);
+    }
+
+    fn sample_app() -> App {
+        let rows = build_rows(
+            vec![("a.ttf".to_string(), local("1")), ("b.ttf".to_string(), local("2"))],
+            vec![remote("b.ttf", "x"), remote("c.ttf", "3")],
+            &HashMap::new(),
+            &ClientState::default(),
+        );
+        App::new("fontsync tui".to_string(), rows)
+    }
+
+    #[test]
+    fn keys_mark_rows_and_jump_to_conflicts() {
+        let mut app = sample_app();
+        assert!(app.handle_selection_key(KeyCode::Char('n'), 10).is_none());
+        assert_eq!(app.table.selected(), Some(1));
+        app.handle_selection_key(KeyCode::Char('d'), 10);
+        assert_eq!(app.rows[1].action, RowAction::Download);
+        app.handle_selection_key(KeyCode::End, 10);
+        app.handle_selection_key(KeyCode::Char('s'), 10);
+        assert_eq!(app.rows[2].action, RowAction::Skip);
+        assert!(matches!(app.handle_selection_key(KeyCode::Enter, 10), Some(Choice::Apply)));
+    }
+
+    #[test]
+    fn progress_events_update_the_dashboard() {
+        let mut app = sample_app();
+        app.rows[1].set_action(RowAction::Download);
+        app.start_transfers();
+        assert_eq!(app.transfer_counts(), (0, 3));
+
+        app.apply_progress(ProgressEvent::FileProgress { operation: Operation::Download, name: "c.ttf".to_string(), done: 512, total: 2048 });
+        assert_eq!(app.rows[2].transfer, Some(Transfer::Running { done: 512, total: 2048 }));
+        app.apply_progress(ProgressEvent::FileFinished { operation: Operation::Upload, name: "a.ttf".to_string(), action: FileAction::Uploaded });
+        app.apply_progress(ProgressEvent::FileFinished { operation: Operation::Download, name: "b.ttf".to_string(), action: FileAction::Failed });
+        // 安装阶段的事件不改变传输状态
+        app.apply_progress(ProgressEvent::FileFinished { operation: Operation::Install, name: "c.ttf".to_string(), action: FileAction::Installed });
+        assert_eq!(app.transfer_counts(), (2, 3));
+
+        let backend = ratatui::backend::TestBackend::new(100, 16);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains(" 25% "), "{}", screen);
+        assert!(screen.contains("failed"));
+        assert!(screen.contains("2/3 files"));
+
+        app.apply_update(Update::Message("Failed to download 'b.ttf': boom".to_string()));
+        app.apply_update(Update::Finished("Sync complete: 1 uploaded, 0 downloaded, 1 failed".to_string()));
+        assert!(matches!(app.phase, Phase::Finished(_)));
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("boom"));
+        assert!(screen.contains("[Enter/q] exit"));
+    }
+}
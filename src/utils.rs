@@ -1,10 +1,18 @@
 use anyhow::{Context, Result};
-use log::error;
+use log::{debug, error, warn};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::time::UNIX_EPOCH;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::font_metadata::{self, EmbeddingPermission, FontDescriptor};
+use crate::i18n::{self, t};
+use crate::ignore::IgnoreRules;
 
 pub fn calculate_sha256(path: &Path) -> Result<String> {
     let mut file = File::open(path)
@@ -28,6 +36,121 @@ pub fn calculate_sha256(path: &Path) -> Result<String> {
     Ok(hex::encode(result))
 }
 
+// 写入中的文件后缀，扩展名不再是字体格式，不会被扫描或安装
+pub const PARTIAL_SUFFIX: &str = ".fontsync.partial";
+
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    path.with_file_name(name)
+}
+
+// 先写入同目录下的 <name>.fontsync.partial 并刷到磁盘，校验通过后再替换目标，
+// 中断或损坏的写入不会留下不完整的字体
+pub fn write_atomic(path: &Path, data: &[u8], expected_sha256: Option<&str>) -> Result<()> {
+    let partial = partial_path(path);
+    let result = (|| {
+        let mut file = File::create(&partial).with_context(|| format!("Failed to create {:?}", partial))?;
+        std::io::Write::write_all(&mut file, data).with_context(|| format!("Failed to write {:?}", partial))?;
+        file.sync_all().with_context(|| format!("Failed to flush {:?}", partial))?;
+        finish_partial(&partial, path, expected_sha256)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+// 与 write_atomic 相同，内容来自 source 文件，校验复制结果与源文件一致
+pub fn copy_atomic(source: &Path, path: &Path) -> Result<()> {
+    let partial = partial_path(path);
+    let result = (|| {
+        let expected = calculate_sha256(source)?;
+        std::fs::copy(source, &partial).with_context(|| format!("Failed to copy {:?} to {:?}", source, partial))?;
+        File::open(&partial)
+            .and_then(|f| f.sync_all())
+            .with_context(|| format!("Failed to flush {:?}", partial))?;
+        finish_partial(&partial, path, Some(&expected))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+// 以硬链接把 source 放到 path，不在同一文件系统等无法链接时改为 copy_atomic
+// （复制在 btrfs、XFS、APFS 等文件系统上会使用 reflink）；返回是否建立了硬链接
+pub fn link_or_copy_atomic(source: &Path, path: &Path) -> Result<bool> {
+    if is_same_file(source, path) {
+        return Ok(true);
+    }
+    let partial = partial_path(path);
+    let _ = std::fs::remove_file(&partial);
+    match std::fs::hard_link(source, &partial) {
+        Ok(()) => {
+            let result = finish_partial(&partial, path, None);
+            if result.is_err() {
+                let _ = std::fs::remove_file(&partial);
+            }
+            result.map(|()| true)
+        }
+        Err(e) => {
+            debug!("Cannot hard link {:?} to {:?}, copying instead: {}", source, path, e);
+            copy_atomic(source, path).map(|()| false)
+        }
+    }
+}
+
+// 文件的硬链接数，无法读取或不支持的平台上为 1
+pub fn hard_link_count(path: &Path) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).map_or(1, |m| m.nlink())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        1
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        false
+    }
+}
+
+// 删除目录中中断的写入留下的临时文件
+pub fn remove_partial_files(dir: &Path) -> usize {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name().to_string_lossy().ends_with(PARTIAL_SUFFIX))
+        .filter(|e| std::fs::remove_file(e.path()).is_ok())
+        .count()
+}
+
+fn finish_partial(partial: &Path, path: &Path, expected_sha256: Option<&str>) -> Result<()> {
+    if let Some(expected) = expected_sha256 {
+        let actual = calculate_sha256(partial)?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            anyhow::bail!("SHA256 mismatch for {:?}: expected={}, got={}", path, expected, actual);
+        }
+    }
+    std::fs::rename(partial, path).with_context(|| format!("Failed to move {:?} into place", path))
+}
+
 pub fn is_font_file(path: &Path) -> bool {
     if let Some(ext) = path.extension() {
         let ext_str = ext.to_string_lossy().to_lowercase();
@@ -59,21 +182,54 @@ pub fn get_font_mime_type(path: &Path) -> String {
     }
 }
 
-pub async fn scan_font_directory(dir: &Path) -> Result<Vec<FontInfo>> {
+// 列出目录下的字体文件。不跟随符号链接时不进入链接到的目录，但指向字体文件的链接仍会列出；
+// 跟随时由 walkdir 发现循环。seen 保存已列出文件的规范路径，同一文件经由不同路径只列出一次
+pub fn font_files(dir: &Path, follow_symlinks: bool, seen: &mut HashSet<PathBuf>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(dir).follow_links(follow_symlinks).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let Some(ancestor) = e.loop_ancestor() {
+                    warn!("Skipping symlink loop at {:?}, it points back to {:?}", e.path().unwrap_or(dir), ancestor);
+                }
+                continue;
+            }
+        };
+        let path = entry.path();
+        if !path.is_file() || !is_font_file(path) {
+            continue;
+        }
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if seen.insert(canonical) {
+            files.push(entry.into_path());
+        } else {
+            debug!("Skipping {:?}, already found through another path", path);
+        }
+    }
+    files
+}
+
+// 路径在 root 之下是否经过符号链接的目录，用于在不跟随链接时过滤文件系统事件
+pub fn via_symlinked_dir(root: &Path, path: &Path) -> bool {
+    path.parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .take_while(|dir| *dir != root && dir.starts_with(root))
+        .any(|dir| dir.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()))
+}
+
+pub async fn scan_font_directory(dir: &Path, follow_symlinks: bool, seen: &mut HashSet<PathBuf>) -> Result<Vec<FontInfo>> {
     let mut fonts = Vec::new();
     
     if !dir.exists() {
         return Ok(fonts);
     }
+    let ignore = IgnoreRules::for_dir(dir);
     
-    for entry in walkdir::WalkDir::new(dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && is_font_file(path) {
-            match scan_single_font(path).await {
+    for path in font_files(dir, follow_symlinks, seen) {
+        if !ignore.is_ignored(&path) {
+            match scan_single_font(&path).await {
                 Ok(font_info) => fonts.push(font_info),
                 Err(e) => error!("Failed to scan font file {:?}: {}", path, e),
             }
@@ -95,6 +251,8 @@ async fn scan_single_font(path: &Path) -> Result<FontInfo> {
         sha256,
         size: metadata.len(),
         modified: metadata.modified()?,
+        embedding: font_metadata::embedding_permission(path),
+        descriptor: font_metadata::descriptor(path),
     })
 }
 
@@ -104,6 +262,54 @@ pub struct FontInfo {
     pub sha256: String,
     pub size: u64,
     pub modified: std::time::SystemTime,
+    pub embedding: Option<EmbeddingPermission>,
+    pub descriptor: FontDescriptor,
+}
+
+// 同一字体家族的所有文件，family 为空表示无法读取家族名
+#[derive(Debug, Clone)]
+pub struct FamilyGroup {
+    pub family: Option<String>,
+    pub fonts: Vec<FontInfo>,
+}
+
+impl FamilyGroup {
+    // 不同目录中存在不同版本的同一家族
+    pub fn has_mixed_versions(&self) -> bool {
+        let versions: HashSet<_> = self
+            .fonts
+            .iter()
+            .filter_map(|font| font.descriptor.version.as_deref())
+            .collect();
+        let dirs: HashSet<_> = self.fonts.iter().filter_map(|font| font.path.parent()).collect();
+        versions.len() > 1 && dirs.len() > 1
+    }
+}
+
+// 按家族名分组（不区分大小写），无家族名的字体排在最后
+pub fn group_by_family(fonts: Vec<FontInfo>) -> Vec<FamilyGroup> {
+    let mut groups: BTreeMap<Option<String>, FamilyGroup> = BTreeMap::new();
+    for font in fonts {
+        let family = font.descriptor.family.clone();
+        groups
+            .entry(family.as_ref().map(|f| f.to_lowercase()))
+            .or_insert_with(|| FamilyGroup { family, fonts: Vec::new() })
+            .fonts
+            .push(font);
+    }
+
+    let mut groups: Vec<FamilyGroup> = groups.into_values().collect();
+    groups.sort_by_key(|group| group.family.is_none());
+    for group in &mut groups {
+        group.fonts.sort_by(|a, b| {
+            a.descriptor
+                .weight
+                .cmp(&b.descriptor.weight)
+                .then_with(|| a.descriptor.style.cmp(&b.descriptor.style))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+    }
+    groups
 }
 
 pub fn get_system_font_directories() -> Vec<PathBuf> {
@@ -155,6 +361,17 @@ pub fn get_file_timestamp(path: &Path) -> Result<u64> {
     Ok(duration.as_secs())
 }
 
+// 将文件修改时间设为指定的 Unix 时间戳（秒）
+pub fn set_file_timestamp(path: &Path, secs: u64) -> Result<()> {
+    let file = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?} to set modified time", path))?;
+    file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(secs))
+        .with_context(|| format!("Failed to set modified time for: {:?}", path))?;
+    Ok(())
+}
+
 pub fn format_file_size(size: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
     let mut size = size as f64;
@@ -172,6 +389,46 @@ pub fn format_file_size(size: u64) -> String {
     }
 }
 
+// 解析 "100MB"、"1.5 GB"、"4096" 等大小，单位按 1024 进制，与 format_file_size 一致
+pub fn parse_file_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size: {}", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => anyhow::bail!("Unknown size unit: {}", other),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+// 解析 "30d"、"12h" 这样的时长，不带单位时按秒计算
+pub fn parse_duration(value: &str) -> Result<std::time::Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration: {}", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => anyhow::bail!("Unknown duration unit: {}", other),
+    };
+    Ok(std::time::Duration::from_secs(number.saturating_mul(multiplier)))
+}
+
 pub fn validate_font_file(path: &Path) -> Result<bool> {
     if !path.exists() {
         return Ok(false);
@@ -205,17 +462,30 @@ pub fn validate_font_file(path: &Path) -> Result<bool> {
     }
 }
 
+// 保留各种文字的字母、空格与标点，统一为 NFC 形式（macOS 文件名常为 NFD）；
+// 只替换路径分隔符、Windows 不允许的字符与控制字符，开头的 '.' 也替换以免与隐藏目录混淆
 pub fn sanitize_filename(filename: &str) -> String {
-    filename
-        .chars()
-        .map(|c| {
-            if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' {
-                c
-            } else {
-                '_'
-            }
-        })
-        .collect()
+    let mut sanitized: String = filename
+        .nfc()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    if sanitized.starts_with('.') {
+        sanitized.replace_range(..1, "_");
+    }
+    sanitized
+}
+
+// URL 路径段中保持原样的字符（RFC 3986 unreserved）
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+// 字体名作为 URL 路径段时的百分号编码
+pub fn encode_path_segment(name: &str) -> String {
+    utf8_percent_encode(&name.nfc().collect::<String>(), PATH_SEGMENT).to_string()
+}
+
+// encode_path_segment 的逆过程，结果按文件名规则规范化
+pub fn decode_path_segment(segment: &str) -> String {
+    sanitize_filename(&percent_decode_str(segment).decode_utf8_lossy())
 }
 
 pub fn generate_unique_filename(path: &Path, counter: i32) -> String {
@@ -228,39 +498,180 @@ pub fn generate_unique_filename(path: &Path, counter: i32) -> String {
     format!("{}-{}.{}", stem, counter, ext)
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ConflictResolution {
     Overwrite,
     Rename,
     Skip,
 }
 
-pub fn prompt_conflict_resolution(
-    filename: &str,
-    local_sha256: &str,
-    remote_sha256: &str,
+// 字体列表的排序字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSort {
+    /// 按文件名
+    #[default]
+    Name,
+    /// 按文件大小
+    Size,
+    /// 按修改时间
+    Modified,
+}
+
+// 非交互模式下的冲突处理策略；同步映射中的写法与命令行相同，如 "overwrite-local"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// 用服务器版本覆盖本地文件
+    OverwriteLocal,
+    /// 用本地版本覆盖服务器文件
+    OverwriteRemote,
+    /// 以新文件名保留双方版本
+    Rename,
+    /// 跳过冲突文件
+    #[default]
+    Skip,
+    /// 修改时间较新的一方胜出
+    Newer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncDirection {
+    Upload,
+    Download,
+}
+
+// 一次冲突的双方信息，修改时间为 Unix 秒
+#[derive(Debug, Clone)]
+pub struct FileConflict<'a> {
+    pub filename: &'a str,
+    pub local_sha256: &'a str,
+    pub remote_sha256: &'a str,
+    pub local_modified: Option<u64>,
+    pub remote_modified: Option<u64>,
+    pub direction: SyncDirection,
+    // 文件名不同、家族与样式相同的另一方文件，按文件名匹配的冲突为空
+    pub counterpart: Option<&'a str>,
+}
+
+// 双方内容不同时，与上次同步的内容比较得出是哪一方发生了变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOrigin {
+    // 只有本地修改，服务器仍是上次同步的版本
+    LocalOnly,
+    // 只有服务器修改，本地仍是上次同步的版本
+    RemoteOnly,
+    // 双方都改过，或从未同步过
+    Both,
+}
+
+pub fn classify_change(last_synced: Option<&str>, local_sha256: &str, remote_sha256: &str) -> ChangeOrigin {
+    match last_synced {
+        Some(base) if base == remote_sha256 => ChangeOrigin::LocalOnly,
+        Some(base) if base == local_sha256 => ChangeOrigin::RemoteOnly,
+        _ => ChangeOrigin::Both,
+    }
+}
+
+impl ConflictPolicy {
+    // 根据同步方向将策略转换为具体操作：上传时 Overwrite 表示覆盖服务器，下载时表示覆盖本地
+    pub fn resolve(self, conflict: &FileConflict) -> ConflictResolution {
+        match (self, conflict.direction) {
+            (ConflictPolicy::OverwriteRemote, SyncDirection::Upload)
+            | (ConflictPolicy::OverwriteLocal, SyncDirection::Download) => ConflictResolution::Overwrite,
+            (ConflictPolicy::OverwriteRemote, SyncDirection::Download)
+            | (ConflictPolicy::OverwriteLocal, SyncDirection::Upload) => ConflictResolution::Skip,
+            (ConflictPolicy::Rename, _) => ConflictResolution::Rename,
+            (ConflictPolicy::Skip, _) => ConflictResolution::Skip,
+            (ConflictPolicy::Newer, direction) => {
+                // 任意一方缺少修改时间或时间相同时无法判断，保守跳过
+                let (ours, theirs) = match direction {
+                    SyncDirection::Upload => (conflict.local_modified, conflict.remote_modified),
+                    SyncDirection::Download => (conflict.remote_modified, conflict.local_modified),
+                };
+                match (ours, theirs) {
+                    (Some(ours), Some(theirs)) if ours > theirs => ConflictResolution::Overwrite,
+                    _ => ConflictResolution::Skip,
+                }
+            }
+        }
+    }
+}
+
+pub async fn prompt_conflict_resolution(
+    conflict: &FileConflict<'_>,
     interactive: bool,
+    policy: ConflictPolicy,
 ) -> Result<ConflictResolution> {
     if !interactive {
-        error!(
-            "Font conflict detected for '{}': local SHA256={}, remote SHA256={}. Skipping due to non-interactive mode.",
-            filename, local_sha256, remote_sha256
+        let resolution = policy.resolve(conflict);
+        warn!(
+            "{}",
+            t!(
+                "conflict.resolved",
+                name = conflict.filename,
+                local = conflict.local_sha256,
+                remote = conflict.remote_sha256,
+                resolution = i18n::resolution(&resolution),
+                policy = format!("{:?}", policy),
+            )
         );
-        return Ok(ConflictResolution::Skip);
+        return Ok(resolution);
     }
 
+    // 监控模式下上传与下载任务可能同时遇到冲突，逐个询问；等待期间不占用运行时的工作线程
+    static PROMPT_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _guard = PROMPT_LOCK.lock().await;
+
+    let lines = conflict_prompt_lines(conflict);
+    tokio::task::spawn_blocking(move || ask_conflict_resolution(&lines))
+        .await
+        .context("Conflict prompt task failed")?
+}
+
+// 询问前显示的冲突说明与各选项的含义
+fn conflict_prompt_lines(conflict: &FileConflict) -> Vec<String> {
+    let mut lines = vec![
+        format!("\n{}", t!("conflict.detected")),
+        t!("conflict.filename", name = conflict.filename),
+        t!("conflict.local_sha256", sha256 = &conflict.local_sha256[..16]),
+        t!("conflict.remote_sha256", sha256 = &conflict.remote_sha256[..16]),
+        format!("\n{}", t!("conflict.question")),
+    ];
+    match (conflict.direction, conflict.counterpart) {
+        (SyncDirection::Upload, Some(counterpart)) => {
+            lines.push(t!("conflict.same_face_remote", name = counterpart));
+            lines.push(t!("conflict.replace_remote", name = counterpart));
+            lines.push(t!("conflict.keep_both"));
+        }
+        (SyncDirection::Download, Some(counterpart)) => {
+            lines.push(t!("conflict.same_face_local", name = counterpart));
+            lines.push(t!("conflict.replace_local", name = counterpart));
+            lines.push(t!("conflict.keep_both"));
+        }
+        (SyncDirection::Upload, None) => {
+            lines.push(t!("conflict.overwrite_remote"));
+            lines.push(t!("conflict.upload_renamed"));
+        }
+        (SyncDirection::Download, None) => {
+            lines.push(t!("conflict.overwrite_local"));
+            lines.push(t!("conflict.save_renamed"));
+        }
+    }
+    lines.push(t!("conflict.skip_file"));
+    lines
+}
+
+// 阻塞读取终端输入，只在 spawn_blocking 中调用
+fn ask_conflict_resolution(lines: &[String]) -> Result<ConflictResolution> {
     use dialoguer::{theme::ColorfulTheme, Select};
-    
-    println!("\n⚠️  Font file conflict detected!");
-    println!("Filename: {}", filename);
-    println!("Local SHA256:  {}...", &local_sha256[..16]);
-    println!("Remote SHA256: {}...", &remote_sha256[..16]);
-    println!("\nWhat would you like to do?");
-    println!("1) Overwrite local file with remote version");
-    println!("2) Rename remote file");
-    println!("3) Skip this file");
-    
-    let items = vec!["Overwrite", "Rename", "Skip"];
+
+    for line in lines {
+        println!("{}", line);
+    }
+    let items = vec![t!("conflict.overwrite"), t!("conflict.rename"), t!("conflict.skip")];
     let selection = Select::with_theme(&ColorfulTheme::default())
         .items(&items)
         .default(2)
@@ -291,6 +702,33 @@ mod tests {
         assert_eq!(result.len(), 64); // SHA256 十六进制字符串长度为 64
     }
 
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("a.ttf");
+        std::fs::write(&target, b"old").unwrap();
+
+        // 校验失败时保留原文件，也不留下临时文件
+        assert!(write_atomic(&target, b"new", Some(&"0".repeat(64))).is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"old");
+        assert!(!partial_path(&target).exists());
+        assert!(!is_font_file(&partial_path(&target)));
+
+        let expected = hex::encode(Sha256::digest(b"new"));
+        write_atomic(&target, b"new", Some(&expected)).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+
+        let copy = dir.path().join("b.ttf");
+        copy_atomic(&target, &copy).unwrap();
+        assert_eq!(std::fs::read(&copy).unwrap(), b"new");
+        assert!(!partial_path(&copy).exists());
+
+        // 中断后留下的临时文件可以清理
+        std::fs::write(partial_path(&copy), b"ne").unwrap();
+        assert_eq!(remove_partial_files(dir.path()), 1);
+        assert!(copy.exists());
+    }
+
     #[test]
     fn test_is_font_file() {
         assert!(is_font_file(Path::new("test.ttf")));
@@ -319,10 +757,41 @@ mod tests {
         assert_eq!(format_file_size(1024 * 1024), "1.00 MB");
     }
 
+    #[test]
+    fn test_parse_file_size() {
+        assert_eq!(parse_file_size("4096").unwrap(), 4096);
+        assert_eq!(parse_file_size("100MB").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_file_size("1.5 gb").unwrap(), 1536 * 1024 * 1024);
+        assert!(parse_file_size("ten MB").is_err());
+        assert!(parse_file_size("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), std::time::Duration::from_secs(90));
+        assert_eq!(parse_duration("30d").unwrap(), std::time::Duration::from_secs(30 * 86400));
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration("3 fortnights").is_err());
+    }
+
     #[test]
     fn test_sanitize_filename() {
-        let sanitized = sanitize_filename("My Font (v1).ttf");
-        assert_eq!(sanitized, "My_Font__v1_.ttf");
+        assert_eq!(sanitize_filename("My Font (v1).ttf"), "My Font (v1).ttf");
+        assert_eq!(sanitize_filename("思源黑体 Bold.otf"), "思源黑体 Bold.otf");
+        assert_eq!(sanitize_filename("../a/b\\c:d?.ttf"), "_._a_b_c_d_.ttf");
+        // NFD 的「が」合并为单个码位
+        assert_eq!(sanitize_filename("\u{304B}\u{3099}.ttf"), "\u{304C}.ttf");
+    }
+
+    #[test]
+    fn path_segments_round_trip() {
+        for name in ["思源黑体.ttf", "Noto Sans #1 (100%).otf", "한글 글꼴.ttf", "a+b&c=d?.woff2"] {
+            let encoded = encode_path_segment(name);
+            assert!(encoded.is_ascii() && !encoded.contains(['/', '?', '#', ' ']));
+            assert_eq!(decode_path_segment(&encoded), sanitize_filename(name));
+        }
+        assert_eq!(encode_path_segment("思源黑体.ttf"), "%E6%80%9D%E6%BA%90%E9%BB%91%E4%BD%93.ttf");
+        assert_eq!(decode_path_segment("..%2Fsecret.ttf"), "_._secret.ttf");
     }
 
     #[test]
@@ -334,6 +803,90 @@ mod tests {
         assert!(validate_font_file(&path).unwrap());
     }
 
+    #[test]
+    fn test_conflict_policy_resolve() {
+        let mut conflict = FileConflict {
+            filename: "a.ttf",
+            local_sha256: "local",
+            remote_sha256: "remote",
+            local_modified: Some(200),
+            remote_modified: Some(100),
+            direction: SyncDirection::Upload,
+            counterpart: None,
+        };
+        assert_eq!(ConflictPolicy::OverwriteRemote.resolve(&conflict), ConflictResolution::Overwrite);
+        assert_eq!(ConflictPolicy::OverwriteLocal.resolve(&conflict), ConflictResolution::Skip);
+        assert_eq!(ConflictPolicy::Newer.resolve(&conflict), ConflictResolution::Overwrite);
+
+        conflict.direction = SyncDirection::Download;
+        assert_eq!(ConflictPolicy::OverwriteLocal.resolve(&conflict), ConflictResolution::Overwrite);
+        assert_eq!(ConflictPolicy::Newer.resolve(&conflict), ConflictResolution::Skip);
+
+        conflict.remote_modified = None;
+        assert_eq!(ConflictPolicy::Newer.resolve(&conflict), ConflictResolution::Skip);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_conflict_resolution() {
+        let conflict = FileConflict {
+            filename: "a.ttf",
+            local_sha256: &"1".repeat(64),
+            remote_sha256: &"2".repeat(64),
+            local_modified: None,
+            remote_modified: None,
+            direction: SyncDirection::Download,
+            counterpart: Some("a-old.ttf"),
+        };
+        // 非交互时直接按策略决定，不读取终端
+        let resolution = prompt_conflict_resolution(&conflict, false, ConflictPolicy::Rename).await.unwrap();
+        assert_eq!(resolution, ConflictResolution::Rename);
+
+        let lines = conflict_prompt_lines(&conflict);
+        assert_eq!(lines[1], "Filename: a.ttf");
+        assert!(lines[2].ends_with(&format!("{}...", "1".repeat(16))));
+        assert!(lines.iter().any(|line| line.contains("a-old.ttf")));
+    }
+
+    #[test]
+    fn test_classify_change() {
+        assert_eq!(classify_change(Some("base"), "local", "base"), ChangeOrigin::LocalOnly);
+        assert_eq!(classify_change(Some("base"), "base", "remote"), ChangeOrigin::RemoteOnly);
+        assert_eq!(classify_change(Some("base"), "local", "remote"), ChangeOrigin::Both);
+        assert_eq!(classify_change(None, "local", "remote"), ChangeOrigin::Both);
+    }
+
+    fn font_in(dir: &str, family: Option<&str>, version: &str) -> FontInfo {
+        FontInfo {
+            path: PathBuf::from(dir).join("font.ttf"),
+            sha256: String::new(),
+            size: 0,
+            modified: UNIX_EPOCH,
+            embedding: None,
+            descriptor: FontDescriptor {
+                family: family.map(str::to_string),
+                version: Some(version.to_string()),
+                ..FontDescriptor::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_group_by_family() {
+        let groups = group_by_family(vec![
+            font_in("/system", None, "1.0"),
+            font_in("/system", Some("Noto Sans"), "Version 2.004"),
+            font_in("/user", Some("noto sans"), "Version 2.001"),
+            font_in("/user", Some("Inter"), "Version 4.0"),
+            font_in("/user/extra", Some("Inter"), "Version 4.0"),
+        ]);
+
+        let families: Vec<_> = groups.iter().map(|g| g.family.as_deref()).collect();
+        assert_eq!(families, vec![Some("Inter"), Some("Noto Sans"), None]);
+        assert!(!groups[0].has_mixed_versions());
+        assert_eq!(groups[1].fonts.len(), 2);
+        assert!(groups[1].has_mixed_versions());
+    }
+
     #[test]
     fn test_get_file_timestamp() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -341,4 +894,30 @@ mod tests {
         let timestamp = get_file_timestamp(temp_file.path()).unwrap();
         assert!(timestamp > 0);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_fonts_are_listed_once() {
+        use std::os::unix::fs::symlink;
+
+        let root = tempdir().unwrap();
+        let fonts = root.path().join("fonts");
+        std::fs::create_dir_all(fonts.join("sub")).unwrap();
+        std::fs::write(fonts.join("a.ttf"), b"font").unwrap();
+        symlink(fonts.join("a.ttf"), fonts.join("alias.ttf")).unwrap();
+        symlink(&fonts, fonts.join("sub/loop")).unwrap();
+        // 另一个根目录链接到同一目录，如 ~/.fonts 与 ~/.local/share/fonts
+        let other = root.path().join("other");
+        symlink(&fonts, &other).unwrap();
+
+        let mut seen = HashSet::new();
+        assert_eq!(font_files(&fonts, false, &mut seen), vec![fonts.join("a.ttf")]);
+        assert!(font_files(&other, false, &mut seen).is_empty());
+
+        // 跟随链接时循环只遍历一次，不会重复列出
+        assert_eq!(font_files(&fonts, true, &mut HashSet::new()).len(), 1);
+        assert!(via_symlinked_dir(&fonts, &fonts.join("sub/loop/a.ttf")));
+        assert!(!via_symlinked_dir(&fonts, &fonts.join("sub/a.ttf")));
+        assert!(!via_symlinked_dir(&other, &other.join("a.ttf")));
+    }
 }
@@ -0,0 +1,353 @@
+use anyhow::{bail, Context, Result};
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::{Compression, Crc};
+use serde::Deserialize;
+use std::io::Write;
+
+// 样式表中字体文件的格式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebFormat {
+    // 由服务器转换为 WOFF 2.0（brotli 压缩），样式表中以 WOFF 兜底
+    Woff2,
+    // 由服务器转换为 WOFF 1.0（zlib 压缩）
+    #[default]
+    Woff,
+    // 原始 TTF/OTF 文件
+    Original,
+}
+
+impl WebFormat {
+    // 由服务器转换的格式在原文件名后追加的扩展名
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            WebFormat::Woff2 => Some("woff2"),
+            WebFormat::Woff => Some("woff"),
+            WebFormat::Original => None,
+        }
+    }
+
+    // 转换后的文件内容，原始格式原样返回
+    pub fn convert(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            WebFormat::Woff2 => to_woff2(data),
+            WebFormat::Woff => to_woff(data),
+            WebFormat::Original => Ok(data.to_vec()),
+        }
+    }
+}
+
+// 一条 @font-face 规则，src 按 sources 的顺序列出 URL 与 format()
+#[derive(Debug, Clone)]
+pub struct WebFace {
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub sources: Vec<(String, &'static str)>,
+}
+
+// 从 OS/2 与 head 表读取字重与是否为斜体，无法解析时返回 None
+pub fn face_style(data: &[u8]) -> Option<(u16, bool)> {
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+    Some((face.weight().to_number(), face.is_italic() || face.is_oblique()))
+}
+
+// CSS font-face 中 format() 的取值
+pub fn css_format(filename: &str) -> &'static str {
+    match filename.rsplit('.').next().map(str::to_ascii_lowercase).as_deref() {
+        Some("woff") => "woff",
+        Some("woff2") => "woff2",
+        Some("otf") => "opentype",
+        _ => "truetype",
+    }
+}
+
+pub fn font_face_css(faces: &[WebFace]) -> String {
+    let mut css = String::new();
+    for face in faces {
+        let src: Vec<String> =
+            face.sources.iter().map(|(url, format)| format!("url(\"{}\") format(\"{}\")", url, format)).collect();
+        css.push_str(&format!(
+            "@font-face {{\n  font-family: \"{}\";\n  font-style: {};\n  font-weight: {};\n  font-display: swap;\n  src: {};\n}}\n\n",
+            face.family.replace('\\', "\\\\").replace('"', "\\\""),
+            if face.italic { "italic" } else { "normal" },
+            face.weight,
+            src.join(", "),
+        ));
+    }
+    css
+}
+
+// 将 TTF/OTF 封装为 WOFF 1.0：每个表单独 zlib 压缩，压缩后不更小则原样保存
+pub fn to_woff(data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(b"ttcf") {
+        bail!("Font collections cannot be converted to WOFF");
+    }
+    let face = ttf_parser::RawFace::parse(data, 0).context("Unsupported font format")?;
+    let flavor = u32::from_be_bytes(data[0..4].try_into().unwrap());
+
+    let records: Vec<_> = face.table_records.into_iter().collect();
+    let num_tables = records.len();
+    let mut directory = Vec::with_capacity(num_tables * 20);
+    let mut tables = Vec::new();
+    let mut offset = 44 + num_tables * 20;
+    let mut sfnt_size = 12 + num_tables * 16;
+
+    for record in &records {
+        let start = record.offset as usize;
+        let table = start
+            .checked_add(record.length as usize)
+            .and_then(|end| data.get(start..end))
+            .context("Table extends past end of font")?;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(table)?;
+        let compressed = encoder.finish()?;
+        let stored = if compressed.len() < table.len() { &compressed[..] } else { table };
+
+        directory.extend_from_slice(&record.tag.to_bytes());
+        directory.extend_from_slice(&(offset as u32).to_be_bytes());
+        directory.extend_from_slice(&(stored.len() as u32).to_be_bytes());
+        directory.extend_from_slice(&record.length.to_be_bytes());
+        directory.extend_from_slice(&record.check_sum.to_be_bytes());
+
+        tables.extend_from_slice(stored);
+        let padding = (4 - stored.len() % 4) % 4;
+        tables.resize(tables.len() + padding, 0);
+        offset += stored.len() + padding;
+        sfnt_size += (table.len() + 3) & !3;
+    }
+
+    let mut woff = Vec::with_capacity(offset);
+    woff.extend_from_slice(b"wOFF");
+    woff.extend_from_slice(&flavor.to_be_bytes());
+    woff.extend_from_slice(&(offset as u32).to_be_bytes());
+    woff.extend_from_slice(&(num_tables as u16).to_be_bytes());
+    woff.extend_from_slice(&0u16.to_be_bytes());
+    woff.extend_from_slice(&(sfnt_size as u32).to_be_bytes());
+    woff.extend_from_slice(&1u16.to_be_bytes());
+    woff.extend_from_slice(&0u16.to_be_bytes());
+    // 不包含扩展元数据与私有数据块
+    woff.extend_from_slice(&[0u8; 20]);
+    woff.extend_from_slice(&directory);
+    woff.extend_from_slice(&tables);
+    Ok(woff)
+}
+
+// 将 TTF/OTF 封装为 WOFF 2.0：所有表不做变换（glyf/loca 使用 null 变换），按目录顺序拼接后整体用 brotli 压缩
+pub fn to_woff2(data: &[u8]) -> Result<Vec<u8>> {
+    // 所有表都使用任意标签（63）而非已知标签索引；glyf 与 loca 的 null 变换版本为 3
+    const ARBITRARY_TAG: u8 = 63;
+    const NULL_TRANSFORM_GLYF: u8 = 3 << 6;
+
+    if data.starts_with(b"ttcf") {
+        bail!("Font collections cannot be converted to WOFF2");
+    }
+    let face = ttf_parser::RawFace::parse(data, 0).context("Unsupported font format")?;
+    let flavor = u32::from_be_bytes(data[0..4].try_into().unwrap());
+
+    // 解码器要求 loca 紧跟在 glyf 之后
+    let mut records: Vec<_> = face.table_records.into_iter().collect();
+    if let Some(loca) = records.iter().position(|r| &r.tag.to_bytes() == b"loca") {
+        let loca = records.remove(loca);
+        let glyf = records.iter().position(|r| &r.tag.to_bytes() == b"glyf").map_or(records.len(), |i| i + 1);
+        records.insert(glyf, loca);
+    }
+
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    let mut sfnt_size = 12 + records.len() * 16;
+    for record in &records {
+        let start = record.offset as usize;
+        let table = start
+            .checked_add(record.length as usize)
+            .and_then(|end| data.get(start..end))
+            .context("Table extends past end of font")?;
+        let tag = record.tag.to_bytes();
+        let transform = if &tag == b"glyf" || &tag == b"loca" { NULL_TRANSFORM_GLYF } else { 0 };
+        directory.push(ARBITRARY_TAG | transform);
+        directory.extend_from_slice(&tag);
+        push_base128(&mut directory, table.len() as u32);
+        stream.extend_from_slice(table);
+        sfnt_size += (table.len() + 3) & !3;
+    }
+
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+    encoder.write_all(&stream)?;
+    let compressed = encoder.into_inner();
+
+    let length = (48 + directory.len() + compressed.len() + 3) & !3;
+    let mut woff2 = Vec::with_capacity(length);
+    woff2.extend_from_slice(b"wOF2");
+    woff2.extend_from_slice(&flavor.to_be_bytes());
+    woff2.extend_from_slice(&(length as u32).to_be_bytes());
+    woff2.extend_from_slice(&(records.len() as u16).to_be_bytes());
+    woff2.extend_from_slice(&0u16.to_be_bytes());
+    woff2.extend_from_slice(&(sfnt_size as u32).to_be_bytes());
+    woff2.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    woff2.extend_from_slice(&1u16.to_be_bytes());
+    woff2.extend_from_slice(&0u16.to_be_bytes());
+    // 不包含扩展元数据与私有数据块
+    woff2.extend_from_slice(&[0u8; 20]);
+    woff2.extend_from_slice(&directory);
+    woff2.extend_from_slice(&compressed);
+    woff2.resize(length, 0);
+    Ok(woff2)
+}
+
+// WOFF2 的 UIntBase128：每字节 7 位，高位优先，最高位表示后面还有字节
+fn push_base128(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = Vec::new();
+    let mut value = value;
+    loop {
+        bytes.push((value & 0x7F) as u8);
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for (i, byte) in bytes.iter().enumerate().rev() {
+        out.push(if i > 0 { byte | 0x80 } else { *byte });
+    }
+}
+
+// 生成 deflate 压缩的 zip 文件，条目名使用 UTF-8
+pub fn zip_archive(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    // DOS 日期 1980-01-01 00:00，保证相同内容生成相同文件
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+    const UTF8_FLAG: u16 = 1 << 11;
+
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    for (name, data) in entries {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let local_offset = archive.len() as u32;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+        header.extend_from_slice(&8u16.to_le_bytes()); // deflate
+        header.extend_from_slice(&DOS_TIME.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&crc.sum().to_le_bytes());
+        header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&compressed);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&header);
+        central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&local_offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let central_offset = archive.len() as u32;
+    archive.extend_from_slice(&central);
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn converts_font_to_woff() {
+        let font = crate::font_metadata::tests::square_font();
+        let woff = to_woff(&font).expect("woff");
+
+        let be32 = |at: usize| u32::from_be_bytes(woff[at..at + 4].try_into().unwrap());
+        assert_eq!(&woff[0..4], b"wOFF");
+        assert_eq!(be32(8) as usize, woff.len());
+
+        // 还原后的 sfnt 大小按 4 字节对齐计算各表长度
+        let face = ttf_parser::RawFace::parse(&font, 0).unwrap();
+        let padded: usize = face.table_records.into_iter().map(|r| (r.length as usize + 3) & !3).sum();
+        assert_eq!(be32(16) as usize, 12 + face.table_records.len() as usize * 16 + padded);
+
+        // 每个表解压后应与原字体中的表一致
+        for (i, record) in face.table_records.into_iter().enumerate() {
+            let entry = 44 + i * 20;
+            let (offset, comp_length, orig_length) = (be32(entry + 4) as usize, be32(entry + 8) as usize, be32(entry + 12) as usize);
+            let stored = &woff[offset..offset + comp_length];
+            let table = if comp_length < orig_length {
+                let mut table = Vec::new();
+                ZlibDecoder::new(stored).read_to_end(&mut table).unwrap();
+                table
+            } else {
+                stored.to_vec()
+            };
+            let start = record.offset as usize;
+            assert_eq!(table, &font[start..start + record.length as usize]);
+        }
+
+        assert_eq!(face_style(&font), Some((400, false)));
+        assert!(to_woff(b"not a font").is_err());
+    }
+
+    #[test]
+    fn converts_font_to_woff2() {
+        let font = crate::font_metadata::tests::square_font();
+        let woff2 = to_woff2(&font).expect("woff2");
+        let be32 = |at: usize| u32::from_be_bytes(woff2[at..at + 4].try_into().unwrap());
+        assert_eq!(&woff2[0..4], b"wOF2");
+        assert_eq!(be32(8) as usize, woff2.len());
+
+        // 目录中每项为标志、标签与原长度（这些表都小于 128 字节，长度占一个字节）
+        let face = ttf_parser::RawFace::parse(&font, 0).unwrap();
+        let mut at = 48;
+        let mut expected = Vec::new();
+        let mut tags = Vec::new();
+        for _ in 0..face.table_records.len() {
+            let tag: [u8; 4] = woff2[at + 1..at + 5].try_into().unwrap();
+            assert_eq!(woff2[at] & 0x3F, 63);
+            let record = face.table_records.into_iter().find(|r| r.tag.to_bytes() == tag).unwrap();
+            assert_eq!(woff2[at + 5] as u32, record.length);
+            expected.extend_from_slice(&font[record.offset as usize..(record.offset + record.length) as usize]);
+            tags.push(tag);
+            at += 6;
+        }
+        let glyf = tags.iter().position(|t| t == b"glyf").unwrap();
+        assert_eq!(&tags[glyf + 1], b"loca");
+
+        let mut stream = Vec::new();
+        brotli::Decompressor::new(&woff2[at..at + be32(20) as usize], 4096).read_to_end(&mut stream).unwrap();
+        assert_eq!(stream, expected);
+
+        let mut encoded = Vec::new();
+        push_base128(&mut encoded, 300);
+        assert_eq!(encoded, [0x82, 0x2C]);
+    }
+
+    #[test]
+    fn writes_zip_archive() {
+        let zip = zip_archive(&[("a.css".to_string(), b"body {}".to_vec())]).expect("zip");
+        assert_eq!(&zip[0..4], b"PK\x03\x04");
+        assert_eq!(&zip[zip.len() - 22..zip.len() - 18], b"PK\x05\x06");
+        assert!(zip.windows(5).any(|w| w == b"a.css"));
+    }
+}
@@ -1,14 +1,40 @@
 use anyhow::{Context, Result};
 use futures::{SinkExt, StreamExt};
-use log::{error, info};
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
 use std::path::{Path, PathBuf};
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 
-use crate::client::{download_server_fonts, upload_local_fonts};
-use crate::font_installer;
-use crate::utils::{calculate_sha256, get_system_font_directories};
-use crate::websocket_server::WebSocketMessage;
+use crate::api::{self, ClientCommand, ErrorResponse, InstalledFont, Tombstone};
+use crate::client::{download_server_fonts, upload_local_fonts, verified_manifest, ApiClient, SyncOptions};
+use crate::client_state::ClientState;
+use crate::credentials;
+use crate::download_cache;
+use crate::font_installer::{self, InstallOptions};
+use crate::http;
+use crate::identity::{ClientIdentity, CLIENT_ID_HEADER};
+use crate::progress::Progress;
+use crate::protected::ProtectedPaths;
+use crate::sse::SseParser;
+use crate::sync_history;
+use crate::sync_report::SyncReport;
+use crate::utils::{
+    calculate_sha256, generate_unique_filename, get_file_timestamp, get_system_font_directories,
+    prompt_conflict_resolution, ConflictResolution, FileConflict, SyncDirection,
+};
+use crate::websocket_server::{decode_message, FontDetails, WebSocketMessage, WS_FEATURES, WS_FEATURES_HEADER};
+
+// WebSocket 连接的底层流：TCP、代理隧道或 Unix 套接字
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+type WsStream = WebSocketStream<Box<dyn Connection>>;
 
 #[derive(Clone)]
 pub struct WebSocketClient {
@@ -16,17 +42,23 @@ pub struct WebSocketClient {
     client_id: String,
     local_font_dirs: Vec<PathBuf>,
     download_dir: PathBuf,
+    options: SyncOptions,
+    // 管理员通过 pause 指令暂停自动下载
+    paused: Arc<AtomicBool>,
+    // 已安装但尚未上报给服务器的字体
+    installed: Arc<Mutex<Vec<InstalledFont>>>,
 }
 
 impl WebSocketClient {
-    pub fn new(server_url: String, client_id: String) -> Self {
+    pub fn new(server_url: String, client_id: String, options: SyncOptions) -> Self {
         Self {
             server_url,
             client_id,
+            options,
+            paused: Arc::new(AtomicBool::new(false)),
+            installed: Arc::new(Mutex::new(Vec::new())),
             local_font_dirs: get_system_font_directories(),
-            download_dir: dirs::cache_dir()
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join("fontsync/downloads"),
+            download_dir: download_cache::download_dir(),
         }
     }
 
@@ -58,7 +90,7 @@ impl WebSocketClient {
 
     async fn run_with_stream(
         &mut self,
-        ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        ws_stream: WsStream,
         ws_url: String,
     ) -> Result<()> {
         // 创建下载目录
@@ -68,7 +100,7 @@ impl WebSocketClient {
 
         info!("Connected to WebSocket server: {}", ws_url);
 
-        let (mut ws_sender, _ws_receiver) = ws_stream.split();
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
         
         // 发送初始同步请求
         let sync_request = WebSocketMessage::SyncRequest {
@@ -82,48 +114,154 @@ impl WebSocketClient {
             .await
             .context("Failed to send sync request")?;
 
-        // 执行初始同步
-        self.perform_initial_sync().await?;
+        // 优先回放错过的事件，无法追赶时执行完整同步；
+        // 期间继续读取连接，使服务器的 Ping 得到应答，到达的事件在同步后处理
+        let mut pending = Vec::new();
+        {
+            let sync = self.catch_up_or_sync();
+            tokio::pin!(sync);
+            loop {
+                tokio::select! {
+                    result = &mut sync => {
+                        result?;
+                        break;
+                    }
+                    msg = ws_receiver.next() => match msg {
+                        Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => pending.push(msg),
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => return Err(e).context("WebSocket connection error"),
+                        None => anyhow::bail!("WebSocket connection closed during initial sync"),
+                    },
+                }
+            }
+        }
+        self.report_installed(&mut ws_sender).await?;
+        for msg in pending {
+            self.handle_frame(&msg, &mut ws_sender).await;
+        }
+
+        // 持续接收服务器通知，直到连接关闭或同步被取消
+        let cancel = self.options.cancel.clone();
+        loop {
+            let msg = tokio::select! {
+                _ = cancel.cancelled() => break,
+                msg = ws_receiver.next() => msg,
+            };
+            match msg {
+                Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => {
+                    self.handle_frame(&msg, &mut ws_sender).await;
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    info!("WebSocket connection closed by server");
+                    break;
+                }
+                // Ping 由 tungstenite 自动回复
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e).context("WebSocket connection error"),
+            }
+        }
 
         info!("WebSocket client operations completed");
         Ok(())
     }
 
+    // 处理一个数据帧，单条通知处理失败不中断连接
+    async fn handle_frame(
+        &self,
+        msg: &Message,
+        ws_sender: &mut futures::stream::SplitSink<WsStream, Message>,
+    ) {
+        match decode_message(msg) {
+            Ok(message) => {
+                let seq = message.seq();
+                if let Err(e) = self.handle_server_message(message, ws_sender).await {
+                    error!("Failed to apply server event: {:#}", e);
+                }
+                // 与 SSE 相同，处理过的事件即记录序号，重连或重启后只回放之后的事件
+                if let Some(seq) = seq {
+                    self.save_event_seq(seq);
+                }
+            }
+            Err(e) => warn!("Ignoring malformed server message: {:#}", e),
+        }
+    }
+
     async fn handle_server_message(
         &self,
         msg: WebSocketMessage,
-        ws_sender: &mut futures::stream::SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
+        ws_sender: &mut futures::stream::SplitSink<WsStream, Message>,
     ) -> Result<()> {
         match msg {
-            WebSocketMessage::FontAdded { filename, sha256, size } => {
+            WebSocketMessage::FontAdded { filename, sha256, size, details, .. } => {
                 info!("Server notified font added: {} ({} bytes, SHA256: {}...)", 
                     filename, size, &sha256[..16]);
                 
                 // 自动下载新字体
-                self.download_font(&filename, &sha256).await?;
+                if !self.downloads_paused(&filename) && !self.outside_subscription(&filename, &details) {
+                    self.download_font(&filename, &sha256).await?;
+                }
             }
-            WebSocketMessage::FontModified { filename, sha256, size } => {
+            WebSocketMessage::FontModified { filename, sha256, size, details, .. } => {
                 info!("Server notified font modified: {} ({} bytes, SHA256: {}...)", 
                     filename, size, &sha256[..16]);
                 
                 // 下载更新后的字体
-                self.download_font(&filename, &sha256).await?;
+                if !self.downloads_paused(&filename) && !self.outside_subscription(&filename, &details) {
+                    self.download_font(&filename, &sha256).await?;
+                }
             }
-            WebSocketMessage::FontRemoved { filename } => {
+            WebSocketMessage::FontsAdded { count, names, .. } => {
+                info!("Server notified {} fonts added", count);
+
+                // 合并的通知不含哈希，列出一次服务器上的字体后逐个下载
+                if !self.downloads_paused(&names.join(", ")) {
+                    let list = ApiClient::new(&self.server_url)?.list_fonts_hashed(&Default::default()).await?;
+                    let fonts: Vec<_> = list
+                        .fonts
+                        .into_iter()
+                        .filter(|font| names.contains(&font.name))
+                        .map(|font| (font.name, font.sha256))
+                        .collect();
+                    self.download_fonts(&fonts).await?;
+                }
+            }
+            WebSocketMessage::FontRemoved { filename, .. } => {
                 info!("Server notified font removed: {}", filename);
                 
                 // 如果本地存在且 SHA256 一致则移除
                 self.handle_font_removal(&filename).await?;
             }
+            WebSocketMessage::FontRenamed { from, to, sha256, .. } => {
+                info!("Server notified font renamed: {} -> {}", from, to);
+                self.handle_font_rename(&from, &to, &sha256).await?;
+            }
             WebSocketMessage::SyncComplete { client_id, success, message } => {
+                // 连接时已经追赶过服务器的变更，这里只记录
                 if client_id == self.client_id {
                     info!("Sync completed: {} - {}", success, message);
-                    if success {
-                        // 执行初始同步
-                        self.perform_initial_sync().await?;
-                    }
                 }
             }
+            WebSocketMessage::HashingComplete { fonts } => {
+                info!("Server finished hashing {} fonts", fonts);
+            }
+            WebSocketMessage::Command { id, command } => {
+                // 先确认收到，再执行
+                let ack = serde_json::to_string(&WebSocketMessage::Ack { message_id: id })
+                    .context("Failed to serialize command acknowledgement")?;
+                ws_sender.send(Message::Text(ack))
+                    .await
+                    .context("Failed to acknowledge command")?;
+                self.run_command(command).await?;
+            }
+            WebSocketMessage::Batch { events } => {
+                for event in events {
+                    Box::pin(self.handle_server_message(event, ws_sender)).await?;
+                }
+            }
+            WebSocketMessage::ResyncRequired { dropped } => {
+                warn!("Server dropped {} events for this client, catching up", dropped);
+                self.catch_up_or_sync().await?;
+            }
             WebSocketMessage::Heartbeat => {
                 // 回复心跳
                 let heartbeat_msg = WebSocketMessage::Heartbeat;
@@ -140,11 +278,102 @@ impl WebSocketClient {
             }
         }
         
-        Ok(())
+        self.report_installed(ws_sender).await
+    }
+
+    // 把新安装的字体上报给服务器
+    async fn report_installed(
+        &self,
+        ws_sender: &mut futures::stream::SplitSink<WsStream, Message>,
+    ) -> Result<()> {
+        let fonts = std::mem::take(&mut *self.installed.lock());
+        if fonts.is_empty() {
+            return Ok(());
+        }
+        let message = WebSocketMessage::FontsInstalled { client_id: self.client_id.clone(), fonts };
+        let json_msg = serde_json::to_string(&message).context("Failed to serialize install report")?;
+        ws_sender.send(Message::Text(json_msg))
+            .await
+            .context("Failed to send install report")
+    }
+
+    fn downloads_paused(&self, filename: &str) -> bool {
+        let paused = self.paused.load(Ordering::Relaxed);
+        if paused {
+            info!("Downloads paused by the server admin, not fetching '{}'", filename);
+        }
+        paused
+    }
+
+    // 事件带有的家族、标签或格式不在订阅范围内时跳过下载；旧版服务器不按订阅过滤事件
+    fn outside_subscription(&self, filename: &str, details: &FontDetails) -> bool {
+        let outside = !self.options.subscription().matches(filename, details.family.as_deref(), details.tags.as_ref());
+        if outside {
+            info!("Font '{}' is outside the subscription, not fetching it", filename);
+        }
+        outside
+    }
+
+    async fn run_command(&self, command: ClientCommand) -> Result<()> {
+        match command {
+            ClientCommand::Resync => {
+                info!("Server requested a resync");
+                self.catch_up_or_sync().await
+            }
+            ClientCommand::Pause => {
+                info!("Server paused automatic downloads");
+                self.paused.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            ClientCommand::Resume => {
+                info!("Server resumed automatic downloads, catching up");
+                self.paused.store(false, Ordering::Relaxed);
+                self.catch_up_or_sync().await
+            }
+            ClientCommand::FetchFont { filename } => {
+                // 指令中的文件名不能指向下载目录之外
+                if Path::new(&filename).file_name().is_none_or(|name| name != filename.as_str()) {
+                    anyhow::bail!("Refusing to fetch '{}': not a plain file name", filename);
+                }
+                info!("Server requested font '{}'", filename);
+                let list = ApiClient::new(&self.server_url)?.list_fonts_hashed(&Default::default()).await?;
+                let font = list
+                    .fonts
+                    .into_iter()
+                    .find(|font| font.name == filename)
+                    .with_context(|| format!("Font '{}' is not on the server", filename))?;
+                self.download_font(&font.name, &font.sha256).await
+            }
+        }
+    }
+
+    // 逐个下载并安装，单个字体失败不影响其余字体；返回成功的数量
+    pub async fn download_fonts(&self, fonts: &[(String, String)]) -> Result<usize> {
+        tokio::fs::create_dir_all(&self.download_dir)
+            .await
+            .context("Failed to create download directory")?;
+        let mut downloaded = 0;
+        for (filename, sha256) in fonts {
+            match self.download_font(filename, sha256).await {
+                Ok(()) => downloaded += 1,
+                Err(e) => error!("Failed to download font {}: {}", filename, e),
+            }
+        }
+        Ok(downloaded)
     }
 
     async fn download_font(&self, filename: &str, expected_sha256: &str) -> Result<()> {
-        let font_path = self.download_dir.join(filename);
+        if ClientState::load().holds_pinned(filename, expected_sha256) {
+            info!("Font {} is pinned, keeping the local version", filename);
+            return Ok(());
+        }
+        let mut font_path = self.download_dir.join(filename);
+        
+        // 安装后副本已清理的字体按记录的哈希判断
+        if !font_path.exists() && ClientState::load().downloaded_sha256(filename) == Some(expected_sha256) {
+            info!("Font {} is already installed with correct SHA256, skipping download", filename);
+            return Ok(());
+        }
         
         // 检查字体是否已存在且 SHA256 正确
         if font_path.exists() {
@@ -153,25 +382,75 @@ impl WebSocketClient {
                     info!("Font {} already exists with correct SHA256, skipping download", filename);
                     return Ok(());
                 }
+                
+                // 本地副本自上次同步后未被修改时直接更新，否则按冲突处理
+                let last_synced = ClientState::load()
+                    .server(&self.server_url)
+                    .and_then(|s| s.synced.get(filename).cloned());
+                if last_synced.as_deref() != Some(local_sha256.as_str()) {
+                    let conflict = FileConflict {
+                        filename,
+                        local_sha256: &local_sha256,
+                        remote_sha256: expected_sha256,
+                        local_modified: get_file_timestamp(&font_path).ok(),
+                        remote_modified: None,
+                        direction: SyncDirection::Download,
+                        counterpart: None,
+                    };
+                    match prompt_conflict_resolution(&conflict, self.options.interactive, self.options.on_conflict).await? {
+                        ConflictResolution::Overwrite => {}
+                        ConflictResolution::Rename => {
+                            let mut counter = 1;
+                            while font_path.exists() {
+                                font_path = self
+                                    .download_dir
+                                    .join(generate_unique_filename(Path::new(filename), counter));
+                                counter += 1;
+                            }
+                            info!("Saving font {} as {:?}", filename, font_path.file_name().unwrap_or_default());
+                        }
+                        ConflictResolution::Skip => {
+                            info!("Keeping modified local copy of {}", filename);
+                            return Ok(());
+                        }
+                    }
+                }
             }
         }
 
-        info!("Downloading font: {}", filename);
-        
-        // 从服务器下载
-        let server_url = self.server_url.clone();
-        let client = reqwest::Client::new();
-        let url = format!("{}/fonts/{}", server_url, filename);
-        
-        let response = client.get(&url).send().await
-            .context("Failed to download font")?;
-        
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to download font: HTTP {}", response.status()));
+        // 只接收订阅范围内的字体
+        let subscription = self.options.subscription();
+        if !subscription.is_empty() {
+            let subscribed = ApiClient::new(&self.server_url)?.list_fonts_tagged(&self.options.tags).await?;
+            if !subscribed.fonts.iter().any(|f| f.name == filename && subscription.matches_font(f)) {
+                info!("Font {} is outside the subscription, skipping", filename);
+                return Ok(());
+            }
         }
+
+        // 事件中的哈希须与签名清单一致
+        if let Some(hashes) = verified_manifest(&self.server_url, &self.options).await?
+            && hashes.get(filename).map(String::as_str) != Some(expected_sha256)
+        {
+            return Err(anyhow::anyhow!(
+                "Font {} does not match the signed manifest, refusing",
+                filename
+            ));
+        }
+
+        info!("Downloading font: {}", filename);
         
-        let bytes = response.bytes().await
-            .context("Failed to read font data")?;
+        // 从服务器下载，本地已有旧版本时只获取增量
+        let api = ApiClient::new(&self.server_url)?;
+        let base = if self.options.e2e_key.is_none() { tokio::fs::read(&font_path).await.ok() } else { None };
+        let fetched = match &base {
+            Some(base) => match api.fetch_font_delta(filename, base).await {
+                Ok((data, modified)) => Ok((bytes::Bytes::from(data), modified)),
+                Err(_) => api.fetch_font(filename).await,
+            },
+            None => api.fetch_font(filename).await,
+        };
+        let (bytes, modified) = fetched.context("Failed to download font")?;
         
         // 校验 SHA256
         let downloaded_sha256 = calculate_sha256_from_bytes(&bytes)?;
@@ -182,56 +461,135 @@ impl WebSocketClient {
             ));
         }
         
+        // 端到端加密的字体需先解密
+        let bytes = if crate::e2e::is_encrypted(&bytes) {
+            let key = self.options.e2e_key.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("Font {} is end-to-end encrypted but no team key is configured", filename)
+            })?;
+            key.decrypt(&bytes)?
+        } else {
+            bytes.to_vec()
+        };
+        let content_sha256 = calculate_sha256_from_bytes(&bytes)?;
+        
         // 保存字体文件
-        tokio::fs::write(&font_path, bytes)
-            .await
-            .context("Failed to save font file")?;
+        crate::utils::write_atomic(&font_path, &bytes, Some(&content_sha256)).context("Failed to save font file")?;
+        if let Some(modified) = modified
+            && let Err(e) = crate::utils::set_file_timestamp(&font_path, modified)
+        {
+            warn!("Failed to set modified time of {:?}: {}", font_path, e);
+        }
         
         info!("Successfully downloaded and verified font: {}", filename);
+        // 重命名保存的副本与服务器上的同名文件不对应
+        if font_path == self.download_dir.join(filename)
+            && let Err(e) = ClientState::record_synced(&self.server_url, [(filename.to_string(), content_sha256)])
+        {
+            warn!("Failed to save client state: {}", e);
+        }
         
         // 安装字体
         self.install_downloaded_font(&font_path).await?;
+        if let Err(e) = download_cache::release_installed(&font_path) {
+            warn!("Failed to clean up download cache: {}", e);
+        }
         
         Ok(())
     }
 
     async fn handle_font_removal(&self, filename: &str) -> Result<()> {
+        // 通过下载目录中的文件校验是否同一字体，副本已清理时使用下载时记录的哈希
+        let download_path = self.download_dir.join(filename);
+        let download_sha256 = if download_path.exists() {
+            calculate_sha256(&download_path)?
+        } else {
+            match ClientState::load().downloaded_sha256(filename) {
+                Some(sha256) => sha256.to_string(),
+                None => return Ok(()),
+            }
+        };
+        if self.uninstall_system_copies(filename, &download_sha256).await? {
+            info!("Removed font from system: {}", filename);
+
+            // 同时移除下载目录中的文件与记录
+            if download_path.exists() {
+                tokio::fs::remove_file(&download_path)
+                    .await
+                    .context("Failed to remove font from download directory")?;
+            }
+            if let Err(e) = ClientState::forget_download(filename) {
+                warn!("Failed to save client state: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // 本地副本与服务器内容一致时直接改名并重新安装，否则按删除原名称并下载新名称处理
+    async fn handle_font_rename(&self, from: &str, to: &str, sha256: &str) -> Result<()> {
+        let from_path = self.download_dir.join(from);
+        let to_path = self.download_dir.join(to);
+        let unchanged = calculate_sha256(&from_path).is_ok_and(|local| local == sha256);
+        if !unchanged || to_path.exists() {
+            self.handle_font_removal(from).await?;
+            if self.downloads_paused(to) {
+                return Ok(());
+            }
+            return self.download_font(to, sha256).await;
+        }
+
+        self.uninstall_system_copies(from, sha256).await?;
+        tokio::fs::rename(&from_path, &to_path)
+            .await
+            .context("Failed to rename font in download directory")?;
+        if let Err(e) = ClientState::record_moved(&self.server_url, from, to, sha256) {
+            warn!("Failed to save client state: {}", e);
+        }
+        info!("Renamed local copy of {} to {}", from, to);
+        self.install_downloaded_font(&to_path).await
+    }
+
+    // 卸载系统字体目录中内容为 sha256 的副本，返回是否卸载了任何副本
+    async fn uninstall_system_copies(&self, filename: &str, sha256: &str) -> Result<bool> {
+        // 安装时规范化过的字体在系统目录中是另一个名称
+        let state = ClientState::load();
+        let installed_names: Vec<&str> = std::iter::once(filename).chain(state.normalized_names(filename)).collect();
+        let candidates: Vec<PathBuf> = self
+            .local_font_dirs
+            .iter()
+            .flat_map(|dir| installed_names.iter().map(|name| dir.join(name)).collect::<Vec<_>>())
+            .collect();
+
         // 从系统字体目录中查找并移除字体
-        for font_dir in &self.local_font_dirs {
-            let font_path = font_dir.join(filename);
-            if font_path.exists() {
-                // 通过下载目录中的文件校验是否同一字体
-                let download_path = self.download_dir.join(filename);
-                if download_path.exists() {
-                    let system_sha256 = calculate_sha256(&font_path)?;
-                    let download_sha256 = calculate_sha256(&download_path)?;
-                    
-                    if system_sha256 == download_sha256 {
-                        // 从系统字体目录移除
-                        tokio::fs::remove_file(&font_path)
-                            .await
-                            .context("Failed to remove font from system")?;
-                        
-                        info!("Removed font from system: {}", filename);
-                        
-                        // 同时移除下载目录中的文件
-                        tokio::fs::remove_file(&download_path)
-                            .await
-                            .context("Failed to remove font from download directory")?;
-                    }
+        let mut uninstalled = false;
+        for font_path in candidates {
+            if font_path.exists() && calculate_sha256(&font_path)? == sha256 {
+                if let Err(e) = ProtectedPaths::load().check_removal(&font_path) {
+                    warn!("Skipped removing font from system: {}", e);
+                    continue;
                 }
+
+                // 从系统字体目录移除
+                font_installer::uninstall_font(&font_path).await?;
+                uninstalled = true;
             }
         }
         
-        Ok(())
+        Ok(uninstalled)
+    }
+
+    // 从下载目录安装时以硬链接代替复制，与缓存中的副本共用磁盘空间
+    fn install_options(&self) -> InstallOptions {
+        InstallOptions { link: true, ..self.options.install_options() }
     }
 
     async fn install_downloaded_font(&self, font_path: &Path) -> Result<()> {
         info!("Installing downloaded font: {:?}", font_path.file_name().unwrap_or_default());
         
-        match font_installer::install_font(font_path).await {
-            Ok(_) => {
-                info!("Successfully installed font");
+        match font_installer::install_font(font_path, self.install_options()).await {
+            Ok(font) => {
+                info!("Successfully installed font: {}", font.label());
+                self.installed.lock().push(font);
                 Ok(())
             }
             Err(e) => {
@@ -241,18 +599,136 @@ impl WebSocketClient {
         }
     }
 
+    async fn catch_up_or_sync(&self) -> Result<()> {
+        let last_seq = self.last_event_seq();
+
+        let latest_seq = match last_seq {
+            Some(since) => match ApiClient::new(&self.server_url)?.all_events(since).await {
+                Ok(page) if page.truncated => {
+                    warn!("Server no longer keeps the events since #{}, performing full sync", since);
+                    self.full_sync().await?
+                }
+                // 服务器日志序号回退说明日志被重置，需要完整同步
+                Ok(page) if page.latest_seq >= since => {
+                    info!("Replaying {} missed events since #{}", page.events.len(), since);
+                    // 回放失败时停在失败事件之前，下次连接从该事件重新回放
+                    let mut replayed = page.latest_seq;
+                    let mut previous = since;
+                    for record in page.events {
+                        if let Err(e) = self.replay_event(record.event).await {
+                            error!("Failed to replay event #{}: {}", record.seq, e);
+                            replayed = previous;
+                            break;
+                        }
+                        previous = record.seq;
+                    }
+                    Some(replayed)
+                }
+                Ok(_) => {
+                    warn!("Server event log was reset, performing full sync");
+                    self.full_sync().await?
+                }
+                Err(e) => {
+                    warn!("Event catch-up unavailable ({}), performing full sync", e);
+                    self.full_sync().await?
+                }
+            },
+            None => self.full_sync().await?,
+        };
+
+        if let Some(seq) = latest_seq {
+            self.save_event_seq(seq);
+        }
+
+        Ok(())
+    }
+
+    // 完整同步前先记录当前最新序号，之后的事件会在下次启动时回放
+    async fn full_sync(&self) -> Result<Option<u64>> {
+        let latest_seq = match ApiClient::new(&self.server_url)?.events(0, Some(0)).await {
+            Ok(page) => Some(page.latest_seq),
+            Err(e) => {
+                warn!("Server does not provide an event log: {}", e);
+                None
+            }
+        };
+
+        self.perform_initial_sync().await?;
+        Ok(latest_seq)
+    }
+
+    async fn replay_event(&self, event: WebSocketMessage) -> Result<()> {
+        match event {
+            WebSocketMessage::FontAdded { filename, sha256, details, .. }
+            | WebSocketMessage::FontModified { filename, sha256, details, .. } => {
+                self.update_tombstones(|tombstones| {
+                    tombstones.remove(&filename);
+                });
+                if self.downloads_paused(&filename) || self.outside_subscription(&filename, &details) {
+                    return Ok(());
+                }
+                self.download_font(&filename, &sha256).await
+            }
+            WebSocketMessage::FontRemoved { filename, sha256, .. } => {
+                if let Some(sha256) = sha256 {
+                    let deleted_at = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    self.update_tombstones(|tombstones| {
+                        tombstones.insert(filename.clone(), Tombstone { sha256, deleted_at });
+                    });
+                }
+                self.handle_font_removal(&filename).await
+            }
+            WebSocketMessage::FontRenamed { from, to, sha256, .. } => {
+                self.update_tombstones(|tombstones| {
+                    tombstones.remove(&to);
+                });
+                self.handle_font_rename(&from, &to, &sha256).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update_tombstones(&self, update: impl FnOnce(&mut std::collections::HashMap<String, Tombstone>)) {
+        if let Err(e) = ClientState::update(|state| update(&mut state.server_mut(&self.server_url).tombstones)) {
+            warn!("Failed to save client state: {}", e);
+        }
+    }
+
+    // 完整同步时按服务器的删除记录移除本地副本，弥补错过的删除事件
+    async fn apply_server_tombstones(&self) -> Result<()> {
+        let tombstones = ApiClient::new(&self.server_url)?.tombstones().await?.tombstones;
+        for entry in &tombstones {
+            if let Err(e) = self.handle_font_removal(&entry.name).await {
+                error!("Failed to remove deleted font {}: {}", entry.name, e);
+            }
+        }
+        self.update_tombstones(|known| {
+            *known = tombstones.into_iter().map(|t| (t.name, t.tombstone)).collect();
+        });
+        Ok(())
+    }
+
     async fn perform_initial_sync(&self) -> Result<()> {
         info!("Performing initial font sync...");
+        if let Err(e) = self.apply_server_tombstones().await {
+            info!("Server does not provide deletion records: {}", e);
+        }
         
         // 上传本地字体到服务器
         let mut total_uploaded = 0;
+        let mut report = SyncReport::default();
+        let started_at = chrono::Utc::now().timestamp() as u64;
         
         for font_dir in &self.local_font_dirs {
             if font_dir.exists() {
                 let (uploaded, _) = upload_local_fonts(
                     &self.server_url,
                     font_dir,
-                    false, // 自动同步使用非交互模式
+                    &self.options,
+                    &mut report,
                 ).await?;
                 
                 total_uploaded += uploaded;
@@ -265,39 +741,257 @@ impl WebSocketClient {
         let (downloaded, skipped) = download_server_fonts(
             &self.server_url,
             &self.download_dir,
-            false, // 自动同步使用非交互模式
+            &self.options,
+            &mut report,
         ).await?;
         
         info!("Download sync complete: {} downloaded, {} skipped", downloaded, skipped);
+        report.log_summary();
+        sync_history::record(&self.server_url, started_at, &report, false);
         
         // 安装已下载字体
         if downloaded > 0 {
-            let (installed, failed) = font_installer::install_fonts_from_directory(&self.download_dir).await?;
+            let results = font_installer::install_fonts_from_directory(&self.download_dir, self.install_options(), &Progress::none()).await?;
+            let (installed, failed, _) = font_installer::tally(&results);
             info!("Installation complete: {} installed, {} failed", installed, failed);
+            self.installed.lock().extend(results.iter().filter_map(|r| r.installed()).cloned());
+            for result in results.iter().filter(|r| r.is_installed()) {
+                if let Err(e) = download_cache::release_installed(&result.path) {
+                    warn!("Failed to clean up download cache: {}", e);
+                }
+            }
         }
         
         Ok(())
     }
 
-    async fn connect_ws(&self) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, String)> {
-        let ws_urls = build_ws_urls(&self.server_url)?;
-        let mut last_err = None;
+    // WebSocket 握手被代理拦截时改用 SSE 接收变更通知，断线后按 Last-Event-ID 续传
+    async fn run_with_sse(&self, mut response: reqwest::Response) -> Result<()> {
+        tokio::fs::create_dir_all(&self.download_dir)
+            .await
+            .context("Failed to create download directory")?;
+
+        // 没有记录过序号时无法续传，先完整同步
+        if self.last_event_seq().is_none() {
+            self.catch_up_or_sync().await?;
+        }
+
+        let api = ApiClient::new(&self.server_url)?;
+        let mut backoff = Duration::from_secs(1);
+        // 同步被取消（如 GUI 关闭实时同步）时结束，不再重连
+        let cancel = self.options.cancel.clone();
+        loop {
+            let consumed = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                consumed = self.consume_sse(response) => consumed,
+            };
+            match consumed {
+                Ok(()) => {
+                    info!("Event stream closed by server, reconnecting");
+                    backoff = Duration::from_secs(1);
+                }
+                Err(e) => warn!("Event stream interrupted: {}", e),
+            }
+
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => return Ok(()),
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(Duration::from_secs(60));
+                match api.event_stream(self.last_event_seq()).await {
+                    Ok(next) => {
+                        response = next;
+                        break;
+                    }
+                    Err(e) => warn!("Failed to reconnect event stream: {}, retrying in {:?}", e, backoff),
+                }
+            }
+        }
+    }
+
+    async fn consume_sse(&self, mut response: reqwest::Response) -> Result<()> {
+        info!("Receiving change notifications via Server-Sent Events");
+        let mut parser = SseParser::default();
+        while let Some(chunk) = response.chunk().await? {
+            for event in parser.push(&chunk) {
+                match serde_json::from_str::<WebSocketMessage>(&event.data) {
+                    Ok(WebSocketMessage::ResyncRequired { dropped }) => {
+                        warn!("Server no longer keeps {} events since the last one received, catching up", dropped);
+                        if let Err(e) = self.catch_up_or_sync().await {
+                            error!("Failed to catch up: {:#}", e);
+                        }
+                    }
+                    Ok(message) => {
+                        if let Err(e) = self.replay_event(message).await {
+                            error!("Failed to apply server event: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Ignoring malformed server event: {}", e),
+                }
+
+                if let Some(seq) = event.id.and_then(|id| id.parse::<u64>().ok()) {
+                    self.save_event_seq(seq);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn save_event_seq(&self, seq: u64) {
+        let result = ClientState::update(|state| {
+            state.server_mut(&self.server_url).last_event_seq = Some(seq);
+        });
+        if let Err(e) = result {
+            warn!("Failed to save client state: {}", e);
+        }
+    }
+
+    fn last_event_seq(&self) -> Option<u64> {
+        ClientState::load().server(&self.server_url).and_then(|s| s.last_event_seq)
+    }
+
+    // 在握手请求中携带客户端身份，便于服务器关联会话
+    fn handshake_request(
+        &self,
+        ws_url: &str,
+    ) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request> {
+        let mut request = ws_url
+            .into_client_request()
+            .context("Invalid WebSocket URL")?;
+
+        let identity = ClientIdentity::current();
+        let headers = request.headers_mut();
+        for (name, value) in identity.headers() {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        }
+        headers.insert(WS_FEATURES_HEADER, HeaderValue::from_static(WS_FEATURES));
+        headers.insert(api::VERSION_HEADER, HeaderValue::from_static(env!("CARGO_PKG_VERSION")));
+        headers.insert(api::PROTOCOL_HEADER, HeaderValue::from(api::PROTOCOL_VERSION));
+        // 声明订阅后服务器只推送范围内字体的新增与修改
+        let subscription = self.options.subscription();
+        if !subscription.is_empty() {
+            let value = HeaderValue::from_str(&subscription.to_header()).context("Invalid subscription header")?;
+            headers.insert(api::SUBSCRIPTION_HEADER, value);
+        }
+        // 命令行指定的 ID 优先于持久化 ID
+        if let Ok(value) = HeaderValue::from_str(&self.client_id) {
+            headers.insert(CLIENT_ID_HEADER, value);
+        }
+        if let Some(token) = credentials::token_for(&self.server_url) {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .context("Stored token is not a valid header value")?;
+            headers.insert("authorization", value);
+        }
+
+        Ok(request)
+    }
+
+    async fn connect_ws(&self) -> Result<(WsStream, String)> {
+        let unix_socket = http::unix_socket_path(&self.server_url);
+        let mut ws_urls = build_ws_urls(&http::resolve_server_url(&self.server_url))?;
+        // 经 Unix 套接字连接时没有旧版的端口 + 1，也不经代理
+        if unix_socket.is_some() {
+            ws_urls.truncate(1);
+        }
+        let mut errors = Vec::new();
 
         for ws_url in ws_urls {
             info!("Connecting to WebSocket server: {}", ws_url);
-            match connect_async(&ws_url).await {
-                Ok((ws_stream, _)) => return Ok((ws_stream, ws_url)),
-                Err(e) => last_err = Some(e),
+            let request = self.handshake_request(&ws_url)?;
+            match open_ws(request, &ws_url, unix_socket.as_deref()).await {
+                Ok(ws_stream) => return Ok((ws_stream, ws_url)),
+                // 协议不兼容时其他地址也一样，直接报告服务器的说明
+                Err(e) => match incompatible_server(&e) {
+                    Some(message) => anyhow::bail!("Incompatible server version: {}", message),
+                    None => errors.push((ws_url, e)),
+                },
             }
         }
 
-        Err(anyhow::anyhow!(
-            "Failed to connect to WebSocket server: {}",
-            last_err
-                .map(|e| e.to_string())
-                .unwrap_or_else(|| "unknown error".to_string())
-        ))
+        // 保留首个地址的原始错误以便判断握手状态，其余地址的失败原因附在说明中
+        let mut errors = errors.into_iter();
+        let Some((ws_url, error)) = errors.next() else {
+            anyhow::bail!("Failed to connect to WebSocket server: no address to try");
+        };
+        let others: Vec<String> = errors.map(|(url, e)| format!("{}: {:#}", url, e)).collect();
+        let context = if others.is_empty() {
+            format!("Failed to connect to WebSocket server at {}", ws_url)
+        } else {
+            format!("Failed to connect to WebSocket server at {} (also tried {})", ws_url, others.join("; "))
+        };
+        Err(error.context(context))
+    }
+}
+
+// Unix 套接字上直接完成握手，其他地址按代理配置连接
+async fn open_ws(
+    request: tokio_tungstenite::tungstenite::handshake::client::Request,
+    ws_url: &str,
+    unix_socket: Option<&Path>,
+) -> Result<WsStream> {
+    let Some(path) = unix_socket else {
+        return connect_with_proxy(request, ws_url).await;
+    };
+    #[cfg(unix)]
+    {
+        let stream: Box<dyn Connection> = Box::new(http::connect_unix_socket(path).await?);
+        Ok(tokio_tungstenite::client_async(request, stream).await?.0)
+    }
+    #[cfg(not(unix))]
+    anyhow::bail!("Unix domain sockets are not supported on this platform ({:?})", path)
+}
+
+// 配置了代理时先经代理建立隧道，再在隧道上完成 WebSocket 握手
+async fn connect_with_proxy(
+    request: tokio_tungstenite::tungstenite::handshake::client::Request,
+    ws_url: &str,
+) -> Result<WsStream> {
+    let url = reqwest::Url::parse(ws_url).context("Invalid WebSocket URL")?;
+    let host = url.host_str().context("WebSocket URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    // 未启用 TLS 支持，不能连接 wss
+    if url.scheme() == "wss" {
+        anyhow::bail!("wss:// connections require TLS support, which is not enabled in this build");
+    }
+    let stream = match http::proxy_for(&url) {
+        Some(proxy) => {
+            debug!("Connecting to {} through proxy {}:{}", ws_url, proxy.host_str().unwrap_or_default(), proxy.port_or_known_default().unwrap_or_default());
+            http::tunnel(&proxy, host, port).await?
+        }
+        None => TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port)).await?,
+    };
+    let stream: Box<dyn Connection> = Box::new(stream);
+    Ok(tokio_tungstenite::client_async(request, stream).await?.0)
+}
+
+// 服务器以 HTTP 响应拒绝握手时的状态码
+pub fn handshake_status(error: &anyhow::Error) -> Option<u16> {
+    use tokio_tungstenite::tungstenite::Error;
+
+    match error.downcast_ref::<Error>() {
+        Some(Error::Http(response)) => Some(response.status().as_u16()),
+        _ => None,
+    }
+}
+
+// 握手被拒绝为 426 时取出服务器的说明
+fn incompatible_server(error: &anyhow::Error) -> Option<String> {
+    use tokio_tungstenite::tungstenite::Error;
+
+    if handshake_status(error) != Some(reqwest::StatusCode::UPGRADE_REQUIRED.as_u16()) {
+        return None;
     }
+    let Some(Error::Http(response)) = error.downcast_ref::<Error>() else {
+        return None;
+    };
+    let body = response.body().as_deref().unwrap_or_default();
+    Some(match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(e) => e.message.unwrap_or(e.error),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    })
 }
 
 fn calculate_sha256_from_bytes(data: &[u8]) -> Result<String> {
@@ -313,14 +1007,33 @@ fn calculate_sha256_from_bytes(data: &[u8]) -> Result<String> {
 pub async fn start_websocket_client(
     server_url: String,
     client_id: String,
+    options: SyncOptions,
 ) -> Result<WebSocketClient> {
-    let client = WebSocketClient::new(server_url, client_id);
+    let client = WebSocketClient::new(server_url, client_id, options);
 
     let (ws_stream, ws_url) = match client.connect_ws().await {
         Ok(result) => result,
-        Err(e) => {
-            error!("WebSocket client error: {}", e);
-            return Err(e);
+        Err(ws_error) => {
+            // 服务器可达但无法升级为 WebSocket 时回退到 SSE
+            let stream = ApiClient::new(&client.server_url)?
+                .event_stream(client.last_event_seq())
+                .await;
+            let response = match stream {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("WebSocket client error: {:#}", ws_error);
+                    return Err(ws_error.context(format!("Event stream fallback failed: {}", e)));
+                }
+            };
+            warn!("WebSocket unavailable ({:#}), falling back to Server-Sent Events", ws_error);
+
+            let client_clone = client.clone();
+            tokio::spawn(async move {
+                if let Err(e) = client_clone.run_with_sse(response).await {
+                    error!("Event stream client error: {}", e);
+                }
+            });
+            return Ok(client);
         }
     };
 
@@ -335,6 +1048,14 @@ pub async fn start_websocket_client(
     Ok(client)
 }
 
+// 只完成一次握手后关闭，用于连接诊断；返回握手成功的地址
+pub async fn check_connection(server_url: &str, client_id: &str) -> Result<String> {
+    let client = WebSocketClient::new(server_url.to_string(), client_id.to_string(), SyncOptions::default());
+    let (mut ws_stream, ws_url) = client.connect_ws().await?;
+    let _ = ws_stream.close(None).await;
+    Ok(ws_url)
+}
+
 fn build_ws_urls(server_url: &str) -> Result<Vec<String>> {
     let mut url = reqwest::Url::parse(server_url)
         .or_else(|_| reqwest::Url::parse(&format!("ws://{}", server_url)))
@@ -351,18 +1072,75 @@ fn build_ws_urls(server_url: &str) -> Result<Vec<String>> {
         _ => return Err(anyhow::anyhow!("Unsupported URL scheme")),
     }
 
-    let mut urls = vec![url.to_string()];
-    if let Some(port) = url.port() {
-        if let Some(next_port) = port.checked_add(1) {
+    // 旧版服务器在端口 + 1 的根路径上提供 WebSocket
+    let legacy = url
+        .port()
+        .and_then(|port| port.checked_add(1))
+        .and_then(|next_port| {
             let mut alt = url.clone();
-            if alt.set_port(Some(next_port)).is_ok() {
-                let alt_str = alt.to_string();
-                if alt_str != urls[0] {
-                    urls.push(alt_str);
-                }
-            }
-        }
+            alt.set_port(Some(next_port)).ok().map(|_| alt.to_string())
+        });
+
+    // 新版服务器在 HTTP 同端口的 /ws 路由上提供 WebSocket，保留反向代理的路径前缀
+    if !url.path().trim_end_matches('/').ends_with("/ws") {
+        let path = format!("{}/ws", url.path().trim_end_matches('/'));
+        url.set_path(&path);
+    }
+
+    let mut urls = vec![url.to_string()];
+    if let Some(alt_str) = legacy.filter(|alt| *alt != urls[0]) {
+        urls.push(alt_str);
     }
 
     Ok(urls)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::build_ws_urls;
+    use crate::api::Subscription;
+    use crate::websocket_server::{FontDetails, WebSocketMessage};
+
+    fn matches_tags(tags: &[&str], details: &FontDetails) -> bool {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        Subscription::new(&[], &tags, &[]).matches("a.ttf", details.family.as_deref(), details.tags.as_ref())
+    }
+
+    #[test]
+    fn build_ws_urls_prefers_same_port_route() {
+        let urls = build_ws_urls("http://localhost:8080").unwrap();
+        assert_eq!(urls, vec!["ws://localhost:8080/ws", "ws://localhost:8081/"]);
+    }
+
+    #[test]
+    fn build_ws_urls_keeps_proxy_prefix() {
+        let urls = build_ws_urls("https://fonts.example.com/fontsync/").unwrap();
+        assert_eq!(urls, vec!["wss://fonts.example.com/fontsync/ws"]);
+    }
+
+    #[test]
+    fn font_events_are_filtered_by_their_tags() {
+        // 旧版服务器的事件没有附加信息，仍可解析并照常下载
+        let legacy = r#"{"type":"FontAdded","data":{"filename":"a.ttf","sha256":"ab","size":7}}"#;
+        let WebSocketMessage::FontAdded { details, .. } = serde_json::from_str(legacy).unwrap() else {
+            panic!("expected FontAdded");
+        };
+        assert_eq!(details, FontDetails::default());
+        assert!(matches_tags(&["cjk"], &details));
+
+        let current = r#"{"type":"FontModified","data":{"filename":"a.ttf","sha256":"ab","size":7,"family":"Noto","format":"ttf","tags":["cjk","sans"]}}"#;
+        let WebSocketMessage::FontModified { details, .. } = serde_json::from_str(current).unwrap() else {
+            panic!("expected FontModified");
+        };
+        assert_eq!(details.family.as_deref(), Some("Noto"));
+        assert!(matches_tags(&[" cjk "], &details));
+        assert!(!matches_tags(&["serif"], &details));
+        assert!(matches_tags(&[], &details));
+    }
+
+    #[test]
+    fn build_ws_urls_accepts_explicit_ws_path() {
+        let urls = build_ws_urls("ws://127.0.0.1:8080/ws").unwrap();
+        assert_eq!(urls[0], "ws://127.0.0.1:8080/ws");
+    }
+}
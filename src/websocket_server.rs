@@ -1,15 +1,43 @@
 use anyhow::{Context, Result};
-use futures::{SinkExt, StreamExt};
-use log::{error, info, warn};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use log::{debug, error, info, warn};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{Read, Write};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::broadcast;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tokio::time::{interval, Duration};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse as HandshakeError, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::access::Role;
+use crate::api::{ClientCommand, CommandStatus, ErrorCode, ErrorResponse, InstalledFont, Subscription};
+use crate::identity::ClientIdentity;
+use crate::request_log;
+use crate::server::{Denied, ServerPolicy};
+
+// 新增与修改通知附带的字体信息，客户端据此在下载前决定是否需要；旧版服务器不提供，各字段均为空
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FontDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    // 加密或无法解析的字体为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    // 小写的文件扩展名，如 ttf、otf、woff2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    // 服务器上的标签；为空集合表示没有标签，None 表示服务器未提供
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeSet<String>>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -18,14 +46,46 @@ pub enum WebSocketMessage {
         filename: String,
         sha256: String,
         size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        #[serde(default, flatten)]
+        details: FontDetails,
     },
     FontModified {
         filename: String,
         sha256: String,
         size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        #[serde(default, flatten)]
+        details: FontDetails,
     },
     FontRemoved {
         filename: String,
+        // 删除前的内容哈希（加密字体为明文哈希），旧版服务器不提供
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+    },
+    // 服务器端改名，内容不变；未声明 rename 的客户端收到等价的删除与新增事件
+    FontRenamed {
+        from: String,
+        to: String,
+        sha256: String,
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+    },
+    // 批量上传新增的多个字体合并为一条通知，seq 为其中最后一个事件的序号；
+    // 事件日志中仍是逐条的 FontAdded，未声明 aggregate 的客户端也收到逐条的事件
+    FontsAdded {
+        count: usize,
+        names: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        #[serde(skip)]
+        events: Vec<WebSocketMessage>,
     },
     FontListRequest,
     FontListResponse {
@@ -40,9 +100,128 @@ pub enum WebSocketMessage {
         message: String,
     },
     Heartbeat,
+    // 客户端发送队列溢出后通知其通过事件日志重新追赶
+    ResyncRequired {
+        dropped: u64,
+    },
+    // 客户端收到 Command 后回复，message_id 为指令 id
     Ack {
         message_id: String,
     },
+    // 发给单个客户端的管理指令
+    Command {
+        id: String,
+        command: ClientCommand,
+    },
+    // 启动时的后台哈希计算完成，之前列表中待定的字体现在都有哈希
+    HashingComplete {
+        fonts: usize,
+    },
+    // 短时间内连续发生的多个事件，只发给声明支持 batch 的客户端
+    Batch {
+        events: Vec<WebSocketMessage>,
+    },
+    // 客户端安装下载的字体后上报，服务器只记录不转发
+    FontsInstalled {
+        client_id: String,
+        fonts: Vec<InstalledFont>,
+    },
+}
+
+impl WebSocketMessage {
+    // 序列化时 type 字段的取值，用于日志
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::FontAdded { .. } => "FontAdded",
+            Self::FontModified { .. } => "FontModified",
+            Self::FontRemoved { .. } => "FontRemoved",
+            Self::FontRenamed { .. } => "FontRenamed",
+            Self::FontsAdded { .. } => "FontsAdded",
+            Self::FontListRequest => "FontListRequest",
+            Self::FontListResponse { .. } => "FontListResponse",
+            Self::SyncRequest { .. } => "SyncRequest",
+            Self::SyncComplete { .. } => "SyncComplete",
+            Self::Heartbeat => "Heartbeat",
+            Self::ResyncRequired { .. } => "ResyncRequired",
+            Self::Ack { .. } => "Ack",
+            Self::Command { .. } => "Command",
+            Self::HashingComplete { .. } => "HashingComplete",
+            Self::Batch { .. } => "Batch",
+            Self::FontsInstalled { .. } => "FontsInstalled",
+        }
+    }
+
+    // 为字体事件附加事件日志序号，其他消息原样返回
+    pub fn with_seq(self, seq: u64) -> Self {
+        match self {
+            WebSocketMessage::FontAdded { filename, sha256, size, details, .. } => {
+                WebSocketMessage::FontAdded { filename, sha256, size, seq: Some(seq), details }
+            }
+            WebSocketMessage::FontModified { filename, sha256, size, details, .. } => {
+                WebSocketMessage::FontModified { filename, sha256, size, seq: Some(seq), details }
+            }
+            WebSocketMessage::FontRemoved { filename, sha256, .. } => {
+                WebSocketMessage::FontRemoved { filename, sha256, seq: Some(seq) }
+            }
+            WebSocketMessage::FontRenamed { from, to, sha256, size, .. } => {
+                WebSocketMessage::FontRenamed { from, to, sha256, size, seq: Some(seq) }
+            }
+            other => other,
+        }
+    }
+
+    // 事件日志序号；合并发送的事件取其中最大的序号
+    pub fn seq(&self) -> Option<u64> {
+        match self {
+            WebSocketMessage::FontAdded { seq, .. }
+            | WebSocketMessage::FontModified { seq, .. }
+            | WebSocketMessage::FontRemoved { seq, .. }
+            | WebSocketMessage::FontRenamed { seq, .. }
+            | WebSocketMessage::FontsAdded { seq, .. } => *seq,
+            WebSocketMessage::Batch { events } => events.iter().filter_map(WebSocketMessage::seq).max(),
+            _ => None,
+        }
+    }
+
+    // 旧客户端不认识改名事件，拆成删除原名称与新增新名称，二者共用同一序号
+    pub fn without_rename(self) -> Vec<WebSocketMessage> {
+        match self {
+            WebSocketMessage::FontRenamed { from, to, sha256, size, seq } => vec![
+                WebSocketMessage::FontRemoved { filename: from, sha256: Some(sha256.clone()), seq },
+                WebSocketMessage::FontAdded { filename: to, sha256, size, seq, details: FontDetails::default() },
+            ],
+            other => vec![other],
+        }
+    }
+
+    // 旧客户端不认识合并的新增通知，还原为其中逐条的事件
+    pub fn without_aggregate(self) -> Vec<WebSocketMessage> {
+        match self {
+            WebSocketMessage::FontsAdded { events, .. } => events,
+            other => vec![other],
+        }
+    }
+
+    // 按连接的订阅筛选新增与修改事件，合并的通知只保留范围内的字体；删除与改名照常发送，由客户端按本地文件判断
+    fn for_subscription(&self, subscription: &Subscription) -> Option<WebSocketMessage> {
+        match self {
+            _ if subscription.is_empty() => Some(self.clone()),
+            WebSocketMessage::FontAdded { filename, details, .. } | WebSocketMessage::FontModified { filename, details, .. } => {
+                subscription
+                    .matches(filename, details.family.as_deref(), details.tags.as_ref())
+                    .then(|| self.clone())
+            }
+            WebSocketMessage::FontsAdded { events, .. } => {
+                let kept: Vec<_> = events.iter().filter_map(|event| event.for_subscription(subscription)).collect();
+                match kept.len() {
+                    0 => None,
+                    n if n == events.len() => Some(self.clone()),
+                    _ => Some(create_fonts_added_event(kept)),
+                }
+            }
+            other => Some(other.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,50 +232,170 @@ pub struct FontInfo {
     pub timestamp: u64,
 }
 
+// 每个客户端独立发送队列的容量，慢客户端不会拖累其他连接
+const CLIENT_QUEUE_CAPACITY: usize = 256;
+
+// 保留最近的指令状态供查询
+const MAX_COMMAND_HISTORY: usize = 256;
+
+type Clients = Arc<RwLock<HashMap<SocketAddr, ClientInfo>>>;
+type Commands = Arc<RwLock<VecDeque<CommandStatus>>>;
+
+// 客户端在握手中声明支持的扩展，逗号分隔
+pub const WS_FEATURES_HEADER: &str = "x-fontsync-ws-features";
+pub const WS_FEATURES: &str = "batch, deflate, rename, aggregate";
+// 第一个事件之后等待后续事件的时长与单批上限
+const BATCH_WINDOW: Duration = Duration::from_millis(50);
+const MAX_BATCH_EVENTS: usize = 200;
+// 超过该长度的 JSON 才值得压缩
+const DEFLATE_THRESHOLD: usize = 1024;
+
+// tungstenite 与 warp 都不支持 permessage-deflate 扩展，压缩在消息层完成：
+// 声明 deflate 的连接上，二进制帧为 raw deflate 压缩的 JSON，文本帧仍是原始 JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WsFeatures {
+    pub batch: bool,
+    pub deflate: bool,
+    pub rename: bool,
+    pub aggregate: bool,
+}
+
+impl WsFeatures {
+    pub fn parse(header: Option<&str>) -> Self {
+        let mut features = Self::default();
+        for feature in header.unwrap_or_default().split(',').map(str::trim) {
+            match feature {
+                "batch" => features.batch = true,
+                "deflate" => features.deflate = true,
+                "rename" => features.rename = true,
+                "aggregate" => features.aggregate = true,
+                _ => {}
+            }
+        }
+        features
+    }
+}
+
+// 按连接声明的扩展编码消息，较大的消息在支持时压缩为二进制帧
+pub fn encode_message(msg: &WebSocketMessage, features: WsFeatures) -> Result<Message> {
+    let json = serde_json::to_string(msg).context("Failed to serialize WebSocket message")?;
+    if !features.deflate || json.len() < DEFLATE_THRESHOLD {
+        return Ok(Message::Text(json));
+    }
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    Ok(Message::Binary(encoder.finish().context("Failed to compress WebSocket message")?))
+}
+
+// 文本帧按 JSON 解析，二进制帧先解压
+pub fn decode_message(msg: &Message) -> Result<WebSocketMessage> {
+    match msg {
+        Message::Text(text) => serde_json::from_str(text).context("Invalid WebSocket message"),
+        Message::Binary(data) => {
+            let mut json = String::new();
+            flate2::read::DeflateDecoder::new(&data[..])
+                .read_to_string(&mut json)
+                .context("Failed to decompress WebSocket message")?;
+            serde_json::from_str(&json).context("Invalid WebSocket message")
+        }
+        _ => anyhow::bail!("Not a data frame"),
+    }
+}
+
+// 供管理界面展示的已连接客户端信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedClient {
+    pub client_id: String,
+    pub addr: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    pub connected_at: u64,
+    // 最近一次 Ping/Pong 往返时间，尚未收到 Pong 时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+    // 因发送队列已满而丢弃的事件数
+    #[serde(default)]
+    pub dropped_events: u64,
+}
+
 #[derive(Debug)]
 struct ClientInfo {
     addr: SocketAddr,
     client_id: String,
+    identity: Option<ClientIdentity>,
+    connected_at: u64,
     last_heartbeat: Arc<RwLock<std::time::Instant>>,
+    rtt: Arc<RwLock<Option<Duration>>>,
+    subscription: Subscription,
+    queue: mpsc::Sender<WebSocketMessage>,
+    dropped: Arc<AtomicU64>,
+    resync_required: Arc<AtomicBool>,
 }
 
 pub struct WebSocketServer {
-    clients: Arc<RwLock<HashMap<SocketAddr, ClientInfo>>>,
-    event_sender: broadcast::Sender<WebSocketMessage>,
-    server_addr: SocketAddr,
+    clients: Clients,
+    commands: Commands,
 }
 
-impl WebSocketServer {
-    pub fn new(addr: SocketAddr) -> Self {
-        let (event_sender, _) = broadcast::channel(1024);
-        Self {
-            clients: Arc::new(RwLock::new(HashMap::new())),
-            event_sender,
-            server_addr: addr,
-        }
+impl Default for WebSocketServer {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub async fn start(&self) -> Result<()> {
-        let listener = TcpListener::bind(self.server_addr)
-            .await
-            .context("Failed to bind WebSocket server")?;
-        
-        info!("WebSocket server listening on: {}", self.server_addr);
+impl WebSocketServer {
+    pub fn new() -> Self {
+        let server = Self {
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            commands: Arc::new(RwLock::new(VecDeque::new())),
+        };
 
         // 启动心跳检查器
-        let clients = Arc::clone(&self.clients);
+        let clients = Arc::clone(&server.clients);
         tokio::spawn(async move {
             Self::heartbeat_checker(clients).await;
         });
 
+        server
+    }
+
+    // 旧版独立端口监听（HTTP 端口 + 1），仅为兼容旧客户端保留
+    // 旧版独立端口的连接在握手时与 /ws 路由一样检查读取令牌
+    pub async fn listen(&self, addr: SocketAddr, policy: ServerPolicy) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .context("Failed to bind WebSocket server")?;
+        
+        info!("Legacy WebSocket server listening on: {}", addr);
+
         // 接受传入连接
         while let Ok((stream, addr)) = listener.accept().await {
             let clients = Arc::clone(&self.clients);
-            let event_sender = self.event_sender.clone();
-            let event_receiver = self.event_sender.subscribe();
+            let commands = Arc::clone(&self.commands);
+            let policy = policy.clone();
 
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(stream, addr, clients, event_sender, event_receiver).await {
+                // 回调的错误类型由 tungstenite 决定
+                #[allow(clippy::result_large_err)]
+                let authorize = |request: &Request, response: Response| {
+                    let authorization = request.headers().get("authorization").and_then(|v| v.to_str().ok());
+                    match policy.authorize(authorization, Role::Reader, false) {
+                        Ok(()) => Ok(response),
+                        Err(denied) => {
+                            warn!("Refused legacy WebSocket connection from {}: {}", addr, denied.message);
+                            Err(denied_response(denied))
+                        }
+                    }
+                };
+                let result = match accept_hdr_async(stream, authorize).await {
+                    Ok(ws_stream) => {
+                        Self::handle_connection(ws_stream, addr, None, WsFeatures::default(), Subscription::default(), clients, commands).await
+                    }
+                    Err(e) => Err(anyhow::anyhow!("Failed to accept WebSocket connection: {}", e)),
+                };
+                if let Err(e) = result {
                     error!("WebSocket connection error for {}: {}", addr, e);
                 }
             });
@@ -105,29 +404,76 @@ impl WebSocketServer {
         Ok(())
     }
 
-    async fn handle_connection(
-        stream: TcpStream,
+    // 处理 HTTP 主端口 /ws 路由升级后的连接
+    pub async fn handle_upgrade(
+        &self,
+        socket: warp::ws::WebSocket,
         addr: SocketAddr,
-        clients: Arc<RwLock<HashMap<SocketAddr, ClientInfo>>>,
-        event_sender: broadcast::Sender<WebSocketMessage>,
-        mut event_receiver: broadcast::Receiver<WebSocketMessage>,
-    ) -> Result<()> {
-        let ws_stream = accept_async(stream)
-            .await
-            .context("Failed to accept WebSocket connection")?;
+        identity: Option<ClientIdentity>,
+        features: WsFeatures,
+        subscription: Subscription,
+    ) {
+        // 将 warp 消息类型转换为 tungstenite 消息，复用同一套连接处理逻辑
+        let socket = socket
+            .map(|msg| msg.map(from_warp_message))
+            .with(|msg: Message| futures::future::ready(Ok::<_, warp::Error>(to_warp_message(msg))));
+
+        let clients = Arc::clone(&self.clients);
+        let commands = Arc::clone(&self.commands);
+
+        if let Err(e) = Self::handle_connection(socket, addr, identity, features, subscription, clients, commands).await {
+            error!("WebSocket connection error for {}: {}", addr, e);
+        }
+    }
 
+    async fn handle_connection<S, E>(
+        socket: S,
+        addr: SocketAddr,
+        identity: Option<ClientIdentity>,
+        features: WsFeatures,
+        subscription: Subscription,
+        clients: Clients,
+        commands: Commands,
+    ) -> Result<()>
+    where
+        S: Stream<Item = Result<Message, E>> + Sink<Message> + Unpin,
+        <S as Sink<Message>>::Error: std::error::Error + Send + Sync + 'static,
+        E: std::fmt::Display,
+    {
         info!("New WebSocket connection from: {}", addr);
 
-        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+        let (mut ws_sender, mut ws_receiver) = socket.split();
         
-        // 生成客户端 ID
-        let client_id = format!("client_{}", uuid::Uuid::new_v4());
+        // 优先使用握手中携带的持久 ID，旧客户端临时生成
+        let client_id = match &identity {
+            Some(identity) => {
+                info!(
+                    "Client {} identified as {} on {} ({})",
+                    addr, identity.client_id, identity.hostname, identity.os
+                );
+                identity.client_id.clone()
+            }
+            None => format!("client_{}", uuid::Uuid::new_v4()),
+        };
+        if !subscription.is_empty() {
+            info!("Client {} subscribed to {}", addr, subscription.to_header());
+        }
         
-        // 注册客户端
+        // 注册客户端及其独立发送队列
+        let (queue, mut queue_receiver) = mpsc::channel(CLIENT_QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let resync_required = Arc::new(AtomicBool::new(false));
         let client_info = ClientInfo {
             addr,
             client_id: client_id.clone(),
+            identity,
+            connected_at: chrono::Utc::now().timestamp() as u64,
             last_heartbeat: Arc::new(RwLock::new(std::time::Instant::now())),
+            rtt: Arc::new(RwLock::new(None)),
+            subscription,
+            queue,
+            dropped: Arc::clone(&dropped),
+            resync_required: Arc::clone(&resync_required),
         };
         
         clients.write().insert(addr, client_info);
@@ -148,24 +494,28 @@ impl WebSocketServer {
             .await
             .context("Failed to send welcome message")?;
 
-        // 处理入站消息与广播事件
+        // 处理入站消息与广播事件；Ping 的负载为发送时距连接建立的时间，用于计算往返时间
         let mut heartbeat_interval = interval(Duration::from_secs(30));
+        let connected = std::time::Instant::now();
         
-        loop {
+        'connection: loop {
             tokio::select! {
                 // 处理客户端 WebSocket 消息
                 msg = ws_receiver.next() => {
                     match msg {
-                        Some(Ok(Message::Text(text))) => {
-                            if let Ok(ws_msg) = serde_json::from_str::<WebSocketMessage>(&text) {
-                                Self::handle_client_message(ws_msg, &mut ws_sender, &event_sender, addr).await?;
-                            } else {
-                                warn!("Received invalid message from {}: {}", addr, text);
+                        Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => {
+                            match decode_message(&msg) {
+                                Ok(ws_msg) => {
+                                    // 每条入站消息分配一个请求 ID，与 HTTP 请求写入同一访问日志
+                                    let request_id = request_log::new_request_id();
+                                    let kind = ws_msg.kind();
+                                    let started = std::time::Instant::now();
+                                    Self::handle_client_message(ws_msg, &mut ws_sender, &clients, &commands, addr).await?;
+                                    request_log::log_ws_message(&request_id, kind, msg.len() as u64, started.elapsed(), &client_id, addr);
+                                }
+                                Err(e) => warn!("Received invalid message from {}: {:#}", addr, e),
                             }
                         }
-                        Some(Ok(Message::Binary(_))) => {
-                            // 忽略二进制消息
-                        }
                         Some(Ok(Message::Close(_))) => {
                             info!("Client {} requested close", addr);
                             break;
@@ -173,10 +523,14 @@ impl WebSocketServer {
                         Some(Ok(Message::Ping(_))) => {
                             // Pong 由 tokio-tungstenite 自动处理
                         }
-                        Some(Ok(Message::Pong(_))) => {
-                            // 收到 Pong 时更新心跳
+                        Some(Ok(Message::Pong(payload))) => {
+                            // 收到 Pong 时更新心跳与往返时间
                             if let Some(client) = clients.read().get(&addr) {
                                 *client.last_heartbeat.write() = std::time::Instant::now();
+                                if let Some(rtt) = rtt_from_pong(connected, &payload) {
+                                    debug!("Client {} round-trip time: {:?}", addr, rtt);
+                                    *client.rtt.write() = Some(rtt);
+                                }
                             }
                         }
                         Some(Ok(Message::Frame(_))) => {
@@ -193,30 +547,65 @@ impl WebSocketServer {
                     }
                 }
                 
-                // 处理广播事件
-                event = event_receiver.recv() => {
-                    match event {
-                        Ok(msg) => {
-                            let json_msg = serde_json::to_string(&msg)
-                                .context("Failed to serialize broadcast message")?;
-                            
-                            if let Err(e) = ws_sender.send(Message::Text(json_msg)).await {
-                                error!("Failed to send message to {}: {}", addr, e);
-                                break;
+                // 处理发送队列中的事件
+                event = queue_receiver.recv() => {
+                    let Some(msg) = event else {
+                        error!("Send queue closed for {}", addr);
+                        break;
+                    };
+
+                    // 支持合并的客户端在短时间窗口内收集后续事件，一次发送
+                    let mut events = vec![msg];
+                    if features.batch {
+                        let deadline = tokio::time::Instant::now() + BATCH_WINDOW;
+                        while events.len() < MAX_BATCH_EVENTS {
+                            match tokio::time::timeout_at(deadline, queue_receiver.recv()).await {
+                                Ok(Some(next)) => events.push(next),
+                                _ => break,
                             }
                         }
-                        Err(broadcast::error::RecvError::Lagged(n)) => {
-                            warn!("Client {} lagged by {} messages", addr, n);
+                    }
+                    if !features.aggregate {
+                        events = events.into_iter().flat_map(WebSocketMessage::without_aggregate).collect();
+                    }
+                    if !features.rename {
+                        events = events.into_iter().flat_map(WebSocketMessage::without_rename).collect();
+                    }
+                    // 不支持合并的客户端逐条发送
+                    let messages = match events.len() {
+                        1 => events,
+                        _ if features.batch => vec![WebSocketMessage::Batch { events }],
+                        _ => events,
+                    };
+
+                    for msg in messages {
+                        if let Err(e) = ws_sender.send(encode_message(&msg, features)?).await {
+                            error!("Failed to send message to {}: {}", addr, e);
+                            break 'connection;
                         }
-                        Err(broadcast::error::RecvError::Closed) => {
-                            error!("Broadcast channel closed for {}", addr);
+                    }
+
+                    // 队列曾经溢出时，在已排队事件之后通知客户端重新同步
+                    if resync_required.swap(false, Ordering::Relaxed) {
+                        let notice = WebSocketMessage::ResyncRequired {
+                            dropped: dropped.load(Ordering::Relaxed),
+                        };
+                        let json_msg = serde_json::to_string(&notice)
+                            .context("Failed to serialize resync notice")?;
+
+                        if let Err(e) = ws_sender.send(Message::Text(json_msg)).await {
+                            error!("Failed to send resync notice to {}: {}", addr, e);
                             break;
                         }
                     }
                 }
                 
-                // 发送心跳
+                // 发送心跳：协议层 Ping 由客户端的 WebSocket 库自动回复，JSON 心跳由客户端应答
                 _ = heartbeat_interval.tick() => {
+                    if let Err(e) = ws_sender.send(Message::Ping(ping_payload(connected))).await {
+                        error!("Failed to send ping to {}: {}", addr, e);
+                        break;
+                    }
                     let heartbeat_msg = WebSocketMessage::Heartbeat;
                     let json_msg = serde_json::to_string(&heartbeat_msg)
                         .context("Failed to serialize heartbeat message")?;
@@ -240,17 +629,27 @@ impl WebSocketServer {
 
         // 断开连接时移除客户端
         clients.write().remove(&addr);
-        info!("Client {} disconnected", addr);
+        let total_dropped = dropped.load(Ordering::Relaxed);
+        if total_dropped > 0 {
+            info!("Client {} disconnected ({} events dropped)", addr, total_dropped);
+        } else {
+            info!("Client {} disconnected", addr);
+        }
 
         Ok(())
     }
 
-    async fn handle_client_message(
+    async fn handle_client_message<W>(
         msg: WebSocketMessage,
-        ws_sender: &mut futures::stream::SplitSink<tokio_tungstenite::WebSocketStream<TcpStream>, Message>,
-        event_sender: &broadcast::Sender<WebSocketMessage>,
+        ws_sender: &mut W,
+        clients: &RwLock<HashMap<SocketAddr, ClientInfo>>,
+        commands: &RwLock<VecDeque<CommandStatus>>,
         addr: SocketAddr,
-    ) -> Result<()> {
+    ) -> Result<()>
+    where
+        W: Sink<Message> + Unpin,
+        W::Error: std::error::Error + Send + Sync + 'static,
+    {
         match msg {
             WebSocketMessage::FontListRequest => {
                 // 返回当前字体列表
@@ -267,7 +666,10 @@ impl WebSocketServer {
             }
             WebSocketMessage::Heartbeat => {
                 // 更新客户端心跳
-                info!("Received heartbeat from {}", addr);
+                debug!("Received heartbeat from {}", addr);
+                if let Some(client) = clients.read().get(&addr) {
+                    *client.last_heartbeat.write() = std::time::Instant::now();
+                }
             }
             WebSocketMessage::SyncRequest { client_id } => {
                 info!("Sync request from client: {}", client_id);
@@ -285,10 +687,24 @@ impl WebSocketServer {
                     .await
                     .context("Failed to send sync response")?;
             }
-            _ => {
-                // 将其他消息广播给所有客户端
-                let _ = event_sender.send(msg);
+            WebSocketMessage::Ack { message_id } => {
+                // 只接受指令目标客户端的确认
+                let client_id = clients.read().get(&addr).map(|c| c.client_id.clone());
+                let mut commands = commands.write();
+                match commands.iter_mut().find(|c| c.id == message_id && Some(&c.client_id) == client_id.as_ref()) {
+                    Some(status) => {
+                        status.acknowledged_at.get_or_insert(chrono::Utc::now().timestamp() as u64);
+                        info!("Client {} acknowledged command {}", status.client_id, message_id);
+                    }
+                    None => warn!("Unexpected acknowledgement {} from {}", message_id, addr),
+                }
+            }
+            WebSocketMessage::FontsInstalled { client_id, fonts } => {
+                let labels: Vec<String> = fonts.iter().map(|font| font.label()).collect();
+                info!("Client {} ({}) installed {} fonts: {}", client_id, addr, fonts.len(), labels.join(", "));
             }
+            // 字体事件与指令只由服务器发出，客户端发来的不转发，避免冒充服务器
+            other => warn!("Ignoring {} message from client {}", other.kind(), addr),
         }
         
         Ok(())
@@ -324,41 +740,525 @@ impl WebSocketServer {
         }
     }
 
-    pub fn broadcast_font_event(&self, event: WebSocketMessage) -> Result<()> {
-        self.event_sender.send(event)
-            .context("Failed to broadcast font event")?;
-        Ok(())
+    // 将事件放入每个客户端的发送队列，队列已满时丢弃并标记需要重新同步
+    fn fan_out(clients: &RwLock<HashMap<SocketAddr, ClientInfo>>, event: &WebSocketMessage) {
+        for (addr, client) in clients.read().iter() {
+            let Some(event) = event.for_subscription(&client.subscription) else {
+                continue;
+            };
+            match client.queue.try_send(event) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    let dropped = client.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    if !client.resync_required.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "Send queue full for client {} ({}), dropping events (total dropped: {})",
+                            client.client_id, addr, dropped
+                        );
+                    }
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    // 连接正在关闭，稍后会从列表中移除
+                }
+            }
+        }
+    }
+
+    pub fn broadcast_font_event(&self, event: WebSocketMessage) {
+        Self::fan_out(&self.clients, &event);
+    }
+
+    // 把指令放入 client_id 的所有连接的发送队列；客户端未连接时返回 None
+    pub fn send_command(&self, client_id: &str, command: ClientCommand) -> Option<CommandStatus> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let message = WebSocketMessage::Command { id: id.clone(), command: command.clone() };
+        let connections = self
+            .clients
+            .read()
+            .values()
+            .filter(|client| client.client_id == client_id)
+            .filter(|client| client.queue.try_send(message.clone()).is_ok())
+            .count();
+        if connections == 0 {
+            return None;
+        }
+
+        let status = CommandStatus {
+            id,
+            client_id: client_id.to_string(),
+            command,
+            sent_at: chrono::Utc::now().timestamp() as u64,
+            connections,
+            acknowledged_at: None,
+        };
+        let mut commands = self.commands.write();
+        if commands.len() >= MAX_COMMAND_HISTORY {
+            commands.pop_front();
+        }
+        commands.push_back(status.clone());
+        Some(status)
+    }
+
+    pub fn command_status(&self, id: &str) -> Option<CommandStatus> {
+        self.commands.read().iter().find(|c| c.id == id).cloned()
     }
 
     pub fn get_connected_clients(&self) -> usize {
         self.clients.read().len()
     }
+
+    pub fn connected_clients(&self) -> Vec<ConnectedClient> {
+        let mut clients: Vec<ConnectedClient> = self
+            .clients
+            .read()
+            .values()
+            .map(|client| ConnectedClient {
+                client_id: client.client_id.clone(),
+                addr: client.addr.to_string(),
+                hostname: client.identity.as_ref().map(|i| i.hostname.clone()),
+                os: client.identity.as_ref().map(|i| i.os.clone()),
+                connected_at: client.connected_at,
+                rtt_ms: client.rtt.read().map(|rtt| rtt.as_secs_f64() * 1000.0),
+                dropped_events: client.dropped.load(Ordering::Relaxed),
+            })
+            .collect();
+        clients.sort_by_key(|client| client.connected_at);
+        clients
+    }
+
+    // Prometheus 文本格式的连接数与每个连接丢弃的事件数
+    pub fn metrics(&self) -> String {
+        use std::fmt::Write as _;
+        let clients = self.connected_clients();
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP fontsync_ws_clients Connected WebSocket clients");
+        let _ = writeln!(out, "# TYPE fontsync_ws_clients gauge");
+        let _ = writeln!(out, "fontsync_ws_clients {}", clients.len());
+        let _ = writeln!(out, "# HELP fontsync_ws_dropped_events_total Events dropped because a client's send queue was full");
+        let _ = writeln!(out, "# TYPE fontsync_ws_dropped_events_total counter");
+        for client in &clients {
+            let _ = writeln!(
+                out,
+                "fontsync_ws_dropped_events_total{{client_id=\"{}\",addr=\"{}\"}} {}",
+                label_value(&client.client_id),
+                client.addr,
+                client.dropped_events
+            );
+        }
+        out
+    }
+}
+
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
 }
 
 pub async fn start_websocket_server(addr: SocketAddr) -> Result<()> {
-    let server = WebSocketServer::new(addr);
-    server.start().await
+    let server = WebSocketServer::new();
+    server.listen(addr, ServerPolicy::default()).await
+}
+
+// 握手被拒绝时的 HTTP 响应，内容与 HTTP 接口的 JSON 错误相同
+fn denied_response(denied: Denied) -> HandshakeError {
+    let body = ErrorResponse::new(ErrorCode::for_status(denied.status.as_u16()), denied.error, denied.message);
+    let mut response = HandshakeError::new(serde_json::to_string(&body).ok());
+    *response.status_mut() = denied.status;
+    response
+}
+
+fn ping_payload(connected: std::time::Instant) -> Vec<u8> {
+    (connected.elapsed().as_micros() as u64).to_be_bytes().to_vec()
+}
+
+// 由 Pong 带回的 Ping 负载计算往返时间，负载不是本连接发出的格式时忽略
+fn rtt_from_pong(connected: std::time::Instant, payload: &[u8]) -> Option<Duration> {
+    let sent = Duration::from_micros(u64::from_be_bytes(payload.try_into().ok()?));
+    connected.elapsed().checked_sub(sent)
+}
+
+fn from_warp_message(msg: warp::ws::Message) -> Message {
+    if let Ok(text) = msg.to_str() {
+        Message::Text(text.to_string())
+    } else if msg.is_ping() {
+        Message::Ping(msg.into_bytes())
+    } else if msg.is_pong() {
+        Message::Pong(msg.into_bytes())
+    } else if msg.is_close() {
+        Message::Close(None)
+    } else {
+        Message::Binary(msg.into_bytes())
+    }
+}
+
+fn to_warp_message(msg: Message) -> warp::ws::Message {
+    match msg {
+        Message::Text(text) => warp::ws::Message::text(text),
+        Message::Binary(data) => warp::ws::Message::binary(data),
+        Message::Ping(data) => warp::ws::Message::ping(data),
+        Message::Pong(data) => warp::ws::Message::pong(data),
+        Message::Close(_) => warp::ws::Message::close(),
+        Message::Frame(frame) => warp::ws::Message::binary(frame.into_data()),
+    }
 }
 
 // 创建字体事件消息的辅助函数
-pub fn create_font_added_event(filename: String, sha256: String, size: u64) -> WebSocketMessage {
+pub fn create_font_added_event(filename: String, sha256: String, size: u64, details: FontDetails) -> WebSocketMessage {
     WebSocketMessage::FontAdded {
         filename,
         sha256,
         size,
+        seq: None,
+        details,
     }
 }
 
-pub fn create_font_modified_event(filename: String, sha256: String, size: u64) -> WebSocketMessage {
+pub fn create_font_modified_event(filename: String, sha256: String, size: u64, details: FontDetails) -> WebSocketMessage {
     WebSocketMessage::FontModified {
         filename,
         sha256,
         size,
+        seq: None,
+        details,
     }
 }
 
-pub fn create_font_removed_event(filename: String) -> WebSocketMessage {
+pub fn create_font_removed_event(filename: String, sha256: Option<String>) -> WebSocketMessage {
     WebSocketMessage::FontRemoved {
         filename,
+        sha256,
+        seq: None,
+    }
+}
+
+pub fn create_font_renamed_event(from: String, to: String, sha256: String, size: u64) -> WebSocketMessage {
+    WebSocketMessage::FontRenamed {
+        from,
+        to,
+        sha256,
+        size,
+        seq: None,
+    }
+}
+
+// events 为已写入事件日志的 FontAdded 事件
+pub fn create_fonts_added_event(events: Vec<WebSocketMessage>) -> WebSocketMessage {
+    let names = events
+        .iter()
+        .filter_map(|event| match event {
+            WebSocketMessage::FontAdded { filename, .. } => Some(filename.clone()),
+            _ => None,
+        })
+        .collect();
+    let seq = events.iter().rev().find_map(|event| match event {
+        WebSocketMessage::FontAdded { seq, .. } => *seq,
+        _ => None,
+    });
+    WebSocketMessage::FontsAdded {
+        count: events.len(),
+        names,
+        seq,
+        events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+    use tokio_tungstenite::WebSocketStream;
+
+    #[tokio::test]
+    async fn batches_and_compresses_event_bursts() {
+        let (server_io, client_io) = tokio::io::duplex(1 << 20);
+        let server_ws = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let mut client_ws = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+        let clients: Clients = Default::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1));
+        let features = WsFeatures::parse(Some(WS_FEATURES));
+        assert!(features.batch && features.deflate);
+        tokio::spawn(WebSocketServer::handle_connection(server_ws, addr, None, features, Default::default(), Arc::clone(&clients), Default::default()));
+
+        // 欢迎消息较短，仍为文本帧
+        let welcome = client_ws.next().await.unwrap().unwrap();
+        assert!(matches!(welcome, Message::Text(_)));
+        assert!(matches!(decode_message(&welcome).unwrap(), WebSocketMessage::SyncComplete { .. }));
+
+        for i in 0..50 {
+            let event = create_font_added_event(format!("font-{}.ttf", i), "ab".repeat(32), i, FontDetails::default());
+            WebSocketServer::fan_out(&clients, &event);
+        }
+        let mut received = Vec::new();
+        while received.len() < 50 {
+            let frame = client_ws.next().await.unwrap().unwrap();
+            if matches!(frame, Message::Ping(_)) {
+                continue;
+            }
+            match decode_message(&frame).unwrap() {
+                WebSocketMessage::Heartbeat => continue,
+                WebSocketMessage::Batch { events } => {
+                    assert!(matches!(frame, Message::Binary(_)), "large batches are compressed");
+                    received.extend(events);
+                }
+                other => panic!("expected a batch, got {:?}", other),
+            }
+        }
+        assert!(matches!(&received[49], WebSocketMessage::FontAdded { filename, .. } if filename == "font-49.ttf"));
+
+        // 未声明扩展的客户端收到原样的文本帧
+        assert_eq!(WsFeatures::parse(None), WsFeatures::default());
+        let single = encode_message(&received[0], WsFeatures::default()).unwrap();
+        assert!(matches!(single, Message::Text(ref text) if text.contains("font-0.ttf")));
+    }
+
+    #[tokio::test]
+    async fn renames_and_aggregates_are_expanded_for_older_clients() {
+        let clients: Clients = Default::default();
+        let mut sockets = Vec::new();
+        for (port, header) in [(1, Some(WS_FEATURES)), (2, None)] {
+            let (server_io, client_io) = tokio::io::duplex(1 << 16);
+            let server_ws = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+            let mut client_ws = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            tokio::spawn(WebSocketServer::handle_connection(
+                server_ws,
+                addr,
+                None,
+                WsFeatures::parse(header),
+                Default::default(),
+                Arc::clone(&clients),
+                Default::default(),
+            ));
+            client_ws.next().await.unwrap().unwrap();
+            sockets.push(client_ws);
+        }
+
+        let event = create_font_renamed_event("a.ttf".to_string(), "b.ttf".to_string(), "ab".repeat(32), 7).with_seq(3);
+        WebSocketServer::fan_out(&clients, &event);
+
+        let renamed = next_event(&mut sockets[0]).await;
+        assert!(matches!(renamed, WebSocketMessage::FontRenamed { ref from, ref to, seq: Some(3), .. } if from == "a.ttf" && to == "b.ttf"));
+
+        // 不支持合并的旧客户端逐条收到删除与新增
+        let removed = next_event(&mut sockets[1]).await;
+        assert!(matches!(removed, WebSocketMessage::FontRemoved { ref filename, seq: Some(3), .. } if filename == "a.ttf"));
+        let added = next_event(&mut sockets[1]).await;
+        assert!(matches!(added, WebSocketMessage::FontAdded { ref filename, size: 7, .. } if filename == "b.ttf"));
+
+        // 合并的新增通知同样只发给声明了 aggregate 的客户端
+        let events = (4..6).map(|seq| create_font_added_event(format!("{}.ttf", seq), "ab".repeat(32), 7, FontDetails::default()).with_seq(seq));
+        WebSocketServer::fan_out(&clients, &create_fonts_added_event(events.collect()));
+        let aggregated = next_event(&mut sockets[0]).await;
+        assert!(matches!(aggregated, WebSocketMessage::FontsAdded { count: 2, ref names, seq: Some(5), .. } if names == &["4.ttf", "5.ttf"]));
+        // 客户端按事件中最大的序号记录进度
+        assert_eq!(WebSocketMessage::Batch { events: vec![aggregated, renamed] }.seq(), Some(5));
+        assert_eq!(WebSocketMessage::Heartbeat.seq(), None);
+        for seq in 4..6 {
+            let added = next_event(&mut sockets[1]).await;
+            assert!(matches!(added, WebSocketMessage::FontAdded { seq: Some(s), .. } if s == seq));
+        }
+    }
+
+    #[tokio::test]
+    async fn overflowing_the_queue_requests_a_resync() {
+        let server = WebSocketServer::new();
+        let (server_io, client_io) = tokio::io::duplex(1 << 20);
+        let server_ws = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let mut client_ws = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+        tokio::spawn(WebSocketServer::handle_connection(
+            server_ws,
+            SocketAddr::from(([127, 0, 0, 1], 1)),
+            None,
+            WsFeatures::default(),
+            Default::default(),
+            Arc::clone(&server.clients),
+            Default::default(),
+        ));
+        client_ws.next().await.unwrap().unwrap();
+
+        // 连续放入超过队列容量的事件，发送任务来不及取走，多出的被丢弃
+        let overflow = 10;
+        for seq in 0..(CLIENT_QUEUE_CAPACITY + overflow) as u64 {
+            let event = create_font_removed_event(format!("{}.ttf", seq), None).with_seq(seq + 1);
+            server.broadcast_font_event(event);
+        }
+        let clients = server.connected_clients();
+        assert_eq!(clients[0].dropped_events, overflow as u64);
+        assert!(server.metrics().contains(&format!(
+            "fontsync_ws_dropped_events_total{{client_id=\"{}\",addr=\"127.0.0.1:1\"}} {}",
+            clients[0].client_id, overflow
+        )));
+
+        // 发送任务取出排队的事件后通知客户端重新同步，其余排队的事件照常送达
+        let mut received = 0;
+        let mut notices = 0;
+        while received < CLIENT_QUEUE_CAPACITY {
+            match next_event(&mut client_ws).await {
+                WebSocketMessage::FontRemoved { .. } => received += 1,
+                WebSocketMessage::ResyncRequired { dropped } => {
+                    assert_eq!(dropped, overflow as u64);
+                    notices += 1;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(notices, 1);
+    }
+
+    #[tokio::test]
+    async fn pings_are_answered_and_measure_round_trip_time() {
+        let server = WebSocketServer::new();
+        let (server_io, client_io) = tokio::io::duplex(1 << 16);
+        let server_ws = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let mut client_ws = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+        tokio::spawn(WebSocketServer::handle_connection(
+            server_ws,
+            SocketAddr::from(([127, 0, 0, 1], 1)),
+            None,
+            WsFeatures::default(),
+            Default::default(),
+            Arc::clone(&server.clients),
+            Default::default(),
+        ));
+
+        // 连接建立后立即发送第一次心跳：先是 Ping，读取时自动回复 Pong
+        client_ws.next().await.unwrap().unwrap();
+        assert!(matches!(client_ws.next().await.unwrap().unwrap(), Message::Ping(_)));
+        let heartbeat = client_ws.next().await.unwrap().unwrap();
+        assert!(matches!(decode_message(&heartbeat).unwrap(), WebSocketMessage::Heartbeat));
+        client_ws.send(heartbeat).await.unwrap();
+        for _ in 0..100 {
+            if let Some(rtt) = server.connected_clients()[0].rtt_ms {
+                assert!(rtt >= 0.0);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("round-trip time was not recorded");
+    }
+
+    #[test]
+    fn events_are_narrowed_to_the_subscription() {
+        let subscription = Subscription::new(&["思源*".to_string(), "Noto Sans CJK*".to_string()], &[], &[".OTF".to_string()]);
+        // 握手头只含 ASCII，中文家族名编码后仍能还原
+        let header = subscription.to_header();
+        assert!(header.is_ascii());
+        assert_eq!(Subscription::parse(Some(&header)), subscription);
+
+        let event = |name: &str, family: Option<&str>| {
+            let details = FontDetails { family: family.map(str::to_string), ..FontDetails::default() };
+            create_font_added_event(name.to_string(), "ab".repeat(32), 7, details)
+        };
+        let cjk = event("cjk.otf", Some("Noto Sans CJK SC"));
+        assert!(cjk.for_subscription(&subscription).is_some());
+        assert!(event("cjk.ttf", Some("Noto Sans CJK SC")).for_subscription(&subscription).is_none());
+        assert!(event("latin.otf", Some("Noto Sans")).for_subscription(&subscription).is_none());
+        // 加密字体没有家族名，只按格式判断
+        assert!(event("secret.otf", None).for_subscription(&subscription).is_some());
+
+        let aggregated = create_fonts_added_event(vec![cjk, event("latin.otf", Some("Arial"))]);
+        let narrowed = aggregated.for_subscription(&subscription).unwrap();
+        assert!(matches!(narrowed, WebSocketMessage::FontsAdded { count: 1, ref names, .. } if names == &["cjk.otf"]));
+        let removed = create_font_removed_event("latin.otf".to_string(), None);
+        assert!(removed.for_subscription(&subscription).is_some());
+    }
+
+    // 跳过期间到达的心跳与 Ping
+    async fn next_event(socket: &mut WebSocketStream<tokio::io::DuplexStream>) -> WebSocketMessage {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Ping(_) => {}
+                frame => match decode_message(&frame).unwrap() {
+                    WebSocketMessage::Heartbeat => {}
+                    event => return event,
+                },
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn legacy_port_requires_a_reader_token() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let entries = vec![crate::access::TokenEntry { token: "read".to_string(), role: crate::access::Role::Reader, name: None, quota: None }];
+        let policy = ServerPolicy { tokens: Arc::new(crate::access::AccessTokens::new(entries).unwrap()), ..ServerPolicy::default() };
+        let server = Arc::new(WebSocketServer::new());
+        tokio::spawn({
+            let server = Arc::clone(&server);
+            async move { server.listen(SocketAddr::from(([127, 0, 0, 1], port)), policy).await }
+        });
+
+        let url = format!("ws://127.0.0.1:{}", port);
+        let mut refused = None;
+        for _ in 0..100 {
+            match tokio_tungstenite::connect_async(url.as_str()).await {
+                Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                    refused = Some(response.status().as_u16());
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                Ok(_) => panic!("connected without a token"),
+            }
+        }
+        assert_eq!(refused, Some(401));
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert("authorization", "Bearer read".parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.expect("reader token accepted");
+        assert!(matches!(decode_message(&socket.next().await.unwrap().unwrap()).unwrap(), WebSocketMessage::SyncComplete { .. }));
+    }
+
+    #[tokio::test]
+    async fn commands_reach_one_client_and_are_acknowledged() {
+        let server = WebSocketServer::new();
+        let mut sockets = Vec::new();
+        for (port, client_id) in [(1, "machine-1"), (2, "machine-2")] {
+            let (server_io, client_io) = tokio::io::duplex(1 << 16);
+            let server_ws = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+            let mut client_ws = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+            let identity = ClientIdentity {
+                client_id: client_id.to_string(),
+                hostname: "host".to_string(),
+                os: "linux".to_string(),
+            };
+            tokio::spawn(WebSocketServer::handle_connection(
+                server_ws,
+                SocketAddr::from(([127, 0, 0, 1], port)),
+                Some(identity),
+                WsFeatures::default(),
+                Default::default(),
+                Arc::clone(&server.clients),
+                Arc::clone(&server.commands),
+            ));
+            client_ws.next().await.unwrap().unwrap();
+            sockets.push(client_ws);
+        }
+
+        // 客户端伪造的指令不会转发给其他客户端，machine-2 收到的第一条是服务器发出的指令
+        let forged = WebSocketMessage::Command { id: "forged".to_string(), command: ClientCommand::Resync };
+        sockets[0].send(Message::Text(serde_json::to_string(&forged).unwrap())).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(server.send_command("machine-3", ClientCommand::Resync).is_none());
+        let status = server.send_command("machine-2", ClientCommand::Pause).expect("connected");
+        assert_eq!(status.connections, 1);
+        let WebSocketMessage::Command { id, command } = next_event(&mut sockets[1]).await else {
+            panic!("expected a command");
+        };
+        assert_eq!((id.as_str(), command), (status.id.as_str(), ClientCommand::Pause));
+
+        // 其他客户端的确认不算数
+        let ack = Message::Text(serde_json::to_string(&WebSocketMessage::Ack { message_id: id.clone() }).unwrap());
+        sockets[0].send(ack.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(server.command_status(&id).unwrap().acknowledged_at.is_none());
+
+        sockets[1].send(ack).await.unwrap();
+        for _ in 0..100 {
+            if server.command_status(&id).unwrap().acknowledged_at.is_some() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("command was not acknowledged");
     }
 }